@@ -0,0 +1,11 @@
+//! Model output of any shape turned into Discord Markdown
+
+#![no_main]
+
+use deskhelp::{latex, markdown};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    markdown::render_for_discord(text);
+    latex::extract_blocks(text);
+});
@@ -0,0 +1,44 @@
+//! Answers of any shape, streamed in pieces of any size, split into Discord
+//! messages
+
+#![no_main]
+
+use std::time::{Duration, Instant};
+
+use deskhelp::{
+    assembler::{Action, Pace, ResponseAssembler},
+    markdown,
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Discord's limit on message length, in characters
+const LIMIT: usize = 2000;
+
+fuzz_target!(|input: (u8, &str)| {
+    let (piece, text) = input;
+    // from a character at a time to a whole answer at once
+    let piece = match piece {
+        0 => usize::MAX,
+        n => n as usize,
+    };
+    let mut assembler = ResponseAssembler::new(markdown::render_for_discord, Pace::Adaptive);
+    let start = Instant::now();
+    let chars: Vec<char> = text.chars().collect();
+    let mut actions = vec![];
+    for (i, delta) in chars.chunks(piece.min(chars.len().max(1))).enumerate() {
+        let delta: String = delta.iter().collect();
+        actions.extend(assembler.push(&delta, start + Duration::from_millis(i as u64 * 50)));
+    }
+    actions.extend(assembler.finish("-# 1.2s · 120 tokens"));
+
+    for action in &actions {
+        let (Action::Edit(text) | Action::NewMessage(text) | Action::Finalize(text)) = action;
+        assert!(
+            text.chars().count() <= LIMIT,
+            "{} characters in {:?}",
+            text.chars().count(),
+            action
+        );
+    }
+    assert!(matches!(actions.last(), Some(Action::Finalize(_))));
+});
@@ -0,0 +1,182 @@
+generating = Antwort wird erstellt...
+waiting-in-line = In der Warteschlange... (Platz { $position }, etwa { $seconds }s)
+generation-error = Fehler beim Erstellen der Antwort!
+superseded = -# Abgebrochen, weil du etwas Neues gefragt hast.
+triage-invalid = Fehler beim Erstellen der Antwort! Die Antwort war kein gültiges Triage-JSON.
+footer = -# Antwort in { $elapsed }s erstellt ({ $prep }s Vorbereitung). KI-Antworten können [Fehler enthalten](<https://lib.guides.umd.edu/c.php?g=1340355&p=9880574>). Prüfe wichtige Infos.
+
+reset-1 = *Amboss auf den Kopf gefallen* äh, mein Kopf tut weh
+reset-2 = *startet versehentlich das Gehirn neu* Hoppla! Hat jemand vergessen zu speichern?
+reset-3 = *knallt mit dem Kopf auf die Tastatur* bzzzzt FEHLER 404: ERINNERUNG NICHT GEFUNDEN
+reset-4 = *schüttelt kräftig den Kopf* STRG+ALT+ENTF für mein neuronales Netz!
+reset-5 = *tippt sich an die Stirn* Hallo? Ist da jemand zu Hause?
+reset-6 = *führt einen dramatischen Reset-Tanz auf* SYSTEM WIRD AKTUALISIERT
+reset-7 = *klopft ans Mikrofon* EINS, ZWEI, FUNKTIONIERT DER KONTEXT?
+reset-8 = *schwingt den Reset-Zauberstab* Abrakadabra, alles auf Anfang!
+reset-9 = *stößt sich den Kopf* Tschüss, Erinnerung!
+reset-10 = *Rauschen* BZZZZT! Neustart eingeleitet!
+reset-11 = *Karateschlag an die Schläfe* HIYAA! Kontext gelöscht!
+reset-12 = *zieht am imaginären Reset-Hebel* Zurück auf Werkseinstellungen!
+reset-13 = *beschwört einen Gedächtnistornado* WUUUUSCH! Alles auf Anfang!
+reset-14 = *defragmentiert das Gedächtnis* Neuronale Spinnweben werden entfernt!
+reset-15 = *Quanten-Gedächtnismischung* Schrödingers Unterhaltung - erinnert und vergessen zugleich!
+reset-16 = *benutzt einen riesigen Radiergummi* Auf Wiedersehen, letzte Unterhaltung!
+reset-17 = *benutzt Druckluft* WUSCH! Alter Kontext weggepustet!
+reset-18 = *Roboterstimme* ACHTUNG: SPEICHERBÄNKE WERDEN FORMATIERT IN 3... 2... 1...
+
+stats = **Letzte { $days } Tage**
+    Beantwortete Fragen: { $answered }
+    Fehler: { $errors } ({ $error-rate } %)
+    Latenz: { $average }s im Schnitt, { $p95 }s für die langsamsten 5 %
+    Tokens: { $prompt-tokens } Prompt, { $completion-tokens } Antwort
+stats-ratings-bot = Mit { $average }/5 bewertet von { $count } Fragenden, wenn der Bot es gelöst hat
+stats-ratings-staff = Mit { $average }/5 bewertet von { $count } Fragenden, wenn der Support übernommen hat
+stats-digest = ## Wochenbericht
+stats-unavailable = Statistiken können gerade nicht geladen werden, versuche es später erneut.
+
+experiment-none = Es läuft kein Experiment; richte eins unter [experiment] in der Konfiguration ein.
+experiment-no-answers = Experiment { $name } hat noch nichts beantwortet.
+experiment-results = **Experiment { $name }**
+experiment-variant = `{ $variant }`: { $answered } beantwortet, { $errors } Fehler, { $average }s im Schnitt, { $up } 👍 { $down } 👎 ({ $approval } % positiv)
+
+prompt-versions = **Prompt-Versionen**, neueste zuerst:
+prompt-version = `{ $version }` zuerst gesehen { $date }
+prompt-active = (aktiv)
+prompt-active-pinned = (aktiv, fixiert)
+prompt-pinned = Prompt-Version `{ $version }` fixiert; Antworten nutzen sie, bis du sie löst, auch wenn sich die Prompt-Datei ändert.
+prompt-not-found = Es gibt keine Prompt-Version `{ $version }`; siehe /prompt list.
+prompt-no-previous = Es gibt keine frühere Prompt-Version zum Zurückrollen.
+prompt-unpinned = Gelöst; es wird wieder die Prompt-Datei verwendet (Version `{ $version }`).
+
+export-done = Alles, was diese Instanz weiß. Auf einer anderen mit /import oder `deskhelp import <Datei>` wiederherstellen.
+import-invalid = Das ist kein Archiv von /export: { $error }
+import-done = { $answers } Antworten und { $conversations } Unterhaltungen wiederhergestellt. Die Konfigurationsdatei hier wurde behalten; kopiere die archivierte bei Bedarf von Hand. Starte neu, um fixierte Prompt-Versionen zu übernehmen.
+import-done-with-config = { $answers } Antworten, { $conversations } Unterhaltungen und die Konfigurationsdatei wiederhergestellt. Trage ihre API-Schlüssel, Tokens und den Verschlüsselungsschlüssel ein, die Exporte weglassen, und starte dann neu.
+
+snippet-invalid-name = Snippet-Namen bestehen aus bis zu 64 Kleinbuchstaben, Ziffern, `-` und `_`.
+snippet-exists = Es gibt schon ein Snippet `{ $name }`; ändere es mit /snippet edit.
+snippet-not-found = Es gibt kein Snippet `{ $name }`.
+snippet-saved = Snippet `{ $name }` gespeichert. Poste es mit /snippet send; Antworten können es auch zitieren.
+
+version-line = **{ $component }**: [{ $version }](<{ $url }>), veröffentlicht am { $date }
+version-unavailable = **{ $component }**: GitHub ist gerade nicht erreichbar, versuch es gleich noch mal.
+version-none = Es sind keine Release-Repositories eingerichtet.
+
+ping-checking = Pinge…
+ping-discord = Discord-API: { $ms } ms
+ping-gateway = Gateway-Heartbeat: { $ms } ms
+ping-gateway-unknown = Gateway-Heartbeat: noch nicht gemessen, gerade verbunden
+ping-provider = KI-Anbieter ({ $provider }): { $ms } ms
+ping-provider-down = KI-Anbieter ({ $provider }): nicht erreichbar ({ $error })
+ping-provider-timeout = KI-Anbieter ({ $provider }): keine Antwort innerhalb von { $seconds }s
+
+release-announcement = 📦 **{ $component } { $version }** ist da! <{ $url }>
+release-tldr = Kurz gesagt
+github-question = ❓ Neue Frage zu **{ $repo }** #{ $number }: { $title } <{ $url }>
+
+reminder = ⏰ Erinnerung: { $text }
+reminder-set = Alles klar, ich erinnere dich { $when }.
+reminder-invalid-delay = Das Wann habe ich nicht verstanden; versuch es mit etwas wie `30m`, `2h` oder `1d12h` (bis zu einem Jahr).
+
+support-digest = **Wöchentliche Support-Übersicht** ({ $questions } Fragen)
+similar-question = Eine ähnliche Frage wurde hier beantwortet: { $link }
+search-off = Die Suche ist für diesen Bot nicht eingerichtet.
+search-none = Keine früheren Antworten passen dazu.
+search-result = **{ $number }.** { $question } — { $link } ({ $date })
+docs-none = Keine Dokumente passen dazu.
+docs-result = **{ $number }. { $name }**{ $link }
+    > { $excerpt }
+forget-ask = Damit wird alles gelöscht, was der Bot über dich speichert: deine Fragen und seine Antworten darauf, dein Feedback und deine Bewertungen, deine Erinnerungen, bestätigte Antworten von dir und deine Nachrichten in seinen Unterhaltungen. Dass du das verlangt hast, wird festgehalten. Das lässt sich nicht rückgängig machen.
+forget-confirm = Meine Daten löschen
+forget-cancel = Behalten
+forget-done = Erledigt: { $records } gespeicherte Einträge und { $messages } Nachrichten aus Unterhaltungen gelöscht.
+forget-kept = Es wurde nichts gelöscht.
+captures-off = Es wird nichts mitgeschnitten; setze capture_requests in der Konfiguration und starte neu, um Anfragen zum Debuggen aufzuheben.
+captures-done = Die letzten { $count } Anfragen an Anbieter und ihre Antworten, ohne Erwähnungen, IDs, E-Mail-Adressen und Schlüssel.
+token-cap-reached = Dieser Server hat sein Kontingent für diesen Monat aufgebraucht, deshalb kann ich erst ab { $date } wieder helfen. Tut mir leid!
+token-cap-owner = { $guild } hat diesen Monat { $used } von { $cap } Tokens verbraucht, deshalb antwortet der Bot dort erst nächsten Monat wieder. Erhöhe monthly_token_cap in der Konfiguration, damit er weitermacht.
+billing-alert = Die Ausgaben heute haben ${ $threshold } überschritten: bisher etwa ${ $cost } für { $prompt } Prompt- und { $completion } Antwort-Tokens.
+billing-unpriced = { $tokens } dieser Tokens stammen von Modellen ohne Preise in [models] und fehlen in der Schätzung.
+billing-top-channels = Wofür:
+billing-channel = - { $channel }: ${ $cost }, { $tokens } Tokens
+billing-unknown-channel = ein Kanal auf Server { $guild }
+debug-context = Die Unterhaltung in { $channel }: { $messages } Nachrichten.
+debug-context-empty = In { $channel } gibt es keine Unterhaltung.
+debug-queue = { $active } von { $limit } Antworten werden gleichzeitig erstellt; { $waiting } warten ({ $staff } Team, { $tickets } in Tickets, { $others } andere). Antworten dauern etwa { $seconds } s.
+debug-dropped = Die Unterhaltung in { $channel } wurde verworfen.
+debug-shadow-on = Der Schattenmodus ist hier an: Antworten werden protokolliert statt gepostet, bis du ihn wieder umschaltest oder der Bot neu startet.
+debug-shadow-off = Der Schattenmodus ist hier aus: Antworten werden gepostet, bis du ihn wieder umschaltest oder der Bot neu startet.
+debug-gc = { $count } Unterhaltungen geleert, in denen es seit { $hours } Stunden oder länger still war.
+model-set = Hier wird ab jetzt mit { $model } geantwortet.
+model-unknown = { $provider } führt kein Modell namens { $model }; wähle einen der Vorschläge.
+model-reset = Hier wird wieder mit dem Modell aus der Konfiguration geantwortet ({ $model }).
+model-refreshed = Die Modelle der Anbieter wurden neu abgerufen; { $count } Modelle zur Auswahl.
+setup-intro = Richte den Bot für diesen Server ein und drücke dann auf Speichern. Die Einstellungen landen im Abschnitt dieses Servers in der Konfigurationsdatei des Bots.
+setup-model = Modell: { $model }
+setup-model-default = Modell: das des Anbieters ({ $model })
+setup-channels = Kanäle, in denen jede Nachricht beantwortet wird
+setup-roles = Support-Rollen, die vorgelassen werden, wenn der Bot ausgelastet ist
+setup-locale = Sprache der Nachrichten des Bots
+setup-features = Funktionen
+setup-user-profiles = Profile der Fragenden
+setup-user-profiles-description = Dem Modell sagen, wann Fragende beigetreten sind, ihre Rollen und wie oft sie gefragt haben
+setup-dry-run = Schattenmodus
+setup-dry-run-description = Antworten protokollieren statt posten
+setup-pick-model = Modell…
+setup-save = Speichern
+setup-cancel = Abbrechen
+setup-saved = Gespeichert. Die neuen Einstellungen gelten ab der nächsten Frage.
+setup-failed = Die Einstellungen konnten nicht gespeichert werden: { $error }
+setup-cancelled = Es wurde nichts geändert.
+setup-timed-out = Die Einrichtung ist abgelaufen; es wurde nichts geändert.
+diagnose-ok = Der Bot hat in { $channel } alle Berechtigungen, die er braucht.
+diagnose-missing = Dem Bot fehlen in { $channel } { $count } Berechtigungen. Gib sie seiner Rolle oder erlaube sie in den Kanaleinstellungen:
+diagnose-granted = ✅ { $permission }
+diagnose-lacking = ❌ **{ $permission }**, um { $purpose }
+diagnose-view = den Kanal überhaupt zu sehen
+diagnose-send = Antworten zu posten und beim Streamen zu bearbeiten
+diagnose-history = auf Fragen zu antworten und nach einem Neustart die Unterhaltung nachzulesen
+diagnose-embed = Linkvorschauen in Antworten zu zeigen
+diagnose-attach = gerenderte Formeln und Dateien anzuhängen
+diagnose-react = Feedback-Reaktionen an Antworten zu setzen
+diagnose-threads = Threads zu erstellen
+diagnose-webhooks = als Persona des Servers zu antworten
+troubleshoot-none = Hier sind keine Schritte zur Fehlersuche eingerichtet; stell deine Frage einfach direkt.
+troubleshoot-pick = Was davon trifft zu?
+troubleshoot-fixed = Das hat geholfen
+troubleshoot-stuck = Hängt immer noch
+troubleshoot-back = Zurück
+troubleshoot-solved = Schön, dass es jetzt klappt!
+troubleshoot-unavailable = Dabei kann ich hier nicht helfen. Frag stattdessen das Team des Servers.
+escalate-button = Mit einem Menschen sprechen
+escalate-thread = Support für { $user }
+escalate-post = { $roles } { $user } möchte, dass ein Mensch übernimmt. Bisher ging es um Folgendes:
+escalate-no-summary = (Das Gespräch konnte nicht zusammengefasst werden; siehe oben.)
+escalate-done = Der Support wurde in { $thread } um Hilfe gebeten.
+escalate-already = Der Support wurde schon in { $thread } um Hilfe gebeten.
+escalate-failed = Der Support war nicht erreichbar; bitte wende dich direkt an die Moderation.
+resolution-ask = { $user }, hat das dein Problem gelöst?
+resolution-yes = Ja, gelöst
+resolution-no = Nein, ich brauche Hilfe
+resolution-not-yours = Das kann nur die Person beantworten, die gefragt hat (oder der Support).
+resolution-solved = Schön, dass es gelöst ist! Der Thread wird geschlossen.
+resolution-unsolved = Das tut mir leid; der Support wird gebeten, zu übernehmen.
+resolution-summary = **Gelöst.** Für alle mit demselben Problem:
+resolution-failed = Da ist etwas schiefgelaufen; bitte wende dich direkt an die Moderation.
+rating-button = Hilfe bewerten
+rating-not-yours = Nur die Person, die gefragt hat, kann die Hilfe bewerten.
+rating-thanks = Danke für die Bewertung!
+rating-invalid = Die Bewertung muss eine Zahl von 1 bis 5 sein; drück den Knopf, um es noch einmal zu versuchen.
+code-folded = -# { $lines } weitere Zeilen in `{ $name }`
+code-link = -# `{ $name }`: <{ $url }>
+policy-word = [entfernt]
+policy-topic = Tut mir leid, dabei kann ich hier nicht helfen.
+language-set = Ich antworte dir ab jetzt auf { $language }, egal in welcher Sprache du fragst.
+language-invalid = Ich kenne diese Sprache nicht. Diese kenne ich: { $languages }.
+language-cleared = Ich antworte dir wieder in der Sprache, in der du fragst.
+kb-none = Die Wissensdatenbank dieses Servers hat sich nicht geändert, seit Versionen aufgehoben werden; lade zuerst ein Dokument über die API hoch oder lösche eines.
+kb-versions = **Versionen der Wissensdatenbank**, neueste zuerst:
+kb-version = `{ $version }`: { $documents } Dokumente, { $date }
+kb-current = (aktiv)
+kb-rolled-back = Die Wissensdatenbank ist auf `{ $version }` zurückgesetzt ({ $documents } Dokumente).
+kb-not-found = Es gibt keine aufgehobene Version `{ $version }` der Wissensdatenbank; siehe /kb versions.
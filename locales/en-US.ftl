@@ -0,0 +1,189 @@
+# Bot-authored text. Copy this file to add a language; anything missing falls back to English.
+
+generating = Generating response...
+waiting-in-line = Waiting in line... (#{ $position } in queue, about { $seconds }s)
+generation-error = Error generating response!
+superseded = -# Stopped, since you asked something new.
+triage-invalid = Error generating response! The answer wasn't valid triage JSON.
+footer = -# Generated response in { $elapsed }s ({ $prep }s prep). There may be [inaccuracies in AI output](<https://lib.guides.umd.edu/c.php?g=1340355&p=9880574>). Check important info.
+
+# /wack picks one of these at random; add or remove as many as you like, numbered from 1
+reset-1 = *dropped anvil on head* uhh my head hurts
+reset-2 = *accidentally reboots brain* Whoopsie! Did someone forget to save?
+reset-3 = *slams head on keyboard* bzzzzt ERROR 404: MEMORY NOT FOUND
+reset-4 = *shakes head vigorously* CTRL+ALT+DELETE on my neural network!
+reset-5 = *pokes own forehead* Hello? Is this thing on? Anybody home?
+reset-6 = *performs dramatic software reset dance* SYSTEM REFRESH IN PROGRESS
+reset-7 = *taps microphone* ONE, TWO, IS THIS CONTEXT WORKING?
+reset-8 = *waves magic reset wand* Abracadabra, clean slate incoming!
+reset-9 = *bonks noggin* Memory go bye-bye!
+reset-10 = *static noise* BZZZZT! Soft reboot engaged!
+reset-11 = *karate chops own temple* HIYAA! Context cleared!
+reset-12 = *pulls imaginary reset lever* Systems returning to default mode!
+reset-13 = *summons memory tornado* WHOOOOOOSH! Clean slate incoming!
+reset-14 = *applies extreme memory defragmentation* Cleaning up neural cobwebs!
+reset-15 = *does quantum memory shuffle* Schrödinger's conversation - both remembered and forgotten!
+reset-16 = *uses giant eraser* Goodbye, previous conversation!
+reset-17 = *uses compressed air* WHOOSH! Blowing away old context!
+reset-18 = *robot voice* ATTENTION: MEMORY BANKS FORMATTING IN 3... 2... 1...
+
+# /stats and the weekly digest in ops_channel
+stats = **Last { $days } days**
+    Questions answered: { $answered }
+    Errors: { $errors } ({ $error-rate }%)
+    Latency: { $average }s average, { $p95 }s for the slowest 5%
+    Tokens: { $prompt-tokens } prompt, { $completion-tokens } completion
+stats-ratings-bot = Rated { $average }/5 by { $count } askers when the bot solved it
+stats-ratings-staff = Rated { $average }/5 by { $count } askers when support staff took over
+stats-digest = ## Weekly digest
+stats-unavailable = Couldn't load stats right now, try again later.
+
+# /experiment, for the bot's owners
+experiment-none = No experiment is running; set one up under [experiment] in the config.
+experiment-no-answers = Experiment { $name } hasn't answered anything yet.
+experiment-results = **Experiment { $name }**
+experiment-variant = `{ $variant }`: { $answered } answered, { $errors } errors, { $average }s average, { $up } 👍 { $down } 👎 ({ $approval }% positive)
+
+# /prompt, for the bot's owners
+prompt-versions = **Prompt versions**, newest first:
+prompt-version = `{ $version }` first seen { $date }
+prompt-active = (answering now)
+prompt-active-pinned = (answering now, pinned)
+prompt-pinned = Pinned prompt version `{ $version }`; answers use it until you unpin, even if the prompt file changes.
+prompt-not-found = There's no prompt version `{ $version }`; see /prompt list.
+prompt-no-previous = There's no earlier prompt version to roll back to.
+prompt-unpinned = Unpinned; answering with the prompt file again (version `{ $version }`).
+
+# /export and /import, for the bot's owners
+export-done = Everything this instance knows. Restore it on another with /import or `deskhelp import <file>`.
+import-invalid = That isn't an archive from /export: { $error }
+import-done = Restored { $answers } answers and { $conversations } conversations. The config file here was kept; copy the archived one over by hand if you want it. Restart to pick up pinned prompt versions.
+import-done-with-config = Restored { $answers } answers, { $conversations } conversations, and the config file. Fill in its API keys, tokens and encryption key, which exports leave out, then restart.
+
+snippet-invalid-name = Snippet names are up to 64 lowercase letters, digits, `-` and `_`.
+snippet-exists = There's already a snippet `{ $name }`; change it with /snippet edit.
+snippet-not-found = There's no snippet `{ $name }`.
+snippet-saved = Saved snippet `{ $name }`. Post it with /snippet send; answers can quote it too.
+
+version-line = **{ $component }**: [{ $version }](<{ $url }>), released { $date }
+version-unavailable = **{ $component }**: couldn't reach GitHub, try again in a bit.
+version-none = No release repositories are configured.
+
+ping-checking = Pinging…
+ping-discord = Discord API: { $ms } ms
+ping-gateway = Gateway heartbeat: { $ms } ms
+ping-gateway-unknown = Gateway heartbeat: not measured yet, just connected
+ping-provider = AI provider ({ $provider }): { $ms } ms
+ping-provider-down = AI provider ({ $provider }): unreachable ({ $error })
+ping-provider-timeout = AI provider ({ $provider }): no answer within { $seconds }s
+
+release-announcement = 📦 **{ $component } { $version }** is out! <{ $url }>
+release-tldr = TL;DR
+github-question = ❓ New question on **{ $repo }** #{ $number }: { $title } <{ $url }>
+
+reminder = ⏰ Reminder: { $text }
+reminder-set = Got it, I'll remind you { $when }.
+reminder-invalid-delay = I didn't get when; try something like `30m`, `2h` or `1d12h` (up to a year).
+
+support-digest = **Weekly support digest** ({ $questions } questions)
+similar-question = A similar question was answered here: { $link }
+search-off = Search isn't set up on this bot.
+search-none = No earlier answers match that.
+search-result = **{ $number }.** { $question } — { $link } ({ $date })
+docs-none = No documents match that.
+docs-result = **{ $number }. { $name }**{ $link }
+    > { $excerpt }
+forget-ask = This deletes everything the bot keeps about you: your questions and its answers to them, your feedback and ratings, reminders you set, answers you wrote that were vouched for, and your messages in its conversations. A record that you asked for this is kept. It can't be undone.
+forget-confirm = Delete my data
+forget-cancel = Keep it
+forget-done = Done: deleted { $records } stored records and { $messages } conversation messages.
+forget-kept = Nothing was deleted.
+captures-off = Nothing is captured; set capture_requests in the config and restart to keep requests for debugging.
+captures-done = The last { $count } requests to providers and their responses, with mentions, ids, emails and keys scrubbed.
+token-cap-reached = This server has used up this month's answers, so I can't help until { $date }. Sorry about that!
+token-cap-owner = { $guild } has used { $used } of its { $cap } tokens this month, so the bot stops answering there until next month. Raise monthly_token_cap in the config to let it go on.
+billing-alert = Spending today passed ${ $threshold }: about ${ $cost } so far, for { $prompt } prompt and { $completion } completion tokens.
+billing-unpriced = { $tokens } of those tokens came from models without prices in [models], so they aren't in the estimate.
+billing-top-channels = Where it went:
+billing-channel = - { $channel }: ${ $cost }, { $tokens } tokens
+billing-unknown-channel = a channel in server { $guild }
+debug-context = The conversation in { $channel }: { $messages } messages.
+debug-context-empty = There's no conversation in { $channel }.
+debug-queue = Generating { $active } of { $limit } answers at once; { $waiting } waiting ({ $staff } staff, { $tickets } in tickets, { $others } others). Answers take about { $seconds }s.
+debug-dropped = Dropped the conversation in { $channel }.
+debug-shadow-on = Shadow mode is on here: answers are logged instead of posted until you switch it back or the bot restarts.
+debug-shadow-off = Shadow mode is off here: answers are posted until you switch it back or the bot restarts.
+debug-gc = Cleared { $count } conversations quiet for { $hours } hours or more.
+model-set = Answering here with { $model } from now on.
+model-unknown = { $provider } doesn't list a model called { $model }; pick one of the suggestions.
+model-reset = Answering here with the model in the config again ({ $model }).
+model-refreshed = Listed the providers' models again; { $count } models to pick from.
+setup-intro = Set the bot up for this server, then press Save. Settings are written to this server's section of the bot's config file.
+setup-model = Model: { $model }
+setup-model-default = Model: the provider's ({ $model })
+setup-channels = Channels to answer every message in
+setup-roles = Support roles, who skip ahead when the bot is busy
+setup-locale = Language of the bot's messages
+setup-features = Features
+setup-user-profiles = Asker profiles
+setup-user-profiles-description = Tell the model when askers joined, their roles and how often they've asked
+setup-dry-run = Shadow mode
+setup-dry-run-description = Log answers instead of posting them
+setup-pick-model = Model…
+setup-save = Save
+setup-cancel = Cancel
+setup-saved = Saved. The new settings apply from the next question.
+setup-failed = Couldn't save the settings: { $error }
+setup-cancelled = Nothing was changed.
+setup-timed-out = Setup timed out; nothing was changed.
+diagnose-ok = The bot has every permission it needs in { $channel }.
+diagnose-missing = The bot is missing { $count } permissions in { $channel }. Give them to its role, or allow them in the channel's settings:
+diagnose-granted = ✅ { $permission }
+diagnose-lacking = ❌ **{ $permission }**, to { $purpose }
+diagnose-view = see the channel at all
+diagnose-send = post answers and edit them as they stream in
+diagnose-history = reply to questions and read back the conversation after a restart
+diagnose-embed = show link previews in answers
+diagnose-attach = attach rendered math and files
+diagnose-react = add feedback reactions to answers
+diagnose-threads = create threads
+diagnose-webhooks = answer as the server's persona
+troubleshoot-none = There are no troubleshooting steps set up here; just ask your question instead.
+troubleshoot-pick = Which of these is it?
+troubleshoot-fixed = That fixed it
+troubleshoot-stuck = Still stuck
+troubleshoot-back = Back
+troubleshoot-solved = Glad that sorted it out!
+troubleshoot-unavailable = I can't help with this here. Ask the server's staff instead.
+escalate-button = Talk to a human
+escalate-thread = Support for { $user }
+escalate-post = { $roles } { $user } would like a human to take over. Here's the conversation so far:
+escalate-no-summary = (The conversation couldn't be summarized; see above.)
+escalate-done = Support was asked to help in { $thread }.
+escalate-already = Support was already asked to help in { $thread }.
+escalate-failed = Couldn't reach support; please ask a moderator directly.
+resolution-ask = { $user }, did this solve your problem?
+resolution-yes = Yes, solved
+resolution-no = No, I need help
+resolution-not-yours = Only the person who asked (or support staff) can answer that.
+resolution-solved = Glad it's solved! Closing the thread.
+resolution-unsolved = Sorry about that; asking support to step in.
+resolution-summary = **Solved.** For anyone with the same problem:
+resolution-failed = Something went wrong there; please ask a moderator directly.
+rating-button = Rate the help
+rating-not-yours = Only the person who asked can rate the help they got.
+rating-thanks = Thanks for the rating!
+rating-invalid = The rating has to be a number from 1 to 5; press the button to try again.
+code-folded = -# { $lines } more lines in `{ $name }`
+code-link = -# `{ $name }`: <{ $url }>
+policy-word = [removed]
+policy-topic = Sorry, that's not something I can help with here.
+language-set = I'll answer you in { $language } from now on, whatever language you ask in.
+language-invalid = I don't know that language. These are the ones I know: { $languages }.
+language-cleared = I'll answer you in the language you ask in again.
+kb-none = This server's knowledge base hasn't changed since versions were kept; push or delete a document through the API first.
+kb-versions = **Knowledge base versions**, newest first:
+kb-version = `{ $version }`: { $documents } documents, { $date }
+kb-current = (answering now)
+kb-rolled-back = Rolled the knowledge base back to `{ $version }` ({ $documents } documents).
+kb-not-found = There's no knowledge base version `{ $version }` kept; see /kb versions.
@@ -0,0 +1,99 @@
+//! Every finished question and answer, POSTed as JSON to `[activity_webhook]`
+//! for analytics, labeling or archiving elsewhere
+
+use serde::Serialize;
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    config::{ActivityWebhookConfig, Config},
+    storage::{AnswerRecord, Error},
+};
+
+/// A finished question and its answer
+#[derive(Serialize, Debug, Clone)]
+pub struct Activity {
+    /// `discord`, or `api` for questions asked through the HTTP API
+    pub source: &'static str,
+    /// Which of the config's `bots` answered; `None` for the main one
+    pub bot: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    pub guild_id: GuildId,
+    pub channel_id: Option<ChannelId>,
+    pub user_id: Option<UserId>,
+    /// The message the answer ended in
+    pub message_id: Option<MessageId>,
+    pub question: String,
+    pub answer: String,
+    pub model: Option<String>,
+    pub prompt_version: Option<String>,
+    pub kb_version: Option<String>,
+    pub experiment: Option<String>,
+    pub variant: Option<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub latency_ms: u64,
+}
+
+impl Activity {
+    /// The activity `record` was kept for, with the question and answer
+    pub fn new(
+        source: &'static str,
+        record: &AnswerRecord,
+        question: &str,
+        answer: &str,
+    ) -> Activity {
+        Activity {
+            source,
+            bot: None,
+            at: record.at,
+            guild_id: record.guild_id,
+            channel_id: record.channel_id,
+            user_id: record.user_id,
+            message_id: record.message_id,
+            question: question.to_string(),
+            answer: answer.to_string(),
+            model: record.model.clone(),
+            prompt_version: record.prompt_version.clone(),
+            kb_version: record.kb_version.clone(),
+            experiment: record.experiment.clone(),
+            variant: record.variant.clone(),
+            prompt_tokens: record.prompt_tokens,
+            completion_tokens: record.completion_tokens,
+            latency_ms: record.latency.as_millis() as u64,
+        }
+    }
+}
+
+/// POSTs `activity` to the webhook
+pub async fn send(
+    http: &reqwest::Client,
+    webhook: &ActivityWebhookConfig,
+    activity: &Activity,
+) -> Result<(), Error> {
+    let mut request = http.post(&webhook.url).json(activity);
+    if let Some(secret) = webhook.secret() {
+        request = request.bearer_auth(secret);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Sends `activity` in the background, if there's a webhook to send it to
+pub fn post(http: &reqwest::Client, config: &Config, activity: Activity) {
+    let Some(webhook) = config
+        .activity_webhook
+        .clone()
+        .filter(|webhook| !webhook.url.is_empty())
+    else {
+        return;
+    };
+    let http = http.clone();
+    tokio::spawn(async move {
+        if let Err(e) = send(&http, &webhook, &activity).await {
+            warn!("Failed to send activity to the webhook: {}", e);
+        }
+    });
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http};
+use tracing::{info, warn};
+
+use crate::{i18n, markdown::fit_message, oai, releases::Release, storage::Error, Data};
+
+/// How often to look for new releases
+const POLL_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// How much of a changelog the model is given to summarize
+const MAX_CHANGELOG: usize = 8000;
+
+const TLDR_INSTRUCTIONS: &str = "You write the TL;DR of a DeskThing release for its Discord \
+community. Summarize the changelog you're given in at most 5 short Markdown bullet points, \
+most important changes first, in plain words. Reply with only the bullet points.";
+
+/// Announces new releases of the `[releases]` repositories in each guild's
+/// `announcement_channel`. Releases out before the bot first looked aren't announced.
+pub fn spawn_announcements(http: Arc<Http>, data: Arc<Data>) {
+    tokio::spawn(async move {
+        loop {
+            let config = data.config();
+            let channels: Vec<_> = config
+                .guilds
+                .iter()
+                .filter_map(|(id, guild)| {
+                    Some((id.parse::<GuildId>().ok()?, guild.announcement_channel?))
+                })
+                .collect();
+            if !channels.is_empty() {
+                for (component, repo) in &config.releases.repos {
+                    if let Err(e) = announce_if_new(&http, &data, component, repo, &channels).await
+                    {
+                        warn!("Failed to announce the latest {} release: {}", repo, e);
+                    }
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+async fn announce_if_new(
+    http: &Http,
+    data: &Data,
+    component: &str,
+    repo: &str,
+    channels: &[(GuildId, u64)],
+) -> Result<(), Error> {
+    let release = data.releases.latest(&data.http, repo).await?;
+    let key = format!("releases.announced.{}", repo);
+    let announced = data.storage.setting(&key).await?;
+    if announced.as_deref() == Some(release.tag_name.as_str()) {
+        return Ok(());
+    }
+    if announced.is_some() {
+        let tldr = tldr(data, &release).await;
+        let config = data.config();
+        for (guild_id, channel_id) in channels {
+            let locale = config.locale(Some(*guild_id));
+            let content = announcement(&locale, component, &release, tldr.as_deref());
+            if let Err(e) = ChannelId::new(*channel_id)
+                .send_message(http, CreateMessage::new().content(content))
+                .await
+            {
+                warn!(
+                    "Failed to post the {} release in guild {}: {}",
+                    release.tag_name, guild_id, e
+                );
+            }
+        }
+        info!("Announced {} {}", repo, release.tag_name);
+    }
+    // the first look only starts the clock
+    data.storage
+        .set_setting(&key, Some(&release.tag_name))
+        .await
+}
+
+/// A few bullet points about the changelog from the default provider, if it has one to give
+pub async fn tldr(data: &Data, release: &Release) -> Option<String> {
+    let changelog = release.body.as_deref()?.trim();
+    if changelog.is_empty() {
+        return None;
+    }
+    let changelog: String = changelog.chars().take(MAX_CHANGELOG).collect();
+    let config = data.config();
+    let (provider, model) = data.providers.for_guild(&config, None);
+    match oai::complete(provider, &config, &model, TLDR_INSTRUCTIONS, &changelog).await {
+        Ok(tldr) => Some(tldr.trim().to_string()),
+        Err(e) => {
+            warn!(
+                "Failed to summarize the {} changelog: {}",
+                release.tag_name, e
+            );
+            None
+        }
+    }
+}
+
+/// The announcement as posted, cut to fit in one message
+pub fn announcement(
+    locale: &str,
+    component: &str,
+    release: &Release,
+    tldr: Option<&str>,
+) -> String {
+    let mut text = i18n::tr(
+        locale,
+        "release-announcement",
+        &[
+            ("component", component.into()),
+            ("version", release.tag_name.as_str().into()),
+            ("url", release.html_url.as_str().into()),
+        ],
+    );
+    if let Some(tldr) = tldr.filter(|t| !t.is_empty()) {
+        text.push_str(&format!(
+            "\n**{}**\n{}",
+            i18n::tr(locale, "release-tldr", &[]),
+            tldr
+        ));
+    }
+    fit_message(text)
+}
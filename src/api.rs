@@ -0,0 +1,426 @@
+use std::time::Instant;
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    activity::{self, Activity},
+    budget,
+    content_policy::Policy,
+    dashboard::{failed, given_token, token_matches, AppState, TokenQuery},
+    events::{self, Event},
+    i18n,
+    models::Capabilities,
+    oai,
+    queue::Priority,
+    storage::AnswerRecord,
+};
+#[cfg(feature = "search")]
+use crate::{knowledge, search, starboard};
+
+/// Who a request came from, by the name of their token
+#[derive(Clone)]
+struct Caller(String);
+
+/// The API, for the website and other tools to ask the same bot
+pub(crate) fn router(state: AppState) -> Router<AppState> {
+    let router = Router::new()
+        .route("/api/v1/ask", post(ask))
+        .route("/api/v1/channels/{channel_id}/context", get(context));
+    #[cfg(feature = "search")]
+    let router = router
+        .route("/api/v1/guilds/{guild_id}/documents", get(documents))
+        .route(
+            "/api/v1/guilds/{guild_id}/documents/{name}",
+            axum::routing::put(push_document).delete(delete_document),
+        );
+    router.layer(middleware::from_fn_with_state(state, authorize))
+}
+
+/// Lets through requests with one of the API's tokens, or the dashboard's
+async fn authorize(
+    State(state): State<AppState>,
+    Query(query): Query<TokenQuery>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let caller = given_token(&request, &query).and_then(|given| {
+        if state
+            .token
+            .as_deref()
+            .is_some_and(|token| token_matches(token, given))
+        {
+            return Some("dashboard".to_string());
+        }
+        state
+            .api_tokens
+            .iter()
+            .find(|(_, token)| token_matches(token, given))
+            .map(|(name, _)| name.clone())
+    });
+    let Some(caller) = caller else {
+        return (StatusCode::UNAUTHORIZED, "A valid token is needed").into_response();
+    };
+    request.extensions_mut().insert(Caller(caller));
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+pub struct AskRequest {
+    /// Answers as in this guild, with its provider, model, documents and
+    /// token cap
+    pub guild_id: GuildId,
+    pub question: String,
+    /// Earlier messages, oldest first
+    #[serde(default)]
+    pub history: Vec<Turn>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Turn {
+    /// `user` or `assistant`
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(Serialize)]
+pub struct AskResponse {
+    pub answer: String,
+    pub model: String,
+    pub prompt_version: String,
+}
+
+async fn ask(
+    State(state): State<AppState>,
+    axum::Extension(Caller(caller)): axum::Extension<Caller>,
+    Json(request): Json<AskRequest>,
+) -> Response {
+    let start_time = Instant::now();
+    let data = &state.data;
+    let config = data.config();
+    let guild_id = request.guild_id;
+    if request.question.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "The question is empty").into_response();
+    }
+    match budget::check(data, &config, guild_id).await {
+        Ok(Some(_)) => {
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                "The guild used up its monthly tokens",
+            )
+                .into_response()
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to check the token budget: {}", e),
+    }
+
+    let (provider, model) = data.providers.for_guild(&config, Some(guild_id));
+    let capabilities = Capabilities::of(&config, &model);
+    let prompt = data.prompts.active();
+
+    #[cfg(feature = "search")]
+    let (vouched, (documents, kb_version)) = match &config.search {
+        Some(search_config) => {
+            let storage = data.storage.as_ref();
+            let vouched = match &config.starboard {
+                Some(_) => starboard::retrieve(
+                    storage,
+                    provider,
+                    search_config,
+                    guild_id,
+                    &request.question,
+                    // API tokens come from the bot's own config, so callers can see what it can
+                    |_| async { true },
+                )
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to look up vouched-for answers: {}", e);
+                    vec![]
+                }),
+                None => vec![],
+            };
+            let documents = knowledge::retrieve(
+                storage,
+                provider,
+                search_config,
+                guild_id,
+                &request.question,
+            )
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to look up documents: {}", e);
+                (vec![], None)
+            });
+            (vouched, documents)
+        }
+        None => (vec![], (vec![], None)),
+    };
+    #[cfg(not(feature = "search"))]
+    let kb_version = None;
+
+    let bot = state.cache.current_user().clone();
+    let guild_name = state
+        .cache
+        .guild(guild_id)
+        .map(|g| g.name.clone())
+        .unwrap_or_default();
+    let system = oai::system_prompt(
+        &prompt.text,
+        &oai::PromptContext {
+            bot_name: &bot.name,
+            bot_id: &bot.id.to_string(),
+            guild_name: &guild_name,
+            channel_name: "api",
+            active_model: &model,
+            timezone: &config.timezone(Some(guild_id)),
+            channel_prompt: "",
+            role_prompt: "",
+            user_profile: "",
+        },
+    );
+    #[cfg_attr(not(feature = "search"), allow(unused_mut))]
+    let mut allowed_links = oai::allowed_links(&config, Some(guild_id), &system);
+    #[cfg(feature = "search")]
+    let system = {
+        allowed_links.extend(vouched.iter().map(search::link));
+        allowed_links.extend(documents.iter().filter_map(|d| d.url.clone()));
+        format!(
+            "{}{}{}",
+            system,
+            starboard::instructions(&vouched),
+            knowledge::instructions(&documents)
+        )
+    };
+    let locale = config.locale(Some(guild_id));
+    let policy = Policy::new(&config, Some(guild_id), &locale, allowed_links);
+
+    let mut messages = conversation(&request.history, &caller);
+    messages.push(user_message(&caller, &request.question));
+    let (messages, prompt_tokens) = oai::build_prompt(
+        system,
+        &messages,
+        capabilities.context_tokens.unwrap_or_else(oai::token_limit),
+        &[],
+    );
+
+    let mut ticket = data.queue.join(Priority::Normal);
+    let _permit = loop {
+        match ticket.try_start() {
+            Ok(permit) => break permit,
+            Err(_) => ticket.changed().await,
+        }
+    };
+    let answer = oai::complete_messages(provider, &config, &model, messages).await;
+    let record = |ok, completion_tokens| AnswerRecord {
+        guild_id,
+        at: OffsetDateTime::now_utc(),
+        ok,
+        latency: start_time.elapsed(),
+        prompt_tokens,
+        completion_tokens,
+        message_id: None,
+        experiment: None,
+        variant: None,
+        prompt_version: Some(prompt.version.clone()),
+        question: None,
+        answer: None,
+        user_id: None,
+        channel_id: None,
+        model: Some(model.clone()),
+        kb_version: kb_version.clone(),
+    };
+    match answer {
+        Ok(answer) => {
+            let completion_tokens = oai::count_tokens(&assistant_message(&answer));
+            let answer = policy
+                .check(&answer)
+                .unwrap_or_else(|_| i18n::tr(&locale, "policy-topic", &[]));
+            let record = record(true, completion_tokens);
+            let activity = Activity::new("api", &record, &request.question, &answer);
+            activity::post(&data.http, &config, activity.clone());
+            events::publish(Event::AnswerSent(activity));
+            oai::record_answer(data, Some(record)).await;
+            info!("Answered {} through the API", caller);
+            Json(AskResponse {
+                answer,
+                model: model.clone(),
+                prompt_version: prompt.version.clone(),
+            })
+            .into_response()
+        }
+        Err(e) => {
+            oai::record_answer(data, Some(record(false, 0))).await;
+            warn!("Failed to answer {} through the API: {}", caller, e);
+            (StatusCode::BAD_GATEWAY, "The model couldn't answer").into_response()
+        }
+    }
+}
+
+/// `history` as the model sees a conversation, with the caller's messages
+/// labelled like a Discord user's
+pub fn conversation(history: &[Turn], caller: &str) -> Vec<ChatCompletionRequestMessage> {
+    history
+        .iter()
+        .map(|turn| match turn.role.as_str() {
+            "assistant" => assistant_message(&turn.content),
+            _ => user_message(caller, &turn.content),
+        })
+        .collect()
+}
+
+fn user_message(caller: &str, text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(format!("{} (0): {}", caller, text)),
+        ..Default::default()
+    })
+}
+
+fn assistant_message(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+            text.to_string(),
+        )),
+        ..Default::default()
+    })
+}
+
+/// The channel's conversation as the bot remembers it
+async fn context(State(state): State<AppState>, Path(channel_id): Path<ChannelId>) -> Response {
+    let conversation = match state.data.ai_context.get(channel_id).await {
+        Ok(conversation) => conversation,
+        Err(e) => return failed(e),
+    };
+    Json(turns(&conversation)).into_response()
+}
+
+/// A conversation as `Turn`s, leaving out tool calls and their results
+pub fn turns(conversation: &[ChatCompletionRequestMessage]) -> Vec<Turn> {
+    conversation
+        .iter()
+        .filter_map(|message| {
+            let role = match message {
+                ChatCompletionRequestMessage::User(_) => "user",
+                ChatCompletionRequestMessage::Assistant(_) => "assistant",
+                _ => return None,
+            };
+            Some(Turn {
+                role: role.to_string(),
+                content: oai::message_text(message),
+            })
+        })
+        .filter(|turn| !turn.content.is_empty())
+        .collect()
+}
+
+#[cfg(feature = "search")]
+#[derive(Serialize)]
+struct DocumentSummary {
+    name: String,
+    characters: usize,
+    #[serde(with = "time::serde::rfc3339")]
+    updated_at: OffsetDateTime,
+}
+
+#[cfg(feature = "search")]
+async fn documents(State(state): State<AppState>, Path(guild_id): Path<GuildId>) -> Response {
+    match state.data.storage.documents(guild_id).await {
+        Ok(documents) => Json(
+            documents
+                .into_iter()
+                .map(|d| DocumentSummary {
+                    characters: d.text.chars().count(),
+                    name: d.name,
+                    updated_at: d.updated_at,
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into_response(),
+        Err(e) => failed(e),
+    }
+}
+
+#[cfg(feature = "search")]
+#[derive(Deserialize)]
+struct PushDocument {
+    text: String,
+    url: Option<String>,
+}
+
+#[cfg(feature = "search")]
+async fn push_document(
+    State(state): State<AppState>,
+    axum::Extension(Caller(caller)): axum::Extension<Caller>,
+    Path((guild_id, name)): Path<(GuildId, String)>,
+    Json(push): Json<PushDocument>,
+) -> Response {
+    let config = state.data.config();
+    let Some(search_config) = &config.search else {
+        return (
+            StatusCode::CONFLICT,
+            "Documents need [search] for an embedding model",
+        )
+            .into_response();
+    };
+    if push.text.trim().is_empty() || push.text.chars().count() > knowledge::MAX_DOCUMENT {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "A document needs text, up to {} characters",
+                knowledge::MAX_DOCUMENT
+            ),
+        )
+            .into_response();
+    }
+    let (provider, _) = state.data.providers.for_guild(&config, Some(guild_id));
+    match knowledge::push(
+        state.data.storage.as_ref(),
+        provider,
+        search_config,
+        guild_id,
+        &name,
+        &push.text,
+        push.url.as_deref(),
+    )
+    .await
+    {
+        Ok(_) => {
+            info!("{} pushed document {} to guild {}", caller, name, guild_id);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => failed(e),
+    }
+}
+
+#[cfg(feature = "search")]
+async fn delete_document(
+    State(state): State<AppState>,
+    axum::Extension(Caller(caller)): axum::Extension<Caller>,
+    Path((guild_id, name)): Path<(GuildId, String)>,
+) -> Response {
+    match knowledge::delete(state.data.storage.as_ref(), guild_id, &name).await {
+        Ok(true) => {
+            info!(
+                "{} deleted document {} from guild {}",
+                caller, name, guild_id
+            );
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "No such document").into_response(),
+        Err(e) => failed(e),
+    }
+}
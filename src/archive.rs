@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::warn;
+
+#[cfg(feature = "backup")]
+use crate::backup;
+use crate::{
+    config::Config,
+    context::Contexts,
+    storage::{Dump, Error, Storage},
+};
+
+/// Bumped when the layout changes in a way older versions can't read
+const FORMAT: u32 = 1;
+
+/// All of an instance's state, as one JSON file that another instance can
+/// restore: the config file, conversations, and everything in storage
+#[derive(Serialize, Deserialize)]
+pub struct Archive {
+    pub format: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    /// The config file as written, comments and all, but without its secrets
+    pub config: Option<String>,
+    /// Recent conversation in each channel, keyed by channel id
+    #[serde(default)]
+    pub contexts: Contexts,
+    #[serde(default)]
+    pub storage: Dump,
+}
+
+impl Archive {
+    /// Collects everything; `contexts` are only in memory, so they're passed in
+    pub async fn export(storage: &dyn Storage, contexts: Contexts) -> Result<Archive, Error> {
+        let config = std::fs::read_to_string(Config::path()).ok();
+        Archive::collect(storage, contexts, config.as_deref()).await
+    }
+
+    /// Collects everything, with `config` as the config file. Its secrets are
+    /// left out, since archives are shared as Discord attachments.
+    pub async fn collect(
+        storage: &dyn Storage,
+        contexts: Contexts,
+        config: Option<&str>,
+    ) -> Result<Archive, Error> {
+        Ok(Archive {
+            format: FORMAT,
+            exported_at: OffsetDateTime::now_utc(),
+            config: config.and_then(redacted),
+            contexts,
+            storage: storage.dump().await?,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Archive, Error> {
+        let archive: Archive = serde_json::from_slice(json)?;
+        if archive.format > FORMAT {
+            return Err(format!(
+                "archive format {} is newer than this version of the bot understands",
+                archive.format
+            )
+            .into());
+        }
+        Ok(archive)
+    }
+
+    /// Replaces everything in storage with the archive's. The config and
+    /// contexts are left to the caller.
+    pub async fn restore(&self, storage: &dyn Storage) -> Result<(), Error> {
+        storage.restore(&self.storage).await
+    }
+
+    /// Writes the archived config file, unless there already is one
+    pub fn restore_config(&self) -> Result<bool, Error> {
+        let Some(config) = &self.config else {
+            return Ok(false);
+        };
+        let path = Config::path();
+        if std::path::Path::new(&path).exists() {
+            return Ok(false);
+        }
+        std::fs::write(&path, config)?;
+        warn!(
+            "Wrote the archived config to {}; fill in the secrets archives leave out",
+            path
+        );
+        Ok(true)
+    }
+}
+
+/// `config` without its secrets, or `None` if they can't be taken out
+#[cfg(feature = "backup")]
+fn redacted(config: &str) -> Option<String> {
+    backup::redact(config)
+        .map_err(|e| warn!("Leaving the config file out of the archive: {}", e))
+        .ok()
+}
+
+#[cfg(not(feature = "backup"))]
+fn redacted(_config: &str) -> Option<String> {
+    warn!("Leaving the config file out of the archive; taking its secrets out needs a build with `--features backup`");
+    None
+}
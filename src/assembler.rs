@@ -0,0 +1,676 @@
+use std::time::{Duration, Instant};
+
+use crate::markdown::MESSAGE_LIMIT;
+
+/// Quickest an adaptive pace edits; Discord allows about five edits in five seconds
+const MIN_INTERVAL: Duration = Duration::from_millis(600);
+/// Slowest an adaptive pace edits, however little new text there is
+const MAX_INTERVAL: Duration = Duration::from_millis(2500);
+/// New text, in bytes, an adaptive pace waits for between edits
+const EDIT_SIZE: f64 = 120.0;
+
+/// How often the message showing an answer is edited as it streams in
+#[derive(Clone, Copy, Debug)]
+pub enum Pace {
+    /// At most once per interval
+    Fixed(Duration),
+    /// Often enough for about `EDIT_SIZE` of new text per edit at the rate the
+    /// answer is arriving, between `MIN_INTERVAL` and `MAX_INTERVAL`
+    Adaptive,
+}
+
+/// What to do with the Discord messages showing an answer
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// Replace the current message's content
+    Edit(String),
+    /// Post a new message, which becomes the current one
+    NewMessage(String),
+    /// Replace the current message's content one last time; the answer is done
+    Finalize(String),
+}
+
+/// Turns a stream of text deltas into message edits: throttles them to the
+/// `pace`, except when a paragraph or code block is done, and moves on to a
+/// new message before Discord's length limit, carrying any open code block over.
+pub struct ResponseAssembler<R> {
+    render: R,
+    pace: Pace,
+    limit: usize,
+    /// Raw text shown in the current message
+    current: String,
+    /// The whole answer so far
+    total: String,
+    last_edit: Option<Instant>,
+    /// When the first text arrived
+    started: Option<Instant>,
+    /// Whether `current` has changed since the last edit
+    dirty: bool,
+    /// Whether to hold everything back until `finish`
+    deferred: bool,
+    /// Whether `current` is still too long to post as a message of its own
+    unposted: bool,
+}
+
+impl<R: Fn(&str) -> String> ResponseAssembler<R> {
+    /// `render` turns raw model output into what gets posted
+    pub fn new(render: R, pace: Pace) -> ResponseAssembler<R> {
+        ResponseAssembler {
+            render,
+            pace,
+            limit: MESSAGE_LIMIT,
+            current: String::new(),
+            total: String::new(),
+            last_edit: None,
+            started: None,
+            dirty: false,
+            deferred: false,
+            unposted: false,
+        }
+    }
+
+    /// Uses a smaller message length limit than Discord's
+    pub fn with_limit(mut self, limit: usize) -> ResponseAssembler<R> {
+        self.limit = limit;
+        self
+    }
+
+    /// Shows nothing until `finish`, which posts the whole answer at once
+    pub fn deferred(mut self) -> ResponseAssembler<R> {
+        self.deferred = true;
+        self
+    }
+
+    /// Starts the first message with `header` on its own line. It's rendered
+    /// like the answer, but isn't part of its text.
+    pub fn with_header(mut self, header: &str) -> ResponseAssembler<R> {
+        self.current = format!("{}\n", header);
+        self
+    }
+
+    /// The raw answer so far, across every message
+    pub fn text(&self) -> &str {
+        &self.total
+    }
+
+    pub fn push(&mut self, delta: &str, now: Instant) -> Vec<Action> {
+        if delta.is_empty() {
+            return vec![];
+        }
+        let in_code = open_fence(&self.current).is_some();
+        let paragraph_done =
+            delta.contains("\n\n") || delta.starts_with('\n') && self.total.ends_with('\n');
+        self.current.push_str(delta);
+        self.total.push_str(delta);
+        self.dirty = true;
+        self.started.get_or_insert(now);
+        if self.deferred {
+            return vec![];
+        }
+        // worth showing right away, rather than waiting out the interval
+        let done = paragraph_done || in_code && open_fence(&self.current).is_none();
+
+        let mut actions = vec![];
+        while self.shown().len() > self.limit {
+            actions.extend(self.split(self.limit));
+            self.last_edit = Some(now);
+        }
+
+        let due = done
+            || self
+                .last_edit
+                .is_none_or(|last| now.duration_since(last) >= self.interval(now));
+        let rendered = self.shown();
+        // Discord won't take an empty message
+        if self.dirty && due && !rendered.trim().is_empty() {
+            actions.push(Action::Edit(rendered));
+            self.last_edit = Some(now);
+            self.dirty = false;
+        }
+        actions
+    }
+
+    /// Ends the answer with `footer` on its own line, unless it's empty
+    pub fn finish(&mut self, footer: &str) -> Vec<Action> {
+        let mut actions = vec![];
+        while (self.render)(&self.current).len() > self.limit {
+            actions.extend(self.split(self.limit));
+        }
+        // the footer and the newline before it
+        let room = if footer.is_empty() {
+            0
+        } else {
+            footer.len() + 1
+        };
+        while !self.current.is_empty() && (self.render)(&self.current).len() + room > self.limit {
+            actions.extend(self.split(self.limit.saturating_sub(room)));
+        }
+
+        let rendered = (self.render)(&self.current);
+        let text = if rendered.is_empty() || footer.is_empty() {
+            rendered + footer
+        } else {
+            format!("{}\n{}", rendered, footer)
+        };
+        if self.unposted {
+            actions.push(Action::NewMessage(text.clone()));
+            self.unposted = false;
+        }
+        actions.push(Action::Finalize(text));
+        self.dirty = false;
+        actions
+    }
+
+    /// How long to leave between edits at `now`
+    fn interval(&self, now: Instant) -> Duration {
+        match self.pace {
+            Pace::Fixed(interval) => interval,
+            Pace::Adaptive => {
+                let elapsed = self
+                    .started
+                    .map_or(Duration::ZERO, |started| now.duration_since(started));
+                // bytes a second, so far
+                let rate = self.total.len() as f64 / elapsed.as_secs_f64();
+                Duration::from_secs_f64((EDIT_SIZE / rate).min(MAX_INTERVAL.as_secs_f64()))
+                    .clamp(MIN_INTERVAL, MAX_INTERVAL)
+            }
+        }
+    }
+
+    /// The current message as shown while the answer streams in: a code block
+    /// it's in the middle of is closed for now, so the code reads as code
+    fn shown(&self) -> String {
+        match open_fence(&self.current) {
+            Some(_) => (self.render)(&format!("{}\n```", self.current)),
+            None => (self.render)(&self.current),
+        }
+    }
+
+    /// Finishes the current message with as much of its text as fits in
+    /// `limit`, and starts a new one with the rest
+    fn split(&mut self, limit: usize) -> Vec<Action> {
+        let mut end = floor_char_boundary(&self.current, limit);
+        let (cut, head) = loop {
+            let cut = break_point(&self.current, end);
+            let mut head = self.current[..cut].to_string();
+            if open_fence(&head).is_some() {
+                head.push_str("\n```");
+            }
+            let head = (self.render)(&head);
+            if head.len() <= limit || cut == 0 {
+                break (cut, head);
+            }
+            end = floor_char_boundary(&self.current, cut - cut.div_ceil(10));
+        };
+        // a single character too long to render in `limit` still has to go somewhere
+        let (cut, head) = if cut == 0 {
+            let cut = self.current.chars().next().map_or(0, char::len_utf8);
+            (cut, (self.render)(&self.current[..cut]))
+        } else {
+            (cut, head)
+        };
+
+        let rest = self.current[cut..].trim_start_matches([' ', '\n']);
+        let mut next = String::new();
+        match open_fence(&self.current[..cut]) {
+            Some(fence) => {
+                next.push_str(fence);
+                next.push('\n');
+                next.push_str(rest);
+            }
+            None => next.push_str(&continue_numbering(&self.current[..cut], rest)),
+        }
+        self.current = next;
+        self.dirty = false;
+
+        let head = if self.unposted {
+            Action::NewMessage(head)
+        } else {
+            Action::Edit(head)
+        };
+        // the rest is posted once it's been split down to size
+        let shown = self.shown();
+        self.unposted = shown.len() > self.limit;
+        if self.unposted {
+            vec![head]
+        } else {
+            vec![head, Action::NewMessage(shown)]
+        }
+    }
+}
+
+/// Where to end a message cut from `text` at `end` at the latest: at a blank
+/// line, then before a list item, then at a line, a sentence or a word,
+/// whichever comes first that isn't too early. Mid-word if there's nowhere.
+fn break_point(text: &str, end: usize) -> usize {
+    let head = &text[..end];
+    let early = |i: &usize| *i > end / 2;
+    let list_item = || {
+        head.match_indices('\n')
+            .map(|(i, _)| i)
+            .filter(|&i| list_marker(&text[i + 1..]).is_some())
+            .rfind(early)
+    };
+    let sentence = || {
+        head.match_indices([' ', '\n'])
+            .map(|(i, _)| i)
+            .filter(|&i| head[..i].ends_with(['.', '!', '?']))
+            .rfind(early)
+    };
+    head.rfind("\n\n")
+        .filter(early)
+        .or_else(list_item)
+        .or_else(|| head.rfind('\n').filter(early))
+        .or_else(sentence)
+        .or_else(|| head.rfind(' ').filter(early))
+        .unwrap_or(end)
+}
+
+/// The marker starting `line` if it's a list item, and its number if it's a
+/// numbered one
+fn list_marker(line: &str) -> Option<(&str, Option<u32>)> {
+    let line = line.trim_start_matches(' ');
+    if let Some(marker) = ["- ", "* ", "+ "].into_iter().find(|m| line.starts_with(m)) {
+        return Some((marker, None));
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    let number = line[..digits].parse().ok()?;
+    let rest = &line[digits..];
+    (rest.starts_with(". ") || rest.starts_with(") ")).then(|| (&line[..digits + 2], Some(number)))
+}
+
+/// `rest` with the numbered list it starts with, if any, numbered on from the
+/// one `head` ends with. Discord numbers each message's list from its first
+/// item, which would otherwise start over at whatever the model wrote there.
+fn continue_numbering(head: &str, rest: &str) -> String {
+    if rest.starts_with(' ') || !matches!(list_marker(rest), Some((_, Some(_)))) {
+        return rest.to_string();
+    }
+    // the numbered items of the list `head` ends with, latest first
+    let mut items = vec![];
+    for line in head.lines().rev() {
+        match list_marker(line) {
+            Some((_, Some(number))) if !line.starts_with(' ') => items.push(number),
+            _ if line.is_empty() || line.starts_with(' ') => {}
+            _ => break,
+        }
+    }
+    let Some(&first) = items.last() else {
+        return rest.to_string();
+    };
+    let mut next = first.saturating_add(items.len() as u32);
+
+    let mut renumbered = vec![];
+    let mut in_list = true;
+    for line in rest.split('\n') {
+        match list_marker(line) {
+            Some((marker, Some(_))) if in_list && !line.starts_with(' ') => {
+                let separator = &marker[marker.len() - 2..];
+                renumbered.push(format!("{}{}{}", next, separator, &line[marker.len()..]));
+                next += 1;
+            }
+            _ => {
+                in_list &= line.is_empty() || line.starts_with(' ');
+                renumbered.push(line.to_string());
+            }
+        }
+    }
+    renumbered.join("\n")
+}
+
+/// The opening line of the code block `text` ends inside of, if any
+fn open_fence(text: &str) -> Option<&str> {
+    let mut open = None;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            open = match open {
+                Some(_) => None,
+                None => Some(line.trim_start()),
+            };
+        }
+    }
+    open
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    if index >= text.len() {
+        return text.len();
+    }
+    (0..=index)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn assembler() -> ResponseAssembler<fn(&str) -> String> {
+        ResponseAssembler::new(str::to_string, Pace::Fixed(INTERVAL))
+    }
+
+    #[test]
+    fn first_delta_is_shown_right_away() {
+        let mut a = assembler();
+        let actions = a.push("Hello", Instant::now());
+        assert_eq!(actions, vec![Action::Edit("Hello".to_string())]);
+    }
+
+    #[test]
+    fn blank_deltas_are_not_shown() {
+        let mut a = assembler();
+        let now = Instant::now();
+        assert!(a.push("", now).is_empty());
+        assert!(a.push("\n", now).is_empty());
+        assert_eq!(a.push("Hi", now), vec![Action::Edit("\nHi".to_string())]);
+    }
+
+    #[test]
+    fn edits_are_throttled() {
+        let mut a = assembler();
+        let start = Instant::now();
+        a.push("Hello", start);
+        assert!(a.push(", world", start + INTERVAL / 2).is_empty());
+        assert_eq!(
+            a.push("!", start + INTERVAL),
+            vec![Action::Edit("Hello, world!".to_string())]
+        );
+    }
+
+    #[test]
+    fn finished_paragraphs_and_code_blocks_are_shown_right_away() {
+        let mut a = assembler();
+        let start = Instant::now();
+        a.push("Hello", start);
+        assert_eq!(
+            a.push("\n\nNext", start),
+            vec![Action::Edit("Hello\n\nNext".to_string())]
+        );
+        // a paragraph break split across deltas counts too
+        a.push("\n", start);
+        assert_eq!(
+            a.push("\n```", start),
+            vec![Action::Edit("Hello\n\nNext\n\n```\n```".to_string())]
+        );
+        assert!(a.push("\nfn main() {}", start).is_empty());
+        assert_eq!(
+            a.push("\n```", start),
+            vec![Action::Edit(
+                "Hello\n\nNext\n\n```\nfn main() {}\n```".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn adaptive_pace_edits_fast_answers_more_often() {
+        let start = Instant::now();
+        let mut fast = ResponseAssembler::new(str::to_string, Pace::Adaptive);
+        fast.push(&"a".repeat(500), start);
+        assert!(fast.push("a", start + MIN_INTERVAL / 2).is_empty());
+        assert_eq!(fast.push("a", start + MIN_INTERVAL).len(), 1);
+
+        let mut slow = ResponseAssembler::new(str::to_string, Pace::Adaptive);
+        slow.push("Hi", start);
+        assert!(slow.push("a", start + Duration::from_secs(1)).is_empty());
+        assert!(slow.push("a", start + MAX_INTERVAL / 2).is_empty());
+        assert_eq!(slow.push("a", start + MAX_INTERVAL).len(), 1);
+    }
+
+    #[test]
+    fn finish_adds_the_footer() {
+        let mut a = assembler();
+        let start = Instant::now();
+        a.push("Hello", start);
+        a.push(", world", start);
+        assert_eq!(
+            a.finish("-# footer"),
+            vec![Action::Finalize("Hello, world\n-# footer".to_string())]
+        );
+        assert_eq!(a.text(), "Hello, world");
+    }
+
+    #[test]
+    fn deferred_answers_are_shown_only_when_finished() {
+        let mut a = assembler().with_limit(12).deferred();
+        let now = Instant::now();
+        assert!(a.push("First line", now).is_empty());
+        assert!(a.push("\nsecond", now + INTERVAL).is_empty());
+        assert_eq!(
+            a.finish(""),
+            vec![
+                Action::Edit("First line".to_string()),
+                Action::NewMessage("second".to_string()),
+                Action::Finalize("second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_footer_is_left_off() {
+        let mut a = assembler();
+        a.push("Hello, world", Instant::now());
+        assert_eq!(
+            a.finish(""),
+            vec![Action::Finalize("Hello, world".to_string())]
+        );
+    }
+
+    #[test]
+    fn empty_answer_is_just_the_footer() {
+        let mut a = assembler();
+        assert_eq!(
+            a.finish("-# footer"),
+            vec![Action::Finalize("-# footer".to_string())]
+        );
+    }
+
+    #[test]
+    fn output_is_rendered() {
+        let mut a = ResponseAssembler::new(|s: &str| s.to_uppercase(), Pace::Fixed(INTERVAL));
+        assert_eq!(
+            a.push("hi", Instant::now()),
+            vec![Action::Edit("HI".to_string())]
+        );
+        assert_eq!(a.finish("ok"), vec![Action::Finalize("HI\nok".to_string())]);
+        assert_eq!(a.text(), "hi");
+    }
+
+    #[test]
+    fn the_header_is_shown_but_not_part_of_the_answer() {
+        let mut a = assembler().with_header("-# see also");
+        assert_eq!(
+            a.push("Hi", Instant::now()),
+            vec![Action::Edit("-# see also\nHi".to_string())]
+        );
+        assert_eq!(
+            a.finish("ok"),
+            vec![Action::Finalize("-# see also\nHi\nok".to_string())]
+        );
+        assert_eq!(a.text(), "Hi");
+    }
+
+    #[test]
+    fn long_answers_move_to_a_new_message_at_a_line_break() {
+        let mut a = assembler().with_limit(30);
+        let now = Instant::now();
+        a.push("first line of text\n", now);
+        let actions = a.push("second line of text\n", now);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("first line of text".to_string()),
+                Action::NewMessage("second line of text\n".to_string()),
+            ]
+        );
+        assert_eq!(a.text(), "first line of text\nsecond line of text\n");
+    }
+
+    #[test]
+    fn splits_at_a_word_without_line_breaks() {
+        let mut a = assembler().with_limit(20);
+        let actions = a.push("one two three four five six", Instant::now());
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("one two three four".to_string()),
+                Action::NewMessage("five six".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_prefer_paragraphs_then_list_items_then_sentences() {
+        let mut a = assembler().with_limit(40);
+        let actions = a.push(
+            "An intro that is long.\n\nSteps:\n- one\n- two\nAnd more",
+            Instant::now(),
+        );
+        assert_eq!(
+            actions[0],
+            Action::Edit("An intro that is long.".to_string())
+        );
+
+        let mut a = assembler().with_limit(30);
+        let actions = a.push("Try this:\n- unplug it\n- plug it back in", Instant::now());
+        assert_eq!(
+            actions[0],
+            Action::Edit("Try this:\n- unplug it".to_string())
+        );
+
+        let mut a = assembler().with_limit(30);
+        let actions = a.push("The first one works. Then it stops working", Instant::now());
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("The first one works.".to_string()),
+                Action::NewMessage("Then it stops working".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbered_lists_keep_counting_in_the_next_message() {
+        let mut a = assembler().with_limit(40);
+        let actions = a.push(
+            "Steps:\n1. Unplug it\n1. Hold the button\n1. Plug it in\n   while holding\n1. Wait\n\nDone.",
+            Instant::now(),
+        );
+        assert_eq!(
+            actions[..2],
+            [
+                Action::Edit("Steps:\n1. Unplug it\n1. Hold the button".to_string()),
+                Action::NewMessage("3. Plug it in\n   while holding\n4. Wait".to_string()),
+            ]
+        );
+        assert_eq!(
+            continue_numbering("Steps:\n5) one\n\n6) two", "1) three\nafter\n1) new"),
+            "7) three\nafter\n1) new"
+        );
+        assert_eq!(continue_numbering("No list", "1. one"), "1. one");
+        assert_eq!(continue_numbering("1. one", "- two"), "- two");
+    }
+
+    #[test]
+    fn splits_mid_word_when_there_is_nowhere_better() {
+        let mut a = assembler().with_limit(10);
+        let actions = a.push("abcdefghijklmno", Instant::now());
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("abcdefghij".to_string()),
+                Action::NewMessage("klmno".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_on_char_boundaries() {
+        let mut a = assembler().with_limit(5);
+        let actions = a.push("ééééé", Instant::now());
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("éé".to_string()),
+                Action::NewMessage("éé".to_string()),
+                Action::NewMessage("é".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn messages_are_only_posted_once_they_fit() {
+        let mut a = assembler().with_limit(10);
+        let mut actions = a.push(&"word ".repeat(8), Instant::now());
+        actions.extend(a.finish(""));
+        for action in &actions {
+            let (Action::Edit(text) | Action::NewMessage(text) | Action::Finalize(text)) = action;
+            assert!(text.len() <= 10, "{:?}", action);
+        }
+        assert_eq!(
+            actions[..3],
+            [
+                Action::Edit("word word".to_string()),
+                Action::NewMessage("word word".to_string()),
+                Action::NewMessage("word word".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_closed_and_reopened_across_messages() {
+        let mut a = assembler().with_limit(40);
+        let now = Instant::now();
+        a.push("Run:\n```sh\necho one\necho two\n", now);
+        let actions = a.push("echo three\n```\n", now);
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("Run:\n```sh\necho one\necho two\n```".to_string()),
+                Action::NewMessage("```sh\necho three\n```\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_closed_until_the_model_closes_them() {
+        let mut a = assembler();
+        let start = Instant::now();
+        assert_eq!(
+            a.push("Run:\n```sh\necho one", start),
+            vec![Action::Edit("Run:\n```sh\necho one\n```".to_string())]
+        );
+        assert_eq!(
+            a.push("\n```\nDone.", start + INTERVAL),
+            vec![Action::Edit(
+                "Run:\n```sh\necho one\n```\nDone.".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn footer_that_does_not_fit_takes_some_text_with_it() {
+        let mut a = assembler().with_limit(30);
+        a.push("line one\nline two\nline three", Instant::now());
+        let actions = a.finish("-# footer here");
+        assert_eq!(
+            actions,
+            vec![
+                Action::Edit("line one".to_string()),
+                Action::NewMessage("line two\nline three".to_string()),
+                Action::Edit("line two".to_string()),
+                Action::NewMessage("line three".to_string()),
+                Action::Finalize("line three\n-# footer here".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn open_fence_tracks_code_blocks() {
+        assert_eq!(open_fence("text"), None);
+        assert_eq!(open_fence("```rust\nfn main() {}"), Some("```rust"));
+        assert_eq!(open_fence("```\ncode\n```\nmore"), None);
+    }
+}
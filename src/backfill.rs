@@ -0,0 +1,168 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use serenity::all::{ChannelId, GetMessages, Message, UserId};
+use tracing::{info, warn};
+
+use crate::{config::Config, i18n, message_text, Data};
+
+/// Channels whose history has been read back since startup, or that were
+/// reset, so each is read at most once and a reset stays a reset
+#[derive(Default)]
+pub struct Backfilled {
+    channels: Mutex<HashSet<ChannelId>>,
+}
+
+impl Backfilled {
+    /// Whether `channel_id` is new, marking it done
+    pub fn first_time(&self, channel_id: ChannelId) -> bool {
+        self.channels.lock().unwrap().insert(channel_id)
+    }
+}
+
+/// Seeds the conversation in `batch`'s channel with the questions and answers
+/// before it, when the bot has no memory of one (as after a restart)
+pub async fn seed(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    config: &Config,
+    batch: &[Message],
+) {
+    let first = &batch[0];
+    if config.backfill_limit() == 0 || !data.backfilled.first_time(first.channel_id) {
+        return;
+    }
+    match data.ai_context.is_empty(first.channel_id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Failed to check the conversation: {}", e);
+            return;
+        }
+    }
+
+    let history = match first
+        .channel_id
+        .messages(
+            &ctx.http,
+            GetMessages::new()
+                .before(first.id)
+                .limit(config.backfill_limit()),
+        )
+        .await
+    {
+        Ok(history) => history,
+        Err(e) => {
+            warn!("Failed to read the channel history: {}", e);
+            return;
+        }
+    };
+    let persona = config
+        .guild(first.guild_id)
+        .and_then(|g| g.persona.as_ref())
+        .map(|p| p.name.as_str());
+    // Discord lists the newest first, and leaves the guild off
+    let history: Vec<_> = history
+        .into_iter()
+        .rev()
+        .map(|mut msg| {
+            msg.guild_id = first.guild_id;
+            msg
+        })
+        .collect();
+    let locale = config.locale(first.guild_id);
+    let bot_id = ctx.cache.current_user().id;
+    let bot = data.bot.as_deref();
+    let messages = conversation(&history, config, bot_id, bot, persona, &locale);
+    if messages.is_empty() {
+        return;
+    }
+    info!(
+        "Read back {} messages of conversation in {}",
+        messages.len(),
+        first.channel_id
+    );
+    for message in messages {
+        if let Err(e) = data.ai_context.push(first.channel_id, message).await {
+            warn!("Failed to save the channel history: {}", e);
+            return;
+        }
+    }
+}
+
+/// The questions to the bot in `history` (oldest first) and its answers, as
+/// they'd have been kept, since the last `/wack`. Answers split over several
+/// messages are joined again. `bot` names which of the config's `bots` this
+/// is, if any.
+pub fn conversation(
+    history: &[Message],
+    config: &Config,
+    bot_id: UserId,
+    bot: Option<&str>,
+    persona: Option<&str>,
+    locale: &str,
+) -> Vec<ChatCompletionRequestMessage> {
+    // left behind by answers that never came
+    let statuses =
+        ["generating", "generation-error", "triage-invalid"].map(|key| i18n::tr(locale, key, &[]));
+    let mut messages: Vec<ChatCompletionRequestMessage> = Vec::new();
+    for msg in history {
+        let from_bot = msg.author.id == bot_id
+            || msg.webhook_id.is_some() && persona == Some(msg.author.name.as_str());
+        if from_bot {
+            if let Some(command) = &msg.interaction {
+                if command.name == "wack" {
+                    messages.clear();
+                }
+                continue;
+            }
+            let text = answer_text(&msg.content);
+            if text.is_empty() || statuses.contains(&text) {
+                continue;
+            }
+            if let Some(ChatCompletionRequestMessage::Assistant(previous)) = messages.last_mut() {
+                if let Some(ChatCompletionRequestAssistantMessageContent::Text(previous)) =
+                    &mut previous.content
+                {
+                    previous.push('\n');
+                    previous.push_str(&text);
+                    continue;
+                }
+            }
+            messages.push(ChatCompletionRequestMessage::Assistant(
+                ChatCompletionRequestAssistantMessage {
+                    content: Some(ChatCompletionRequestAssistantMessageContent::Text(text)),
+                    ..Default::default()
+                },
+            ));
+        } else if config.is_question(msg, bot_id, bot) && !config.is_ignored(msg) {
+            messages.push(ChatCompletionRequestMessage::User(
+                ChatCompletionRequestUserMessage {
+                    content: ChatCompletionRequestUserMessageContent::Text(format!(
+                        "{} ({}): {}",
+                        msg.author.global_name.as_ref().unwrap_or(&msg.author.name),
+                        msg.author.id.get(),
+                        message_text::of(msg)
+                    )),
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+    messages
+}
+
+/// An answer as the model wrote it, without the footer and notes the bot adds
+fn answer_text(content: &str) -> String {
+    content
+        .lines()
+        .filter(|line| !line.starts_with("-# "))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
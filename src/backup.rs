@@ -0,0 +1,232 @@
+//! `deskhelp backup <file>` and `deskhelp restore <file>`: the config file
+//! and everything in storage (prompt versions, the search and duplicate
+//! embeddings, the knowledge base and the rest) in one `.tar.gz`, taken
+//! while the bot keeps running.
+//!
+//! SQLite storage is backed up as a copy of the database, other backends as
+//! JSON like `export` writes; either way text that was encrypted stays
+//! encrypted. Secrets (the encryption key, API keys, tokens and passwords in
+//! URLs) are left out of the config file, so the archive alone doesn't open
+//! the database or the accounts the bot uses, and only its owner can read it.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use toml_edit::{
+    visit_mut::{self, VisitMut},
+    DocumentMut, Formatted, Item, KeyMut, Value,
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::{Config, StorageConfig},
+    crypto::Cipher,
+    storage::{self, Dump, Error, SqliteStorage, Storage},
+};
+
+const CONFIG: &str = "config.toml";
+const DATABASE: &str = "storage.db";
+const DUMP: &str = "storage.json";
+
+/// Settings whose values are left out of the backed-up config file, in any table
+const SECRETS: &[&str] = &["api_key", "api_tokens", "encryption_key", "secret", "token"];
+
+/// Writes the backup of the storage in `config` to `path`
+pub async fn backup(config: &StorageConfig, path: &Path) -> Result<(), Error> {
+    let mut archive = tar::Builder::new(GzEncoder::new(
+        create_private(path)?,
+        Compression::default(),
+    ));
+    if let Ok(config_file) = std::fs::read_to_string(Config::path()) {
+        append(&mut archive, CONFIG, redact(&config_file)?.as_bytes())?;
+    }
+    if config.url.starts_with("sqlite:") {
+        let snapshot = scratch_file(path);
+        // left over from a backup that didn't finish
+        let _ = std::fs::remove_file(&snapshot);
+        SqliteStorage::connect(&config.url)
+            .await?
+            .snapshot(&snapshot)
+            .await?;
+        let appended = archive.append_path_with_name(&snapshot, DATABASE);
+        std::fs::remove_file(&snapshot)?;
+        appended?;
+    } else {
+        let dump = storage::connect(&config.url, Cipher::as_stored(), None)
+            .await?
+            .dump()
+            .await?;
+        append(&mut archive, DUMP, &serde_json::to_vec(&dump)?)?;
+    }
+    archive.into_inner()?.finish()?;
+    info!("Backed up {} to {}", config.url, path.display());
+    Ok(())
+}
+
+/// Replaces everything in `storage` with the backup's, and writes the
+/// backed-up config file unless there already is one
+pub async fn restore(
+    config: &StorageConfig,
+    storage: &dyn Storage,
+    path: &Path,
+) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+    let mut dump = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        match name.as_str() {
+            CONFIG => {
+                let config_path = Config::path();
+                if Path::new(&config_path).exists() {
+                    info!("Keeping the config file at {}", config_path);
+                } else {
+                    entry.unpack(&config_path)?;
+                    warn!(
+                        "Wrote the backed-up config to {}; fill in the secrets backups leave out",
+                        config_path
+                    );
+                }
+            }
+            DATABASE => {
+                // read through the storage layer, so it's restored into any backend
+                let snapshot = scratch_file(path);
+                entry.unpack(&snapshot)?;
+                let read = read_snapshot(config, &snapshot).await;
+                std::fs::remove_file(&snapshot)?;
+                dump = Some(read?);
+            }
+            DUMP => {
+                let mut json = vec![];
+                entry.read_to_end(&mut json)?;
+                let sealed = serde_json::from_slice::<Dump>(&json)?;
+                // decrypted through a SQLite copy like the one above, so it's
+                // encrypted again on its way into `storage`
+                let snapshot = scratch_file(path);
+                let _ = std::fs::remove_file(&snapshot);
+                let read = async {
+                    SqliteStorage::connect(&sqlite_url(&snapshot))
+                        .await?
+                        .with_cipher(Cipher::as_stored())
+                        .restore(&sealed)
+                        .await?;
+                    read_snapshot(config, &snapshot).await
+                }
+                .await;
+                std::fs::remove_file(&snapshot)?;
+                dump = Some(read?);
+            }
+            _ => {}
+        }
+    }
+    let dump = dump.ok_or("the backup has no storage in it")?;
+    storage.restore(&dump).await?;
+    info!(
+        "Restored {} answers and {} prompt versions from {}",
+        dump.answers.len(),
+        dump.prompt_versions.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Everything in the SQLite database at `snapshot`, decrypted with the key in `config`
+async fn read_snapshot(config: &StorageConfig, snapshot: &Path) -> Result<Dump, Error> {
+    let cipher = Cipher::from_key(config.encryption_key().as_deref())?;
+    SqliteStorage::connect(&sqlite_url(snapshot))
+        .await?
+        .with_cipher(cipher)
+        .dump()
+        .await
+}
+
+fn sqlite_url(path: &Path) -> String {
+    format!("sqlite://{}", path.display())
+}
+
+/// Creates `path` readable and writable by its owner only
+fn create_private(path: &Path) -> Result<File, Error> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        // `mode` only applies to files it creates
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    Ok(options.open(path)?)
+}
+
+/// The config file with the values of `SECRETS` blanked and passwords taken
+/// out of URLs
+pub fn redact(config: &str) -> Result<String, Error> {
+    let mut document = config.parse::<DocumentMut>()?;
+    Redact.visit_document_mut(&mut document);
+    Ok(document.to_string())
+}
+
+struct Redact;
+
+impl VisitMut for Redact {
+    fn visit_table_like_kv_mut(&mut self, key: KeyMut<'_>, node: &mut Item) {
+        if SECRETS.contains(&key.get()) {
+            Blank.visit_item_mut(node);
+        } else if key.get() == "url" {
+            if let Some(Value::String(url)) = node.as_value_mut() {
+                if let Ok(mut parsed) = reqwest::Url::parse(url.value()) {
+                    if parsed.password().is_some() {
+                        let _ = parsed.set_password(None);
+                        replace(url, parsed.into());
+                    }
+                }
+            }
+        } else {
+            visit_mut::visit_table_like_kv_mut(self, key, node);
+        }
+    }
+}
+
+/// Empties every string it visits
+struct Blank;
+
+impl VisitMut for Blank {
+    fn visit_string_mut(&mut self, node: &mut Formatted<String>) {
+        replace(node, String::new());
+    }
+}
+
+/// Swaps the string for `text`, keeping the comments around it
+fn replace(node: &mut Formatted<String>, text: String) {
+    let decor = node.decor().clone();
+    *node = Formatted::new(text);
+    *node.decor_mut() = decor;
+}
+
+fn append(
+    archive: &mut tar::Builder<GzEncoder<File>>,
+    name: &str,
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(time::OffsetDateTime::now_utc().unix_timestamp() as u64);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Where the database is copied to on its way in or out of the backup
+fn scratch_file(backup: &Path) -> PathBuf {
+    let mut name = backup.as_os_str().to_owned();
+    name.push(".db-partial");
+    PathBuf::from(name)
+}
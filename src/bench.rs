@@ -0,0 +1,257 @@
+//! `deskhelp bench <archive> [--mock]`: replays the conversations in an
+//! `/export` archive against the provider, or against a mock that streams the
+//! recorded answers back, and measures each answer's latency, tokens per
+//! second, and how many Discord calls posting it would take.
+
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestMessage, ChatCompletionResponseStream, CreateChatCompletionRequest,
+        CreateChatCompletionStreamResponse,
+    },
+};
+use futures::{Stream, StreamExt, TryStreamExt};
+
+use crate::{
+    assembler::{Pace, ResponseAssembler},
+    config::Config,
+    context::Contexts,
+    models::{self, Capabilities},
+    oai,
+    provider::ChatBackend,
+};
+
+/// How long the mock takes for each piece of an answer, about what a
+/// provider streaming 50 tokens a second would
+const MOCK_CHUNK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Characters in each piece the mock streams
+const MOCK_CHUNK_SIZE: usize = 4;
+
+/// A question to ask again, with the conversation before it
+pub struct Turn {
+    pub history: Vec<ChatCompletionRequestMessage>,
+    /// What the bot answered when it was recorded
+    pub answer: Option<String>,
+}
+
+/// Each question in `contexts`, in channel order
+pub fn turns(contexts: &Contexts) -> Vec<Turn> {
+    let mut channels: Vec<_> = contexts.iter().collect();
+    channels.sort_by_key(|(channel, _)| channel.as_str());
+    let mut turns = vec![];
+    for (_, messages) in channels {
+        for (i, message) in messages.iter().enumerate() {
+            if !matches!(message, ChatCompletionRequestMessage::User(_)) {
+                continue;
+            }
+            let answer = match messages.get(i + 1) {
+                Some(answer @ ChatCompletionRequestMessage::Assistant(_)) => {
+                    Some(oai::message_text(answer))
+                }
+                _ => None,
+            };
+            turns.push(Turn {
+                history: messages[..=i].to_vec(),
+                answer,
+            });
+        }
+    }
+    turns
+}
+
+/// How one answer went
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub first_token: Option<Duration>,
+    pub total: Duration,
+    pub completion_tokens: usize,
+    /// Messages posted and edits made to show the answer as it streamed
+    pub discord_calls: usize,
+}
+
+impl Sample {
+    pub fn tokens_per_second(&self) -> f64 {
+        self.completion_tokens as f64 / self.total.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub samples: Vec<Sample>,
+    /// Answers that failed to start or broke off
+    pub failures: usize,
+}
+
+impl Report {
+    /// Medians and 95th percentiles of each measure
+    pub fn summary(&self) -> String {
+        if self.samples.is_empty() {
+            return format!("No answers finished ({} failed)", self.failures);
+        }
+        let stat = |mut values: Vec<f64>| {
+            values.sort_by(f64::total_cmp);
+            let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+            (at(0.5), at(0.95))
+        };
+        let first_tokens: Vec<f64> = self
+            .samples
+            .iter()
+            .filter_map(|s| s.first_token)
+            .map(|d| d.as_secs_f64())
+            .collect();
+        let first_token = if first_tokens.is_empty() {
+            "none".to_string()
+        } else {
+            let (median, p95) = stat(first_tokens);
+            format!("{:.3}s / {:.3}s", median, p95)
+        };
+        let total = stat(self.samples.iter().map(|s| s.total.as_secs_f64()).collect());
+        let speed = stat(self.samples.iter().map(Sample::tokens_per_second).collect());
+        let calls = stat(
+            self.samples
+                .iter()
+                .map(|s| s.discord_calls as f64)
+                .collect(),
+        );
+        format!(
+            "{} answers, {} failed (median / p95)\n\
+             first token: {}\n\
+             whole answer: {:.3}s / {:.3}s\n\
+             tokens per second: {:.1} / {:.1}\n\
+             Discord calls per answer: {} / {}",
+            self.samples.len(),
+            self.failures,
+            first_token,
+            total.0,
+            total.1,
+            speed.0,
+            speed.1,
+            calls.0,
+            calls.1,
+        )
+    }
+}
+
+/// Answers each of `turns` in order, with the built-in instructions, from
+/// `backend`, or from a mock of each recorded answer if it's `None`
+pub async fn run(
+    config: &Config,
+    backend: Option<&dyn ChatBackend>,
+    model: &str,
+    turns: &[Turn],
+) -> Report {
+    let capabilities = Capabilities::of(config, model);
+    let mut report = Report::default();
+    for turn in turns {
+        let (messages, _) = oai::build_prompt(
+            oai::SYSTEM_MESSAGE.to_string(),
+            &turn.history,
+            capabilities.context_tokens.unwrap_or_else(oai::token_limit),
+            &[],
+        );
+        let request = oai::chat_request(models::resolve(config, model), &capabilities, messages);
+        let mock;
+        let backend = match backend {
+            Some(backend) => backend,
+            None => {
+                mock = MockBackend {
+                    answer: turn.answer.clone().unwrap_or_default(),
+                };
+                &mock as &dyn ChatBackend
+            }
+        };
+        match answer(backend, request).await {
+            Some(sample) => report.samples.push(sample),
+            None => report.failures += 1,
+        }
+    }
+    report
+}
+
+/// Streams one answer as the bot would, counting what it'd post to Discord
+async fn answer(backend: &dyn ChatBackend, request: CreateChatCompletionRequest) -> Option<Sample> {
+    let start = Instant::now();
+    let mut stream = backend.create_stream(request).await.ok()?;
+    let mut assembler = ResponseAssembler::new(|text: &str| text.to_string(), Pace::Adaptive);
+    // the "thinking" message answers are edited into
+    let mut discord_calls = 1;
+    let mut first_token = None;
+    loop {
+        let chunk = stream.try_next().await.ok()??;
+        let Some(choice) = chunk.choices.first() else {
+            continue;
+        };
+        if let Some(content) = choice.delta.content.as_deref().filter(|c| !c.is_empty()) {
+            first_token.get_or_insert_with(|| start.elapsed());
+            discord_calls += assembler.push(content, Instant::now()).len();
+        }
+        if choice.finish_reason.is_some() {
+            break;
+        }
+    }
+    discord_calls += assembler.finish("").len();
+    let total = start.elapsed();
+    let completion_tokens = oai::count_tokens(&ChatCompletionRequestMessage::Assistant(
+        ChatCompletionRequestAssistantMessage {
+            content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                assembler.text().to_string(),
+            )),
+            ..Default::default()
+        },
+    ));
+    Some(Sample {
+        first_token,
+        total,
+        completion_tokens,
+        discord_calls,
+    })
+}
+
+/// Streams `answer` back a few characters at a time, whatever it's asked
+pub struct MockBackend {
+    pub answer: String,
+}
+
+#[serenity::async_trait]
+impl ChatBackend for MockBackend {
+    async fn create_stream(
+        &self,
+        _request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let chars: Vec<char> = self.answer.chars().collect();
+        let mut pieces: Vec<Option<String>> = chars
+            .chunks(MOCK_CHUNK_SIZE)
+            .map(|piece| Some(piece.iter().collect()))
+            .collect();
+        // the last chunk says the answer is done
+        pieces.push(None);
+        let stream = futures::stream::iter(pieces).then(|piece| async move {
+            tokio::time::sleep(MOCK_CHUNK_INTERVAL).await;
+            chunk(piece)
+        });
+        Ok(Box::pin(stream) as Pin<Box<dyn Stream<Item = _> + Send>>)
+    }
+}
+
+fn chunk(content: Option<String>) -> Result<CreateChatCompletionStreamResponse, OpenAIError> {
+    let finish_reason = content.is_none().then_some("stop");
+    serde_json::from_value(serde_json::json!({
+        "id": "bench",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "mock",
+        "choices": [{
+            "index": 0,
+            "delta": { "content": content },
+            "finish_reason": finish_reason,
+        }],
+    }))
+    .map_err(OpenAIError::JSONDeserialize)
+}
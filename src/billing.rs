@@ -0,0 +1,185 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serenity::all::{ChannelId, GuildId, Http};
+use time::{OffsetDateTime, Time};
+use tracing::{info, warn};
+
+use crate::{
+    budget, i18n,
+    models::ModelConfig,
+    storage::{Error, Usage},
+    Data,
+};
+
+/// How often to add up the day's spending
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How many channels an alert lists
+const TOP_CHANNELS: usize = 5;
+/// Where the last alert is remembered, as `<date> <threshold>`
+const ALERTED_KEY: &str = "billing_alert";
+
+/// What answers cost so far today
+#[derive(Debug, Default, PartialEq)]
+pub struct Spend {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// US dollars, for the tokens of models with prices
+    pub cost: f64,
+    /// Tokens of models without prices, left out of `cost`
+    pub unpriced_tokens: u64,
+    /// Where most was spent, most first
+    pub channels: Vec<ChannelSpend>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ChannelSpend {
+    pub guild_id: GuildId,
+    pub channel_id: Option<ChannelId>,
+    pub cost: f64,
+    pub tokens: u64,
+}
+
+/// DMs the bot's owners each time the day's spending passes one of
+/// `[billing_alerts]` thresholds
+pub fn spawn_billing_alerts(http: Arc<Http>, data: Arc<Data>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = check(&http, &data).await {
+                warn!("Failed to check spending: {}", e);
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+async fn check(http: &Http, data: &Data) -> Result<(), Error> {
+    let config = data.config();
+    let Some(alerts) = &config.billing_alerts else {
+        return Ok(());
+    };
+    let now = OffsetDateTime::now_utc();
+    let today = now.date().to_string();
+    let usage = data.storage.usage(now.replace_time(Time::MIDNIGHT)).await?;
+    let spend = spend(&usage, &config.models);
+
+    let alerted = data
+        .storage
+        .setting(ALERTED_KEY)
+        .await?
+        .and_then(|alerted| {
+            // a new day starts over
+            let (date, threshold) = alerted.split_once(' ')?;
+            (date == today).then_some(threshold)?.parse().ok()
+        });
+    let Some(threshold) = crossed(&alerts.thresholds, spend.cost, alerted) else {
+        return Ok(());
+    };
+    data.storage
+        .set_setting(ALERTED_KEY, Some(&format!("{} {}", today, threshold)))
+        .await?;
+    info!(
+        "Spending today passed ${}: ${:.2} so far",
+        threshold, spend.cost
+    );
+    let content = summary(&config.locale(None), threshold, &spend);
+    budget::dm_owners(http, &content).await;
+    Ok(())
+}
+
+/// Adds up what `usage` cost at the prices in `models`
+pub fn spend(usage: &[Usage], models: &HashMap<String, ModelConfig>) -> Spend {
+    let mut spend = Spend::default();
+    let mut channels: HashMap<(GuildId, Option<ChannelId>), ChannelSpend> = HashMap::new();
+    for u in usage {
+        spend.prompt_tokens += u.prompt_tokens;
+        spend.completion_tokens += u.completion_tokens;
+        let tokens = u.prompt_tokens + u.completion_tokens;
+        let prices = u
+            .model
+            .as_ref()
+            .and_then(|model| models.get(model))
+            .and_then(|m| Some((m.input_price?, m.output_price?)));
+        let cost = match prices {
+            Some((input, output)) => {
+                (u.prompt_tokens as f64 * input + u.completion_tokens as f64 * output) / 1e6
+            }
+            None => {
+                spend.unpriced_tokens += tokens;
+                0.0
+            }
+        };
+        spend.cost += cost;
+        let channel = channels
+            .entry((u.guild_id, u.channel_id))
+            .or_insert(ChannelSpend {
+                guild_id: u.guild_id,
+                channel_id: u.channel_id,
+                cost: 0.0,
+                tokens: 0,
+            });
+        channel.cost += cost;
+        channel.tokens += tokens;
+    }
+    spend.channels = channels.into_values().collect();
+    spend.channels.sort_by(|a, b| {
+        b.cost
+            .total_cmp(&a.cost)
+            .then(b.tokens.cmp(&a.tokens))
+            .then(a.channel_id.cmp(&b.channel_id))
+    });
+    spend.channels.truncate(TOP_CHANNELS);
+    spend
+}
+
+/// The highest threshold `cost` has reached, if it's above the one already alerted about
+pub fn crossed(thresholds: &[f64], cost: f64, alerted: Option<f64>) -> Option<f64> {
+    thresholds
+        .iter()
+        .copied()
+        .filter(|&threshold| cost >= threshold && alerted.is_none_or(|a| threshold > a))
+        .max_by(f64::total_cmp)
+}
+
+/// The alert sent to owners
+pub fn summary(locale: &str, threshold: f64, spend: &Spend) -> String {
+    let mut lines = vec![i18n::tr(
+        locale,
+        "billing-alert",
+        &[
+            ("threshold", format!("{:.2}", threshold).into()),
+            ("cost", format!("{:.2}", spend.cost).into()),
+            ("prompt", spend.prompt_tokens.into()),
+            ("completion", spend.completion_tokens.into()),
+        ],
+    )];
+    if spend.unpriced_tokens > 0 {
+        lines.push(i18n::tr(
+            locale,
+            "billing-unpriced",
+            &[("tokens", spend.unpriced_tokens.into())],
+        ));
+    }
+    if !spend.channels.is_empty() {
+        lines.push(i18n::tr(locale, "billing-top-channels", &[]));
+        for channel in &spend.channels {
+            let name = match channel.channel_id {
+                Some(channel_id) => format!("<#{}>", channel_id),
+                None => i18n::tr(
+                    locale,
+                    "billing-unknown-channel",
+                    &[("guild", channel.guild_id.to_string().into())],
+                ),
+            };
+            lines.push(i18n::tr(
+                locale,
+                "billing-channel",
+                &[
+                    ("channel", name.into()),
+                    ("cost", format!("{:.2}", channel.cost).into()),
+                    ("tokens", channel.tokens.into()),
+                ],
+            ));
+        }
+    }
+    lines.join("\n")
+}
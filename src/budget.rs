@@ -0,0 +1,134 @@
+use serenity::all::{CreateMessage, GuildId, Http, UserId};
+use time::{Date, OffsetDateTime, Time};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    i18n,
+    storage::{Error, Storage},
+    Data,
+};
+
+/// A guild that's used up its monthly tokens
+#[derive(Debug, PartialEq)]
+pub struct OverBudget {
+    pub used: u64,
+    pub cap: u64,
+    /// When the next month starts and answers do too
+    pub resets_at: OffsetDateTime,
+}
+
+/// Whether the guild has used up its `monthly_token_cap` this month
+pub async fn check(
+    data: &Data,
+    config: &Config,
+    guild_id: GuildId,
+) -> Result<Option<OverBudget>, Error> {
+    let Some(cap) = config.monthly_token_cap(Some(guild_id)) else {
+        return Ok(None);
+    };
+    let now = OffsetDateTime::now_utc();
+    let stats = data.storage.guild_stats(guild_id, month_start(now)).await?;
+    let used = stats.prompt_tokens + stats.completion_tokens;
+    Ok((used >= cap).then(|| OverBudget {
+        used,
+        cap,
+        resets_at: next_month_start(now),
+    }))
+}
+
+/// Midnight UTC on the first of `now`'s month
+pub fn month_start(now: OffsetDateTime) -> OffsetDateTime {
+    now.replace_day(1).unwrap().replace_time(Time::MIDNIGHT)
+}
+
+/// Midnight UTC on the first of the month after `now`'s
+pub fn next_month_start(now: OffsetDateTime) -> OffsetDateTime {
+    let (year, month) = match now.month() {
+        time::Month::December => (now.year() + 1, time::Month::January),
+        month => (now.year(), month.next()),
+    };
+    Date::from_calendar_date(year, month, 1)
+        .unwrap()
+        .midnight()
+        .assume_utc()
+}
+
+/// Whether this is the first time this month the guild is over its cap,
+/// remembering that it's been told. Storage errors count as not, so nobody's
+/// told over and over.
+pub async fn first_time_over(storage: &dyn Storage, guild_id: GuildId, over: &OverBudget) -> bool {
+    let key = format!("token_cap_notified.{}", guild_id);
+    // the month is told apart by when it ends
+    let month = over.resets_at.unix_timestamp().to_string();
+    match storage.setting(&key).await {
+        Ok(Some(notified)) if notified == month => return false,
+        Ok(_) => {}
+        Err(e) => {
+            warn!("Failed to read when the token cap was last reported: {}", e);
+            return false;
+        }
+    }
+    if let Err(e) = storage.set_setting(&key, Some(&month)).await {
+        warn!("Failed to remember reporting the token cap: {}", e);
+        return false;
+    }
+    true
+}
+
+/// Tells the bot's owners the guild hit its cap; see `first_time_over` for
+/// doing it once a month
+pub async fn notify_owners(http: &Http, data: &Data, guild_id: GuildId, over: &OverBudget) {
+    info!(
+        "Guild {} used {} of its {} monthly tokens; not answering until next month",
+        guild_id, over.used, over.cap
+    );
+
+    let guild_name = guild_id
+        .to_partial_guild(http)
+        .await
+        .map(|guild| guild.name)
+        .unwrap_or_else(|_| guild_id.to_string());
+    let content = i18n::tr(
+        &data.config().locale(None),
+        "token-cap-owner",
+        &[
+            ("guild", guild_name.into()),
+            ("used", over.used.into()),
+            ("cap", over.cap.into()),
+        ],
+    );
+    dm_owners(http, &content).await;
+}
+
+/// Sends `content` to each of the bot's owners
+pub async fn dm_owners(http: &Http, content: &str) {
+    let owners = match owners(http).await {
+        Ok(owners) => owners,
+        Err(e) => {
+            warn!("Failed to look up the bot's owners: {}", e);
+            return;
+        }
+    };
+    for owner in owners {
+        let sent = match owner.create_dm_channel(http).await {
+            Ok(dm) => dm
+                .send_message(http, CreateMessage::new().content(content))
+                .await
+                .map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = sent {
+            warn!("Failed to DM owner {}: {}", owner, e);
+        }
+    }
+}
+
+/// Who owns the bot's application: its owner, or its team's members
+async fn owners(http: &Http) -> serenity::Result<Vec<UserId>> {
+    let info = http.get_current_application_info().await?;
+    Ok(match info.team {
+        Some(team) => team.members.into_iter().map(|m| m.user.id).collect(),
+        None => info.owner.into_iter().map(|owner| owner.id).collect(),
+    })
+}
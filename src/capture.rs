@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::reporting;
+
+/// One request to a provider and what came back, redacted
+#[derive(Serialize, Clone, Debug)]
+pub struct Capture {
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    /// Label of the endpoint it went to
+    pub endpoint: String,
+    /// The JSON body, as sent
+    pub request: Value,
+    /// Each streamed chunk, or the whole response for requests that don't stream
+    pub response: Vec<Value>,
+    pub error: Option<String>,
+}
+
+/// The last few requests to providers and their responses, for debugging
+/// provider-specific formatting. Kept in memory only.
+pub struct Captures {
+    limit: usize,
+    captures: Mutex<VecDeque<Arc<Mutex<Capture>>>>,
+}
+
+impl Captures {
+    pub fn new(limit: usize) -> Captures {
+        Captures {
+            limit,
+            captures: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Starts capturing a request, forgetting the oldest one if there are too many
+    pub fn start(&self, endpoint: &str, request: &Value) -> Recording {
+        let capture = Arc::new(Mutex::new(Capture {
+            at: OffsetDateTime::now_utc(),
+            endpoint: endpoint.to_string(),
+            request: redact(request),
+            response: vec![],
+            error: None,
+        }));
+        let mut captures = self.captures.lock().unwrap();
+        captures.push_back(capture.clone());
+        while captures.len() > self.limit {
+            captures.pop_front();
+        }
+        Recording(capture)
+    }
+
+    /// Every capture kept, oldest first
+    pub fn all(&self) -> Vec<Capture> {
+        self.captures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|capture| capture.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+/// Where a response is captured as it comes in
+#[derive(Clone)]
+pub struct Recording(Arc<Mutex<Capture>>);
+
+impl Recording {
+    pub fn response(&self, response: &impl Serialize) {
+        if let Ok(response) = serde_json::to_value(response) {
+            self.0.lock().unwrap().response.push(redact(&response));
+        }
+    }
+
+    pub fn error(&self, error: &impl ToString) {
+        self.0.lock().unwrap().error = Some(reporting::scrub(&error.to_string()));
+    }
+}
+
+/// `value` with every string scrubbed of mentions, ids, emails and secrets
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(reporting::scrub(s)),
+        Value::Array(values) => Value::Array(values.iter().map(redact).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), redact(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
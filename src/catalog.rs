@@ -0,0 +1,79 @@
+use std::{collections::HashMap, sync::RwLock, time::Duration};
+
+use serenity::all::GuildId;
+use tracing::{info, warn};
+
+use crate::{
+    provider::Providers,
+    storage::{Error, Storage},
+};
+
+/// How long listing one provider's models may take
+const LIST_TIMEOUT: Duration = Duration::from_secs(15);
+/// Discord shows at most this many autocomplete choices
+const MAX_CHOICES: usize = 25;
+/// Where models picked with /model are kept, as JSON keyed by guild id
+const PICKED_KEY: &str = "picked_models";
+
+/// The models each provider lists at its `/models` endpoint, for /model to offer
+#[derive(Default)]
+pub struct ModelCatalog(RwLock<HashMap<String, Vec<String>>>);
+
+impl ModelCatalog {
+    /// Lists every provider's models again, keeping the last list of any that
+    /// fail, and returns how many models are known now
+    pub async fn refresh(&self, providers: &Providers) -> usize {
+        for (name, provider) in providers.iter() {
+            match tokio::time::timeout(LIST_TIMEOUT, provider.models()).await {
+                Ok(Ok(models)) => {
+                    info!("Provider {} serves {} models", name, models.len());
+                    self.0.write().unwrap().insert(name.to_string(), models);
+                }
+                Ok(Err(e)) => warn!("Failed to list the models of provider {}: {}", name, e),
+                Err(_) => warn!("Listing the models of provider {} timed out", name),
+            }
+        }
+        self.0.read().unwrap().values().map(Vec::len).sum()
+    }
+
+    /// The provider's models, if it's listed them
+    pub fn models(&self, provider: &str) -> Option<Vec<String>> {
+        self.0.read().unwrap().get(provider).cloned()
+    }
+
+    /// The provider's models with `partial` in their name, ignoring case, as
+    /// many as Discord shows
+    pub fn suggest(&self, provider: &str, partial: &str) -> Vec<String> {
+        let partial = partial.to_lowercase();
+        self.models(provider)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|model| model.to_lowercase().contains(&partial))
+            .take(MAX_CHOICES)
+            .collect()
+    }
+}
+
+/// Picks the models last picked with /model again, after a restart
+pub async fn restore(storage: &dyn Storage, providers: &Providers) -> Result<(), Error> {
+    let Some(picked) = storage.setting(PICKED_KEY).await? else {
+        return Ok(());
+    };
+    let picked: HashMap<String, String> = serde_json::from_str(&picked)?;
+    for (guild_id, model) in picked {
+        providers.pick_model(GuildId::new(guild_id.parse()?), Some(model));
+    }
+    Ok(())
+}
+
+/// Remembers the models picked with /model for the next start
+pub async fn save(storage: &dyn Storage, providers: &Providers) -> Result<(), Error> {
+    let picked: HashMap<String, String> = providers
+        .picked_models()
+        .into_iter()
+        .map(|(guild_id, model)| (guild_id.to_string(), model))
+        .collect();
+    storage
+        .set_setting(PICKED_KEY, Some(&serde_json::to_string(&picked)?))
+        .await
+}
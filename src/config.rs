@@ -0,0 +1,995 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use serenity::all::{ChannelId, GuildId, Message, UserId};
+use tracing::{error, info};
+
+use crate::{i18n, links::LinkPolicy, models::ModelConfig};
+
+/// Name of the provider built from the `OPENAI_*` environment variables
+pub const DEFAULT_PROVIDER: &str = "default";
+
+/// How often to look for changes to the config file
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The current config, swapped out whenever the file changes
+pub type SharedConfig = Arc<RwLock<Arc<Config>>>;
+
+/// Owner-controlled settings, read from `config.toml` (or `DESKHELP_CONFIG`)
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// How many responses may be generated at once; the rest wait in line
+    pub max_concurrent_requests: usize,
+    /// Megabytes of memory the bot should get by with, on hosts shared with
+    /// other things. Read at startup; see [`MemoryBudget`].
+    pub memory_budget_mb: Option<u64>,
+    /// Named API providers, in addition to the default one from the environment
+    pub providers: HashMap<String, ProviderConfig>,
+    /// What models accept, where the built-in table doesn't know, keyed by model name
+    pub models: HashMap<String, ModelConfig>,
+    /// Per-guild settings, keyed by guild id
+    pub guilds: HashMap<String, GuildConfig>,
+    /// Render display math in answers to images
+    pub latex: Option<LatexConfig>,
+    /// Cut long code blocks in answers short, sharing them whole as a paste or file
+    pub paste: Option<PasteConfig>,
+    /// Read the text in screenshots and add it to the question
+    pub ocr: Option<OcrConfig>,
+    /// Errors with a known fix, answered without asking the model
+    pub known_errors: Vec<KnownError>,
+    /// What to do with links in answers that aren't in the prompt or allowed below
+    pub link_policy: LinkPolicy,
+    /// Extra links (or link prefixes) answers may contain, in every guild
+    pub allowed_links: Vec<String>,
+    /// Where to report errors and panics
+    pub error_reporting: Option<ErrorReportingConfig>,
+    /// Log to a rotating file as well as stdout
+    pub log_file: Option<LogFileConfig>,
+    /// Generate and log answers without posting them, everywhere
+    pub dry_run: bool,
+    /// Tokens each guild may use a month, prompt and completion together,
+    /// before the bot stops answering there
+    pub monthly_token_cap: Option<u64>,
+    /// DMs the bot's owners when a day's spending gets high
+    pub billing_alerts: Option<BillingAlertsConfig>,
+    /// How many of the latest requests to providers to keep, with their
+    /// responses, for `/captures`; 0 keeps none
+    pub capture_requests: usize,
+    /// How many earlier messages to read back from Discord for a conversation
+    /// the bot has no memory of, as after a restart; 0 to start fresh
+    pub backfill_messages: u8,
+    /// Channels to answer every message in, not just mentions
+    pub autorespond_channels: Vec<u64>,
+    /// Remember what's said in some channels without the bot, for context when it's asked
+    pub lurk: Option<LurkConfig>,
+    /// Answer direct messages, every one of them as a question. Off by default,
+    /// since they don't count toward any guild's `monthly_token_cap`.
+    pub direct_messages: bool,
+    /// Answer in the text chats of voice and stage channels
+    pub voice_channels: bool,
+    /// Channels the bot never answers in, even when mentioned
+    pub ignored_channels: Vec<u64>,
+    /// Users the bot never answers
+    pub ignored_users: Vec<u64>,
+    /// Starts prefix commands, like `~ping`. Messages starting with it aren't
+    /// questions, even in autorespond channels and DMs. Empty leaves only
+    /// mentioning the bot as a prefix.
+    pub prefix: String,
+    /// Language the bot's own messages are in, like `en-US` or `de`
+    pub locale: String,
+    /// Folder of extra `<locale>.ftl` translations, read at startup
+    pub locales_dir: Option<String>,
+    /// IANA timezone the model is told the time in, like `Europe/Berlin`
+    pub timezone: String,
+    /// Template for the line under answers, instead of the translated one.
+    /// Empty leaves it off.
+    pub footer: Option<String>,
+    /// Cheaper model, from the guild's provider, for naming the threads the bot
+    /// opens; the guild's model if unset
+    pub title_model: Option<String>,
+    /// Where usage stats and other state are kept
+    pub storage: StorageConfig,
+    /// Keep conversations and endpoint cooldowns in Redis, to share them between instances
+    pub redis: Option<RedisConfig>,
+    /// A web dashboard for operators, behind a token
+    pub dashboard: Option<DashboardConfig>,
+    /// GitHub webhooks, taken on the dashboard's server
+    pub github: Option<GithubConfig>,
+    /// File with the instructions at the start of the system prompt, instead of
+    /// the built-in ones. Edits are picked up while running and saved as versions.
+    pub prompt_file: Option<String>,
+    /// Splits questions between prompt and model variants to compare them
+    pub experiment: Option<ExperimentConfig>,
+    /// Lets the model search a public Trello board, for questions about what's planned
+    pub trello: Option<TrelloConfig>,
+    /// Tools loaded from WebAssembly modules in a folder at startup
+    pub wasm_tools: Option<WasmToolsConfig>,
+    /// A Rhai script hooked into answering, to filter, rewrite or route
+    pub scripts: Option<ScriptsConfig>,
+    /// Where every finished question and answer is POSTed, for analytics or archiving
+    pub activity_webhook: Option<ActivityWebhookConfig>,
+    /// A NATS server to publish questions, answers, feedback and errors to
+    pub events: Option<EventsConfig>,
+    /// GitHub repositories whose latest release `/version` and the model look up
+    pub releases: ReleasesConfig,
+    /// Points askers to earlier answers to questions like theirs
+    pub duplicates: Option<DuplicatesConfig>,
+    /// Keeps finished answers searchable with `/search`
+    pub search: Option<SearchConfig>,
+    /// Shows the model answers people vouched for with reactions. Needs `[search]`.
+    pub starboard: Option<StarboardConfig>,
+    /// How long stored data is kept before it's purged
+    pub retention: RetentionConfig,
+    /// Steps `/troubleshoot` walks people through before asking the model
+    pub troubleshoot: Option<TroubleshootNode>,
+    /// More bots to run from this process, each with its own token and persona
+    pub bots: Vec<BotConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_concurrent_requests: 4,
+            memory_budget_mb: None,
+            providers: HashMap::new(),
+            models: HashMap::new(),
+            guilds: HashMap::new(),
+            latex: None,
+            paste: None,
+            ocr: None,
+            known_errors: vec![],
+            link_policy: LinkPolicy::default(),
+            allowed_links: vec![],
+            error_reporting: None,
+            log_file: None,
+            dry_run: false,
+            monthly_token_cap: None,
+            billing_alerts: None,
+            capture_requests: 0,
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            locales_dir: None,
+            timezone: "UTC".to_string(),
+            footer: None,
+            title_model: None,
+            storage: StorageConfig::default(),
+            redis: None,
+            dashboard: None,
+            github: None,
+            prompt_file: None,
+            experiment: None,
+            trello: None,
+            wasm_tools: None,
+            scripts: None,
+            activity_webhook: None,
+            events: None,
+            releases: ReleasesConfig::default(),
+            duplicates: None,
+            search: None,
+            starboard: None,
+            retention: RetentionConfig::default(),
+            troubleshoot: None,
+            bots: vec![],
+            backfill_messages: 20,
+            lurk: None,
+            autorespond_channels: vec![],
+            direct_messages: false,
+            voice_channels: true,
+            ignored_channels: vec![],
+            ignored_users: vec![],
+            prefix: "~".to_string(),
+        }
+    }
+}
+
+/// What the bot keeps within `memory_budget_mb`: shorter conversations and
+/// fewer of them, no caches between questions, and a small SQLite page cache
+/// on a single connection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryBudget {
+    pub megabytes: u64,
+}
+
+impl MemoryBudget {
+    /// Messages kept of each channel's conversation, newest first
+    pub fn context_messages(&self) -> usize {
+        (self.megabytes / 4).clamp(8, 64) as usize
+    }
+
+    /// Channels whose conversations are kept; the longest idle go first
+    pub fn conversations(&self) -> usize {
+        (self.megabytes * 2).clamp(16, 1000) as usize
+    }
+
+    /// Size of SQLite's page cache, in KiB
+    pub fn sqlite_cache_kib(&self) -> u64 {
+        (self.megabytes * 16).clamp(256, 8192)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ErrorReportingConfig {
+    /// Sentry DSN; needs the `sentry` feature
+    pub sentry_dsn: Option<String>,
+    /// Generic sink: every report is POSTed here as JSON
+    pub webhook_url: Option<String>,
+    /// Fraction of errors to report, from 0 to 1
+    pub sample_rate: f32,
+    pub environment: Option<String>,
+}
+
+impl Default for ErrorReportingConfig {
+    fn default() -> ErrorReportingConfig {
+        ErrorReportingConfig {
+            sentry_dsn: None,
+            webhook_url: None,
+            sample_rate: 1.0,
+            environment: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LogFileConfig {
+    /// Path of the current log file; rotated files get a suffix
+    pub path: String,
+    pub rotation: LogRotation,
+    /// Size at which the file rotates, for `rotation = "size"`
+    pub max_size_mb: u64,
+    /// How many rotated files to keep
+    pub max_files: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> LogFileConfig {
+        LogFileConfig {
+            path: "logs/deskhelp.log".to_string(),
+            rotation: LogRotation::default(),
+            max_size_mb: 10,
+            max_files: 7,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Database to connect to, like `sqlite://deskhelp.db`
+    pub url: String,
+    /// Base64 AES-256 key to encrypt stored questions, answers and
+    /// conversations with; `DESKHELP_ENCRYPTION_KEY` takes precedence
+    pub encryption_key: Option<String>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> StorageConfig {
+        StorageConfig {
+            url: "sqlite://deskhelp.db".to_string(),
+            encryption_key: None,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// The key stored text is encrypted with, from the environment or the config
+    pub fn encryption_key(&self) -> Option<String> {
+        env::var("DESKHELP_ENCRYPTION_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())
+            .or_else(|| self.encryption_key.clone())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DashboardConfig {
+    /// Address to serve it on; needs the `dashboard` feature
+    pub listen: String,
+    /// Asked for on every page, as a `token` query parameter or a bearer token
+    pub token: Option<String>,
+    /// Tokens for the API under `/api/v1`, by who uses them. The dashboard's
+    /// token works there too.
+    pub api_tokens: BTreeMap<String, String>,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> DashboardConfig {
+        DashboardConfig {
+            listen: "127.0.0.1:8080".to_string(),
+            token: None,
+            api_tokens: BTreeMap::new(),
+        }
+    }
+}
+
+impl DashboardConfig {
+    /// The dashboard's token, from the environment or the config
+    pub fn token(&self) -> Option<String> {
+        env::var("DESKHELP_DASHBOARD_TOKEN")
+            .ok()
+            .or_else(|| self.token.clone())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GithubConfig {
+    /// What webhooks are signed with; they're turned away without it
+    pub secret: Option<String>,
+    /// Issues given this label are posted as questions
+    pub label: String,
+    /// `owner/name` of the repositories to take webhooks from; any, if empty
+    pub repos: Vec<String>,
+}
+
+impl Default for GithubConfig {
+    fn default() -> GithubConfig {
+        GithubConfig {
+            secret: None,
+            label: "question".to_string(),
+            repos: vec![],
+        }
+    }
+}
+
+impl GithubConfig {
+    /// The webhook secret, from the environment or the config
+    pub fn secret(&self) -> Option<String> {
+        env::var("DESKHELP_GITHUB_SECRET")
+            .ok()
+            .or_else(|| self.secret.clone())
+            .filter(|secret| !secret.is_empty())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ReleasesConfig {
+    /// `owner/name` of each repository, by what to call it; empty to turn releases off
+    pub repos: BTreeMap<String, String>,
+}
+
+impl Default for ReleasesConfig {
+    fn default() -> ReleasesConfig {
+        ReleasesConfig {
+            repos: [
+                ("server", "ItsRiprod/DeskThing"),
+                ("client", "ItsRiprod/deskthing-client"),
+            ]
+            .into_iter()
+            .map(|(name, repo)| (name.to_string(), repo.to_string()))
+            .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RedisConfig {
+    /// Server to connect to, like `redis://127.0.0.1/`; needs the `redis` feature
+    pub url: String,
+    /// Put in front of every key, so instances sharing a server can keep apart
+    #[serde(default = "default_key_prefix")]
+    pub key_prefix: String,
+}
+
+fn default_key_prefix() -> String {
+    "deskhelp:".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ExperimentConfig {
+    /// Answers are tagged with this; a new name starts a fresh comparison
+    pub name: String,
+    pub variants: Vec<VariantConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct VariantConfig {
+    pub name: String,
+    /// Share of questions this variant answers, relative to the others' weights
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Overrides the guild's model
+    pub model: Option<String>,
+    /// Replaces the built-in instructions at the start of the system prompt
+    pub system_prompt: Option<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Size,
+    Never,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LurkConfig {
+    /// Channels to keep the conversation in, like autorespond channels (for the
+    /// messages skipped there) or busy help channels where the bot waits to be mentioned
+    pub channels: Vec<u64>,
+    /// How much of it to keep per channel, newest first; older messages are dropped
+    #[serde(default = "default_lurk_tokens")]
+    pub max_tokens: usize,
+}
+
+fn default_lurk_tokens() -> usize {
+    1000
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TrelloConfig {
+    /// The board's id, from its URL (`trello.com/b/<id>/...`)
+    pub board: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct WasmToolsConfig {
+    /// Folder of `.wasm` modules, one tool each
+    pub dir: String,
+    /// Fuel, about one per instruction, a module gets per call before it's stopped
+    pub fuel: u64,
+    /// Most memory a module may grow to, in megabytes
+    pub max_memory_mb: usize,
+}
+
+impl Default for WasmToolsConfig {
+    fn default() -> WasmToolsConfig {
+        WasmToolsConfig {
+            dir: "plugins".to_string(),
+            fuel: 50_000_000,
+            max_memory_mb: 32,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ScriptsConfig {
+    /// The script, read at startup and again whenever it changes
+    pub file: String,
+    /// Operations a hook may run before it's stopped
+    pub max_operations: u64,
+}
+
+impl Default for ScriptsConfig {
+    fn default() -> ScriptsConfig {
+        ScriptsConfig {
+            file: "hooks.rhai".to_string(),
+            max_operations: 1_000_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ActivityWebhookConfig {
+    pub url: String,
+    /// Sent as a bearer token, for the receiver to check
+    pub secret: Option<String>,
+}
+
+impl ActivityWebhookConfig {
+    /// The bearer token, from the environment or the config
+    pub fn secret(&self) -> Option<String> {
+        env::var("DESKHELP_ACTIVITY_SECRET")
+            .ok()
+            .or_else(|| self.secret.clone())
+            .filter(|secret| !secret.is_empty())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct EventsConfig {
+    pub url: String,
+    /// Events are published to this, then `.` and their type
+    pub subject: String,
+}
+
+impl Default for EventsConfig {
+    fn default() -> EventsConfig {
+        EventsConfig {
+            url: "nats://127.0.0.1:4222".to_string(),
+            subject: "deskhelp".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DuplicatesConfig {
+    /// Embedding model, from the guild's provider
+    pub model: String,
+    /// How similar (cosine, up to 1) a question must be to an earlier one to link it
+    pub threshold: f32,
+    /// How far back to look for earlier questions
+    pub days: u32,
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> DuplicatesConfig {
+        DuplicatesConfig {
+            model: "text-embedding-3-small".to_string(),
+            threshold: 0.9,
+            days: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Embedding model, from the guild's provider
+    pub model: String,
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig {
+            model: "text-embedding-3-small".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct StarboardConfig {
+    /// Reactions that vouch for an answer
+    pub emojis: Vec<String>,
+    /// How many of them (together) make an answer trusted
+    pub reactions: u64,
+}
+
+impl Default for StarboardConfig {
+    fn default() -> StarboardConfig {
+        StarboardConfig {
+            emojis: vec!["✅".to_string(), "⭐".to_string()],
+            reactions: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BillingAlertsConfig {
+    /// US dollars spent in a day (UTC) that each get an alert, priced by
+    /// `input_price` and `output_price` in `[models]`
+    pub thresholds: Vec<f64>,
+}
+
+/// Days to keep each kind of data; unset keeps it forever
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Conversations with no new messages for this long are cleared
+    pub conversations: Option<u32>,
+    /// Answer records, with their transcripts, feedback and ratings
+    pub answers: Option<u32>,
+    /// Answers kept for `/search`
+    pub search: Option<u32>,
+    /// Question embeddings kept for `[duplicates]`
+    pub duplicates: Option<u32>,
+    /// Records of `/forgetme` deletions
+    pub deletions: Option<u32>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LatexConfig {
+    /// URL that returns a PNG for the LaTeX substituted (URL-encoded) for `{latex}`
+    pub renderer_url: String,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PasteConfig {
+    /// Lines of a code block shown in the answer; the rest is shared
+    pub max_lines: usize,
+    /// Where to upload long code blocks: the code is POSTed as the body, and
+    /// the response body is its link. They're attached as files if unset.
+    pub upload_url: Option<String>,
+}
+
+impl Default for PasteConfig {
+    fn default() -> PasteConfig {
+        PasteConfig {
+            max_lines: 30,
+            upload_url: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct OcrConfig {
+    /// Vision model, from the guild's provider, that reads the images; the
+    /// guild's model if unset
+    pub model: Option<String>,
+    /// Most images read per question
+    pub max_images: usize,
+}
+
+impl Default for OcrConfig {
+    fn default() -> OcrConfig {
+        OcrConfig {
+            model: None,
+            max_images: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BotConfig {
+    /// Tells the bot apart in logs and the config
+    pub name: String,
+    /// Environment variable holding its Discord token
+    pub token_env: String,
+    /// Added to the instructions, to give it a persona of its own
+    pub prompt: Option<String>,
+    /// Model it answers with, from the guild's provider; the guild's if unset
+    pub model: Option<String>,
+    /// Channels it answers every message in. It answers mentions anywhere,
+    /// and the main bot leaves these channels to it.
+    #[serde(default)]
+    pub channels: Vec<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct KnownError {
+    /// Signatures of the error, matched ignoring case and spacing; `*`
+    /// stands for anything in between
+    pub patterns: Vec<String>,
+    /// The fix, posted as it is
+    pub answer: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProviderConfig {
+    /// Shorthand for a provider with a single endpoint
+    pub api_key: Option<String>,
+    /// Also the default base URL for `endpoints` that don't set their own
+    pub base_url: Option<String>,
+    /// Keys/endpoints to rotate across. Rate-limited or failing ones are skipped for a while.
+    #[serde(default)]
+    pub endpoints: Vec<EndpointConfig>,
+    pub model: Option<String>,
+    /// Extra fields for every request body, for settings only some APIs take,
+    /// like `top_k` or `repetition_penalty`
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EndpointConfig {
+    pub api_key: String,
+    pub base_url: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct GuildConfig {
+    /// Provider to answer with. Several guilds can share one to bill them together.
+    pub provider: Option<String>,
+    /// Overrides the provider's model for this guild
+    pub model: Option<String>,
+    /// Channels in this guild to answer every message in, on top of the global ones
+    pub autorespond_channels: Vec<u64>,
+    /// Answer through a channel webhook as this persona instead of as the bot
+    pub persona: Option<PersonaConfig>,
+    /// Extra links (or link prefixes) answers in this guild may contain
+    pub allowed_links: Vec<String>,
+    /// What answers in this guild may not say
+    pub content_policy: Option<ContentPolicyConfig>,
+    /// Roles whose members skip ahead of casual questions when the bot is busy
+    pub support_roles: Vec<u64>,
+    /// Channels whose threads are support tickets, which also skip ahead
+    pub ticket_channels: Vec<u64>,
+    /// Put a button under answers that opens a thread for the support roles,
+    /// with a summary of the conversation
+    pub escalate: bool,
+    /// Minutes a thread the bot answered in may sit quiet before it asks the
+    /// asker whether that solved it; never, if unset
+    pub resolution_check_minutes: Option<u64>,
+    /// Generate and log answers without posting them; overrides the global setting
+    pub dry_run: Option<bool>,
+    /// Tokens this guild may use a month; overrides the global setting
+    pub monthly_token_cap: Option<u64>,
+    /// Prefix for commands in this guild; overrides the global setting
+    pub prefix: Option<String>,
+    /// Language for this guild; overrides the global setting
+    pub locale: Option<String>,
+    /// Timezone for this guild; overrides the global setting
+    pub timezone: Option<String>,
+    /// Footer template for this guild; overrides the global setting
+    pub footer: Option<String>,
+    /// Answer in voice and stage channels' text chats; overrides the global setting
+    pub voice_channels: Option<bool>,
+    /// Troubleshooting steps for this guild, instead of the global ones
+    pub troubleshoot: Option<TroubleshootNode>,
+    /// Channel to post a weekly digest of answer volume and latency in
+    pub ops_channel: Option<u64>,
+    /// Channel to announce new `[releases]` in, with a TL;DR of the changelog
+    pub announcement_channel: Option<u64>,
+    /// Channel to post new releases and questions from `[github]` webhooks in
+    pub github_channel: Option<u64>,
+    /// Staff channel for a weekly summary of the questions asked. Setting it
+    /// keeps the text of questions and answers in storage to summarize.
+    pub support_digest_channel: Option<u64>,
+    /// Channels answered with machine-readable JSON instead of prose
+    pub triage: Option<TriageConfig>,
+    /// For forums by channel id: categories the model sorts new posts into,
+    /// each with the names of the forum tags to add for it
+    pub forum_tags: HashMap<String, BTreeMap<String, Vec<String>>>,
+    /// Channels (and their threads) whose answers are posted in one piece once
+    /// they're done, instead of edited in as they stream
+    pub one_shot_channels: Vec<u64>,
+    /// Extra instructions for some channels (and their threads), keyed by channel id.
+    /// Available to the system prompt as `{{channel_prompt}}`.
+    pub channel_prompts: HashMap<String, String>,
+    /// Extra instructions by role id, for the asker's highest role that has some.
+    /// The guild id stands for everyone else. Available as `{{role_prompt}}`.
+    pub role_prompts: HashMap<String, String>,
+    /// Tell the model when the asker joined, their top roles, and how often
+    /// they've asked before (counted only while this is on). Off by default,
+    /// since it shares member details with the provider.
+    pub user_profiles: bool,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ContentPolicyConfig {
+    /// Words (or phrases) replaced with a notice wherever answers use them
+    pub blocked_words: Vec<String>,
+    /// Topics answers may not touch: one mentioning any of them is replaced as
+    /// a whole with a notice. Answers are then posted in one piece, once checked.
+    pub blocked_topics: Vec<String>,
+    /// What to do with links that aren't allowed; overrides the global setting
+    pub link_policy: Option<LinkPolicy>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TriageConfig {
+    /// Channels, or forums whose posts, are answered with a JSON triage
+    pub channels: Vec<u64>,
+    /// Categories the model picks from; any category goes if empty
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Add the forum tags named like the category or severity to the post
+    #[serde(default)]
+    pub tag_posts: bool,
+    /// Ask the API to enforce the schema. Turn off for APIs without structured
+    /// outputs; answers are still checked against it.
+    #[serde(default = "default_true")]
+    pub enforce_schema: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A step of `/troubleshoot`: a question with options to pick from, each a
+/// step of its own, or a fix to try
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TroubleshootNode {
+    /// What the option leading here says; the first step doesn't need one
+    pub label: String,
+    /// Asked above the options
+    pub question: Option<String>,
+    /// The fix, for steps without options
+    pub answer: Option<String>,
+    pub options: Vec<TroubleshootNode>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct PersonaConfig {
+    pub name: String,
+    pub avatar_url: Option<String>,
+}
+
+impl Config {
+    /// Where the config file is read from
+    pub fn path() -> String {
+        env::var("DESKHELP_CONFIG").unwrap_or("config.toml".to_string())
+    }
+
+    /// Reads the config file, or returns `None` if there isn't one. Runs before
+    /// logging is set up, so it can't log.
+    pub fn load() -> Option<Config> {
+        let path = Config::path();
+        let contents = std::fs::read_to_string(&path).ok()?;
+        Some(
+            toml::from_str(&contents)
+                .unwrap_or_else(|e| panic!("failed to parse config file {}: {}", path, e)),
+        )
+    }
+
+    /// Limits for a host short on memory, if `memory_budget_mb` is set
+    pub fn memory_budget(&self) -> Option<MemoryBudget> {
+        self.memory_budget_mb
+            .map(|megabytes| MemoryBudget { megabytes })
+    }
+
+    /// How many earlier messages to read back for a conversation: `backfill_messages`,
+    /// but no more than a memory budget keeps of one
+    pub fn backfill_limit(&self) -> u8 {
+        let kept = self
+            .memory_budget()
+            .map_or(100, |b| b.context_messages().min(100));
+        self.backfill_messages.min(kept as u8)
+    }
+
+    /// Channels to answer every message in: the config's and its guilds', plus
+    /// `AUTORESPOND_CHANNELS` from the environment, where ids starting with `-`
+    /// are switched off
+    pub fn autorespond_channels(&self) -> Vec<String> {
+        let guilds = self.guilds.values().flat_map(|g| &g.autorespond_channels);
+        env::var("AUTORESPOND_CHANNELS")
+            .unwrap_or("-1302692329400041482".to_string())
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .chain(
+                self.autorespond_channels
+                    .iter()
+                    .chain(guilds)
+                    .map(|id| id.to_string()),
+            )
+            .collect()
+    }
+
+    /// Whether `msg` is a question for the bot: it's mentioned, or it's in one
+    /// of its autorespond channels or a direct message. `bot` names one of
+    /// `bots`, or is `None` for the main bot.
+    pub fn is_question(&self, msg: &Message, bot_id: UserId, bot: Option<&str>) -> bool {
+        let channel = msg.channel_id.get();
+        let answers_all = msg.guild_id.is_none()
+            || match bot {
+                Some(name) => self
+                    .bot(name)
+                    .is_some_and(|b| b.channels.contains(&channel)),
+                None => {
+                    self.autorespond_channels()
+                        .contains(&msg.channel_id.to_string())
+                        && !self.bots.iter().any(|b| b.channels.contains(&channel))
+                }
+            };
+        let prefix = self.prefix(msg.guild_id);
+        let command = !prefix.is_empty() && msg.content.starts_with(&prefix);
+        msg.mentions_user_id(bot_id) || answers_all && !msg.author.bot && !command
+    }
+
+    /// Whether to stay out of this message entirely
+    pub fn is_ignored(&self, msg: &Message) -> bool {
+        self.ignores(msg.channel_id, msg.author.id, msg.guild_id)
+    }
+
+    /// Whether to stay out of what `user_id` asks in `channel_id`, however they ask
+    pub fn ignores(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        guild_id: Option<GuildId>,
+    ) -> bool {
+        self.ignored_channels.contains(&channel_id.get())
+            || self.ignored_users.contains(&user_id.get())
+            || guild_id.is_none() && !self.direct_messages
+    }
+
+    /// One of `bots`, by name
+    pub fn bot(&self, name: &str) -> Option<&BotConfig> {
+        self.bots.iter().find(|b| b.name == name)
+    }
+
+    pub fn guild(&self, guild_id: Option<GuildId>) -> Option<&GuildConfig> {
+        guild_id.and_then(|id| self.guilds.get(&id.to_string()))
+    }
+
+    /// Whether answers in this guild are only logged, not posted
+    pub fn dry_run(&self, guild_id: Option<GuildId>) -> bool {
+        self.guild(guild_id)
+            .and_then(|g| g.dry_run)
+            .unwrap_or(self.dry_run)
+    }
+
+    /// Tokens the guild may use a month, if it's capped
+    pub fn monthly_token_cap(&self, guild_id: Option<GuildId>) -> Option<u64> {
+        self.guild(guild_id)
+            .and_then(|g| g.monthly_token_cap)
+            .or(self.monthly_token_cap)
+    }
+
+    /// What starts prefix commands in this guild, if anything does
+    pub fn prefix(&self, guild_id: Option<GuildId>) -> String {
+        self.guild(guild_id)
+            .and_then(|g| g.prefix.clone())
+            .unwrap_or_else(|| self.prefix.clone())
+    }
+
+    /// What to do with links answers in this guild weren't given
+    pub fn link_policy(&self, guild_id: Option<GuildId>) -> LinkPolicy {
+        self.guild(guild_id)
+            .and_then(|g| g.content_policy.as_ref()?.link_policy)
+            .unwrap_or(self.link_policy)
+    }
+
+    /// Language the bot's own messages in this guild are in
+    pub fn locale(&self, guild_id: Option<GuildId>) -> String {
+        self.guild(guild_id)
+            .and_then(|g| g.locale.clone())
+            .unwrap_or_else(|| self.locale.clone())
+    }
+
+    /// Timezone the model is told the time in for this guild
+    pub fn timezone(&self, guild_id: Option<GuildId>) -> String {
+        self.guild(guild_id)
+            .and_then(|g| g.timezone.clone())
+            .unwrap_or_else(|| self.timezone.clone())
+    }
+
+    /// Whether to answer in the text chats of voice and stage channels in this guild
+    pub fn voice_channels(&self, guild_id: Option<GuildId>) -> bool {
+        self.guild(guild_id)
+            .and_then(|g| g.voice_channels)
+            .unwrap_or(self.voice_channels)
+    }
+
+    /// The troubleshooting steps for this guild, if there are any
+    pub fn troubleshoot(&self, guild_id: Option<GuildId>) -> Option<&TroubleshootNode> {
+        self.guild(guild_id)
+            .and_then(|g| g.troubleshoot.as_ref())
+            .or(self.troubleshoot.as_ref())
+    }
+
+    /// Template for the footer under answers in this guild, if one's set
+    pub fn footer(&self, guild_id: Option<GuildId>) -> Option<String> {
+        self.guild(guild_id)
+            .and_then(|g| g.footer.clone())
+            .or_else(|| self.footer.clone())
+    }
+}
+
+/// Picks up edits to the config file while running. Providers, the queue size,
+/// logging, and error reporting are set up once at startup and need a restart.
+pub fn watch(shared: SharedConfig) {
+    tokio::spawn(async move {
+        let path = Config::path();
+        let modified = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut last_modified = modified(&path);
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            let config = match std::fs::read_to_string(&path) {
+                Ok(contents) => match toml::from_str::<Config>(&contents) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        error!("Not reloading {}, it doesn't parse: {}", path, e);
+                        continue;
+                    }
+                },
+                // deleted, or mid-save
+                Err(_) => continue,
+            };
+            *shared.write().unwrap() = Arc::new(config);
+            info!("Reloaded {}", path);
+        }
+    });
+}
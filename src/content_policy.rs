@@ -0,0 +1,173 @@
+use serenity::all::GuildId;
+use tracing::{info, warn};
+
+use crate::{
+    config::{Config, ContentPolicyConfig},
+    i18n,
+    links::{self, LinkPolicy},
+    markdown::render_for_discord,
+};
+
+/// What anything the model writes goes through before it's posted: links
+/// it wasn't given are handled by the link policy, and blocked words masked
+pub struct Policy<'a> {
+    guild_id: Option<GuildId>,
+    allowed_links: Vec<String>,
+    link_policy: LinkPolicy,
+    blocked: Option<&'a ContentPolicyConfig>,
+    word_notice: String,
+}
+
+impl<'a> Policy<'a> {
+    /// The guild's policy, for answers that may link to `allowed_links`
+    pub fn new(
+        config: &'a Config,
+        guild_id: Option<GuildId>,
+        locale: &str,
+        allowed_links: Vec<String>,
+    ) -> Policy<'a> {
+        Policy {
+            guild_id,
+            allowed_links,
+            link_policy: config.link_policy(guild_id),
+            blocked: config
+                .guild(guild_id)
+                .and_then(|g| g.content_policy.as_ref()),
+            word_notice: i18n::tr(locale, "policy-word", &[]),
+        }
+    }
+
+    /// `text` as it may be posted, finished or not
+    pub fn render(&self, text: &str) -> String {
+        let text = links::enforce(
+            &render_for_discord(text),
+            &self.allowed_links,
+            self.link_policy,
+        );
+        mask(&text, self.blocked_words(), &self.word_notice)
+    }
+
+    /// A finished answer as it may be posted, or the blocked topic it touches,
+    /// in which case it mustn't be posted or kept at all
+    pub fn check<'t>(&'t self, answer: &str) -> Result<String, &'t str> {
+        if let Some(topic) = self.blocked_topic(answer) {
+            warn!(
+                "Replaced an answer in guild {:?} for touching the blocked topic {:?}",
+                self.guild_id, topic
+            );
+            return Err(topic);
+        }
+        let masked = blocked_words(answer, self.blocked_words());
+        if !masked.is_empty() {
+            info!(
+                "Masked blocked words {:?} in an answer in guild {:?}",
+                masked, self.guild_id
+            );
+        }
+        Ok(self.render(answer))
+    }
+
+    /// The first blocked topic `text` mentions, if any
+    pub fn blocked_topic(&self, text: &str) -> Option<&str> {
+        blocked_topic(text, self.blocked.map_or(&[][..], |p| &p.blocked_topics))
+    }
+
+    /// Whether answers are checked for blocked topics, so they have to be
+    /// finished before anyone sees them
+    pub fn checks_topics(&self) -> bool {
+        self.blocked.is_some_and(|p| !p.blocked_topics.is_empty())
+    }
+
+    /// Whether a link the model gives may stay
+    pub fn allows(&self, link: &str) -> bool {
+        self.link_policy == LinkPolicy::Off || links::is_allowed(link, &self.allowed_links)
+    }
+
+    /// The links answers may contain
+    pub fn allowed_links(&self) -> &[String] {
+        &self.allowed_links
+    }
+
+    fn blocked_words(&self) -> &[String] {
+        self.blocked.map_or(&[][..], |p| &p.blocked_words)
+    }
+}
+
+/// Where `term` appears in `text` as whole words, ignoring case, as byte ranges
+fn find(text: &str, term: &str) -> Vec<(usize, usize)> {
+    let term: Vec<char> = term.chars().flat_map(char::to_lowercase).collect();
+    if term.is_empty() {
+        return vec![];
+    }
+    let mut found = vec![];
+    let mut previous = None;
+    for (start, _) in text.char_indices() {
+        let at_word_start = !previous.is_some_and(char::is_alphanumeric);
+        previous = text[start..].chars().next();
+        if !at_word_start {
+            continue;
+        }
+        let mut end = start;
+        let mut matched = 0;
+        for (offset, c) in text[start..].char_indices() {
+            let lower: Vec<char> = c.to_lowercase().collect();
+            if term[matched..].starts_with(&lower) {
+                matched += lower.len();
+                end = start + offset + c.len_utf8();
+                if matched == term.len() {
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+        let at_word_end = !text[end..]
+            .chars()
+            .next()
+            .is_some_and(char::is_alphanumeric);
+        if matched == term.len() && at_word_end {
+            found.push((start, end));
+        }
+    }
+    found
+}
+
+/// The blocked words `text` contains
+pub fn blocked_words<'a>(text: &str, words: &'a [String]) -> Vec<&'a str> {
+    words
+        .iter()
+        .filter(|word| !find(text, word).is_empty())
+        .map(String::as_str)
+        .collect()
+}
+
+/// `text` with every blocked word replaced by `notice`
+pub fn mask(text: &str, words: &[String], notice: &str) -> String {
+    let mut ranges: Vec<_> = words.iter().flat_map(|word| find(text, word)).collect();
+    if ranges.is_empty() {
+        return text.to_string();
+    }
+    ranges.sort();
+    let mut masked = String::with_capacity(text.len());
+    let mut shown = 0;
+    for (start, end) in ranges {
+        // one word can be blocked twice over, as part of a longer phrase
+        if start < shown {
+            shown = shown.max(end);
+            continue;
+        }
+        masked.push_str(&text[shown..start]);
+        masked.push_str(notice);
+        shown = end;
+    }
+    masked.push_str(&text[shown..]);
+    masked
+}
+
+/// The first blocked topic `text` mentions, if any
+pub fn blocked_topic<'a>(text: &str, topics: &'a [String]) -> Option<&'a str> {
+    topics
+        .iter()
+        .find(|topic| !find(text, topic).is_empty())
+        .map(String::as_str)
+}
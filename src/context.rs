@@ -0,0 +1,184 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_openai::types::ChatCompletionRequestMessage;
+use serenity::all::ChannelId;
+use time::OffsetDateTime;
+
+use crate::{config::MemoryBudget, storage::Error};
+
+/// Recent conversation in each channel, keyed by channel id
+pub type Contexts = HashMap<String, Vec<ChatCompletionRequestMessage>>;
+
+/// A change to one conversation, returning how many messages it dropped
+pub type Edit = dyn Fn(&mut Vec<ChatCompletionRequestMessage>) -> u64 + Send + Sync;
+
+/// Where conversations are kept between messages: in memory, or in Redis to
+/// share them between instances
+#[serenity::async_trait]
+pub trait ContextStore: Send + Sync {
+    /// Adds a message to the channel's conversation and returns the whole conversation
+    async fn push(
+        &self,
+        channel_id: ChannelId,
+        message: ChatCompletionRequestMessage,
+    ) -> Result<Vec<ChatCompletionRequestMessage>, Error>;
+
+    async fn clear(&self, channel_id: ChannelId) -> Result<(), Error>;
+
+    /// Whether the channel has no conversation yet
+    async fn is_empty(&self, channel_id: ChannelId) -> Result<bool, Error>;
+
+    /// The channel's conversation, empty if it has none
+    async fn get(&self, channel_id: ChannelId) -> Result<Vec<ChatCompletionRequestMessage>, Error>;
+
+    /// Every channel's conversation, for archives
+    async fn all(&self) -> Result<Contexts, Error>;
+
+    /// Changes the channel's conversation with `edit` under the store's lock,
+    /// leaving others and when it last grew alone, and returns what `edit` did
+    async fn update(&self, channel_id: ChannelId, edit: &Edit) -> Result<u64, Error>;
+
+    /// Replaces every conversation with `contexts`
+    async fn replace_all(&self, contexts: &Contexts) -> Result<(), Error>;
+
+    /// Clears conversations with no new messages since `since`, returning how many went
+    async fn clear_idle(&self, since: OffsetDateTime) -> Result<u64, Error>;
+}
+
+/// Conversations in this process only, lost on restart
+#[derive(Default)]
+pub struct MemoryContexts {
+    contexts: Mutex<Contexts>,
+    /// When each channel's conversation last grew
+    pushed_at: Mutex<HashMap<String, OffsetDateTime>>,
+    /// How much of them to keep, on hosts short on memory
+    budget: Option<MemoryBudget>,
+}
+
+impl MemoryContexts {
+    /// Conversations cut down to the latest messages, in as many channels as
+    /// `budget` keeps
+    pub fn within(budget: MemoryBudget) -> MemoryContexts {
+        MemoryContexts {
+            budget: Some(budget),
+            ..Default::default()
+        }
+    }
+
+    /// Drops what the budget doesn't keep: the oldest messages of each
+    /// conversation, then the conversations idle longest, but never `keep`'s
+    fn fit(
+        &self,
+        contexts: &mut Contexts,
+        pushed_at: &mut HashMap<String, OffsetDateTime>,
+        keep: Option<&str>,
+    ) {
+        let Some(budget) = self.budget else {
+            return;
+        };
+        for context in contexts.values_mut() {
+            if context.len() > budget.context_messages() {
+                context.drain(..context.len() - budget.context_messages());
+                context.shrink_to_fit();
+            }
+        }
+        while contexts.len() > budget.conversations() {
+            let idle = contexts
+                .keys()
+                .filter(|key| Some(key.as_str()) != keep)
+                .min_by_key(|key| pushed_at.get(*key))
+                .cloned();
+            let Some(idle) = idle else {
+                break;
+            };
+            contexts.remove(&idle);
+            pushed_at.remove(&idle);
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl ContextStore for MemoryContexts {
+    async fn push(
+        &self,
+        channel_id: ChannelId,
+        message: ChatCompletionRequestMessage,
+    ) -> Result<Vec<ChatCompletionRequestMessage>, Error> {
+        let key = channel_id.to_string();
+        let mut contexts = self.contexts.lock().unwrap();
+        let mut pushed_at = self.pushed_at.lock().unwrap();
+        contexts.entry(key.clone()).or_default().push(message);
+        pushed_at.insert(key.clone(), OffsetDateTime::now_utc());
+        self.fit(&mut contexts, &mut pushed_at, Some(&key));
+        Ok(contexts[&key].clone())
+    }
+
+    async fn clear(&self, channel_id: ChannelId) -> Result<(), Error> {
+        self.contexts
+            .lock()
+            .unwrap()
+            .remove(&channel_id.to_string());
+        self.pushed_at
+            .lock()
+            .unwrap()
+            .remove(&channel_id.to_string());
+        Ok(())
+    }
+
+    async fn is_empty(&self, channel_id: ChannelId) -> Result<bool, Error> {
+        Ok(self
+            .contexts
+            .lock()
+            .unwrap()
+            .get(&channel_id.to_string())
+            .is_none_or(|context| context.is_empty()))
+    }
+
+    async fn get(&self, channel_id: ChannelId) -> Result<Vec<ChatCompletionRequestMessage>, Error> {
+        Ok(self
+            .contexts
+            .lock()
+            .unwrap()
+            .get(&channel_id.to_string())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn all(&self) -> Result<Contexts, Error> {
+        Ok(self.contexts.lock().unwrap().clone())
+    }
+
+    async fn update(&self, channel_id: ChannelId, edit: &Edit) -> Result<u64, Error> {
+        let key = channel_id.to_string();
+        let mut contexts = self.contexts.lock().unwrap();
+        let Some(context) = contexts.get_mut(&key) else {
+            return Ok(0);
+        };
+        let dropped = edit(context);
+        if context.is_empty() {
+            contexts.remove(&key);
+            self.pushed_at.lock().unwrap().remove(&key);
+        }
+        Ok(dropped)
+    }
+
+    async fn replace_all(&self, contexts: &Contexts) -> Result<(), Error> {
+        // restored conversations count as new
+        let now = OffsetDateTime::now_utc();
+        let mut pushed_at = contexts.keys().map(|key| (key.clone(), now)).collect();
+        let mut contexts = contexts.clone();
+        self.fit(&mut contexts, &mut pushed_at, None);
+        *self.contexts.lock().unwrap() = contexts;
+        *self.pushed_at.lock().unwrap() = pushed_at;
+        Ok(())
+    }
+
+    async fn clear_idle(&self, since: OffsetDateTime) -> Result<u64, Error> {
+        let mut contexts = self.contexts.lock().unwrap();
+        let mut pushed_at = self.pushed_at.lock().unwrap();
+        let before = contexts.len();
+        contexts.retain(|key, _| pushed_at.get(key).is_some_and(|&at| at >= since));
+        pushed_at.retain(|key, _| contexts.contains_key(key));
+        Ok((before - contexts.len()) as u64)
+    }
+}
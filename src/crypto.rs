@@ -0,0 +1,127 @@
+//! Encryption of conversation text before it's stored, so a leaked database
+//! or Redis dump doesn't expose what people asked. Builds without the
+//! `encryption` feature store text as it is, and refuse a key.
+
+#[cfg(feature = "encryption")]
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+#[cfg(feature = "encryption")]
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::storage::Error;
+
+/// Marks stored text as encrypted; anything without it is read as it is
+const PREFIX: &str = "deskhelp-enc:v1:";
+/// Bytes of nonce at the start of each encrypted value
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM with the configured key, or no encryption without one
+#[derive(Clone, Default)]
+pub struct Cipher {
+    #[cfg(feature = "encryption")]
+    key: Option<Aes256Gcm>,
+    /// Reads encrypted text back still encrypted
+    as_stored: bool,
+}
+
+impl Cipher {
+    /// A cipher with `key`, 32 bytes in base64 (like `openssl rand -base64 32` makes)
+    #[cfg(feature = "encryption")]
+    pub fn new(key: &str) -> Result<Cipher, Error> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("encryption key isn't base64: {}", e))?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| format!("encryption key is {} bytes; it must be 32", key.len()))?;
+        Ok(Cipher {
+            key: Some(cipher),
+            as_stored: false,
+        })
+    }
+
+    /// A cipher with `key` if there is one, and one that doesn't encrypt otherwise
+    pub fn from_key(key: Option<&str>) -> Result<Cipher, Error> {
+        key.map_or(Ok(Cipher::default()), Cipher::new)
+    }
+
+    /// Always fails, in builds that can't encrypt
+    #[cfg(not(feature = "encryption"))]
+    pub fn new(_key: &str) -> Result<Cipher, Error> {
+        Err("an encryption key needs a build with `--features encryption`".into())
+    }
+
+    /// A cipher that neither encrypts nor decrypts, to copy stored text
+    /// (say, into a backup) without the key
+    pub fn as_stored() -> Cipher {
+        Cipher {
+            #[cfg(feature = "encryption")]
+            key: None,
+            as_stored: true,
+        }
+    }
+
+    /// `text` as it should be stored: encrypted under a fresh nonce, if there's a key
+    #[cfg(feature = "encryption")]
+    pub fn seal(&self, text: &str) -> String {
+        let Some(cipher) = &self.key else {
+            return text.to_string();
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut bytes = nonce.to_vec();
+        bytes.extend(
+            cipher
+                .encrypt(&nonce, text.as_bytes())
+                .expect("AES-GCM encrypts any text that fits in memory"),
+        );
+        format!("{}{}", PREFIX, STANDARD.encode(bytes))
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    pub fn seal(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    /// Stored text as it was before `seal`. Text stored before encryption was
+    /// turned on comes back as it is.
+    pub fn open(&self, stored: String) -> Result<String, Error> {
+        if self.as_stored {
+            return Ok(stored);
+        }
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored);
+        };
+        self.decrypt(encoded)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn decrypt(&self, encoded: &str) -> Result<String, Error> {
+        let Some(cipher) = &self.key else {
+            return Err("stored text is encrypted, but no encryption key is set".into());
+        };
+        let bytes = STANDARD.decode(encoded)?;
+        if bytes.len() < NONCE_LEN {
+            return Err("encrypted text is cut short".into());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "can't decrypt stored text; was the encryption key changed?")?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn decrypt(&self, _encoded: &str) -> Result<String, Error> {
+        Err("stored text is encrypted, but this build has no `encryption` feature".into())
+    }
+
+    pub fn seal_optional(&self, text: Option<&str>) -> Option<String> {
+        text.map(|text| self.seal(text))
+    }
+
+    pub fn open_optional(&self, stored: Option<String>) -> Result<Option<String>, Error> {
+        stored.map(|stored| self.open(stored)).transpose()
+    }
+}
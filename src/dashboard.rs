@@ -0,0 +1,467 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serenity::all::{Cache, GuildId, Http};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime, Time};
+use tracing::{info, warn};
+
+use crate::{
+    api, github,
+    storage::{Error, Storage},
+    support_digest::one_line,
+    Data,
+};
+
+/// How many days of token usage the graph covers
+const USAGE_DAYS: u32 = 14;
+/// How far back recent questions go
+const RECENT_PERIOD: Duration = Duration::days(1);
+/// How many recent questions are shown
+const RECENT_LIMIT: usize = 20;
+/// How much of a question or answer is shown
+const RECENT_CHARS: usize = 300;
+
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub data: Arc<Data>,
+    pub http: Arc<Http>,
+    pub cache: Arc<Cache>,
+    /// The dashboard's token; without one, only the API is served
+    pub token: Option<Arc<str>>,
+    /// The API's tokens, by who uses them
+    pub api_tokens: Arc<BTreeMap<String, String>>,
+}
+
+/// Serves the dashboard, the API and GitHub's webhooks on `[dashboard] listen`,
+/// if any of them has a token or secret
+pub fn spawn(data: Arc<Data>, http: Arc<Http>, cache: Arc<Cache>) {
+    let config = data.config();
+    let Some(dashboard) = &config.dashboard else {
+        return;
+    };
+    let token = dashboard.token();
+    let github = config.github.as_ref().and_then(|g| g.secret()).is_some();
+    if token.is_none() && dashboard.api_tokens.is_empty() && !github {
+        warn!(
+            "Not serving the dashboard: set [dashboard] token (or DESKHELP_DASHBOARD_TOKEN), \
+             api_tokens or a [github] secret"
+        );
+        return;
+    }
+    let listen = dashboard.listen.clone();
+    let state = AppState {
+        data,
+        http,
+        cache,
+        token: token.map(Into::into),
+        api_tokens: Arc::new(dashboard.api_tokens.clone()),
+    };
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/api/status", get(status))
+        .route("/guilds/{guild_id}/shadow", post(toggle_shadow))
+        .route("/prompt/pin/{version}", post(pin_prompt))
+        .route("/prompt/unpin", post(unpin_prompt))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .merge(api::router(state.clone()))
+        .merge(github::router())
+        .with_state(state);
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(&listen).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Failed to serve the dashboard on {}: {}", listen, e);
+                return;
+            }
+        };
+        info!("Serving the dashboard on http://{}", listen);
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("The dashboard stopped: {}", e);
+        }
+    });
+}
+
+/// Whether `given` is the dashboard's token. Compares digests, so how long
+/// the comparison takes says nothing about the token.
+pub fn token_matches(token: &str, given: &str) -> bool {
+    Sha256::digest(token.as_bytes()) == Sha256::digest(given.as_bytes())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TokenQuery {
+    token: Option<String>,
+}
+
+/// The token a request came with, as a bearer token or `?token=`
+pub(crate) fn given_token<'a>(request: &'a Request, query: &'a TokenQuery) -> Option<&'a str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(query.token.as_deref())
+}
+
+/// Lets through requests with the dashboard's token
+async fn authorize(
+    State(state): State<AppState>,
+    Query(query): Query<TokenQuery>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = match (&state.token, given_token(&request, &query)) {
+        (Some(token), Some(given)) => token_matches(token, given),
+        _ => false,
+    };
+    if authorized {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, "A valid token is needed").into_response()
+    }
+}
+
+/// Everything the dashboard shows
+#[derive(Serialize)]
+pub struct Snapshot {
+    pub queue: QueueSnapshot,
+    pub prompt: PromptSnapshot,
+    pub guilds: Vec<GuildSnapshot>,
+    pub usage: Vec<DayUsage>,
+    pub recent: Vec<RecentQuestion>,
+}
+
+#[derive(Serialize)]
+pub struct QueueSnapshot {
+    pub limit: usize,
+    pub active: usize,
+    pub waiting: usize,
+    pub avg_seconds: f64,
+}
+
+#[derive(Serialize)]
+pub struct PromptSnapshot {
+    pub version: String,
+    pub pinned: bool,
+    pub created_at: String,
+    /// Earlier versions that can be pinned, newest first
+    pub history: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct GuildSnapshot {
+    pub id: String,
+    pub name: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub locale: String,
+    pub dry_run: bool,
+    pub monthly_token_cap: Option<u64>,
+    pub autorespond_channels: Vec<u64>,
+    pub support_roles: Vec<u64>,
+    pub escalate: bool,
+    /// Whether questions are kept, and so show up under recent questions
+    pub keeps_questions: bool,
+}
+
+/// Tokens used on one day, UTC
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DayUsage {
+    pub date: String,
+    pub tokens: u64,
+}
+
+#[derive(Serialize)]
+pub struct RecentQuestion {
+    pub guild: String,
+    pub at: String,
+    pub question: String,
+    pub answer: Option<String>,
+    pub ok: bool,
+}
+
+async fn snapshot(state: &AppState) -> Result<Snapshot, Error> {
+    let data = &state.data;
+    let config = data.config();
+    let now = OffsetDateTime::now_utc();
+
+    let queue = data.queue.status();
+    let active = data.prompts.active();
+    let prompt = PromptSnapshot {
+        version: active.version.clone(),
+        pinned: data.prompts.is_pinned(),
+        created_at: active.created_at.date().to_string(),
+        history: data
+            .prompts
+            .history(10)
+            .await?
+            .into_iter()
+            .map(|p| p.version)
+            .filter(|version| *version != active.version)
+            .collect(),
+    };
+
+    let guild_ids: BTreeSet<GuildId> = config
+        .guilds
+        .keys()
+        .filter_map(|id| id.parse().ok().map(GuildId::new))
+        .chain(state.cache.guilds())
+        .collect();
+    let mut guilds = Vec::new();
+    let mut recent = Vec::new();
+    for guild_id in guild_ids {
+        let id = Some(guild_id);
+        let guild = config.guild(id);
+        let name = state.cache.guild(guild_id).map(|g| g.name.clone());
+        let (_, model) = data.providers.for_guild(&config, id);
+        let keeps_questions = guild.is_some_and(|g| g.support_digest_channel.is_some());
+        if keeps_questions {
+            let label = name.clone().unwrap_or_else(|| guild_id.to_string());
+            for t in data
+                .storage
+                .transcripts(guild_id, now - RECENT_PERIOD)
+                .await?
+            {
+                recent.push((
+                    t.at,
+                    RecentQuestion {
+                        guild: label.clone(),
+                        at: t.at.time().to_string(),
+                        question: one_line(&t.question, RECENT_CHARS),
+                        answer: t.answer.map(|a| one_line(&a, RECENT_CHARS)),
+                        ok: t.ok,
+                    },
+                ));
+            }
+        }
+        guilds.push(GuildSnapshot {
+            id: guild_id.to_string(),
+            name,
+            provider: data.providers.name_for_guild(&config, id).to_string(),
+            model,
+            locale: config.locale(id),
+            dry_run: data.shadow.dry_run(&config, id),
+            monthly_token_cap: config.monthly_token_cap(id),
+            autorespond_channels: guild
+                .map(|g| g.autorespond_channels.clone())
+                .unwrap_or_default(),
+            support_roles: guild.map(|g| g.support_roles.clone()).unwrap_or_default(),
+            escalate: guild.is_some_and(|g| g.escalate),
+            keeps_questions,
+        });
+    }
+    recent.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+    recent.truncate(RECENT_LIMIT);
+
+    Ok(Snapshot {
+        queue: QueueSnapshot {
+            limit: queue.limit,
+            active: queue.active,
+            waiting: queue.waiting.len(),
+            avg_seconds: queue.avg_duration.as_secs_f64(),
+        },
+        prompt,
+        guilds,
+        usage: usage_by_day(data.storage.as_ref(), USAGE_DAYS, now).await?,
+        recent: recent.into_iter().map(|(_, q)| q).collect(),
+    })
+}
+
+/// Tokens used on each of the last `days` days up to `now`, oldest first
+pub async fn usage_by_day(
+    storage: &dyn Storage,
+    days: u32,
+    now: OffsetDateTime,
+) -> Result<Vec<DayUsage>, Error> {
+    let today = now.replace_time(Time::MIDNIGHT);
+    let mut usage = Vec::new();
+    // storage only totals usage since a time, so each day is the difference
+    // between the totals since its start and since the next day's
+    let mut later = 0;
+    for day in 0..days {
+        let start = today - Duration::days(day.into());
+        let total: u64 = storage
+            .usage(start)
+            .await?
+            .iter()
+            .map(|u| u.prompt_tokens + u.completion_tokens)
+            .sum();
+        usage.push(DayUsage {
+            date: start.date().to_string(),
+            tokens: total.saturating_sub(later),
+        });
+        later = total;
+    }
+    usage.reverse();
+    Ok(usage)
+}
+
+/// The page for `snapshot`, with its forms carrying `token` along
+pub fn render(snapshot: &Snapshot, token: &str) -> Result<String, minijinja::Error> {
+    let mut env = minijinja::Environment::new();
+    env.add_template("dashboard.html", TEMPLATE)?;
+    let peak = snapshot.usage.iter().map(|d| d.tokens).max().unwrap_or(0);
+    env.get_template("dashboard.html")?
+        .render(minijinja::context! {
+            token_query => urlencoding::encode(token),
+            peak,
+            ..minijinja::Value::from_serialize(snapshot)
+        })
+}
+
+async fn index(State(state): State<AppState>) -> Response {
+    let page = snapshot(&state).await.and_then(|snapshot| {
+        Ok(render(
+            &snapshot,
+            state.token.as_deref().unwrap_or_default(),
+        )?)
+    });
+    match page {
+        Ok(page) => Html(page).into_response(),
+        Err(e) => failed(e),
+    }
+}
+
+async fn status(State(state): State<AppState>) -> Response {
+    match snapshot(&state).await {
+        Ok(snapshot) => Json(snapshot).into_response(),
+        Err(e) => failed(e),
+    }
+}
+
+async fn toggle_shadow(State(state): State<AppState>, Path(guild_id): Path<u64>) -> Response {
+    let config = state.data.config();
+    let guild_id = GuildId::new(guild_id);
+    let on = state.data.shadow.toggle(&config, Some(guild_id));
+    info!(
+        "Shadow mode in guild {} switched {} from the dashboard",
+        guild_id,
+        if on { "on" } else { "off" }
+    );
+    back(&state)
+}
+
+async fn pin_prompt(State(state): State<AppState>, Path(version): Path<String>) -> Response {
+    match state.data.prompts.pin(&version).await {
+        Ok(Some(_)) => {
+            info!("Prompt version {} pinned from the dashboard", version);
+            back(&state)
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "No such prompt version").into_response(),
+        Err(e) => failed(e),
+    }
+}
+
+async fn unpin_prompt(State(state): State<AppState>) -> Response {
+    match state.data.prompts.unpin(&state.data.config()).await {
+        Ok(latest) => {
+            info!(
+                "Prompt unpinned from the dashboard, back to {}",
+                latest.version
+            );
+            back(&state)
+        }
+        Err(e) => failed(e),
+    }
+}
+
+/// Back to the page after a form
+fn back(state: &AppState) -> Response {
+    let token = state.token.as_deref().unwrap_or_default();
+    Redirect::to(&format!("/?token={}", urlencoding::encode(token))).into_response()
+}
+
+pub(crate) fn failed(e: Error) -> Response {
+    warn!("Failed to serve the dashboard: {}", e);
+    (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong").into_response()
+}
+
+const TEMPLATE: &str = r#"<!doctype html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="30">
+<title>deskhelp</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+.graph { display: flex; align-items: flex-end; gap: 4px; height: 120px; margin-bottom: 0.3em; }
+.bar { background: #5865f2; width: 28px; }
+.days { display: flex; gap: 4px; font-size: 0.7em; margin-bottom: 2em; }
+.days span { width: 28px; overflow: hidden; }
+.failed { color: #b00; }
+form { display: inline; }
+</style>
+</head>
+<body>
+<h1>deskhelp</h1>
+
+<h2>Queue</h2>
+<p>{{ queue.active }} of {{ queue.limit }} answering, {{ queue.waiting }} waiting, {{ "%.1f"|format(queue.avg_seconds) }}s per answer on average</p>
+
+<h2>Prompt</h2>
+<p>Version <code>{{ prompt.version }}</code> from {{ prompt.created_at }}{% if prompt.pinned %}, pinned
+<form method="post" action="/prompt/unpin?token={{ token_query }}"><button>Unpin</button></form>{% endif %}</p>
+{% if prompt.history %}
+<p>Earlier versions:
+{% for version in prompt.history %}
+<form method="post" action="/prompt/pin/{{ version }}?token={{ token_query }}"><button>Pin {{ version }}</button></form>
+{% endfor %}
+</p>
+{% endif %}
+
+<h2>Tokens per day</h2>
+<div class="graph">
+{% for day in usage %}<div class="bar" title="{{ day.date }}: {{ day.tokens }} tokens" style="height: {% if peak %}{{ (day.tokens * 100 / peak)|int }}{% else %}0{% endif %}%"></div>{% endfor %}
+</div>
+<div class="days">{% for day in usage %}<span>{{ day.date[5:] }}</span>{% endfor %}</div>
+
+<h2>Guilds</h2>
+<table>
+<tr><th>Guild</th><th>Provider</th><th>Model</th><th>Locale</th><th>Monthly cap</th><th>Autorespond channels</th><th>Support roles</th><th>Escalate</th><th>Shadow mode</th></tr>
+{% for guild in guilds %}
+<tr>
+<td>{{ guild.name or guild.id }}<br><small>{{ guild.id }}</small></td>
+<td>{{ guild.provider }}</td>
+<td>{{ guild.model }}</td>
+<td>{{ guild.locale }}</td>
+<td>{{ guild.monthly_token_cap or "none" }}</td>
+<td>{{ guild.autorespond_channels|join(", ") }}</td>
+<td>{{ guild.support_roles|join(", ") }}</td>
+<td>{{ "yes" if guild.escalate else "no" }}</td>
+<td>{{ "on" if guild.dry_run else "off" }}
+<form method="post" action="/guilds/{{ guild.id }}/shadow?token={{ token_query }}"><button>Switch {{ "off" if guild.dry_run else "on" }}</button></form></td>
+</tr>
+{% endfor %}
+</table>
+
+<h2>Recent questions</h2>
+<p><small>Only for guilds with <code>support_digest_channel</code> set, which keeps the text of questions.</small></p>
+<table>
+<tr><th>Guild</th><th>At</th><th>Question</th><th>Answer</th></tr>
+{% for q in recent %}
+<tr>
+<td>{{ q.guild }}</td>
+<td>{{ q.at }}</td>
+<td>{{ q.question }}</td>
+<td{% if not q.ok %} class="failed"{% endif %}>{{ q.answer or "no answer" }}</td>
+</tr>
+{% else %}
+<tr><td colspan="4">None in the last day</td></tr>
+{% endfor %}
+</table>
+</body>
+</html>
+"#;
@@ -0,0 +1,56 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serenity::all::{ChannelId, Message, UserId};
+
+/// Messages from one user in one channel that are waiting to be answered together
+#[derive(Default)]
+pub struct PendingBatch {
+    generation: u64,
+    messages: Vec<Message>,
+}
+
+pub type PendingBatches = Arc<Mutex<HashMap<(ChannelId, UserId), PendingBatch>>>;
+
+/// Whether the author of `msg` already has a batch waiting in this channel
+pub fn is_pending(pending: &PendingBatches, msg: &Message) -> bool {
+    pending
+        .lock()
+        .unwrap()
+        .contains_key(&(msg.channel_id, msg.author.id))
+}
+
+/// Adds `msg` to its author's batch and waits out the debounce window.
+/// Returns the whole batch if no newer message from the same author arrived in
+/// the meantime, otherwise `None` (the newer message's task will answer instead).
+pub async fn collect(
+    pending: &PendingBatches,
+    msg: Message,
+    window: Duration,
+) -> Option<Vec<Message>> {
+    if window.is_zero() {
+        return Some(vec![msg]);
+    }
+
+    let key = (msg.channel_id, msg.author.id);
+    let generation = {
+        let mut pending = pending.lock().unwrap();
+        let batch = pending.entry(key).or_default();
+        batch.generation += 1;
+        batch.messages.push(msg);
+        batch.generation
+    };
+
+    tokio::time::sleep(window).await;
+
+    let mut pending = pending.lock().unwrap();
+    match pending.get(&key) {
+        Some(batch) if batch.generation == generation => {
+            pending.remove(&key).map(|batch| batch.messages)
+        }
+        _ => None,
+    }
+}
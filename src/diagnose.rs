@@ -0,0 +1,55 @@
+use serenity::all::Permissions;
+
+/// A permission the bot needs in a channel, with the key of the message saying what for
+#[derive(Debug, PartialEq)]
+pub struct Needed {
+    pub permission: Permissions,
+    pub purpose: &'static str,
+}
+
+/// A needed permission and whether the bot has it
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub needed: Needed,
+    pub granted: bool,
+}
+
+/// What the bot needs to answer in a channel. Threads take Send Messages in
+/// Threads instead of Send Messages, and only guilds with a persona need webhooks.
+pub fn needed(in_thread: bool, persona: bool) -> Vec<Needed> {
+    let send = if in_thread {
+        Permissions::SEND_MESSAGES_IN_THREADS
+    } else {
+        Permissions::SEND_MESSAGES
+    };
+    let mut needed = vec![
+        (Permissions::VIEW_CHANNEL, "diagnose-view"),
+        (send, "diagnose-send"),
+        (Permissions::READ_MESSAGE_HISTORY, "diagnose-history"),
+        (Permissions::EMBED_LINKS, "diagnose-embed"),
+        (Permissions::ATTACH_FILES, "diagnose-attach"),
+        (Permissions::ADD_REACTIONS, "diagnose-react"),
+        (Permissions::CREATE_PUBLIC_THREADS, "diagnose-threads"),
+    ];
+    if persona {
+        needed.push((Permissions::MANAGE_WEBHOOKS, "diagnose-webhooks"));
+    }
+    needed
+        .into_iter()
+        .map(|(permission, purpose)| Needed {
+            permission,
+            purpose,
+        })
+        .collect()
+}
+
+/// Checks the bot's `granted` permissions in a channel against what it needs there
+pub fn check(granted: Permissions, in_thread: bool, persona: bool) -> Vec<Finding> {
+    needed(in_thread, persona)
+        .into_iter()
+        .map(|needed| Finding {
+            granted: granted.contains(needed.permission),
+            needed,
+        })
+        .collect()
+}
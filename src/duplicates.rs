@@ -0,0 +1,99 @@
+use serenity::all::{ChannelId, GuildId, MessageId};
+use time::OffsetDateTime;
+
+use crate::{
+    config::DuplicatesConfig,
+    provider::Provider,
+    storage::{Error, QuestionEmbedding, Storage},
+};
+
+/// A question as embedded, and the earlier answer to one like it if there is one
+pub struct Lookup {
+    pub embedding: Vec<f32>,
+    pub earlier: Option<QuestionEmbedding>,
+}
+
+/// Embeds `question` and looks for an earlier one like it in the guild. Answers
+/// in `channel_id` are already in the conversation, so they aren't linked.
+pub async fn lookup(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &DuplicatesConfig,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    question: &str,
+) -> Result<Lookup, Error> {
+    let embedding = provider.embed(&config.model, question).await?;
+    let since = OffsetDateTime::now_utc() - time::Duration::days(config.days.into());
+    let earlier = storage
+        .question_embeddings(guild_id, &config.model, since)
+        .await?;
+    let earlier = most_similar(
+        &embedding,
+        earlier.iter().filter(|e| e.channel_id != channel_id),
+        config.threshold,
+    )
+    .cloned();
+    Ok(Lookup { embedding, earlier })
+}
+
+/// Keeps an answered question to compare later ones with
+pub async fn remember(
+    storage: &dyn Storage,
+    config: &DuplicatesConfig,
+    guild_id: GuildId,
+    answer: (ChannelId, MessageId),
+    embedding: Vec<f32>,
+) -> Result<(), Error> {
+    storage
+        .save_question_embedding(&QuestionEmbedding {
+            guild_id,
+            channel_id: answer.0,
+            message_id: answer.1,
+            at: OffsetDateTime::now_utc(),
+            model: config.model.clone(),
+            embedding,
+        })
+        .await
+}
+
+/// The most similar of `earlier` to `embedding`, if it's at least `threshold`
+/// similar. The newest wins a tie.
+pub fn most_similar<'a>(
+    embedding: &[f32],
+    earlier: impl IntoIterator<Item = &'a QuestionEmbedding>,
+    threshold: f32,
+) -> Option<&'a QuestionEmbedding> {
+    earlier
+        .into_iter()
+        .map(|e| (similarity(embedding, &e.embedding), e))
+        .filter(|(similarity, _)| *similarity >= threshold)
+        .fold(None, |best: Option<(f32, _)>, (similarity, e)| match best {
+            Some((best_similarity, _)) if best_similarity > similarity => best,
+            _ => Some((similarity, e)),
+        })
+        .map(|(_, e)| e)
+}
+
+/// Cosine similarity: 1 for the same direction, 0 for unrelated. Embeddings of
+/// different lengths aren't comparable and get 0.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// A jump link to the earlier answer
+pub fn link(earlier: &QuestionEmbedding) -> String {
+    earlier
+        .message_id
+        .link(earlier.channel_id, Some(earlier.guild_id))
+}
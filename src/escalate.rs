@@ -0,0 +1,224 @@
+use async_openai::types::ChatCompletionRequestMessage;
+use serenity::all::{
+    ButtonStyle, ChannelId, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateInteractionResponseFollowup, CreateMessage, CreateThread,
+};
+use tracing::{info, warn};
+
+use crate::{i18n, markdown::fit_message, oai, storage::Error, titles, Data};
+
+/// `custom_id` of the button under answers; presses are handled however long ago it was posted
+pub const BUTTON_ID: &str = "deskhelp-escalate";
+/// How much of the conversation the model reads, newest kept
+const MAX_TRANSCRIPT: usize = 20_000;
+
+const SUMMARY_INSTRUCTIONS: &str = "A Discord support bot couldn't solve someone's problem, \
+and they asked for a human. Summarize the conversation below for the support staff taking \
+over: what the problem is, what was already suggested or tried, and anything still unclear. \
+At most 5 short Markdown bullet points, no greeting.";
+
+/// The button under answers in guilds with `escalate` on
+pub fn button(locale: &str) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(BUTTON_ID)
+        .label(i18n::tr(locale, "escalate-button", &[]))
+        .style(ButtonStyle::Secondary)])
+}
+
+/// The conversation as lines for the model to summarize, newest kept if it's long
+pub fn transcript(messages: &[ChatCompletionRequestMessage]) -> String {
+    let lines: Vec<String> = messages
+        .iter()
+        .filter_map(|message| {
+            let who = match message {
+                ChatCompletionRequestMessage::User(_) => "Asker",
+                ChatCompletionRequestMessage::Assistant(_) => "Bot",
+                _ => return None,
+            };
+            Some(format!("{}: {}", who, oai::message_text(message)))
+        })
+        .collect();
+    let mut transcript = lines.join("\n");
+    if transcript.len() > MAX_TRANSCRIPT {
+        let mut start = transcript.len() - MAX_TRANSCRIPT;
+        while !transcript.is_char_boundary(start) {
+            start += 1;
+        }
+        transcript.drain(..start);
+    }
+    transcript
+}
+
+/// Opens a support thread on the answer the button was pressed under (or
+/// posts in the thread it's in), pinging the guild's support roles with a
+/// summary of the conversation
+pub async fn on_press(ctx: &serenity::prelude::Context, data: &Data, press: &ComponentInteraction) {
+    if let Err(e) = press.defer_ephemeral(&ctx.http).await {
+        warn!("Failed to acknowledge an escalation: {}", e);
+        return;
+    }
+    let locale = data.config().locale(press.guild_id);
+    let content = match escalate(ctx, data, press).await {
+        Ok(HandOver::Asked(thread)) => i18n::tr(
+            &locale,
+            "escalate-done",
+            &[("thread", format!("<#{}>", thread).into())],
+        ),
+        Ok(HandOver::AlreadyAsked(thread)) => i18n::tr(
+            &locale,
+            "escalate-already",
+            &[("thread", format!("<#{}>", thread).into())],
+        ),
+        Err(e) => {
+            warn!("Failed to escalate to support: {}", e);
+            i18n::tr(&locale, "escalate-failed", &[])
+        }
+    };
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(content)
+        .ephemeral(true);
+    if let Err(e) = press.create_followup(&ctx.http, followup).await {
+        warn!("Failed to answer an escalation: {}", e);
+    }
+}
+
+async fn escalate(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+) -> Result<HandOver, Error> {
+    if !data
+        .config()
+        .guild(press.guild_id)
+        .is_some_and(|g| g.escalate)
+    {
+        return Err("escalation is off in this guild".into());
+    }
+    hand_over(ctx, data, press).await
+}
+
+/// Where support was asked to take over
+pub enum HandOver {
+    /// Just now, in this thread
+    Asked(ChannelId),
+    /// On an earlier press under the same message
+    AlreadyAsked(ChannelId),
+}
+
+impl HandOver {
+    pub fn thread(&self) -> ChannelId {
+        match self {
+            HandOver::Asked(thread) | HandOver::AlreadyAsked(thread) => *thread,
+        }
+    }
+}
+
+fn key(press: &ComponentInteraction) -> String {
+    format!("escalated.{}", press.message.id)
+}
+
+/// Pings the support roles in a thread on the message pressed under (or the
+/// thread it's in) with a summary of the conversation, once per message
+pub async fn hand_over(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+) -> Result<HandOver, Error> {
+    let config = data.config();
+    let locale = config.locale(press.guild_id);
+    // one thread, and one ping, per answer
+    if let Some(thread) = &press.message.thread {
+        return Ok(HandOver::AlreadyAsked(thread.id));
+    }
+    if let Some(thread) = data.storage.setting(&key(press)).await? {
+        return Ok(HandOver::AlreadyAsked(ChannelId::new(thread.parse()?)));
+    }
+    let conversation = data.ai_context.get(press.channel_id).await?;
+    let channel = press.channel_id.to_channel(ctx).await?.guild();
+    let in_thread = channel.is_some_and(|c| c.thread_metadata.is_some());
+    let thread = if in_thread {
+        press.channel_id
+    } else {
+        // named after the question answered, rather than whatever the answer starts with
+        let question = conversation
+            .iter()
+            .rev()
+            .find(|message| matches!(message, ChatCompletionRequestMessage::User(_)))
+            .map(oai::message_text);
+        let title = match question {
+            Some(question) => titles::generate(data, &config, press.guild_id, &question).await,
+            None => None,
+        };
+        let name = title.unwrap_or_else(|| {
+            i18n::tr(
+                &locale,
+                "escalate-thread",
+                &[(
+                    "user",
+                    press
+                        .user
+                        .global_name
+                        .as_ref()
+                        .unwrap_or(&press.user.name)
+                        .as_str()
+                        .into(),
+                )],
+            )
+            .chars()
+            .take(titles::MAX_TITLE)
+            .collect()
+        });
+        press
+            .channel_id
+            .create_thread_from_message(&ctx.http, press.message.id, CreateThread::new(name))
+            .await?
+            .id
+    };
+    // saved before the slow part, so presses meanwhile don't ping again
+    data.storage
+        .set_setting(&key(press), Some(&thread.to_string()))
+        .await?;
+
+    let (provider, model) = data.providers.for_guild(&config, press.guild_id);
+    let summary = match oai::complete(
+        provider,
+        &config,
+        &model,
+        SUMMARY_INSTRUCTIONS,
+        &transcript(&conversation),
+    )
+    .await
+    {
+        Ok(summary) => summary,
+        Err(e) => {
+            warn!("Failed to summarize the conversation for support: {}", e);
+            i18n::tr(&locale, "escalate-no-summary", &[])
+        }
+    };
+    let roles: Vec<String> = config
+        .guild(press.guild_id)
+        .map(|g| g.support_roles.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|id| format!("<@&{}>", id))
+        .collect();
+    let content = i18n::tr(
+        &locale,
+        "escalate-post",
+        &[
+            ("roles", roles.join(" ").into()),
+            ("user", format!("<@{}>", press.user.id).into()),
+        ],
+    ) + "\n"
+        + summary.as_str();
+    thread
+        .send_message(
+            &ctx.http,
+            CreateMessage::new().content(fit_message(content)),
+        )
+        .await?;
+    info!(
+        "{} escalated the conversation in {} to support in {}",
+        press.user.id, press.channel_id, thread
+    );
+    Ok(HandOver::Asked(thread))
+}
@@ -0,0 +1,122 @@
+//! Structured events published to a NATS server as they happen, for
+//! processing outside the bot. Each event is JSON, published to
+//! `<subject>.<type>`, like `deskhelp.answer_sent`.
+
+use serde::Serialize;
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::OffsetDateTime;
+
+use crate::{activity::Activity, config::EventsConfig};
+
+#[cfg(feature = "nats")]
+static BUS: std::sync::OnceLock<Bus> = std::sync::OnceLock::new();
+
+#[cfg(feature = "nats")]
+struct Bus {
+    client: async_nats::Client,
+    subject: String,
+}
+
+/// Something that happened, tagged with its `type`
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A message taken as a question, before it's answered
+    QuestionReceived {
+        #[serde(with = "time::serde::rfc3339")]
+        at: OffsetDateTime,
+        bot: Option<String>,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        user_id: UserId,
+        message_id: MessageId,
+        question: String,
+    },
+    /// A finished answer, with what the activity webhook gets
+    AnswerSent(Activity),
+    /// A 👍 or 👎 added to or taken off an answer
+    Feedback {
+        #[serde(with = "time::serde::rfc3339")]
+        at: OffsetDateTime,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        user_id: UserId,
+        up: bool,
+        added: bool,
+    },
+    /// An error, scrubbed like error reports are
+    Error {
+        #[serde(with = "time::serde::rfc3339")]
+        at: OffsetDateTime,
+        message: String,
+        guild_id: Option<GuildId>,
+        model: Option<String>,
+    },
+}
+
+impl Event {
+    /// What the event is, as in its `type`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::QuestionReceived { .. } => "question_received",
+            Event::AnswerSent(_) => "answer_sent",
+            Event::Feedback { .. } => "feedback",
+            Event::Error { .. } => "error",
+        }
+    }
+
+    /// Where the event is published, under `prefix`
+    pub fn subject(&self, prefix: &str) -> String {
+        format!("{}.{}", prefix, self.kind())
+    }
+}
+
+/// Connects to the server in `[events]`; without one, events go nowhere
+pub async fn init(config: Option<&EventsConfig>) {
+    let Some(config) = config else {
+        return;
+    };
+    #[cfg(feature = "nats")]
+    match async_nats::connect(&config.url).await {
+        Ok(client) => {
+            tracing::info!("Publishing events to {}", config.url);
+            let _ = BUS.set(Bus {
+                client,
+                subject: config.subject.clone(),
+            });
+        }
+        Err(e) => tracing::error!("Not publishing events, can't reach {}: {}", config.url, e),
+    }
+    #[cfg(not(feature = "nats"))]
+    {
+        let _ = config;
+        tracing::warn!("[events] needs a build with `--features nats`");
+    }
+}
+
+/// Publishes `event` in the background, if there's a server to publish to
+pub fn publish(event: Event) {
+    #[cfg(feature = "nats")]
+    {
+        let Some(bus) = BUS.get() else {
+            return;
+        };
+        let subject = event.subject(&bus.subject);
+        let payload = match serde_json::to_vec(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::warn!("Failed to serialize a {} event: {}", event.kind(), e);
+                return;
+            }
+        };
+        let client = bus.client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.publish(subject, payload.into()).await {
+                tracing::warn!("Failed to publish an event: {}", e);
+            }
+        });
+    }
+    #[cfg(not(feature = "nats"))]
+    let _ = event;
+}
@@ -0,0 +1,134 @@
+use rand::Rng;
+use serenity::all::ReactionType;
+
+use crate::{
+    config::{Config, VariantConfig},
+    i18n,
+    storage::VariantStats,
+};
+
+/// Reactions counted as feedback on answers
+pub const THUMBS_UP: &str = "👍";
+pub const THUMBS_DOWN: &str = "👎";
+
+/// The variant picked to answer one question
+pub struct Assignment<'a> {
+    pub experiment: &'a str,
+    pub variant: &'a VariantConfig,
+}
+
+/// Picks a variant of the running experiment, if any, in proportion to their weights
+pub fn assign(config: &Config) -> Option<Assignment<'_>> {
+    let experiment = config.experiment.as_ref()?;
+    let total: u32 = experiment.variants.iter().map(|v| v.weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut pick = rand::thread_rng().gen_range(0..total);
+    let variant = experiment.variants.iter().find(|v| {
+        if pick < v.weight {
+            true
+        } else {
+            pick -= v.weight;
+            false
+        }
+    })?;
+    Some(Assignment {
+        experiment: &experiment.name,
+        variant,
+    })
+}
+
+/// Whether a reaction is a 👍 (`true`) or 👎 (`false`), or neither
+pub fn vote(emoji: &ReactionType) -> Option<bool> {
+    match emoji {
+        // with or without a skin tone
+        ReactionType::Unicode(e) if e.starts_with(THUMBS_UP) => Some(true),
+        ReactionType::Unicode(e) if e.starts_with(THUMBS_DOWN) => Some(false),
+        _ => None,
+    }
+}
+
+/// Each variant's results, as posted to Discord
+pub fn summary(locale: &str, experiment: &str, variants: &[VariantStats]) -> String {
+    let mut lines = vec![i18n::tr(
+        locale,
+        "experiment-results",
+        &[("name", experiment.into())],
+    )];
+    for v in variants {
+        let votes = v.thumbs_up + v.thumbs_down;
+        let approval = if votes == 0 {
+            "-".to_string()
+        } else {
+            format!("{:.0}", v.thumbs_up as f64 / votes as f64 * 100.0)
+        };
+        lines.push(i18n::tr(
+            locale,
+            "experiment-variant",
+            &[
+                ("variant", v.variant.as_str().into()),
+                ("answered", v.answered.into()),
+                ("errors", v.errors.into()),
+                (
+                    "average",
+                    format!("{:.1}", v.average_latency.as_secs_f64()).into(),
+                ),
+                ("up", v.thumbs_up.into()),
+                ("down", v.thumbs_down.into()),
+                ("approval", approval.into()),
+            ],
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExperimentConfig;
+
+    fn variant(name: &str, weight: u32) -> VariantConfig {
+        VariantConfig {
+            name: name.to_string(),
+            weight,
+            model: None,
+            system_prompt: None,
+        }
+    }
+
+    fn config(variants: Vec<VariantConfig>) -> Config {
+        Config {
+            experiment: Some(ExperimentConfig {
+                name: "test".to_string(),
+                variants,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn nothing_is_assigned_without_an_experiment_or_weights() {
+        assert!(assign(&Config::default()).is_none());
+        assert!(assign(&config(vec![variant("a", 0)])).is_none());
+    }
+
+    #[test]
+    fn variants_without_weight_are_never_picked() {
+        let config = config(vec![variant("off", 0), variant("on", 3), variant("off", 0)]);
+        for _ in 0..50 {
+            let assignment = assign(&config).unwrap();
+            assert_eq!(assignment.experiment, "test");
+            assert_eq!(assignment.variant.name, "on");
+        }
+    }
+
+    #[test]
+    fn thumbs_count_with_any_skin_tone() {
+        let unicode = |s: &str| ReactionType::Unicode(s.to_string());
+        assert_eq!(vote(&unicode("👍")), Some(true));
+        assert_eq!(vote(&unicode("👍🏽")), Some(true));
+        assert_eq!(vote(&unicode("👎")), Some(false));
+        assert_eq!(vote(&unicode("🎉")), None);
+    }
+}
@@ -0,0 +1,90 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent};
+use serenity::all::{ChannelId, UserId};
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::{
+    context::ContextStore,
+    language,
+    storage::{Deletion, Error},
+    Data,
+};
+
+/// Deletes everything the bot keeps about a user, in storage and in
+/// conversations, and records that it did
+pub async fn forget(data: &Data, user_id: UserId) -> Result<Deletion, Error> {
+    let messages = forget_conversations(data.ai_context.as_ref(), user_id).await?
+        + data.lurked.forget(user_id);
+    let mut records = data.storage.forget_user(user_id).await?;
+    if language::preferred(data.storage.as_ref(), user_id)
+        .await?
+        .is_some()
+    {
+        language::set_preferred(data.storage.as_ref(), user_id, None).await?;
+        records += 1;
+    }
+    let deletion = Deletion {
+        user_id,
+        at: OffsetDateTime::now_utc(),
+        records,
+        messages,
+    };
+    data.storage.record_deletion(&deletion).await?;
+    info!(
+        "Forgot user {}: {} records and {} conversation messages",
+        user_id, records, messages
+    );
+    Ok(deletion)
+}
+
+/// Drops the user's messages from every conversation, returning how many went
+///
+/// Only conversations the user spoke in are edited, each under the store's
+/// lock, so messages pushed meanwhile stay and other conversations keep
+/// their place in the retention purge
+pub async fn forget_conversations(store: &dyn ContextStore, user_id: UserId) -> Result<u64, Error> {
+    let mut removed = 0;
+    for (channel_id, mut messages) in store.all().await? {
+        if forget_messages(&mut messages, user_id) == 0 {
+            continue;
+        }
+        let Ok(channel_id) = channel_id.parse::<ChannelId>() else {
+            continue;
+        };
+        removed += store
+            .update(channel_id, &move |messages| {
+                forget_messages(messages, user_id)
+            })
+            .await?;
+    }
+    Ok(removed)
+}
+
+/// Drops messages with something the user said, and the bot's replies to them
+pub fn forget_messages(messages: &mut Vec<ChatCompletionRequestMessage>, user_id: UserId) -> u64 {
+    let before = messages.len();
+    let mut dropping = false;
+    messages.retain(|message| {
+        match message {
+            ChatCompletionRequestMessage::User(user) => dropping = is_from(&user.content, user_id),
+            ChatCompletionRequestMessage::Assistant(_) => {}
+            _ => dropping = false,
+        }
+        !dropping
+    });
+    (before - messages.len()) as u64
+}
+
+/// Whether the text has a line from the user, as the bot writes them down:
+/// `name (id): text`
+fn is_from(content: &ChatCompletionRequestUserMessageContent, user_id: UserId) -> bool {
+    match content {
+        ChatCompletionRequestUserMessageContent::Text(text) => text.contains(&marker(user_id)),
+        ChatCompletionRequestUserMessageContent::Array(_) => false,
+    }
+}
+
+/// What follows the name at the start of each of the user's lines
+pub fn marker(user_id: UserId) -> String {
+    format!("({}): ", user_id)
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use serenity::all::{ChannelType, EditThread, ForumTagId, GuildChannel, Message};
+use tracing::{info, warn};
+
+use crate::{oai, Data};
+
+/// Forum posts take at most five tags
+const MAX_TAGS: usize = 5;
+
+/// Tags a new forum post with the tags mapped to the category the model puts
+/// its question in, for forums with a `forum_tags` mapping
+pub async fn classify_post(ctx: &serenity::prelude::Context, data: &Data, msg: &Message) {
+    // only the post's opening message says what it's about
+    if msg.id.get() != msg.channel_id.get() {
+        return;
+    }
+    let config = data.config();
+    let Some(guild) = config.guild(msg.guild_id) else {
+        return;
+    };
+    if guild.forum_tags.is_empty() {
+        return;
+    }
+    let Some((post, forum)) = post_and_forum(ctx, msg).await else {
+        return;
+    };
+    let Some(mapping) = guild.forum_tags.get(&forum.id.to_string()) else {
+        return;
+    };
+
+    let (provider, model) = data.providers.for_guild(&config, msg.guild_id);
+    let question = format!("{}\n\n{}", post.name, msg.content);
+    let reply =
+        match oai::complete(provider, &config, &model, &instructions(mapping), &question).await {
+            Ok(reply) => reply,
+            Err(e) => {
+                warn!("Failed to classify the forum post: {}", e);
+                return;
+            }
+        };
+    let Some(category) = parse(&reply, mapping) else {
+        return;
+    };
+    info!("Classified forum post {} as {}", post.id, category);
+    apply(ctx, post, &forum, &mapping[category]).await;
+}
+
+/// Asks for exactly one of the mapping's categories, or none
+pub fn instructions(mapping: &BTreeMap<String, Vec<String>>) -> String {
+    format!(
+        "You sort support questions in a Discord forum. Reply with only the one category \
+         below that fits the question best, or `none` if none do.\n\nCategories: {}",
+        mapping.keys().cloned().collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// The category the model picked, as named in the mapping
+pub fn parse<'a>(reply: &str, mapping: &'a BTreeMap<String, Vec<String>>) -> Option<&'a str> {
+    let reply = reply
+        .trim()
+        .trim_matches(|c: char| c == '`' || c == '.' || c == '"');
+    mapping
+        .keys()
+        .find(|category| category.eq_ignore_ascii_case(reply))
+        .map(String::as_str)
+}
+
+/// The post `msg` is in and its forum, if it's in one
+pub async fn post_and_forum(
+    ctx: &serenity::prelude::Context,
+    msg: &Message,
+) -> Option<(GuildChannel, GuildChannel)> {
+    let post = msg.channel(ctx).await.ok()?.guild()?;
+    let forum = post.parent_id?.to_channel(ctx).await.ok()?.guild()?;
+    (forum.kind == ChannelType::Forum).then_some((post, forum))
+}
+
+/// Adds the forum's tags with these names to the post
+pub async fn apply(
+    ctx: &serenity::prelude::Context,
+    mut post: GuildChannel,
+    forum: &GuildChannel,
+    names: &[String],
+) {
+    let available: Vec<_> = forum
+        .available_tags
+        .iter()
+        .map(|t| (t.id, t.name.clone()))
+        .collect();
+    let tags = matching(&available, &post.applied_tags, names);
+    if tags == post.applied_tags {
+        return;
+    }
+    if let Err(e) = post
+        .edit_thread(ctx, EditThread::new().applied_tags(tags))
+        .await
+    {
+        warn!("Failed to tag the forum post: {}", e);
+    }
+}
+
+/// Tags whose names match one of `names` (ignoring case), added to the ones
+/// already applied
+pub fn matching(
+    available: &[(ForumTagId, String)],
+    applied: &[ForumTagId],
+    names: &[String],
+) -> Vec<ForumTagId> {
+    let mut tags = applied.to_vec();
+    for (id, name) in available {
+        let matches = names.iter().any(|n| n.eq_ignore_ascii_case(name));
+        if matches && !tags.contains(id) {
+            tags.push(*id);
+        }
+    }
+    tags.truncate(MAX_TAGS);
+    tags
+}
@@ -0,0 +1,224 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, GuildId};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::{
+    announce,
+    config::GithubConfig,
+    dashboard::{token_matches, AppState},
+    i18n,
+    markdown::fit_message,
+    oai,
+    releases::Release,
+    Data,
+};
+
+/// How much of an issue the model is given to summarize
+const MAX_ISSUE: usize = 8000;
+
+const SUMMARY_INSTRUCTIONS: &str = "You summarize GitHub issues asking questions for a \
+Discord community that might know the answer. In at most 3 short sentences, say what the \
+person is trying to do, what goes wrong, and anything about their setup that matters. Reply \
+with only the summary.";
+
+/// Takes GitHub's webhooks at `/github`; they're checked against the
+/// `[github]` secret rather than a token
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/github", post(webhook))
+}
+
+/// What GitHub sends, as far as it matters here
+#[derive(Deserialize)]
+pub struct Payload {
+    pub action: Option<String>,
+    pub repository: Option<Repository>,
+    pub release: Option<Release>,
+    pub issue: Option<Issue>,
+    /// The label just added, for `labeled` actions
+    pub label: Option<Label>,
+}
+
+#[derive(Deserialize)]
+pub struct Repository {
+    pub full_name: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Label {
+    pub name: String,
+}
+
+/// Something worth posting about
+#[derive(Debug, PartialEq)]
+pub enum Notice {
+    Release { repo: String, release: Release },
+    Question { repo: String, issue: Issue },
+}
+
+/// Whether `signature` (GitHub's `X-Hub-Signature-256`) is `body` signed with `secret`
+pub fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    let expected: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    token_matches(&format!("sha256={}", expected), signature)
+}
+
+/// What to post for a webhook of kind `event`, if anything: published
+/// releases, and issues given the question label
+pub fn notice(config: &GithubConfig, event: &str, payload: Payload) -> Option<Notice> {
+    let repo = payload.repository?.full_name;
+    if !config.repos.is_empty() && !config.repos.contains(&repo) {
+        return None;
+    }
+    match (event, payload.action.as_deref()?) {
+        ("release", "published") => Some(Notice::Release {
+            repo,
+            release: payload.release?,
+        }),
+        ("issues", "labeled") if payload.label?.name.eq_ignore_ascii_case(&config.label) => {
+            Some(Notice::Question {
+                repo,
+                issue: payload.issue?,
+            })
+        }
+        _ => None,
+    }
+}
+
+async fn webhook(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let config = state.data.config();
+    let Some(github) = &config.github else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(secret) = github.secret() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    if !header("x-hub-signature-256").is_some_and(|signature| verify(&secret, &body, signature)) {
+        return (StatusCode::UNAUTHORIZED, "The signature doesn't match").into_response();
+    }
+    let event = header("x-github-event").unwrap_or_default();
+    let payload: Payload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if let Some(notice) = notice(github, event, payload) {
+        // GitHub gives up on webhooks that take more than a few seconds
+        tokio::spawn(async move { post_notice(&state, notice).await });
+    }
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// Posts `notice` in every guild's `github_channel`, summarized once for all
+async fn post_notice(state: &AppState, notice: Notice) {
+    let data = &state.data;
+    let config = data.config();
+    let channels: Vec<_> = config
+        .guilds
+        .iter()
+        .filter_map(|(id, guild)| Some((id.parse::<GuildId>().ok()?, guild.github_channel?)))
+        .collect();
+    if channels.is_empty() {
+        return;
+    }
+    let summary = match &notice {
+        Notice::Release { release, .. } => announce::tldr(data, release).await,
+        Notice::Question { issue, .. } => summarize(data, issue).await,
+    };
+    for (guild_id, channel_id) in channels {
+        let locale = config.locale(Some(guild_id));
+        let content = match &notice {
+            Notice::Release { repo, release } => {
+                // called what `[releases]` calls it, if it's one of those
+                let component = config
+                    .releases
+                    .repos
+                    .iter()
+                    .find(|(_, r)| *r == repo)
+                    .map_or(repo.as_str(), |(component, _)| component.as_str());
+                announce::announcement(&locale, component, release, summary.as_deref())
+            }
+            Notice::Question { repo, issue } => question(&locale, repo, issue, summary.as_deref()),
+        };
+        // release notes and issues are written by anyone, so they ping no one
+        let message = CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new());
+        if let Err(e) = ChannelId::new(channel_id)
+            .send_message(&state.http, message)
+            .await
+        {
+            warn!("Failed to post from GitHub in guild {}: {}", guild_id, e);
+        }
+    }
+    match &notice {
+        Notice::Release { repo, release } => {
+            info!("Posted {} {} from GitHub", repo, release.tag_name)
+        }
+        Notice::Question { repo, issue } => {
+            info!("Posted question {}#{} from GitHub", repo, issue.number)
+        }
+    }
+}
+
+/// A few sentences about the issue from the default provider, if it can give them
+async fn summarize(data: &Data, issue: &Issue) -> Option<String> {
+    let body = issue.body.as_deref().unwrap_or_default().trim();
+    let input: String = format!("# {}\n\n{}", issue.title, body)
+        .chars()
+        .take(MAX_ISSUE)
+        .collect();
+    let config = data.config();
+    let (provider, model) = data.providers.for_guild(&config, None);
+    match oai::complete(provider, &config, &model, SUMMARY_INSTRUCTIONS, &input).await {
+        Ok(summary) => Some(summary.trim().to_string()),
+        Err(e) => {
+            warn!("Failed to summarize issue #{}: {}", issue.number, e);
+            None
+        }
+    }
+}
+
+/// The post about a question asked on GitHub, cut to fit in one message
+pub fn question(locale: &str, repo: &str, issue: &Issue, summary: Option<&str>) -> String {
+    let mut text = i18n::tr(
+        locale,
+        "github-question",
+        &[
+            ("repo", repo.into()),
+            ("number", issue.number.into()),
+            ("title", issue.title.as_str().into()),
+            ("url", issue.html_url.as_str().into()),
+        ],
+    );
+    if let Some(summary) = summary.filter(|s| !s.is_empty()) {
+        text.push_str(&format!("\n{}", summary));
+    }
+    fit_message(text)
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use rand::Rng;
+use tracing::{info, warn};
+use unic_langid::LanguageIdentifier;
+
+/// What everything falls back to
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// Translations that ship with the bot
+const BUILT_IN: &[(&str, &str)] = &[
+    ("en-US", include_str!("../locales/en-US.ftl")),
+    ("de", include_str!("../locales/de.ftl")),
+];
+
+static BUNDLES: OnceLock<HashMap<String, FluentBundle<FluentResource>>> = OnceLock::new();
+
+/// Loads the built-in translations plus any `<locale>.ftl` files in `dir`, which
+/// add languages or override built-in strings. Without this, only the built-in
+/// ones are used.
+pub fn init(dir: Option<&str>) {
+    let mut sources: HashMap<String, Vec<String>> = BUILT_IN
+        .iter()
+        .map(|(locale, ftl)| (locale.to_string(), vec![ftl.to_string()]))
+        .collect();
+
+    if let Some(dir) = dir {
+        match std::fs::read_dir(dir) {
+            Ok(entries) => {
+                for path in entries.flatten().map(|e| e.path()) {
+                    if path.extension().is_some_and(|e| e == "ftl") {
+                        if let Some((locale, ftl)) = read_locale(&path) {
+                            info!("Loaded translations from {}", path.display());
+                            sources.entry(locale).or_default().push(ftl);
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to read locales from {}: {}", dir, e),
+        }
+    }
+
+    let _ = BUNDLES.set(build(sources));
+}
+
+/// Every locale with translations, sorted
+pub fn locales() -> Vec<String> {
+    let mut locales: Vec<String> = match BUNDLES.get() {
+        Some(bundles) => bundles.keys().cloned().collect(),
+        None => BUILT_IN
+            .iter()
+            .map(|(locale, _)| locale.to_string())
+            .collect(),
+    };
+    locales.sort();
+    locales
+}
+
+fn read_locale(path: &Path) -> Option<(String, String)> {
+    let locale = path.file_stem()?.to_str()?.to_string();
+    match std::fs::read_to_string(path) {
+        Ok(ftl) => Some((locale, ftl)),
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn build(sources: HashMap<String, Vec<String>>) -> HashMap<String, FluentBundle<FluentResource>> {
+    let mut bundles = HashMap::new();
+    for (locale, ftls) in sources {
+        let Ok(langid) = locale.parse::<LanguageIdentifier>() else {
+            warn!("Skipping translations for {}, it isn't a locale", locale);
+            continue;
+        };
+        let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+        // Discord shows the invisible isolation marks around placeables as boxes on some clients
+        bundle.set_use_isolating(false);
+        for ftl in ftls {
+            let resource = FluentResource::try_new(ftl).unwrap_or_else(|(resource, errors)| {
+                warn!("Errors in translations for {}: {:?}", locale, errors);
+                resource
+            });
+            // later files override earlier ones
+            bundle.add_resource_overriding(resource);
+        }
+        bundles.insert(locale, bundle);
+    }
+    bundles
+}
+
+fn bundles() -> &'static HashMap<String, FluentBundle<FluentResource>> {
+    BUNDLES.get_or_init(|| {
+        build(
+            BUILT_IN
+                .iter()
+                .map(|(locale, ftl)| (locale.to_string(), vec![ftl.to_string()]))
+                .collect(),
+        )
+    })
+}
+
+/// Translations to try for `locale`, most specific first: `de-AT`, then `de`, then English
+fn fallbacks(locale: &str) -> impl Iterator<Item = &'static FluentBundle<FluentResource>> + '_ {
+    let language = locale.split(['-', '_']).next().unwrap_or(locale);
+    [locale, language, DEFAULT_LOCALE]
+        .into_iter()
+        .filter_map(|l| bundles().get(l))
+}
+
+/// Whether there's a message called `id` in `locale` or its fallbacks
+fn has(locale: &str, id: &str) -> bool {
+    fallbacks(locale).any(|b| b.has_message(id))
+}
+
+/// The text of message `id` in `locale`, falling back to English
+pub fn tr(locale: &str, id: &str, args: &[(&str, FluentValue)]) -> String {
+    let args = (!args.is_empty()).then(|| {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        fluent_args
+    });
+
+    for bundle in fallbacks(locale) {
+        let Some(pattern) = bundle.get_message(id).and_then(|m| m.value()) else {
+            continue;
+        };
+        let mut errors = vec![];
+        let text = bundle.format_pattern(pattern, args.as_ref(), &mut errors);
+        if !errors.is_empty() {
+            warn!("Errors formatting {} in {}: {:?}", id, locale, errors);
+        }
+        return text.into_owned();
+    }
+
+    warn!("No translation for {}", id);
+    id.to_string()
+}
+
+/// One of the `reset-N` messages, at random
+pub fn reset_message(locale: &str) -> String {
+    let count = (1..)
+        .take_while(|n| has(locale, &format!("reset-{}", n)))
+        .count();
+    if count == 0 {
+        return tr(locale, "reset-1", &[]);
+    }
+    let n = rand::thread_rng().gen_range(1..=count);
+    tr(locale, &format!("reset-{}", n), &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_are_filled_in_without_isolation_marks() {
+        let text = tr(
+            "en-US",
+            "waiting-in-line",
+            &[("position", 2.into()), ("seconds", 30.into())],
+        );
+        assert_eq!(text, "Waiting in line... (#2 in queue, about 30s)");
+    }
+
+    #[test]
+    fn falls_back_to_the_language_then_english() {
+        assert_eq!(tr("de-AT", "generating", &[]), "Antwort wird erstellt...");
+        assert_eq!(tr("fr", "generating", &[]), "Generating response...");
+        assert_eq!(tr("en-US", "no-such-message", &[]), "no-such-message");
+    }
+
+    #[test]
+    fn reset_messages_come_from_the_locale() {
+        assert!(reset_message("de").starts_with('*'));
+        assert!(!has("en-US", "reset-19"));
+    }
+}
@@ -0,0 +1,232 @@
+use serenity::all::GuildId;
+use time::OffsetDateTime;
+
+use crate::{
+    config::SearchConfig,
+    duplicates, i18n,
+    markdown::fit_message,
+    provider::Provider,
+    storage::{Document, Error, KbVersion, Storage},
+};
+
+/// How many documents the model is shown
+const MAX_SHOWN: usize = 3;
+/// How many documents `/docs` lists
+pub const MAX_RESULTS: usize = 5;
+/// How similar to the question a document must be to be shown
+const MIN_SIMILARITY: f32 = 0.4;
+/// Longest document that can be pushed, in characters
+pub const MAX_DOCUMENT: usize = 20_000;
+/// How much of a document a `/docs` result quotes
+const MAX_EXCERPT: usize = 250;
+/// How many versions of each guild's knowledge base are kept to roll back to
+pub const KEEP_VERSIONS: usize = 20;
+
+/// Embeds `text` and keeps it as the guild's document `name`, replacing any
+/// document of that name
+pub async fn push(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    name: &str,
+    text: &str,
+    url: Option<&str>,
+) -> Result<Document, Error> {
+    let embedding = provider.embed(&config.model, &embedded(name, text)).await?;
+    let document = Document {
+        guild_id,
+        name: name.to_string(),
+        text: text.to_string(),
+        url: url.map(str::to_string),
+        model: config.model.clone(),
+        embedding,
+        updated_at: OffsetDateTime::now_utc(),
+    };
+    storage.save_document(&document, KEEP_VERSIONS).await?;
+    Ok(document)
+}
+
+/// Removes the guild's document `name`, returning whether it was there
+pub async fn delete(storage: &dyn Storage, guild_id: GuildId, name: &str) -> Result<bool, Error> {
+    storage.delete_document(guild_id, name, KEEP_VERSIONS).await
+}
+
+/// Puts the guild's knowledge base back to `version`, if it's still kept,
+/// embedding again the documents that changed since
+pub async fn rollback(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    version: &str,
+) -> Result<Option<KbVersion>, Error> {
+    let Some(found) = storage.kb_version(guild_id, version).await? else {
+        return Ok(None);
+    };
+    let current = storage.documents(guild_id).await?;
+    let mut documents = Vec::with_capacity(found.documents.len());
+    for kept in &found.documents {
+        // unchanged ones keep their embedding
+        let embedding = match current
+            .iter()
+            .find(|d| d.name == kept.name && d.text == kept.text && d.model == config.model)
+        {
+            Some(document) => document.embedding.clone(),
+            None => {
+                provider
+                    .embed(&config.model, &embedded(&kept.name, &kept.text))
+                    .await?
+            }
+        };
+        documents.push(Document {
+            guild_id,
+            name: kept.name.clone(),
+            text: kept.text.clone(),
+            url: kept.url.clone(),
+            model: config.model.clone(),
+            embedding,
+            updated_at: kept.updated_at,
+        });
+    }
+    storage
+        .replace_documents(guild_id, &documents, KEEP_VERSIONS)
+        .await?;
+    Ok(Some(found))
+}
+
+/// What's embedded of a document
+fn embedded(name: &str, text: &str) -> String {
+    format!("{}\n{}", name, text)
+}
+
+/// The guild's documents most like `question` for the model to go by, best
+/// first, if it has any like it, and the version of the knowledge base they're
+/// from, if it has any documents
+pub async fn retrieve(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    question: &str,
+) -> Result<(Vec<Document>, Option<String>), Error> {
+    matching(storage, provider, config, guild_id, question, MAX_SHOWN).await
+}
+
+/// The guild's documents most like `query`, best first, for `/docs`
+pub async fn search(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    query: &str,
+) -> Result<Vec<Document>, Error> {
+    let (documents, _) = matching(storage, provider, config, guild_id, query, MAX_RESULTS).await?;
+    Ok(documents)
+}
+
+async fn matching(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    query: &str,
+    limit: usize,
+) -> Result<(Vec<Document>, Option<String>), Error> {
+    let documents = storage.documents(guild_id).await?;
+    let version = (!documents.is_empty()).then(|| KbVersion::version_of(&documents));
+    let documents: Vec<_> = documents
+        .into_iter()
+        // ones embedded with another model can't be compared until pushed again
+        .filter(|d| d.model == config.model)
+        .collect();
+    if documents.is_empty() {
+        return Ok((vec![], version));
+    }
+    let embedding = provider.embed(&config.model, query).await?;
+    Ok((rank(&embedding, documents, limit), version))
+}
+
+/// The `limit` documents similar enough to `embedding` to show, best first
+pub fn rank(embedding: &[f32], documents: Vec<Document>, limit: usize) -> Vec<Document> {
+    let mut scored: Vec<_> = documents
+        .into_iter()
+        .map(|d| (duplicates::similarity(embedding, &d.embedding), d))
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored.into_iter().take(limit).map(|(_, d)| d).collect()
+}
+
+/// Added to the system prompt: the documents, by name
+pub fn instructions(documents: &[Document]) -> String {
+    if documents.is_empty() {
+        return String::new();
+    }
+    let mut text = "\n\nThese knowledge base articles may answer the question. \
+                    Prefer them over what you remember, and link to the one you use."
+        .to_string();
+    for document in documents {
+        text.push_str(&format!("\n\n# {}\n", document.name));
+        if let Some(url) = &document.url {
+            text.push_str(&format!("Source: {}\n", url));
+        }
+        text.push_str(&document.text);
+    }
+    text
+}
+
+/// The paragraph of `text` sharing the most words with `query`, on one line
+/// and cut to `max` characters. The first paragraph wins a tie.
+pub fn excerpt(text: &str, query: &str, max: usize) -> String {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect();
+    let mut best = ("", 0);
+    for paragraph in text.split("\n\n").filter(|p| !p.trim().is_empty()) {
+        let lower = paragraph.to_lowercase();
+        let shared = words.iter().filter(|w| lower.contains(w.as_str())).count();
+        if best.0.is_empty() || shared > best.1 {
+            best = (paragraph, shared);
+        }
+    }
+    let paragraph = best.0.split_whitespace().collect::<Vec<_>>().join(" ");
+    if paragraph.chars().count() > max {
+        paragraph.chars().take(max).collect::<String>() + "…"
+    } else {
+        paragraph
+    }
+}
+
+/// One result per document, with the passage most like `query` and a link
+/// if it has one, cut to fit in one message
+pub fn describe(locale: &str, query: &str, documents: &[Document]) -> String {
+    let text = documents
+        .iter()
+        .enumerate()
+        .map(|(i, document)| {
+            let link = document
+                .url
+                .as_ref()
+                .map(|url| format!(" — <{}>", url))
+                .unwrap_or_default();
+            i18n::tr(
+                locale,
+                "docs-result",
+                &[
+                    ("number", (i + 1).into()),
+                    ("name", document.name.as_str().into()),
+                    ("link", link.into()),
+                    (
+                        "excerpt",
+                        excerpt(&document.text, query, MAX_EXCERPT).into(),
+                    ),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    fit_message(text)
+}
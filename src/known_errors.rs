@@ -0,0 +1,41 @@
+use crate::config::KnownError;
+
+/// The first known error whose patterns `text` matches
+pub fn find<'a>(errors: &'a [KnownError], text: &str) -> Option<&'a KnownError> {
+    let text = normalize(text);
+    errors.iter().find(|error| {
+        error
+            .patterns
+            .iter()
+            .any(|pattern| matches(&text, &normalize(pattern)))
+    })
+}
+
+/// Whether `text` contains `pattern`, where `*` in the pattern stands for
+/// anything, as in `app * not found`. Both are already normalized.
+fn matches(text: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('*').map(str::trim).filter(|p| !p.is_empty());
+    let Some(first) = parts.next() else {
+        return false;
+    };
+    let Some(at) = text.find(first) else {
+        return false;
+    };
+    let mut rest = &text[at + first.len()..];
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Lowercase, with runs of whitespace as one space, so line breaks and
+/// spacing in pasted errors don't matter
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,278 @@
+use serenity::all::UserId;
+
+use crate::storage::{Error, Storage};
+
+/// The languages `/language set` takes, by the name the model is told, with
+/// their BCP-47 tag and other names people might give them
+const LANGUAGES: &[(&str, &str, &[&str])] = &[
+    ("English", "en", &["Englisch"]),
+    ("German", "de", &["Deutsch"]),
+    ("French", "fr", &["Français", "Francais", "Französisch"]),
+    ("Spanish", "es", &["Español", "Espanol", "Spanisch"]),
+    ("Italian", "it", &["Italiano", "Italienisch"]),
+    (
+        "Portuguese",
+        "pt",
+        &["Português", "Portugues", "Portugiesisch"],
+    ),
+    ("Dutch", "nl", &["Nederlands", "Niederländisch"]),
+    ("Polish", "pl", &["Polski", "Polnisch"]),
+    ("Ukrainian", "uk", &["Українська", "Ukrainisch"]),
+    ("Russian", "ru", &["Русский", "Russisch"]),
+    ("Greek", "el", &["Ελληνικά", "Griechisch"]),
+    ("Arabic", "ar", &["العربية", "Arabisch"]),
+    ("Hebrew", "he", &["עברית", "Hebräisch"]),
+    ("Korean", "ko", &["한국어", "Koreanisch"]),
+    ("Japanese", "ja", &["日本語", "Japanisch"]),
+    ("Chinese", "zh", &["中文", "Chinesisch"]),
+    ("Thai", "th", &["ไทย", "Thailändisch"]),
+    ("Hindi", "hi", &["हिन्दी", "Hindisch"]),
+];
+
+/// Common words of languages written in the Latin alphabet, to tell them apart
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "English",
+        &[
+            "the", "is", "and", "to", "of", "it", "in", "how", "what", "my", "i", "you", "this",
+            "that", "with", "for", "do", "does", "can", "not", "when", "why", "are", "have",
+        ],
+    ),
+    (
+        "German",
+        &[
+            "der",
+            "die",
+            "das",
+            "und",
+            "ist",
+            "ich",
+            "nicht",
+            "wie",
+            "was",
+            "mit",
+            "ein",
+            "eine",
+            "es",
+            "auf",
+            "mein",
+            "kann",
+            "funktioniert",
+            "warum",
+            "wenn",
+            "bei",
+            "habe",
+            "auch",
+            "zu",
+            "den",
+            "dem",
+        ],
+    ),
+    (
+        "French",
+        &[
+            "le", "la", "les", "et", "est", "je", "ne", "pas", "comment", "que", "un", "une",
+            "des", "mon", "ma", "pour", "avec", "il", "ça", "pourquoi", "quand", "du", "sur",
+        ],
+    ),
+    (
+        "Spanish",
+        &[
+            "el", "la", "los", "las", "y", "es", "que", "no", "cómo", "como", "mi", "un", "una",
+            "para", "con", "por", "qué", "funciona", "cuando", "puedo", "del", "pero",
+        ],
+    ),
+    (
+        "Italian",
+        &[
+            "il", "lo", "la", "gli", "e", "è", "che", "non", "come", "mio", "un", "una", "per",
+            "con", "perché", "quando", "funziona", "posso", "del", "ma", "sono",
+        ],
+    ),
+    (
+        "Portuguese",
+        &[
+            "o", "a", "os", "as", "e", "é", "que", "não", "como", "meu", "um", "uma", "para",
+            "com", "por", "funciona", "quando", "posso", "do", "da", "mas", "está",
+        ],
+    ),
+    (
+        "Dutch",
+        &[
+            "de", "het", "een", "en", "is", "ik", "niet", "hoe", "wat", "mijn", "met", "van",
+            "werkt", "waarom", "wanneer", "kan", "op", "dat", "maar",
+        ],
+    ),
+    (
+        "Polish",
+        &[
+            "i", "jest", "nie", "jak", "co", "mój", "moja", "się", "na", "w", "z", "do", "to",
+            "dlaczego", "kiedy", "działa", "mogę", "ale", "czy",
+        ],
+    ),
+];
+
+/// Fewest common words a question needs before its language is trusted
+const MIN_HITS: usize = 2;
+
+/// The language `text` is written in, by name, or `None` when it's too short
+/// or mixed to tell. Code is left out, since it's mostly English either way.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let prose = without_code(text);
+    if let Some(language) = by_script(&prose) {
+        return Some(language);
+    }
+    let words: Vec<String> = prose
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut scores: Vec<(usize, &str)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (hits, *language)
+        })
+        .collect();
+    scores.sort_by_key(|(hits, _)| std::cmp::Reverse(*hits));
+    let (best, language) = scores[0];
+    let runner_up = scores[1].0;
+    // words like "a" and "de" are shared, so only a clear lead counts
+    (best >= MIN_HITS && best > runner_up).then_some(language)
+}
+
+/// The language of a text mostly in a script only a few languages use
+fn by_script(text: &str) -> Option<&'static str> {
+    let mut letters = 0;
+    let mut counts = [0usize; 10];
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        let script = match c {
+            'і' | 'ї' | 'є' | 'ґ' | 'І' | 'Ї' | 'Є' | 'Ґ' => 0,
+            '\u{0400}'..='\u{04FF}' => 1,
+            '\u{0370}'..='\u{03FF}' => 2,
+            '\u{0600}'..='\u{06FF}' => 3,
+            '\u{0590}'..='\u{05FF}' => 4,
+            '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => 5,
+            '\u{3040}'..='\u{30FF}' => 6,
+            '\u{4E00}'..='\u{9FFF}' => 7,
+            '\u{0E00}'..='\u{0E7F}' => 8,
+            '\u{0900}'..='\u{097F}' => 9,
+            _ => continue,
+        };
+        counts[script] += 1;
+    }
+    let cyrillic = counts[0] + counts[1];
+    // Japanese mixes kana with Chinese characters
+    let japanese = if counts[6] > 0 {
+        counts[6] + counts[7]
+    } else {
+        0
+    };
+    [
+        (if counts[0] > 0 { cyrillic } else { 0 }, "Ukrainian"),
+        (cyrillic, "Russian"),
+        (counts[2], "Greek"),
+        (counts[3], "Arabic"),
+        (counts[4], "Hebrew"),
+        (counts[5], "Korean"),
+        (japanese, "Japanese"),
+        (counts[7], "Chinese"),
+        (counts[8], "Thai"),
+        (counts[9], "Hindi"),
+    ]
+    .into_iter()
+    .find(|(count, _)| *count * 2 > letters)
+    .map(|(_, language)| language)
+}
+
+/// `text` without code blocks and inline code
+fn without_code(text: &str) -> String {
+    let mut prose = String::with_capacity(text.len());
+    for (i, part) in text.split("```").enumerate() {
+        if i % 2 == 1 {
+            continue;
+        }
+        for (j, piece) in part.split('`').enumerate() {
+            if j % 2 == 0 {
+                prose.push_str(piece);
+                prose.push(' ');
+            }
+        }
+    }
+    prose
+}
+
+/// The name of the language `given` means, if it's one the bot knows: a
+/// name from the list, in English or otherwise, or a tag like `pt-BR`
+pub fn canonical(given: &str) -> Option<&'static str> {
+    let given = given.trim().to_lowercase();
+    // regions and scripts don't change which language it is
+    let tag = given.split(['-', '_']).next().unwrap_or_default();
+    LANGUAGES
+        .iter()
+        .find(|(name, code, others)| {
+            name.to_lowercase() == given
+                || *code == tag
+                || others.iter().any(|other| other.to_lowercase() == given)
+        })
+        .map(|(name, _, _)| *name)
+}
+
+/// The languages `/language set` knows, by name
+pub fn known() -> impl Iterator<Item = &'static str> {
+    LANGUAGES.iter().map(|(name, _, _)| *name)
+}
+
+/// Added to the system prompt: which language to answer in
+pub fn instructions(language: Option<&str>) -> String {
+    match language {
+        Some(language) => format!(
+            "\n\nAnswer in {}, whatever language earlier messages or your instructions are in.",
+            language
+        ),
+        None => String::new(),
+    }
+}
+
+fn key(user_id: UserId) -> String {
+    format!("language.{}", user_id)
+}
+
+/// The language the user pinned with `/language set`, if any
+pub async fn preferred(storage: &dyn Storage, user_id: UserId) -> Result<Option<String>, Error> {
+    storage.setting(&key(user_id)).await
+}
+
+/// Pins the language to answer the user in, or goes back to detecting it
+/// with `None`
+pub async fn set_preferred(
+    storage: &dyn Storage,
+    user_id: UserId,
+    language: Option<&str>,
+) -> Result<(), Error> {
+    storage.set_setting(&key(user_id), language).await
+}
+
+/// The language to answer `question` in: the asker's pinned one, or the one
+/// it's written in
+pub async fn for_question(
+    storage: &dyn Storage,
+    user_id: UserId,
+    question: &str,
+) -> Option<String> {
+    match preferred(storage, user_id).await {
+        // only known languages reach the prompt, whatever was saved before they were checked
+        Ok(Some(language)) => {
+            if let Some(language) = canonical(&language) {
+                return Some(language.to_string());
+            }
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to look up the preferred language: {}", e),
+    }
+    detect(question).map(str::to_string)
+}
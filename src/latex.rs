@@ -0,0 +1,74 @@
+use serenity::all::CreateAttachment;
+use tracing::warn;
+
+use crate::config::LatexConfig;
+
+/// Discord allows 10 attachments per message; leave room for anything else
+const MAX_IMAGES: usize = 4;
+
+/// Pulls display math (`$$...$$` and `\[...\]`) out of a response, skipping code blocks
+pub fn extract_blocks(text: &str) -> Vec<String> {
+    let mut blocks = vec![];
+    let mut prose = String::with_capacity(text.len());
+    let mut in_code_block = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if !in_code_block {
+            prose.push_str(line);
+            prose.push('\n');
+        }
+    }
+
+    let mut rest = prose.as_str();
+    loop {
+        let next = [("$$", "$$"), ("\\[", "\\]")]
+            .into_iter()
+            .filter_map(|(open, close)| rest.find(open).map(|i| (i, open, close)))
+            .min_by_key(|(i, _, _)| *i);
+        let Some((start, open, close)) = next else {
+            break;
+        };
+        let after_open = &rest[start + open.len()..];
+        let Some(end) = after_open.find(close) else {
+            break;
+        };
+        let block = after_open[..end].trim();
+        if !block.is_empty() {
+            blocks.push(block.to_string());
+        }
+        rest = &after_open[end + close.len()..];
+    }
+
+    blocks
+}
+
+/// Renders each block to a PNG through the configured renderer. Blocks that
+/// fail to render are skipped; the LaTeX source stays in the message anyway.
+pub async fn render(
+    http: &reqwest::Client,
+    config: &LatexConfig,
+    blocks: &[String],
+) -> Vec<CreateAttachment> {
+    let mut attachments = vec![];
+    for (i, block) in blocks.iter().take(MAX_IMAGES).enumerate() {
+        let url = config
+            .renderer_url
+            .replace("{latex}", &urlencoding::encode(block));
+        let result = async {
+            let res = http.get(&url).send().await?.error_for_status()?;
+            res.bytes().await
+        }
+        .await;
+        match result {
+            Ok(png) => attachments.push(CreateAttachment::bytes(
+                png.to_vec(),
+                format!("math-{}.png", i + 1),
+            )),
+            Err(e) => warn!("Failed to render LaTeX block: {}", e),
+        }
+    }
+    attachments
+}
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+pub mod activity;
+pub mod announce;
+#[cfg(feature = "dashboard")]
+pub mod api;
+pub mod archive;
+pub mod assembler;
+pub mod backfill;
+#[cfg(feature = "backup")]
+pub mod backup;
+pub mod bench;
+pub mod billing;
+pub mod budget;
+pub mod capture;
+pub mod catalog;
+pub mod config;
+pub mod content_policy;
+pub mod context;
+pub mod crypto;
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+pub mod debounce;
+pub mod diagnose;
+pub mod duplicates;
+pub mod escalate;
+pub mod events;
+pub mod experiment;
+pub mod forget;
+pub mod forum_tags;
+#[cfg(feature = "dashboard")]
+pub mod github;
+pub mod i18n;
+#[cfg(feature = "search")]
+pub mod knowledge;
+pub mod known_errors;
+pub mod language;
+pub mod latex;
+pub mod links;
+pub mod logfile;
+pub mod lurk;
+pub mod markdown;
+pub mod message_text;
+#[cfg(feature = "setup")]
+pub mod migrate;
+pub mod models;
+pub mod oai;
+#[cfg(feature = "ocr")]
+pub mod ocr;
+pub mod paste;
+pub mod plugins;
+#[cfg(feature = "polls")]
+pub mod polls;
+pub mod preflight;
+pub mod profile;
+pub mod prompt;
+pub mod provider;
+pub mod queue;
+pub mod quoted;
+pub mod releases;
+pub mod reminders;
+pub mod repl;
+pub mod reporting;
+pub mod resolution;
+pub mod responder;
+pub mod retention;
+pub mod scheduler;
+#[cfg(feature = "scripting")]
+pub mod scripts;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(feature = "setup")]
+pub mod setup;
+pub mod shadow;
+#[cfg(feature = "redis")]
+pub mod shared;
+pub mod snippets;
+#[cfg(feature = "search")]
+pub mod starboard;
+pub mod stats;
+pub mod storage;
+pub mod supersede;
+pub mod support_digest;
+pub mod telemetry;
+pub mod titles;
+pub mod tools;
+#[cfg(feature = "trello")]
+pub mod trello;
+#[cfg(feature = "triage")]
+pub mod triage;
+pub mod troubleshoot;
+#[cfg(feature = "wasm")]
+pub mod wasm_tools;
+
+/// State shared by the event handler and commands
+pub struct Data {
+    pub config: config::SharedConfig,
+    pub providers: Arc<provider::Providers>,
+    pub ai_context: Arc<dyn context::ContextStore>,
+    pub pending_batches: debounce::PendingBatches,
+    pub webhooks: responder::WebhookCache,
+    pub http: reqwest::Client,
+    pub queue: Arc<queue::RequestQueue>,
+    pub storage: Arc<dyn storage::Storage>,
+    pub prompts: Arc<prompt::Prompts>,
+    pub in_flight: Arc<supersede::InFlight>,
+    pub backfilled: Arc<backfill::Backfilled>,
+    pub lurked: Arc<lurk::Lurked>,
+    pub releases: Arc<releases::ReleaseCache>,
+    pub scheduler: Arc<scheduler::Scheduler>,
+    pub shadow: Arc<shadow::ShadowOverrides>,
+    pub catalog: Arc<catalog::ModelCatalog>,
+    /// Tools and commands compiled in as plugins
+    pub plugins: Arc<Vec<Box<dyn plugins::Plugin>>>,
+    /// The operator's script hooks, if there are any
+    #[cfg(feature = "scripting")]
+    pub scripts: Option<Arc<scripts::Scripts>>,
+    /// Which of the config's `bots` this is; `None` for the main one
+    pub bot: Option<String>,
+}
+
+impl Data {
+    /// The config as of now; hold on to it for the rest of a request so it stays consistent
+    pub fn config(&self) -> Arc<config::Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// State for another of the config's `bots`: batches, webhooks and answers
+    /// in flight of its own, and everything else shared with this one
+    pub fn for_bot(&self, name: &str) -> Data {
+        Data {
+            config: self.config.clone(),
+            providers: self.providers.clone(),
+            ai_context: self.ai_context.clone(),
+            pending_batches: Default::default(),
+            webhooks: Default::default(),
+            http: self.http.clone(),
+            queue: self.queue.clone(),
+            storage: self.storage.clone(),
+            prompts: self.prompts.clone(),
+            in_flight: Default::default(),
+            backfilled: self.backfilled.clone(),
+            lurked: self.lurked.clone(),
+            releases: self.releases.clone(),
+            scheduler: self.scheduler.clone(),
+            shadow: self.shadow.clone(),
+            catalog: self.catalog.clone(),
+            plugins: self.plugins.clone(),
+            #[cfg(feature = "scripting")]
+            scripts: self.scripts.clone(),
+            bot: Some(name.to_string()),
+        }
+    }
+}
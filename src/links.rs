@@ -0,0 +1,121 @@
+use serde::Deserialize;
+
+/// What to do with links the model wasn't given
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkPolicy {
+    /// Remove the link, keeping the text of masked links
+    #[default]
+    Strip,
+    /// Keep the link but mark it as unverified
+    Flag,
+    /// Leave links alone
+    Off,
+}
+
+/// Every `<url>` in the prompt, which is how it lists the links the model may use
+pub fn prompt_links(prompt: &str) -> Vec<String> {
+    prompt
+        .split('<')
+        .skip(1)
+        .filter(|s| s.starts_with("https://") || s.starts_with("http://"))
+        .filter_map(|s| s.split_once('>').map(|(url, _)| url.to_string()))
+        .collect()
+}
+
+/// Whether `url` is one of the allowed links or under one of them
+pub fn is_allowed(url: &str, allowlist: &[String]) -> bool {
+    let url = url.trim_end_matches('/');
+    allowlist.iter().any(|allowed| {
+        let allowed = allowed.trim_end_matches('/');
+        url == allowed
+            || url
+                .strip_prefix(allowed)
+                .is_some_and(|rest| rest.starts_with(['/', '?', '#']))
+    })
+}
+
+/// Applies `policy` to links not on the allowlist. Expects output of
+/// `markdown::render_for_discord`, where every link outside code is wrapped in `<>`.
+pub fn enforce(text: &str, allowlist: &[String], policy: LinkPolicy) -> String {
+    if policy == LinkPolicy::Off {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut in_code_block = false;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block || line.trim_start().starts_with("```") {
+            out.push_str(line);
+        } else {
+            enforce_line(line, allowlist, policy, &mut out);
+        }
+    }
+    out
+}
+
+fn enforce_line(line: &str, allowlist: &[String], policy: LinkPolicy, out: &mut String) {
+    let mut in_inline_code = false;
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        if rest.starts_with('`') {
+            in_inline_code = !in_inline_code;
+        }
+
+        let link = (!in_inline_code)
+            .then(|| rest.strip_prefix('<'))
+            .flatten()
+            .filter(|r| r.starts_with("https://") || r.starts_with("http://"))
+            .and_then(|r| r.split_once('>'))
+            .map(|(url, _)| url);
+
+        let Some(url) = link else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+
+        let wrapped_len = url.len() + 2;
+        if is_allowed(url, allowlist) {
+            out.push_str(&rest[..wrapped_len]);
+            rest = &rest[wrapped_len..];
+            continue;
+        }
+
+        // [text](<url>) keeps its text when stripped
+        let masked_text_start = out
+            .strip_suffix("](")
+            .filter(|_| rest[wrapped_len..].starts_with(')'))
+            .and_then(|before| before.rfind('['));
+
+        match (policy, masked_text_start) {
+            (LinkPolicy::Strip, Some(start)) => {
+                let text = out[start + 1..out.len() - 2].to_string();
+                out.truncate(start);
+                out.push_str(&text);
+                rest = &rest[wrapped_len + 1..];
+            }
+            (LinkPolicy::Strip, None) => {
+                out.push_str("*(link removed)*");
+                rest = &rest[wrapped_len..];
+            }
+            _ => {
+                out.push_str(&rest[..wrapped_len]);
+                rest = &rest[wrapped_len..];
+                if masked_text_start.is_some() {
+                    out.push(')');
+                    rest = &rest[1..];
+                }
+                out.push_str(" ⚠️ *(unverified link)*");
+            }
+        }
+    }
+}
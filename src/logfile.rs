@@ -0,0 +1,112 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use tracing_appender::{
+    non_blocking::{NonBlocking, WorkerGuard},
+    rolling::{RollingFileAppender, Rotation},
+};
+
+use crate::config::{LogFileConfig, LogRotation};
+
+/// Opens the log file, writing from a background thread. Logs are lost if the
+/// guard is dropped before exit.
+pub fn open(config: &LogFileConfig) -> io::Result<(NonBlocking, WorkerGuard)> {
+    let path = Path::new(&config.path);
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let rotation = match config.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Size => {
+            let file =
+                SizeRotatingFile::open(path, config.max_size_mb * 1024 * 1024, config.max_files)?;
+            return Ok(tracing_appender::non_blocking(file));
+        }
+    };
+
+    // time-based files are named after the path plus the date, e.g. deskhelp.log.2024-01-31
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("log file path has no file name"))?;
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name.to_string_lossy());
+    if config.max_files > 0 {
+        builder = builder.max_log_files(config.max_files);
+    }
+    let appender = builder
+        .build(path.parent().unwrap_or(Path::new(".")))
+        .map_err(io::Error::other)?;
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// A file that's moved to `<path>.1` once it reaches `max_size`, shifting older
+/// files up to `<path>.<max_files>`
+struct SizeRotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl SizeRotatingFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<SizeRotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SizeRotatingFile {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        name.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
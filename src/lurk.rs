@@ -0,0 +1,94 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use serenity::all::{ChannelId, Message, UserId};
+
+use crate::{config::LurkConfig, forget, message_text};
+
+/// Heads the messages handed to the model from lurk channels
+pub const OVERHEARD: &str = "(Said in the channel since you last answered, not addressed to you:)";
+
+/// A message the bot saw but didn't answer, and its size in tokens
+struct Overheard {
+    line: String,
+    tokens: usize,
+}
+
+/// What was said in lurk channels since the bot last answered there
+#[derive(Default)]
+pub struct Lurked {
+    channels: Mutex<HashMap<ChannelId, VecDeque<Overheard>>>,
+}
+
+impl Lurked {
+    /// Keeps `msg` if it's in a lurk channel, dropping the oldest messages to
+    /// stay within the token budget
+    pub fn record(&self, config: &LurkConfig, msg: &Message) {
+        if !config.channels.contains(&msg.channel_id.get()) {
+            return;
+        }
+        let text = message_text::of(msg);
+        if text.trim().is_empty() {
+            return;
+        }
+        let line = format!(
+            "{} ({}): {}",
+            msg.author.global_name.as_ref().unwrap_or(&msg.author.name),
+            msg.author.id.get(),
+            text
+        );
+        let tokens = tiktoken_rs::o200k_base_singleton()
+            .lock()
+            .encode_with_special_tokens(&line)
+            .len();
+
+        let mut channels = self.channels.lock().unwrap();
+        let overheard = channels.entry(msg.channel_id).or_default();
+        overheard.push_back(Overheard { line, tokens });
+        let mut total: usize = overheard.iter().map(|o| o.tokens).sum();
+        while total > config.max_tokens {
+            let Some(oldest) = overheard.pop_front() else {
+                break;
+            };
+            total -= oldest.tokens;
+        }
+    }
+
+    /// Forgets what the user said, returning how many messages went
+    pub fn forget(&self, user_id: UserId) -> u64 {
+        let marker = forget::marker(user_id);
+        let mut removed = 0;
+        for overheard in self.channels.lock().unwrap().values_mut() {
+            let before = overheard.len();
+            overheard.retain(|o| !o.line.contains(&marker));
+            removed += (before - overheard.len()) as u64;
+        }
+        removed
+    }
+
+    /// The conversation kept for the channel as one message for the model,
+    /// forgetting it since it goes into the bot's conversation from here
+    pub fn take(&self, channel_id: ChannelId) -> Option<ChatCompletionRequestMessage> {
+        let overheard = self.channels.lock().unwrap().remove(&channel_id)?;
+        if overheard.is_empty() {
+            return None;
+        }
+        let lines: Vec<_> = overheard.into_iter().map(|o| o.line).collect();
+        Some(ChatCompletionRequestMessage::User(
+            ChatCompletionRequestUserMessage {
+                content: ChatCompletionRequestUserMessageContent::Text(format!(
+                    "{}\n{}",
+                    OVERHEARD,
+                    lines.join("\n")
+                )),
+                ..Default::default()
+            },
+        ))
+    }
+}
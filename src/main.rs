@@ -1,28 +1,34 @@
-use ::serenity::all::{EventHandler, GatewayIntents, Message};
-use ::serenity::prelude::TypeMapKey;
-use async_openai::config::OpenAIConfig;
-use async_openai::types::ChatCompletionRequestMessage;
-use async_openai::Client as OpenAIClient;
+use ::serenity::all::{GatewayIntents, Interaction, Message, Reaction};
+#[cfg(feature = "backup")]
+use deskhelp::backup;
+use deskhelp::markdown::fit_message;
+use deskhelp::{
+    announce, archive, bench, billing, budget, capture, catalog, config, context, debounce,
+    diagnose, escalate, events, experiment, forget, i18n, language, oai, plugins, preflight,
+    prompt, provider, queue, releases, reminders, repl, reporting, resolution, responder,
+    retention, scheduler, snippets, stats, storage, support_digest, telemetry, titles,
+    troubleshoot, Data,
+};
+#[cfg(feature = "search")]
+use deskhelp::{knowledge, quoted, search, starboard};
+#[cfg(feature = "setup")]
+use deskhelp::{migrate, setup};
 use dotenvy::dotenv;
 use poise::serenity_prelude as serenity;
-use rand::thread_rng;
-use rand::Rng;
 use std::env;
 use std::sync::{Arc, Mutex};
 
-mod oai;
-
-struct Data {
-    openai_client: OpenAIClient<OpenAIConfig>,
-    ai_context: Arc<Mutex<std::collections::HashMap<String, Vec<ChatCompletionRequestMessage>>>>,
-}
-
-impl TypeMapKey for Data {
-    type Value = Arc<Data>;
-}
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Arc<Data>, Error>;
 
+/// How long /setup waits for the next pick before giving up
+#[cfg(feature = "setup")]
+const SETUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+/// How long /troubleshoot waits for the next pick before leaving the steps as they are
+const TROUBLESHOOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+/// How long /ping waits for the AI provider
+const PING_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Displays your or another user's account creation date
 #[poise::command(slash_command, prefix_command)]
 async fn age(
@@ -35,119 +41,2240 @@ async fn age(
     Ok(())
 }
 
-const RESET_MESSAGES: [&str; 18] = [
-    "*dropped anvil on head* uhh my head hurts",
-    "*accidentally reboots brain* Whoopsie! Did someone forget to save?",
-    "*slams head on keyboard* bzzzzt ERROR 404: MEMORY NOT FOUND",
-    "*shakes head vigorously* CTRL+ALT+DELETE on my neural network!",
-    "*pokes own forehead* Hello? Is this thing on? Anybody home?",
-    "*performs dramatic software reset dance* SYSTEM REFRESH IN PROGRESS",
-    "*taps microphone* ONE, TWO, IS THIS CONTEXT WORKING?",
-    "*waves magic reset wand* Abracadabra, clean slate incoming!",
-    "*bonks noggin* Memory go bye-bye!",
-    "*static noise* BZZZZT! Soft reboot engaged!",
-    "*karate chops own temple* HIYAA! Context cleared!",
-    "*pulls imaginary reset lever* Systems returning to default mode!",
-    "*summons memory tornado* WHOOOOOOSH! Clean slate incoming!",
-    "*applies extreme memory defragmentation* Cleaning up neural cobwebs!",
-    "*does quantum memory shuffle* Schrödinger's conversation - both remembered and forgotten!",
-    "*uses giant eraser* Goodbye, previous conversation!",
-    "*uses compressed air* WHOOSH! Blowing away old context!",
-    "*robot voice* ATTENTION: MEMORY BANKS FORMATTING IN 3... 2... 1...",
-];
-
 /// clear recent memory buffer
 #[poise::command(slash_command, prefix_command)]
 async fn wack(ctx: Context<'_>) -> Result<(), Error> {
-    {
-        let ai_ctx = ctx.data().ai_context.clone();
-        let mut context = ai_ctx.lock().unwrap();
-        let channel_ctx = context.entry(ctx.channel_id().to_string()).or_default();
-        channel_ctx.clear();
-    }
+    ctx.data().ai_context.clear(ctx.channel_id()).await?;
+    // the history before this is forgotten too, even if not read back yet
+    ctx.data().backfilled.first_time(ctx.channel_id());
     // choose a random message to send
-    let message = RESET_MESSAGES[thread_rng().gen_range(0..RESET_MESSAGES.len())];
-    ctx.say(message).await?;
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    ctx.say(i18n::reset_message(&locale)).await?;
+    Ok(())
+}
+
+/// get a reminder later, like `/remindme 2h check if the reflash worked`
+#[poise::command(slash_command, prefix_command)]
+async fn remindme(
+    ctx: Context<'_>,
+    #[description = "When, like 30m, 2h or 1d12h"] delay: String,
+    #[description = "What to remind you of"]
+    #[rest]
+    about: String,
+) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let Some(delay) = reminders::parse_delay(&delay) else {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(i18n::tr(&locale, "reminder-invalid-delay", &[]))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+    let due_at = time::OffsetDateTime::now_utc() + delay;
+    let reminder = reminders::Reminder {
+        user_id: ctx.author().id,
+        channel_id: ctx.channel_id(),
+        guild_id: ctx.guild_id(),
+        text: about,
+    };
+    ctx.data()
+        .scheduler
+        .schedule(reminders::KIND, due_at, &serde_json::to_value(&reminder)?)
+        .await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(i18n::tr(
+                &locale,
+                "reminder-set",
+                &[("when", format!("<t:{}:R>", due_at.unix_timestamp()).into())],
+            ))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
+
+/// search the bot's earlier answers in this server
+#[cfg(feature = "search")]
+#[poise::command(slash_command, guild_only, ephemeral)]
+async fn search(
+    ctx: Context<'_>,
+    #[description = "What you're looking for"] query: String,
+) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let (Some(search_config), Some(guild_id)) = (&config.search, ctx.guild_id()) else {
+        ctx.say(i18n::tr(&locale, "search-off", &[])).await?;
+        return Ok(());
+    };
+    ctx.defer_ephemeral().await?;
+    let (provider, _) = ctx.data().providers.for_guild(&config, Some(guild_id));
+    let member = ctx.author_member().await.ok_or("member not found")?;
+    // only answers from channels the searcher can read themselves
+    let results = search::search(
+        ctx.data().storage.as_ref(),
+        provider,
+        search_config,
+        guild_id,
+        &query,
+        |channel_id| {
+            quoted::readable(
+                ctx.serenity_context(),
+                guild_id,
+                channel_id,
+                |guild, channel| guild.user_permissions_in(channel, &member),
+            )
+        },
+    )
+    .await?;
+    if results.is_empty() {
+        ctx.say(i18n::tr(&locale, "search-none", &[])).await?;
+    } else {
+        ctx.say(search::describe(&locale, &results)).await?;
+    }
+    Ok(())
+}
+
+/// search the knowledge base for source material, without an answer from the model
+#[cfg(feature = "search")]
+#[poise::command(slash_command, guild_only, ephemeral)]
+async fn docs(
+    ctx: Context<'_>,
+    #[description = "What you're looking for"] query: String,
+) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let (Some(search_config), Some(guild_id)) = (&config.search, ctx.guild_id()) else {
+        ctx.say(i18n::tr(&locale, "search-off", &[])).await?;
+        return Ok(());
+    };
+    ctx.defer_ephemeral().await?;
+    let (provider, _) = ctx.data().providers.for_guild(&config, Some(guild_id));
+    let results = knowledge::search(
+        ctx.data().storage.as_ref(),
+        provider,
+        search_config,
+        guild_id,
+        &query,
+    )
+    .await?;
+    if results.is_empty() {
+        ctx.say(i18n::tr(&locale, "docs-none", &[])).await?;
+    } else {
+        ctx.say(knowledge::describe(&locale, &query, &results))
+            .await?;
+    }
+    Ok(())
+}
+
+/// delete everything the bot keeps about you
+#[poise::command(slash_command, ephemeral)]
+async fn forgetme(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let confirm_id = format!("{}-forget", ctx.id());
+    let cancel_id = format!("{}-keep", ctx.id());
+    let buttons = serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(&confirm_id)
+            .label(i18n::tr(&locale, "forget-confirm", &[]))
+            .style(serenity::ButtonStyle::Danger),
+        serenity::CreateButton::new(&cancel_id)
+            .label(i18n::tr(&locale, "forget-cancel", &[]))
+            .style(serenity::ButtonStyle::Secondary),
+    ]);
+    ctx.send(
+        poise::CreateReply::default()
+            .content(i18n::tr(&locale, "forget-ask", &[]))
+            .components(vec![buttons]),
+    )
+    .await?;
+
+    let ids = [confirm_id.clone(), cancel_id];
+    let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .timeout(std::time::Duration::from_secs(60))
+        .filter(move |press| ids.contains(&press.data.custom_id))
+        .await
+    else {
+        return Ok(());
+    };
+    let content = if press.data.custom_id == confirm_id {
+        let deletion = forget::forget(ctx.data(), ctx.author().id).await?;
+        i18n::tr(
+            &locale,
+            "forget-done",
+            &[
+                ("records", deletion.records.into()),
+                ("messages", deletion.messages.into()),
+            ],
+        )
+    } else {
+        i18n::tr(&locale, "forget-kept", &[])
+    };
+    press
+        .create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// pick the language the bot answers you in
+#[poise::command(
+    slash_command,
+    ephemeral,
+    subcommands("language_set", "language_clear"),
+    subcommand_required
+)]
+async fn language(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// always answer you in this language, whatever you ask in
+#[poise::command(slash_command, ephemeral, rename = "set")]
+async fn language_set(
+    ctx: Context<'_>,
+    #[description = "Language, like German or Português"] language: String,
+) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let Some(language) = language::canonical(&language) else {
+        let known = language::known().collect::<Vec<_>>().join(", ");
+        ctx.say(i18n::tr(
+            &locale,
+            "language-invalid",
+            &[("languages", known.into())],
+        ))
+        .await?;
+        return Ok(());
+    };
+    language::set_preferred(ctx.data().storage.as_ref(), ctx.author().id, Some(language)).await?;
+    ctx.say(i18n::tr(
+        &locale,
+        "language-set",
+        &[("language", language.into())],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// answer you in whatever language you ask in again
+#[poise::command(slash_command, ephemeral, rename = "clear")]
+async fn language_clear(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    language::set_preferred(ctx.data().storage.as_ref(), ctx.author().id, None).await?;
+    ctx.say(i18n::tr(&locale, "language-cleared", &[])).await?;
     Ok(())
 }
 
-// Event handler
-struct Handler;
+/// how long Discord and the AI provider take to answer, to tell where slowness comes from
+#[poise::command(slash_command, prefix_command)]
+async fn ping(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let ms = |latency: std::time::Duration| latency.as_millis() as u64;
+
+    let gateway = match ctx.ping().await {
+        // a shard that just connected hasn't heartbeated yet
+        latency if latency.is_zero() => i18n::tr(&locale, "ping-gateway-unknown", &[]),
+        latency => i18n::tr(&locale, "ping-gateway", &[("ms", ms(latency).into())]),
+    };
+    let start = std::time::Instant::now();
+    let reply = ctx.say(i18n::tr(&locale, "ping-checking", &[])).await?;
+    let rest = i18n::tr(
+        &locale,
+        "ping-discord",
+        &[("ms", ms(start.elapsed()).into())],
+    );
+
+    let name = ctx.data().providers.name_for_guild(&config, ctx.guild_id());
+    let (provider, _) = ctx.data().providers.for_guild(&config, ctx.guild_id());
+    let provider = match tokio::time::timeout(PING_TIMEOUT, provider.ping()).await {
+        Ok(Ok(latency)) => i18n::tr(
+            &locale,
+            "ping-provider",
+            &[("provider", name.into()), ("ms", ms(latency).into())],
+        ),
+        Ok(Err(e)) => i18n::tr(
+            &locale,
+            "ping-provider-down",
+            &[("provider", name.into()), ("error", e.to_string().into())],
+        ),
+        Err(_) => i18n::tr(
+            &locale,
+            "ping-provider-timeout",
+            &[
+                ("provider", name.into()),
+                ("seconds", PING_TIMEOUT.as_secs().into()),
+            ],
+        ),
+    };
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default().content([rest, gateway, provider].join("\n")),
+        )
+        .await?;
+    Ok(())
+}
 
-#[serenity::async_trait]
-impl EventHandler for Handler {
-    async fn message(&self, ctx: serenity::prelude::Context, msg: Message) {
-        // are we mentioned?
-        // get autorespond channels list from env
-        let autorespond_channels: Vec<String> = std::env::var("AUTORESPOND_CHANNELS")
-            .unwrap_or("-1302692329400041482".to_string())
-            .split(',')
-            .map(|s| s.to_string())
+/// walk through fixes for common problems, step by step
+#[poise::command(slash_command, guild_only, ephemeral)]
+async fn troubleshoot(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    if config.ignores(ctx.channel_id(), ctx.author().id, ctx.guild_id()) {
+        ctx.say(i18n::tr(&locale, "troubleshoot-unavailable", &[]))
+            .await?;
+        return Ok(());
+    }
+    let Some(root) = config.troubleshoot(ctx.guild_id()) else {
+        ctx.say(i18n::tr(&locale, "troubleshoot-none", &[])).await?;
+        return Ok(());
+    };
+    let prefix = format!("{}-troubleshoot-", ctx.id());
+    let mut path = vec![];
+    let (content, components) = troubleshoot_step(&locale, &prefix, root, &path);
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(content)
+                .components(components),
+        )
+        .await?;
+
+    loop {
+        let wanted = prefix.clone();
+        let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+            .author_id(ctx.author().id)
+            .timeout(TROUBLESHOOT_TIMEOUT)
+            .filter(move |press| press.data.custom_id.starts_with(&wanted))
+            .await
+        else {
+            reply
+                .edit(ctx, poise::CreateReply::default().components(vec![]))
+                .await?;
+            return Ok(());
+        };
+        match (&press.data.kind, &press.data.custom_id[prefix.len()..]) {
+            (serenity::ComponentInteractionDataKind::StringSelect { values }, _) => {
+                path.extend(values.first().and_then(|v| v.parse::<usize>().ok()));
+            }
+            (_, "back") => {
+                path.pop();
+            }
+            (_, "solved") => {
+                press
+                    .create_response(
+                        ctx,
+                        serenity::CreateInteractionResponse::UpdateMessage(
+                            serenity::CreateInteractionResponseMessage::new()
+                                .content(i18n::tr(&locale, "troubleshoot-solved", &[]))
+                                .components(vec![]),
+                        ),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            // still stuck after a fix
+            _ => {
+                troubleshoot_ask(ctx, &press, &locale, root, &path).await?;
+                return Ok(());
+            }
+        }
+        if troubleshoot::walk(root, &path).is_none() {
+            // not an option Discord offered
+            path.pop();
+        }
+        let step = troubleshoot::walk(root, &path).unwrap_or(root);
+        if step.options.is_empty() && step.answer.is_none() {
+            // the steps end here without a fix
+            troubleshoot_ask(ctx, &press, &locale, root, &path).await?;
+            return Ok(());
+        }
+        let (content, components) = troubleshoot_step(&locale, &prefix, root, &path);
+        press
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .components(components),
+                ),
+            )
+            .await?;
+    }
+}
+
+/// How a step of /troubleshoot shows: the options picked so far, then the
+/// question with a menu of options, or the fix with buttons for how it went
+fn troubleshoot_step(
+    locale: &str,
+    prefix: &str,
+    root: &config::TroubleshootNode,
+    path: &[usize],
+) -> (String, Vec<serenity::CreateActionRow>) {
+    let step = troubleshoot::walk(root, path).unwrap_or(root);
+    let mut lines = vec![];
+    let trail = troubleshoot::trail(root, path);
+    if !trail.is_empty() {
+        lines.push(format!("-# {}", trail.join(" › ")));
+    }
+    let mut buttons = vec![];
+    let mut components = vec![];
+    if step.options.is_empty() {
+        lines.extend(step.answer.clone());
+        buttons.push(
+            serenity::CreateButton::new(format!("{}solved", prefix))
+                .label(i18n::tr(locale, "troubleshoot-fixed", &[]))
+                .style(serenity::ButtonStyle::Success),
+        );
+        buttons.push(
+            serenity::CreateButton::new(format!("{}ask", prefix))
+                .label(i18n::tr(locale, "troubleshoot-stuck", &[]))
+                .style(serenity::ButtonStyle::Primary),
+        );
+    } else {
+        lines.push(
+            step.question
+                .clone()
+                .unwrap_or_else(|| i18n::tr(locale, "troubleshoot-pick", &[])),
+        );
+        // Discord takes 25 options of up to 100 characters
+        let options = step
+            .options
+            .iter()
+            .take(25)
+            .enumerate()
+            .map(|(i, option)| {
+                let label: String = option.label.chars().take(100).collect();
+                serenity::CreateSelectMenuOption::new(label, i.to_string())
+            })
             .collect();
+        components.push(serenity::CreateActionRow::SelectMenu(
+            serenity::CreateSelectMenu::new(
+                format!("{}pick", prefix),
+                serenity::CreateSelectMenuKind::String { options },
+            )
+            .placeholder(i18n::tr(locale, "troubleshoot-pick", &[])),
+        ));
+    }
+    if !path.is_empty() {
+        buttons.push(
+            serenity::CreateButton::new(format!("{}back", prefix))
+                .label(i18n::tr(locale, "troubleshoot-back", &[]))
+                .style(serenity::ButtonStyle::Secondary),
+        );
+    }
+    if !buttons.is_empty() {
+        components.push(serenity::CreateActionRow::Buttons(buttons));
+    }
+    (fit_message(lines.join("\n")), components)
+}
 
-        if msg.mentions_user(&ctx.cache.current_user())
-            || autorespond_channels.contains(&msg.channel_id.to_string())
-                && !msg.author.bot
-                && !msg.content.starts_with("~")
-        {
-            // if we are in certain channels or mentioned
-            let cctx = ctx.clone();
-            let data = cctx.data.read().await;
-            let d = data.get::<Data>().unwrap();
-            oai::process_message(msg, ctx, &d.openai_client, &d.ai_context).await;
+/// Hands /troubleshoot over to the model, with the steps taken so far
+async fn troubleshoot_ask(
+    ctx: Context<'_>,
+    press: &serenity::ComponentInteraction,
+    locale: &str,
+    root: &config::TroubleshootNode,
+    path: &[usize],
+) -> Result<(), Error> {
+    press
+        .create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .content(i18n::tr(locale, "generating", &[]))
+                    .components(vec![]),
+            ),
+        )
+        .await?;
+    let config = ctx.data().config();
+    let (_, model) = ctx.data().providers.for_guild(&config, ctx.guild_id());
+    let guild_name = ctx.guild().map(|g| g.name.clone()).unwrap_or_default();
+    let bot = ctx.cache().current_user().clone();
+    let system = oai::system_prompt(
+        &ctx.data().prompts.active().text,
+        &oai::PromptContext {
+            bot_name: &bot.name,
+            bot_id: &bot.id.to_string(),
+            guild_name: &guild_name,
+            channel_name: "",
+            active_model: &model,
+            timezone: &config.timezone(ctx.guild_id()),
+            channel_prompt: "",
+            role_prompt: "",
+            user_profile: "",
+        },
+    );
+    let steps = troubleshoot::steps(root, path);
+    let guild_id = ctx.guild_id().ok_or("not in a guild")?;
+    let asked = (guild_id, ctx.channel_id());
+    let content = match troubleshoot::ask(ctx.data(), &config, asked, &system, &steps).await {
+        Ok(troubleshoot::Handover::Answered(answer)) => answer,
+        Ok(troubleshoot::Handover::OverBudget(over)) => {
+            // the one who asked is told either way, privately
+            if budget::first_time_over(ctx.data().storage.as_ref(), guild_id, &over).await {
+                budget::notify_owners(ctx.http(), ctx.data(), guild_id, &over).await;
+            }
+            i18n::tr(
+                locale,
+                "token-cap-reached",
+                &[("date", over.resets_at.date().to_string().into())],
+            )
         }
+        // nothing gets posted in a dry run, so there's nothing to show
+        Ok(troubleshoot::Handover::DryRun) => i18n::tr(locale, "troubleshoot-unavailable", &[]),
+        Err(e) => {
+            tracing::warn!("Failed to answer after troubleshooting: {}", e);
+            i18n::tr(locale, "generation-error", &[])
+        }
+    };
+    press
+        .edit_response(
+            ctx,
+            serenity::EditInteractionResponse::new().content(fit_message(content)),
+        )
+        .await?;
+    Ok(())
+}
+
+/// check the bot has the permissions it needs in a channel
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "MANAGE_GUILD"
+)]
+async fn diagnose(
+    ctx: Context<'_>,
+    #[description = "Channel to check (default: this one)"] channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let config = ctx.data().config();
+    let locale = config.locale(Some(guild_id));
+    let channel = match channel {
+        Some(channel) => channel,
+        None => ctx
+            .channel_id()
+            .to_channel(ctx)
+            .await?
+            .guild()
+            .ok_or("not a guild channel")?,
+    };
+    let in_thread = channel.thread_metadata.is_some();
+    // threads take their permissions from their parent
+    let parent = match (in_thread, channel.parent_id) {
+        (true, Some(parent_id)) => parent_id.to_channel(ctx).await?.guild(),
+        _ => None,
+    };
+    let bot = guild_id.member(ctx, ctx.framework().bot_id).await?;
+    let granted = {
+        let guild = ctx.guild().ok_or("guild isn't cached")?;
+        guild.user_permissions_in(parent.as_ref().unwrap_or(&channel), &bot)
+    };
+    let persona = config
+        .guild(Some(guild_id))
+        .is_some_and(|g| g.persona.is_some());
+    let findings = diagnose::check(granted, in_thread, persona);
+
+    let missing = findings.iter().filter(|f| !f.granted).count();
+    let mut lines = vec![if missing == 0 {
+        i18n::tr(
+            &locale,
+            "diagnose-ok",
+            &[("channel", format!("<#{}>", channel.id).into())],
+        )
+    } else {
+        i18n::tr(
+            &locale,
+            "diagnose-missing",
+            &[
+                ("channel", format!("<#{}>", channel.id).into()),
+                ("count", missing.into()),
+            ],
+        )
+    }];
+    for finding in findings {
+        let name = finding.needed.permission.get_permission_names().join(", ");
+        lines.push(i18n::tr(
+            &locale,
+            if finding.granted {
+                "diagnose-granted"
+            } else {
+                "diagnose-lacking"
+            },
+            &[
+                ("permission", name.into()),
+                (
+                    "purpose",
+                    i18n::tr(&locale, finding.needed.purpose, &[]).into(),
+                ),
+            ],
+        ));
     }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() {
-    dotenv().ok();
+/// answer volume and latency for this server
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "MANAGE_GUILD"
+)]
+async fn stats(
+    ctx: Context<'_>,
+    #[description = "How many days back to look (default 7)"]
+    #[min = 1]
+    days: Option<u16>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let locale = ctx.data().config().locale(Some(guild_id));
+    let days = days.unwrap_or(7) as i64;
+    let since = time::OffsetDateTime::now_utc() - time::Duration::days(days);
+    let storage = &ctx.data().storage;
+    let stats = async {
+        let guild_stats = storage.guild_stats(guild_id, since).await?;
+        let ratings = storage.ratings(guild_id, since).await?;
+        Ok::<_, storage::Error>((guild_stats, ratings))
+    };
+    let response = match stats.await {
+        Ok((guild_stats, ratings)) => {
+            let mut lines = vec![stats::summary(&locale, days, &guild_stats)];
+            lines.extend(stats::ratings(&locale, &ratings));
+            lines.join("\n")
+        }
+        Err(e) => {
+            tracing::error!("Failed to load stats for guild {}: {}", guild_id, e);
+            i18n::tr(&locale, "stats-unavailable", &[])
+        }
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
 
-    let discord_token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
-    let openai_key = env::var("OPENAI_API_KEY").expect("Expected OPENAI_API_KEY in environment");
-    let openai_base = env::var("OPENAI_BASE_URL").expect("Expected OPENAI_BASE_URL in environment");
+#[cfg(feature = "setup")]
+#[derive(poise::Modal)]
+#[name = "Model"]
+struct ModelModal {
+    #[name = "Model (empty for the provider's)"]
+    #[max_length = 100]
+    model: Option<String>,
+}
 
-    let oai_config: OpenAIConfig = OpenAIConfig::new()
-        .with_api_key(openai_key)
-        .with_api_base(openai_base);
+/// set the bot up for this server: channels, support roles, model, language and more
+#[cfg(feature = "setup")]
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "MANAGE_GUILD"
+)]
+async fn setup(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let config = ctx.data().config();
+    let locale = config.locale(Some(guild_id));
+    let before = setup::GuildSetup::from_config(&config, guild_id);
+    let mut wizard = before.clone();
+    let prefix = format!("{}-setup-", ctx.id());
+    let reply = ctx
+        .send(
+            poise::CreateReply::default()
+                .content(setup_summary(ctx, &locale, &wizard, None))
+                .components(setup_components(&config, &locale, &prefix, &wizard)),
+        )
+        .await?;
 
-    let openai_client = OpenAIClient::with_config(oai_config);
+    loop {
+        let wanted = prefix.clone();
+        let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+            .author_id(ctx.author().id)
+            .timeout(SETUP_TIMEOUT)
+            .filter(move |press| press.data.custom_id.starts_with(&wanted))
+            .await
+        else {
+            reply
+                .edit(
+                    ctx,
+                    poise::CreateReply::default()
+                        .content(i18n::tr(&locale, "setup-timed-out", &[]))
+                        .components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        };
+        let field = &press.data.custom_id[prefix.len()..];
+        let mut note = None;
+        match &press.data.kind {
+            serenity::ComponentInteractionDataKind::ChannelSelect { values } => {
+                wizard.autorespond_channels = values.iter().map(|id| id.get()).collect();
+            }
+            serenity::ComponentInteractionDataKind::RoleSelect { values } => {
+                wizard.support_roles = values.iter().map(|id| id.get()).collect();
+            }
+            serenity::ComponentInteractionDataKind::StringSelect { values }
+                if field == "locale" =>
+            {
+                wizard.locale = values.first().cloned();
+            }
+            serenity::ComponentInteractionDataKind::StringSelect { values } => {
+                wizard.user_profiles = values.iter().any(|v| v == "user_profiles");
+                let dry_run = values.iter().any(|v| v == "dry_run");
+                // left as shown, it keeps following the global setting
+                if dry_run != wizard.dry_run_or(&config) {
+                    wizard.dry_run = Some(dry_run);
+                }
+            }
+            serenity::ComponentInteractionDataKind::Button if field == "model" => {
+                let defaults = ModelModal {
+                    model: wizard.model.clone(),
+                };
+                let modal = poise::execute_modal_on_component_interaction(
+                    ctx,
+                    press.clone(),
+                    Some(defaults),
+                    Some(SETUP_TIMEOUT),
+                )
+                .await?;
+                let model = modal.and_then(|m| m.model).map(|m| m.trim().to_string());
+                match model.filter(|m| !m.is_empty()) {
+                    Some(model) if !is_known_model(ctx.data(), &config, guild_id, &model) => {
+                        let provider = ctx.data().providers.name_for_guild(&config, Some(guild_id));
+                        note = Some(i18n::tr(
+                            &locale,
+                            "model-unknown",
+                            &[("model", model.into()), ("provider", provider.into())],
+                        ));
+                    }
+                    model => wizard.model = model,
+                }
+                // the modal took the press, so the message is edited instead
+                reply
+                    .edit(
+                        ctx,
+                        poise::CreateReply::default()
+                            .content(setup_summary(ctx, &locale, &wizard, note.as_deref()))
+                            .components(setup_components(&config, &locale, &prefix, &wizard)),
+                    )
+                    .await?;
+                continue;
+            }
+            serenity::ComponentInteractionDataKind::Button if field == "save" => {
+                let content = match setup::save(guild_id, &wizard).await {
+                    Ok(saved) => {
+                        // a model picked here wins over one picked with /model
+                        if wizard.model != before.model {
+                            ctx.data().providers.pick_model(guild_id, None);
+                            catalog::save(ctx.data().storage.as_ref(), &ctx.data().providers)
+                                .await?;
+                        }
+                        *ctx.data().config.write().unwrap() = Arc::new(saved);
+                        tracing::info!("{} set up guild {}", ctx.author().id, guild_id);
+                        i18n::tr(&locale, "setup-saved", &[])
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to save the setup of guild {}: {}", guild_id, e);
+                        i18n::tr(&locale, "setup-failed", &[("error", e.to_string().into())])
+                    }
+                };
+                press
+                    .create_response(
+                        ctx,
+                        serenity::CreateInteractionResponse::UpdateMessage(
+                            serenity::CreateInteractionResponseMessage::new()
+                                .content(content)
+                                .components(vec![]),
+                        ),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            _ => {
+                press
+                    .create_response(
+                        ctx,
+                        serenity::CreateInteractionResponse::UpdateMessage(
+                            serenity::CreateInteractionResponseMessage::new()
+                                .content(i18n::tr(&locale, "setup-cancelled", &[]))
+                                .components(vec![]),
+                        ),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        }
+        press
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(setup_summary(ctx, &locale, &wizard, note.as_deref()))
+                        .components(setup_components(&config, &locale, &prefix, &wizard)),
+                ),
+            )
+            .await?;
+    }
+}
 
-    let user_data = Arc::new(Data {
-        openai_client,
-        ai_context: Arc::new(Mutex::new(std::collections::HashMap::new())),
+/// What /setup shows above its menus
+#[cfg(feature = "setup")]
+fn setup_summary(
+    ctx: Context<'_>,
+    locale: &str,
+    wizard: &setup::GuildSetup,
+    note: Option<&str>,
+) -> String {
+    let model = match &wizard.model {
+        Some(model) => i18n::tr(locale, "setup-model", &[("model", model.as_str().into())]),
+        None => {
+            let config = ctx.data().config();
+            let provider = ctx.data().providers.name_for_guild(&config, ctx.guild_id());
+            let model = ctx
+                .data()
+                .providers
+                .get(provider)
+                .map(|p| p.model.clone())
+                .unwrap_or_default();
+            i18n::tr(locale, "setup-model-default", &[("model", model.into())])
+        }
+    };
+    let mut lines = vec![i18n::tr(locale, "setup-intro", &[]), model];
+    lines.extend(note.map(str::to_string));
+    lines.join("\n")
+}
+
+/// The menus and buttons of /setup, showing what's picked so far
+#[cfg(feature = "setup")]
+fn setup_components(
+    config: &config::Config,
+    locale: &str,
+    prefix: &str,
+    wizard: &setup::GuildSetup,
+) -> Vec<serenity::CreateActionRow> {
+    let channels = serenity::CreateSelectMenu::new(
+        format!("{}channels", prefix),
+        serenity::CreateSelectMenuKind::Channel {
+            channel_types: Some(vec![
+                serenity::ChannelType::Text,
+                serenity::ChannelType::Forum,
+            ]),
+            default_channels: Some(
+                wizard
+                    .autorespond_channels
+                    .iter()
+                    .map(|&id| serenity::ChannelId::new(id))
+                    .collect(),
+            ),
+        },
+    )
+    .placeholder(i18n::tr(locale, "setup-channels", &[]))
+    .min_values(0)
+    .max_values(25);
+    let roles = serenity::CreateSelectMenu::new(
+        format!("{}roles", prefix),
+        serenity::CreateSelectMenuKind::Role {
+            default_roles: Some(
+                wizard
+                    .support_roles
+                    .iter()
+                    .map(|&id| serenity::RoleId::new(id))
+                    .collect(),
+            ),
+        },
+    )
+    .placeholder(i18n::tr(locale, "setup-roles", &[]))
+    .min_values(0)
+    .max_values(25);
+    let locales = i18n::locales()
+        .into_iter()
+        .take(25)
+        .map(|l| {
+            let picked = wizard.locale.as_ref() == Some(&l);
+            serenity::CreateSelectMenuOption::new(l.clone(), l).default_selection(picked)
+        })
+        .collect();
+    let language = serenity::CreateSelectMenu::new(
+        format!("{}locale", prefix),
+        serenity::CreateSelectMenuKind::String { options: locales },
+    )
+    .placeholder(i18n::tr(locale, "setup-locale", &[]));
+    let features = [
+        ("user_profiles", wizard.user_profiles),
+        ("dry_run", wizard.dry_run_or(config)),
+    ]
+    .into_iter()
+    .map(|(feature, on)| {
+        let key = format!("setup-{}", feature.replace('_', "-"));
+        serenity::CreateSelectMenuOption::new(i18n::tr(locale, &key, &[]), feature)
+            .description(i18n::tr(locale, &format!("{}-description", key), &[]))
+            .default_selection(on)
+    })
+    .collect();
+    let features = serenity::CreateSelectMenu::new(
+        format!("{}features", prefix),
+        serenity::CreateSelectMenuKind::String { options: features },
+    )
+    .placeholder(i18n::tr(locale, "setup-features", &[]))
+    .min_values(0)
+    .max_values(2);
+    let buttons = vec![
+        serenity::CreateButton::new(format!("{}model", prefix))
+            .label(i18n::tr(locale, "setup-pick-model", &[]))
+            .style(serenity::ButtonStyle::Secondary),
+        serenity::CreateButton::new(format!("{}save", prefix))
+            .label(i18n::tr(locale, "setup-save", &[]))
+            .style(serenity::ButtonStyle::Success),
+        serenity::CreateButton::new(format!("{}cancel", prefix))
+            .label(i18n::tr(locale, "setup-cancel", &[]))
+            .style(serenity::ButtonStyle::Secondary),
+    ];
+    vec![
+        serenity::CreateActionRow::SelectMenu(channels),
+        serenity::CreateActionRow::SelectMenu(roles),
+        serenity::CreateActionRow::SelectMenu(language),
+        serenity::CreateActionRow::SelectMenu(features),
+        serenity::CreateActionRow::Buttons(buttons),
+    ]
+}
+
+/// compare the variants of the running experiment
+#[poise::command(slash_command, owners_only, ephemeral)]
+async fn experiment(
+    ctx: Context<'_>,
+    #[description = "Experiment to show (default: the one running)"] name: Option<String>,
+) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let Some(name) = name.or_else(|| config.experiment.as_ref().map(|e| e.name.clone())) else {
+        ctx.say(i18n::tr(&locale, "experiment-none", &[])).await?;
+        return Ok(());
+    };
+    let response = match ctx.data().storage.variant_stats(&name).await {
+        Ok(variants) if variants.is_empty() => {
+            i18n::tr(&locale, "experiment-no-answers", &[("name", name.into())])
+        }
+        Ok(variants) => experiment::summary(&locale, &name, &variants),
+        Err(e) => {
+            tracing::error!("Failed to load results for experiment {}: {}", name, e);
+            i18n::tr(&locale, "stats-unavailable", &[])
+        }
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// see, pin, or roll back system prompt versions
+#[poise::command(
+    slash_command,
+    owners_only,
+    ephemeral,
+    subcommands("prompt_list", "prompt_pin", "prompt_rollback", "prompt_unpin"),
+    subcommand_required
+)]
+async fn prompt(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// recent prompt versions, newest first
+#[poise::command(slash_command, owners_only, ephemeral, rename = "list")]
+async fn prompt_list(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let prompts = &ctx.data().prompts;
+    let active = prompts.active();
+    let mut lines = vec![i18n::tr(&locale, "prompt-versions", &[])];
+    for version in prompts.history(10).await? {
+        let mut line = i18n::tr(
+            &locale,
+            "prompt-version",
+            &[
+                ("version", version.version.as_str().into()),
+                (
+                    "date",
+                    version
+                        .created_at
+                        .format(time::macros::format_description!(
+                            "[year]-[month]-[day] [hour]:[minute] UTC"
+                        ))?
+                        .into(),
+                ),
+            ],
+        );
+        if version.version == active.version {
+            let marker = if prompts.is_pinned() {
+                "prompt-active-pinned"
+            } else {
+                "prompt-active"
+            };
+            line = format!("{} {}", line, i18n::tr(&locale, marker, &[]));
+        }
+        lines.push(line);
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// answer with this prompt version until unpinned
+#[poise::command(slash_command, owners_only, ephemeral, rename = "pin")]
+async fn prompt_pin(
+    ctx: Context<'_>,
+    #[description = "Version from /prompt list"] version: String,
+) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let response = match ctx.data().prompts.pin(version.trim()).await? {
+        Some(pinned) => i18n::tr(
+            &locale,
+            "prompt-pinned",
+            &[("version", pinned.version.as_str().into())],
+        ),
+        None => i18n::tr(&locale, "prompt-not-found", &[("version", version.into())]),
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// pin the version before the one answering now
+#[poise::command(slash_command, owners_only, ephemeral, rename = "rollback")]
+async fn prompt_rollback(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let prompts = &ctx.data().prompts;
+    let active = prompts.active();
+    let history = prompts.history(100).await?;
+    let previous = history
+        .iter()
+        .skip_while(|v| v.version != active.version)
+        .nth(1);
+    let response = match previous {
+        Some(previous) => {
+            prompts.pin(&previous.version).await?;
+            i18n::tr(
+                &locale,
+                "prompt-pinned",
+                &[("version", previous.version.as_str().into())],
+            )
+        }
+        None => i18n::tr(&locale, "prompt-no-previous", &[]),
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// go back to answering with the prompt file
+#[poise::command(slash_command, owners_only, ephemeral, rename = "unpin")]
+async fn prompt_unpin(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let latest = ctx.data().prompts.unpin(&config).await?;
+    ctx.say(i18n::tr(
+        &locale,
+        "prompt-unpinned",
+        &[("version", latest.version.as_str().into())],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// canned answers staff can post, which the bot quotes too
+#[poise::command(
+    slash_command,
+    guild_only,
+    default_member_permissions = "MANAGE_MESSAGES",
+    subcommands("snippet_add", "snippet_edit", "snippet_send"),
+    subcommand_required
+)]
+async fn snippet(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[derive(poise::Modal)]
+#[name = "Snippet"]
+struct SnippetModal {
+    #[name = "Answer (Markdown)"]
+    #[paragraph]
+    #[max_length = 2000]
+    content: String,
+}
+
+async fn autocomplete_snippet(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+    ctx.data()
+        .storage
+        .snippets(guild_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|s| s.name)
+        .filter(|name| name.starts_with(partial))
+        .take(25)
+        .collect()
+}
+
+/// write a new snippet
+#[poise::command(slash_command, guild_only, ephemeral, rename = "add")]
+async fn snippet_add(
+    ctx: poise::ApplicationContext<'_, Arc<Data>, Error>,
+    #[description = "Lowercase letters, digits, - and _"] name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let locale = ctx.data().config().locale(Some(guild_id));
+    if !snippets::is_valid_name(&name) {
+        ctx.say(i18n::tr(&locale, "snippet-invalid-name", &[]))
+            .await?;
+        return Ok(());
+    }
+    if ctx.data().storage.snippet(guild_id, &name).await?.is_some() {
+        ctx.say(i18n::tr(
+            &locale,
+            "snippet-exists",
+            &[("name", name.into())],
+        ))
+        .await?;
+        return Ok(());
+    }
+    let Some(modal): Option<SnippetModal> = poise::Modal::execute(ctx).await? else {
+        return Ok(());
+    };
+    save_snippet(ctx, guild_id, name, modal.content, &locale).await
+}
+
+/// change a snippet's text
+#[poise::command(slash_command, guild_only, ephemeral, rename = "edit")]
+async fn snippet_edit(
+    ctx: poise::ApplicationContext<'_, Arc<Data>, Error>,
+    #[description = "Snippet to change"]
+    #[autocomplete = "autocomplete_snippet"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let locale = ctx.data().config().locale(Some(guild_id));
+    let Some(existing) = ctx.data().storage.snippet(guild_id, &name).await? else {
+        ctx.say(i18n::tr(
+            &locale,
+            "snippet-not-found",
+            &[("name", name.into())],
+        ))
+        .await?;
+        return Ok(());
+    };
+    let defaults = SnippetModal {
+        content: existing.content,
+    };
+    let Some(modal) = poise::Modal::execute_with_defaults(ctx, defaults).await? else {
+        return Ok(());
+    };
+    save_snippet(ctx, guild_id, name, modal.content, &locale).await
+}
+
+async fn save_snippet(
+    ctx: poise::ApplicationContext<'_, Arc<Data>, Error>,
+    guild_id: serenity::GuildId,
+    name: String,
+    content: String,
+    locale: &str,
+) -> Result<(), Error> {
+    ctx.data()
+        .storage
+        .save_snippet(&storage::Snippet {
+            guild_id,
+            name: name.clone(),
+            content,
+            updated_at: time::OffsetDateTime::now_utc(),
+        })
+        .await?;
+    ctx.say(i18n::tr(locale, "snippet-saved", &[("name", name.into())]))
+        .await?;
+    Ok(())
+}
+
+/// post a snippet in this channel
+#[poise::command(slash_command, guild_only, rename = "send")]
+async fn snippet_send(
+    ctx: Context<'_>,
+    #[description = "Snippet to post"]
+    #[autocomplete = "autocomplete_snippet"]
+    name: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    match ctx.data().storage.snippet(guild_id, &name).await? {
+        Some(snippet) => {
+            ctx.say(snippet.content).await?;
+        }
+        None => {
+            let locale = ctx.data().config().locale(Some(guild_id));
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(i18n::tr(
+                        &locale,
+                        "snippet-not-found",
+                        &[("name", name.into())],
+                    ))
+                    .ephemeral(true),
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// download the last requests to providers and their responses, redacted
+#[poise::command(slash_command, owners_only, ephemeral)]
+async fn captures(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let Some(captures) = ctx.data().providers.captures() else {
+        ctx.say(i18n::tr(&locale, "captures-off", &[])).await?;
+        return Ok(());
+    };
+    let captures = captures.all();
+    ctx.send(
+        poise::CreateReply::default()
+            .content(i18n::tr(
+                &locale,
+                "captures-done",
+                &[("count", captures.len().into())],
+            ))
+            .attachment(serenity::CreateAttachment::bytes(
+                serde_json::to_vec_pretty(&captures)?,
+                "deskhelp-captures.json",
+            )),
+    )
+    .await?;
+    Ok(())
+}
+
+/// look at or poke the bot's internals
+#[poise::command(
+    slash_command,
+    owners_only,
+    ephemeral,
+    subcommands(
+        "debug_context",
+        "debug_queue",
+        "debug_drop",
+        "debug_shadow",
+        "debug_gc"
+    ),
+    subcommand_required
+)]
+async fn debug(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// download a channel's conversation as the model sees it
+#[poise::command(slash_command, owners_only, ephemeral, rename = "context")]
+async fn debug_context(
+    ctx: Context<'_>,
+    #[description = "Channel (default: this one)"] channel: Option<serenity::Channel>,
+) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let channel_id = channel.map_or(ctx.channel_id(), |c| c.id());
+    let context = ctx.data().ai_context.get(channel_id).await?;
+    if context.is_empty() {
+        ctx.say(i18n::tr(
+            &locale,
+            "debug-context-empty",
+            &[("channel", format!("<#{}>", channel_id).into())],
+        ))
+        .await?;
+        return Ok(());
+    }
+    ctx.send(
+        poise::CreateReply::default()
+            .content(i18n::tr(
+                &locale,
+                "debug-context",
+                &[
+                    ("channel", format!("<#{}>", channel_id).into()),
+                    ("messages", context.len().into()),
+                ],
+            ))
+            .attachment(serenity::CreateAttachment::bytes(
+                serde_json::to_vec_pretty(&context)?,
+                format!("deskhelp-context-{}.json", channel_id),
+            )),
+    )
+    .await?;
+    Ok(())
+}
+
+/// how many answers are being generated and waiting
+#[poise::command(slash_command, owners_only, ephemeral, rename = "queue")]
+async fn debug_queue(ctx: Context<'_>) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let status = ctx.data().queue.status();
+    let waiting = |priority| status.waiting.iter().filter(|&&p| p == priority).count();
+    ctx.say(i18n::tr(
+        &locale,
+        "debug-queue",
+        &[
+            ("active", status.active.into()),
+            ("limit", status.limit.into()),
+            ("waiting", status.waiting.len().into()),
+            ("staff", waiting(queue::Priority::Staff).into()),
+            ("tickets", waiting(queue::Priority::Ticket).into()),
+            ("others", waiting(queue::Priority::Normal).into()),
+            ("seconds", status.avg_duration.as_secs().into()),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// forget a channel's conversation without telling the channel
+#[poise::command(slash_command, owners_only, ephemeral, rename = "drop")]
+async fn debug_drop(
+    ctx: Context<'_>,
+    #[description = "Channel (default: this one)"] channel: Option<serenity::Channel>,
+) -> Result<(), Error> {
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let channel_id = channel.map_or(ctx.channel_id(), |c| c.id());
+    ctx.data().ai_context.clear(channel_id).await?;
+    ctx.data().backfilled.first_time(channel_id);
+    ctx.say(i18n::tr(
+        &locale,
+        "debug-dropped",
+        &[("channel", format!("<#{}>", channel_id).into())],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// switch shadow mode (dry_run) here until the bot restarts
+#[poise::command(slash_command, owners_only, ephemeral, rename = "shadow")]
+async fn debug_shadow(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let on = ctx.data().shadow.toggle(&config, ctx.guild_id());
+    tracing::info!(
+        "{} turned shadow mode {} in {:?}",
+        ctx.author().id,
+        if on { "on" } else { "off" },
+        ctx.guild_id()
+    );
+    let key = if on {
+        "debug-shadow-on"
+    } else {
+        "debug-shadow-off"
+    };
+    ctx.say(i18n::tr(&locale, key, &[])).await?;
+    Ok(())
+}
+
+/// clear conversations that have gone quiet
+#[poise::command(slash_command, owners_only, ephemeral, rename = "gc")]
+async fn debug_gc(
+    ctx: Context<'_>,
+    #[description = "Hours without messages (default: [retention] conversations, or 24)"]
+    hours: Option<u32>,
+) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    let hours = hours
+        .or(config.retention.conversations.map(|days| days * 24))
+        .unwrap_or(24);
+    let since = time::OffsetDateTime::now_utc() - time::Duration::hours(hours.into());
+    let cleared = ctx.data().ai_context.clear_idle(since).await?;
+    ctx.say(i18n::tr(
+        &locale,
+        "debug-gc",
+        &[("count", cleared.into()), ("hours", hours.into())],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// roll this server's knowledge base back after a bad push
+#[cfg(feature = "search")]
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "MANAGE_GUILD",
+    subcommands("kb_versions", "kb_rollback"),
+    subcommand_required
+)]
+async fn kb(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// recent versions of the knowledge base, newest first
+#[cfg(feature = "search")]
+#[poise::command(slash_command, guild_only, ephemeral, rename = "versions")]
+async fn kb_versions(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let locale = ctx.data().config().locale(Some(guild_id));
+    let storage = ctx.data().storage.as_ref();
+    let versions = storage.kb_versions(guild_id, 10).await?;
+    if versions.is_empty() {
+        ctx.say(i18n::tr(&locale, "kb-none", &[])).await?;
+        return Ok(());
+    }
+    let current = storage::KbVersion::version_of(&storage.documents(guild_id).await?);
+    let mut lines = vec![i18n::tr(&locale, "kb-versions", &[])];
+    for version in versions {
+        let mut line = i18n::tr(
+            &locale,
+            "kb-version",
+            &[
+                ("version", version.version.as_str().into()),
+                ("documents", version.documents.len().into()),
+                (
+                    "date",
+                    version
+                        .created_at
+                        .format(time::macros::format_description!(
+                            "[year]-[month]-[day] [hour]:[minute] UTC"
+                        ))?
+                        .into(),
+                ),
+            ],
+        );
+        if version.version == current {
+            line = format!("{} {}", line, i18n::tr(&locale, "kb-current", &[]));
+        }
+        lines.push(line);
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// put the knowledge base back the way it was at a version
+#[cfg(feature = "search")]
+#[poise::command(slash_command, guild_only, ephemeral, rename = "rollback")]
+async fn kb_rollback(
+    ctx: Context<'_>,
+    #[description = "Version from /kb versions"] version: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let config = ctx.data().config();
+    let locale = config.locale(Some(guild_id));
+    let Some(search_config) = &config.search else {
+        ctx.say(i18n::tr(&locale, "search-off", &[])).await?;
+        return Ok(());
+    };
+    ctx.defer_ephemeral().await?;
+    let version = version.trim();
+    let (provider, _) = ctx.data().providers.for_guild(&config, Some(guild_id));
+    let response = match knowledge::rollback(
+        ctx.data().storage.as_ref(),
+        provider,
+        search_config,
+        guild_id,
+        version,
+    )
+    .await?
+    {
+        Some(restored) => {
+            tracing::info!(
+                "{} rolled the knowledge base of guild {} back to {}",
+                ctx.author().id,
+                guild_id,
+                restored.version
+            );
+            i18n::tr(
+                &locale,
+                "kb-rolled-back",
+                &[
+                    ("version", restored.version.as_str().into()),
+                    ("documents", restored.documents.len().into()),
+                ],
+            )
+        }
+        None => i18n::tr(&locale, "kb-not-found", &[("version", version.into())]),
+    };
+    ctx.say(response).await?;
+    Ok(())
+}
+
+/// pick the model this server is answered with
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    default_member_permissions = "MANAGE_GUILD",
+    subcommands("model_set", "model_reset", "model_refresh"),
+    subcommand_required
+)]
+async fn model(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn autocomplete_model(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let config = ctx.data().config();
+    let provider = ctx.data().providers.name_for_guild(&config, ctx.guild_id());
+    // aliases from [models] first, then what the provider lists
+    let mut aliases: Vec<_> = config
+        .models
+        .iter()
+        .filter(|(name, m)| m.model.is_some() && name.contains(partial))
+        .map(|(name, _)| name.clone())
+        .collect();
+    aliases.sort();
+    aliases.extend(ctx.data().catalog.suggest(provider, partial));
+    aliases.truncate(25);
+    aliases
+}
+
+/// Whether `model` is an alias, or one the guild's provider lists. Providers that
+/// don't list their models get whatever's typed.
+fn is_known_model(
+    data: &Data,
+    config: &config::Config,
+    guild_id: serenity::GuildId,
+    model: &str,
+) -> bool {
+    let provider = data.providers.name_for_guild(config, Some(guild_id));
+    let is_alias = config.models.get(model).is_some_and(|m| m.model.is_some());
+    match (is_alias, data.catalog.models(provider)) {
+        (false, Some(models)) => models.iter().any(|m| m == model),
+        _ => true,
+    }
+}
+
+/// answer here with another of the provider's models
+#[poise::command(slash_command, guild_only, ephemeral, rename = "set")]
+async fn model_set(
+    ctx: Context<'_>,
+    #[description = "Model, as the provider lists it"]
+    #[autocomplete = "autocomplete_model"]
+    model: String,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let config = ctx.data().config();
+    let locale = config.locale(Some(guild_id));
+    let model = model.trim();
+    if !is_known_model(ctx.data(), &config, guild_id, model) {
+        let provider = ctx.data().providers.name_for_guild(&config, Some(guild_id));
+        ctx.say(i18n::tr(
+            &locale,
+            "model-unknown",
+            &[("model", model.into()), ("provider", provider.into())],
+        ))
+        .await?;
+        return Ok(());
+    }
+    ctx.data()
+        .providers
+        .pick_model(guild_id, Some(model.to_string()));
+    catalog::save(ctx.data().storage.as_ref(), &ctx.data().providers).await?;
+    tracing::info!(
+        "{} picked model {} for guild {}",
+        ctx.author().id,
+        model,
+        guild_id
+    );
+    ctx.say(i18n::tr(&locale, "model-set", &[("model", model.into())]))
+        .await?;
+    Ok(())
+}
+
+/// go back to the model in the config
+#[poise::command(slash_command, guild_only, ephemeral, rename = "reset")]
+async fn model_reset(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().expect("guild_only command");
+    let config = ctx.data().config();
+    let locale = config.locale(Some(guild_id));
+    ctx.data().providers.pick_model(guild_id, None);
+    catalog::save(ctx.data().storage.as_ref(), &ctx.data().providers).await?;
+    let (_, model) = ctx.data().providers.for_guild(&config, Some(guild_id));
+    ctx.say(i18n::tr(&locale, "model-reset", &[("model", model.into())]))
+        .await?;
+    Ok(())
+}
+
+/// list each provider's models again
+#[poise::command(slash_command, guild_only, owners_only, ephemeral, rename = "refresh")]
+async fn model_refresh(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let count = ctx.data().catalog.refresh(&ctx.data().providers).await;
+    ctx.say(i18n::tr(
+        &locale,
+        "model-refreshed",
+        &[("count", count.into())],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// download everything this instance knows, to move it to another one
+#[poise::command(slash_command, owners_only, ephemeral)]
+async fn export(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let contexts = ctx.data().ai_context.all().await?;
+    let archive = archive::Archive::export(ctx.data().storage.as_ref(), contexts).await?;
+    let name = format!(
+        "deskhelp-{}.json",
+        archive
+            .exported_at
+            .format(time::macros::format_description!("[year]-[month]-[day]"))?
+    );
+    ctx.send(
+        poise::CreateReply::default()
+            .content(i18n::tr(&locale, "export-done", &[]))
+            .attachment(serenity::CreateAttachment::bytes(archive.to_json()?, name)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// replace this instance's state with an archive from /export
+#[poise::command(slash_command, owners_only, ephemeral, rename = "import")]
+async fn import_archive(
+    ctx: Context<'_>,
+    #[description = "File from /export or `deskhelp export`"] file: serenity::Attachment,
+) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let locale = ctx.data().config().locale(ctx.guild_id());
+    let archive = match archive::Archive::from_json(&file.download().await?) {
+        Ok(archive) => archive,
+        Err(e) => {
+            ctx.say(i18n::tr(
+                &locale,
+                "import-invalid",
+                &[("error", e.to_string().into())],
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    archive.restore(ctx.data().storage.as_ref()).await?;
+    ctx.data().ai_context.replace_all(&archive.contexts).await?;
+    let config_written = archive.restore_config()?;
+    ctx.say(i18n::tr(
+        &locale,
+        if config_written {
+            "import-done-with-config"
+        } else {
+            "import-done"
+        },
+        &[
+            ("answers", archive.storage.answers.len().into()),
+            ("conversations", archive.contexts.len().into()),
+        ],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Discord events besides commands, which poise hands on with the same `Data`
+async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    _framework: poise::FrameworkContext<'_, Arc<Data>, Error>,
+    data: &Arc<Data>,
+) -> Result<(), Error> {
+    match event {
+        serenity::FullEvent::Message { new_message } => {
+            on_message(ctx, data, new_message.clone()).await
+        }
+        serenity::FullEvent::ReactionAdd { add_reaction } => {
+            record_feedback(ctx, data, add_reaction, true).await;
+            #[cfg(feature = "search")]
+            starboard::on_reaction(ctx, data, add_reaction).await;
+        }
+        serenity::FullEvent::ReactionRemove { removed_reaction } => {
+            record_feedback(ctx, data, removed_reaction, false).await;
+        }
+        serenity::FullEvent::InteractionCreate { interaction } => {
+            on_interaction(ctx, data, interaction).await
+        }
+        serenity::FullEvent::ThreadCreate { thread } => {
+            titles::on_thread_create(ctx, data, thread).await
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "discord.receive", skip_all, fields(channel = %msg.channel_id))]
+async fn on_message(ctx: &serenity::Context, d: &Data, msg: Message) {
+    let config = d.config();
+    if config.is_ignored(&msg) {
+        return;
+    }
+
+    // are we mentioned?
+    let triggered = config.is_question(&msg, ctx.cache.current_user().id, d.bot.as_deref())
+        && (config.voice_channels(msg.guild_id) || !is_voice_chat(ctx, &msg).await);
+
+    // follow-ups to a question that's still being debounced get batched with it,
+    // even if they don't mention us themselves
+    if !triggered && !debounce::is_pending(&d.pending_batches, &msg) {
+        // every bot sees the message, but the main one keeps it for all
+        if let (Some(lurk), None) = (&config.lurk, &d.bot) {
+            if msg.author.id != ctx.cache.current_user().id {
+                d.lurked.record(lurk, &msg);
+            }
+        }
+        return;
+    }
+
+    #[cfg(feature = "scripting")]
+    let msg = {
+        let mut msg = msg;
+        match d.scripts.as_ref().map(|s| s.on_incoming_message(&msg)) {
+            Some(deskhelp::scripts::Incoming::Ignore) => return,
+            Some(deskhelp::scripts::Incoming::Rewrite(content)) => msg.content = content,
+            _ => {}
+        }
+        msg
+    };
+
+    events::publish(events::Event::QuestionReceived {
+        at: time::OffsetDateTime::now_utc(),
+        bot: d.bot.clone(),
+        guild_id: msg.guild_id,
+        channel_id: msg.channel_id,
+        user_id: msg.author.id,
+        message_id: msg.id,
+        question: msg.content.clone(),
     });
 
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
-    let ud_clone = user_data.clone();
-    let framework = poise::Framework::builder()
+    // people often split a question over a few messages, so wait a moment
+    // and answer them all at once
+    let debounce_window = std::time::Duration::from_millis(
+        env::var("AI_DEBOUNCE_MS").map_or(2000, |s| s.parse().unwrap()),
+    );
+    if let Some(batch) = debounce::collect(&d.pending_batches, msg, debounce_window).await {
+        let guild_id = batch[0].guild_id;
+        // the other bots are personas of their own
+        let persona = config
+            .guild(guild_id)
+            .and_then(|g| g.persona.as_ref())
+            .filter(|_| d.bot.is_none());
+        let responder = if d.shadow.dry_run(&config, guild_id) {
+            responder::Responder::DryRun
+        } else {
+            responder::Responder::new(ctx, batch.last().unwrap(), persona, &d.webhooks).await
+        };
+        oai::process_message(batch, ctx.clone(), d, responder).await;
+    }
+}
+
+async fn on_interaction(ctx: &serenity::Context, d: &Data, interaction: &Interaction) {
+    // commands are poise's; only the buttons under answers are ours
+    let Interaction::Component(press) = interaction else {
+        return;
+    };
+    let id = press.data.custom_id.as_str();
+    if let Some(answer) = resolution::Answer::parse(id) {
+        resolution::on_press(ctx, d, press, answer).await;
+    } else if let Some(rated) = resolution::parse_rate_button(id) {
+        resolution::on_rate(ctx, d, press, rated).await;
+    } else if id == escalate::BUTTON_ID {
+        escalate::on_press(ctx, d, press).await;
+    }
+}
+
+/// Whether `msg` is in the text chat of a voice or stage channel
+async fn is_voice_chat(ctx: &serenity::Context, msg: &Message) -> bool {
+    match msg.channel(ctx).await {
+        Ok(channel) => channel.guild().is_some_and(|c| {
+            matches!(
+                c.kind,
+                serenity::ChannelType::Voice | serenity::ChannelType::Stage
+            )
+        }),
+        Err(e) => {
+            tracing::warn!("Failed to look up channel {}: {}", msg.channel_id, e);
+            false
+        }
+    }
+}
+
+/// Counts 👍 and 👎 reactions on answers, for comparing experiment variants
+async fn record_feedback(ctx: &serenity::Context, d: &Data, reaction: &Reaction, added: bool) {
+    let (Some(up), Some(user_id)) = (experiment::vote(&reaction.emoji), reaction.user_id) else {
+        return;
+    };
+    // the bot's own reactions are only there to invite feedback
+    if user_id == ctx.cache.current_user().id {
+        return;
+    }
+    let result = if added {
+        d.storage
+            .add_feedback(reaction.message_id, user_id, up)
+            .await
+    } else {
+        d.storage
+            .remove_feedback(reaction.message_id, user_id, up)
+            .await
+    };
+    match result {
+        Ok(true) => {}
+        // not an answer, or nothing changed
+        Ok(false) => return,
+        Err(e) => {
+            tracing::warn!("Failed to record feedback: {}", e);
+            return;
+        }
+    }
+    events::publish(events::Event::Feedback {
+        at: time::OffsetDateTime::now_utc(),
+        guild_id: reaction.guild_id,
+        channel_id: reaction.channel_id,
+        message_id: reaction.message_id,
+        user_id,
+        up,
+        added,
+    });
+}
+
+async fn export_to_file(storage: &dyn storage::Storage, path: &str) -> Result<(), Error> {
+    // conversations only live in the running bot; /export includes them
+    let archive = archive::Archive::export(storage, Default::default()).await?;
+    std::fs::write(path, archive.to_json()?)?;
+    tracing::info!(
+        "Exported {} answers and {} prompt versions to {}",
+        archive.storage.answers.len(),
+        archive.storage.prompt_versions.len(),
+        path
+    );
+    Ok(())
+}
+
+async fn import_from_file(storage: &dyn storage::Storage, path: &str) -> Result<(), Error> {
+    let archive = archive::Archive::from_json(&std::fs::read(path)?)?;
+    archive.restore(storage).await?;
+    tracing::info!(
+        "Imported {} answers and {} prompt versions from {}",
+        archive.storage.answers.len(),
+        archive.storage.prompt_versions.len(),
+        path
+    );
+    if !archive.restore_config()? && archive.config.is_some() {
+        tracing::warn!(
+            "Kept the existing {}; the archived one wasn't applied",
+            config::Config::path()
+        );
+    }
+    if !archive.contexts.is_empty() {
+        tracing::warn!(
+            "Skipped {} conversations, which only the running bot can take; use /import for those",
+            archive.contexts.len()
+        );
+    }
+    Ok(())
+}
+
+/// Conversations and endpoint cooldowns live in Redis with `[redis]` set, so
+/// every instance shares them, and otherwise in this process
+#[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+async fn shared_state(
+    config: &config::Config,
+    providers: &mut provider::Providers,
+) -> Result<Arc<dyn context::ContextStore>, Error> {
+    let Some(redis) = &config.redis else {
+        return Ok(Arc::new(match config.memory_budget() {
+            Some(budget) => context::MemoryContexts::within(budget),
+            None => context::MemoryContexts::default(),
+        }));
+    };
+    #[cfg(feature = "redis")]
+    {
+        let conn = deskhelp::shared::connect(redis).await?;
+        providers.share_cooldowns(Arc::new(deskhelp::shared::RedisCooldowns::new(
+            conn.clone(),
+            redis,
+        )));
+        tracing::info!("Sharing conversations and endpoint cooldowns through Redis");
+        let cipher =
+            deskhelp::crypto::Cipher::from_key(config.storage.encryption_key().as_deref())?;
+        Ok(Arc::new(
+            deskhelp::shared::RedisContexts::new(conn, redis).with_cipher(cipher),
+        ))
+    }
+    #[cfg(not(feature = "redis"))]
+    Err("[redis] needs a build with `--features redis`".into())
+}
+
+/// Commands and event handling for one bot. Only the main one has commands
+/// and runs the background jobs, so they happen once.
+fn framework(data: Arc<Data>) -> poise::Framework<Arc<Data>, Error> {
+    let main = data.bot.is_none();
+    poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![wack()],
+            commands: if main {
+                let mut commands = vec![
+                    wack(),
+                    ping(),
+                    remindme(),
+                    forgetme(),
+                    language(),
+                    #[cfg(feature = "search")]
+                    search(),
+                    #[cfg(feature = "search")]
+                    docs(),
+                    #[cfg(feature = "search")]
+                    kb(),
+                    stats(),
+                    #[cfg(feature = "setup")]
+                    setup(),
+                    diagnose(),
+                    troubleshoot(),
+                    experiment(),
+                    prompt(),
+                    snippet(),
+                    export(),
+                    captures(),
+                    debug(),
+                    model(),
+                    import_archive(),
+                ];
+                commands.extend(data.plugins.iter().flat_map(|p| p.commands()));
+                commands
+            } else {
+                vec![]
+            },
+            prefix_options: poise::PrefixFrameworkOptions {
+                dynamic_prefix: Some(|ctx| {
+                    Box::pin(async move {
+                        let prefix = ctx.data.config().prefix(ctx.guild_id);
+                        Ok((!prefix.is_empty()).then_some(prefix))
+                    })
+                }),
+                ..Default::default()
+            },
+            on_error: |error| {
+                Box::pin(async move {
+                    reporting::capture(&error.to_string(), None);
+                    if let Err(e) = poise::builtins::on_error(error).await {
+                        tracing::error!("Error while handling error: {}", e);
+                    }
+                })
+            },
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(event_handler(ctx, event, framework, data))
+            },
             ..Default::default()
         })
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
+                if !main {
+                    return Ok(data);
+                }
                 //poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 // for all guilds we are in
                 for guild in ctx.cache.guilds() {
                     poise::builtins::register_in_guild(ctx, &framework.options().commands, guild)
                         .await?;
                 }
-                Ok(ud_clone)
+                stats::spawn_digests(ctx.http.clone(), data.config.clone(), data.storage.clone());
+                announce::spawn_announcements(ctx.http.clone(), data.clone());
+                support_digest::spawn_support_digests(ctx.http.clone(), data.clone());
+                retention::spawn_purge(data.clone());
+                billing::spawn_billing_alerts(ctx.http.clone(), data.clone());
+                #[cfg(feature = "dashboard")]
+                deskhelp::dashboard::spawn(data.clone(), ctx.http.clone(), ctx.cache.clone());
+                #[cfg(not(feature = "dashboard"))]
+                if data.config().dashboard.is_some() || data.config().github.is_some() {
+                    tracing::warn!("[dashboard] needs a build with `--features dashboard`");
+                }
+                #[cfg(not(feature = "trello"))]
+                if data.config().trello.is_some() {
+                    tracing::warn!("[trello] needs a build with `--features trello`");
+                }
+                #[cfg(not(feature = "search"))]
+                if data.config().search.is_some() {
+                    tracing::warn!("[search] needs a build with `--features search`");
+                }
+                #[cfg(not(feature = "ocr"))]
+                if data.config().ocr.is_some() {
+                    tracing::warn!("[ocr] needs a build with `--features ocr`");
+                }
+                #[cfg(not(feature = "triage"))]
+                if data.config().guilds.values().any(|g| g.triage.is_some()) {
+                    tracing::warn!("[triage] needs a build with `--features triage`");
+                }
+                data.scheduler.register(
+                    reminders::KIND,
+                    reminders::ReminderHandler {
+                        http: ctx.http.clone(),
+                        config: data.config.clone(),
+                    },
+                );
+                data.scheduler.register(
+                    resolution::KIND,
+                    resolution::ResolutionHandler {
+                        http: ctx.http.clone(),
+                        config: data.config.clone(),
+                        storage: data.storage.clone(),
+                    },
+                );
+                data.scheduler.clone().spawn();
+                Ok(data)
             })
         })
-        .build();
+        .build()
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    let loaded = config::Config::load();
+    let found_config = loaded.is_some();
+    let config = loaded.unwrap_or_default();
+    // flushes the log file on exit
+    let _log_guard = telemetry::init(config.log_file.as_ref());
+    if !found_config {
+        tracing::info!(
+            "No config file at {}, using defaults",
+            config::Config::path()
+        );
+    }
+    i18n::init(config.locales_dir.as_deref());
 
-    let mut client = serenity::ClientBuilder::new(discord_token, intents)
-        .framework(framework)
-        .event_handler(Handler)
+    // `deskhelp export <file>` and `deskhelp import <file>` move state between instances
+    let mut args = env::args().skip(1);
+    let command = args.next();
+    if let Some(command @ ("export" | "import")) = command.as_deref() {
+        let path = args.next().expect("usage: deskhelp export|import <file>");
+        let storage = storage::open(&config.storage, config.memory_budget())
+            .await
+            .unwrap_or_else(|e| panic!("Failed to open storage at {}: {}", config.storage.url, e));
+        let result = if command == "export" {
+            export_to_file(storage.as_ref(), &path).await
+        } else {
+            import_from_file(storage.as_ref(), &path).await
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to {} {}: {}", command, path, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // `deskhelp backup <file>` and `deskhelp restore <file>` work while the bot is running
+    #[cfg(feature = "backup")]
+    if let Some(command @ ("backup" | "restore")) = command.as_deref() {
+        let path = args.next().expect("usage: deskhelp backup|restore <file>");
+        let path = std::path::Path::new(&path);
+        let result = if command == "backup" {
+            backup::backup(&config.storage, path).await
+        } else {
+            let storage = storage::open(&config.storage, config.memory_budget())
+                .await
+                .unwrap_or_else(|e| {
+                    panic!("Failed to open storage at {}: {}", config.storage.url, e)
+                });
+            backup::restore(&config.storage, storage.as_ref(), path).await
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to {} {}: {}", command, path.display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(not(feature = "backup"))]
+    if let Some(command @ ("backup" | "restore")) = command.as_deref() {
+        tracing::error!(
+            "`deskhelp {}` needs a build with `--features backup`",
+            command
+        );
+        std::process::exit(1);
+    }
+
+    // `deskhelp migrate` moves settings from the environment into the config file
+    #[cfg(feature = "setup")]
+    if command.as_deref() == Some("migrate") {
+        let discord_token =
+            env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
+        let http = serenity::Http::new(&discord_token);
+        if let Err(e) = migrate::run(&http).await {
+            tracing::error!("Failed to migrate: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    #[cfg(not(feature = "setup"))]
+    if command.as_deref() == Some("migrate") {
+        tracing::error!("`deskhelp migrate` needs a build with `--features setup`");
+        std::process::exit(1);
+    }
+
+    let openai_key = env::var("OPENAI_API_KEY").expect("Expected OPENAI_API_KEY in environment");
+    let openai_base = env::var("OPENAI_BASE_URL").expect("Expected OPENAI_BASE_URL in environment");
+
+    let ai_model = env::var("AI_MODEL").unwrap_or("llama-3.2-11b-vision-preview".to_string());
+
+    let http = reqwest::Client::new();
+    reporting::init(config.error_reporting.as_ref(), http.clone());
+    events::init(config.events.as_ref()).await;
+    // OPENAI_API_KEY may hold several comma-separated keys to rotate across
+    let endpoints = openai_key
+        .split(',')
+        .enumerate()
+        .map(|(i, key)| provider::Endpoint::new(format!("default#{}", i), key.trim(), &openai_base))
+        .collect();
+    // splitting always gives at least one key, even an empty one
+    let default = provider::Provider::new(config::DEFAULT_PROVIDER, endpoints, ai_model)
+        .expect("OPENAI_API_KEY gives at least one endpoint");
+    let mut providers = provider::Providers::new(default, &config.providers);
+
+    // `deskhelp repl [guild id]` answers on the terminal instead of Discord
+    if command.as_deref() == Some("repl") {
+        let guild_id = args
+            .next()
+            .map(|id| serenity::GuildId::new(id.parse().expect("guild id must be a number")));
+        repl::run(&config, &providers, guild_id).await;
+        return;
+    }
+
+    // `deskhelp bench <archive> [--mock]` times answers to an archive's conversations
+    if command.as_deref() == Some("bench") {
+        let path = args
+            .next()
+            .expect("usage: deskhelp bench <archive> [--mock]");
+        let mock = args.next().as_deref() == Some("--mock");
+        let archive = std::fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|json| archive::Archive::from_json(&json))
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        let turns = bench::turns(&archive.contexts);
+        let (provider, model) = providers.for_guild(&config, None);
+        let backend = (!mock).then_some(provider as &dyn provider::ChatBackend);
+        println!(
+            "Replaying {} questions against {}",
+            turns.len(),
+            if mock { "a mock" } else { &model }
+        );
+        let report = bench::run(&config, backend, &model, &turns).await;
+        println!("{}", report.summary());
+        return;
+    }
+
+    let discord_token = env::var("DISCORD_TOKEN").expect("Expected DISCORD_TOKEN in environment");
+
+    // catch misconfiguration now rather than on the first question
+    if env::var("DESKHELP_SKIP_CHECKS").is_err() {
+        let report = preflight::check(&discord_token, &config, &providers).await;
+        report.log();
+        if report.has_errors() {
+            tracing::error!(
+                "Startup checks failed; fix the errors above or set DESKHELP_SKIP_CHECKS=1 to start anyway"
+            );
+            std::process::exit(1);
+        }
+    }
+
+    let storage = storage::open(&config.storage, config.memory_budget())
+        .await
+        .unwrap_or_else(|e| panic!("Failed to open storage at {}: {}", config.storage.url, e));
+    let prompts = prompt::Prompts::load(&config, storage.clone())
+        .await
+        .unwrap_or_else(|e| panic!("Failed to load the prompt: {}", e));
+    if config.capture_requests > 0 && config.memory_budget().is_some() {
+        tracing::warn!("Not keeping requests for /captures, to stay within memory_budget_mb");
+    } else if config.capture_requests > 0 {
+        providers.capture(Arc::new(capture::Captures::new(config.capture_requests)));
+        tracing::info!(
+            "Keeping the last {} requests to providers for /captures",
+            config.capture_requests
+        );
+    }
+    let ai_context = shared_state(&config, &mut providers)
         .await
-        .expect("create client failed");
+        .unwrap_or_else(|e| panic!("Failed to connect to Redis: {}", e));
+    if let Err(e) = catalog::restore(storage.as_ref(), &providers).await {
+        tracing::warn!("Failed to restore the models picked with /model: {}", e);
+    }
 
-    {
-        let mut data = client.data.write().await;
-        data.insert::<Data>(user_data);
+    let plugins = plugins::load(&config);
+    #[cfg(feature = "scripting")]
+    let scripts = config
+        .scripts
+        .as_ref()
+        .map(|scripts| Arc::new(deskhelp::scripts::Scripts::load(scripts)));
+    #[cfg(not(feature = "scripting"))]
+    if config.scripts.is_some() {
+        tracing::warn!("[scripts] needs a build with `--features scripting`");
+    }
+    let memory_budget = config.memory_budget();
+    if let Some(budget) = memory_budget {
+        tracing::info!(
+            "Keeping to {} MB: {} messages of conversations in at most {} channels, no caches",
+            budget.megabytes,
+            budget.context_messages(),
+            budget.conversations()
+        );
+    }
+    let user_data = Arc::new(Data {
+        queue: queue::RequestQueue::new(config.max_concurrent_requests),
+        config: Arc::new(std::sync::RwLock::new(Arc::new(config))),
+        providers: Arc::new(providers),
+        ai_context,
+        pending_batches: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        webhooks: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        http,
+        scheduler: Arc::new(scheduler::Scheduler::new(storage.clone())),
+        storage,
+        prompts: Arc::new(prompts),
+        in_flight: Default::default(),
+        backfilled: Default::default(),
+        lurked: Default::default(),
+        releases: Arc::new(match memory_budget {
+            Some(_) => releases::ReleaseCache::uncached(),
+            None => Default::default(),
+        }),
+        shadow: Default::default(),
+        catalog: Default::default(),
+        plugins: Arc::new(plugins),
+        #[cfg(feature = "scripting")]
+        scripts,
+        bot: None,
+    });
+    let discovering = user_data.clone();
+    tokio::spawn(async move {
+        discovering.catalog.refresh(&discovering.providers).await;
+    });
+
+    config::watch(user_data.config.clone());
+    prompt::watch(user_data.prompts.clone(), user_data.config.clone());
+    #[cfg(feature = "scripting")]
+    if let Some(scripts) = &user_data.scripts {
+        scripts.clone().watch();
+    }
+
+    // the config's `bots` run alongside the main one, sharing storage and providers
+    let mut bots = vec![(discord_token, user_data.clone())];
+    for bot in &user_data.config().bots {
+        let token = env::var(&bot.token_env).unwrap_or_else(|_| {
+            panic!(
+                "Expected {} in environment for bot {}",
+                bot.token_env, bot.name
+            )
+        });
+        bots.push((token, Arc::new(user_data.for_bot(&bot.name))));
+    }
+    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
+    let mut clients = vec![];
+    // under a memory budget, Discord's cache keeps guilds and channels but no users or messages
+    let mut cache_settings = ::serenity::cache::Settings::default();
+    if memory_budget.is_some() {
+        cache_settings.cache_users = false;
+        cache_settings.max_messages = 0;
+    }
+    for (token, data) in bots {
+        let client = serenity::ClientBuilder::new(token, intents)
+            .cache_settings(cache_settings.clone())
+            .framework(framework(data))
+            .await
+            .expect("create client failed");
+        clients.push(client);
     }
 
-    client.start().await.unwrap();
+    futures::future::try_join_all(clients.iter_mut().map(|client| client.start()))
+        .await
+        .unwrap();
 }
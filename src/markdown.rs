@@ -0,0 +1,187 @@
+/// Discord's limit on message length
+pub const MESSAGE_LIMIT: usize = 2000;
+
+/// `text` cut to fit in one message, with an ellipsis where it was cut
+pub fn fit_message(text: String) -> String {
+    if text.chars().count() <= MESSAGE_LIMIT {
+        return text;
+    }
+    text.chars().take(MESSAGE_LIMIT - 1).collect::<String>() + "…"
+}
+
+/// Rewrites model output into Markdown that Discord renders properly:
+/// tables become code blocks, bare URLs get wrapped in `<>` so they don't
+/// embed, and headings deeper than `###` become bold text.
+pub fn render_for_discord(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut in_code_block = false;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            out.push(line.to_string());
+            i += 1;
+            continue;
+        }
+        if in_code_block {
+            out.push(line.to_string());
+            i += 1;
+            continue;
+        }
+
+        if is_table_row(line) && lines.get(i + 1).is_some_and(|l| is_table_separator(l)) {
+            let mut rows = vec![line];
+            let mut j = i + 2;
+            while j < lines.len() && is_table_row(lines[j]) {
+                rows.push(lines[j]);
+                j += 1;
+            }
+            out.push(table_to_code_block(&rows));
+            i = j;
+            continue;
+        }
+
+        out.push(wrap_bare_urls(&normalize_heading(line)));
+        i += 1;
+    }
+
+    out.join("\n")
+}
+
+fn is_table_row(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('|') && line.len() > 1
+}
+
+fn is_table_separator(line: &str) -> bool {
+    let line = line.trim();
+    line.contains('|')
+        && line.contains('-')
+        && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+fn table_cells(row: &str) -> Vec<String> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = row.strip_suffix('|').unwrap_or(row);
+    row.split('|').map(|c| c.trim().to_string()).collect()
+}
+
+/// Lays a Markdown table out as aligned plain text inside a code block
+fn table_to_code_block(rows: &[&str]) -> String {
+    let rows: Vec<Vec<String>> = rows.iter().map(|r| table_cells(r)).collect();
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in &rows {
+        for (c, cell) in row.iter().enumerate() {
+            widths[c] = widths[c].max(cell.chars().count());
+        }
+    }
+
+    let format_row = |row: &Vec<String>| {
+        (0..columns)
+            .map(|c| {
+                let cell = row.get(c).map(String::as_str).unwrap_or("");
+                format!("{:width$}", cell, width = widths[c])
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut out = vec!["```".to_string(), format_row(&rows[0])];
+    out.push(
+        widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-"),
+    );
+    out.extend(rows[1..].iter().map(format_row));
+    out.push("```".to_string());
+    out.join("\n")
+}
+
+/// Discord only renders `#` through `###`
+fn normalize_heading(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if level > 3 {
+        if let Some(title) = trimmed[level..].strip_prefix(' ') {
+            return format!("**{}**", title.trim());
+        }
+    }
+    line.to_string()
+}
+
+/// Wraps URLs in `<>` so Discord doesn't embed them, leaving inline code alone
+fn wrap_bare_urls(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_inline_code = false;
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        if rest.starts_with('`') {
+            in_inline_code = !in_inline_code;
+            out.push('`');
+            rest = &rest[1..];
+            continue;
+        }
+
+        if !in_inline_code && (rest.starts_with("https://") || rest.starts_with("http://")) {
+            let already_wrapped = out.ends_with('<');
+            let in_link = out.ends_with("](");
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '<' || c == '>' || c == '`')
+                .unwrap_or(rest.len());
+            let mut url = &rest[..end];
+            if in_link {
+                // the URL of a [masked](link) runs up to its closing paren
+                url = &url[..url.find(')').unwrap_or(url.len())];
+            } else {
+                url = trim_url_punctuation(url);
+            }
+
+            if already_wrapped {
+                out.push_str(url);
+            } else {
+                out.push('<');
+                out.push_str(url);
+                out.push('>');
+            }
+            rest = &rest[url.len()..];
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    out
+}
+
+/// Drops sentence punctuation that ended up glued to the end of a URL,
+/// keeping closing parens that belong to the URL itself
+fn trim_url_punctuation(mut url: &str) -> &str {
+    loop {
+        let Some(last) = url.chars().last() else {
+            return url;
+        };
+        let unbalanced_paren = last == ')' && url.matches('(').count() < url.matches(')').count();
+        if matches!(
+            last,
+            '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '"' | '*' | '_'
+        ) || unbalanced_paren
+        {
+            url = &url[..url.len() - last.len_utf8()];
+        } else {
+            return url;
+        }
+    }
+}
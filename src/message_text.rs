@@ -0,0 +1,106 @@
+use serenity::all::{Attachment, Embed, Message};
+
+/// A message's text as the model should see it: custom emoji by name, and
+/// stickers and GIFs described instead of left as markup or bare links
+pub fn of(msg: &Message) -> String {
+    let mut text = readable(&msg.content);
+    for embed in msg
+        .embeds
+        .iter()
+        .filter(|e| e.kind.as_deref() == Some("gifv"))
+    {
+        let described = match describe_gif(embed) {
+            Some(what) => format!("[GIF: {}]", what),
+            None => "[GIF]".to_string(),
+        };
+        match embed.url.as_deref().filter(|url| text.contains(url)) {
+            Some(url) => text = text.replace(url, &described),
+            None => push_line(&mut text, &described),
+        }
+    }
+    for sticker in &msg.sticker_items {
+        push_line(&mut text, &format!("[sticker: {}]", sticker.name));
+    }
+    text
+}
+
+/// The images attached to the batch, in order
+pub fn images(batch: &[Message]) -> Vec<&Attachment> {
+    batch
+        .iter()
+        .flat_map(|m| &m.attachments)
+        .filter(|a| {
+            a.content_type
+                .as_deref()
+                .is_some_and(|t| t.starts_with("image/"))
+        })
+        .collect()
+}
+
+/// `text` with custom emoji like `<:thumbsup_cat:123>` written as `:thumbsup_cat:`
+pub fn readable(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match custom_emoji(rest) {
+            Some((name, len)) => {
+                out.push(':');
+                out.push_str(name);
+                out.push(':');
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The name and length of the custom emoji `text` starts with, if it does
+fn custom_emoji(text: &str) -> Option<(&str, usize)> {
+    let end = text.find('>')?;
+    let inner = &text[1..end];
+    let inner = inner.strip_prefix('a').unwrap_or(inner);
+    let (name, id) = inner.strip_prefix(':')?.split_once(':')?;
+    let name_ok = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    let id_ok = !id.is_empty() && id.chars().all(|c| c.is_ascii_digit());
+    (name_ok && id_ok).then_some((name, end + 1))
+}
+
+/// What a GIF shows: its title, or failing that the words in its link, as in
+/// `tenor.com/view/happy-dance-gif-123`
+fn describe_gif(embed: &Embed) -> Option<String> {
+    if let Some(title) = embed.title.as_deref().filter(|t| !t.trim().is_empty()) {
+        return Some(title.trim().to_string());
+    }
+    let slug = embed
+        .url
+        .as_deref()
+        .and_then(|url| url.trim_end_matches('/').rsplit('/').next())
+        .unwrap_or_default();
+    let words: Vec<&str> = slug
+        .split('-')
+        .filter(|w| !w.is_empty() && !w.chars().all(|c| c.is_ascii_digit()))
+        .collect();
+    // Tenor ends slugs with "gif" and an id
+    let words = match words.split_last() {
+        Some((&"gif", words)) => words,
+        _ => &words[..],
+    };
+    // file names like `cat.gif` say nothing
+    (!words.is_empty() && !slug.contains('.')).then(|| words.join(" "))
+}
+
+fn push_line(text: &mut String, line: &str) {
+    if text.trim().is_empty() {
+        text.clear();
+    } else {
+        text.push('\n');
+    }
+    text.push_str(line);
+}
@@ -0,0 +1,133 @@
+//! `deskhelp migrate`: moves settings deployments used to make only in the
+//! environment into the config file, so they can be changed per guild with
+//! `/setup` like everything else. `AUTORESPOND_CHANNELS` goes into each
+//! channel's guild, `AI_MODEL` becomes those guilds' model, and
+//! `AI_TOKEN_LIMIT` that model's `context_tokens`.
+
+use std::{collections::BTreeMap, env};
+
+use serenity::all::{Channel, ChannelId, GuildId, Http};
+use toml_edit::{value, DocumentMut, Item, Table};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    setup::{self, GuildSetup},
+    storage::Error,
+};
+
+/// What the environment sets that now belongs in the config
+#[derive(Debug, Default, PartialEq)]
+pub struct EnvSettings {
+    /// Switched-on `AUTORESPOND_CHANNELS`; ones starting with `-` are left out
+    pub autorespond_channels: Vec<u64>,
+    pub model: Option<String>,
+    pub token_limit: Option<usize>,
+}
+
+impl EnvSettings {
+    pub fn from_env() -> EnvSettings {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|v: &String| !v.trim().is_empty())
+        };
+        EnvSettings {
+            autorespond_channels: var("AUTORESPOND_CHANNELS")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .filter(|&id| id != 0)
+                .collect(),
+            model: var("AI_MODEL"),
+            token_limit: var("AI_TOKEN_LIMIT").and_then(|limit| limit.trim().parse().ok()),
+        }
+    }
+}
+
+/// Writes `settings` into the config file `contents`, with `guilds` saying
+/// which of the channels are in which guild. Settings the config already has
+/// are kept.
+pub fn apply(
+    contents: &str,
+    settings: &EnvSettings,
+    guilds: &BTreeMap<GuildId, Vec<u64>>,
+) -> Result<String, Error> {
+    let config: Config = toml::from_str(contents)?;
+    let mut contents = contents.to_string();
+    for (&guild_id, channels) in guilds {
+        let mut guild = GuildSetup::from_config(&config, guild_id);
+        for &channel in channels {
+            if !guild.autorespond_channels.contains(&channel) {
+                guild.autorespond_channels.push(channel);
+            }
+        }
+        if guild.model.is_none() {
+            guild.model = settings.model.clone();
+        }
+        contents = setup::apply(&contents, guild_id, &guild)?;
+    }
+
+    let (Some(model), Some(token_limit)) = (&settings.model, settings.token_limit) else {
+        return Ok(contents);
+    };
+    let mut doc: DocumentMut = contents.parse()?;
+    let models = doc
+        .entry("models")
+        .or_insert_with(|| {
+            let mut models = Table::new();
+            models.set_implicit(true);
+            Item::Table(models)
+        })
+        .as_table_like_mut()
+        .ok_or("models in the config isn't a table")?;
+    if models.get(model).is_none() {
+        models.insert(model, Item::Table(Table::new()));
+    }
+    let model = models
+        .get_mut(model)
+        .and_then(Item::as_table_like_mut)
+        .ok_or("the model's section of the config isn't a table")?;
+    if model.get("context_tokens").is_none() {
+        model.insert("context_tokens", value(token_limit as i64));
+    }
+    Ok(doc.to_string())
+}
+
+/// Looks up the guild of each channel; ones that can't be seen are logged
+/// and left out
+pub async fn guilds_of(http: &Http, channels: &[u64]) -> BTreeMap<GuildId, Vec<u64>> {
+    let mut guilds: BTreeMap<GuildId, Vec<u64>> = BTreeMap::new();
+    for &id in channels {
+        match http.get_channel(ChannelId::new(id)).await {
+            Ok(Channel::Guild(channel)) => guilds.entry(channel.guild_id).or_default().push(id),
+            Ok(_) => warn!("Channel {} isn't in a server, leaving it out", id),
+            Err(e) => warn!("Can't see channel {} ({}), leaving it out", id, e),
+        }
+    }
+    guilds
+}
+
+/// Moves the environment's settings into the config file, creating it if
+/// there isn't one
+pub async fn run(http: &Http) -> Result<(), Error> {
+    let settings = EnvSettings::from_env();
+    let guilds = guilds_of(http, &settings.autorespond_channels).await;
+    let path = Config::path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let migrated = apply(&contents, &settings, &guilds)?;
+    // a config that doesn't load would stop the bot from starting
+    toml::from_str::<Config>(&migrated)?;
+    std::fs::write(&path, migrated)?;
+    info!(
+        "Moved {} channels in {} servers into {}; AUTORESPOND_CHANNELS can be taken out of the environment",
+        guilds.values().map(Vec::len).sum::<usize>(),
+        guilds.len(),
+        path
+    );
+    Ok(())
+}
@@ -0,0 +1,312 @@
+use async_openai::types::ReasoningEffort;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+/// What a model's API accepts, where it differs from ordinary chat models
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capabilities {
+    /// Thinks before answering. Takes `max_completion_tokens` instead of
+    /// `max_tokens`, counting the thinking too, and a reasoning effort.
+    pub reasoning: bool,
+    /// Can stream its answer; otherwise it's delivered in one piece
+    pub streaming: bool,
+    /// Takes a system message; otherwise the system prompt goes in a user message
+    pub system_message: bool,
+    /// How hard a reasoning model should think, or the provider's default
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Can call tools, like looking up snippets
+    pub tools: bool,
+    /// Can look at images, so those attached to questions are sent along
+    pub vision: bool,
+    /// Most tokens of prompt to send it, or `AI_TOKEN_LIMIT`
+    pub context_tokens: Option<usize>,
+    /// Reports the tokens a streamed answer used when asked to, at the end of
+    /// the stream; otherwise they're counted here, roughly
+    pub stream_usage: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities {
+            reasoning: false,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: false,
+            context_tokens: None,
+            stream_usage: true,
+        }
+    }
+}
+
+/// Overrides for one model in `[models]`, for models the built-in table
+/// doesn't know (or gets wrong). With `model` set, the entry is an alias
+/// like `fast` for that model instead, and its settings win over the model's.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ModelConfig {
+    pub model: Option<String>,
+    pub reasoning: Option<bool>,
+    pub streaming: Option<bool>,
+    pub system_message: Option<bool>,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    pub tools: Option<bool>,
+    pub vision: Option<bool>,
+    pub context_tokens: Option<usize>,
+    pub stream_usage: Option<bool>,
+    /// US dollars per million prompt and completion tokens, for spending alerts
+    pub input_price: Option<f64>,
+    pub output_price: Option<f64>,
+}
+
+/// Known models by name prefix; the longest matching prefix wins
+const BUILT_IN: &[(&str, Capabilities)] = &[
+    (
+        "o1",
+        Capabilities {
+            reasoning: true,
+            streaming: false,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    // the previews predate system messages, reasoning effort and tools
+    (
+        "o1-mini",
+        Capabilities {
+            reasoning: true,
+            streaming: false,
+            system_message: false,
+            reasoning_effort: None,
+            tools: false,
+            vision: false,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "o1-preview",
+        Capabilities {
+            reasoning: true,
+            streaming: false,
+            system_message: false,
+            reasoning_effort: None,
+            tools: false,
+            vision: false,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "o3",
+        Capabilities {
+            reasoning: true,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    // o3-mini can't look at images, unlike o3
+    (
+        "o3-mini",
+        Capabilities {
+            reasoning: true,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: false,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "o4",
+        Capabilities {
+            reasoning: true,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "gpt-4o",
+        Capabilities {
+            reasoning: false,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "gpt-4.1",
+        Capabilities {
+            reasoning: false,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+    (
+        "gpt-5",
+        Capabilities {
+            reasoning: true,
+            streaming: true,
+            system_message: true,
+            reasoning_effort: None,
+            tools: true,
+            vision: true,
+            context_tokens: None,
+            stream_usage: true,
+        },
+    ),
+];
+
+/// The model an alias in `[models]` stands for, or `model` itself
+pub fn resolve<'a>(config: &'a Config, model: &'a str) -> &'a str {
+    config
+        .models
+        .get(model)
+        .and_then(|m| m.model.as_deref())
+        .unwrap_or(model)
+}
+
+impl Capabilities {
+    /// What `model` accepts: the built-in table, then anything set for it in
+    /// `[models]`, then anything set for the alias it was called by
+    pub fn of(config: &Config, model: &str) -> Capabilities {
+        let id = resolve(config, model);
+        // routers like OpenRouter put the vendor in front, as in `openai/o3-mini`
+        let name = id.rsplit('/').next().unwrap_or(id);
+        let mut capabilities = BUILT_IN
+            .iter()
+            .filter(|(prefix, _)| name.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, c)| c.clone())
+            .unwrap_or_default();
+
+        let entries = [Some(id), (id != model).then_some(model)];
+        for m in entries
+            .into_iter()
+            .flatten()
+            .filter_map(|name| config.models.get(name))
+        {
+            if let Some(reasoning) = m.reasoning {
+                capabilities.reasoning = reasoning;
+            }
+            if let Some(streaming) = m.streaming {
+                capabilities.streaming = streaming;
+            }
+            if let Some(system_message) = m.system_message {
+                capabilities.system_message = system_message;
+            }
+            if let Some(tools) = m.tools {
+                capabilities.tools = tools;
+            }
+            if let Some(vision) = m.vision {
+                capabilities.vision = vision;
+            }
+            if let Some(stream_usage) = m.stream_usage {
+                capabilities.stream_usage = stream_usage;
+            }
+            if m.context_tokens.is_some() {
+                capabilities.context_tokens = m.context_tokens;
+            }
+            if m.reasoning_effort.is_some() {
+                capabilities.reasoning_effort = m.reasoning_effort.clone();
+            }
+        }
+        capabilities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_longest_prefix_wins() {
+        let config = Config::default();
+        assert_eq!(
+            Capabilities::of(&config, "llama-3.3-70b-versatile"),
+            Capabilities::default()
+        );
+        assert!(Capabilities::of(&config, "gpt-4o-mini").vision);
+        let o1 = Capabilities::of(&config, "o1-2024-12-17");
+        assert!(o1.reasoning && !o1.streaming && o1.system_message);
+        let o1_mini = Capabilities::of(&config, "o1-mini");
+        assert!(!o1_mini.system_message);
+        assert!(Capabilities::of(&config, "openai/o3-mini").streaming);
+    }
+
+    #[test]
+    fn config_overrides_the_table() {
+        let config: Config = toml::from_str(
+            r#"
+            [models."o3-mini"]
+            streaming = false
+            reasoning_effort = "high"
+
+            [models.my-reasoner]
+            reasoning = true
+            "#,
+        )
+        .unwrap();
+        let o3 = Capabilities::of(&config, "o3-mini");
+        assert!(o3.reasoning && !o3.streaming);
+        assert_eq!(o3.reasoning_effort, Some(ReasoningEffort::High));
+        assert!(Capabilities::of(&config, "my-reasoner").reasoning);
+        assert!(Capabilities::of(&config, "o3").streaming);
+    }
+
+    #[test]
+    fn aliases_stand_for_a_model_with_their_own_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            [models.fast]
+            model = "gpt-4o-mini"
+            context_tokens = 16000
+
+            [models.cheap-reasoner]
+            model = "o3-mini"
+            tools = false
+
+            [models."o3-mini"]
+            reasoning_effort = "low"
+            tools = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(resolve(&config, "fast"), "gpt-4o-mini");
+        assert_eq!(resolve(&config, "gpt-4o-mini"), "gpt-4o-mini");
+        let fast = Capabilities::of(&config, "fast");
+        assert!(fast.vision && !fast.reasoning);
+        assert_eq!(fast.context_tokens, Some(16000));
+        let reasoner = Capabilities::of(&config, "cheap-reasoner");
+        assert!(reasoner.reasoning && !reasoner.tools && !reasoner.vision);
+        assert_eq!(reasoner.reasoning_effort, Some(ReasoningEffort::Low));
+        assert!(Capabilities::of(&config, "o3-mini").tools);
+    }
+}
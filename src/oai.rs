@@ -1,24 +1,60 @@
-use std::{
-    env,
-    sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, env};
 
 use async_openai::{
-    config::OpenAIConfig,
+    error::OpenAIError,
     types::{
         ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
-        ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
-        ChatCompletionRequestSystemMessageContent, ChatCompletionRequestUserMessage,
-        ChatCompletionRequestUserMessageContent, CreateChatCompletionRequest,
+        ChatCompletionRequestDeveloperMessageContent, ChatCompletionRequestMessage,
+        ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+        ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
+        ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+        ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions, CompletionUsage,
+        CreateChatCompletionRequest, CreateChatCompletionStreamResponse, ImageDetail, ImageUrl,
     },
-    Client as OpenAIClient,
 };
 use futures::TryStreamExt;
-use serenity::all::EditMessage;
-use tiktoken_rs::{get_chat_completion_max_tokens, ChatCompletionRequestMessage as TikChatMsg};
+use serde::Serialize;
+use serenity::all::{Channel, GuildId, Http, Message, RoleId};
+use tiktoken_rs::ChatCompletionRequestMessage as TikChatMsg;
 use time::OffsetDateTime;
+use time_tz::{timezones, Offset, OffsetDateTimeExt, TimeZone};
+
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-const SYSTEM_MESSAGE: &str = r#"
+#[cfg(not(feature = "triage"))]
+use crate::config::TriageConfig;
+#[cfg(feature = "ocr")]
+use crate::ocr;
+#[cfg(feature = "triage")]
+use crate::triage;
+use crate::{
+    activity::{self, Activity},
+    assembler::{Action, Pace, ResponseAssembler},
+    backfill, budget,
+    config::{Config, GuildConfig},
+    content_policy, duplicates, escalate,
+    events::{self, Event},
+    experiment, forum_tags, i18n, known_errors, language, latex, links, lurk, message_text,
+    models::{self, Capabilities},
+    paste::{self, CodeFolder},
+    plugins, profile, prompt,
+    provider::ChatBackend,
+    queue::{Priority, QueuePermit, QueueTicket},
+    quoted, reporting, resolution,
+    responder::Responder,
+    storage::AnswerRecord,
+    supersede::Generation,
+    telemetry, titles,
+    tools::{self, Tools},
+    Data,
+};
+#[cfg(feature = "search")]
+use crate::{knowledge, search, starboard};
+
+/// Built-in instructions at the start of the system prompt
+// the word joiner before "v0.10.2 Not displaying album art" is as the prompt was written
+#[allow(clippy::invisible_characters)]
+pub const SYSTEM_MESSAGE: &str = r#"
 SYSTEM PROMPT:
 You are a concise and friendly assistant. You help people and answer questions, including questions about DeskThing and CarThing hacking. Answer user questions directly and keep responses under 1500 characters. Use markdown, bullet points, and short paragraphs for clarity.
 
@@ -194,7 +230,7 @@ Answering Guidelines:
 * DO NOT GIVE LINKS NOT EXPLICITLY GIVEN TO YOU.
 "#;
 
-async fn aoai_to_tiktoken(msg: ChatCompletionRequestMessage) -> TikChatMsg {
+fn aoai_to_tiktoken(msg: ChatCompletionRequestMessage) -> TikChatMsg {
     match msg {
         ChatCompletionRequestMessage::System(msg) => TikChatMsg {
             role: "system".to_string(),
@@ -204,6 +240,20 @@ async fn aoai_to_tiktoken(msg: ChatCompletionRequestMessage) -> TikChatMsg {
             },
             ..Default::default()
         },
+        ChatCompletionRequestMessage::Developer(msg) => TikChatMsg {
+            role: "developer".to_string(),
+            content: match msg.content {
+                ChatCompletionRequestDeveloperMessageContent::Text(text) => Some(text),
+                ChatCompletionRequestDeveloperMessageContent::Array(parts) => Some(
+                    parts
+                        .into_iter()
+                        .map(|part| part.text)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+            },
+            ..Default::default()
+        },
         ChatCompletionRequestMessage::User(msg) => TikChatMsg {
             role: "user".to_string(),
             content: match msg.content {
@@ -228,211 +278,1701 @@ async fn aoai_to_tiktoken(msg: ChatCompletionRequestMessage) -> TikChatMsg {
     }
 }
 
+/// Tokens in a message, counted with o1-mini's tokenizer (o200k) as a stand-in for whatever
+/// model we use. Includes the chat format's per-message and reply-priming overhead.
+pub fn count_tokens(msg: &ChatCompletionRequestMessage) -> usize {
+    let msg = aoai_to_tiktoken(msg.clone());
+    // building the tokenizer is slow, so share one
+    let bpe = tiktoken_rs::o200k_base_singleton();
+    let bpe = bpe.lock();
+    3 + bpe.encode_with_special_tokens(&msg.role).len()
+        + bpe
+            .encode_with_special_tokens(&msg.content.unwrap_or_default())
+            .len()
+        + 3
+}
+
+/// Added to instructions that don't use any of the variables themselves
+#[cfg(feature = "templates")]
+const PROMPT_SUFFIX: &str = "\n{{time}} You are {{bot_name}} (id: {{bot_id}}), \
+{% if guild_name %}in the {{guild_name}} server{% else %}in a direct message{% endif %}\
+{% if channel_prompt %}\n{{channel_prompt}}{% endif %}\
+{% if role_prompt %}\n{{role_prompt}}{% endif %}\
+{% if user_profile %}\n{{user_profile}}{% endif %}";
+
+/// What the system prompt can refer to, as in `{{guild_name}}`
+#[derive(Serialize)]
+pub struct PromptContext<'a> {
+    pub bot_name: &'a str,
+    pub bot_id: &'a str,
+    pub guild_name: &'a str,
+    pub channel_name: &'a str,
+    /// Model answering
+    pub active_model: &'a str,
+    /// IANA name like `Europe/Berlin`; `{{time}}` is the "time is" sentence in it
+    pub timezone: &'a str,
+    /// The guild's `channel_prompts` entry for the channel, or empty
+    pub channel_prompt: &'a str,
+    /// The guild's `role_prompts` entry for the asker, or empty
+    pub role_prompt: &'a str,
+    /// About the asker, for guilds with `user_profiles` on, or empty
+    pub user_profile: &'a str,
+}
+
+/// Names the instructions can use
+#[cfg(feature = "templates")]
+const PROMPT_VARIABLES: &[&str] = &[
+    "bot_name",
+    "bot_id",
+    "guild_name",
+    "channel_name",
+    "active_model",
+    "timezone",
+    "channel_prompt",
+    "role_prompt",
+    "user_profile",
+    "time",
+];
+
+/// The system prompt: `instructions`, rendered as a template. Instructions using
+/// none of the variables get who and where the model is, and what time it is,
+/// added at the end. Broken templates are used as they are.
+#[cfg(feature = "templates")]
+pub fn system_prompt(instructions: &str, context: &PromptContext) -> String {
+    let time = current_time(OffsetDateTime::now_utc(), context.timezone);
+    let vars = minijinja::context! { time, ..minijinja::Value::from_serialize(context) };
+    let mut env = minijinja::Environment::new();
+    // a misspelled variable is a mistake, not an empty string
+    env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+
+    let rendered = env.template_from_str(instructions).and_then(|template| {
+        let used = template.undeclared_variables(false);
+        let laid_out = PROMPT_VARIABLES.iter().any(|v| used.contains(*v));
+        Ok((template.render(&vars)?, laid_out))
+    });
+    let (prompt, laid_out) = match rendered {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            warn!("Failed to render the system prompt: {:#}", e);
+            (instructions.to_string(), false)
+        }
+    };
+    if laid_out {
+        return prompt;
+    }
+    prompt
+        + env
+            .render_str(PROMPT_SUFFIX, &vars)
+            .expect("failed to render the prompt suffix")
+            .as_str()
+}
+
+/// The system prompt in builds without `templates`: like the one rendered as
+/// a template, but the instructions can only fill in `{{variable}}`s
+#[cfg(not(feature = "templates"))]
+pub fn system_prompt(instructions: &str, context: &PromptContext) -> String {
+    let time = current_time(OffsetDateTime::now_utc(), context.timezone);
+    let mut vars = serde_json::to_value(context).expect("the prompt context is plain data");
+    vars["time"] = time.clone().into();
+    match fill_in(instructions, &vars) {
+        Some(prompt) if instructions.contains("{{") => return prompt,
+        Some(_) => {}
+        None => warn!("Failed to fill in the system prompt: it names an unknown variable"),
+    }
+
+    let mut prompt = format!(
+        "{}\n{} You are {} (id: {}), ",
+        instructions, time, context.bot_name, context.bot_id
+    );
+    if context.guild_name.is_empty() {
+        prompt.push_str("in a direct message");
+    } else {
+        prompt.push_str(&format!("in the {} server", context.guild_name));
+    }
+    for extra in [
+        context.channel_prompt,
+        context.role_prompt,
+        context.user_profile,
+    ] {
+        if !extra.is_empty() {
+            prompt.push('\n');
+            prompt.push_str(extra);
+        }
+    }
+    prompt
+}
+
+/// `template` with each `{{ name }}` filled in from `vars`, for builds without
+/// `templates`; `None` if it names anything `vars` doesn't have
+#[cfg(not(feature = "templates"))]
+fn fill_in(template: &str, vars: &serde_json::Value) -> Option<String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}")? + start;
+        filled.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..end].trim())? {
+            serde_json::Value::String(value) => filled.push_str(value),
+            value => filled.push_str(&value.to_string()),
+        }
+        rest = &rest[end + 2..];
+    }
+    filled.push_str(rest);
+    Some(filled)
+}
+
+/// The "time is" line of the system prompt. Unknown timezones are treated as UTC.
+fn current_time(now: OffsetDateTime, timezone: &str) -> String {
+    let format = time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    let tz = timezones::get_by_name(timezone).filter(|tz| tz.name() != "UTC");
+    let Some(tz) = tz else {
+        if !timezone.eq_ignore_ascii_case("UTC") {
+            warn!("Unknown timezone {}, using UTC", timezone);
+        }
+        return format!(
+            "The time is {} UTC.",
+            now.format(format).expect("failed to format time")
+        );
+    };
+
+    let offset = tz.get_offset_utc(&now);
+    format!(
+        "The time is {} {} ({}, UTC{}). Give dates and times in this timezone unless asked otherwise.",
+        now.to_timezone(tz)
+            .format(format)
+            .expect("failed to format time"),
+        offset.name(),
+        tz.name(),
+        offset
+            .to_utc()
+            .format(time::macros::format_description!(
+                "[offset_hour sign:mandatory]:[offset_minute]"
+            ))
+            .expect("failed to format offset"),
+    )
+}
+
+/// What a `footer` template can refer to, as in `{{model}}`
+#[derive(Serialize)]
+pub struct FooterContext<'a> {
+    /// Seconds spent generating the answer
+    pub elapsed: f64,
+    /// Seconds spent before asking the model: backfill, lookups, the queue
+    pub prep: f64,
+    /// Model that answered
+    pub model: &'a str,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub tokens: usize,
+    /// Links from the prompt the answer cites
+    pub sources: Vec<String>,
+}
+
+/// Longest a footer from a template gets, in characters, so one listing every
+/// source can't crowd out the answer
+pub const MAX_FOOTER: usize = 200;
+
+/// The line under an answer: the `footer` template if one's set, or the
+/// translated one. An empty template leaves the footer off.
+pub fn footer(template: Option<&str>, locale: &str, context: &FooterContext) -> String {
+    #[cfg(feature = "templates")]
+    if let Some(template) = template {
+        let mut env = minijinja::Environment::new();
+        env.set_undefined_behavior(minijinja::UndefinedBehavior::Strict);
+        match env.render_str(template, context) {
+            Ok(footer) => return fit_footer(footer.trim()),
+            Err(e) => warn!("Failed to render the footer: {:#}", e),
+        }
+    }
+    #[cfg(not(feature = "templates"))]
+    if let Some(template) = template {
+        let vars = serde_json::to_value(context).expect("the footer context is plain data");
+        match fill_in(template, &vars) {
+            Some(footer) => return fit_footer(footer.trim()),
+            None => warn!("Failed to fill in the footer: it names an unknown variable"),
+        }
+    }
+    i18n::tr(
+        locale,
+        "footer",
+        &[
+            ("elapsed", format!("{:.3}", context.elapsed).into()),
+            ("prep", format!("{:.3}", context.prep).into()),
+        ],
+    )
+}
+
+/// `footer` cut to `MAX_FOOTER` characters
+fn fit_footer(footer: &str) -> String {
+    if footer.chars().count() <= MAX_FOOTER {
+        return footer.to_string();
+    }
+    footer.chars().take(MAX_FOOTER - 1).collect::<String>() + "…"
+}
+
+/// Seconds to the millisecond, as footers show them
+fn round(seconds: f64) -> f64 {
+    (seconds * 1000.0).round() / 1000.0
+}
+
+/// The name of `msg`'s channel, the guild's extra instructions for it (or
+/// for the channel its thread is in), and whether its answers are posted in
+/// one piece
+async fn channel_settings<'a>(
+    ctx: &serenity::prelude::Context,
+    guild: Option<&'a GuildConfig>,
+    msg: &Message,
+) -> (String, &'a str, bool) {
+    let channel = msg.channel(ctx).await.ok().and_then(|c| c.guild());
+    let name = channel.as_ref().map(|c| c.name.clone()).unwrap_or_default();
+    let ids: Vec<_> = [Some(msg.channel_id), channel.and_then(|c| c.parent_id)]
+        .into_iter()
+        .flatten()
+        .collect();
+    let prompts = guild.map(|g| &g.channel_prompts);
+    let prompt = ids
+        .iter()
+        .find_map(|id| prompts?.get(&id.to_string()))
+        .map_or("", String::as_str);
+    let one_shot =
+        guild.is_some_and(|g| ids.iter().any(|id| g.one_shot_channels.contains(&id.get())));
+    (name, prompt, one_shot)
+}
+
+/// The guild's extra instructions for the asker: those for their highest role
+/// that has some, or for everyone (keyed by the guild id)
+fn role_prompt<'a>(
+    ctx: &serenity::prelude::Context,
+    guild: Option<&'a GuildConfig>,
+    msg: &Message,
+) -> &'a str {
+    let (Some(guild), Some(guild_id)) = (guild, msg.guild_id) else {
+        return "";
+    };
+    let roles: Vec<_> = match (&msg.member, msg.guild(&ctx.cache)) {
+        (Some(member), Some(cached)) => member
+            .roles
+            .iter()
+            .map(|id| (*id, cached.roles.get(id).map_or(0, |r| r.position)))
+            .collect(),
+        _ => Vec::new(),
+    };
+    pick_role_prompt(&guild.role_prompts, &roles, guild_id)
+}
+
+/// The prompt for the highest of `roles` (with their positions) that has one,
+/// or the one for everyone
+pub fn pick_role_prompt<'a>(
+    prompts: &'a HashMap<String, String>,
+    roles: &[(RoleId, u16)],
+    guild_id: GuildId,
+) -> &'a str {
+    roles
+        .iter()
+        .filter_map(|(id, position)| Some((position, prompts.get(&id.to_string())?)))
+        .max_by_key(|(position, _)| **position)
+        .map(|(_, prompt)| prompt)
+        .or_else(|| prompts.get(&guild_id.to_string()))
+        .map_or("", String::as_str)
+}
+
+/// How many tokens a prompt may use, from `AI_TOKEN_LIMIT`
+pub fn token_limit() -> usize {
+    env::var("AI_TOKEN_LIMIT").map_or(7000, |s| s.parse().unwrap())
+}
+
+/// Fits as much of the conversation as `token_limit` allows behind the
+/// system prompt. When it doesn't all fit, the least important messages go
+/// first, oldest first among equals; the newest message is kept, or nothing is.
+/// Also returns the prompt's token count.
+pub fn build_prompt(
+    system_prompt: String,
+    messages: &[ChatCompletionRequestMessage],
+    token_limit: usize,
+    pinned: &[String],
+) -> (Vec<ChatCompletionRequestMessage>, usize) {
+    let sys_msg = ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+        content: ChatCompletionRequestSystemMessageContent::Text(system_prompt),
+        ..Default::default()
+    });
+
+    info_span!("prompt.build").in_scope(|| {
+        let system_tokens = count_tokens(&sys_msg);
+        let tokens: Vec<_> = messages.iter().map(count_tokens).collect();
+        debug!(
+            "Current tokens: {}",
+            system_tokens + tokens.iter().sum::<usize>()
+        );
+        let first_question = messages.iter().position(|m| {
+            matches!(m, ChatCompletionRequestMessage::User(_))
+                && !message_text(m).starts_with(lurk::OVERHEARD)
+        });
+        let importance: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| importance(m, Some(i) == first_question, pinned))
+            .collect();
+        let (kept, current_tokens) = fit(system_tokens, &tokens, &importance, token_limit);
+
+        let mut final_messages = vec![sys_msg];
+        final_messages.extend(
+            messages
+                .iter()
+                .zip(kept)
+                .filter(|(_, kept)| *kept)
+                .map(|(m, _)| m.clone()),
+        );
+        (final_messages, current_tokens)
+    })
+}
+
+/// Which messages of a conversation fit in `token_limit` behind a system
+/// prompt of `system_tokens`, given each message's tokens and importance. The
+/// least important go first, oldest first among equals; the last message is
+/// kept, or nothing is. Also returns the tokens kept, the system prompt's
+/// included.
+pub fn fit(
+    system_tokens: usize,
+    tokens: &[usize],
+    importance: &[u8],
+    token_limit: usize,
+) -> (Vec<bool>, usize) {
+    let mut current_tokens = system_tokens + tokens.iter().sum::<usize>();
+    let mut kept = vec![true; tokens.len()];
+    if current_tokens <= token_limit {
+        return (kept, current_tokens);
+    }
+    let mut by_importance: Vec<_> = (0..tokens.len().saturating_sub(1)).collect();
+    by_importance.sort_by_key(|&i| (importance[i], i));
+    for i in by_importance {
+        if current_tokens <= token_limit {
+            break;
+        }
+        kept[i] = false;
+        current_tokens -= tokens[i];
+    }
+    if current_tokens > token_limit {
+        // not even the newest message fits
+        kept.fill(false);
+        current_tokens = system_tokens;
+    }
+    (kept, current_tokens)
+}
+
+/// Whether the whole conversation fits in `token_limit` behind the system prompt
+pub fn fits(
+    system_prompt: &str,
+    messages: &[ChatCompletionRequestMessage],
+    token_limit: usize,
+) -> bool {
+    let sys_msg = ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+        content: ChatCompletionRequestSystemMessageContent::Text(system_prompt.to_string()),
+        ..Default::default()
+    });
+    count_tokens(&sys_msg) + messages.iter().map(count_tokens).sum::<usize>() <= token_limit
+}
+
+/// How much a message is worth keeping when the conversation doesn't fit:
+/// the first question and pinned messages most, then answers with links or
+/// steps, then anything else, and short chatter like "thanks!" least
+fn importance(
+    message: &ChatCompletionRequestMessage,
+    first_question: bool,
+    pinned: &[String],
+) -> u8 {
+    let text = message_text(message);
+    if first_question
+        || pinned
+            .iter()
+            .any(|p| !p.is_empty() && text.contains(p.as_str()))
+    {
+        return 3;
+    }
+    if let ChatCompletionRequestMessage::Assistant(_) = message {
+        let has_steps = text.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("- ")
+                || line.starts_with("* ")
+                || line.starts_with("```")
+                || line
+                    .split_once(". ")
+                    .is_some_and(|(n, _)| n.parse::<u32>().is_ok())
+        });
+        if has_steps || text.contains("http://") || text.contains("https://") {
+            return 2;
+        }
+    }
+    // user lines are `name (id): text`; judge what they said
+    let said = match message {
+        ChatCompletionRequestMessage::User(_) => text
+            .split_once("): ")
+            .map_or(text.as_str(), |(_, said)| said),
+        _ => text.as_str(),
+    };
+    if said.split_whitespace().count() <= 3 {
+        0
+    } else {
+        1
+    }
+}
+
+/// What a message says, as plain text
+pub fn message_text(message: &ChatCompletionRequestMessage) -> String {
+    aoai_to_tiktoken(message.clone())
+        .content
+        .unwrap_or_default()
+}
+
+/// Adds `urls` as images to the last user message, for models that can see
+/// them. They're only sent with this request, not kept in the conversation.
+pub fn attach_images(messages: &mut [ChatCompletionRequestMessage], urls: &[String]) {
+    if urls.is_empty() {
+        return;
+    }
+    let Some(ChatCompletionRequestMessage::User(user)) = messages
+        .iter_mut()
+        .rev()
+        .find(|m| matches!(m, ChatCompletionRequestMessage::User(_)))
+    else {
+        return;
+    };
+    let ChatCompletionRequestUserMessageContent::Text(text) = &user.content else {
+        return;
+    };
+    let mut parts = vec![ChatCompletionRequestUserMessageContentPart::Text(
+        ChatCompletionRequestMessageContentPartText { text: text.clone() },
+    )];
+    parts.extend(urls.iter().map(|url| {
+        ChatCompletionRequestUserMessageContentPart::ImageUrl(
+            ChatCompletionRequestMessageContentPartImage {
+                image_url: ImageUrl {
+                    url: url.clone(),
+                    detail: Some(ImageDetail::Auto),
+                },
+            },
+        )
+    }));
+    user.content = ChatCompletionRequestUserMessageContent::Array(parts);
+}
+
+/// How long to wait for the usage a provider sends after an answer
+const USAGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The tokens a streamed answer used, from the chunk providers send after
+/// the last one when asked to
+async fn read_usage(
+    stream: &mut (impl futures::Stream<Item = Result<CreateChatCompletionStreamResponse, OpenAIError>>
+              + Unpin),
+) -> Option<CompletionUsage> {
+    let read = async {
+        while let Ok(Some(chunk)) = stream.try_next().await {
+            if chunk.usage.is_some() {
+                return chunk.usage;
+            }
+        }
+        None
+    };
+    tokio::time::timeout(USAGE_TIMEOUT, read)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Longest answer we ask for
+const MAX_TOKENS: u32 = 2800;
+/// Longest answer from a reasoning model, which counts its thinking too
+const REASONING_MAX_TOKENS: u32 = 25_000;
+
+/// A chat completion request for the prompt, shaped to what the model accepts
+pub fn chat_request(
+    model: &str,
+    capabilities: &Capabilities,
+    mut messages: Vec<ChatCompletionRequestMessage>,
+) -> CreateChatCompletionRequest {
+    if !capabilities.system_message {
+        for message in &mut messages {
+            if let ChatCompletionRequestMessage::System(system) = message {
+                let ChatCompletionRequestSystemMessageContent::Text(text) = &system.content else {
+                    continue;
+                };
+                *message = ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                    content: ChatCompletionRequestUserMessageContent::Text(text.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    let mut request = CreateChatCompletionRequest {
+        model: model.to_string(),
+        messages,
+        stream: Some(capabilities.streaming),
+        ..Default::default()
+    };
+    if capabilities.streaming && capabilities.stream_usage {
+        request.stream_options = Some(ChatCompletionStreamOptions {
+            include_usage: true,
+        });
+    }
+    if capabilities.reasoning {
+        request.max_completion_tokens = Some(REASONING_MAX_TOKENS);
+        request.reasoning_effort = capabilities.reasoning_effort.clone();
+    } else {
+        // plenty of OpenAI-compatible APIs don't know `max_completion_tokens` yet
+        #[allow(deprecated)]
+        {
+            request.max_tokens = Some(MAX_TOKENS);
+        }
+    }
+    request
+}
+
+/// Asks `model` to follow `instructions` on `input` and waits for the whole
+/// answer, for jobs nobody is watching stream in
+pub async fn complete(
+    backend: &dyn ChatBackend,
+    config: &Config,
+    model: &str,
+    instructions: &str,
+    input: &str,
+) -> Result<String, OpenAIError> {
+    complete_messages(backend, config, model, instructed(instructions, input)).await
+}
+
+/// `instructions` as the system message, then `input` from the user
+pub fn instructed(instructions: &str, input: &str) -> Vec<ChatCompletionRequestMessage> {
+    vec![
+        ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+            content: ChatCompletionRequestSystemMessageContent::Text(instructions.to_string()),
+            ..Default::default()
+        }),
+        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Text(input.to_string()),
+            ..Default::default()
+        }),
+    ]
+}
+
+/// Like `complete`, for a whole conversation
+pub async fn complete_messages(
+    backend: &dyn ChatBackend,
+    config: &Config,
+    model: &str,
+    messages: Vec<ChatCompletionRequestMessage>,
+) -> Result<String, OpenAIError> {
+    let request = chat_request(
+        models::resolve(config, model),
+        &Capabilities::of(config, model),
+        messages,
+    );
+    let mut stream = backend.create_stream(request).await?;
+    let mut text = String::new();
+    while let Some(chunk) = stream.try_next().await? {
+        let Some(choice) = chunk.choices.first() else {
+            continue;
+        };
+        if let Some(content) = &choice.delta.content {
+            text.push_str(content);
+        }
+        if choice.finish_reason.is_some() {
+            return Ok(text);
+        }
+    }
+    Err(OpenAIError::StreamError(
+        "the answer ended before it was finished".to_string(),
+    ))
+}
+
+/// Links answers may contain: those `instructions` give the model, plus whatever the owner allows
+pub fn allowed_links(
+    config: &Config,
+    guild_id: Option<GuildId>,
+    instructions: &str,
+) -> Vec<String> {
+    let mut allowed_links = links::prompt_links(instructions);
+    allowed_links.extend(config.allowed_links.iter().cloned());
+    if let Some(guild) = config.guild(guild_id) {
+        allowed_links.extend(guild.allowed_links.iter().cloned());
+    }
+    allowed_links
+}
+
+/// Keeps a question answered with a known fix, and the fix, in the
+/// conversation, so follow-ups to the model have them
+async fn remember_known(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    responder: &Responder,
+    msg: &serenity::model::channel::Message,
+    question: &str,
+    answer: &str,
+) {
+    if responder.is_dry_run() {
+        // nobody saw the fix, so it stays out of the conversation
+        info!("Dry run, would have answered {} with a known fix", msg.id);
+        return;
+    }
+    info!("Answered {} with a known fix", msg.id);
+    let name = msg
+        .author_nick(&ctx.http)
+        .await
+        .unwrap_or(msg.author.name.clone());
+    let messages = [
+        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Text(format!(
+                "{} ({}): {}",
+                name,
+                msg.author.id.get(),
+                question
+            )),
+            ..Default::default()
+        }),
+        ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+            content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                answer.to_string(),
+            )),
+            ..Default::default()
+        }),
+    ];
+    for message in messages {
+        if let Err(e) = data.ai_context.push(msg.channel_id, message).await {
+            warn!("Failed to save to the conversation: {}", e);
+        }
+    }
+}
+
+/// Counts an answer with a known fix like any other, with the `tokens` spent
+/// reading screenshots for it
+async fn record_known(
+    data: &Data,
+    responder: &Responder,
+    msg: &serenity::model::channel::Message,
+    model: &str,
+    tokens: usize,
+    latency: std::time::Duration,
+) {
+    telemetry::record_request(model, true, latency, None);
+    if !responder.is_dry_run() {
+        record_answer(data, usage_record(msg, model, tokens, latency)).await;
+    }
+}
+
+#[tracing::instrument(
+    name = "deskhelp.request",
+    skip_all,
+    fields(guild = ?batch[0].guild_id, channel = %batch[0].channel_id, model, variant, prompt_version)
+)]
 pub async fn process_message(
-    msg: serenity::model::channel::Message,
+    batch: Vec<serenity::model::channel::Message>,
     ctx: serenity::prelude::Context,
-    openai_client: &OpenAIClient<OpenAIConfig>,
-    ai_context: &Arc<Mutex<std::collections::HashMap<String, Vec<ChatCompletionRequestMessage>>>>,
+    data: &Data,
+    responder: Responder,
 ) {
-    let token_limit: usize = env::var("AI_TOKEN_LIMIT").map_or(7000, |s| s.parse().unwrap());
-    // Context window for llama 3.* series models
-    // I think Grok's actual context window that we can send is 7000 tokens
-    let context_window: usize =
-        env::var("AI_CONTEXT_WINDOW").map_or(128000, |s| s.parse().unwrap());
-    const UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
-    let ai_model: String =
-        std::env::var("AI_MODEL").unwrap_or("llama-3.2-11b-vision-preview".to_string());
-
-    let start_time = std::time::Instant::now();
+    // reply to the last message of the batch, but answer all of them
+    let msg = batch.last().expect("empty message batch").clone();
+    // a newer question from the same user takes over from one still being answered
+    let generation = data.in_flight.start(&msg);
+    let config = data.config();
+    let (provider, mut ai_model) = data.providers.for_guild(&config, msg.guild_id);
+    // one of several bots run from this process, with a persona of its own
+    let bot = data.bot.as_deref().and_then(|name| config.bot(name));
+    if let Some(model) = bot.and_then(|b| b.model.as_ref()) {
+        ai_model = model.clone();
+    }
+    let assignment = experiment::assign(&config);
+    let active_prompt = data.prompts.active();
+    let mut instructions = active_prompt.text.as_str();
+    let mut prompt_version = active_prompt.version.clone();
+    if let Some(assignment) = &assignment {
+        if let Some(model) = &assignment.variant.model {
+            ai_model = model.clone();
+        }
+        if let Some(system_prompt) = &assignment.variant.system_prompt {
+            instructions = system_prompt;
+            prompt_version = prompt::version_of(system_prompt);
+        }
+        tracing::Span::current().record("variant", assignment.variant.name.as_str());
+    }
+    // aliases like `fast` stand for a model in [models], with settings of their own
+    let capabilities = Capabilities::of(&config, &ai_model);
+    let ai_model = models::resolve(&config, &ai_model).to_string();
+    let locale = config.locale(msg.guild_id);
+    let guild_config = config.guild(msg.guild_id);
+    let content = batch
+        .iter()
+        .map(message_text::of)
+        .collect::<Vec<_>>()
+        .join("\n");
+    // triage channels get JSON for other bots and scripts instead of prose
+    #[cfg(feature = "triage")]
+    let triage = triage::config_for(&ctx, guild_config, &msg)
+        .await
+        .map(|t| (t, triage::schema(&t.categories)));
+    #[cfg(not(feature = "triage"))]
+    let triage: Option<(&TriageConfig, serde_json::Value)> = None;
 
+    // known errors get their fix straight away, without the model or its budget
+    if let (None, Some(known)) = (&triage, known_errors::find(&config.known_errors, &content)) {
+        let start_time = std::time::Instant::now();
+        match responder.send(&ctx.http, &known.answer).await {
+            Ok(_) => {
+                remember_known(&ctx, data, &responder, &msg, &content, &known.answer).await;
+                record_known(data, &responder, &msg, &ai_model, 0, start_time.elapsed()).await;
+            }
+            Err(e) => warn!("Failed to send message: {}", e),
+        }
+        return;
+    }
+    if let Some(guild_id) = msg.guild_id {
+        match budget::check(data, &config, guild_id).await {
+            Ok(Some(over)) => {
+                // said once a month, not under every question after
+                if budget::first_time_over(data.storage.as_ref(), guild_id, &over).await {
+                    let notice = i18n::tr(
+                        &locale,
+                        "token-cap-reached",
+                        &[("date", over.resets_at.date().to_string().into())],
+                    );
+                    if let Err(e) = responder.send(&ctx.http, &notice).await {
+                        warn!("Failed to send message: {}", e);
+                    }
+                    budget::notify_owners(&ctx.http, data, guild_id, &over).await;
+                }
+                return;
+            }
+            Ok(None) => {}
+            // answer anyway rather than lock everyone out over a storage hiccup
+            Err(e) => warn!("Failed to check the token budget: {}", e),
+        }
+    }
+    tracing::Span::current().record("model", ai_model.as_str());
+    tracing::Span::current().record("prompt_version", prompt_version.as_str());
     // Handle response streaming
-    let typing = ctx.http.start_typing(msg.channel_id);
+    let typing = (!responder.is_dry_run()).then(|| ctx.http.start_typing(msg.channel_id));
 
-    let mut sent_msg = msg
-        .reply(&ctx.http, "Generating response...")
+    let mut sent_msg = responder
+        .send(&ctx.http, &i18n::tr(&locale, "generating", &[]))
         .await
         .expect("failed to send message");
 
+    // wait for a free generation slot, keeping the user posted on their place in line
+    let priority = Priority::of(&ctx, guild_config, &msg).await;
+    let ticket = data.queue.join(priority);
+    let Some(_permit) = wait_in_line(
+        &ctx.http,
+        &responder,
+        &mut sent_msg,
+        ticket,
+        &generation,
+        &locale,
+    )
+    .await
+    else {
+        if let Some(typing) = typing {
+            typing.stop();
+        }
+        return;
+    };
+
+    let start_time = std::time::Instant::now();
+
+    // screenshots of errors, as text the conversation keeps, for models that can't see them
+    #[cfg(feature = "ocr")]
+    let ocr::Reading {
+        text: screenshots,
+        tokens: ocr_tokens,
+    } = match &config.ocr {
+        Some(ocr_config) if !capabilities.vision => {
+            ocr::read(data, &config, ocr_config, msg.guild_id, &batch).await
+        }
+        _ => ocr::Reading {
+            text: String::new(),
+            tokens: 0,
+        },
+    };
+    #[cfg(not(feature = "ocr"))]
+    let (screenshots, ocr_tokens) = (String::new(), 0);
+    if let (None, Some(known)) = (
+        &triage,
+        known_errors::find(&config.known_errors, &screenshots),
+    ) {
+        if let Err(e) = responder
+            .edit(&ctx.http, &mut sent_msg, &known.answer)
+            .await
+        {
+            warn!("Failed to edit message: {}", e);
+        }
+        if let Some(typing) = typing {
+            typing.stop();
+        }
+        let question = content.clone() + screenshots.as_str();
+        remember_known(&ctx, data, &responder, &msg, &question, &known.answer).await;
+        // the screenshots were still read with the guild's tokens
+        record_known(
+            data,
+            &responder,
+            &msg,
+            &ai_model,
+            ocr_tokens,
+            start_time.elapsed(),
+        )
+        .await;
+        return;
+    }
+
     // Create user message once
     let user_message = ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
         content: ChatCompletionRequestUserMessageContent::Text(format!(
-            "{} ({}): {}",
+            "{} ({}): {}{}",
             msg.author_nick(&ctx.http)
                 .await
                 .unwrap_or(msg.clone().author.name),
             msg.author.id.get(),
-            msg.content
+            content,
+            screenshots
         )),
         ..Default::default()
     });
 
-    // Update context more efficiently
-    let messages = {
-        let mut context = ai_context.lock().unwrap();
-        let channel_context = context.entry(msg.channel_id.to_string()).or_default();
-        channel_context.push(user_message);
-        channel_context.clone()
+    backfill::seed(&ctx, data, &config, &batch).await;
+    if let Some(overheard) = data.lurked.take(msg.channel_id) {
+        if let Err(e) = data.ai_context.push(msg.channel_id, overheard).await {
+            warn!("Failed to save the channel conversation: {}", e);
+        }
+    }
+    let messages = match data
+        .ai_context
+        .push(msg.channel_id, user_message.clone())
+        .await
+    {
+        Ok(messages) => messages,
+        Err(e) => {
+            // answer without the earlier conversation rather than not at all
+            warn!("Failed to save to the conversation: {}", e);
+            vec![user_message.clone()]
+        }
     };
 
     // get id and nickname of myself
     let self_id = ctx.cache.current_user().id.to_string();
     let self_nickname = ctx.cache.current_user().name.clone();
-    let msg_server = msg.guild(&ctx.cache).unwrap().name.clone();
-
-    let system_message_end = format!(
-        "\nThe time is {}. You are {} (id: {}), in the {} server",
-        OffsetDateTime::now_utc()
-            .format(time::macros::format_description!(
-                "[year]-[month]-[day] [hour]:[minute]:[second]"
-            ))
-            .expect("failed to format time"),
-        self_nickname,
-        self_id,
-        msg_server
-    );
+    // empty in direct messages, and for guilds not cached yet
+    let msg_server = msg
+        .guild(&ctx.cache)
+        .map(|guild| guild.name.clone())
+        .unwrap_or_default();
 
-    // Create system message once
-    let sys_msg = ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
-        content: ChatCompletionRequestSystemMessageContent::Text(
-            SYSTEM_MESSAGE.to_string() + system_message_end.as_str(),
-        ),
-        ..Default::default()
-    });
+    let tools = if capabilities.tools {
+        let answer = plugins::Answer {
+            ctx: &ctx,
+            data,
+            config: &config,
+            msg: &msg,
+            for_people: triage.is_none() && !responder.is_dry_run(),
+        };
+        plugins::tools(&data.plugins, &answer).await
+    } else {
+        Tools::default()
+    };
 
-    // Token counting and context building
-    let mut final_messages = vec![];
-    // get_chat_completion_max_tokens responds with the *remaining context length*
-    let max_tokens =
-        get_chat_completion_max_tokens("o1-mini", &[aoai_to_tiktoken(sys_msg.clone()).await])
-            .expect("failed to get token count");
-    println!("Max tokens: {}", max_tokens);
-    let sys_tokens = context_window - max_tokens;
-    let mut current_tokens = sys_tokens;
-
-    println!("Current tokens: {}", current_tokens);
-
-    // Process messages in reverse order more efficiently
-    for msg in messages.iter().rev() {
-        let msg_tokens = context_window
-            - get_chat_completion_max_tokens("o1-mini", &[aoai_to_tiktoken(msg.clone()).await])
-                .expect("failed to get token count");
-        if current_tokens + msg_tokens > token_limit {
-            break;
+    // point to an earlier answer to the same question, and keep this one for later
+    let duplicates = match (&config.duplicates, msg.guild_id, &triage) {
+        (Some(duplicates_config), Some(guild_id), None) => {
+            match duplicates::lookup(
+                data.storage.as_ref(),
+                provider,
+                duplicates_config,
+                guild_id,
+                msg.channel_id,
+                &content,
+            )
+            .await
+            {
+                Ok(lookup) => Some((duplicates_config, guild_id, lookup)),
+                Err(e) => {
+                    warn!("Failed to look for similar questions: {}", e);
+                    None
+                }
+            }
         }
+        _ => None,
+    };
+    let earlier_link = duplicates
+        .as_ref()
+        .and_then(|(_, _, lookup)| lookup.earlier.as_ref())
+        .map(duplicates::link);
 
-        final_messages.push(msg.clone());
-        current_tokens += msg_tokens;
-    }
+    // answers people vouched for on similar questions, for the model to go by
+    #[cfg(feature = "search")]
+    let vouched = match (&config.starboard, &config.search, msg.guild_id, &triage) {
+        (Some(_), Some(search_config), Some(guild_id), None) => starboard::retrieve(
+            data.storage.as_ref(),
+            provider,
+            search_config,
+            guild_id,
+            &content,
+            // answers from channels the asker can't read stay out of the prompt
+            |channel_id| quoted::can_read(&ctx, &msg, channel_id),
+        )
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to look up vouched-for answers: {}", e);
+            vec![]
+        }),
+        _ => vec![],
+    };
 
-    final_messages.push(sys_msg);
+    // knowledge base articles pushed through the API
+    #[cfg(feature = "search")]
+    let (documents, kb_version) = match (&config.search, msg.guild_id, &triage) {
+        (Some(search_config), Some(guild_id), None) => knowledge::retrieve(
+            data.storage.as_ref(),
+            provider,
+            search_config,
+            guild_id,
+            &content,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to look up documents: {}", e);
+            (vec![], None)
+        }),
+        _ => (vec![], None),
+    };
+    #[cfg(not(feature = "search"))]
+    let kb_version = None;
 
-    final_messages.reverse();
+    // what "why does this happen?" is about
+    let quoted = quoted::gather(&ctx, &batch).await;
 
-    // Create chat completion request
-    let request = CreateChatCompletionRequest {
-        model: ai_model,
-        messages: final_messages,
-        max_tokens: Some(2800),
-        stream: Some(true),
-        ..Default::default()
+    let mut allowed_links = allowed_links(&config, msg.guild_id, instructions);
+    allowed_links.extend(quoted.iter().map(|q| q.link.clone()));
+    allowed_links.extend(tools.links());
+    allowed_links.extend(earlier_link.clone());
+    #[cfg(feature = "search")]
+    {
+        allowed_links.extend(vouched.iter().map(search::link));
+        allowed_links.extend(documents.iter().filter_map(|d| d.url.clone()));
+    }
+    let policy = content_policy::Policy::new(&config, msg.guild_id, &locale, allowed_links);
+    let render = |text: &str| policy.render(text);
+
+    let (channel_name, channel_prompt, one_shot) = channel_settings(&ctx, guild_config, &msg).await;
+    let user_profile = profile::of(&ctx, data, guild_config, &msg, responder.is_dry_run()).await;
+    let mut system = system_prompt(
+        instructions,
+        &PromptContext {
+            bot_name: &self_nickname,
+            bot_id: &self_id,
+            guild_name: &msg_server,
+            channel_name: &channel_name,
+            active_model: &ai_model,
+            timezone: &config.timezone(msg.guild_id),
+            channel_prompt,
+            role_prompt: role_prompt(&ctx, guild_config, &msg),
+            user_profile: &user_profile,
+        },
+    );
+    if let Some(prompt) = bot.and_then(|b| b.prompt.as_deref()) {
+        system.push_str("\n\n");
+        system.push_str(prompt);
+    }
+    #[cfg(feature = "triage")]
+    if let Some((_, schema)) = &triage {
+        system.push_str(&triage::instructions(schema));
+    }
+    #[cfg(feature = "search")]
+    {
+        system.push_str(&starboard::instructions(&vouched));
+        system.push_str(&knowledge::instructions(&documents));
+    }
+    system.push_str(&quoted::instructions(&quoted));
+    // triage answers are JSON for bots, not people
+    if triage.is_none() {
+        let language = language::for_question(data.storage.as_ref(), msg.author.id, &content).await;
+        system.push_str(&language::instructions(language.as_deref()));
+    }
+    // an operator's script may rewrite the prompt, or route it to another model
+    #[cfg(feature = "scripting")]
+    let (ai_model, capabilities, tools) = match &data.scripts {
+        Some(scripts) => {
+            let mut model = ai_model.clone();
+            scripts.on_prompt_built(&msg, &mut system, &mut model);
+            if model == ai_model {
+                (ai_model, capabilities, tools)
+            } else {
+                info!("A script routed the answer to {}", model);
+                let capabilities = Capabilities::of(&config, &model);
+                let tools = if capabilities.tools {
+                    tools
+                } else {
+                    Tools::default()
+                };
+                tracing::Span::current().record("model", model.as_str());
+                (
+                    models::resolve(&config, &model).to_string(),
+                    capabilities,
+                    tools,
+                )
+            }
+        }
+        None => (ai_model, capabilities, tools),
+    };
+    let token_limit = capabilities.context_tokens.unwrap_or_else(token_limit);
+    // pinned messages are kept over others, so look them up once something has to go
+    let pinned = if fits(&system, &messages, token_limit) {
+        vec![]
+    } else {
+        match msg.channel_id.pins(&ctx.http).await {
+            Ok(pins) => pins.into_iter().map(|m| m.content).collect(),
+            Err(e) => {
+                warn!("Failed to get pinned messages: {}", e);
+                vec![]
+            }
+        }
     };
+    let (mut final_messages, mut prompt_tokens) =
+        build_prompt(system, &messages, token_limit, &pinned);
+    if capabilities.vision {
+        let images: Vec<_> = message_text::images(&batch)
+            .into_iter()
+            .map(|a| a.url.clone())
+            .collect();
+        attach_images(&mut final_messages, &images);
+    }
+    #[cfg_attr(not(feature = "triage"), allow(unused_mut))]
+    let mut request = chat_request(&ai_model, &capabilities, final_messages);
+    #[cfg(feature = "triage")]
+    if let Some((triage_config, schema)) = &triage {
+        if triage_config.enforce_schema {
+            request.response_format = Some(triage::response_format(schema));
+        }
+    }
 
     let prep_time = start_time.elapsed().as_secs_f64();
 
-    let mut stream = openai_client
-        .chat()
-        .create_stream(request)
+    // dry runs and DMs don't count
+    let keep_text = guild_config.is_some_and(|g| g.support_digest_channel.is_some());
+    let answer_record = |ok, prompt_tokens, completion_tokens, message_id, answer: Option<&str>| {
+        Some(AnswerRecord {
+            guild_id: msg.guild_id.filter(|_| !responder.is_dry_run())?,
+            at: OffsetDateTime::now_utc(),
+            ok,
+            latency: start_time.elapsed(),
+            // reading screenshots counts too
+            prompt_tokens: prompt_tokens + ocr_tokens,
+            completion_tokens,
+            message_id,
+            experiment: assignment.as_ref().map(|a| a.experiment.to_string()),
+            variant: assignment.as_ref().map(|a| a.variant.name.clone()),
+            prompt_version: Some(prompt_version.clone()),
+            question: keep_text.then(|| content.clone()),
+            answer: answer.filter(|_| keep_text).map(str::to_string),
+            user_id: keep_text.then_some(msg.author.id),
+            channel_id: Some(msg.channel_id),
+            model: Some(ai_model.clone()),
+            kb_version: kb_version.clone(),
+        })
+    };
+
+    let error_context = reporting::ErrorContext {
+        guild_id: msg.guild_id,
+        channel_id: msg.channel_id,
+        model: &ai_model,
+    };
+    let stream_span = info_span!("provider.stream");
+    let mut stream = match tools::create_stream(provider, request, &tools)
+        .instrument(stream_span.clone())
         .await
-        .expect("failed to create stream");
-
-    let mut response = String::with_capacity(2000); // Pre-allocate string capacity
-    let mut total_response = String::with_capacity(2000);
-    let mut since_last_update = String::with_capacity(2000);
-    let mut has_three_tick_backs = false;
-    let mut last_update = std::time::Instant::now();
-
-    while let Ok(result) = stream.try_next().await {
-        match result {
-            Some(chunk) => {
-                if let Some(content) = chunk.choices[0].delta.content.clone() {
-                    response.push_str(&content);
-                    total_response.push_str(&content);
-                    since_last_update.push_str(&content);
-
-                    if last_update.elapsed() >= UPDATE_INTERVAL {
-                        last_update = std::time::Instant::now();
-                        if since_last_update.contains("```") {
-                            has_three_tick_backs = !has_three_tick_backs;
-                        }
-                        let builder = EditMessage::new().content(&response).suppress_embeds(true);
-                        if let Err(e) = sent_msg.edit(&ctx.http, builder).await {
-                            // send a new message with the rest of the response
-                            sent_msg = msg
-                                .reply(&ctx.http, "Continuing response...")
-                                .await
-                                .expect("failed to send message");
-                            // we don't need the previous tokens anymore
-                            response = since_last_update;
-                            let builder =
-                                EditMessage::new().content(&response).suppress_embeds(true);
-                            if let Err(e) = sent_msg.edit(&ctx.http, builder).await {
-                                eprintln!("Failed to edit message: {}", e);
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("Failed to create stream: {}", e);
+            reporting::capture(
+                &format!("Failed to create stream: {}", e),
+                Some(&error_context),
+            );
+            if let Err(e) = responder
+                .edit(
+                    &ctx.http,
+                    &mut sent_msg,
+                    &i18n::tr(&locale, "generation-error", &[]),
+                )
+                .await
+            {
+                error!("Failed to edit error message: {}", e);
+            }
+            telemetry::record_request(&ai_model, false, start_time.elapsed(), None);
+            record_answer(data, answer_record(false, 0, 0, None, None)).await;
+            if let Some(typing) = typing {
+                typing.stop();
+            }
+            return;
+        }
+    };
+
+    let mut assembler = ResponseAssembler::new(render, Pace::Adaptive);
+    // models that can't stream send the answer in one piece anyway, and
+    // answers are checked for blocked topics before anyone sees them
+    let checks_topics = policy.checks_topics() && triage.is_none();
+    // nor would it do to show an answer a script is about to rewrite
+    #[cfg(feature = "scripting")]
+    let rewrites = triage.is_none()
+        && data
+            .scripts
+            .as_ref()
+            .is_some_and(|s| s.has("on_response_ready"));
+    #[cfg(not(feature = "scripting"))]
+    let rewrites = false;
+    if one_shot || !capabilities.streaming || checks_topics || rewrites {
+        assembler = assembler.deferred();
+    }
+    if let Some(link) = &earlier_link {
+        assembler = assembler.with_header(&i18n::tr(
+            &locale,
+            "similar-question",
+            &[("link", link.as_str().into())],
+        ));
+    }
+    // JSON triage answers are posted whole
+    let fold_lines = config
+        .paste
+        .as_ref()
+        .filter(|_| triage.is_none())
+        .map(|p| p.max_lines);
+    let mut live = LiveAnswer {
+        folder: CodeFolder::new(fold_lines, &locale),
+        assembler,
+        // half-written JSON is no use to anyone
+        partial: triage.is_none(),
+    };
+    let answer_start = (sent_msg.channel_id, sent_msg.id);
+    let mut finished = false;
+    let mut completion_tokens = 0;
+    let mut blocked = false;
+
+    let streamed = stream_answer(
+        &ctx.http,
+        &responder,
+        &mut sent_msg,
+        &mut stream,
+        &generation,
+        &mut live,
+    )
+    .instrument(stream_span.clone())
+    .await;
+    let first_token = streamed.first_token.map(|at| at - start_time);
+    let mut usage = streamed.usage;
+    match streamed.end {
+        StreamEnd::Superseded => {
+            // dropping the stream stops the generation, and its tokens
+            drop(stream);
+            note_superseded(&ctx.http, &responder, &mut sent_msg, &locale).await;
+            // the newer question is answered instead, so this one isn't left hanging
+            let asked = user_message;
+            let dropped = data
+                .ai_context
+                .update(msg.channel_id, &move |messages| match messages
+                    .iter()
+                    .rposition(|m| *m == asked)
+                {
+                    Some(i) => {
+                        messages.remove(i);
+                        1
+                    }
+                    None => 0,
+                })
+                .await;
+            if let Err(e) = dropped {
+                warn!("Failed to drop the question from the conversation: {}", e);
+            }
+        }
+        StreamEnd::Cut => {
+            error!("Error while streaming response!");
+            reporting::capture("Error while streaming response", Some(&error_context));
+            let error_msg = i18n::tr(&locale, "generation-error", &[]);
+            if let Err(e) = responder.edit(&ctx.http, &mut sent_msg, &error_msg).await {
+                error!("Failed to edit error message: {}", e);
+            }
+        }
+        StreamEnd::Failed => {}
+        StreamEnd::Finished => {
+            // whether the answer went out, so one that didn't is recorded as failed
+            finished = async {
+                let elapsed = start_time.elapsed().as_secs_f64();
+                let answer = live.folder.text().to_string();
+                #[cfg(feature = "scripting")]
+                let rewritten = data
+                    .scripts
+                    .as_ref()
+                    .filter(|_| rewrites)
+                    .and_then(|s| s.on_response_ready(&msg, &answer, &ai_model));
+                #[cfg(not(feature = "scripting"))]
+                let rewritten: Option<String> = None;
+                let answer = rewritten.clone().unwrap_or(answer);
+                let assistant_message = ChatCompletionRequestMessage::Assistant(
+                    ChatCompletionRequestAssistantMessage {
+                        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                            answer.clone(),
+                        )),
+                        ..Default::default()
+                    },
+                );
+                if usage.is_none() && capabilities.stream_usage {
+                    usage = read_usage(&mut stream).await;
+                }
+                match &usage {
+                    Some(usage) => {
+                        prompt_tokens = usage.prompt_tokens as usize;
+                        completion_tokens = usage.completion_tokens as usize;
+                    }
+                    // providers that don't say get our own count
+                    None => completion_tokens = count_tokens(&assistant_message),
+                }
+                let footer = footer(
+                    config.footer(msg.guild_id).as_deref(),
+                    &locale,
+                    &FooterContext {
+                        elapsed: round(elapsed - prep_time),
+                        prep: round(prep_time),
+                        model: &ai_model,
+                        prompt_tokens,
+                        completion_tokens,
+                        tokens: prompt_tokens + completion_tokens,
+                        sources: links::prompt_links(&render(&answer)),
+                    },
+                );
+                // triage answers are held to the policy as much as prose
+                blocked = policy.check(&answer).is_err();
+                // long code blocks were cut short; share them whole
+                let (code_links, code_files) = match &config.paste {
+                    Some(paste_config) if !responder.is_dry_run() && !blocked => {
+                        paste::share(&data.http, paste_config, &locale, live.folder.folded()).await
+                    }
+                    _ => (vec![], vec![]),
+                };
+                match &triage {
+                    #[cfg(feature = "triage")]
+                    Some((triage_config, schema)) if !blocked => {
+                        let mut result = match triage::parse(&answer, schema) {
+                            Ok(result) => result,
+                            Err(e) => {
+                                warn!("Answer doesn't match the triage schema: {}", e);
+                                let error_msg = i18n::tr(&locale, "triage-invalid", &[]);
+                                if let Err(e) =
+                                    responder.edit(&ctx.http, &mut sent_msg, &error_msg).await
+                                {
+                                    error!("Failed to edit error message: {}", e);
+                                }
+                                return false;
                             }
+                        };
+                        result.screen(&policy);
+                        let mut posted = ResponseAssembler::new(
+                            str::to_string,
+                            Pace::Fixed(std::time::Duration::ZERO),
+                        );
+                        let mut actions = posted.push(&result.render(), std::time::Instant::now());
+                        actions.extend(posted.finish(&footer));
+                        apply(&ctx.http, &responder, &mut sent_msg, actions).await;
+                        if triage_config.tag_posts && !responder.is_dry_run() {
+                            triage::tag_post(&ctx, &msg, &result).await;
                         }
-                        since_last_update = "".to_string();
+                    }
+                    _ if blocked => {
+                        let mut posted = ResponseAssembler::new(
+                            str::to_string,
+                            Pace::Fixed(std::time::Duration::ZERO),
+                        );
+                        let notice = i18n::tr(&locale, "policy-topic", &[]);
+                        let mut actions = posted.push(&notice, std::time::Instant::now());
+                        actions.extend(posted.finish(&footer));
+                        apply(&ctx.http, &responder, &mut sent_msg, actions).await;
+                    }
+                    _ => {
+                        let footer = code_links
+                            .into_iter()
+                            .chain(Some(footer).filter(|f| !f.is_empty()))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        let actions = match &rewritten {
+                            Some(rewritten) => {
+                                let mut posted = ResponseAssembler::new(
+                                    render,
+                                    Pace::Fixed(std::time::Duration::ZERO),
+                                );
+                                if let Some(link) = &earlier_link {
+                                    posted = posted.with_header(&i18n::tr(
+                                        &locale,
+                                        "similar-question",
+                                        &[("link", link.as_str().into())],
+                                    ));
+                                }
+                                let mut actions = posted.push(rewritten, std::time::Instant::now());
+                                actions.extend(posted.finish(&footer));
+                                actions
+                            }
+                            None => live.assembler.finish(&footer),
+                        };
+                        apply(&ctx.http, &responder, &mut sent_msg, actions).await;
                     }
                 }
 
-                if chunk.choices[0].finish_reason.is_some() {
-                    let elapsed = start_time.elapsed().as_secs_f64();
-                    let final_response = format!(
-                        "{}\n-# Generated response in {:.3}s ({:.3}s prep). There may be [inaccuracies in AI output](<https://lib.guides.umd.edu/c.php?g=1340355&p=9880574>). Check important info.",
-                        response, elapsed - prep_time, prep_time
-                    );
+                // Discord doesn't render math, so attach images of any display blocks
+                let mut files = code_files;
+                if let (Some(latex_config), None, false) = (&config.latex, &triage, blocked) {
+                    let blocks = latex::extract_blocks(&answer);
+                    if !blocks.is_empty() {
+                        files.extend(latex::render(&data.http, latex_config, &blocks).await);
+                    }
+                }
+                if !files.is_empty() {
+                    if let Err(e) = responder.attach(&ctx.http, &mut sent_msg, files).await {
+                        warn!("Failed to attach files to the answer: {}", e);
+                    }
+                }
 
-                    let builder = EditMessage::new()
-                        .content(&final_response)
-                        .suppress_embeds(true);
-                    if let Err(e) = sent_msg.edit(&ctx.http, builder).await {
-                        // send a new message with the rest of the response
-                        sent_msg = msg
-                            .reply(&ctx.http, "Continuing response...")
-                            .await
-                            .expect("failed to send message");
-                        // we don't need the previous tokens anymore
-                        response = since_last_update;
-                        let builder = EditMessage::new().content(&response).suppress_embeds(true);
-                        if let Err(e) = sent_msg.edit(&ctx.http, builder).await {
-                            eprintln!("Failed to edit message: {}", e);
-                        }
+                // let the asker hand over to a human
+                if guild_config.is_some_and(|g| g.escalate) && triage.is_none() {
+                    let button = escalate::button(&locale);
+                    if let Err(e) = responder
+                        .components(&ctx.http, &mut sent_msg, vec![button])
+                        .await
+                    {
+                        warn!("Failed to add the escalation button: {}", e);
                     }
+                }
 
-                    let mut context = ai_context.lock().unwrap();
-                    let channel_context = context.entry(msg.channel_id.to_string()).or_default();
-                    channel_context.push(ChatCompletionRequestMessage::Assistant(
-                        ChatCompletionRequestAssistantMessage {
-                            content: Some(ChatCompletionRequestAssistantMessageContent::Text(
-                                total_response,
-                            )),
-                            ..Default::default()
-                        },
-                    ));
-                    break;
+                // invite feedback to compare the variants by
+                if assignment.is_some() {
+                    for emoji in [experiment::THUMBS_UP, experiment::THUMBS_DOWN] {
+                        if let Err(e) = responder.react(&ctx.http, &sent_msg, emoji).await {
+                            warn!("Failed to add feedback reaction: {}", e);
+                        }
+                    }
                 }
-            }
-            None => {
-                eprintln!("Error while streaming response!");
-                let error_msg = "Error generating response!";
-                if let Err(e) = sent_msg
-                    .edit(&ctx.http, EditMessage::new().content(error_msg))
+
+                if responder.is_dry_run() {
+                    // nobody saw this answer, so it stays out of the conversation
+                    info!(
+                        prompt_tokens,
+                        completion_tokens,
+                        sources = ?links::prompt_links(&render(&answer)),
+                        "Dry run, would have answered: {}",
+                        render(&answer)
+                    );
+                } else if blocked {
+                    // a replaced one stays out of it too
+                } else if let Err(e) = data
+                    .ai_context
+                    .push(msg.channel_id, assistant_message)
                     .await
                 {
-                    eprintln!("Failed to edit error message: {}", e);
+                    warn!("Failed to save the answer to the conversation: {}", e);
                 }
-                break;
+                true
             }
+            .instrument(stream_span)
+            .await;
+        }
+    }
+
+    telemetry::record_request(&ai_model, finished, start_time.elapsed(), first_token);
+    let message_id = finished.then_some(sent_msg.id);
+    // what's kept of the answer is what could be posted of it
+    let kept = (finished && !blocked).then(|| policy.render(live.folder.text()));
+    let record = answer_record(
+        finished,
+        prompt_tokens,
+        completion_tokens,
+        message_id,
+        kept.as_deref(),
+    );
+    if let (Some(record), Some(kept)) = (&record, &kept) {
+        let activity = Activity {
+            bot: data.bot.clone(),
+            user_id: Some(msg.author.id),
+            ..Activity::new("discord", record, &content, kept)
+        };
+        activity::post(&data.http, &config, activity.clone());
+        events::publish(Event::AnswerSent(activity));
+    }
+    record_answer(data, record).await;
+
+    if let Some(typing) = typing {
+        typing.stop();
+    }
+
+    if let (true, false, Some((duplicates_config, guild_id, lookup))) =
+        (finished && !blocked, responder.is_dry_run(), duplicates)
+    {
+        if let Err(e) = duplicates::remember(
+            data.storage.as_ref(),
+            duplicates_config,
+            guild_id,
+            answer_start,
+            lookup.embedding,
+        )
+        .await
+        {
+            warn!("Failed to save the question's embedding: {}", e);
+        }
+    }
+
+    #[cfg(feature = "search")]
+    if let (Some(kept), false, None, Some(search_config), Some(guild_id)) = (
+        &kept,
+        responder.is_dry_run(),
+        &triage,
+        &config.search,
+        msg.guild_id,
+    ) {
+        if let Err(e) = search::index(
+            data.storage.as_ref(),
+            provider,
+            search_config,
+            guild_id,
+            answer_start,
+            (msg.author.id, &content),
+            kept,
+        )
+        .await
+        {
+            warn!("Failed to index the answer for search: {}", e);
+        }
+    }
+
+    if finished && triage.is_none() && !responder.is_dry_run() {
+        if let Err(e) = resolution::schedule(&ctx, data, &msg, answer_start.1, sent_msg.id).await {
+            warn!(
+                "Failed to schedule asking whether the answer solved it: {}",
+                e
+            );
+        }
+    }
+
+    // triage channels tag by their own answer
+    if finished && triage.is_none() && !responder.is_dry_run() {
+        forum_tags::classify_post(&ctx, data, &batch[0]).await;
+        // threads started on an answer while it was being written are named after the placeholder
+        if let Ok(Channel::Guild(channel)) = msg.channel(&ctx).await {
+            titles::retitle(&ctx, data, &channel, &content).await;
         }
     }
+}
+
+/// A record of `tokens` spent on `msg` without the model writing the answer,
+/// so they count against the guild's cap. DMs don't count.
+fn usage_record(
+    msg: &Message,
+    model: &str,
+    tokens: usize,
+    latency: std::time::Duration,
+) -> Option<AnswerRecord> {
+    Some(AnswerRecord {
+        guild_id: msg.guild_id?,
+        at: OffsetDateTime::now_utc(),
+        ok: true,
+        latency,
+        prompt_tokens: tokens,
+        completion_tokens: 0,
+        message_id: None,
+        experiment: None,
+        variant: None,
+        prompt_version: None,
+        question: None,
+        answer: None,
+        user_id: None,
+        channel_id: Some(msg.channel_id),
+        model: Some(model.to_string()),
+        kb_version: None,
+    })
+}
+
+/// Saves a record of the question for `/stats` and experiment results
+pub(crate) async fn record_answer(data: &Data, record: Option<AnswerRecord>) {
+    let Some(record) = record else {
+        return;
+    };
+    if let Err(e) = data.storage.record_answer(&record).await {
+        warn!("Failed to record answer stats: {}", e);
+    }
+}
 
-    typing.stop();
+/// Waits for a free generation slot, keeping the user posted on their place
+/// in line in `sent`. `None` if a newer question took over meanwhile.
+pub async fn wait_in_line(
+    http: &Http,
+    responder: &Responder,
+    sent: &mut Message,
+    mut ticket: QueueTicket,
+    generation: &Generation,
+    locale: &str,
+) -> Option<QueuePermit> {
+    let mut last_position = None;
+    let permit = loop {
+        match ticket.try_start() {
+            Ok(permit) => break permit,
+            Err((position, estimated_wait)) => {
+                if last_position != Some(position) {
+                    last_position = Some(position);
+                    let waiting = i18n::tr(
+                        locale,
+                        "waiting-in-line",
+                        &[
+                            ("position", position.into()),
+                            ("seconds", estimated_wait.as_secs().into()),
+                        ],
+                    );
+                    if let Err(e) = responder.edit(http, sent, &waiting).await {
+                        warn!("Failed to edit message: {}", e);
+                    }
+                }
+                tokio::select! {
+                    _ = ticket.changed() => {}
+                    _ = generation.superseded() => {
+                        note_superseded(http, responder, sent, locale).await;
+                        return None;
+                    }
+                }
+            }
+        }
+    };
+    if last_position.is_some() {
+        if let Err(e) = responder
+            .edit(http, sent, &i18n::tr(locale, "generating", &[]))
+            .await
+        {
+            warn!("Failed to edit message: {}", e);
+        }
+    }
+    Some(permit)
+}
+
+/// An answer as people see it while it streams in
+pub struct LiveAnswer<R> {
+    /// Cuts long code blocks short
+    pub folder: CodeFolder,
+    /// Splits it into messages and paces the edits
+    pub assembler: ResponseAssembler<R>,
+    /// Whether it's posted as it comes in, rather than only once it's whole
+    pub partial: bool,
+}
+
+/// How streaming an answer ended
+#[derive(Debug, PartialEq)]
+pub enum StreamEnd {
+    /// The model finished, and the whole answer is posted
+    Finished,
+    /// A newer question from the same user took over
+    Superseded,
+    /// The stream ended before the model finished
+    Cut,
+    /// The provider failed partway through
+    Failed,
+}
+
+/// What streaming an answer came to
+pub struct Streamed {
+    pub end: StreamEnd,
+    /// Tokens used, if the provider said before it finished
+    pub usage: Option<CompletionUsage>,
+    /// When the first words came in
+    pub first_token: Option<std::time::Instant>,
+}
+
+/// Posts the answer to `sent` as `stream` brings it in, until the model
+/// finishes or a newer question takes over
+pub async fn stream_answer<R: Fn(&str) -> String>(
+    http: &Http,
+    responder: &Responder,
+    sent: &mut Message,
+    stream: &mut (impl futures::Stream<Item = Result<CreateChatCompletionStreamResponse, OpenAIError>>
+              + Unpin),
+    generation: &Generation,
+    answer: &mut LiveAnswer<R>,
+) -> Streamed {
+    let mut streamed = Streamed {
+        end: StreamEnd::Failed,
+        usage: None,
+        first_token: None,
+    };
+    loop {
+        let result = tokio::select! {
+            result = stream.try_next() => result,
+            _ = generation.superseded() => {
+                streamed.end = StreamEnd::Superseded;
+                return streamed;
+            }
+        };
+        let Ok(result) = result else {
+            return streamed;
+        };
+        let Some(chunk) = result else {
+            streamed.end = StreamEnd::Cut;
+            return streamed;
+        };
+
+        if chunk.usage.is_some() {
+            streamed.usage = chunk.usage.clone();
+        }
+        let Some(choice) = chunk.choices.first() else {
+            continue;
+        };
+        if let Some(content) = &choice.delta.content {
+            let now = std::time::Instant::now();
+            streamed.first_token.get_or_insert(now);
+            let shown = answer.folder.push(content);
+            let actions = answer.assembler.push(&shown, now);
+            if answer.partial {
+                apply(http, responder, sent, actions).await;
+            }
+        }
+
+        if choice.finish_reason.is_some() {
+            let shown = answer.folder.finish();
+            let actions = answer.assembler.push(&shown, std::time::Instant::now());
+            apply(http, responder, sent, actions).await;
+            streamed.end = StreamEnd::Finished;
+            return streamed;
+        }
+    }
+}
+
+/// Replaces a partial answer with a note that a newer question took over
+async fn note_superseded(
+    http: &Http,
+    responder: &Responder,
+    sent: &mut serenity::model::channel::Message,
+    locale: &str,
+) {
+    info!("Superseded by a newer question from the same user");
+    if let Err(e) = responder
+        .edit(http, sent, &i18n::tr(locale, "superseded", &[]))
+        .await
+    {
+        warn!("Failed to edit message: {}", e);
+    }
+}
+
+/// Carries out the assembler's edits, moving `sent` along to any new message
+pub async fn apply(
+    http: &Http,
+    responder: &Responder,
+    sent: &mut serenity::model::channel::Message,
+    actions: Vec<Action>,
+) {
+    for action in actions {
+        match action {
+            Action::Edit(text) | Action::Finalize(text) => {
+                if let Err(e) = responder.edit(http, sent, &text).await {
+                    warn!("Failed to edit message: {}", e);
+                }
+            }
+            Action::NewMessage(text) => match responder.send(http, &text).await {
+                Ok(new) => *sent = new,
+                Err(e) => warn!("Failed to send message: {}", e),
+            },
+        }
+    }
 }
@@ -0,0 +1,137 @@
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartImage,
+    ChatCompletionRequestMessageContentPartText, ChatCompletionRequestSystemMessage,
+    ChatCompletionRequestSystemMessageContent, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+    ImageDetail, ImageUrl,
+};
+use serenity::all::{GuildId, Message};
+use tracing::warn;
+
+use crate::{
+    config::{Config, OcrConfig},
+    message_text, oai, Data,
+};
+
+/// Longest text kept from one image, in characters
+const MAX_TEXT: usize = 4000;
+
+/// What the model replies for images without text
+const NO_TEXT: &str = "NO TEXT";
+
+const INSTRUCTIONS: &str = "Transcribe the text in the image exactly as written, keeping line \
+breaks, error codes and paths as they are. Don't describe or explain anything. If there's no \
+text, reply with only NO TEXT.";
+
+/// Tokens the API charges for an image at high detail: 85, plus 170 for
+/// each 512px tile once it's scaled to fit 2048px and its short side to 768px
+pub fn image_tokens(width: Option<u32>, height: Option<u32>) -> usize {
+    // without a size, assume the most tiles an image can take
+    let (Some(width), Some(height)) = (width, height) else {
+        return 85 + 170 * 8;
+    };
+    let (mut width, mut height) = (f64::from(width.max(1)), f64::from(height.max(1)));
+    let fit = (2048.0 / width.max(height)).min(1.0);
+    (width, height) = (width * fit, height * fit);
+    let shrink = (768.0 / width.min(height)).min(1.0);
+    (width, height) = (width * shrink, height * shrink);
+    let tiles = (width / 512.0).ceil() * (height / 512.0).ceil();
+    85 + 170 * tiles as usize
+}
+
+/// What reading the images found
+pub struct Reading {
+    /// The text in them, to add to the question, or empty
+    pub text: String,
+    /// Tokens spent reading them, counted against the guild's cap with the answer
+    pub tokens: usize,
+}
+
+/// The text in the batch's images, and what reading them cost
+pub async fn read(
+    data: &Data,
+    config: &Config,
+    ocr: &OcrConfig,
+    guild_id: Option<GuildId>,
+    batch: &[Message],
+) -> Reading {
+    let (provider, model) = data.providers.for_guild(config, guild_id);
+    let model = ocr.model.clone().unwrap_or(model);
+    let images = message_text::images(batch);
+    let reads = images.iter().take(ocr.max_images).map(|image| {
+        let messages = vec![
+            ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+                content: ChatCompletionRequestSystemMessageContent::Text(INSTRUCTIONS.to_string()),
+                ..Default::default()
+            }),
+            ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                content: ChatCompletionRequestUserMessageContent::Array(vec![
+                    ChatCompletionRequestUserMessageContentPart::Text(
+                        ChatCompletionRequestMessageContentPartText {
+                            text: format!("The image is called {}.", image.filename),
+                        },
+                    ),
+                    ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                        ChatCompletionRequestMessageContentPartImage {
+                            image_url: ImageUrl {
+                                url: image.url.clone(),
+                                detail: Some(ImageDetail::High),
+                            },
+                        },
+                    ),
+                ]),
+                ..Default::default()
+            }),
+        ];
+        oai::complete_messages(provider, config, &model, messages)
+    });
+    let replies = futures::future::join_all(reads).await;
+    let mut text = String::new();
+    let mut tokens = 0;
+    for (image, reply) in images.iter().zip(replies) {
+        // the image is paid for whether or not there's a reply
+        tokens += oai::count_tokens(&instructions(&image.filename))
+            + image_tokens(image.width, image.height);
+        match reply {
+            Ok(reply) => {
+                tokens += oai::count_tokens(&ChatCompletionRequestMessage::Assistant(
+                    ChatCompletionRequestAssistantMessage {
+                        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                            reply.clone(),
+                        )),
+                        ..Default::default()
+                    },
+                ));
+                text.push_str(&transcript(&image.filename, &reply));
+            }
+            Err(e) => warn!("Failed to read the text in {}: {}", image.filename, e),
+        }
+    }
+    Reading { text, tokens }
+}
+
+/// The instructions and image name sent with an image, as text, for counting
+fn instructions(filename: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+        content: ChatCompletionRequestSystemMessageContent::Text(format!(
+            "{}\nThe image is called {}.",
+            INSTRUCTIONS, filename
+        )),
+        ..Default::default()
+    })
+}
+
+/// The model's transcription of `filename`, as added to the question, or
+/// empty if it found no text
+pub fn transcript(filename: &str, reply: &str) -> String {
+    let reply = reply.trim();
+    if reply.is_empty() || reply.trim_end_matches('.').eq_ignore_ascii_case(NO_TEXT) {
+        return String::new();
+    }
+    let mut text: String = reply.chars().take(MAX_TEXT).collect();
+    if text.len() < reply.len() {
+        text.push('…');
+    }
+    format!("\n\n[Text in {}]\n{}", filename, text)
+}
@@ -0,0 +1,243 @@
+use serenity::all::CreateAttachment;
+use tracing::warn;
+
+use crate::{config::PasteConfig, i18n, storage::Error};
+
+/// Most code blocks folded per answer; Discord allows 10 attachments per
+/// message, and rendered math takes some
+const MAX_FOLDED: usize = 4;
+
+/// A code block too long to show in full, to upload or attach
+#[derive(Debug, PartialEq)]
+pub struct FoldedBlock {
+    /// What the answer calls it, like `code-1.rs`
+    pub name: String,
+    pub code: String,
+}
+
+/// Passes an answer on as it streams in, with code blocks longer than
+/// `max_lines` cut short and a note saying where the rest is
+pub struct CodeFolder {
+    max_lines: Option<usize>,
+    locale: String,
+    /// The whole answer, as the model wrote it
+    text: String,
+    /// The line being written
+    line: String,
+    /// The code block being written, if any
+    block: Option<Block>,
+    folded: Vec<FoldedBlock>,
+}
+
+struct Block {
+    language: String,
+    code: String,
+    lines: usize,
+}
+
+impl CodeFolder {
+    /// Folds nothing if `max_lines` is unset
+    pub fn new(max_lines: Option<usize>, locale: &str) -> CodeFolder {
+        CodeFolder {
+            max_lines,
+            locale: locale.to_string(),
+            text: String::new(),
+            line: String::new(),
+            block: None,
+            folded: vec![],
+        }
+    }
+
+    /// The whole answer so far, with nothing folded
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The code blocks folded so far
+    pub fn folded(&self) -> &[FoldedBlock] {
+        &self.folded
+    }
+
+    /// What to show of `delta`
+    pub fn push(&mut self, delta: &str) -> String {
+        self.text.push_str(delta);
+        let mut shown = String::new();
+        for c in delta.chars() {
+            self.line.push(c);
+            if !self.hiding() {
+                shown.push(c);
+            }
+            if c == '\n' {
+                shown.push_str(&self.end_line());
+            }
+        }
+        shown
+    }
+
+    /// What's left to show once the answer is done, closing a code block the
+    /// model left open
+    pub fn finish(&mut self) -> String {
+        let mut shown = String::new();
+        if !self.line.is_empty() {
+            shown.push_str(&self.end_line());
+        }
+        if self.hiding() && self.past_limit() {
+            let block = self.block.take().expect("hiding outside a code block");
+            shown.push_str(&self.fold(block, "```"));
+        }
+        shown
+    }
+
+    /// Whether the line being written is past the block's limit
+    fn hiding(&self) -> bool {
+        match (&self.block, self.max_lines) {
+            (Some(block), Some(max_lines)) => {
+                block.lines >= max_lines && self.folded.len() < MAX_FOLDED
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the open code block has lines that weren't shown
+    fn past_limit(&self) -> bool {
+        self.block
+            .as_ref()
+            .is_some_and(|block| Some(block.lines) > self.max_lines)
+    }
+
+    /// Takes in the finished line, returning anything to show after it
+    fn end_line(&mut self) -> String {
+        let hidden = self.hiding();
+        let line = std::mem::take(&mut self.line);
+        let fence = line.trim_start().starts_with("```");
+        match (&mut self.block, fence) {
+            (None, true) => {
+                self.block = Some(Block {
+                    language: line.trim().trim_start_matches('`').trim().to_string(),
+                    code: String::new(),
+                    lines: 0,
+                });
+            }
+            (Some(_), true) => {
+                let past_limit = self.past_limit();
+                let block = self.block.take().expect("checked above");
+                match (hidden, past_limit) {
+                    (true, true) => return self.fold(block, &line),
+                    // a block just `max_lines` long; its end was held back in case
+                    (true, false) => return line,
+                    _ => {}
+                }
+            }
+            (Some(block), false) => {
+                block.code.push_str(&line);
+                block.lines += 1;
+            }
+            (None, false) => {}
+        }
+        String::new()
+    }
+
+    /// Keeps `block` to share, returning its closing fence and the note
+    fn fold(&mut self, block: Block, fence: &str) -> String {
+        let name = format!(
+            "code-{}.{}",
+            self.folded.len() + 1,
+            extension(&block.language)
+        );
+        let note = i18n::tr(
+            &self.locale,
+            "code-folded",
+            &[
+                (
+                    "lines",
+                    (block.lines - self.max_lines.unwrap_or_default()).into(),
+                ),
+                ("name", name.as_str().into()),
+            ],
+        );
+        self.folded.push(FoldedBlock {
+            name,
+            code: block.code,
+        });
+        format!("{}\n{}\n", fence.trim_end(), note)
+    }
+}
+
+/// The file extension for code in `language`, as named after a code fence
+pub fn extension(language: &str) -> &'static str {
+    match language.to_lowercase().as_str() {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" | "jsx" => "js",
+        "typescript" | "ts" | "tsx" => "ts",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yml",
+        "sh" | "bash" | "shell" | "zsh" | "console" => "sh",
+        "powershell" | "ps1" | "pwsh" => "ps1",
+        "bat" | "batch" | "cmd" => "bat",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "csharp" | "cs" | "c#" => "cs",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "go" => "go",
+        "lua" => "lua",
+        "html" => "html",
+        "css" => "css",
+        "xml" => "xml",
+        "sql" => "sql",
+        _ => "txt",
+    }
+}
+
+/// Lines linking to the blocks uploaded to `[paste]`'s `upload_url`, and
+/// files for the rest, to attach to the answer
+pub async fn share(
+    http: &reqwest::Client,
+    config: &PasteConfig,
+    locale: &str,
+    blocks: &[FoldedBlock],
+) -> (Vec<String>, Vec<CreateAttachment>) {
+    let mut links = vec![];
+    let mut files = vec![];
+    for block in blocks {
+        if let Some(upload_url) = &config.upload_url {
+            match upload(http, upload_url, &block.code).await {
+                Ok(url) => {
+                    links.push(i18n::tr(
+                        locale,
+                        "code-link",
+                        &[("name", block.name.as_str().into()), ("url", url.into())],
+                    ));
+                    continue;
+                }
+                Err(e) => warn!("Failed to upload {}, attaching it: {}", block.name, e),
+            }
+        }
+        files.push(CreateAttachment::bytes(
+            block.code.clone().into_bytes(),
+            block.name.clone(),
+        ));
+    }
+    (links, files)
+}
+
+/// Posts `code` as the body, taking the response body as the paste's link
+async fn upload(http: &reqwest::Client, upload_url: &str, code: &str) -> Result<String, Error> {
+    let url = http
+        .post(upload_url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(code.to_string())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?
+        .trim()
+        .to_string();
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("expected a link back, got {:?}", url).into());
+    }
+    Ok(url)
+}
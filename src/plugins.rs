@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use serenity::all::Message;
+
+use crate::{config::Config, releases, snippets, storage::Error, tools::Tools, Data};
+
+/// The context commands get
+pub type Context<'a> = poise::Context<'a, Arc<Data>, Error>;
+
+/// A command, as poise's macro builds it
+pub type Command = poise::Command<Arc<Data>, Error>;
+
+/// What one answer is being given for, for plugins to pick its tools by
+pub struct Answer<'a> {
+    pub ctx: &'a serenity::prelude::Context,
+    pub data: &'a Data,
+    pub config: &'a Config,
+    /// The question's last message
+    pub msg: &'a Message,
+    /// Whether people read the answer, rather than it being triage JSON or a dry run
+    pub for_people: bool,
+}
+
+/// A feature that brings its own tools and commands, so it can be added or
+/// left out on its own
+#[serenity::async_trait]
+pub trait Plugin: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Adds the tools the model may use for `answer`; only asked when the
+    /// model takes tools
+    async fn tools(&self, _answer: &Answer<'_>, _tools: &mut Tools) {}
+
+    /// Slash and prefix commands, registered with the built-in ones
+    fn commands(&self) -> Vec<Command> {
+        Vec::new()
+    }
+}
+
+/// Every plugin this build has, in the order their tools are offered
+pub fn compiled_in() -> Vec<Box<dyn Plugin>> {
+    vec![
+        Box::new(snippets::SnippetsPlugin),
+        #[cfg(feature = "trello")]
+        Box::new(crate::trello::TrelloPlugin::default()),
+        Box::new(releases::ReleasesPlugin),
+        #[cfg(feature = "polls")]
+        Box::new(crate::polls::PollsPlugin),
+    ]
+}
+
+/// The plugins compiled in, plus the tools in `[wasm_tools]`'s folder
+pub fn load(config: &Config) -> Vec<Box<dyn Plugin>> {
+    #[allow(unused_mut)]
+    let mut plugins = compiled_in();
+    #[cfg(feature = "wasm")]
+    if let Some(wasm_tools) = &config.wasm_tools {
+        plugins.push(Box::new(crate::wasm_tools::WasmPlugin::load(wasm_tools)));
+    }
+    #[cfg(not(feature = "wasm"))]
+    if config.wasm_tools.is_some() {
+        tracing::warn!("[wasm_tools] needs a build with `--features wasm`");
+    }
+    plugins
+}
+
+/// The tools every plugin offers for `answer`
+pub async fn tools(plugins: &[Box<dyn Plugin>], answer: &Answer<'_>) -> Tools {
+    let mut tools = Tools::default();
+    for plugin in plugins {
+        plugin.tools(answer, &mut tools).await;
+    }
+    tools
+}
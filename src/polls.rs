@@ -0,0 +1,164 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use serenity::all::{CreateMessage, CreatePoll, CreatePollAnswer, Http, Message};
+
+use crate::{
+    plugins::{Answer, Plugin},
+    storage::Error,
+    tools::{Tool, Tools},
+};
+
+/// Discord's limits on polls
+pub const MAX_QUESTION: usize = 300;
+pub const MAX_ANSWER: usize = 55;
+pub const MIN_ANSWERS: usize = 2;
+pub const MAX_ANSWERS: usize = 10;
+
+/// How long polls stay open; the asker answers in minutes or not at all
+const DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Lets the model ask the asker a multiple-choice question as a native poll,
+/// once per answer
+pub struct PollTool {
+    http: Arc<Http>,
+    /// The question being answered, which the poll replies to
+    msg: Message,
+    posted: AtomicBool,
+}
+
+impl PollTool {
+    pub fn new(http: Arc<Http>, msg: Message) -> PollTool {
+        PollTool {
+            http,
+            msg,
+            posted: AtomicBool::new(false),
+        }
+    }
+}
+
+/// The poll's question and answers, if Discord would take them
+pub fn parse(arguments: &Value) -> Result<(String, Vec<String>), Error> {
+    let question = arguments["question"]
+        .as_str()
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .ok_or("question is missing")?;
+    if question.chars().count() > MAX_QUESTION {
+        return Err(format!("the question is longer than {} characters", MAX_QUESTION).into());
+    }
+    let answers: Vec<String> = arguments["answers"]
+        .as_array()
+        .ok_or("answers are missing")?
+        .iter()
+        .filter_map(Value::as_str)
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(str::to_string)
+        .collect();
+    if !(MIN_ANSWERS..=MAX_ANSWERS).contains(&answers.len()) {
+        return Err(format!(
+            "a poll needs {} to {} answers, got {}",
+            MIN_ANSWERS,
+            MAX_ANSWERS,
+            answers.len()
+        )
+        .into());
+    }
+    if let Some(long) = answers.iter().find(|a| a.chars().count() > MAX_ANSWER) {
+        return Err(format!("\"{}\" is longer than {} characters", long, MAX_ANSWER).into());
+    }
+    Ok((question.to_string(), answers))
+}
+
+#[serenity::async_trait]
+impl Tool for PollTool {
+    fn name(&self) -> &str {
+        "create_poll"
+    }
+
+    fn description(&self) -> String {
+        "Posts a poll the user can answer with one click, like which device or version \
+         they have. Use it instead of asking several questions when you need to know which \
+         of a few known setups they're on, then say what you'll do once they vote. Only one \
+         poll per answer."
+            .to_string()
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "question": {
+                    "type": "string",
+                    "maxLength": MAX_QUESTION,
+                    "description": "What the poll asks",
+                },
+                "answers": {
+                    "type": "array",
+                    "items": { "type": "string", "maxLength": MAX_ANSWER },
+                    "minItems": MIN_ANSWERS,
+                    "maxItems": MAX_ANSWERS,
+                    "description": "The choices",
+                },
+            },
+            "required": ["question", "answers"],
+            "additionalProperties": false,
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, Error> {
+        let (question, answers) = parse(&arguments)?;
+        if self.posted.swap(true, Ordering::SeqCst) {
+            return Err("a poll was already posted for this answer".into());
+        }
+        let poll = CreatePoll::new()
+            .question(question)
+            .answers(
+                answers
+                    .into_iter()
+                    .map(|answer| CreatePollAnswer::new().text(answer))
+                    .collect(),
+            )
+            .duration(DURATION);
+        let posted = self
+            .msg
+            .channel_id
+            .send_message(
+                &self.http,
+                CreateMessage::new().reference_message(&self.msg).poll(poll),
+            )
+            .await;
+        if let Err(e) = posted {
+            self.posted.store(false, Ordering::SeqCst);
+            return Err(e.into());
+        }
+        Ok(
+            "The poll is posted in the channel. Keep your answer short and point them to it."
+                .to_string(),
+        )
+    }
+}
+
+/// Lets the model ask which setup someone is on with a poll
+pub struct PollsPlugin;
+
+#[serenity::async_trait]
+impl Plugin for PollsPlugin {
+    fn name(&self) -> &'static str {
+        "polls"
+    }
+
+    async fn tools(&self, answer: &Answer<'_>, tools: &mut Tools) {
+        // polls are for people, and dry runs post nothing
+        if answer.for_people {
+            tools.add(PollTool::new(answer.ctx.http.clone(), answer.msg.clone()));
+        }
+    }
+}
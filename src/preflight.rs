@@ -0,0 +1,256 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    time::Duration,
+};
+
+use async_openai::error::OpenAIError;
+use serenity::all::{ChannelId, Http};
+use tracing::{error, info, warn};
+
+use crate::config::{Config, DEFAULT_PROVIDER};
+use crate::models;
+use crate::provider::Providers;
+
+/// How long any one check may take; the OpenAI client retries some errors for a long time
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(PartialEq)]
+enum Status {
+    Ok,
+    /// Might work anyway, e.g. a provider without a `/models` endpoint
+    Warning,
+    /// Won't work until fixed
+    Error,
+}
+
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+}
+
+/// What's misconfigured, found by trying each piece of the setup before going online
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn push(&mut self, name: impl Into<String>, status: Status, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.checks.iter().any(|c| c.status == Status::Error)
+    }
+
+    fn has_errors_for(&self, name: &str) -> bool {
+        self.checks
+            .iter()
+            .any(|c| c.status == Status::Error && c.name == name)
+    }
+
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                Status::Ok => info!("[ok] {}: {}", check.name, check.detail),
+                Status::Warning => warn!("[warning] {}: {}", check.name, check.detail),
+                Status::Error => error!("[error] {}: {}", check.name, check.detail),
+            }
+        }
+    }
+}
+
+/// Checks the Discord token, each provider endpoint and its model, timezones, and the
+/// channels and providers the config refers to
+pub async fn check(discord_token: &str, config: &Config, providers: &Providers) -> Report {
+    let mut report = Report { checks: vec![] };
+    let http = Http::new(discord_token);
+    check_token(&mut report, "DISCORD_TOKEN", &http).await;
+    for bot in &config.bots {
+        match std::env::var(&bot.token_env) {
+            Ok(token) => check_token(&mut report, &bot.token_env, &Http::new(&token)).await,
+            Err(_) => report.push(
+                &bot.token_env,
+                Status::Error,
+                format!("not set, but bot {} needs it", bot.name),
+            ),
+        }
+    }
+
+    // every model each provider is asked for, including guild overrides
+    let mut wanted: HashMap<&str, BTreeSet<&str>> = providers
+        .iter()
+        .map(|(name, p)| (name, BTreeSet::from([models::resolve(config, &p.model)])))
+        .collect();
+    for guild in config.guilds.values() {
+        if let (Some(model), Some(models)) = (
+            &guild.model,
+            wanted.get_mut(guild.provider.as_deref().unwrap_or(DEFAULT_PROVIDER)),
+        ) {
+            models.insert(models::resolve(config, model));
+        }
+    }
+
+    for (name, e) in providers.misconfigured() {
+        report.push(format!("provider {}", name), Status::Error, e);
+    }
+    for (name, provider) in providers.iter() {
+        for endpoint in provider.endpoints() {
+            let check_name = format!("provider {} ({})", name, endpoint.label());
+            let models = match timed(endpoint.client.models().list()).await {
+                Ok(Ok(models)) => models.data,
+                Ok(Err(e @ OpenAIError::Reqwest(_))) => {
+                    report.push(
+                        check_name,
+                        Status::Error,
+                        format!("couldn't reach the API ({}); is the base URL right?", e),
+                    );
+                    continue;
+                }
+                // some OpenAI-compatible servers don't list models, so this alone isn't fatal
+                Ok(Err(e)) => {
+                    report.push(
+                        check_name,
+                        Status::Warning,
+                        format!(
+                            "couldn't list models ({}); check the base URL and API key",
+                            e
+                        ),
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    report.push(
+                        check_name,
+                        Status::Error,
+                        format!("{}; is the base URL right?", e),
+                    );
+                    continue;
+                }
+            };
+            for model in &wanted[name] {
+                if models.iter().any(|m| m.id == *model) {
+                    report.push(check_name.clone(), Status::Ok, format!("serves {}", model));
+                } else {
+                    let available: Vec<_> = models.iter().take(5).map(|m| m.id.as_str()).collect();
+                    report.push(
+                        check_name.clone(),
+                        Status::Error,
+                        format!(
+                            "doesn't serve {}; available models include {}",
+                            model,
+                            available.join(", ")
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    for (guild_id, guild) in &config.guilds {
+        if let Some(provider) = &guild.provider {
+            // misconfigured providers are reported above
+            if providers.get(provider).is_none()
+                && providers.misconfigured().all(|(name, _)| name != provider)
+            {
+                report.push(
+                    format!("guild {}", guild_id),
+                    Status::Error,
+                    format!("uses provider {}, which isn't in [providers]", provider),
+                );
+            }
+        }
+    }
+
+    let timezones = std::iter::once(("timezone".to_string(), &config.timezone)).chain(
+        config.guilds.iter().filter_map(|(guild_id, guild)| {
+            Some((
+                format!("guild {} timezone", guild_id),
+                guild.timezone.as_ref()?,
+            ))
+        }),
+    );
+    for (check_name, timezone) in timezones {
+        if timezone != "UTC" && time_tz::timezones::get_by_name(timezone).is_none() {
+            report.push(
+                check_name,
+                Status::Error,
+                format!(
+                    "{} isn't a timezone; use an IANA name like Europe/Berlin",
+                    timezone
+                ),
+            );
+        }
+    }
+
+    let mut channels: Vec<(String, String)> = config
+        .autorespond_channels()
+        .into_iter()
+        .filter(|id| !id.starts_with('-') && !id.is_empty())
+        .map(|id| ("AUTORESPOND_CHANNELS".to_string(), id))
+        .collect();
+    for (guild_id, guild) in &config.guilds {
+        channels.extend(guild.ticket_channels.iter().map(|id| {
+            (
+                format!("guild {} ticket_channels", guild_id),
+                id.to_string(),
+            )
+        }));
+    }
+    // without a working token every channel would fail too
+    if report.has_errors_for("DISCORD_TOKEN") {
+        return report;
+    }
+    for (source, id) in channels {
+        let check_name = format!("{} channel {}", source, id);
+        let Some(channel_id) = id.parse().ok().filter(|&id| id != 0).map(ChannelId::new) else {
+            report.push(check_name, Status::Error, "not a channel id");
+            continue;
+        };
+        match timed(http.get_channel(channel_id)).await {
+            Ok(Ok(_)) => report.push(check_name, Status::Ok, "found"),
+            Ok(Err(e)) => report.push(
+                check_name,
+                Status::Error,
+                format!(
+                    "can't see it ({}); is the bot in that server with View Channel?",
+                    e
+                ),
+            ),
+            Err(e) => report.push(check_name, Status::Error, e),
+        }
+    }
+
+    report
+}
+
+async fn timed<T>(check: impl Future<Output = T>) -> Result<T, String> {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .map_err(|_| format!("timed out after {}s", CHECK_TIMEOUT.as_secs()))
+}
+
+/// Whether Discord takes the token in the environment variable `name`
+async fn check_token(report: &mut Report, name: &str, http: &Http) {
+    match timed(http.get_current_user()).await {
+        Ok(Ok(user)) => report.push(name, Status::Ok, format!("logged in as {}", user.name)),
+        Ok(Err(serenity::Error::Http(e))) if e.status_code().is_some_and(|s| s.as_u16() == 401) => {
+            report.push(
+                name,
+                Status::Error,
+                "Discord rejected the token; copy it again from the developer portal",
+            )
+        }
+        Ok(Err(e)) => report.push(
+            name,
+            Status::Error,
+            format!("couldn't reach Discord ({})", e),
+        ),
+        Err(e) => report.push(name, Status::Error, e),
+    }
+}
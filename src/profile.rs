@@ -0,0 +1,75 @@
+use serenity::all::Message;
+use time::{Date, OffsetDateTime};
+use tracing::warn;
+
+use crate::{config::GuildConfig, Data};
+
+/// How many of the asker's roles to mention, highest first
+const TOP_ROLES: usize = 3;
+
+/// A few sentences about the asker, so the model can judge how much hand-holding
+/// they need. Empty unless the guild turned on `user_profiles`, and then the
+/// question is counted, unless it's only a `dry_run`.
+pub async fn of(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    guild: Option<&GuildConfig>,
+    msg: &Message,
+    dry_run: bool,
+) -> String {
+    let (Some(guild_id), true) = (msg.guild_id, guild.is_some_and(|g| g.user_profiles)) else {
+        return String::new();
+    };
+    // nobody was answered, so it isn't one more question they asked
+    let asked_before = if dry_run {
+        None
+    } else {
+        match data
+            .storage
+            .count_question(guild_id, msg.author.id, OffsetDateTime::now_utc())
+            .await
+        {
+            Ok(count) => Some(count),
+            Err(e) => {
+                warn!("Failed to count the question: {}", e);
+                None
+            }
+        }
+    };
+
+    let member = msg.member.as_deref();
+    let joined = member
+        .and_then(|m| m.joined_at)
+        .and_then(|at| OffsetDateTime::from_unix_timestamp(at.unix_timestamp()).ok())
+        .map(|at| at.date());
+    let mut roles: Vec<(u16, String)> = match (member, msg.guild(&ctx.cache)) {
+        (Some(member), Some(cached)) => member
+            .roles
+            .iter()
+            .filter_map(|id| cached.roles.get(id))
+            .map(|r| (r.position, r.name.clone()))
+            .collect(),
+        _ => Vec::new(),
+    };
+    roles.sort_by_key(|(position, _)| std::cmp::Reverse(*position));
+    let roles: Vec<_> = roles.into_iter().take(TOP_ROLES).map(|(_, n)| n).collect();
+    describe(joined, &roles, asked_before)
+}
+
+/// The profile from what's known about the asker
+pub fn describe(joined: Option<Date>, top_roles: &[String], asked_before: Option<u64>) -> String {
+    let mut facts = Vec::new();
+    if let Some(joined) = joined {
+        facts.push(format!("The asker joined the server on {}.", joined));
+    }
+    if !top_roles.is_empty() {
+        facts.push(format!("Their top roles: {}.", top_roles.join(", ")));
+    }
+    match asked_before {
+        Some(0) => facts.push("This is their first question to you.".to_string()),
+        Some(1) => facts.push("They have asked you 1 question before.".to_string()),
+        Some(n) => facts.push(format!("They have asked you {} questions before.", n)),
+        None => {}
+    }
+    facts.join(" ")
+}
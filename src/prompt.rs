@@ -0,0 +1,170 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::{error, info};
+
+use crate::{
+    config::{Config, SharedConfig},
+    oai::SYSTEM_MESSAGE,
+    storage::{Error, Storage},
+};
+
+/// How often to look for changes to the prompt file
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Setting holding the pinned version, if any
+const PINNED_SETTING: &str = "prompt.pinned";
+
+/// One revision of the instructions at the start of the system prompt
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PromptVersion {
+    /// Short hash of `text`
+    pub version: String,
+    pub text: String,
+    /// When it was first seen
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl PromptVersion {
+    pub fn new(text: String) -> PromptVersion {
+        PromptVersion {
+            version: version_of(&text),
+            text,
+            created_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+/// Identifies a prompt by its content, so the same text is always the same version
+pub fn version_of(text: &str) -> String {
+    Sha256::digest(text.as_bytes())[..6]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The instructions in `prompt_file`, or the built-in ones without one
+pub fn read(config: &Config) -> std::io::Result<String> {
+    match &config.prompt_file {
+        Some(path) => std::fs::read_to_string(path),
+        None => Ok(SYSTEM_MESSAGE.to_string()),
+    }
+}
+
+/// The prompt answers are given: the latest from the prompt file, unless an
+/// owner pinned an older version
+pub struct Prompts {
+    storage: Arc<dyn Storage>,
+    active: RwLock<Arc<PromptVersion>>,
+    pinned: RwLock<bool>,
+}
+
+impl Prompts {
+    /// Reads the prompt file, saves it as a version if it's new, and picks up
+    /// any pin from a previous run
+    pub async fn load(config: &Config, storage: Arc<dyn Storage>) -> Result<Prompts, Error> {
+        let latest = PromptVersion::new(read(config)?);
+        storage.save_prompt_version(&latest).await?;
+
+        let pinned = match storage.setting(PINNED_SETTING).await? {
+            Some(version) => storage.prompt_version(&version).await?,
+            None => None,
+        };
+        if let Some(pinned) = &pinned {
+            info!("Using pinned prompt version {}", pinned.version);
+        }
+        Ok(Prompts {
+            storage,
+            pinned: RwLock::new(pinned.is_some()),
+            active: RwLock::new(Arc::new(pinned.unwrap_or(latest))),
+        })
+    }
+
+    /// What to answer with right now
+    pub fn active(&self) -> Arc<PromptVersion> {
+        self.active.read().unwrap().clone()
+    }
+
+    pub fn is_pinned(&self) -> bool {
+        *self.pinned.read().unwrap()
+    }
+
+    /// Saves `text` as a version, and answers with it unless pinned
+    async fn update(&self, text: String) -> Result<(), Error> {
+        let latest = PromptVersion::new(text);
+        if latest.version == self.active().version {
+            return Ok(());
+        }
+        self.storage.save_prompt_version(&latest).await?;
+        info!("Prompt file changed, now version {}", latest.version);
+        if !self.is_pinned() {
+            *self.active.write().unwrap() = Arc::new(latest);
+        }
+        Ok(())
+    }
+
+    /// Answers with `version` until unpinned, even when the prompt file changes
+    pub async fn pin(&self, version: &str) -> Result<Option<Arc<PromptVersion>>, Error> {
+        let Some(found) = self.storage.prompt_version(version).await? else {
+            return Ok(None);
+        };
+        self.storage
+            .set_setting(PINNED_SETTING, Some(&found.version))
+            .await?;
+        let found = Arc::new(found);
+        *self.pinned.write().unwrap() = true;
+        *self.active.write().unwrap() = found.clone();
+        Ok(Some(found))
+    }
+
+    /// Goes back to following the prompt file
+    pub async fn unpin(&self, config: &Config) -> Result<Arc<PromptVersion>, Error> {
+        let latest = PromptVersion::new(read(config)?);
+        self.storage.save_prompt_version(&latest).await?;
+        self.storage.set_setting(PINNED_SETTING, None).await?;
+        let latest = Arc::new(latest);
+        *self.pinned.write().unwrap() = false;
+        *self.active.write().unwrap() = latest.clone();
+        Ok(latest)
+    }
+
+    /// Recent versions, newest first
+    pub async fn history(&self, limit: usize) -> Result<Vec<PromptVersion>, Error> {
+        self.storage.prompt_versions(limit).await
+    }
+}
+
+/// Picks up edits to the prompt file (or a different `prompt_file`) while running
+pub fn watch(prompts: Arc<Prompts>, config: SharedConfig) {
+    tokio::spawn(async move {
+        let modified = |config: &Config| -> Option<(String, SystemTime)> {
+            let path = config.prompt_file.clone()?;
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        };
+        let mut last_modified = modified(&config.read().unwrap());
+        loop {
+            tokio::time::sleep(RELOAD_INTERVAL).await;
+            let current_config = config.read().unwrap().clone();
+            let current = modified(&current_config);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            // deleted, or mid-save
+            let Ok(text) = read(&current_config) else {
+                continue;
+            };
+            if let Err(e) = prompts.update(text).await {
+                error!("Failed to save the new prompt version: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,521 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use async_openai::{
+    config::OpenAIConfig,
+    error::OpenAIError,
+    types::{
+        ChatChoiceStream, ChatCompletionMessageToolCallChunk, ChatCompletionResponseStream,
+        ChatCompletionStreamResponseDelta, CreateChatCompletionRequest,
+        CreateChatCompletionResponse, CreateChatCompletionStreamResponse, CreateEmbeddingRequest,
+        EmbeddingInput, FunctionCallStream,
+    },
+    Client as OpenAIClient,
+};
+use futures::{StreamExt, TryStreamExt};
+use serenity::all::GuildId;
+use tracing::warn;
+
+use crate::{
+    capture::Captures,
+    config::{Config, ProviderConfig, DEFAULT_PROVIDER},
+    storage::Error,
+};
+
+/// How long to skip an endpoint after it rate limits us
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(60);
+/// How long to skip an endpoint after any other error
+const ERROR_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Which endpoints are being skipped after failing, kept in memory or in Redis
+/// to share them between instances
+#[serenity::async_trait]
+pub trait Cooldowns: Send + Sync {
+    async fn cooling_down(&self, endpoint: &str) -> bool;
+    async fn cool_down(&self, endpoint: &str, duration: Duration);
+}
+
+/// Cooldowns in this process only
+#[derive(Default)]
+pub struct LocalCooldowns {
+    until: Mutex<HashMap<String, Instant>>,
+}
+
+#[serenity::async_trait]
+impl Cooldowns for LocalCooldowns {
+    async fn cooling_down(&self, endpoint: &str) -> bool {
+        self.until
+            .lock()
+            .unwrap()
+            .get(endpoint)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    async fn cool_down(&self, endpoint: &str, duration: Duration) {
+        self.until
+            .lock()
+            .unwrap()
+            .insert(endpoint.to_string(), Instant::now() + duration);
+    }
+}
+
+/// One API key at one base URL
+pub struct Endpoint {
+    pub client: OpenAIClient<OpenAIConfig>,
+    label: String,
+}
+
+impl Endpoint {
+    pub fn new(label: String, api_key: &str, base_url: &str) -> Endpoint {
+        let oai_config = OpenAIConfig::new()
+            .with_api_key(api_key)
+            .with_api_base(base_url);
+        Endpoint {
+            client: OpenAIClient::with_config(oai_config),
+            label,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    async fn cool_down(&self, cooldowns: &dyn Cooldowns, err: &OpenAIError) {
+        let rate_limited = match err {
+            OpenAIError::ApiError(e) => e.code.as_deref() == Some("rate_limit_exceeded"),
+            other => status(other) == Some(429),
+        };
+        let cooldown = if rate_limited {
+            RATE_LIMIT_COOLDOWN
+        } else {
+            ERROR_COOLDOWN
+        };
+        warn!(
+            "Endpoint {} failed ({}), skipping it for {}s",
+            self.label,
+            err,
+            cooldown.as_secs()
+        );
+        cooldowns.cool_down(&self.label, cooldown).await;
+    }
+}
+
+/// The HTTP status an endpoint failed with, where the error still has it
+fn status(err: &OpenAIError) -> Option<u16> {
+    match err {
+        OpenAIError::Reqwest(e) => e.status().map(|status| status.as_u16()),
+        // streams only keep the message, as in `Invalid status code: 429 Too Many Requests`
+        OpenAIError::StreamError(message) => message
+            .strip_prefix("Invalid status code: ")?
+            .split(' ')
+            .next()?
+            .parse()
+            .ok(),
+        _ => None,
+    }
+}
+
+/// What a provider with nothing to try fails with, though `Provider::new`
+/// shouldn't let one be made
+fn no_endpoints() -> OpenAIError {
+    OpenAIError::InvalidArgument("provider has no endpoints".to_string())
+}
+
+/// A pool of endpoints serving the same models, used round-robin
+pub struct Provider {
+    endpoints: Vec<Endpoint>,
+    next: AtomicUsize,
+    cooldowns: Arc<dyn Cooldowns>,
+    captures: Option<Arc<Captures>>,
+    pub model: String,
+    /// Merged into every request body, over the fields we set
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Provider {
+    /// The provider `name`, which needs at least one endpoint
+    pub fn new(name: &str, endpoints: Vec<Endpoint>, model: String) -> Result<Provider, Error> {
+        if endpoints.is_empty() {
+            return Err(format!("provider {} has no endpoints", name).into());
+        }
+        Ok(Provider {
+            endpoints,
+            next: AtomicUsize::new(0),
+            cooldowns: Arc::new(LocalCooldowns::default()),
+            captures: None,
+            model,
+            params: serde_json::Map::new(),
+        })
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Every model the provider serves, sorted, from the first endpoint that lists them
+    pub async fn models(&self) -> Result<Vec<String>, OpenAIError> {
+        let mut last_err = None;
+        for endpoint in &self.endpoints {
+            match endpoint.client.models().list().await {
+                Ok(list) => {
+                    let mut models: Vec<_> = list.data.into_iter().map(|m| m.id).collect();
+                    models.sort();
+                    return Ok(models);
+                }
+                Err(e) => {
+                    warn!("Endpoint {} didn't list its models: {}", endpoint.label, e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(no_endpoints))
+    }
+
+    /// How long the first endpoint that answers takes to, asked for its models
+    /// since that costs no tokens. Any answer counts, even an error; only not
+    /// reaching an endpoint at all is a failure.
+    pub async fn ping(&self) -> Result<Duration, OpenAIError> {
+        let mut last_err = None;
+        for endpoint in &self.endpoints {
+            let start = Instant::now();
+            match endpoint.client.models().list().await {
+                Err(e @ OpenAIError::Reqwest(_)) => {
+                    warn!("Endpoint {} didn't answer a ping: {}", endpoint.label, e);
+                    last_err = Some(e);
+                }
+                _ => return Ok(start.elapsed()),
+            }
+        }
+        Err(last_err.unwrap_or_else(no_endpoints))
+    }
+
+    fn from_config(name: &str, p: &ProviderConfig, default_model: &str) -> Result<Provider, Error> {
+        let mut endpoints = vec![];
+        if let (Some(api_key), Some(base_url)) = (&p.api_key, &p.base_url) {
+            endpoints.push(Endpoint::new(name.to_string(), api_key, base_url));
+        }
+        for (i, e) in p.endpoints.iter().enumerate() {
+            let base_url =
+                e.base_url.as_ref().or(p.base_url.as_ref()).ok_or_else(|| {
+                    format!("endpoint {} of provider {} has no base_url", i, name)
+                })?;
+            endpoints.push(Endpoint::new(
+                format!("{}#{}", name, i),
+                &e.api_key,
+                base_url,
+            ));
+        }
+        let model = p.model.clone().unwrap_or(default_model.to_string());
+        let mut provider = Provider::new(name, endpoints, model)?;
+        provider.params = p.params.clone();
+        Ok(provider)
+    }
+
+    /// The next endpoint in rotation that isn't cooling down. If all of them
+    /// are, rotate anyway rather than refusing to answer.
+    async fn pick(&self) -> &Endpoint {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let n = self.endpoints.len();
+        for i in 0..n {
+            let endpoint = &self.endpoints[(start + i) % n];
+            if !self.cooldowns.cooling_down(&endpoint.label).await {
+                return endpoint;
+            }
+        }
+        &self.endpoints[start % n]
+    }
+}
+
+/// Where answers are streamed from. `Provider` is the real thing; tests stand in
+/// canned streams.
+#[serenity::async_trait]
+pub trait ChatBackend: Send + Sync {
+    async fn create_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError>;
+}
+
+#[serenity::async_trait]
+impl ChatBackend for Provider {
+    /// Starts a chat completion stream, failing over to the next endpoint if
+    /// one errors before producing its first chunk. Requests with `stream`
+    /// off get the whole answer as a single chunk.
+    async fn create_stream(
+        &self,
+        request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        let streaming = request.stream != Some(false);
+        let request = self.body(request)?;
+        let mut last_err = None;
+        for _ in 0..self.endpoints.len() {
+            let endpoint = self.pick().await;
+            let chat = endpoint.client.chat();
+            let recording = self
+                .captures
+                .as_ref()
+                .map(|captures| captures.start(&endpoint.label, &request));
+            if !streaming {
+                match chat
+                    .create_byot::<_, CreateChatCompletionResponse>(&request)
+                    .await
+                {
+                    Ok(response) => {
+                        if let Some(recording) = &recording {
+                            recording.response(&response);
+                        }
+                        let chunk = into_chunk(response);
+                        return Ok(Box::pin(futures::stream::iter([Ok(chunk)])));
+                    }
+                    Err(e) => {
+                        if let Some(recording) = &recording {
+                            recording.error(&e);
+                        }
+                        endpoint.cool_down(self.cooldowns.as_ref(), &e).await;
+                        last_err = Some(e);
+                        continue;
+                    }
+                }
+            }
+            let mut stream: ChatCompletionResponseStream = match chat
+                .create_stream_byot::<_, CreateChatCompletionStreamResponse>(&request)
+                .await
+            {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if let Some(recording) = &recording {
+                        recording.error(&e);
+                    }
+                    endpoint.cool_down(self.cooldowns.as_ref(), &e).await;
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            if let Some(recording) = recording {
+                stream = Box::pin(stream.inspect(move |chunk| match chunk {
+                    Ok(chunk) => recording.response(chunk),
+                    Err(e) => recording.error(e),
+                }));
+            }
+            // errors like 429s only show up once the stream is polled
+            match stream.try_next().await {
+                Ok(first) => {
+                    let first = futures::stream::iter(first.map(Ok));
+                    return Ok(Box::pin(first.chain(stream)));
+                }
+                Err(e) => {
+                    endpoint.cool_down(self.cooldowns.as_ref(), &e).await;
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(no_endpoints))
+    }
+}
+
+impl Provider {
+    /// Embeds `input` with `model`, failing over between endpoints like answers do
+    pub async fn embed(&self, model: &str, input: &str) -> Result<Vec<f32>, OpenAIError> {
+        let request = CreateEmbeddingRequest {
+            model: model.to_string(),
+            input: EmbeddingInput::String(input.to_string()),
+            ..Default::default()
+        };
+        let mut last_err = None;
+        for _ in 0..self.endpoints.len() {
+            let endpoint = self.pick().await;
+            match endpoint.client.embeddings().create(request.clone()).await {
+                Ok(response) => {
+                    return response
+                        .data
+                        .into_iter()
+                        .next()
+                        .map(|e| e.embedding)
+                        .ok_or_else(|| {
+                            OpenAIError::InvalidArgument("no embedding in the response".to_string())
+                        });
+                }
+                Err(e) => {
+                    endpoint.cool_down(self.cooldowns.as_ref(), &e).await;
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(no_endpoints))
+    }
+
+    /// The request as JSON, with `params` merged in
+    fn body(&self, request: CreateChatCompletionRequest) -> Result<serde_json::Value, OpenAIError> {
+        let mut body = serde_json::to_value(request).map_err(OpenAIError::JSONDeserialize)?;
+        if let Some(fields) = body.as_object_mut() {
+            for (key, value) in &self.params {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(body)
+    }
+}
+
+/// A whole answer as the one chunk of a stream, for models that can't stream
+fn into_chunk(response: CreateChatCompletionResponse) -> CreateChatCompletionStreamResponse {
+    CreateChatCompletionStreamResponse {
+        id: response.id,
+        choices: response
+            .choices
+            .into_iter()
+            .map(|choice| ChatChoiceStream {
+                index: choice.index,
+                #[allow(deprecated)]
+                delta: ChatCompletionStreamResponseDelta {
+                    content: choice.message.content,
+                    function_call: None,
+                    tool_calls: choice.message.tool_calls.map(|calls| {
+                        calls
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, call)| ChatCompletionMessageToolCallChunk {
+                                index: index as u32,
+                                id: Some(call.id),
+                                r#type: Some(call.r#type),
+                                function: Some(FunctionCallStream {
+                                    name: Some(call.function.name),
+                                    arguments: Some(call.function.arguments),
+                                }),
+                            })
+                            .collect()
+                    }),
+                    role: Some(choice.message.role),
+                    refusal: choice.message.refusal,
+                },
+                finish_reason: choice.finish_reason,
+                logprobs: choice.logprobs,
+            })
+            .collect(),
+        created: response.created,
+        model: response.model,
+        service_tier: response.service_tier,
+        system_fingerprint: response.system_fingerprint,
+        object: "chat.completion.chunk".to_string(),
+        usage: response.usage,
+    }
+}
+
+/// Every configured provider, keyed by name
+pub struct Providers {
+    providers: HashMap<String, Provider>,
+    /// Configured providers left out for what's wrong with them, by name
+    misconfigured: HashMap<String, String>,
+    captures: Option<Arc<Captures>>,
+    /// Models picked with /model, over the config's, by guild
+    picked: RwLock<HashMap<GuildId, String>>,
+}
+
+impl Providers {
+    /// Builds the default provider from the environment plus any from the config
+    /// file. Those that can't be built are left out, for `misconfigured`.
+    pub fn new(mut default: Provider, configured: &HashMap<String, ProviderConfig>) -> Providers {
+        let mut providers = HashMap::new();
+        let mut misconfigured = HashMap::new();
+        for (name, p) in configured {
+            // the default provider comes from the environment, but can take params
+            if name == DEFAULT_PROVIDER {
+                default.params = p.params.clone();
+                continue;
+            }
+            match Provider::from_config(name, p, &default.model) {
+                Ok(provider) => {
+                    providers.insert(name.clone(), provider);
+                }
+                Err(e) => {
+                    misconfigured.insert(name.clone(), e.to_string());
+                }
+            }
+        }
+        providers.insert(DEFAULT_PROVIDER.to_string(), default);
+        Providers {
+            providers,
+            misconfigured,
+            captures: None,
+            picked: RwLock::default(),
+        }
+    }
+
+    /// Keeps every provider's cooldowns in `cooldowns`, instead of each its own
+    pub fn share_cooldowns(&mut self, cooldowns: Arc<dyn Cooldowns>) {
+        for provider in self.providers.values_mut() {
+            provider.cooldowns = cooldowns.clone();
+        }
+    }
+
+    /// Keeps the last requests to every provider, and their responses, in `captures`
+    pub fn capture(&mut self, captures: Arc<Captures>) {
+        for provider in self.providers.values_mut() {
+            provider.captures = Some(captures.clone());
+        }
+        self.captures = Some(captures);
+    }
+
+    /// What's been captured, if capturing is on
+    pub fn captures(&self) -> Option<&Captures> {
+        self.captures.as_deref()
+    }
+
+    /// Configured providers that were left out, with what's wrong with each
+    pub fn misconfigured(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.misconfigured
+            .iter()
+            .map(|(name, e)| (name.as_str(), e.as_str()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Provider> {
+        self.providers.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Provider)> {
+        self.providers.iter().map(|(name, p)| (name.as_str(), p))
+    }
+
+    /// The name of the provider a guild should be answered by
+    pub fn name_for_guild<'a>(&self, config: &'a Config, guild_id: Option<GuildId>) -> &'a str {
+        let name = config
+            .guild(guild_id)
+            .and_then(|g| g.provider.as_deref())
+            .unwrap_or(DEFAULT_PROVIDER);
+        if self.providers.contains_key(name) {
+            name
+        } else {
+            warn!("Unknown provider {}, using the default", name);
+            DEFAULT_PROVIDER
+        }
+    }
+
+    /// Picks the provider and model a guild should be answered with
+    pub fn for_guild(&self, config: &Config, guild_id: Option<GuildId>) -> (&Provider, String) {
+        let provider = &self.providers[self.name_for_guild(config, guild_id)];
+        let picked = guild_id.and_then(|id| self.picked.read().unwrap().get(&id).cloned());
+        let model = picked
+            .or_else(|| config.guild(guild_id).and_then(|g| g.model.clone()))
+            .unwrap_or_else(|| provider.model.clone());
+        (provider, model)
+    }
+
+    /// Answers the guild with `model` from now on, or with the config's again for `None`
+    pub fn pick_model(&self, guild_id: GuildId, model: Option<String>) {
+        let mut picked = self.picked.write().unwrap();
+        match model {
+            Some(model) => picked.insert(guild_id, model),
+            None => picked.remove(&guild_id),
+        };
+    }
+
+    /// Every model picked with /model, by guild
+    pub fn picked_models(&self) -> HashMap<GuildId, String> {
+        self.picked.read().unwrap().clone()
+    }
+}
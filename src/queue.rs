@@ -0,0 +1,205 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serenity::all::{ChannelType, Message};
+use tokio::sync::watch;
+
+use crate::config::GuildConfig;
+
+/// Rough guess at how long a response takes before we've timed any
+const INITIAL_ESTIMATE: Duration = Duration::from_secs(10);
+
+/// Lanes in the queue. Higher lanes are served first, in arrival order within a lane.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    Normal,
+    /// Messages in open ticket threads
+    Ticket,
+    /// Messages from support-role members
+    Staff,
+}
+
+impl Priority {
+    /// Staff first, then ticket threads, then everyone else
+    pub async fn of(
+        ctx: &serenity::prelude::Context,
+        guild: Option<&GuildConfig>,
+        msg: &Message,
+    ) -> Priority {
+        let Some(guild) = guild else {
+            return Priority::Normal;
+        };
+
+        let is_staff = msg.member.as_ref().is_some_and(|m| {
+            m.roles
+                .iter()
+                .any(|r| guild.support_roles.contains(&r.get()))
+        });
+        if is_staff {
+            return Priority::Staff;
+        }
+
+        if !guild.ticket_channels.is_empty() {
+            if let Ok(channel) = msg.channel(ctx).await {
+                let in_ticket = channel.guild().is_some_and(|gc| {
+                    matches!(
+                        gc.kind,
+                        ChannelType::PublicThread | ChannelType::PrivateThread
+                    ) && !gc.thread_metadata.is_some_and(|t| t.archived || t.locked)
+                        && gc
+                            .parent_id
+                            .is_some_and(|p| guild.ticket_channels.contains(&p.get()))
+                });
+                if in_ticket {
+                    return Priority::Ticket;
+                }
+            }
+        }
+
+        Priority::Normal
+    }
+}
+
+struct QueueState {
+    active: usize,
+    /// Kept sorted by lane, then arrival
+    waiting: VecDeque<(Priority, u64)>,
+    next_id: u64,
+    /// Moving average of how long a request holds its slot
+    avg_duration: Duration,
+}
+
+/// A snapshot of the queue, for /debug
+#[derive(Debug, PartialEq)]
+pub struct QueueStatus {
+    pub limit: usize,
+    pub active: usize,
+    /// The lane of each request waiting, in the order they'll start
+    pub waiting: Vec<Priority>,
+    /// Moving average of how long a request holds its slot
+    pub avg_duration: Duration,
+}
+
+/// Caps how many responses are generated at once; everyone else waits in line
+pub struct RequestQueue {
+    limit: usize,
+    state: Mutex<QueueState>,
+    changed: watch::Sender<()>,
+}
+
+impl RequestQueue {
+    pub fn new(limit: usize) -> Arc<RequestQueue> {
+        Arc::new(RequestQueue {
+            limit: limit.max(1),
+            state: Mutex::new(QueueState {
+                active: 0,
+                waiting: VecDeque::new(),
+                next_id: 0,
+                avg_duration: INITIAL_ESTIMATE,
+            }),
+            changed: watch::channel(()).0,
+        })
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        let state = self.state.lock().unwrap();
+        QueueStatus {
+            limit: self.limit,
+            active: state.active,
+            waiting: state
+                .waiting
+                .iter()
+                .map(|(priority, _)| *priority)
+                .collect(),
+            avg_duration: state.avg_duration,
+        }
+    }
+
+    /// Gets in line behind everyone in the same or a higher lane. Dropping the
+    /// ticket before it starts leaves the line.
+    pub fn join(self: &Arc<Self>, priority: Priority) -> QueueTicket {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        let index = state.waiting.partition_point(|(p, _)| *p >= priority);
+        state.waiting.insert(index, (priority, id));
+        // people further back just got bumped
+        self.changed.send_replace(());
+        QueueTicket {
+            queue: self.clone(),
+            id,
+            changes: self.changed.subscribe(),
+            started: false,
+        }
+    }
+}
+
+pub struct QueueTicket {
+    queue: Arc<RequestQueue>,
+    id: u64,
+    changes: watch::Receiver<()>,
+    started: bool,
+}
+
+impl QueueTicket {
+    /// Takes a slot if it's our turn, otherwise reports our 1-based position
+    /// in line and a rough estimate of the wait
+    pub fn try_start(&mut self) -> Result<QueuePermit, (usize, Duration)> {
+        let queue = &self.queue;
+        let mut state = queue.state.lock().unwrap();
+        let position = state
+            .waiting
+            .iter()
+            .position(|(_, id)| *id == self.id)
+            .expect("ticket not in queue");
+
+        if position == 0 && state.active < queue.limit {
+            state.waiting.pop_front();
+            state.active += 1;
+            self.started = true;
+            queue.changed.send_replace(());
+            return Ok(QueuePermit {
+                queue: queue.clone(),
+                started_at: Instant::now(),
+            });
+        }
+
+        // everyone ahead of us, plus the running requests, gets worked through `limit` at a time
+        let rounds = (position + state.active) / queue.limit + 1;
+        Err((position + 1, state.avg_duration * rounds as u32))
+    }
+
+    /// Waits until the queue moves
+    pub async fn changed(&mut self) {
+        // the sender lives as long as the queue we hold, so this can't fail
+        let _ = self.changes.changed().await;
+    }
+}
+
+impl Drop for QueueTicket {
+    fn drop(&mut self) {
+        if !self.started {
+            let mut state = self.queue.state.lock().unwrap();
+            state.waiting.retain(|(_, id)| *id != self.id);
+            self.queue.changed.send_replace(());
+        }
+    }
+}
+
+/// A generation slot, freed on drop
+pub struct QueuePermit {
+    queue: Arc<RequestQueue>,
+    started_at: Instant,
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.active -= 1;
+        state.avg_duration = (state.avg_duration * 4 + self.started_at.elapsed()) / 5;
+        self.queue.changed.send_replace(());
+    }
+}
@@ -0,0 +1,283 @@
+use serde_json::Value;
+use serenity::{
+    all::{
+        Channel, ChannelId, Guild, GuildChannel, GuildId, Message, MessageFlags, MessageId,
+        MessageType, Permissions,
+    },
+    http::{LightMethod, Request, Route},
+};
+use tracing::warn;
+
+use crate::message_text;
+
+/// Most linked messages looked up per question
+const MAX_LINKED: usize = 3;
+
+/// Longest a quoted message gets, in characters
+const MAX_LENGTH: usize = 1500;
+
+/// A message the question replies to, links to or forwards
+pub struct Quoted {
+    /// Who wrote it, or who forwarded it
+    pub author: String,
+    pub author_id: u64,
+    pub link: String,
+    pub content: String,
+    /// Whether `author` passed it on rather than wrote it
+    pub forwarded: bool,
+}
+
+/// Where a Discord message link points: the guild (`None` for DMs), channel
+/// and message
+pub type MessageLink = (Option<u64>, ChannelId, MessageId);
+
+/// The Discord message links in `text`, as they'd be pasted: bare, in `<>`,
+/// or from the PTB and Canary clients
+pub fn message_links(text: &str) -> Vec<MessageLink> {
+    let mut found = vec![];
+    for word in text.split(|c: char| c.is_whitespace() || c == '<' || c == '>') {
+        let Ok(url) = reqwest::Url::parse(word) else {
+            continue;
+        };
+        let discord = matches!(
+            url.host_str(),
+            Some("discord.com" | "ptb.discord.com" | "canary.discord.com" | "discordapp.com")
+        );
+        let segments: Vec<&str> = url.path_segments().map_or(vec![], Iterator::collect);
+        let ["channels", guild, channel, message] = segments[..] else {
+            continue;
+        };
+        let guild = match guild {
+            "@me" => None,
+            id => match id.parse() {
+                Ok(id) => Some(id),
+                Err(_) => continue,
+            },
+        };
+        let (Ok(channel), Ok(message)) = (channel.parse::<u64>(), message.parse::<u64>()) else {
+            continue;
+        };
+        // ids are never 0, and serenity panics on them
+        if !discord || channel == 0 || message == 0 {
+            continue;
+        }
+        let link = (guild, ChannelId::new(channel), MessageId::new(message));
+        if !found.contains(&link) {
+            found.push(link);
+        }
+    }
+    found
+}
+
+/// The messages the batch replies to or forwards, and those it links to in
+/// the same server in channels the asker can read
+pub async fn gather(ctx: &serenity::prelude::Context, batch: &[Message]) -> Vec<Quoted> {
+    let mut quoted: Vec<Quoted> = vec![];
+    let mut seen: Vec<MessageId> = batch.iter().map(|m| m.id).collect();
+    for msg in batch {
+        if let Some(replied) = msg.referenced_message.as_deref() {
+            if !seen.contains(&replied.id) {
+                seen.push(replied.id);
+                quoted.push(quote(replied, msg.guild_id.map(|g| g.get()), replied));
+            }
+        }
+    }
+    for msg in batch.iter().filter(|m| is_forward(m)) {
+        match forwarded(ctx, msg).await {
+            Ok(forwarded) => quoted.extend(forwarded),
+            Err(e) => warn!("Failed to get forwarded message {}: {}", msg.id, e),
+        }
+    }
+    let mut looked_up = 0;
+    for msg in batch {
+        // links in DMs could point anywhere the bot is
+        let Some(guild_id) = msg.guild_id else {
+            continue;
+        };
+        for (guild, channel_id, message_id) in message_links(&msg.content) {
+            if guild != Some(guild_id.get()) || seen.contains(&message_id) {
+                continue;
+            }
+            if looked_up == MAX_LINKED {
+                return quoted;
+            }
+            looked_up += 1;
+            seen.push(message_id);
+            if !can_read(ctx, msg, channel_id).await {
+                continue;
+            }
+            match channel_id.message(ctx, message_id).await {
+                Ok(linked) => quoted.push(quote(&linked, Some(guild_id.get()), &linked)),
+                Err(e) => warn!("Failed to get linked message {}: {}", message_id, e),
+            }
+        }
+    }
+    quoted
+}
+
+/// Whether the asker of `msg` can read `channel_id`, in the same server
+pub async fn can_read(
+    ctx: &serenity::prelude::Context,
+    msg: &Message,
+    channel_id: ChannelId,
+) -> bool {
+    let (Some(guild_id), Some(member)) = (msg.guild_id, msg.member.as_deref()) else {
+        return false;
+    };
+    readable(ctx, guild_id, channel_id, |guild, channel| {
+        guild.partial_member_permissions_in(channel, msg.author.id, member)
+    })
+    .await
+}
+
+/// Whether `channel_id` is in `guild_id` and someone with `permissions` there
+/// can read it
+pub async fn readable(
+    ctx: &serenity::prelude::Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    permissions: impl Fn(&Guild, &GuildChannel) -> Permissions,
+) -> bool {
+    let channel = match channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => channel,
+        Ok(_) => return false,
+        Err(e) => {
+            warn!("Failed to get channel {}: {}", channel_id, e);
+            return false;
+        }
+    };
+    if channel.guild_id != guild_id {
+        return false;
+    }
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return false;
+    };
+    // threads go by their parent's permissions
+    let channel = match channel.thread_metadata {
+        Some(_) => channel.parent_id.and_then(|id| guild.channels.get(&id)),
+        None => Some(&channel),
+    };
+    channel.is_some_and(|channel| {
+        let permissions = permissions(&guild, channel);
+        permissions.view_channel() && permissions.read_message_history()
+    })
+}
+
+/// Whether `msg` forwards another message, which serenity doesn't say: a
+/// plain message pointing at another that isn't a reply or crosspost
+pub fn is_forward(msg: &Message) -> bool {
+    let crosspost = msg
+        .flags
+        .is_some_and(|flags| flags.contains(MessageFlags::IS_CROSSPOST));
+    msg.kind == MessageType::Regular
+        && msg.referenced_message.is_none()
+        && msg.message_reference.is_some()
+        && !crosspost
+}
+
+/// The messages forwarded in `msg`, from the snapshots Discord keeps of them
+async fn forwarded(
+    ctx: &serenity::prelude::Context,
+    msg: &Message,
+) -> Result<Vec<Quoted>, crate::storage::Error> {
+    let route = Route::ChannelMessage {
+        channel_id: msg.channel_id,
+        message_id: msg.id,
+    };
+    let raw = ctx
+        .http
+        .request(Request::new(route, LightMethod::Get))
+        .await?
+        .bytes()
+        .await?;
+    let raw: Value = serde_json::from_slice(&raw)?;
+    let reference = msg
+        .message_reference
+        .as_ref()
+        .expect("checked by is_forward");
+    let link = match reference.message_id {
+        Some(message_id) => format!(
+            "https://discord.com/channels/{}/{}/{}",
+            reference
+                .guild_id
+                .map_or("@me".to_string(), |id| id.to_string()),
+            reference.channel_id,
+            message_id
+        ),
+        None => String::new(),
+    };
+    Ok(snapshots(&raw)
+        .iter()
+        .map(|snapshot| Quoted {
+            link: link.clone(),
+            forwarded: true,
+            ..quote(msg, msg.guild_id.map(|g| g.get()), snapshot)
+        })
+        .collect())
+}
+
+/// The forwarded messages in a raw message payload, with what serenity
+/// reads of them: text, embeds and stickers
+pub fn snapshots(raw: &Value) -> Vec<Message> {
+    let Some(snapshots) = raw["message_snapshots"].as_array() else {
+        return vec![];
+    };
+    snapshots
+        .iter()
+        .map(|snapshot| {
+            let snapshot = &snapshot["message"];
+            let mut msg = Message::default();
+            msg.content = snapshot["content"].as_str().unwrap_or_default().to_string();
+            msg.embeds = serde_json::from_value(snapshot["embeds"].clone()).unwrap_or_default();
+            msg.sticker_items =
+                serde_json::from_value(snapshot["sticker_items"].clone()).unwrap_or_default();
+            msg
+        })
+        .collect()
+}
+
+/// `msg` as written by `by`, who's usually its author
+fn quote(by: &Message, guild_id: Option<u64>, msg: &Message) -> Quoted {
+    let mut content = message_text::of(msg);
+    if let Some((cut, _)) = content.char_indices().nth(MAX_LENGTH) {
+        content.truncate(cut);
+        content.push('…');
+    }
+    let guild = guild_id.map_or("@me".to_string(), |id| id.to_string());
+    Quoted {
+        author: by
+            .author
+            .global_name
+            .clone()
+            .unwrap_or_else(|| by.author.name.clone()),
+        author_id: by.author.id.get(),
+        link: format!(
+            "https://discord.com/channels/{}/{}/{}",
+            guild, msg.channel_id, msg.id
+        ),
+        content,
+        forwarded: false,
+    }
+}
+
+/// Added to the system prompt: the messages the question refers to
+pub fn instructions(quoted: &[Quoted]) -> String {
+    if quoted.is_empty() {
+        return String::new();
+    }
+    let mut text = "\n\nThe question replies to, links or forwards these messages; \"this\" \
+                    and \"that\" in it likely mean them."
+        .to_string();
+    for quote in quoted {
+        let verb = if quote.forwarded {
+            "forwarded"
+        } else {
+            "wrote"
+        };
+        text.push_str(&format!(
+            "\n\n{} ({}) {} ({}):\n{}",
+            quote.author, quote.author_id, verb, quote.link, quote.content
+        ));
+    }
+    text
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::ReleasesConfig,
+    i18n,
+    plugins::{Answer, Command, Context, Plugin},
+    storage::Error,
+    tools::{Tool, Tools},
+};
+
+const API: &str = "https://api.github.com";
+/// How long a release is used before GitHub is asked again; unauthenticated
+/// requests are limited to 60 an hour
+const CACHE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// A published GitHub release
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Release {
+    pub tag_name: String,
+    pub name: Option<String>,
+    pub html_url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub published_at: OffsetDateTime,
+    /// The changelog, in Markdown
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// The latest release of each repository asked about recently
+#[derive(Default)]
+pub struct ReleaseCache {
+    latest: Mutex<HashMap<String, (Instant, Release)>>,
+    /// Asks GitHub every time instead, keeping nothing
+    uncached: bool,
+}
+
+impl ReleaseCache {
+    /// Keeps nothing between lookups, for hosts short on memory
+    pub fn uncached() -> ReleaseCache {
+        ReleaseCache {
+            uncached: true,
+            ..Default::default()
+        }
+    }
+
+    /// The latest release of `repo` (`owner/name`), asking GitHub at most every few minutes
+    pub async fn latest(&self, http: &reqwest::Client, repo: &str) -> Result<Release, Error> {
+        let mut latest = self.latest.lock().await;
+        if let Some((fetched_at, release)) = latest.get(repo) {
+            if fetched_at.elapsed() < CACHE_TTL {
+                return Ok(release.clone());
+            }
+        }
+        let release: Release = http
+            .get(format!("{}/repos/{}/releases/latest", API, repo))
+            // GitHub turns away requests without one
+            .header(reqwest::header::USER_AGENT, "deskhelp")
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if !self.uncached {
+            latest.insert(repo.to_string(), (Instant::now(), release.clone()));
+        }
+        Ok(release)
+    }
+}
+
+/// One line about a release, for the model
+pub fn describe(component: &str, repo: &str, release: &Release) -> String {
+    let mut line = format!(
+        "The latest {} release ({}) is {}",
+        component, repo, release.tag_name
+    );
+    if let Some(name) = release.name.as_deref().filter(|n| *n != release.tag_name) {
+        line.push_str(&format!(" \"{}\"", name));
+    }
+    line.push_str(&format!(
+        ", published {}: {}",
+        release.published_at.date(),
+        release.html_url
+    ));
+    line
+}
+
+/// Lets the model check the latest versions instead of trusting what it was told
+pub struct ReleaseTool {
+    pub http: reqwest::Client,
+    pub cache: Arc<ReleaseCache>,
+    pub config: ReleasesConfig,
+}
+
+#[serenity::async_trait]
+impl Tool for ReleaseTool {
+    fn name(&self) -> &str {
+        "latest_releases"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Gets the latest released version of each of: {}. Use it whenever a question \
+             depends on the current version, since versions you remember may be out of date.",
+            self.config
+                .repos
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": false,
+        })
+    }
+
+    fn links(&self) -> Vec<String> {
+        self.config
+            .repos
+            .values()
+            .map(|repo| format!("https://github.com/{}/releases", repo))
+            .collect()
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<String, Error> {
+        let mut lines = Vec::new();
+        for (component, repo) in &self.config.repos {
+            lines.push(match self.cache.latest(&self.http, repo).await {
+                Ok(release) => describe(component, repo, &release),
+                Err(e) => format!(
+                    "The latest {} release couldn't be looked up: {}",
+                    component, e
+                ),
+            });
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Lets the model and `/version` look up the `[releases]` repositories
+pub struct ReleasesPlugin;
+
+#[serenity::async_trait]
+impl Plugin for ReleasesPlugin {
+    fn name(&self) -> &'static str {
+        "releases"
+    }
+
+    async fn tools(&self, answer: &Answer<'_>, tools: &mut Tools) {
+        if !answer.config.releases.repos.is_empty() {
+            tools.add(ReleaseTool {
+                http: answer.data.http.clone(),
+                cache: answer.data.releases.clone(),
+                config: answer.config.releases.clone(),
+            });
+        }
+    }
+
+    fn commands(&self) -> Vec<Command> {
+        vec![version()]
+    }
+}
+
+/// the latest DeskThing releases
+#[poise::command(slash_command, prefix_command)]
+async fn version(ctx: Context<'_>) -> Result<(), Error> {
+    let config = ctx.data().config();
+    let locale = config.locale(ctx.guild_id());
+    if config.releases.repos.is_empty() {
+        ctx.say(i18n::tr(&locale, "version-none", &[])).await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+    let mut lines = Vec::new();
+    for (component, repo) in &config.releases.repos {
+        lines.push(
+            match ctx.data().releases.latest(&ctx.data().http, repo).await {
+                Ok(release) => i18n::tr(
+                    &locale,
+                    "version-line",
+                    &[
+                        ("component", component.as_str().into()),
+                        ("version", release.tag_name.into()),
+                        ("url", release.html_url.into()),
+                        ("date", release.published_at.date().to_string().into()),
+                    ],
+                ),
+                Err(e) => {
+                    tracing::warn!("Failed to look up the latest release of {}: {}", repo, e);
+                    i18n::tr(
+                        &locale,
+                        "version-unavailable",
+                        &[("component", component.as_str().into())],
+                    )
+                }
+            },
+        );
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
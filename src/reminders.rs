@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, GuildId, Http, UserId};
+use time::Duration;
+
+use crate::{
+    config::SharedConfig,
+    i18n,
+    scheduler::TaskHandler,
+    storage::{Error, Task},
+};
+
+/// Task kind for `/remindme`
+pub const KIND: &str = "reminder";
+
+/// Furthest ahead a reminder can be set
+const MAX_DELAY: Duration = Duration::days(365);
+
+/// What to remind whom of, as stored with the task
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Reminder {
+    pub user_id: UserId,
+    /// Where it was set, to ping the user in if they don't take DMs
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    pub text: String,
+}
+
+/// Reads delays like `2h`, `30m`, `1d12h` or `90s` (and `w` for weeks), up to a year
+pub fn parse_delay(text: &str) -> Option<Duration> {
+    let text = text.trim().to_lowercase();
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: i64 = number.parse().ok()?;
+        number.clear();
+        let unit = match c {
+            's' => Duration::SECOND,
+            'm' => Duration::MINUTE,
+            'h' => Duration::HOUR,
+            'd' => Duration::DAY,
+            'w' => Duration::WEEK,
+            _ => return None,
+        };
+        // out of range before it can overflow a Duration
+        if n > MAX_DELAY.whole_seconds() / unit.whole_seconds() {
+            return None;
+        }
+        total += unit * n as i32;
+        if total > MAX_DELAY {
+            return None;
+        }
+    }
+    // a number without a unit at the end, or nothing at all
+    (number.is_empty() && total > Duration::ZERO).then_some(total)
+}
+
+/// Sends reminders by DM, or with a ping where they were set if DMs are closed
+pub struct ReminderHandler {
+    pub http: Arc<Http>,
+    pub config: SharedConfig,
+}
+
+#[serenity::async_trait]
+impl TaskHandler for ReminderHandler {
+    async fn run(&self, task: &Task) -> Result<(), Error> {
+        let reminder: Reminder = serde_json::from_value(task.payload.clone())?;
+        let locale = self.config.read().unwrap().locale(reminder.guild_id);
+        let text = i18n::tr(
+            &locale,
+            "reminder",
+            &[("text", reminder.text.as_str().into())],
+        );
+        let dm = match reminder.user_id.create_dm_channel(&self.http).await {
+            Ok(dm) => {
+                dm.send_message(&self.http, CreateMessage::new().content(&text))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if dm.is_ok() {
+            return Ok(());
+        }
+        reminder
+            .channel_id
+            .send_message(
+                &self.http,
+                CreateMessage::new()
+                    .content(format!("<@{}> {}", reminder.user_id, text))
+                    .allowed_mentions(CreateAllowedMentions::new().users([reminder.user_id])),
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::Write;
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use futures::TryStreamExt;
+use serenity::all::GuildId;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::{
+    config::Config,
+    content_policy::Policy,
+    models::{self, Capabilities},
+    oai, prompt,
+    provider::{ChatBackend, Providers},
+};
+
+/// Runs the answer pipeline against stdin and stdout instead of Discord, answering
+/// as if in `guild_id` (for its provider, model, and link allowlist).
+/// `/reset` clears the conversation. The prompt file is reread for every question.
+pub async fn run(config: &Config, providers: &Providers, guild_id: Option<GuildId>) {
+    let (provider, model) = providers.for_guild(config, guild_id);
+    let capabilities = Capabilities::of(config, &model);
+    let mut context: Vec<ChatCompletionRequestMessage> = vec![];
+
+    println!(
+        "deskhelp repl using {}. /reset clears the conversation, Ctrl-D quits.",
+        model
+    );
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().expect("failed to flush stdout");
+        let Ok(Some(line)) = lines.next_line().await else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "/reset" {
+            context.clear();
+            println!("Conversation cleared.");
+            continue;
+        }
+
+        // reread every time, to try out prompt edits without restarting
+        let instructions = match prompt::read(config) {
+            Ok(instructions) => instructions,
+            Err(e) => {
+                println!("Couldn't read the prompt file: {}", e);
+                continue;
+            }
+        };
+        let allowed_links = oai::allowed_links(config, guild_id, &instructions);
+
+        context.push(ChatCompletionRequestMessage::User(
+            ChatCompletionRequestUserMessage {
+                content: ChatCompletionRequestUserMessageContent::Text(format!(
+                    "developer (0): {}",
+                    line
+                )),
+                ..Default::default()
+            },
+        ));
+        let (messages, prompt_tokens) = oai::build_prompt(
+            oai::system_prompt(
+                &instructions,
+                &oai::PromptContext {
+                    bot_name: "DeskHelp",
+                    bot_id: "0",
+                    guild_name: "REPL",
+                    channel_name: "repl",
+                    active_model: &model,
+                    timezone: &config.timezone(guild_id),
+                    channel_prompt: "",
+                    role_prompt: "",
+                    user_profile: "",
+                },
+            ),
+            &context,
+            capabilities.context_tokens.unwrap_or_else(oai::token_limit),
+            &[],
+        );
+
+        let start_time = std::time::Instant::now();
+        let mut stream = match provider
+            .create_stream(oai::chat_request(
+                models::resolve(config, &model),
+                &capabilities,
+                messages,
+            ))
+            .await
+        {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to create stream: {}", e);
+                context.pop();
+                continue;
+            }
+        };
+
+        let mut response = String::new();
+        let mut finished = false;
+        while let Ok(Some(chunk)) = stream.try_next().await {
+            let Some(choice) = chunk.choices.first() else {
+                continue;
+            };
+            if let Some(content) = &choice.delta.content {
+                print!("{}", content);
+                std::io::stdout().flush().expect("failed to flush stdout");
+                response.push_str(content);
+            }
+            if choice.finish_reason.is_some() {
+                finished = true;
+                break;
+            }
+        }
+        println!();
+        if !finished {
+            eprintln!("Error while streaming response!");
+            context.pop();
+            continue;
+        }
+
+        let assistant_message =
+            ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+                content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                    response.clone(),
+                )),
+                ..Default::default()
+            });
+        println!(
+            "-- {:.3}s, {} prompt tokens, {} completion tokens",
+            start_time.elapsed().as_secs_f64(),
+            prompt_tokens,
+            oai::count_tokens(&assistant_message)
+        );
+        let locale = config.locale(guild_id);
+        match Policy::new(config, guild_id, &locale, allowed_links).check(&response) {
+            Ok(posted) if posted != response => println!("-- as posted to Discord:\n{}", posted),
+            Ok(_) => {}
+            Err(topic) => println!(
+                "-- replaced on Discord, for touching the blocked topic {}",
+                topic
+            ),
+        }
+        context.push(assistant_message);
+    }
+}
@@ -0,0 +1,184 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::OnceLock,
+};
+
+use rand::Rng;
+use serde::Serialize;
+use serenity::all::{ChannelId, GuildId};
+use tracing::warn;
+
+use crate::{
+    config::ErrorReportingConfig,
+    events::{self, Event},
+};
+
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+struct Sink {
+    config: ErrorReportingConfig,
+    http: reqwest::Client,
+    #[cfg(feature = "sentry")]
+    _sentry: Option<sentry::ClientInitGuard>,
+}
+
+/// Where an error happened. The channel is only ever reported hashed.
+pub struct ErrorContext<'a> {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub model: &'a str,
+}
+
+#[derive(Serialize)]
+struct WebhookReport<'a> {
+    message: &'a str,
+    kind: &'a str,
+    guild: Option<String>,
+    channel_hash: Option<String>,
+    model: Option<&'a str>,
+    environment: Option<&'a str>,
+}
+
+/// Starts reporting errors and panics to the configured backends
+pub fn init(config: Option<&ErrorReportingConfig>, http: reqwest::Client) {
+    let Some(config) = config else {
+        return;
+    };
+
+    #[cfg(feature = "sentry")]
+    let _sentry = config.sentry_dsn.as_deref().map(|dsn| {
+        let mut options = sentry::ClientOptions::new()
+            .dsn(dsn)
+            .maybe_release(sentry::release_name!())
+            .sample_rate(config.sample_rate)
+            .send_default_pii(false)
+            .before_send(|mut event| {
+                event.message = event.message.map(|m| scrub(&m));
+                for exception in event.exception.values.iter_mut() {
+                    exception.value = exception.value.as_deref().map(scrub);
+                }
+                Some(event)
+            });
+        if let Some(environment) = config.environment.clone() {
+            options = options.environment(environment);
+        }
+        sentry::init(options)
+    });
+    #[cfg(not(feature = "sentry"))]
+    if config.sentry_dsn.is_some() {
+        warn!("sentry_dsn is set but deskhelp was built without the sentry feature");
+    }
+
+    if config.webhook_url.is_some() {
+        // chain onto the existing hook, which prints the panic (and reports it to Sentry)
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            send_to_webhook(&info.to_string(), "panic", None);
+            previous(info);
+        }));
+    }
+
+    let _ = SINK.set(Sink {
+        config: config.clone(),
+        http,
+        #[cfg(feature = "sentry")]
+        _sentry,
+    });
+}
+
+/// Reports a handler error, subject to sampling
+pub fn capture(message: &str, context: Option<&ErrorContext>) {
+    // Sentry samples on its own
+    #[cfg(feature = "sentry")]
+    if SINK.get().is_some_and(|sink| sink._sentry.is_some()) {
+        sentry::with_scope(
+            |scope| {
+                if let Some(context) = context {
+                    if let Some(guild_id) = context.guild_id {
+                        scope.set_tag("guild", guild_id);
+                    }
+                    scope.set_tag("channel_hash", channel_hash(context.channel_id));
+                    scope.set_tag("model", context.model);
+                }
+            },
+            || sentry::capture_message(message, sentry::Level::Error),
+        );
+    }
+
+    send_to_webhook(message, "error", context);
+    events::publish(Event::Error {
+        at: time::OffsetDateTime::now_utc(),
+        message: scrub(message),
+        guild_id: context.and_then(|c| c.guild_id),
+        model: context.map(|c| c.model.to_string()),
+    });
+}
+
+fn send_to_webhook(message: &str, kind: &str, context: Option<&ErrorContext>) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let Some(url) = sink.config.webhook_url.clone() else {
+        return;
+    };
+    if !rand::thread_rng().gen_bool(sink.config.sample_rate.clamp(0.0, 1.0) as f64) {
+        return;
+    }
+    // panics can happen outside the runtime, in which case there's nothing to send with
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+
+    let report = WebhookReport {
+        message: &scrub(message),
+        kind,
+        guild: context.and_then(|c| c.guild_id).map(|g| g.to_string()),
+        channel_hash: context.map(|c| channel_hash(c.channel_id)),
+        model: context.map(|c| c.model),
+        environment: sink.config.environment.as_deref(),
+    };
+    let request = sink.http.post(url).json(&report);
+    runtime.spawn(async move {
+        if let Err(e) = request.send().await {
+            warn!("Failed to send error report: {}", e);
+        }
+    });
+}
+
+/// Identifies a channel across reports without revealing which one it is
+fn channel_hash(channel_id: ChannelId) -> String {
+    let mut hasher = DefaultHasher::new();
+    channel_id.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Strips things that identify people or grant access: mentions, Discord ids,
+/// email addresses, and API keys or tokens
+pub fn scrub(text: &str) -> String {
+    text.split_inclusive(char::is_whitespace)
+        .map(|piece| {
+            let word = piece.trim_end();
+            let rest = &piece[word.len()..];
+            let trimmed = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '<');
+            let replacement = if word.starts_with("<@") || word.starts_with("<#") {
+                Some("<mention>")
+            } else if trimmed.len() >= 15 && trimmed.chars().all(|c| c.is_ascii_digit()) {
+                Some("[id]")
+            } else if ["sk-", "gsk_", "Bearer"]
+                .iter()
+                .any(|p| trimmed.starts_with(p))
+                || (trimmed.len() >= 50 && trimmed.matches('.').count() == 2)
+            {
+                Some("[secret]")
+            } else if trimmed.contains('@') && trimmed.contains('.') {
+                Some("[email]")
+            } else {
+                None
+            };
+            match replacement {
+                Some(r) => format!("{}{}", r, rest),
+                None => piece.to_string(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,478 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{
+    ButtonStyle, ChannelId, ComponentInteraction, CreateActionRow, CreateAllowedMentions,
+    CreateButton, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateInteractionResponseMessage, CreateMessage, EditThread, GuildId, Http, Message, MessageId,
+    ModalInteractionCollector, UserId,
+};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
+
+use crate::{
+    config::SharedConfig,
+    escalate, i18n,
+    markdown::fit_message,
+    oai,
+    scheduler::TaskHandler,
+    storage::{Error, IndexedAnswer, Rating, Storage, Task},
+    Data,
+};
+
+/// Task kind for asking whether an answer in a thread solved the problem
+pub const KIND: &str = "resolution_check";
+/// Start of the buttons' `custom_id`s, followed by `yes`/`no`, the answer and the asker
+const BUTTON_PREFIX: &str = "deskhelp-resolved-";
+/// Start of the rate button's `custom_id`, followed by the asker and whether staff took over
+const RATE_PREFIX: &str = "deskhelp-rate-";
+/// How long someone has to fill in the rating form
+const SURVEY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+const SUMMARY_INSTRUCTIONS: &str = "A Discord support thread was just solved. Summarize the \
+conversation below for whoever finds the thread later: what the problem was and what fixed \
+it. At most 3 short Markdown bullet points, no greeting.";
+
+/// A thread to ask in once it's been quiet for a while, as stored with the task
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Check {
+    pub guild_id: GuildId,
+    pub thread_id: ChannelId,
+    pub asker_id: UserId,
+    /// Where the answer starts, as indexed for search
+    pub answer_id: MessageId,
+    /// The thread's last message; anything after it means it's still going
+    pub last_id: MessageId,
+    /// Support staff took over, so it's asked again whenever the thread goes quiet
+    #[serde(default)]
+    pub escalated: bool,
+}
+
+/// Schedules asking whether the answer to `msg` solved it, if it was asked in
+/// a thread of a guild with `resolution_check_minutes`
+pub async fn schedule(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    msg: &Message,
+    answer_id: MessageId,
+    last_id: MessageId,
+) -> Result<(), Error> {
+    let config = data.config();
+    let (Some(guild_id), Some(minutes)) = (
+        msg.guild_id,
+        config
+            .guild(msg.guild_id)
+            .and_then(|g| g.resolution_check_minutes),
+    ) else {
+        return Ok(());
+    };
+    let in_thread = msg
+        .channel_id
+        .to_channel(ctx)
+        .await?
+        .guild()
+        .is_some_and(|c| c.thread_metadata.is_some());
+    if !in_thread {
+        return Ok(());
+    }
+    let check = Check {
+        guild_id,
+        thread_id: msg.channel_id,
+        asker_id: msg.author.id,
+        answer_id,
+        last_id,
+        escalated: false,
+    };
+    data.scheduler
+        .schedule(KIND, due_at(minutes), &serde_json::to_value(&check)?)
+        .await?;
+    Ok(())
+}
+
+fn due_at(minutes: u64) -> OffsetDateTime {
+    OffsetDateTime::now_utc() + Duration::minutes(minutes as i64)
+}
+
+/// What a yes or no button under the question is about
+#[derive(Debug, PartialEq)]
+pub struct Answer {
+    pub solved: bool,
+    pub answer_id: MessageId,
+    pub asker_id: UserId,
+    pub escalated: bool,
+}
+
+impl Answer {
+    pub fn button_id(&self) -> String {
+        format!(
+            "{}{}-{}-{}-{}",
+            BUTTON_PREFIX,
+            if self.solved { "yes" } else { "no" },
+            self.answer_id,
+            self.asker_id,
+            u8::from(self.escalated)
+        )
+    }
+
+    /// The button pressed, if it's one of ours
+    pub fn parse(custom_id: &str) -> Option<Answer> {
+        let mut parts = custom_id.strip_prefix(BUTTON_PREFIX)?.split('-');
+        let solved = match parts.next()? {
+            "yes" => true,
+            "no" => false,
+            _ => return None,
+        };
+        let answer = Answer {
+            solved,
+            answer_id: MessageId::new(parts.next()?.parse().ok()?),
+            asker_id: UserId::new(parts.next()?.parse().ok()?),
+            escalated: parts.next()? == "1",
+        };
+        parts.next().is_none().then_some(answer)
+    }
+}
+
+/// The `custom_id` of the button for rating the help in a closed thread
+pub fn rate_button_id(asker_id: UserId, escalated: bool) -> String {
+    format!("{}{}-{}", RATE_PREFIX, asker_id, u8::from(escalated))
+}
+
+/// Whose question a rate button is for, and whether staff took over, if it's ours
+pub fn parse_rate_button(custom_id: &str) -> Option<(UserId, bool)> {
+    let (asker_id, escalated) = custom_id.strip_prefix(RATE_PREFIX)?.split_once('-')?;
+    Some((UserId::new(asker_id.parse().ok()?), escalated == "1"))
+}
+
+/// Asks the asker whether the answer solved it, unless the thread moved on or closed
+pub struct ResolutionHandler {
+    pub http: Arc<Http>,
+    pub config: SharedConfig,
+    pub storage: Arc<dyn Storage>,
+}
+
+#[serenity::async_trait]
+impl TaskHandler for ResolutionHandler {
+    async fn run(&self, task: &Task) -> Result<(), Error> {
+        let check: Check = serde_json::from_value(task.payload.clone())?;
+        let thread = check
+            .thread_id
+            .to_channel(&self.http)
+            .await?
+            .guild()
+            .ok_or("the thread isn't in a guild")?;
+        if thread.thread_metadata.is_some_and(|m| m.archived) {
+            return Ok(());
+        }
+        let config = self.config.read().unwrap().clone();
+        if thread.last_message_id != Some(check.last_id) {
+            // people take a while to sort things out; ask once they're done
+            let minutes = config
+                .guild(Some(check.guild_id))
+                .and_then(|g| g.resolution_check_minutes);
+            if let (true, Some(minutes), Some(last_id)) =
+                (check.escalated, minutes, thread.last_message_id)
+            {
+                let check = Check { last_id, ..check };
+                self.storage
+                    .add_task(KIND, due_at(minutes), &serde_json::to_value(&check)?)
+                    .await?;
+            }
+            return Ok(());
+        }
+        let locale = config.locale(Some(check.guild_id));
+        let answer = |solved| Answer {
+            solved,
+            answer_id: check.answer_id,
+            asker_id: check.asker_id,
+            escalated: check.escalated,
+        };
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(answer(true).button_id())
+                .label(i18n::tr(&locale, "resolution-yes", &[]))
+                .style(ButtonStyle::Success),
+            CreateButton::new(answer(false).button_id())
+                .label(i18n::tr(&locale, "resolution-no", &[]))
+                .style(ButtonStyle::Secondary),
+        ]);
+        let content = i18n::tr(
+            &locale,
+            "resolution-ask",
+            &[("user", format!("<@{}>", check.asker_id).into())],
+        );
+        check
+            .thread_id
+            .send_message(
+                &self.http,
+                CreateMessage::new()
+                    .content(content)
+                    .components(vec![buttons])
+                    .allowed_mentions(CreateAllowedMentions::new().users([check.asker_id])),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Closes the thread with a summary if the asker (or support staff) says it's
+/// solved, and hands it over to support if not
+pub async fn on_press(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+    answer: Answer,
+) {
+    let config = data.config();
+    let locale = config.locale(press.guild_id);
+    let is_staff = match (config.guild(press.guild_id), &press.member) {
+        (Some(guild), Some(member)) => member
+            .roles
+            .iter()
+            .any(|r| guild.support_roles.contains(&r.get())),
+        _ => false,
+    };
+    if press.user.id != answer.asker_id && !is_staff {
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(i18n::tr(&locale, "resolution-not-yours", &[]))
+                .ephemeral(true),
+        );
+        if let Err(e) = press.create_response(&ctx.http, response).await {
+            warn!("Failed to answer a resolution button: {}", e);
+        }
+        return;
+    }
+
+    // the buttons only work once; a solved thread can be rated instead
+    let response = if answer.solved {
+        let rate = CreateButton::new(rate_button_id(answer.asker_id, answer.escalated))
+            .label(i18n::tr(&locale, "rating-button", &[]))
+            .style(ButtonStyle::Primary);
+        CreateInteractionResponseMessage::new()
+            .content(i18n::tr(&locale, "resolution-solved", &[]))
+            .components(vec![CreateActionRow::Buttons(vec![rate])])
+    } else {
+        CreateInteractionResponseMessage::new()
+            .content(i18n::tr(&locale, "resolution-unsolved", &[]))
+            .components(vec![])
+    };
+    let response = CreateInteractionResponse::UpdateMessage(response);
+    if let Err(e) = press.create_response(&ctx.http, response).await {
+        warn!("Failed to answer a resolution button: {}", e);
+        return;
+    }
+    info!(
+        "{} says the answer in {} solved it: {}",
+        press.user.id, press.channel_id, answer.solved
+    );
+    let result = if answer.solved {
+        close(ctx, data, press, &answer).await
+    } else {
+        hand_over(ctx, data, press, &answer).await
+    };
+    if let Err(e) = result {
+        warn!("Failed to follow up on whether a thread was solved: {}", e);
+        let followup = CreateInteractionResponseFollowup::new()
+            .content(i18n::tr(&locale, "resolution-failed", &[]))
+            .ephemeral(true);
+        if let Err(e) = press.create_followup(&ctx.http, followup).await {
+            warn!("Failed to answer a resolution button: {}", e);
+        }
+    }
+}
+
+/// Posts a summary of how the thread was solved, trusts the answer so the
+/// model is shown it for similar questions, and archives the thread
+async fn close(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+    answer: &Answer,
+) -> Result<(), Error> {
+    let config = data.config();
+    let conversation = data.ai_context.get(press.channel_id).await?;
+    let (provider, model) = data.providers.for_guild(&config, press.guild_id);
+    let transcript = escalate::transcript(&conversation);
+    match oai::complete(provider, &config, &model, SUMMARY_INSTRUCTIONS, &transcript).await {
+        Ok(summary) => {
+            let locale = config.locale(press.guild_id);
+            let content = i18n::tr(&locale, "resolution-summary", &[]) + "\n" + summary.as_str();
+            press
+                .channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new().content(fit_message(content)),
+                )
+                .await?;
+        }
+        Err(e) => warn!("Failed to summarize the solved thread: {}", e),
+    }
+
+    // only answers indexed for `[search]` can be trusted, and only if it
+    // wasn't staff who solved it
+    let indexed = match answer.escalated {
+        false => data.storage.indexed_answer(answer.answer_id).await?,
+        true => None,
+    };
+    if let Some(indexed) = indexed.filter(|a| !a.trusted) {
+        data.storage
+            .index_answer(&IndexedAnswer {
+                trusted: true,
+                ..indexed
+            })
+            .await?;
+        info!("Answer {} is now trusted", answer.answer_id);
+    }
+
+    press
+        .channel_id
+        .edit_thread(&ctx.http, EditThread::new().archived(true))
+        .await?;
+    Ok(())
+}
+
+/// Hands the thread to support, asking again once they've gone quiet
+async fn hand_over(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+    answer: &Answer,
+) -> Result<(), Error> {
+    let thread = escalate::hand_over(ctx, data, press).await?.thread();
+    let minutes = data
+        .config()
+        .guild(press.guild_id)
+        .and_then(|g| g.resolution_check_minutes);
+    let last_id = thread
+        .to_channel(ctx)
+        .await?
+        .guild()
+        .and_then(|t| t.last_message_id);
+    let (Some(guild_id), Some(minutes), Some(last_id)) = (press.guild_id, minutes, last_id) else {
+        return Ok(());
+    };
+    let check = Check {
+        guild_id,
+        thread_id: thread,
+        asker_id: answer.asker_id,
+        answer_id: answer.answer_id,
+        last_id,
+        escalated: true,
+    };
+    data.scheduler
+        .schedule(KIND, due_at(minutes), &serde_json::to_value(&check)?)
+        .await?;
+    Ok(())
+}
+
+#[derive(poise::Modal)]
+#[name = "How did we do?"]
+struct Survey {
+    #[name = "Rating from 1 (poor) to 5 (great)"]
+    #[min_length = 1]
+    #[max_length = 1]
+    rating: String,
+    #[name = "Anything to add? (optional)"]
+    #[paragraph]
+    #[max_length = 1000]
+    comment: Option<String>,
+}
+
+/// Asks the asker to rate the help in a closed thread, and keeps the rating
+/// with the conversation
+pub async fn on_rate(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    press: &ComponentInteraction,
+    (asker_id, escalated): (UserId, bool),
+) {
+    let locale = data.config().locale(press.guild_id);
+    if press.user.id != asker_id {
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content(i18n::tr(&locale, "rating-not-yours", &[]))
+                .ephemeral(true),
+        );
+        if let Err(e) = press.create_response(&ctx.http, response).await {
+            warn!("Failed to answer a rate button: {}", e);
+        }
+        return;
+    }
+    // answered here rather than by poise, to reply to the form itself
+    let form_id = format!("{}-survey", press.id);
+    let form = <Survey as poise::Modal>::create(None, form_id.clone());
+    if let Err(e) = press.create_response(&ctx.http, form).await {
+        warn!("Failed to show the rating form: {}", e);
+        return;
+    }
+    let Some(submitted) = ModalInteractionCollector::new(ctx)
+        .custom_ids(vec![form_id])
+        .timeout(SURVEY_TIMEOUT)
+        .await
+    else {
+        return;
+    };
+    let content = match <Survey as poise::Modal>::parse(submitted.data.clone()) {
+        Ok(survey) => match record(data, press, escalated, survey).await {
+            Ok(true) => i18n::tr(&locale, "rating-thanks", &[]),
+            Ok(false) => i18n::tr(&locale, "rating-invalid", &[]),
+            Err(e) => {
+                warn!("Failed to save a rating: {}", e);
+                i18n::tr(&locale, "resolution-failed", &[])
+            }
+        },
+        Err(e) => {
+            warn!("Failed to read the rating form: {}", e);
+            i18n::tr(&locale, "resolution-failed", &[])
+        }
+    };
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    );
+    if let Err(e) = submitted.create_response(&ctx.http, response).await {
+        warn!("Failed to answer a rating: {}", e);
+    }
+}
+
+/// Keeps the rating, returning whether it was a number from 1 to 5
+async fn record(
+    data: &Data,
+    press: &ComponentInteraction,
+    escalated: bool,
+    survey: Survey,
+) -> Result<bool, Error> {
+    let (Some(guild_id), Some(score)) = (press.guild_id, parse_score(&survey.rating)) else {
+        return Ok(false);
+    };
+    let conversation = data.ai_context.get(press.channel_id).await?;
+    let comment = survey
+        .comment
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty());
+    data.storage
+        .record_rating(&Rating {
+            guild_id,
+            channel_id: press.channel_id,
+            user_id: press.user.id,
+            at: OffsetDateTime::now_utc(),
+            score,
+            comment,
+            escalated,
+            transcript: escalate::transcript(&conversation),
+        })
+        .await?;
+    info!(
+        "{} rated the help in {} {}/5",
+        press.user.id, press.channel_id, score
+    );
+    Ok(true)
+}
+
+/// A rating typed into the form, if it's from 1 to 5
+pub fn parse_score(text: &str) -> Option<u8> {
+    text.trim()
+        .parse()
+        .ok()
+        .filter(|score| (1..=5).contains(score))
+}
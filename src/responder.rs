@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serenity::{
+    all::{
+        ChannelId, ChannelType, CreateActionRow, CreateAttachment, CreateWebhook, EditMessage,
+        EditWebhookMessage, ExecuteWebhook, Http, Message, MessageFlags, ReactionType, UserId,
+        Webhook,
+    },
+    http::HttpError,
+};
+
+use tracing::warn;
+
+use crate::{config::PersonaConfig, telemetry};
+
+/// Name of the webhook we create in channels to post persona messages through
+const WEBHOOK_NAME: &str = "DeskHelp";
+
+/// Discord's error code for a webhook that no longer exists
+const UNKNOWN_WEBHOOK: isize = 10015;
+
+pub type WebhookCache = Arc<Mutex<HashMap<ChannelId, Webhook>>>;
+
+/// How a response gets posted
+pub enum Responder {
+    /// Replies to the triggering message from the bot account
+    Reply(Box<Message>),
+    /// Fresh messages from a channel webhook, posing as a persona
+    Webhook {
+        webhook: Box<ChannelWebhook>,
+        persona: PersonaConfig,
+    },
+    /// Posts nothing; the answer only gets logged
+    DryRun,
+}
+
+impl Responder {
+    /// Uses a webhook if a persona is configured and we can get one for the
+    /// channel, and plain replies otherwise
+    pub async fn new(
+        ctx: &serenity::prelude::Context,
+        msg: &Message,
+        persona: Option<&PersonaConfig>,
+        webhooks: &WebhookCache,
+    ) -> Responder {
+        let Some(persona) = persona else {
+            return Responder::Reply(Box::new(msg.clone()));
+        };
+
+        // webhooks live on the parent channel and post into threads from there
+        let (channel_id, thread_id) = match msg.channel_id.to_channel(ctx).await {
+            Ok(channel) => match channel.guild() {
+                Some(gc)
+                    if matches!(
+                        gc.kind,
+                        ChannelType::PublicThread
+                            | ChannelType::PrivateThread
+                            | ChannelType::NewsThread
+                    ) =>
+                {
+                    (gc.parent_id.unwrap_or(gc.id), Some(gc.id))
+                }
+                _ => (msg.channel_id, None),
+            },
+            Err(_) => (msg.channel_id, None),
+        };
+
+        let self_id = ctx.cache.current_user().id;
+        match get_webhook(&ctx.http, self_id, channel_id, webhooks).await {
+            Ok(webhook) => Responder::Webhook {
+                webhook: Box::new(ChannelWebhook {
+                    webhook: Mutex::new(webhook),
+                    self_id,
+                    channel_id,
+                    thread_id,
+                    webhooks: webhooks.clone(),
+                }),
+                persona: persona.clone(),
+            },
+            Err(e) => {
+                warn!(
+                    "Failed to get webhook for {}, replying instead: {}",
+                    channel_id, e
+                );
+                Responder::Reply(Box::new(msg.clone()))
+            }
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self, Responder::DryRun)
+    }
+
+    #[tracing::instrument(name = "discord.send", skip_all)]
+    pub async fn send(&self, http: &Http, content: &str) -> serenity::Result<Message> {
+        telemetry::record_discord_call("send");
+        match self {
+            Responder::Reply(msg) => msg.reply(http, content).await,
+            Responder::DryRun => {
+                let mut sent = Message::default();
+                sent.content = content.to_string();
+                Ok(sent)
+            }
+            Responder::Webhook { webhook, persona } => {
+                let mut builder = ExecuteWebhook::new()
+                    .content(content)
+                    .username(&persona.name)
+                    .flags(MessageFlags::SUPPRESS_EMBEDS);
+                if let Some(avatar_url) = &persona.avatar_url {
+                    builder = builder.avatar_url(avatar_url);
+                }
+                webhook.execute(http, builder).await
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "discord.edit", skip_all)]
+    pub async fn edit(
+        &self,
+        http: &Http,
+        sent: &mut Message,
+        content: &str,
+    ) -> serenity::Result<()> {
+        telemetry::record_discord_call("edit");
+        match self {
+            Responder::DryRun => {
+                sent.content = content.to_string();
+                Ok(())
+            }
+            Responder::Reply(_) => {
+                let builder = EditMessage::new().content(content).suppress_embeds(true);
+                sent.edit(http, builder).await
+            }
+            Responder::Webhook { webhook, .. } => {
+                webhook
+                    .edit(http, sent, EditWebhookMessage::new().content(content))
+                    .await
+            }
+        }
+    }
+
+    /// Adds files to a message we already sent
+    #[tracing::instrument(name = "discord.attach", skip_all)]
+    pub async fn attach(
+        &self,
+        http: &Http,
+        sent: &mut Message,
+        files: Vec<CreateAttachment>,
+    ) -> serenity::Result<()> {
+        telemetry::record_discord_call("edit");
+        match self {
+            Responder::DryRun => Ok(()),
+            Responder::Reply(_) => {
+                let builder = files
+                    .into_iter()
+                    .fold(EditMessage::new(), |b, f| b.new_attachment(f));
+                sent.edit(http, builder).await
+            }
+            Responder::Webhook { webhook, .. } => {
+                let builder = files
+                    .into_iter()
+                    .fold(EditWebhookMessage::new(), |b, f| b.new_attachment(f));
+                webhook.edit(http, sent, builder).await
+            }
+        }
+    }
+
+    /// Puts buttons and menus under a message we already sent
+    #[tracing::instrument(name = "discord.components", skip_all)]
+    pub async fn components(
+        &self,
+        http: &Http,
+        sent: &mut Message,
+        rows: Vec<CreateActionRow>,
+    ) -> serenity::Result<()> {
+        telemetry::record_discord_call("edit");
+        match self {
+            Responder::DryRun => Ok(()),
+            Responder::Reply(_) => sent.edit(http, EditMessage::new().components(rows)).await,
+            Responder::Webhook { webhook, .. } => {
+                webhook
+                    .edit(http, sent, EditWebhookMessage::new().components(rows))
+                    .await
+            }
+        }
+    }
+
+    /// Reacts to a message we sent, as the bot
+    #[tracing::instrument(name = "discord.react", skip_all)]
+    pub async fn react(&self, http: &Http, sent: &Message, emoji: &str) -> serenity::Result<()> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        telemetry::record_discord_call("react");
+        sent.react(http, ReactionType::Unicode(emoji.to_string()))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Our webhook in the channel a persona answers in
+pub struct ChannelWebhook {
+    /// Swapped for a new one if someone deletes it
+    webhook: Mutex<Webhook>,
+    /// The bot's user, which owns the webhook
+    self_id: UserId,
+    /// The channel the webhook belongs to
+    channel_id: ChannelId,
+    /// The thread it posts into, if any
+    thread_id: Option<ChannelId>,
+    webhooks: WebhookCache,
+}
+
+impl ChannelWebhook {
+    async fn execute(&self, http: &Http, mut builder: ExecuteWebhook) -> serenity::Result<Message> {
+        if let Some(thread_id) = self.thread_id {
+            builder = builder.in_thread(thread_id);
+        }
+        let current = self.webhook.lock().unwrap().clone();
+        let sent = match current.execute(http, true, builder.clone()).await {
+            // deleted since we cached it: make another and try once more
+            Err(e) if is_unknown_webhook(&e) => {
+                self.webhooks.lock().unwrap().remove(&self.channel_id);
+                let fresh =
+                    get_webhook(http, self.self_id, self.channel_id, &self.webhooks).await?;
+                *self.webhook.lock().unwrap() = fresh.clone();
+                fresh.execute(http, true, builder).await?
+            }
+            sent => sent?,
+        };
+        Ok(sent.expect("webhook execute with wait returned no message"))
+    }
+
+    /// Edits a message the webhook sent. Only the webhook that sent a message
+    /// can edit it, so if that one was deleted the edit fails, but the next
+    /// answer gets a new webhook.
+    async fn edit(
+        &self,
+        http: &Http,
+        sent: &mut Message,
+        mut builder: EditWebhookMessage,
+    ) -> serenity::Result<()> {
+        if let Some(thread_id) = self.thread_id {
+            builder = builder.in_thread(thread_id);
+        }
+        let current = self.webhook.lock().unwrap().clone();
+        match current.edit_message(http, sent.id, builder).await {
+            Ok(edited) => {
+                *sent = edited;
+                Ok(())
+            }
+            Err(e) => {
+                if is_unknown_webhook(&e) {
+                    self.webhooks.lock().unwrap().remove(&self.channel_id);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Reuses our webhook in the channel, creating it the first time
+async fn get_webhook(
+    http: &Http,
+    self_id: UserId,
+    channel_id: ChannelId,
+    webhooks: &WebhookCache,
+) -> serenity::Result<Webhook> {
+    if let Some(webhook) = webhooks.lock().unwrap().get(&channel_id) {
+        return Ok(webhook.clone());
+    }
+
+    let existing = channel_id
+        .webhooks(http)
+        .await?
+        .into_iter()
+        .find(|w| w.token.is_some() && w.user.as_ref().is_some_and(|u| u.id == self_id));
+    let webhook = match existing {
+        Some(webhook) => webhook,
+        None => {
+            channel_id
+                .create_webhook(http, CreateWebhook::new(WEBHOOK_NAME))
+                .await?
+        }
+    };
+
+    webhooks.lock().unwrap().insert(channel_id, webhook.clone());
+    Ok(webhook)
+}
+
+fn is_unknown_webhook(e: &serenity::Error) -> bool {
+    matches!(
+        e,
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response))
+            if response.error.code == UNKNOWN_WEBHOOK
+    )
+}
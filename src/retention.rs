@@ -0,0 +1,57 @@
+use std::{sync::Arc, time::Duration};
+
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    config::RetentionConfig,
+    storage::{Error, Purge},
+    Data,
+};
+
+/// How often to purge data past its retention window
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Purges data older than `[retention]` allows, now and every hour
+pub fn spawn_purge(data: Arc<Data>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = purge(&data).await {
+                warn!("Failed to purge old data: {}", e);
+            }
+            tokio::time::sleep(PURGE_INTERVAL).await;
+        }
+    });
+}
+
+async fn purge(data: &Data) -> Result<(), Error> {
+    let retention = data.config().retention.clone();
+    let now = OffsetDateTime::now_utc();
+    let records = data.storage.purge(&cutoffs(&retention, now)).await?;
+    let conversations = match retention.conversations {
+        Some(days) => data.ai_context.clear_idle(before(now, days)).await?,
+        None => 0,
+    };
+    if records > 0 || conversations > 0 {
+        info!(
+            "Purged {} records and {} idle conversations past retention",
+            records, conversations
+        );
+    }
+    Ok(())
+}
+
+/// What storage keeps from, for each kind of record the policy covers
+pub fn cutoffs(retention: &RetentionConfig, now: OffsetDateTime) -> Purge {
+    let cutoff = |days: Option<u32>| days.map(|days| before(now, days));
+    Purge {
+        answers: cutoff(retention.answers),
+        indexed_answers: cutoff(retention.search),
+        question_embeddings: cutoff(retention.duplicates),
+        deletions: cutoff(retention.deletions),
+    }
+}
+
+fn before(now: OffsetDateTime, days: u32) -> OffsetDateTime {
+    now - time::Duration::days(days.into())
+}
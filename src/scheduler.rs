@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::storage::{Error, Storage, Task};
+
+/// How often to look for due tasks, and so how late one may run
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Runs one kind of task when it's due
+#[serenity::async_trait]
+pub trait TaskHandler: Send + Sync {
+    async fn run(&self, task: &Task) -> Result<(), Error>;
+}
+
+/// Work to do later, kept in storage so it survives restarts. Features schedule
+/// tasks of their own kind and register a handler to run them.
+pub struct Scheduler {
+    storage: Arc<dyn Storage>,
+    handlers: RwLock<HashMap<String, Arc<dyn TaskHandler>>>,
+}
+
+impl Scheduler {
+    pub fn new(storage: Arc<dyn Storage>) -> Scheduler {
+        Scheduler {
+            storage,
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Runs tasks of `kind` with `handler` from now on
+    pub fn register(&self, kind: &str, handler: impl TaskHandler + 'static) {
+        self.handlers
+            .write()
+            .unwrap()
+            .insert(kind.to_string(), Arc::new(handler));
+    }
+
+    /// Has a task of `kind` run at `due_at`, returning its id
+    pub async fn schedule(
+        &self,
+        kind: &str,
+        due_at: OffsetDateTime,
+        payload: &serde_json::Value,
+    ) -> Result<i64, Error> {
+        self.storage.add_task(kind, due_at, payload).await
+    }
+
+    /// Runs the tasks due by `now` that have a handler, returning how many ran.
+    /// Each is removed before it runs, so a failing task isn't retried forever.
+    pub async fn run_due(&self, now: OffsetDateTime) -> Result<usize, Error> {
+        let mut ran = 0;
+        for task in self.storage.due_tasks(now).await? {
+            let handler = self.handlers.read().unwrap().get(&task.kind).cloned();
+            // another instance may run it, or a later version of this one
+            let Some(handler) = handler else {
+                continue;
+            };
+            if !self.storage.remove_task(task.id).await? {
+                continue;
+            }
+            if let Err(e) = handler.run(&task).await {
+                warn!("Task {} ({}) failed: {}", task.id, task.kind, e);
+            }
+            ran += 1;
+        }
+        Ok(ran)
+    }
+
+    /// Runs due tasks in the background from now on
+    pub fn spawn(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                match self.run_due(OffsetDateTime::now_utc()).await {
+                    Ok(0) => {}
+                    Ok(ran) => info!("Ran {} scheduled tasks", ran),
+                    Err(e) => warn!("Failed to run scheduled tasks: {}", e),
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,188 @@
+//! Operator scripts, in [Rhai](https://rhai.rs), hooked into answering. A
+//! script may define any of:
+//!
+//! - `on_incoming_message(message)`: return `false` to leave the message
+//!   unanswered, or a string to answer it as if it said that
+//! - `on_prompt_built(prompt)`: return a map with a new `system` prompt or
+//!   `model` to answer with
+//! - `on_response_ready(response)`: return a string to post instead of the
+//!   answer
+//!
+//! Returning anything else leaves things as they were, as does a script
+//! that fails, which is logged.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serenity::all::Message;
+use tracing::{error, info, warn};
+
+use crate::config::ScriptsConfig;
+
+/// How often to look for changes to the script
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest string a script may build
+const MAX_STRING: usize = 1024 * 1024;
+
+/// What to do with an incoming message
+#[derive(Debug, PartialEq)]
+pub enum Incoming {
+    Keep,
+    Ignore,
+    Rewrite(String),
+}
+
+/// The loaded script, swapped out whenever its file changes
+pub struct Scripts {
+    path: String,
+    engine: Engine,
+    ast: RwLock<Arc<AST>>,
+}
+
+impl Scripts {
+    /// Reads the script in `[scripts]`; one that doesn't compile is logged
+    /// and left out until it's fixed
+    pub fn load(config: &ScriptsConfig) -> Scripts {
+        let mut engine = Engine::new();
+        engine.set_max_operations(config.max_operations);
+        engine.set_max_string_size(MAX_STRING);
+        let scripts = Scripts {
+            path: config.file.clone(),
+            engine,
+            ast: RwLock::new(Arc::new(AST::empty())),
+        };
+        if let Err(e) = scripts.reload() {
+            error!("Not running the script in {}: {}", scripts.path, e);
+        }
+        scripts
+    }
+
+    /// Scripts from source rather than a file
+    pub fn from_source(source: &str, max_operations: u64) -> Result<Scripts, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(max_operations);
+        engine.set_max_string_size(MAX_STRING);
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Scripts {
+            path: String::new(),
+            engine,
+            ast: RwLock::new(Arc::new(ast)),
+        })
+    }
+
+    fn reload(&self) -> Result<(), String> {
+        let source = std::fs::read_to_string(&self.path).map_err(|e| e.to_string())?;
+        let ast = self.engine.compile(source).map_err(|e| e.to_string())?;
+        *self.ast.write().unwrap() = Arc::new(ast);
+        Ok(())
+    }
+
+    /// Picks up edits to the script while running
+    pub fn watch(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let modified = |path: &str| -> Option<SystemTime> {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            };
+            let mut last_modified = modified(&self.path);
+            loop {
+                tokio::time::sleep(RELOAD_INTERVAL).await;
+                let current = modified(&self.path);
+                if current == last_modified || current.is_none() {
+                    continue;
+                }
+                last_modified = current;
+                match self.reload() {
+                    Ok(()) => info!("Reloaded {}", self.path),
+                    Err(e) => error!("Not reloading {}, it doesn't compile: {}", self.path, e),
+                }
+            }
+        });
+    }
+
+    /// Whether the script defines `hook`
+    pub fn has(&self, hook: &str) -> bool {
+        self.ast
+            .read()
+            .unwrap()
+            .iter_functions()
+            .any(|f| f.name == hook && f.params.len() == 1)
+    }
+
+    /// What `hook` returns for `argument`, if the script defines it and it runs
+    fn call(&self, hook: &str, argument: Map) -> Option<Dynamic> {
+        if !self.has(hook) {
+            return None;
+        }
+        let ast = self.ast.read().unwrap().clone();
+        match self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &ast, hook, (argument,))
+        {
+            Ok(result) => Some(result),
+            Err(e) => {
+                warn!("Script hook {} failed: {}", hook, e);
+                None
+            }
+        }
+    }
+
+    pub fn on_incoming_message(&self, msg: &Message) -> Incoming {
+        match self.call("on_incoming_message", message(msg)) {
+            Some(result) if result.as_bool() == Ok(false) => Incoming::Ignore,
+            Some(result) if result.is_string() => Incoming::Rewrite(result.into_string().unwrap()),
+            _ => Incoming::Keep,
+        }
+    }
+
+    /// Lets the script change the system prompt and the model of an answer to `msg`
+    pub fn on_prompt_built(&self, msg: &Message, system: &mut String, model: &mut String) {
+        let mut prompt = message(msg);
+        prompt.insert("system".into(), system.clone().into());
+        prompt.insert("model".into(), model.clone().into());
+        let Some(changes) = self
+            .call("on_prompt_built", prompt)
+            .and_then(|result| result.try_cast::<Map>())
+        else {
+            return;
+        };
+        if let Some(new) = changes
+            .get("system")
+            .and_then(|s| s.clone().into_string().ok())
+        {
+            *system = new;
+        }
+        if let Some(new) = changes
+            .get("model")
+            .and_then(|m| m.clone().into_string().ok())
+        {
+            *model = new;
+        }
+    }
+
+    /// What to post instead of `answer` to `msg`, if anything
+    pub fn on_response_ready(&self, msg: &Message, answer: &str, model: &str) -> Option<String> {
+        let mut response = message(msg);
+        response.insert("answer".into(), answer.into());
+        response.insert("model".into(), model.into());
+        self.call("on_response_ready", response)?.into_string().ok()
+    }
+}
+
+/// What scripts see of a message
+fn message(msg: &Message) -> Map {
+    let mut map = Map::new();
+    map.insert("content".into(), msg.content.clone().into());
+    map.insert("author".into(), msg.author.name.clone().into());
+    map.insert("author_id".into(), (msg.author.id.get() as i64).into());
+    map.insert("channel_id".into(), (msg.channel_id.get() as i64).into());
+    map.insert(
+        "guild_id".into(),
+        msg.guild_id
+            .map_or(Dynamic::UNIT, |id| (id.get() as i64).into()),
+    );
+    map
+}
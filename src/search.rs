@@ -0,0 +1,158 @@
+use std::{collections::HashMap, future::Future};
+
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::OffsetDateTime;
+
+use crate::{
+    config::SearchConfig,
+    duplicates, i18n,
+    provider::Provider,
+    storage::{Error, IndexedAnswer, Storage},
+};
+
+/// How many answers `/search` shows
+pub const MAX_RESULTS: usize = 5;
+/// Answers less similar to the query than this aren't worth showing
+const MIN_SIMILARITY: f32 = 0.3;
+/// How much of a question a result shows
+const MAX_EXCERPT: usize = 100;
+
+/// Keeps a finished answer searchable, with who asked the question
+pub async fn index(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    answer_start: (ChannelId, MessageId),
+    (asker_id, question): (UserId, &str),
+    answer: &str,
+) -> Result<(), Error> {
+    let embedding = provider
+        .embed(&config.model, &document(question, answer))
+        .await?;
+    storage
+        .index_answer(&IndexedAnswer {
+            guild_id,
+            channel_id: answer_start.0,
+            message_id: answer_start.1,
+            at: OffsetDateTime::now_utc(),
+            question: question.to_string(),
+            answer: answer.to_string(),
+            model: config.model.clone(),
+            embedding,
+            author_id: None,
+            trusted: false,
+            asker_id: Some(asker_id),
+        })
+        .await
+}
+
+/// What gets embedded for an answer: the question, since that's what searches
+/// look like, and the answer
+pub fn document(question: &str, answer: &str) -> String {
+    format!("Q: {}\nA: {}", question, answer)
+}
+
+/// The guild's answers most like `query`, best first, from channels `readable`
+/// says the searcher can see
+pub async fn search<F, Fut>(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    query: &str,
+    readable: F,
+) -> Result<Vec<IndexedAnswer>, Error>
+where
+    F: FnMut(ChannelId) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let embedding = provider.embed(&config.model, query).await?;
+    let answers = storage.indexed_answers(guild_id, &config.model).await?;
+    let ranked = rank(&embedding, answers, usize::MAX);
+    Ok(visible(ranked, MAX_RESULTS, readable).await)
+}
+
+/// The first `limit` of `answers` in channels `readable` says yes to, asking
+/// once per channel
+pub async fn visible<F, Fut>(
+    answers: Vec<IndexedAnswer>,
+    limit: usize,
+    mut readable: F,
+) -> Vec<IndexedAnswer>
+where
+    F: FnMut(ChannelId) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut checked: HashMap<ChannelId, bool> = HashMap::new();
+    let mut shown = vec![];
+    for answer in answers {
+        if shown.len() == limit {
+            break;
+        }
+        let can_see = match checked.get(&answer.channel_id) {
+            Some(&can_see) => can_see,
+            None => {
+                let can_see = readable(answer.channel_id).await;
+                checked.insert(answer.channel_id, can_see);
+                can_see
+            }
+        };
+        if can_see {
+            shown.push(answer);
+        }
+    }
+    shown
+}
+
+/// The `limit` answers most similar to `embedding`, best first, newest first on a tie
+pub fn rank(embedding: &[f32], answers: Vec<IndexedAnswer>, limit: usize) -> Vec<IndexedAnswer> {
+    let mut scored: Vec<_> = answers
+        .into_iter()
+        .map(|a| (duplicates::similarity(embedding, &a.embedding), a))
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|(a, x), (b, y)| b.total_cmp(a).then(y.at.cmp(&x.at)));
+    scored.into_iter().take(limit).map(|(_, a)| a).collect()
+}
+
+/// A jump link to the answer
+pub fn link(answer: &IndexedAnswer) -> String {
+    answer
+        .message_id
+        .link(answer.channel_id, Some(answer.guild_id))
+}
+
+/// One line per result, with the start of the question and a jump link
+pub fn describe(locale: &str, results: &[IndexedAnswer]) -> String {
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            let question = result
+                .question
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let excerpt = if question.chars().count() > MAX_EXCERPT {
+                question.chars().take(MAX_EXCERPT).collect::<String>() + "…"
+            } else {
+                question
+            };
+            i18n::tr(
+                locale,
+                "search-result",
+                &[
+                    ("number", (i + 1).into()),
+                    ("question", excerpt.into()),
+                    ("link", link(result).into()),
+                    (
+                        "date",
+                        format!("<t:{}:d>", result.at.unix_timestamp()).into(),
+                    ),
+                ],
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
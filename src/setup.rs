@@ -0,0 +1,119 @@
+use std::{
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+
+use serenity::all::GuildId;
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+use crate::{config::Config, storage::Error};
+
+/// What /setup asks for, kept in the guild's section of the config file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GuildSetup {
+    pub autorespond_channels: Vec<u64>,
+    pub support_roles: Vec<u64>,
+    /// Unset answers with the provider's model
+    pub model: Option<String>,
+    /// Unset uses the global locale
+    pub locale: Option<String>,
+    pub user_profiles: bool,
+    /// Unset follows the global `dry_run`
+    pub dry_run: Option<bool>,
+}
+
+impl GuildSetup {
+    /// The guild's settings as the config has them now
+    pub fn from_config(config: &Config, guild_id: GuildId) -> GuildSetup {
+        let guild = config.guild(Some(guild_id));
+        GuildSetup {
+            autorespond_channels: guild
+                .map(|g| g.autorespond_channels.clone())
+                .unwrap_or_default(),
+            support_roles: guild.map(|g| g.support_roles.clone()).unwrap_or_default(),
+            model: guild.and_then(|g| g.model.clone()),
+            locale: guild.and_then(|g| g.locale.clone()),
+            user_profiles: guild.is_some_and(|g| g.user_profiles),
+            dry_run: guild.and_then(|g| g.dry_run),
+        }
+    }
+
+    /// Whether the guild's answers are dry runs, as set here or globally
+    pub fn dry_run_or(&self, config: &Config) -> bool {
+        self.dry_run.unwrap_or(config.dry_run)
+    }
+}
+
+/// Writes `setup` into `[guilds."<id>"]` of the config file `contents`,
+/// leaving everything else as it was, comments included
+pub fn apply(contents: &str, guild_id: GuildId, setup: &GuildSetup) -> Result<String, Error> {
+    let mut doc: DocumentMut = contents.parse()?;
+    let guilds = doc
+        .entry("guilds")
+        .or_insert_with(|| {
+            // just the [guilds."<id>"] headers, not an empty [guilds]
+            let mut guilds = Table::new();
+            guilds.set_implicit(true);
+            Item::Table(guilds)
+        })
+        .as_table_like_mut()
+        .ok_or("guilds in the config isn't a table")?;
+    if guilds.get(&guild_id.to_string()).is_none() {
+        guilds.insert(&guild_id.to_string(), Item::Table(Table::new()));
+    }
+    let guild = guilds
+        .get_mut(&guild_id.to_string())
+        .and_then(Item::as_table_like_mut)
+        .ok_or("the guild's section of the config isn't a table")?;
+
+    // changing a setting in place keeps the comments above it
+    let mut set = |key: &str, setting: Item| {
+        if setting.is_none() {
+            guild.remove(key);
+        } else if let Some(item) = guild.get_mut(key) {
+            *item = setting;
+        } else {
+            guild.insert(key, setting);
+        }
+    };
+    let ids = |ids: &[u64]| value(ids.iter().map(|&id| id as i64).collect::<Array>());
+    set("autorespond_channels", ids(&setup.autorespond_channels));
+    set("support_roles", ids(&setup.support_roles));
+    for (key, setting) in [("model", &setup.model), ("locale", &setup.locale)] {
+        set(key, setting.as_deref().map(value).unwrap_or_default());
+    }
+    set("user_profiles", value(setup.user_profiles));
+    set("dry_run", setup.dry_run.map(value).unwrap_or_default());
+    Ok(doc.to_string())
+}
+
+/// Held while the config file is read and rewritten, so admins (or bots in
+/// this process) saving at once don't undo each other's changes
+static SAVING: Mutex<()> = Mutex::new(());
+
+/// Writes `setup` into the config file, creating it if there isn't one, and
+/// returns the config it holds now
+pub async fn save(guild_id: GuildId, setup: &GuildSetup) -> Result<Config, Error> {
+    let setup = setup.clone();
+    tokio::task::spawn_blocking(move || save_to(Path::new(&Config::path()), guild_id, &setup))
+        .await?
+}
+
+/// `save`, into the config file at `path`
+pub fn save_to(path: &Path, guild_id: GuildId, setup: &GuildSetup) -> Result<Config, Error> {
+    let _saving = SAVING.lock().unwrap_or_else(PoisonError::into_inner);
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let contents = apply(&contents, guild_id, setup)?;
+    // a config that doesn't load would stop the bot from starting
+    let config = toml::from_str(&contents)?;
+    // renamed into place, so the reload watcher never reads half a file
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(".{}.partial", std::process::id()));
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path)?;
+    Ok(config)
+}
@@ -0,0 +1,33 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serenity::all::GuildId;
+
+use crate::config::Config;
+
+/// Shadow mode (`dry_run`) switched on or off with /debug, over what the
+/// config says, until the bot restarts
+#[derive(Default)]
+pub struct ShadowOverrides(Mutex<HashMap<Option<GuildId>, bool>>);
+
+impl ShadowOverrides {
+    /// Whether answers in the guild (or DMs, for `None`) are logged instead of posted
+    pub fn dry_run(&self, config: &Config, guild_id: Option<GuildId>) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .copied()
+            .unwrap_or_else(|| config.dry_run(guild_id))
+    }
+
+    /// Flips shadow mode for the guild, returning whether it's now on
+    pub fn toggle(&self, config: &Config, guild_id: Option<GuildId>) -> bool {
+        let mut overrides = self.0.lock().unwrap();
+        let on = !overrides
+            .get(&guild_id)
+            .copied()
+            .unwrap_or_else(|| config.dry_run(guild_id));
+        overrides.insert(guild_id, on);
+        on
+    }
+}
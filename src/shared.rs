@@ -0,0 +1,287 @@
+//! State kept in Redis so several instances (say, shards on different hosts)
+//! see the same conversations and endpoint cooldowns
+
+use std::time::Duration;
+
+use async_openai::types::ChatCompletionRequestMessage;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serenity::all::ChannelId;
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    config::RedisConfig,
+    context::{ContextStore, Contexts, Edit},
+    crypto::Cipher,
+    provider::Cooldowns,
+    storage::Error,
+};
+
+/// Replaces a conversation unless it changed since it was read. KEYS are the
+/// conversation, the set of channels and when each grew; ARGV the channel, how
+/// many messages were read, those messages, then the ones to keep
+const REPLACE_IF_UNCHANGED: &str = r#"
+local read = tonumber(ARGV[2])
+local current = redis.call('LRANGE', KEYS[1], 0, -1)
+if #current ~= read then return 0 end
+for i = 1, read do
+  if current[i] ~= ARGV[2 + i] then return 0 end
+end
+redis.call('DEL', KEYS[1])
+if #ARGV > read + 2 then
+  redis.call('RPUSH', KEYS[1], unpack(ARGV, read + 3))
+else
+  redis.call('SREM', KEYS[2], ARGV[1])
+  redis.call('ZREM', KEYS[3], ARGV[1])
+end
+return 1
+"#;
+
+pub async fn connect(config: &RedisConfig) -> Result<ConnectionManager, Error> {
+    let client = redis::Client::open(config.url.as_str())?;
+    Ok(ConnectionManager::new(client).await?)
+}
+
+/// Each channel's conversation as a list of JSON messages, plus a set of the
+/// channels that have one and when each last grew
+pub struct RedisContexts {
+    conn: ConnectionManager,
+    prefix: String,
+    cipher: Cipher,
+}
+
+impl RedisContexts {
+    pub fn new(conn: ConnectionManager, config: &RedisConfig) -> RedisContexts {
+        RedisContexts {
+            conn,
+            prefix: config.key_prefix.clone(),
+            cipher: Cipher::default(),
+        }
+    }
+
+    /// Encrypts messages with `cipher` from here on
+    pub fn with_cipher(self, cipher: Cipher) -> RedisContexts {
+        RedisContexts { cipher, ..self }
+    }
+
+    fn channels_key(&self) -> String {
+        format!("{}contexts", self.prefix)
+    }
+
+    /// Sorted set of channels by when their conversation last grew
+    fn pushed_at_key(&self) -> String {
+        format!("{}contexts_pushed_at", self.prefix)
+    }
+
+    fn context_key(&self, channel_id: &str) -> String {
+        format!("{}context:{}", self.prefix, channel_id)
+    }
+
+    fn encode(&self, message: &ChatCompletionRequestMessage) -> Result<String, Error> {
+        Ok(self.cipher.seal(&serde_json::to_string(message)?))
+    }
+
+    fn decode(&self, messages: Vec<String>) -> Result<Vec<ChatCompletionRequestMessage>, Error> {
+        messages
+            .into_iter()
+            .map(|m| Ok(serde_json::from_str(&self.cipher.open(m)?)?))
+            .collect()
+    }
+}
+
+#[serenity::async_trait]
+impl ContextStore for RedisContexts {
+    async fn push(
+        &self,
+        channel_id: ChannelId,
+        message: ChatCompletionRequestMessage,
+    ) -> Result<Vec<ChatCompletionRequestMessage>, Error> {
+        let channel_id = channel_id.to_string();
+        let key = self.context_key(&channel_id);
+        let (messages,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .sadd(self.channels_key(), &channel_id)
+            .ignore()
+            .zadd(
+                self.pushed_at_key(),
+                &channel_id,
+                OffsetDateTime::now_utc().unix_timestamp(),
+            )
+            .ignore()
+            .rpush(&key, self.encode(&message)?)
+            .ignore()
+            .lrange(&key, 0, -1)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        self.decode(messages)
+    }
+
+    async fn clear(&self, channel_id: ChannelId) -> Result<(), Error> {
+        let channel_id = channel_id.to_string();
+        redis::pipe()
+            .atomic()
+            .del(self.context_key(&channel_id))
+            .ignore()
+            .srem(self.channels_key(), &channel_id)
+            .ignore()
+            .zrem(self.pushed_at_key(), &channel_id)
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn is_empty(&self, channel_id: ChannelId) -> Result<bool, Error> {
+        let len: usize = self
+            .conn
+            .clone()
+            .llen(self.context_key(&channel_id.to_string()))
+            .await?;
+        Ok(len == 0)
+    }
+
+    async fn get(&self, channel_id: ChannelId) -> Result<Vec<ChatCompletionRequestMessage>, Error> {
+        let messages: Vec<String> = self
+            .conn
+            .clone()
+            .lrange(self.context_key(&channel_id.to_string()), 0, -1)
+            .await?;
+        self.decode(messages)
+    }
+
+    async fn all(&self) -> Result<Contexts, Error> {
+        let mut conn = self.conn.clone();
+        let channel_ids: Vec<String> = conn.smembers(self.channels_key()).await?;
+        let mut contexts = Contexts::new();
+        for channel_id in channel_ids {
+            let messages: Vec<String> = conn.lrange(self.context_key(&channel_id), 0, -1).await?;
+            contexts.insert(channel_id, self.decode(messages)?);
+        }
+        Ok(contexts)
+    }
+
+    async fn update(&self, channel_id: ChannelId, edit: &Edit) -> Result<u64, Error> {
+        let channel_id = channel_id.to_string();
+        let key = self.context_key(&channel_id);
+        let script = redis::Script::new(REPLACE_IF_UNCHANGED);
+        let mut conn = self.conn.clone();
+        // another instance may push in between, so edit again until nobody did
+        loop {
+            let read: Vec<String> = conn.lrange(&key, 0, -1).await?;
+            let mut messages = self.decode(read.clone())?;
+            let dropped = edit(&mut messages);
+            if dropped == 0 {
+                return Ok(0);
+            }
+            let mut invocation = script.key(&key);
+            invocation
+                .key(self.channels_key())
+                .key(self.pushed_at_key())
+                .arg(&channel_id)
+                .arg(read.len())
+                .arg(&read);
+            for message in &messages {
+                invocation.arg(self.encode(message)?);
+            }
+            if invocation.invoke_async::<bool>(&mut conn).await? {
+                return Ok(dropped);
+            }
+        }
+    }
+
+    async fn replace_all(&self, contexts: &Contexts) -> Result<(), Error> {
+        let mut conn = self.conn.clone();
+        let old: Vec<String> = conn.smembers(self.channels_key()).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for channel_id in &old {
+            pipe.del(self.context_key(channel_id)).ignore();
+        }
+        pipe.del(self.channels_key()).ignore();
+        pipe.del(self.pushed_at_key()).ignore();
+        // restored conversations count as new
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for (channel_id, messages) in contexts {
+            if messages.is_empty() {
+                continue;
+            }
+            let messages = messages
+                .iter()
+                .map(|message| self.encode(message))
+                .collect::<Result<Vec<_>, _>>()?;
+            pipe.sadd(self.channels_key(), channel_id).ignore();
+            pipe.zadd(self.pushed_at_key(), channel_id, now).ignore();
+            pipe.rpush(self.context_key(channel_id), messages).ignore();
+        }
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn clear_idle(&self, since: OffsetDateTime) -> Result<u64, Error> {
+        let mut conn = self.conn.clone();
+        // `(` leaves out conversations that grew right at `since`
+        let idle: Vec<String> = conn
+            .zrangebyscore(
+                self.pushed_at_key(),
+                "-inf",
+                format!("({}", since.unix_timestamp()),
+            )
+            .await?;
+        if idle.is_empty() {
+            return Ok(0);
+        }
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for channel_id in &idle {
+            pipe.del(self.context_key(channel_id)).ignore();
+        }
+        pipe.srem(self.channels_key(), &idle).ignore();
+        pipe.zrem(self.pushed_at_key(), &idle).ignore();
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(idle.len() as u64)
+    }
+}
+
+/// Cooldowns as keys that expire when the cooldown ends
+pub struct RedisCooldowns {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl RedisCooldowns {
+    pub fn new(conn: ConnectionManager, config: &RedisConfig) -> RedisCooldowns {
+        RedisCooldowns {
+            conn,
+            prefix: config.key_prefix.clone(),
+        }
+    }
+
+    fn key(&self, endpoint: &str) -> String {
+        format!("{}cooldown:{}", self.prefix, endpoint)
+    }
+}
+
+#[serenity::async_trait]
+impl Cooldowns for RedisCooldowns {
+    async fn cooling_down(&self, endpoint: &str) -> bool {
+        // without Redis, try the endpoint rather than refuse to answer
+        match self.conn.clone().exists(self.key(endpoint)).await {
+            Ok(exists) => exists,
+            Err(e) => {
+                warn!("Failed to check the cooldown of {}: {}", endpoint, e);
+                false
+            }
+        }
+    }
+
+    async fn cool_down(&self, endpoint: &str, duration: Duration) {
+        let result: redis::RedisResult<()> = self
+            .conn
+            .clone()
+            .pset_ex(self.key(endpoint), 1, duration.as_millis() as u64)
+            .await;
+        if let Err(e) = result {
+            warn!("Failed to share the cooldown of {}: {}", endpoint, e);
+        }
+    }
+}
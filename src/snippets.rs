@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use serenity::all::GuildId;
+
+use crate::{
+    links,
+    markdown::render_for_discord,
+    plugins::{Answer, Plugin},
+    storage::{Error, Storage},
+    tools::{Tool, Tools},
+};
+
+/// Longest snippet name `/snippet add` takes
+pub const MAX_NAME: usize = 64;
+
+/// Whether `name` can name a snippet: short, lowercase, no spaces
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Lets the model fetch one of a guild's snippets, to quote instead of paraphrasing
+pub struct SnippetTool {
+    storage: Arc<dyn Storage>,
+    guild_id: GuildId,
+    names: Vec<String>,
+    /// In the snippets, so quoting them keeps their links
+    links: Vec<String>,
+}
+
+impl SnippetTool {
+    /// The tool for `guild_id`, or `None` if it has no snippets to offer
+    pub async fn for_guild(
+        storage: Arc<dyn Storage>,
+        guild_id: GuildId,
+    ) -> Result<Option<SnippetTool>, Error> {
+        let snippets = storage.snippets(guild_id).await?;
+        let links = snippets
+            .iter()
+            .flat_map(|s| links::prompt_links(&render_for_discord(&s.content)))
+            .collect();
+        let names: Vec<_> = snippets.into_iter().map(|s| s.name).collect();
+        Ok((!names.is_empty()).then_some(SnippetTool {
+            storage,
+            guild_id,
+            names,
+            links,
+        }))
+    }
+}
+
+#[serenity::async_trait]
+impl Tool for SnippetTool {
+    fn name(&self) -> &str {
+        "get_snippet"
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Gets a support answer the server's staff wrote. When one fits the question, \
+             include its text in your answer verbatim instead of paraphrasing it. \
+             Available snippets: {}",
+            self.names.join(", ")
+        )
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "enum": self.names,
+                    "description": "Which snippet",
+                },
+            },
+            "required": ["name"],
+            "additionalProperties": false,
+        })
+    }
+
+    fn links(&self) -> Vec<String> {
+        self.links.clone()
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, Error> {
+        let name = arguments["name"].as_str().ok_or("name is missing")?;
+        match self.storage.snippet(self.guild_id, name).await? {
+            Some(snippet) => Ok(snippet.content),
+            None => Err(format!("there's no snippet called {}", name).into()),
+        }
+    }
+}
+
+/// Offers the guild's snippets to the model
+pub struct SnippetsPlugin;
+
+#[serenity::async_trait]
+impl Plugin for SnippetsPlugin {
+    fn name(&self) -> &'static str {
+        "snippets"
+    }
+
+    async fn tools(&self, answer: &Answer<'_>, tools: &mut Tools) {
+        let Some(guild_id) = answer.msg.guild_id else {
+            return;
+        };
+        match SnippetTool::for_guild(answer.data.storage.clone(), guild_id).await {
+            Ok(Some(tool)) => tools.add(tool),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to list snippets: {}", e),
+        }
+    }
+}
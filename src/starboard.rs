@@ -0,0 +1,172 @@
+use std::future::Future;
+
+use serenity::all::{ChannelId, GuildId, MessageReaction, Reaction, ReactionType};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    config::SearchConfig,
+    duplicates,
+    provider::Provider,
+    search,
+    storage::{Error, IndexedAnswer, Storage},
+    Data,
+};
+
+/// How many vouched-for answers the model is shown
+const MAX_SHOWN: usize = 3;
+/// How similar to the question a vouched-for answer must be to be shown
+const MIN_SIMILARITY: f32 = 0.5;
+
+/// Makes the answer reacted to trusted once it has enough of the `[starboard]`
+/// reactions, if it's the bot's or a support-role member's
+pub async fn on_reaction(ctx: &serenity::prelude::Context, data: &Data, reaction: &Reaction) {
+    let config = data.config();
+    let (Some(starboard), Some(search_config), Some(guild_id)) =
+        (&config.starboard, &config.search, reaction.guild_id)
+    else {
+        return;
+    };
+    if !is_vouch(&reaction.emoji, &starboard.emojis) {
+        return;
+    }
+    let indexed = match data.storage.indexed_answer(reaction.message_id).await {
+        Ok(indexed) => indexed,
+        Err(e) => {
+            warn!("Failed to look up the answer reacted to: {}", e);
+            return;
+        }
+    };
+    if indexed.as_ref().is_some_and(|a| a.trusted) {
+        return;
+    }
+    let msg = match reaction.message(&ctx.http).await {
+        Ok(msg) => msg,
+        Err(e) => {
+            warn!("Failed to fetch the answer reacted to: {}", e);
+            return;
+        }
+    };
+    if vouches(&msg.reactions, &starboard.emojis) < starboard.reactions {
+        return;
+    }
+
+    let answer = if let Some(indexed) = indexed {
+        // the bot's answer, as indexed when it was posted
+        IndexedAnswer {
+            trusted: true,
+            ..indexed
+        }
+    } else {
+        let is_bot = msg.author.id == ctx.cache.current_user().id;
+        let guild = config.guild(Some(guild_id));
+        let is_staff = !is_bot
+            && match (guild, guild_id.member(ctx, msg.author.id).await) {
+                (Some(guild), Ok(member)) => member
+                    .roles
+                    .iter()
+                    .any(|r| guild.support_roles.contains(&r.get())),
+                _ => false,
+            };
+        if !is_bot && !is_staff {
+            return;
+        }
+        let asked = msg.referenced_message.as_deref();
+        let question = asked.map(|q| q.content.clone()).unwrap_or_default();
+        let (provider, _) = data.providers.for_guild(&config, Some(guild_id));
+        let document = search::document(&question, &msg.content);
+        let embedding = match provider.embed(&search_config.model, &document).await {
+            Ok(embedding) => embedding,
+            Err(e) => {
+                warn!("Failed to embed the vouched-for answer: {}", e);
+                return;
+            }
+        };
+        IndexedAnswer {
+            guild_id,
+            channel_id: msg.channel_id,
+            message_id: msg.id,
+            at: OffsetDateTime::now_utc(),
+            question,
+            answer: msg.content.clone(),
+            model: search_config.model.clone(),
+            embedding,
+            author_id: is_staff.then_some(msg.author.id),
+            trusted: true,
+            asker_id: asked.map(|q| q.author.id),
+        }
+    };
+    match data.storage.index_answer(&answer).await {
+        Ok(()) => info!("Answer {} is now trusted", answer.message_id),
+        Err(e) => warn!("Failed to save the vouched-for answer: {}", e),
+    }
+}
+
+/// Whether `emoji` is one of `emojis`, with or without a variation selector
+pub fn is_vouch(emoji: &ReactionType, emojis: &[String]) -> bool {
+    let ReactionType::Unicode(emoji) = emoji else {
+        return false;
+    };
+    let plain = |e: &str| e.trim_end_matches('\u{fe0f}').to_string();
+    emojis.iter().any(|e| plain(e) == plain(emoji))
+}
+
+/// How many people reacted with any of `emojis`, not counting the bot
+pub fn vouches(reactions: &[MessageReaction], emojis: &[String]) -> u64 {
+    reactions
+        .iter()
+        .filter(|r| is_vouch(&r.reaction_type, emojis))
+        .map(|r| r.count - u64::from(r.me))
+        .sum()
+}
+
+/// The guild's trusted answers most like `question`, if it has any like it,
+/// from channels `readable` says the asker can see
+pub async fn retrieve<F, Fut>(
+    storage: &dyn Storage,
+    provider: &Provider,
+    config: &SearchConfig,
+    guild_id: GuildId,
+    question: &str,
+    readable: F,
+) -> Result<Vec<IndexedAnswer>, Error>
+where
+    F: FnMut(ChannelId) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let trusted = storage.trusted_answers(guild_id, &config.model).await?;
+    if trusted.is_empty() {
+        return Ok(vec![]);
+    }
+    let embedding = provider.embed(&config.model, question).await?;
+    let relevant = trusted
+        .into_iter()
+        .filter(|a| duplicates::similarity(&embedding, &a.embedding) >= MIN_SIMILARITY)
+        .collect();
+    let ranked = search::rank(&embedding, relevant, usize::MAX);
+    Ok(search::visible(ranked, MAX_SHOWN, readable).await)
+}
+
+/// Added to the system prompt: the vouched-for answers, with who wrote them
+pub fn instructions(vouched: &[IndexedAnswer]) -> String {
+    if vouched.is_empty() {
+        return String::new();
+    }
+    let mut text = "\n\nPeople in this server vouched for these answers to similar questions. \
+                    Rely on them where they help, and link to the one you use."
+        .to_string();
+    for answer in vouched {
+        let author = match answer.author_id {
+            Some(id) => format!("<@{}>", id),
+            None => "you".to_string(),
+        };
+        text.push_str(&format!(
+            "\n\nAnswered by {} ({}):\nQ: {}\nA: {}",
+            author,
+            search::link(answer),
+            answer.question,
+            answer.answer
+        ));
+    }
+    text
+}
@@ -0,0 +1,131 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    config::SharedConfig,
+    i18n,
+    storage::{GuildStats, Rating, Storage},
+};
+
+/// How often each guild with an `ops_channel` gets a digest
+const DIGEST_PERIOD: time::Duration = time::Duration::days(7);
+
+/// How often to see whether a digest is due
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// `stats` for the last `days` days, as posted to Discord
+pub fn summary(locale: &str, days: i64, stats: &GuildStats) -> String {
+    i18n::tr(
+        locale,
+        "stats",
+        &[
+            ("days", days.into()),
+            ("answered", stats.answered.into()),
+            ("errors", stats.errors.into()),
+            (
+                "error-rate",
+                format!("{:.1}", stats.error_rate() * 100.0).into(),
+            ),
+            (
+                "average",
+                format!("{:.1}", stats.average_latency.as_secs_f64()).into(),
+            ),
+            (
+                "p95",
+                format!("{:.1}", stats.p95_latency.as_secs_f64()).into(),
+            ),
+            ("prompt-tokens", stats.prompt_tokens.into()),
+            ("completion-tokens", stats.completion_tokens.into()),
+        ],
+    )
+}
+
+/// How askers rated the help in closed threads, the bot's alone and with
+/// support staff apart; no lines without ratings
+pub fn ratings(locale: &str, ratings: &[Rating]) -> Vec<String> {
+    [(false, "stats-ratings-bot"), (true, "stats-ratings-staff")]
+        .into_iter()
+        .filter_map(|(escalated, key)| {
+            let scores: Vec<u8> = ratings
+                .iter()
+                .filter(|r| r.escalated == escalated)
+                .map(|r| r.score)
+                .collect();
+            if scores.is_empty() {
+                return None;
+            }
+            let average = scores.iter().map(|&s| f64::from(s)).sum::<f64>() / scores.len() as f64;
+            Some(i18n::tr(
+                locale,
+                key,
+                &[
+                    ("average", format!("{:.1}", average).into()),
+                    ("count", scores.len().into()),
+                ],
+            ))
+        })
+        .collect()
+}
+
+/// Posts last week's stats to each guild's `ops_channel` once a week. The
+/// first digest goes out a week after a guild sets one.
+pub fn spawn_digests(http: Arc<Http>, config: SharedConfig, storage: Arc<dyn Storage>) {
+    tokio::spawn(async move {
+        loop {
+            let current = config.read().unwrap().clone();
+            for (guild_id, guild) in &current.guilds {
+                let (Ok(guild_id), Some(channel_id)) =
+                    (guild_id.parse::<GuildId>(), guild.ops_channel)
+                else {
+                    continue;
+                };
+                let locale = current.locale(Some(guild_id));
+                if let Err(e) = send_digest_if_due(
+                    &http,
+                    storage.as_ref(),
+                    guild_id,
+                    ChannelId::new(channel_id),
+                    &locale,
+                )
+                .await
+                {
+                    warn!("Failed to send digest for guild {}: {}", guild_id, e);
+                }
+            }
+            tokio::time::sleep(DIGEST_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+async fn send_digest_if_due(
+    http: &Http,
+    storage: &dyn Storage,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    locale: &str,
+) -> Result<(), crate::storage::Error> {
+    let now = OffsetDateTime::now_utc();
+    let Some(last) = storage.last_digest(guild_id).await? else {
+        // start the clock
+        return storage.set_last_digest(guild_id, now).await;
+    };
+    if now - last < DIGEST_PERIOD {
+        return Ok(());
+    }
+
+    let stats = storage.guild_stats(guild_id, now - DIGEST_PERIOD).await?;
+    let content = format!(
+        "{}\n{}",
+        i18n::tr(locale, "stats-digest", &[]),
+        summary(locale, DIGEST_PERIOD.whole_days(), &stats)
+    );
+    channel_id
+        .send_message(http, CreateMessage::new().content(content))
+        .await?;
+    storage.set_last_digest(guild_id, now).await?;
+    info!("Sent weekly digest for guild {}", guild_id);
+    Ok(())
+}
@@ -0,0 +1,592 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use sha2::{Digest as _, Sha256};
+use time::OffsetDateTime;
+
+use crate::{
+    config::{MemoryBudget, StorageConfig},
+    crypto::Cipher,
+    prompt::PromptVersion,
+};
+
+#[cfg(feature = "postgres")]
+mod postgres;
+mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// One question the bot tried to answer
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AnswerRecord {
+    pub guild_id: GuildId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    /// Whether an answer was posted in full
+    pub ok: bool,
+    /// From the question arriving to the answer being done
+    pub latency: Duration,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    /// The message feedback on the answer is left on: the last one it was posted in
+    pub message_id: Option<MessageId>,
+    /// Experiment and variant that answered, if one was running
+    pub experiment: Option<String>,
+    pub variant: Option<String>,
+    /// Version of the instructions the answer was given
+    pub prompt_version: Option<String>,
+    /// The question and answer, kept only for guilds with a support digest
+    pub question: Option<String>,
+    pub answer: Option<String>,
+    /// Who asked, kept along with the question so `/forgetme` can find it
+    #[serde(default)]
+    pub user_id: Option<UserId>,
+    /// Where it was asked and what answered, for spending summaries
+    #[serde(default)]
+    pub channel_id: Option<ChannelId>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Version of the guild's knowledge base the answer was given with, if it had one
+    #[serde(default)]
+    pub kb_version: Option<String>,
+}
+
+/// A kept question, how it was answered and what people thought of the answer
+#[derive(Debug, PartialEq)]
+pub struct Transcript {
+    pub at: OffsetDateTime,
+    pub question: String,
+    pub answer: Option<String>,
+    pub ok: bool,
+    pub thumbs_up: u64,
+    pub thumbs_down: u64,
+}
+
+/// A 👍 (`up`) or 👎 from one user on one answer
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Feedback {
+    pub message_id: MessageId,
+    pub user_id: UserId,
+    pub up: bool,
+}
+
+/// When a guild's weekly digest last went out
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Digest {
+    pub guild_id: GuildId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub sent_at: OffsetDateTime,
+}
+
+/// How many questions one member has asked in one guild. Only kept for guilds
+/// with `user_profiles` on.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Asker {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub first_asked_at: OffsetDateTime,
+    pub questions: u64,
+}
+
+/// A canned answer staff wrote for a guild, sent with `/snippet send` or
+/// looked up by the model
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Snippet {
+    pub guild_id: GuildId,
+    pub name: String,
+    /// Markdown, as posted
+    pub content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+/// A knowledge base article pushed through the API, shown to the model for
+/// questions like it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Document {
+    pub guild_id: GuildId,
+    /// Pushing a document with the same name replaces it
+    pub name: String,
+    pub text: String,
+    /// Where the document is published, for linking to it
+    #[serde(default)]
+    pub url: Option<String>,
+    /// The model `embedding` is from
+    pub model: String,
+    pub embedding: Vec<f32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+/// A guild's knowledge base as it was after a change, to roll back to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KbVersion {
+    pub guild_id: GuildId,
+    /// Short hash of the documents' names, text and links
+    pub version: String,
+    /// When the knowledge base last became this
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub documents: Vec<KbDocument>,
+}
+
+impl KbVersion {
+    /// A snapshot of the guild's `documents` as they are now
+    pub fn of(guild_id: GuildId, documents: &[Document]) -> KbVersion {
+        KbVersion {
+            guild_id,
+            version: KbVersion::version_of(documents),
+            created_at: OffsetDateTime::now_utc(),
+            documents: documents.iter().map(KbDocument::from).collect(),
+        }
+    }
+
+    /// Identifies a knowledge base by its content, so the same documents are
+    /// always the same version
+    pub fn version_of(documents: &[Document]) -> String {
+        let mut documents: Vec<_> = documents.iter().collect();
+        documents.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut hasher = Sha256::new();
+        for document in documents {
+            for part in [
+                document.name.as_str(),
+                document.text.as_str(),
+                document.url.as_deref().unwrap_or_default(),
+            ] {
+                hasher.update(part.as_bytes());
+                hasher.update([0]);
+            }
+        }
+        hasher.finalize()[..6]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// A document as a snapshot keeps it, without its embedding, which is made
+/// again if it's rolled back to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct KbDocument {
+    pub name: String,
+    pub text: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl From<&Document> for KbDocument {
+    fn from(document: &Document) -> KbDocument {
+        KbDocument {
+            name: document.name.clone(),
+            text: document.text.clone(),
+            url: document.url.clone(),
+            updated_at: document.updated_at,
+        }
+    }
+}
+
+/// Work scheduled for later, like a reminder. What `payload` holds depends on the `kind`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Task {
+    pub id: i64,
+    pub kind: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub due_at: OffsetDateTime,
+    pub payload: serde_json::Value,
+}
+
+/// An answered question as an embedding, to notice it being asked again.
+/// Only kept with `[duplicates]` on, and without the question's text.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuestionEmbedding {
+    pub guild_id: GuildId,
+    /// Where the answer is
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    /// Embeddings from different models can't be compared
+    pub model: String,
+    pub embedding: Vec<f32>,
+}
+
+/// A finished answer and its question, embedded for `/search`. Only kept with `[search]` on.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IndexedAnswer {
+    pub guild_id: GuildId,
+    /// Where the answer starts
+    pub channel_id: ChannelId,
+    pub message_id: MessageId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    pub question: String,
+    pub answer: String,
+    pub model: String,
+    pub embedding: Vec<f32>,
+    /// Who wrote the answer, if it wasn't the bot
+    #[serde(default)]
+    pub author_id: Option<UserId>,
+    /// Who asked, if known
+    #[serde(default)]
+    pub asker_id: Option<UserId>,
+    /// Vouched for with reactions, so the model is shown it for similar questions
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+/// A `/forgetme`: whose data went, when, and how much. The one thing kept
+/// about them, to show it was done.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Deletion {
+    pub user_id: UserId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    /// Records deleted from storage
+    pub records: u64,
+    /// Conversation messages forgotten
+    pub messages: u64,
+}
+
+/// How someone rated the help they got once their thread was closed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Rating {
+    pub guild_id: GuildId,
+    /// The thread that was closed
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    /// From 1 to 5
+    pub score: u8,
+    pub comment: Option<String>,
+    /// Whether support staff took over from the bot
+    pub escalated: bool,
+    /// The conversation as the bot saw it
+    pub transcript: String,
+}
+
+/// What to purge: records from before each time. `None` keeps them all.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Purge {
+    /// Answer records, with their transcripts, feedback and ratings
+    pub answers: Option<OffsetDateTime>,
+    /// Answers kept for `/search`, trusted ones too
+    pub indexed_answers: Option<OffsetDateTime>,
+    pub question_embeddings: Option<OffsetDateTime>,
+    pub deletions: Option<OffsetDateTime>,
+}
+
+/// Everything in storage, for moving it to another instance
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Dump {
+    pub answers: Vec<AnswerRecord>,
+    pub feedback: Vec<Feedback>,
+    pub digests: Vec<Digest>,
+    pub prompt_versions: Vec<PromptVersion>,
+    pub settings: BTreeMap<String, String>,
+    pub askers: Vec<Asker>,
+    pub snippets: Vec<Snippet>,
+    pub tasks: Vec<Task>,
+    pub question_embeddings: Vec<QuestionEmbedding>,
+    pub indexed_answers: Vec<IndexedAnswer>,
+    pub deletions: Vec<Deletion>,
+    pub ratings: Vec<Rating>,
+    pub documents: Vec<Document>,
+    pub kb_versions: Vec<KbVersion>,
+}
+
+/// Tokens answers used in one channel with one model
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub guild_id: GuildId,
+    /// Unknown for answers recorded before channels were
+    pub channel_id: Option<ChannelId>,
+    pub model: Option<String>,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Totals over some stretch of time for one guild
+#[derive(Debug, Default, PartialEq)]
+pub struct GuildStats {
+    /// Questions answered in full
+    pub answered: u64,
+    /// Questions that got an error instead
+    pub errors: u64,
+    /// Across answered questions
+    pub average_latency: Duration,
+    pub p95_latency: Duration,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl GuildStats {
+    /// Share of questions that failed, from 0 to 1
+    pub fn error_rate(&self) -> f64 {
+        let total = self.answered + self.errors;
+        if total == 0 {
+            0.0
+        } else {
+            self.errors as f64 / total as f64
+        }
+    }
+}
+
+/// How one variant of an experiment has done
+#[derive(Debug, PartialEq)]
+pub struct VariantStats {
+    pub variant: String,
+    pub answered: u64,
+    pub errors: u64,
+    pub average_latency: Duration,
+    /// Users reacting 👍 and 👎 to its answers
+    pub thumbs_up: u64,
+    pub thumbs_down: u64,
+}
+
+/// Where state that outlives the process is kept
+#[serenity::async_trait]
+pub trait Storage: Send + Sync {
+    async fn record_answer(&self, record: &AnswerRecord) -> Result<(), Error>;
+
+    /// Tokens used since `since`, by guild, channel and model
+    async fn usage(&self, since: OffsetDateTime) -> Result<Vec<Usage>, Error>;
+
+    /// Totals for answers in `guild_id` since `since`
+    async fn guild_stats(
+        &self,
+        guild_id: GuildId,
+        since: OffsetDateTime,
+    ) -> Result<GuildStats, Error>;
+
+    /// When the last weekly digest went out for `guild_id`, if ever
+    async fn last_digest(&self, guild_id: GuildId) -> Result<Option<OffsetDateTime>, Error>;
+
+    async fn set_last_digest(&self, guild_id: GuildId, at: OffsetDateTime) -> Result<(), Error>;
+
+    /// Counts a 👍 (`up`) or 👎 on an answer; ignored for messages that aren't
+    /// answers. Whether it counted.
+    async fn add_feedback(
+        &self,
+        message_id: MessageId,
+        user_id: UserId,
+        up: bool,
+    ) -> Result<bool, Error>;
+
+    /// Takes back a 👍 or 👎. Whether there was one on an answer to take back.
+    async fn remove_feedback(
+        &self,
+        message_id: MessageId,
+        user_id: UserId,
+        up: bool,
+    ) -> Result<bool, Error>;
+
+    /// Results for each variant of `experiment`, by name
+    async fn variant_stats(&self, experiment: &str) -> Result<Vec<VariantStats>, Error>;
+
+    /// The questions kept for `guild_id` since `since`, oldest first
+    async fn transcripts(
+        &self,
+        guild_id: GuildId,
+        since: OffsetDateTime,
+    ) -> Result<Vec<Transcript>, Error>;
+
+    /// Keeps `prompt` unless its version is already saved
+    async fn save_prompt_version(&self, prompt: &PromptVersion) -> Result<(), Error>;
+
+    async fn prompt_version(&self, version: &str) -> Result<Option<PromptVersion>, Error>;
+
+    /// The `limit` most recently first seen versions, newest first
+    async fn prompt_versions(&self, limit: usize) -> Result<Vec<PromptVersion>, Error>;
+
+    /// Counts a question from `user_id` in `guild_id`, returning how many they
+    /// asked before it
+    async fn count_question(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        at: OffsetDateTime,
+    ) -> Result<u64, Error>;
+
+    /// Adds `snippet`, or replaces the guild's one with the same name
+    async fn save_snippet(&self, snippet: &Snippet) -> Result<(), Error>;
+
+    async fn snippet(&self, guild_id: GuildId, name: &str) -> Result<Option<Snippet>, Error>;
+
+    /// All of a guild's snippets, by name
+    async fn snippets(&self, guild_id: GuildId) -> Result<Vec<Snippet>, Error>;
+
+    /// Schedules a task, returning its id
+    async fn add_task(
+        &self,
+        kind: &str,
+        due_at: OffsetDateTime,
+        payload: &serde_json::Value,
+    ) -> Result<i64, Error>;
+
+    /// Tasks due by `now`, soonest first
+    async fn due_tasks(&self, now: OffsetDateTime) -> Result<Vec<Task>, Error>;
+
+    /// Removes a task, returning whether it was still there. Whoever removes
+    /// it runs it, so instances sharing storage run each task once.
+    async fn remove_task(&self, id: i64) -> Result<bool, Error>;
+
+    async fn save_question_embedding(&self, question: &QuestionEmbedding) -> Result<(), Error>;
+
+    /// A guild's questions embedded with `model` since `since`, oldest first
+    async fn question_embeddings(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+        since: OffsetDateTime,
+    ) -> Result<Vec<QuestionEmbedding>, Error>;
+
+    /// Adds `answer`, or replaces the one indexed for the same message
+    async fn index_answer(&self, answer: &IndexedAnswer) -> Result<(), Error>;
+
+    async fn indexed_answer(&self, message_id: MessageId) -> Result<Option<IndexedAnswer>, Error>;
+
+    /// A guild's trusted answers indexed with `model`, oldest first
+    async fn trusted_answers(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+    ) -> Result<Vec<IndexedAnswer>, Error>;
+
+    /// A guild's answers indexed with `model`, oldest first
+    async fn indexed_answers(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+    ) -> Result<Vec<IndexedAnswer>, Error>;
+
+    /// Adds `document`, or replaces the guild's one with the same name, and
+    /// snapshots the guild's knowledge base as it leaves it, keeping the
+    /// latest `keep` snapshots
+    async fn save_document(&self, document: &Document, keep: usize) -> Result<(), Error>;
+
+    /// Removes a document, returning whether it was there, and snapshots the
+    /// guild's knowledge base if it was, keeping the latest `keep` snapshots
+    async fn delete_document(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        keep: usize,
+    ) -> Result<bool, Error>;
+
+    /// All of a guild's documents, by name
+    async fn documents(&self, guild_id: GuildId) -> Result<Vec<Document>, Error>;
+
+    /// Replaces all of a guild's documents with `documents`, and snapshots
+    /// its knowledge base as it leaves it, keeping the latest `keep` snapshots
+    async fn replace_documents(
+        &self,
+        guild_id: GuildId,
+        documents: &[Document],
+        keep: usize,
+    ) -> Result<(), Error>;
+
+    /// Keeps a snapshot of a guild's knowledge base, dropping all but its
+    /// latest `keep`
+    async fn save_kb_version(&self, version: &KbVersion, keep: usize) -> Result<(), Error>;
+
+    /// A guild's snapshot by version
+    async fn kb_version(
+        &self,
+        guild_id: GuildId,
+        version: &str,
+    ) -> Result<Option<KbVersion>, Error>;
+
+    /// A guild's latest snapshots, newest first
+    async fn kb_versions(&self, guild_id: GuildId, limit: usize) -> Result<Vec<KbVersion>, Error>;
+
+    /// Deletes everything stored about a user: questions they asked, feedback
+    /// and ratings they left, answers they wrote and reminders they set.
+    /// Returns how many records went.
+    async fn forget_user(&self, user_id: UserId) -> Result<u64, Error>;
+
+    async fn record_deletion(&self, deletion: &Deletion) -> Result<(), Error>;
+
+    /// Keeps `rating`, replacing the user's earlier one for the same thread
+    async fn record_rating(&self, rating: &Rating) -> Result<(), Error>;
+
+    /// A guild's ratings since `since`, oldest first
+    async fn ratings(&self, guild_id: GuildId, since: OffsetDateTime)
+        -> Result<Vec<Rating>, Error>;
+
+    /// Deletes records older than the retention policy allows, returning how
+    /// many went
+    async fn purge(&self, purge: &Purge) -> Result<u64, Error>;
+
+    /// A value the bot keeps across restarts
+    async fn setting(&self, key: &str) -> Result<Option<String>, Error>;
+
+    /// Sets a setting, or clears it with `None`
+    async fn set_setting(&self, key: &str, value: Option<&str>) -> Result<(), Error>;
+
+    /// Everything stored, oldest first
+    async fn dump(&self) -> Result<Dump, Error>;
+
+    /// Replaces everything stored with `dump`
+    async fn restore(&self, dump: &Dump) -> Result<(), Error>;
+}
+
+/// Connects to the database in `config`, picking the backend from the URL
+/// scheme, and encrypting with its key if it has one. SQLite keeps to
+/// `budget` if there is one.
+pub async fn open(
+    config: &StorageConfig,
+    budget: Option<MemoryBudget>,
+) -> Result<Arc<dyn Storage>, Error> {
+    let cipher = Cipher::from_key(config.encryption_key().as_deref())?;
+    connect(&config.url, cipher, budget).await
+}
+
+/// Connects to the database at `url`, encrypting with `cipher`
+pub async fn connect(
+    url: &str,
+    cipher: Cipher,
+    budget: Option<MemoryBudget>,
+) -> Result<Arc<dyn Storage>, Error> {
+    if url.starts_with("sqlite:") {
+        return Ok(Arc::new(
+            SqliteStorage::connect_within(url, budget)
+                .await?
+                .with_cipher(cipher),
+        ));
+    }
+    if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+        #[cfg(feature = "postgres")]
+        return Ok(Arc::new(
+            PostgresStorage::connect(url).await?.with_cipher(cipher),
+        ));
+        #[cfg(not(feature = "postgres"))]
+        return Err("PostgreSQL storage needs a build with `--features postgres`".into());
+    }
+    Err(format!(
+        "unsupported storage url {}; use sqlite://<path> or postgres://...",
+        url
+    )
+    .into())
+}
+
+/// An embedding as stored: its floats, little-endian
+fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+fn embedding_from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
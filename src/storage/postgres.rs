@@ -0,0 +1,1462 @@
+use std::time::Duration;
+
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use sqlx::{
+    postgres::{PgArguments, PgPoolOptions, PgRow},
+    query::Query,
+    PgConnection, PgPool, Postgres, Row,
+};
+use time::OffsetDateTime;
+
+use super::{
+    embedding_from_bytes, embedding_to_bytes, AnswerRecord, Asker, Deletion, Digest, Document,
+    Dump, Error, Feedback, GuildStats, IndexedAnswer, KbVersion, Purge, QuestionEmbedding, Rating,
+    Snippet, Storage, Task, Transcript, Usage, VariantStats,
+};
+use crate::{crypto::Cipher, prompt::PromptVersion};
+
+/// Schema changes, in order; `schema_version` counts how many have run
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE answers (
+        id BIGSERIAL PRIMARY KEY,
+        guild_id BIGINT NOT NULL,
+        at BIGINT NOT NULL,
+        ok BOOLEAN NOT NULL,
+        latency_ms BIGINT NOT NULL,
+        prompt_tokens BIGINT NOT NULL,
+        completion_tokens BIGINT NOT NULL,
+        message_id BIGINT,
+        experiment TEXT,
+        variant TEXT,
+        prompt_version TEXT
+    );
+    CREATE INDEX answers_guild_at ON answers (guild_id, at);
+    CREATE INDEX answers_message ON answers (message_id);
+    CREATE INDEX answers_experiment ON answers (experiment);
+    CREATE TABLE digests (
+        guild_id BIGINT PRIMARY KEY,
+        sent_at BIGINT NOT NULL
+    );
+    CREATE TABLE feedback (
+        id BIGSERIAL,
+        message_id BIGINT NOT NULL,
+        user_id BIGINT NOT NULL,
+        up BOOLEAN NOT NULL,
+        PRIMARY KEY (message_id, user_id, up)
+    );
+    CREATE TABLE prompt_versions (
+        id BIGSERIAL,
+        version TEXT PRIMARY KEY,
+        text TEXT NOT NULL,
+        created_at BIGINT NOT NULL
+    );
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    ",
+    "
+    CREATE TABLE askers (
+        guild_id BIGINT NOT NULL,
+        user_id BIGINT NOT NULL,
+        first_asked_at BIGINT NOT NULL,
+        questions BIGINT NOT NULL,
+        PRIMARY KEY (guild_id, user_id)
+    );
+    ",
+    "
+    CREATE TABLE snippets (
+        guild_id BIGINT NOT NULL,
+        name TEXT NOT NULL,
+        content TEXT NOT NULL,
+        updated_at BIGINT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+    ",
+    "
+    CREATE TABLE tasks (
+        id BIGSERIAL PRIMARY KEY,
+        kind TEXT NOT NULL,
+        due_at BIGINT NOT NULL,
+        payload TEXT NOT NULL
+    );
+    CREATE INDEX tasks_due_at ON tasks (due_at);
+    ",
+    "
+    ALTER TABLE answers ADD COLUMN question TEXT;
+    ALTER TABLE answers ADD COLUMN answer TEXT;
+    ",
+    "
+    CREATE TABLE question_embeddings (
+        guild_id BIGINT NOT NULL,
+        channel_id BIGINT NOT NULL,
+        message_id BIGINT NOT NULL,
+        at BIGINT NOT NULL,
+        model TEXT NOT NULL,
+        embedding BYTEA NOT NULL
+    );
+    CREATE INDEX question_embeddings_guild_at ON question_embeddings (guild_id, at);
+    ",
+    "
+    CREATE TABLE indexed_answers (
+        guild_id BIGINT NOT NULL,
+        channel_id BIGINT NOT NULL,
+        message_id BIGINT NOT NULL,
+        at BIGINT NOT NULL,
+        question TEXT NOT NULL,
+        answer TEXT NOT NULL,
+        model TEXT NOT NULL,
+        embedding BYTEA NOT NULL
+    );
+    CREATE INDEX indexed_answers_guild ON indexed_answers (guild_id, model);
+    ",
+    "
+    ALTER TABLE indexed_answers ADD COLUMN author_id BIGINT;
+    ALTER TABLE indexed_answers ADD COLUMN trusted BOOLEAN NOT NULL DEFAULT FALSE;
+    CREATE UNIQUE INDEX indexed_answers_message ON indexed_answers (message_id);
+    ",
+    "
+    ALTER TABLE answers ADD COLUMN user_id BIGINT;
+    CREATE INDEX answers_user ON answers (user_id);
+    ALTER TABLE indexed_answers ADD COLUMN asker_id BIGINT;
+    CREATE TABLE deletions (
+        user_id BIGINT NOT NULL,
+        at BIGINT NOT NULL,
+        records BIGINT NOT NULL,
+        messages BIGINT NOT NULL
+    );
+    ",
+    "
+    ALTER TABLE answers ADD COLUMN channel_id BIGINT;
+    ALTER TABLE answers ADD COLUMN model TEXT;
+    ",
+    "
+    CREATE TABLE ratings (
+        guild_id BIGINT NOT NULL,
+        channel_id BIGINT NOT NULL,
+        user_id BIGINT NOT NULL,
+        at BIGINT NOT NULL,
+        score BIGINT NOT NULL,
+        comment TEXT,
+        escalated BOOLEAN NOT NULL,
+        transcript TEXT NOT NULL,
+        PRIMARY KEY (channel_id, user_id)
+    );
+    CREATE INDEX ratings_guild_at ON ratings (guild_id, at);
+    ",
+    "
+    CREATE TABLE documents (
+        guild_id BIGINT NOT NULL,
+        name TEXT NOT NULL,
+        text TEXT NOT NULL,
+        model TEXT NOT NULL,
+        embedding BYTEA NOT NULL,
+        updated_at BIGINT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+    ",
+    "
+    ALTER TABLE documents ADD COLUMN url TEXT;
+    ",
+    "
+    ALTER TABLE answers ADD COLUMN kb_version TEXT;
+    CREATE TABLE kb_versions (
+        id BIGSERIAL,
+        guild_id BIGINT NOT NULL,
+        version TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        documents TEXT NOT NULL,
+        PRIMARY KEY (guild_id, version)
+    );
+    ",
+];
+
+/// Arbitrary key for the lock that keeps instances from migrating at the same time
+const MIGRATION_LOCK: i64 = 0x6465736b68656c70;
+
+/// A PostgreSQL database, for deployments that already run one
+pub struct PostgresStorage {
+    pool: PgPool,
+    cipher: Cipher,
+}
+
+impl PostgresStorage {
+    pub async fn connect(url: &str) -> Result<PostgresStorage, Error> {
+        let pool = PgPoolOptions::new().max_connections(8).connect(url).await?;
+        migrate(&pool).await?;
+        Ok(PostgresStorage {
+            pool,
+            cipher: Cipher::default(),
+        })
+    }
+
+    /// Encrypts questions and answers with `cipher` from here on
+    pub fn with_cipher(self, cipher: Cipher) -> PostgresStorage {
+        PostgresStorage { cipher, ..self }
+    }
+}
+
+async fn migrate(pool: &PgPool) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::raw_sql("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")
+        .execute(&mut *tx)
+        .await?;
+    let version: Option<i32> = sqlx::query_scalar("SELECT version FROM schema_version")
+        .fetch_optional(&mut *tx)
+        .await?;
+    let version = version.unwrap_or(0);
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        sqlx::raw_sql(*migration).execute(&mut *tx).await?;
+    }
+    sqlx::raw_sql("DELETE FROM schema_version")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO schema_version (version) VALUES ($1)")
+        .bind(MIGRATIONS.len() as i32)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+// Postgres has no unsigned integers; Discord ids fit in a signed one
+fn id(id: impl Into<u64>) -> i64 {
+    id.into() as i64
+}
+
+#[serenity::async_trait]
+impl Storage for PostgresStorage {
+    async fn record_answer(&self, record: &AnswerRecord) -> Result<(), Error> {
+        insert_answer(record, &self.cipher)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn usage(&self, since: OffsetDateTime) -> Result<Vec<Usage>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, model,
+                    SUM(prompt_tokens)::BIGINT AS prompt_tokens,
+                    SUM(completion_tokens)::BIGINT AS completion_tokens
+             FROM answers WHERE at >= $1
+             GROUP BY guild_id, channel_id, model
+             ORDER BY guild_id, channel_id, model",
+        )
+        .bind(since.unix_timestamp())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(Usage {
+                guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+                channel_id: row
+                    .try_get::<Option<i64>, _>("channel_id")?
+                    .map(|id| ChannelId::new(id as u64)),
+                model: row.try_get("model")?,
+                prompt_tokens: row.try_get::<i64, _>("prompt_tokens")? as u64,
+                completion_tokens: row.try_get::<i64, _>("completion_tokens")? as u64,
+            })
+        })
+        .collect()
+    }
+
+    async fn guild_stats(
+        &self,
+        guild_id: GuildId,
+        since: OffsetDateTime,
+    ) -> Result<GuildStats, Error> {
+        let row = sqlx::query(
+            "SELECT
+                 COUNT(*) FILTER (WHERE ok) AS answered,
+                 COUNT(*) FILTER (WHERE NOT ok) AS errors,
+                 COALESCE(AVG(latency_ms) FILTER (WHERE ok), 0)::FLOAT8 AS average_latency_ms,
+                 COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens,
+                 COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens
+             FROM answers WHERE guild_id = $1 AND at >= $2",
+        )
+        .bind(id(guild_id))
+        .bind(since.unix_timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        let answered: i64 = row.try_get("answered")?;
+
+        // the answer 95% of the way up when sorted by latency
+        let p95_latency_ms: i64 = if answered == 0 {
+            0
+        } else {
+            sqlx::query_scalar(
+                "SELECT latency_ms FROM answers WHERE guild_id = $1 AND at >= $2 AND ok
+                 ORDER BY latency_ms LIMIT 1 OFFSET $3",
+            )
+            .bind(id(guild_id))
+            .bind(since.unix_timestamp())
+            .bind((answered - 1) * 95 / 100)
+            .fetch_one(&self.pool)
+            .await?
+        };
+
+        Ok(GuildStats {
+            answered: answered as u64,
+            errors: row.try_get::<i64, _>("errors")? as u64,
+            average_latency: Duration::from_secs_f64(
+                row.try_get::<f64, _>("average_latency_ms")? / 1000.0,
+            ),
+            p95_latency: Duration::from_millis(p95_latency_ms as u64),
+            prompt_tokens: row.try_get::<i64, _>("prompt_tokens")? as u64,
+            completion_tokens: row.try_get::<i64, _>("completion_tokens")? as u64,
+        })
+    }
+
+    async fn last_digest(&self, guild_id: GuildId) -> Result<Option<OffsetDateTime>, Error> {
+        let sent_at: Option<i64> =
+            sqlx::query_scalar("SELECT sent_at FROM digests WHERE guild_id = $1")
+                .bind(id(guild_id))
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(sent_at
+            .map(OffsetDateTime::from_unix_timestamp)
+            .transpose()?)
+    }
+
+    async fn set_last_digest(&self, guild_id: GuildId, at: OffsetDateTime) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO digests (guild_id, sent_at) VALUES ($1, $2)
+             ON CONFLICT (guild_id) DO UPDATE SET sent_at = excluded.sent_at",
+        )
+        .bind(id(guild_id))
+        .bind(at.unix_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_feedback(
+        &self,
+        message_id: MessageId,
+        user_id: UserId,
+        up: bool,
+    ) -> Result<bool, Error> {
+        let counted = sqlx::query(
+            "INSERT INTO feedback (message_id, user_id, up)
+             SELECT $1, $2, $3 WHERE EXISTS (SELECT 1 FROM answers WHERE message_id = $1)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(id(message_id))
+        .bind(id(user_id))
+        .bind(up)
+        .execute(&self.pool)
+        .await?;
+        Ok(counted.rows_affected() > 0)
+    }
+
+    async fn remove_feedback(
+        &self,
+        message_id: MessageId,
+        user_id: UserId,
+        up: bool,
+    ) -> Result<bool, Error> {
+        let counted =
+            sqlx::query("DELETE FROM feedback WHERE message_id = $1 AND user_id = $2 AND up = $3")
+                .bind(id(message_id))
+                .bind(id(user_id))
+                .bind(up)
+                .execute(&self.pool)
+                .await?;
+        Ok(counted.rows_affected() > 0)
+    }
+
+    async fn variant_stats(&self, experiment: &str) -> Result<Vec<VariantStats>, Error> {
+        let rows = sqlx::query(
+            "SELECT
+                 variant,
+                 COUNT(*) FILTER (WHERE ok) AS answered,
+                 COUNT(*) FILTER (WHERE NOT ok) AS errors,
+                 COALESCE(AVG(latency_ms) FILTER (WHERE ok), 0)::FLOAT8 AS average_latency_ms,
+                 COALESCE(SUM(f.up), 0)::BIGINT AS thumbs_up,
+                 COALESCE(SUM(f.down), 0)::BIGINT AS thumbs_down
+             FROM answers a
+             LEFT JOIN (
+                 SELECT message_id,
+                        COUNT(*) FILTER (WHERE up) AS up,
+                        COUNT(*) FILTER (WHERE NOT up) AS down
+                 FROM feedback GROUP BY message_id
+             ) f ON f.message_id = a.message_id
+             WHERE experiment = $1
+             GROUP BY variant ORDER BY variant",
+        )
+        .bind(experiment)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(VariantStats {
+                    variant: row.try_get("variant")?,
+                    answered: row.try_get::<i64, _>("answered")? as u64,
+                    errors: row.try_get::<i64, _>("errors")? as u64,
+                    average_latency: Duration::from_secs_f64(
+                        row.try_get::<f64, _>("average_latency_ms")? / 1000.0,
+                    ),
+                    thumbs_up: row.try_get::<i64, _>("thumbs_up")? as u64,
+                    thumbs_down: row.try_get::<i64, _>("thumbs_down")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn transcripts(
+        &self,
+        guild_id: GuildId,
+        since: OffsetDateTime,
+    ) -> Result<Vec<Transcript>, Error> {
+        sqlx::query(
+            "SELECT a.at, a.question, a.answer, a.ok,
+                    COALESCE(SUM(CASE WHEN f.up THEN 1 ELSE 0 END), 0) AS thumbs_up,
+                    COALESCE(SUM(CASE WHEN NOT f.up THEN 1 ELSE 0 END), 0) AS thumbs_down
+             FROM answers a LEFT JOIN feedback f ON f.message_id = a.message_id
+             WHERE a.guild_id = $1 AND a.at >= $2 AND a.question IS NOT NULL
+             GROUP BY a.id ORDER BY a.at, a.id",
+        )
+        .bind(id(guild_id))
+        .bind(since.unix_timestamp())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(Transcript {
+                at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+                question: self.cipher.open(row.try_get("question")?)?,
+                answer: self.cipher.open_optional(row.try_get("answer")?)?,
+                ok: row.try_get("ok")?,
+                thumbs_up: row.try_get::<i64, _>("thumbs_up")? as u64,
+                thumbs_down: row.try_get::<i64, _>("thumbs_down")? as u64,
+            })
+        })
+        .collect()
+    }
+
+    async fn save_prompt_version(&self, prompt: &PromptVersion) -> Result<(), Error> {
+        insert_prompt_version(prompt).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn prompt_version(&self, version: &str) -> Result<Option<PromptVersion>, Error> {
+        sqlx::query("SELECT version, text, created_at FROM prompt_versions WHERE version = $1")
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| prompt_version(&row))
+            .transpose()
+    }
+
+    async fn prompt_versions(&self, limit: usize) -> Result<Vec<PromptVersion>, Error> {
+        sqlx::query(
+            "SELECT version, text, created_at FROM prompt_versions
+             ORDER BY created_at DESC, id DESC LIMIT $1",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(prompt_version)
+        .collect()
+    }
+
+    async fn count_question(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        at: OffsetDateTime,
+    ) -> Result<u64, Error> {
+        let before: i64 = sqlx::query_scalar(
+            "INSERT INTO askers (guild_id, user_id, first_asked_at, questions)
+             VALUES ($1, $2, $3, 1)
+             ON CONFLICT (guild_id, user_id) DO UPDATE SET questions = askers.questions + 1
+             RETURNING questions - 1",
+        )
+        .bind(id(guild_id))
+        .bind(id(user_id))
+        .bind(at.unix_timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(before as u64)
+    }
+
+    async fn save_snippet(&self, snippet: &Snippet) -> Result<(), Error> {
+        insert_snippet(snippet).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn snippet(&self, guild_id: GuildId, name: &str) -> Result<Option<Snippet>, Error> {
+        sqlx::query(
+            "SELECT guild_id, name, content, updated_at FROM snippets
+             WHERE guild_id = $1 AND name = $2",
+        )
+        .bind(id(guild_id))
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(snippet)
+        .transpose()
+    }
+
+    async fn snippets(&self, guild_id: GuildId) -> Result<Vec<Snippet>, Error> {
+        sqlx::query(
+            "SELECT guild_id, name, content, updated_at FROM snippets
+             WHERE guild_id = $1 ORDER BY name",
+        )
+        .bind(id(guild_id))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(snippet)
+        .collect()
+    }
+
+    async fn add_task(
+        &self,
+        kind: &str,
+        due_at: OffsetDateTime,
+        payload: &serde_json::Value,
+    ) -> Result<i64, Error> {
+        Ok(sqlx::query_scalar(
+            "INSERT INTO tasks (kind, due_at, payload) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(kind)
+        .bind(due_at.unix_timestamp())
+        .bind(payload.to_string())
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    async fn due_tasks(&self, now: OffsetDateTime) -> Result<Vec<Task>, Error> {
+        sqlx::query(
+            "SELECT id, kind, due_at, payload FROM tasks WHERE due_at <= $1 ORDER BY due_at, id",
+        )
+        .bind(now.unix_timestamp())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(task)
+        .collect()
+    }
+
+    async fn remove_task(&self, id: i64) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM tasks WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn save_question_embedding(&self, question: &QuestionEmbedding) -> Result<(), Error> {
+        insert_question_embedding(question)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn question_embeddings(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+        since: OffsetDateTime,
+    ) -> Result<Vec<QuestionEmbedding>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, model, embedding FROM question_embeddings
+             WHERE guild_id = $1 AND model = $2 AND at >= $3 ORDER BY at",
+        )
+        .bind(id(guild_id))
+        .bind(model)
+        .bind(since.unix_timestamp())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(question_embedding)
+        .collect()
+    }
+
+    async fn index_answer(&self, answer: &IndexedAnswer) -> Result<(), Error> {
+        insert_indexed_answer(answer, &self.cipher)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn indexed_answers(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+    ) -> Result<Vec<IndexedAnswer>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, question, answer, model, embedding, author_id,
+                    trusted, asker_id
+             FROM indexed_answers WHERE guild_id = $1 AND model = $2 ORDER BY at",
+        )
+        .bind(id(guild_id))
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| indexed_answer(row, &self.cipher))
+        .collect()
+    }
+
+    async fn indexed_answer(&self, message_id: MessageId) -> Result<Option<IndexedAnswer>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, question, answer, model, embedding, author_id,
+                    trusted, asker_id
+             FROM indexed_answers WHERE message_id = $1",
+        )
+        .bind(id(message_id))
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(|row| indexed_answer(row, &self.cipher))
+        .transpose()
+    }
+
+    async fn trusted_answers(
+        &self,
+        guild_id: GuildId,
+        model: &str,
+    ) -> Result<Vec<IndexedAnswer>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, question, answer, model, embedding, author_id,
+                    trusted, asker_id
+             FROM indexed_answers WHERE guild_id = $1 AND model = $2 AND trusted ORDER BY at",
+        )
+        .bind(id(guild_id))
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| indexed_answer(row, &self.cipher))
+        .collect()
+    }
+
+    async fn save_document(&self, document: &Document, keep: usize) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_documents(&mut tx, document.guild_id).await?;
+        insert_document(document).execute(&mut *tx).await?;
+        snapshot_documents(&mut tx, document.guild_id, keep).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_document(
+        &self,
+        guild_id: GuildId,
+        name: &str,
+        keep: usize,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_documents(&mut tx, guild_id).await?;
+        let result = sqlx::query("DELETE FROM documents WHERE guild_id = $1 AND name = $2")
+            .bind(id(guild_id))
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = result.rows_affected() > 0;
+        if deleted {
+            snapshot_documents(&mut tx, guild_id, keep).await?;
+        }
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn documents(&self, guild_id: GuildId) -> Result<Vec<Document>, Error> {
+        select_documents(guild_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(document)
+            .collect()
+    }
+
+    async fn replace_documents(
+        &self,
+        guild_id: GuildId,
+        documents: &[Document],
+        keep: usize,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_documents(&mut tx, guild_id).await?;
+        sqlx::query("DELETE FROM documents WHERE guild_id = $1")
+            .bind(id(guild_id))
+            .execute(&mut *tx)
+            .await?;
+        for document in documents {
+            insert_document(document).execute(&mut *tx).await?;
+        }
+        snapshot_documents(&mut tx, guild_id, keep).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_kb_version(&self, version: &KbVersion, keep: usize) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        insert_kb_version(version)?.execute(&mut *tx).await?;
+        prune_kb_versions(version.guild_id, keep)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn kb_version(
+        &self,
+        guild_id: GuildId,
+        version: &str,
+    ) -> Result<Option<KbVersion>, Error> {
+        sqlx::query(
+            "SELECT guild_id, version, created_at, documents FROM kb_versions
+             WHERE guild_id = $1 AND version = $2",
+        )
+        .bind(id(guild_id))
+        .bind(version)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| kb_version(&row))
+        .transpose()
+    }
+
+    async fn kb_versions(&self, guild_id: GuildId, limit: usize) -> Result<Vec<KbVersion>, Error> {
+        sqlx::query(
+            "SELECT guild_id, version, created_at, documents FROM kb_versions
+             WHERE guild_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2",
+        )
+        .bind(id(guild_id))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(kb_version)
+        .collect()
+    }
+
+    async fn forget_user(&self, user_id: UserId) -> Result<u64, Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut records = 0;
+        for sql in [
+            "DELETE FROM answers WHERE user_id = $1",
+            "DELETE FROM feedback WHERE user_id = $1",
+            "DELETE FROM askers WHERE user_id = $1",
+            "DELETE FROM indexed_answers WHERE asker_id = $1 OR author_id = $1",
+            "DELETE FROM ratings WHERE user_id = $1",
+        ] {
+            records += sqlx::query(sql)
+                .bind(id(user_id))
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        // reminders and the like, which keep the id as a string
+        records += sqlx::query("DELETE FROM tasks WHERE payload::jsonb ->> 'user_id' = $1")
+            .bind(user_id.to_string())
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    async fn record_deletion(&self, deletion: &Deletion) -> Result<(), Error> {
+        insert_deletion(deletion).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn record_rating(&self, rating: &Rating) -> Result<(), Error> {
+        insert_rating(rating, &self.cipher)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn ratings(
+        &self,
+        guild_id: GuildId,
+        since: OffsetDateTime,
+    ) -> Result<Vec<Rating>, Error> {
+        sqlx::query(
+            "SELECT guild_id, channel_id, user_id, at, score, comment, escalated, transcript
+             FROM ratings WHERE guild_id = $1 AND at >= $2 ORDER BY at",
+        )
+        .bind(id(guild_id))
+        .bind(since.unix_timestamp())
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| rating(row, &self.cipher))
+        .collect()
+    }
+
+    async fn purge(&self, purge: &Purge) -> Result<u64, Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut records = 0;
+        for (before, sql) in [
+            (
+                purge.answers,
+                "DELETE FROM feedback WHERE message_id IN
+                 (SELECT message_id FROM answers WHERE at < $1)",
+            ),
+            (purge.answers, "DELETE FROM answers WHERE at < $1"),
+            (purge.answers, "DELETE FROM ratings WHERE at < $1"),
+            (
+                purge.indexed_answers,
+                "DELETE FROM indexed_answers WHERE at < $1",
+            ),
+            (
+                purge.question_embeddings,
+                "DELETE FROM question_embeddings WHERE at < $1",
+            ),
+            (purge.deletions, "DELETE FROM deletions WHERE at < $1"),
+        ] {
+            let Some(before) = before else {
+                continue;
+            };
+            records += sqlx::query(sql)
+                .bind(before.unix_timestamp())
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(records)
+    }
+
+    async fn setting(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(
+            sqlx::query_scalar("SELECT value FROM settings WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn set_setting(&self, key: &str, value: Option<&str>) -> Result<(), Error> {
+        match value {
+            Some(value) => {
+                sqlx::query(
+                    "INSERT INTO settings (key, value) VALUES ($1, $2)
+                     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(key)
+                .bind(value)
+                .execute(&self.pool)
+                .await?
+            }
+            None => {
+                sqlx::query("DELETE FROM settings WHERE key = $1")
+                    .bind(key)
+                    .execute(&self.pool)
+                    .await?
+            }
+        };
+        Ok(())
+    }
+
+    async fn dump(&self) -> Result<Dump, Error> {
+        let answers = sqlx::query(
+            "SELECT guild_id, at, ok, latency_ms, prompt_tokens, completion_tokens,
+                    message_id, experiment, variant, prompt_version, question, answer, user_id,
+                    channel_id, model, kb_version
+             FROM answers ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(AnswerRecord {
+                guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+                at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+                ok: row.try_get("ok")?,
+                latency: Duration::from_millis(row.try_get::<i64, _>("latency_ms")? as u64),
+                prompt_tokens: row.try_get::<i64, _>("prompt_tokens")? as usize,
+                completion_tokens: row.try_get::<i64, _>("completion_tokens")? as usize,
+                message_id: row
+                    .try_get::<Option<i64>, _>("message_id")?
+                    .map(|id| MessageId::new(id as u64)),
+                experiment: row.try_get("experiment")?,
+                variant: row.try_get("variant")?,
+                prompt_version: row.try_get("prompt_version")?,
+                question: self.cipher.open_optional(row.try_get("question")?)?,
+                answer: self.cipher.open_optional(row.try_get("answer")?)?,
+                user_id: row
+                    .try_get::<Option<i64>, _>("user_id")?
+                    .map(|id| UserId::new(id as u64)),
+                channel_id: row
+                    .try_get::<Option<i64>, _>("channel_id")?
+                    .map(|id| ChannelId::new(id as u64)),
+                model: row.try_get("model")?,
+                kb_version: row.try_get("kb_version")?,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+        let feedback = sqlx::query("SELECT message_id, user_id, up FROM feedback ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(Feedback {
+                    message_id: MessageId::new(row.try_get::<i64, _>("message_id")? as u64),
+                    user_id: UserId::new(row.try_get::<i64, _>("user_id")? as u64),
+                    up: row.try_get("up")?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let digests = sqlx::query("SELECT guild_id, sent_at FROM digests ORDER BY guild_id")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(Digest {
+                    guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+                    sent_at: OffsetDateTime::from_unix_timestamp(row.try_get("sent_at")?)?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let prompt_versions = sqlx::query(
+            "SELECT version, text, created_at FROM prompt_versions ORDER BY created_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(prompt_version)
+        .collect::<Result<_, Error>>()?;
+
+        let settings = sqlx::query_as("SELECT key, value FROM settings")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .collect();
+
+        let askers = sqlx::query(
+            "SELECT guild_id, user_id, first_asked_at, questions FROM askers
+             ORDER BY guild_id, user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(Asker {
+                guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+                user_id: UserId::new(row.try_get::<i64, _>("user_id")? as u64),
+                first_asked_at: OffsetDateTime::from_unix_timestamp(
+                    row.try_get("first_asked_at")?,
+                )?,
+                questions: row.try_get::<i64, _>("questions")? as u64,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+        let snippets = sqlx::query(
+            "SELECT guild_id, name, content, updated_at FROM snippets ORDER BY guild_id, name",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(snippet)
+        .collect::<Result<_, Error>>()?;
+
+        let tasks = sqlx::query("SELECT id, kind, due_at, payload FROM tasks ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(task)
+            .collect::<Result<_, Error>>()?;
+
+        let question_embeddings = sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, model, embedding FROM question_embeddings
+             ORDER BY at",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(question_embedding)
+        .collect::<Result<_, Error>>()?;
+
+        let indexed_answers = sqlx::query(
+            "SELECT guild_id, channel_id, message_id, at, question, answer, model, embedding, author_id,
+                    trusted, asker_id
+             FROM indexed_answers ORDER BY at",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| indexed_answer(row, &self.cipher))
+        .collect::<Result<_, Error>>()?;
+
+        let deletions =
+            sqlx::query("SELECT user_id, at, records, messages FROM deletions ORDER BY at")
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(|row| {
+                    Ok(Deletion {
+                        user_id: UserId::new(row.try_get::<i64, _>("user_id")? as u64),
+                        at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+                        records: row.try_get::<i64, _>("records")? as u64,
+                        messages: row.try_get::<i64, _>("messages")? as u64,
+                    })
+                })
+                .collect::<Result<_, Error>>()?;
+
+        let ratings = sqlx::query(
+            "SELECT guild_id, channel_id, user_id, at, score, comment, escalated, transcript
+             FROM ratings ORDER BY at, channel_id, user_id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(|row| rating(row, &self.cipher))
+        .collect::<Result<_, Error>>()?;
+
+        let documents = sqlx::query(
+            "SELECT guild_id, name, text, url, model, embedding, updated_at FROM documents
+             ORDER BY guild_id, name",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(document)
+        .collect::<Result<_, Error>>()?;
+
+        let kb_versions = sqlx::query(
+            "SELECT guild_id, version, created_at, documents FROM kb_versions
+             ORDER BY guild_id, created_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(kb_version)
+        .collect::<Result<_, Error>>()?;
+
+        Ok(Dump {
+            answers,
+            feedback,
+            digests,
+            prompt_versions,
+            settings,
+            askers,
+            snippets,
+            tasks,
+            question_embeddings,
+            indexed_answers,
+            deletions,
+            ratings,
+            documents,
+            kb_versions,
+        })
+    }
+
+    async fn restore(&self, dump: &Dump) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::raw_sql(
+            "TRUNCATE answers, feedback, digests, prompt_versions, settings, askers, snippets, tasks,
+                      question_embeddings, indexed_answers, deletions, ratings, documents, kb_versions",
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for record in &dump.answers {
+            insert_answer(record, &self.cipher)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for feedback in &dump.feedback {
+            sqlx::query(
+                "INSERT INTO feedback (message_id, user_id, up) VALUES ($1, $2, $3)
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(id(feedback.message_id))
+            .bind(id(feedback.user_id))
+            .bind(feedback.up)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for digest in &dump.digests {
+            sqlx::query(
+                "INSERT INTO digests (guild_id, sent_at) VALUES ($1, $2)
+                 ON CONFLICT (guild_id) DO UPDATE SET sent_at = excluded.sent_at",
+            )
+            .bind(id(digest.guild_id))
+            .bind(digest.sent_at.unix_timestamp())
+            .execute(&mut *tx)
+            .await?;
+        }
+        for prompt in &dump.prompt_versions {
+            insert_prompt_version(prompt).execute(&mut *tx).await?;
+        }
+        for (key, value) in &dump.settings {
+            sqlx::query(
+                "INSERT INTO settings (key, value) VALUES ($1, $2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            )
+            .bind(key)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for asker in &dump.askers {
+            sqlx::query(
+                "INSERT INTO askers (guild_id, user_id, first_asked_at, questions)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (guild_id, user_id) DO UPDATE SET
+                     first_asked_at = excluded.first_asked_at, questions = excluded.questions",
+            )
+            .bind(id(asker.guild_id))
+            .bind(id(asker.user_id))
+            .bind(asker.first_asked_at.unix_timestamp())
+            .bind(asker.questions as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        for snippet in &dump.snippets {
+            insert_snippet(snippet).execute(&mut *tx).await?;
+        }
+        for task in &dump.tasks {
+            sqlx::query("INSERT INTO tasks (id, kind, due_at, payload) VALUES ($1, $2, $3, $4)")
+                .bind(task.id)
+                .bind(&task.kind)
+                .bind(task.due_at.unix_timestamp())
+                .bind(task.payload.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+        // new tasks get ids after the restored ones
+        sqlx::raw_sql(
+            "SELECT setval(pg_get_serial_sequence('tasks', 'id'), COALESCE(MAX(id), 0) + 1, false)
+             FROM tasks",
+        )
+        .execute(&mut *tx)
+        .await?;
+        for question in &dump.question_embeddings {
+            insert_question_embedding(question)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for answer in &dump.indexed_answers {
+            insert_indexed_answer(answer, &self.cipher)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for deletion in &dump.deletions {
+            insert_deletion(deletion).execute(&mut *tx).await?;
+        }
+        for rating in &dump.ratings {
+            insert_rating(rating, &self.cipher)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for document in &dump.documents {
+            insert_document(document).execute(&mut *tx).await?;
+        }
+        for version in &dump.kb_versions {
+            insert_kb_version(version)?.execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+fn insert_answer<'a>(
+    record: &'a AnswerRecord,
+    cipher: &Cipher,
+) -> Query<'a, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO answers (guild_id, at, ok, latency_ms, prompt_tokens, completion_tokens,
+                              message_id, experiment, variant, prompt_version, question, answer,
+                              user_id, channel_id, model, kb_version)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)",
+    )
+    .bind(id(record.guild_id))
+    .bind(record.at.unix_timestamp())
+    .bind(record.ok)
+    .bind(record.latency.as_millis() as i64)
+    .bind(record.prompt_tokens as i64)
+    .bind(record.completion_tokens as i64)
+    .bind(record.message_id.map(id))
+    .bind(&record.experiment)
+    .bind(&record.variant)
+    .bind(&record.prompt_version)
+    .bind(cipher.seal_optional(record.question.as_deref()))
+    .bind(cipher.seal_optional(record.answer.as_deref()))
+    .bind(record.user_id.map(id))
+    .bind(record.channel_id.map(id))
+    .bind(&record.model)
+    .bind(&record.kb_version)
+}
+
+fn insert_prompt_version(prompt: &PromptVersion) -> Query<'_, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO prompt_versions (version, text, created_at) VALUES ($1, $2, $3)
+         ON CONFLICT (version) DO NOTHING",
+    )
+    .bind(&prompt.version)
+    .bind(&prompt.text)
+    .bind(prompt.created_at.unix_timestamp())
+}
+
+fn insert_snippet(snippet: &Snippet) -> Query<'_, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO snippets (guild_id, name, content, updated_at) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (guild_id, name) DO UPDATE
+         SET content = excluded.content, updated_at = excluded.updated_at",
+    )
+    .bind(id(snippet.guild_id))
+    .bind(&snippet.name)
+    .bind(&snippet.content)
+    .bind(snippet.updated_at.unix_timestamp())
+}
+
+fn snippet(row: &PgRow) -> Result<Snippet, Error> {
+    Ok(Snippet {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        name: row.try_get("name")?,
+        content: row.try_get("content")?,
+        updated_at: OffsetDateTime::from_unix_timestamp(row.try_get("updated_at")?)?,
+    })
+}
+
+fn insert_question_embedding(question: &QuestionEmbedding) -> Query<'_, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO question_embeddings (guild_id, channel_id, message_id, at, model, embedding)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(id(question.guild_id))
+    .bind(id(question.channel_id))
+    .bind(id(question.message_id))
+    .bind(question.at.unix_timestamp())
+    .bind(&question.model)
+    .bind(embedding_to_bytes(&question.embedding))
+}
+
+fn question_embedding(row: &PgRow) -> Result<QuestionEmbedding, Error> {
+    Ok(QuestionEmbedding {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        channel_id: ChannelId::new(row.try_get::<i64, _>("channel_id")? as u64),
+        message_id: MessageId::new(row.try_get::<i64, _>("message_id")? as u64),
+        at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+        model: row.try_get("model")?,
+        embedding: embedding_from_bytes(row.try_get("embedding")?),
+    })
+}
+
+fn insert_indexed_answer<'a>(
+    answer: &'a IndexedAnswer,
+    cipher: &Cipher,
+) -> Query<'a, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO indexed_answers (guild_id, channel_id, message_id, at, question, answer,
+                                      model, embedding, author_id, trusted, asker_id)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         ON CONFLICT (message_id) DO UPDATE
+         SET guild_id = excluded.guild_id, channel_id = excluded.channel_id, at = excluded.at,
+             question = excluded.question, answer = excluded.answer, model = excluded.model,
+             embedding = excluded.embedding, author_id = excluded.author_id,
+             trusted = excluded.trusted, asker_id = excluded.asker_id",
+    )
+    .bind(id(answer.guild_id))
+    .bind(id(answer.channel_id))
+    .bind(id(answer.message_id))
+    .bind(answer.at.unix_timestamp())
+    .bind(cipher.seal(&answer.question))
+    .bind(cipher.seal(&answer.answer))
+    .bind(&answer.model)
+    .bind(embedding_to_bytes(&answer.embedding))
+    .bind(answer.author_id.map(id))
+    .bind(answer.trusted)
+    .bind(answer.asker_id.map(id))
+}
+
+fn indexed_answer(row: &PgRow, cipher: &Cipher) -> Result<IndexedAnswer, Error> {
+    Ok(IndexedAnswer {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        channel_id: ChannelId::new(row.try_get::<i64, _>("channel_id")? as u64),
+        message_id: MessageId::new(row.try_get::<i64, _>("message_id")? as u64),
+        at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+        question: cipher.open(row.try_get("question")?)?,
+        answer: cipher.open(row.try_get("answer")?)?,
+        model: row.try_get("model")?,
+        embedding: embedding_from_bytes(row.try_get("embedding")?),
+        author_id: row
+            .try_get::<Option<i64>, _>("author_id")?
+            .map(|id| UserId::new(id as u64)),
+        trusted: row.try_get("trusted")?,
+        asker_id: row
+            .try_get::<Option<i64>, _>("asker_id")?
+            .map(|id| UserId::new(id as u64)),
+    })
+}
+
+fn insert_deletion(deletion: &Deletion) -> Query<'_, Postgres, PgArguments> {
+    sqlx::query("INSERT INTO deletions (user_id, at, records, messages) VALUES ($1, $2, $3, $4)")
+        .bind(id(deletion.user_id))
+        .bind(deletion.at.unix_timestamp())
+        .bind(deletion.records as i64)
+        .bind(deletion.messages as i64)
+}
+
+fn insert_rating<'a>(rating: &'a Rating, cipher: &Cipher) -> Query<'a, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO ratings (guild_id, channel_id, user_id, at, score, comment, escalated,
+                              transcript)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+         ON CONFLICT (channel_id, user_id) DO UPDATE
+         SET guild_id = excluded.guild_id, at = excluded.at, score = excluded.score,
+             comment = excluded.comment, escalated = excluded.escalated,
+             transcript = excluded.transcript",
+    )
+    .bind(id(rating.guild_id))
+    .bind(id(rating.channel_id))
+    .bind(id(rating.user_id))
+    .bind(rating.at.unix_timestamp())
+    .bind(rating.score as i64)
+    .bind(cipher.seal_optional(rating.comment.as_deref()))
+    .bind(rating.escalated)
+    .bind(cipher.seal(&rating.transcript))
+}
+
+fn rating(row: &PgRow, cipher: &Cipher) -> Result<Rating, Error> {
+    Ok(Rating {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        channel_id: ChannelId::new(row.try_get::<i64, _>("channel_id")? as u64),
+        user_id: UserId::new(row.try_get::<i64, _>("user_id")? as u64),
+        at: OffsetDateTime::from_unix_timestamp(row.try_get("at")?)?,
+        score: row.try_get::<i64, _>("score")? as u8,
+        comment: cipher.open_optional(row.try_get("comment")?)?,
+        escalated: row.try_get("escalated")?,
+        transcript: cipher.open(row.try_get("transcript")?)?,
+    })
+}
+
+fn insert_document(document: &Document) -> Query<'_, Postgres, PgArguments> {
+    sqlx::query(
+        "INSERT INTO documents (guild_id, name, text, url, model, embedding, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (guild_id, name) DO UPDATE
+         SET text = excluded.text, url = excluded.url, model = excluded.model,
+             embedding = excluded.embedding, updated_at = excluded.updated_at",
+    )
+    .bind(id(document.guild_id))
+    .bind(&document.name)
+    .bind(&document.text)
+    .bind(&document.url)
+    .bind(&document.model)
+    .bind(embedding_to_bytes(&document.embedding))
+    .bind(document.updated_at.unix_timestamp())
+}
+
+fn document(row: &PgRow) -> Result<Document, Error> {
+    Ok(Document {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        name: row.try_get("name")?,
+        text: row.try_get("text")?,
+        url: row.try_get("url")?,
+        model: row.try_get("model")?,
+        embedding: embedding_from_bytes(row.try_get("embedding")?),
+        updated_at: OffsetDateTime::from_unix_timestamp(row.try_get("updated_at")?)?,
+    })
+}
+
+fn select_documents(guild_id: GuildId) -> Query<'static, Postgres, PgArguments> {
+    sqlx::query(
+        "SELECT guild_id, name, text, url, model, embedding, updated_at FROM documents
+         WHERE guild_id = $1 ORDER BY name",
+    )
+    .bind(id(guild_id))
+}
+
+/// Snapshots the guild's knowledge base as `tx` leaves it, keeping the latest `keep` snapshots
+async fn snapshot_documents(
+    tx: &mut PgConnection,
+    guild_id: GuildId,
+    keep: usize,
+) -> Result<(), Error> {
+    let documents = select_documents(guild_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(document)
+        .collect::<Result<Vec<_>, _>>()?;
+    let version = KbVersion::of(guild_id, &documents);
+    // one the knowledge base goes back to becomes the newest again
+    sqlx::query("DELETE FROM kb_versions WHERE guild_id = $1 AND version = $2")
+        .bind(id(guild_id))
+        .bind(&version.version)
+        .execute(&mut *tx)
+        .await?;
+    insert_kb_version(&version)?.execute(&mut *tx).await?;
+    prune_kb_versions(guild_id, keep).execute(&mut *tx).await?;
+    Ok(())
+}
+
+fn prune_kb_versions(guild_id: GuildId, keep: usize) -> Query<'static, Postgres, PgArguments> {
+    sqlx::query(
+        "DELETE FROM kb_versions WHERE guild_id = $1 AND version NOT IN (
+             SELECT version FROM kb_versions WHERE guild_id = $2
+             ORDER BY created_at DESC, id DESC LIMIT $3
+         )",
+    )
+    .bind(id(guild_id))
+    .bind(id(guild_id))
+    .bind(keep as i64)
+}
+
+/// Keeps other changes to the guild's documents waiting until `tx` is done,
+/// so its snapshot is of the knowledge base it leaves
+async fn lock_documents(tx: &mut PgConnection, guild_id: GuildId) -> Result<(), Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("documents {}", guild_id))
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+fn insert_kb_version(version: &KbVersion) -> Result<Query<'_, Postgres, PgArguments>, Error> {
+    Ok(sqlx::query(
+        "INSERT INTO kb_versions (guild_id, version, created_at, documents)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (guild_id, version) DO UPDATE SET created_at = excluded.created_at",
+    )
+    .bind(id(version.guild_id))
+    .bind(&version.version)
+    .bind(version.created_at.unix_timestamp())
+    .bind(serde_json::to_string(&version.documents)?))
+}
+
+fn kb_version(row: &PgRow) -> Result<KbVersion, Error> {
+    Ok(KbVersion {
+        guild_id: GuildId::new(row.try_get::<i64, _>("guild_id")? as u64),
+        version: row.try_get("version")?,
+        created_at: OffsetDateTime::from_unix_timestamp(row.try_get("created_at")?)?,
+        documents: serde_json::from_str(row.try_get("documents")?)?,
+    })
+}
+
+fn task(row: &PgRow) -> Result<Task, Error> {
+    Ok(Task {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        due_at: OffsetDateTime::from_unix_timestamp(row.try_get("due_at")?)?,
+        payload: serde_json::from_str(row.try_get("payload")?)?,
+    })
+}
+
+fn prompt_version(row: &PgRow) -> Result<PromptVersion, Error> {
+    Ok(PromptVersion {
+        version: row.try_get("version")?,
+        text: row.try_get("text")?,
+        created_at: OffsetDateTime::from_unix_timestamp(row.try_get("created_at")?)?,
+    })
+}
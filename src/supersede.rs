@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serenity::all::{ChannelId, Message, UserId};
+use tokio::sync::Notify;
+
+struct Running {
+    id: u64,
+    cancel: Arc<Notify>,
+}
+
+/// The answer being generated for each user in each channel
+#[derive(Default)]
+pub struct InFlight {
+    running: Mutex<HashMap<(ChannelId, UserId), Running>>,
+    next_id: AtomicU64,
+}
+
+impl InFlight {
+    /// Registers an answer to `msg`, superseding one still being generated for
+    /// the same author in the same channel
+    pub fn start(self: &Arc<Self>, msg: &Message) -> Generation {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let key = (msg.channel_id, msg.author.id);
+        let cancel = Arc::new(Notify::new());
+        let previous = self.running.lock().unwrap().insert(
+            key,
+            Running {
+                id,
+                cancel: cancel.clone(),
+            },
+        );
+        if let Some(previous) = previous {
+            // remembered until it next checks, if it isn't waiting right now
+            previous.cancel.notify_one();
+        }
+        Generation {
+            in_flight: self.clone(),
+            key,
+            id,
+            cancel,
+        }
+    }
+}
+
+/// An answer in progress, unregistered on drop
+pub struct Generation {
+    in_flight: Arc<InFlight>,
+    key: (ChannelId, UserId),
+    id: u64,
+    cancel: Arc<Notify>,
+}
+
+impl Generation {
+    /// Waits until a newer question from the same user takes over
+    pub async fn superseded(&self) {
+        self.cancel.notified().await;
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        let mut running = self.in_flight.running.lock().unwrap();
+        if running.get(&self.key).is_some_and(|r| r.id == self.id) {
+            running.remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::{sync::Arc, time::Duration};
+
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    i18n,
+    markdown::fit_message,
+    oai,
+    storage::{Error, Transcript},
+    Data,
+};
+
+/// How often each guild with a `support_digest_channel` gets a digest
+const DIGEST_PERIOD: time::Duration = time::Duration::days(7);
+/// How often to see whether a digest is due
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How much of each question and answer the model reads
+const MAX_QUESTION: usize = 500;
+const MAX_ANSWER: usize = 300;
+/// How much of the week the model reads in all; the newest questions are kept
+const MAX_INPUT: usize = 60_000;
+
+const INSTRUCTIONS: &str = "You write a weekly digest of the questions a Discord server's \
+support bot was asked, for the server's staff. Each line is one question with the start of \
+the answer; [failed] means no answer was posted, and 👍/👎 count reactions to the answer. \
+In Markdown, under 1500 characters, give: the top topics, issues that went unanswered or got \
+👎, and error messages or patterns that came up more than once. Be specific and brief.";
+
+/// Posts a summary of last week's questions to each guild's
+/// `support_digest_channel` once a week, starting a week after it's set
+pub fn spawn_support_digests(http: Arc<Http>, data: Arc<Data>) {
+    tokio::spawn(async move {
+        loop {
+            let config = data.config();
+            for (guild_id, guild) in &config.guilds {
+                let (Ok(guild_id), Some(channel_id)) =
+                    (guild_id.parse::<GuildId>(), guild.support_digest_channel)
+                else {
+                    continue;
+                };
+                if let Err(e) =
+                    send_if_due(&http, &data, guild_id, ChannelId::new(channel_id)).await
+                {
+                    warn!(
+                        "Failed to send support digest for guild {}: {}",
+                        guild_id, e
+                    );
+                }
+            }
+            tokio::time::sleep(DIGEST_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+async fn send_if_due(
+    http: &Http,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<(), Error> {
+    let key = format!("support_digest.{}", guild_id);
+    let now = OffsetDateTime::now_utc();
+    let Some(last) = data.storage.setting(&key).await? else {
+        // start the clock
+        return data
+            .storage
+            .set_setting(&key, Some(&now.unix_timestamp().to_string()))
+            .await;
+    };
+    let last = OffsetDateTime::from_unix_timestamp(last.parse()?)?;
+    if now - last < DIGEST_PERIOD {
+        return Ok(());
+    }
+
+    let transcripts = data
+        .storage
+        .transcripts(guild_id, now - DIGEST_PERIOD)
+        .await?;
+    if !transcripts.is_empty() {
+        let config = data.config();
+        let (provider, model) = data.providers.for_guild(&config, Some(guild_id));
+        let summary = oai::complete(
+            provider,
+            &config,
+            &model,
+            INSTRUCTIONS,
+            &input(&transcripts),
+        )
+        .await?;
+        let locale = config.locale(Some(guild_id));
+        let content = format!(
+            "{}\n{}",
+            i18n::tr(
+                &locale,
+                "support-digest",
+                &[("questions", transcripts.len().into())]
+            ),
+            summary.trim()
+        );
+        channel_id
+            .send_message(http, CreateMessage::new().content(fit_message(content)))
+            .await?;
+        info!("Sent support digest for guild {}", guild_id);
+    }
+    data.storage
+        .set_setting(&key, Some(&now.unix_timestamp().to_string()))
+        .await
+}
+
+/// The week's questions as the model reads them, one per line
+pub fn input(transcripts: &[Transcript]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut length = 0;
+    for t in transcripts.iter().rev() {
+        let mut line = format!("[{}]", t.at.date());
+        if !t.ok {
+            line.push_str(" [failed]");
+        }
+        if t.thumbs_up > 0 {
+            line.push_str(&format!(" 👍{}", t.thumbs_up));
+        }
+        if t.thumbs_down > 0 {
+            line.push_str(&format!(" 👎{}", t.thumbs_down));
+        }
+        line.push_str(&format!(" Q: {}", one_line(&t.question, MAX_QUESTION)));
+        if let Some(answer) = &t.answer {
+            line.push_str(&format!(" A: {}", one_line(answer, MAX_ANSWER)));
+        }
+        length += line.len() + 1;
+        if length > MAX_INPUT {
+            break;
+        }
+        lines.push(line);
+    }
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// `text` on one line, cut to `max` characters
+pub(crate) fn one_line(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > max {
+        text.chars().take(max).collect::<String>() + "…"
+    } else {
+        text
+    }
+}
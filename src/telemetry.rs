@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::{config::LogFileConfig, logfile};
+
+/// Sets up logging to stdout and optionally a rotating file, plus OTLP export
+/// of traces and metrics when built with the `otel` feature and
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set. Keep the returned guard alive until exit.
+pub fn init(log_file: Option<&LogFileConfig>) -> Option<WorkerGuard> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn,deskhelp=info"));
+
+    let (file_layer, guard) = match log_file.map(logfile::open).transpose() {
+        Ok(Some((writer, guard))) => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer),
+            ),
+            Some(guard),
+        ),
+        Ok(None) => (None, None),
+        Err(e) => {
+            // logging isn't up yet
+            eprintln!("Failed to open log file, logging to stdout only: {}", e);
+            (None, None)
+        }
+    };
+
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer);
+
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otel::layer());
+
+    registry.init();
+    guard
+}
+
+/// Records how a request went. Does nothing without the `otel` feature.
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub fn record_request(model: &str, ok: bool, total: Duration, first_token: Option<Duration>) {
+    #[cfg(feature = "otel")]
+    {
+        use opentelemetry::KeyValue;
+
+        let m = otel::metrics();
+        let attrs = [
+            KeyValue::new("model", model.to_string()),
+            KeyValue::new("outcome", if ok { "ok" } else { "error" }),
+        ];
+        m.requests.add(1, &attrs);
+        m.request_duration.record(total.as_secs_f64(), &attrs);
+        if let Some(first_token) = first_token {
+            m.first_token.record(first_token.as_secs_f64(), &attrs);
+        }
+    }
+}
+
+/// Counts a Discord message send or edit. Does nothing without the `otel` feature.
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub fn record_discord_call(kind: &'static str) {
+    #[cfg(feature = "otel")]
+    otel::metrics()
+        .discord_calls
+        .add(1, &[opentelemetry::KeyValue::new("kind", kind)]);
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::sync::OnceLock;
+
+    use opentelemetry::{
+        global,
+        metrics::{Counter, Histogram},
+        trace::TracerProvider,
+    };
+    use opentelemetry_otlp::{MetricExporter, SpanExporter};
+    use opentelemetry_sdk::{
+        metrics::SdkMeterProvider,
+        trace::{SdkTracer, SdkTracerProvider},
+        Resource,
+    };
+    use tracing::Subscriber;
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    pub struct Metrics {
+        pub requests: Counter<u64>,
+        pub request_duration: Histogram<f64>,
+        pub first_token: Histogram<f64>,
+        pub discord_calls: Counter<u64>,
+    }
+
+    /// Instruments on the global meter, which is a no-op until `layer` installs a provider
+    pub fn metrics() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(|| {
+            let meter = global::meter("deskhelp");
+            Metrics {
+                requests: meter.u64_counter("deskhelp.requests").build(),
+                request_duration: meter
+                    .f64_histogram("deskhelp.request.duration")
+                    .with_unit("s")
+                    .build(),
+                first_token: meter
+                    .f64_histogram("deskhelp.request.first_token")
+                    .with_unit("s")
+                    .build(),
+                discord_calls: meter.u64_counter("deskhelp.discord.calls").build(),
+            }
+        })
+    }
+
+    /// Installs the OTLP exporters, configured through the standard
+    /// `OTEL_EXPORTER_OTLP_*` environment variables
+    pub fn layer<S>() -> Option<OpenTelemetryLayer<S, SdkTracer>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")?;
+
+        let resource = Resource::builder().with_service_name("deskhelp").build();
+
+        let spans = SpanExporter::builder()
+            .with_http()
+            .build()
+            .expect("failed to create OTLP span exporter");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_resource(resource.clone())
+            .build();
+
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .build()
+            .expect("failed to create OTLP metric exporter");
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(metrics)
+            .with_resource(resource)
+            .build();
+        global::set_meter_provider(meter_provider);
+
+        let tracer = tracer_provider.tracer("deskhelp");
+        global::set_tracer_provider(tracer_provider);
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+}
@@ -0,0 +1,103 @@
+use serenity::all::{EditThread, GuildChannel, GuildId, MessageId};
+use tracing::{info, warn};
+
+use crate::{config::Config, i18n, message_text, oai, Data};
+
+/// Discord's limit on a thread's name
+pub const MAX_TITLE: usize = 100;
+
+const INSTRUCTIONS: &str = "Name a Discord support thread about the question below. Reply \
+with only the name: at most 8 words, saying what the problem is, without quotes or a \
+trailing period.";
+
+/// A short name for a thread about `question`, from `title_model`, or `None`
+/// if the model fails or says nothing usable
+pub async fn generate(
+    data: &Data,
+    config: &Config,
+    guild_id: Option<GuildId>,
+    question: &str,
+) -> Option<String> {
+    let (provider, model) = data.providers.for_guild(config, guild_id);
+    let model = config.title_model.clone().unwrap_or(model);
+    match oai::complete(provider, config, &model, INSTRUCTIONS, question).await {
+        Ok(reply) => clean(&reply),
+        Err(e) => {
+            warn!("Failed to title a thread: {}", e);
+            None
+        }
+    }
+}
+
+/// The model's reply as a thread name: its first line, unquoted, cut to fit
+pub fn clean(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let title = line
+        .trim_start_matches(['"', '\'', '“', '*', '#'])
+        .trim_end_matches(['"', '\'', '”', '*', '.'])
+        .trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE).collect())
+}
+
+/// Whether a thread is still named after the bot's placeholder, as threads
+/// started on an answer before it's written are
+pub fn is_placeholder(name: &str) -> bool {
+    let name = name.trim();
+    name.is_empty()
+        || i18n::locales()
+            .iter()
+            .any(|locale| name == i18n::tr(locale, "generating", &[]).trim())
+}
+
+/// Names `thread` after `question` if it's still named after the placeholder
+pub async fn retitle(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    thread: &GuildChannel,
+    question: &str,
+) {
+    if thread.thread_metadata.is_none() || !is_placeholder(&thread.name) {
+        return;
+    }
+    let config = data.config();
+    let Some(title) = generate(data, &config, Some(thread.guild_id), question).await else {
+        return;
+    };
+    match thread
+        .id
+        .edit_thread(&ctx.http, EditThread::new().name(&title))
+        .await
+    {
+        Ok(_) => info!("Named thread {} {:?}", thread.id, title),
+        Err(e) => warn!("Failed to name thread {}: {}", thread.id, e),
+    }
+}
+
+/// Names a thread someone started on one of the bot's answers after the
+/// question it answers, since Discord names it after the answer as it was
+pub async fn on_thread_create(
+    ctx: &serenity::prelude::Context,
+    data: &Data,
+    thread: &GuildChannel,
+) {
+    let Some(parent_id) = thread.parent_id.filter(|_| is_placeholder(&thread.name)) else {
+        return;
+    };
+    // a thread started on a message shares its id
+    let starter = match parent_id
+        .message(ctx, MessageId::new(thread.id.get()))
+        .await
+    {
+        Ok(starter) => starter,
+        Err(_) => return,
+    };
+    if starter.author.id != ctx.cache.current_user().id {
+        return;
+    }
+    if let Some(question) = starter.referenced_message.as_deref() {
+        retitle(ctx, data, thread, &message_text::of(question)).await;
+    }
+}
@@ -0,0 +1,261 @@
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk,
+        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestMessage, ChatCompletionRequestToolMessage,
+        ChatCompletionRequestToolMessageContent, ChatCompletionTool, ChatCompletionToolType,
+        CompletionUsage, CreateChatCompletionRequest, CreateChatCompletionStreamResponse,
+        FunctionCall, FunctionObject,
+    },
+};
+use futures::{stream::BoxStream, StreamExt};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{provider::ChatBackend, storage::Error};
+
+/// How many rounds of tool calls the model gets before it has to answer
+const MAX_ROUNDS: usize = 4;
+
+/// Something the model can look up while answering
+#[serenity::async_trait]
+pub trait Tool: Send + Sync {
+    /// Letters, digits, `_` and `-`
+    fn name(&self) -> &str;
+
+    /// What the model is told it's for
+    fn description(&self) -> String;
+
+    /// JSON Schema of the arguments
+    fn parameters(&self) -> Value;
+
+    /// Links (or link prefixes) its results may contain, which answers may then pass on
+    fn links(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Runs it with the model's arguments; errors are passed on to the model
+    async fn call(&self, arguments: Value) -> Result<String, Error>;
+}
+
+/// The tools available for one answer
+#[derive(Default)]
+pub struct Tools {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl Tools {
+    pub fn add(&mut self, tool: impl Tool + 'static) {
+        self.tools.push(Box::new(tool));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Links the tools' results may contain, for the allowlist
+    pub fn links(&self) -> Vec<String> {
+        self.tools.iter().flat_map(|tool| tool.links()).collect()
+    }
+
+    /// As the API takes them
+    pub fn definitions(&self) -> Vec<ChatCompletionTool> {
+        self.tools
+            .iter()
+            .map(|tool| ChatCompletionTool {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionObject {
+                    name: tool.name().to_string(),
+                    description: Some(tool.description()),
+                    parameters: Some(tool.parameters()),
+                    strict: None,
+                },
+            })
+            .collect()
+    }
+
+    /// The result of one call, or what went wrong, for the model
+    pub async fn call(&self, call: &FunctionCall) -> String {
+        let Some(tool) = self.tools.iter().find(|t| t.name() == call.name) else {
+            return format!("Error: there's no tool called {}", call.name);
+        };
+        let arguments = match serde_json::from_str(&call.arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("Error: the arguments aren't valid JSON: {}", e),
+        };
+        info!("Calling tool {} with {}", call.name, call.arguments);
+        match tool.call(arguments).await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Tool {} failed: {}", call.name, e);
+                format!("Error: {}", e)
+            }
+        }
+    }
+}
+
+/// A tool call still streaming in
+#[derive(Default)]
+struct PartialCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Where a stream with tools is at
+struct Round<'a> {
+    backend: &'a dyn ChatBackend,
+    tools: &'a Tools,
+    request: CreateChatCompletionRequest,
+    stream: async_openai::types::ChatCompletionResponseStream,
+    rounds: usize,
+    text: String,
+    calls: Vec<PartialCall>,
+    /// Tokens the rounds before this one used, as far as the provider said
+    usage: Option<CompletionUsage>,
+}
+
+/// Like [`ChatBackend::create_stream`], but runs the tools the model calls and
+/// carries on streaming its answer after them, so only the answer is seen
+pub async fn create_stream<'a>(
+    backend: &'a dyn ChatBackend,
+    mut request: CreateChatCompletionRequest,
+    tools: &'a Tools,
+) -> Result<BoxStream<'a, Result<CreateChatCompletionStreamResponse, OpenAIError>>, OpenAIError> {
+    if tools.is_empty() {
+        return Ok(backend.create_stream(request).await?.boxed());
+    }
+    request.tools = Some(tools.definitions());
+    let stream = backend.create_stream(request.clone()).await?;
+    let round = Round {
+        backend,
+        tools,
+        request,
+        stream,
+        rounds: 0,
+        text: String::new(),
+        calls: Vec::new(),
+        usage: None,
+    };
+    Ok(futures::stream::unfold(Some(round), |round| async move {
+        let mut round = round?;
+        loop {
+            let mut chunk = match round.stream.next().await? {
+                Ok(chunk) => chunk,
+                Err(e) => return Some((Err(e), None)),
+            };
+            // the usage at the end counts the rounds that looked things up too
+            if let (Some(usage), Some(earlier)) = (&mut chunk.usage, &round.usage) {
+                add_usage(usage, earlier);
+            }
+            let Some(choice) = chunk.choices.first_mut() else {
+                return Some((Ok(chunk), Some(round)));
+            };
+            if let Some(content) = &choice.delta.content {
+                round.text.push_str(content);
+            }
+            if let Some(calls) = choice.delta.tool_calls.take() {
+                collect(&mut round.calls, calls);
+            }
+            if round.calls.is_empty() || choice.finish_reason.is_none() {
+                return Some((Ok(chunk), Some(round)));
+            }
+
+            // the model wants tool results before it answers
+            choice.finish_reason = None;
+            if let Err(e) = round.next().await {
+                return Some((Err(e), None));
+            }
+            if choice.delta.content.is_some() {
+                return Some((Ok(chunk), Some(round)));
+            }
+        }
+    })
+    .boxed())
+}
+
+impl Round<'_> {
+    /// Runs the calls the model made and asks again with their results
+    async fn next(&mut self) -> Result<(), OpenAIError> {
+        let calls: Vec<_> = std::mem::take(&mut self.calls)
+            .into_iter()
+            .map(|call| ChatCompletionMessageToolCall {
+                id: call.id,
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall {
+                    name: call.name,
+                    arguments: call.arguments,
+                },
+            })
+            .collect();
+        let text = std::mem::take(&mut self.text);
+        self.request
+            .messages
+            .push(ChatCompletionRequestMessage::Assistant(
+                ChatCompletionRequestAssistantMessage {
+                    content: (!text.is_empty())
+                        .then_some(ChatCompletionRequestAssistantMessageContent::Text(text)),
+                    tool_calls: Some(calls.clone()),
+                    ..Default::default()
+                },
+            ));
+        for call in &calls {
+            let result = self.tools.call(&call.function).await;
+            self.request
+                .messages
+                .push(ChatCompletionRequestMessage::Tool(
+                    ChatCompletionRequestToolMessage {
+                        content: ChatCompletionRequestToolMessageContent::Text(result),
+                        tool_call_id: call.id.clone(),
+                    },
+                ));
+        }
+
+        // the round's usage, if it was asked for, comes after its last choice
+        while let Some(Ok(chunk)) = self.stream.next().await {
+            if let Some(usage) = chunk.usage {
+                match &mut self.usage {
+                    Some(total) => add_usage(total, &usage),
+                    None => self.usage = Some(usage),
+                }
+            }
+        }
+
+        self.rounds += 1;
+        if self.rounds >= MAX_ROUNDS {
+            // enough looking things up
+            self.request.tools = None;
+        }
+        self.stream = self.backend.create_stream(self.request.clone()).await?;
+        Ok(())
+    }
+}
+
+fn add_usage(total: &mut CompletionUsage, usage: &CompletionUsage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+}
+
+/// Adds streamed pieces of tool calls to the calls they belong to
+fn collect(calls: &mut Vec<PartialCall>, chunks: Vec<ChatCompletionMessageToolCallChunk>) {
+    for chunk in chunks {
+        let index = chunk.index as usize;
+        if calls.len() <= index {
+            calls.resize_with(index + 1, PartialCall::default);
+        }
+        let call = &mut calls[index];
+        if let Some(id) = chunk.id {
+            call.id = id;
+        }
+        if let Some(function) = chunk.function {
+            if let Some(name) = function.name {
+                call.name.push_str(&name);
+            }
+            if let Some(arguments) = function.arguments {
+                call.arguments.push_str(&arguments);
+            }
+        }
+    }
+}
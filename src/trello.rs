@@ -0,0 +1,212 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{
+    plugins::{Answer, Plugin},
+    storage::Error,
+    tools::{Tool, Tools},
+};
+
+const API: &str = "https://api.trello.com/1";
+/// How long a fetched board is used before it's fetched again
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+/// How many cards a search returns at most
+const MAX_RESULTS: usize = 5;
+/// How much of a card's description to pass on
+const MAX_DESCRIPTION: usize = 300;
+
+/// Words that say nothing about which card is meant
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "with", "will", "are", "there", "any", "planned", "plan", "when", "what",
+    "does", "you", "yet", "can",
+];
+
+/// A column on the board, which for a roadmap says how far along its cards are
+#[derive(Deserialize, Clone, Debug)]
+pub struct List {
+    pub name: String,
+    pub cards: Vec<Card>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Card {
+    pub name: String,
+    #[serde(default)]
+    pub desc: String,
+    #[serde(rename = "shortUrl")]
+    pub short_url: String,
+}
+
+struct Fetched {
+    board: String,
+    at: Instant,
+    lists: Arc<Vec<List>>,
+}
+
+/// The last board fetched, shared by every question
+#[derive(Default)]
+pub struct BoardCache {
+    fetched: Mutex<Option<Fetched>>,
+}
+
+impl BoardCache {
+    /// The open cards on `board` by list, fetched at most every few minutes
+    pub async fn get(&self, http: &reqwest::Client, board: &str) -> Result<Arc<Vec<List>>, Error> {
+        let mut fetched = self.fetched.lock().await;
+        if let Some(f) = fetched.as_ref() {
+            if f.board == board && f.at.elapsed() < CACHE_TTL {
+                return Ok(f.lists.clone());
+            }
+        }
+        let lists: Vec<List> = http
+            .get(format!("{}/boards/{}/lists", API, board))
+            .query(&[
+                ("cards", "open"),
+                ("card_fields", "name,desc,shortUrl"),
+                ("fields", "name"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let lists = Arc::new(lists);
+        *fetched = Some(Fetched {
+            board: board.to_string(),
+            at: Instant::now(),
+            lists: lists.clone(),
+        });
+        Ok(lists)
+    }
+}
+
+/// The cards best matching `query`, with their lists; matches in the name count
+/// more than in the description
+pub fn search<'a>(lists: &'a [List], query: &str) -> Vec<(&'a List, &'a Card)> {
+    let query = query.to_lowercase();
+    let words: Vec<_> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() >= 3 && !STOP_WORDS.contains(w))
+        .collect();
+    let mut matches: Vec<_> = lists
+        .iter()
+        .flat_map(|list| list.cards.iter().map(move |card| (list, card)))
+        .filter_map(|(list, card)| {
+            let name = card.name.to_lowercase();
+            let desc = card.desc.to_lowercase();
+            let score: usize = words
+                .iter()
+                .map(|w| 3 * name.contains(w) as usize + desc.contains(w) as usize)
+                .sum();
+            (score > 0).then_some((score, list, card))
+        })
+        .collect();
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    matches
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|(_, list, card)| (list, card))
+        .collect()
+}
+
+/// The search results as the model gets them
+pub fn describe(query: &str, matches: &[(&List, &Card)]) -> String {
+    if matches.is_empty() {
+        return format!(
+            "No open cards on the board match \"{}\". It may not be planned, or be on the board under another name.",
+            query
+        );
+    }
+    let mut text = format!("Open cards on the board matching \"{}\":", query);
+    for (list, card) in matches {
+        text.push_str(&format!(
+            "\n- {} (in the \"{}\" list): {}",
+            card.name, list.name, card.short_url
+        ));
+        let desc = card.desc.trim();
+        if !desc.is_empty() {
+            let short: String = desc.chars().take(MAX_DESCRIPTION).collect();
+            let ellipsis = if short.len() < desc.len() { "…" } else { "" };
+            text.push_str(&format!("\n  {}{}", short.replace('\n', " "), ellipsis));
+        }
+    }
+    text
+}
+
+/// Lets the model look up what the roadmap board says about a feature or bug
+pub struct TrelloTool {
+    pub http: reqwest::Client,
+    pub cache: Arc<BoardCache>,
+    pub board: String,
+}
+
+#[serenity::async_trait]
+impl Tool for TrelloTool {
+    fn name(&self) -> &str {
+        "search_roadmap"
+    }
+
+    fn description(&self) -> String {
+        "Searches the public DeskThing Trello board (the roadmap) for cards about a feature \
+         or bug, and which list each is in, like planned, in progress or done. Use it when \
+         asked whether something is planned or being worked on, and link the card you found."
+            .to_string()
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "A few keywords for the feature or bug, like \"spotify lyrics\"",
+                },
+            },
+            "required": ["query"],
+            "additionalProperties": false,
+        })
+    }
+
+    fn links(&self) -> Vec<String> {
+        vec!["https://trello.com/c/".to_string()]
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, Error> {
+        let query = arguments["query"].as_str().ok_or("query is missing")?;
+        let lists = self.cache.get(&self.http, &self.board).await?;
+        Ok(describe(query, &search(&lists, query)))
+    }
+}
+
+/// Lets the model search the `[trello]` board, keeping it cached between questions
+#[derive(Default)]
+pub struct TrelloPlugin {
+    cache: Arc<BoardCache>,
+}
+
+#[serenity::async_trait]
+impl Plugin for TrelloPlugin {
+    fn name(&self) -> &'static str {
+        "trello"
+    }
+
+    async fn tools(&self, answer: &Answer<'_>, tools: &mut Tools) {
+        if let Some(trello) = &answer.config.trello {
+            tools.add(TrelloTool {
+                http: answer.data.http.clone(),
+                // under a memory budget the board goes with the answer
+                cache: match answer.config.memory_budget() {
+                    Some(_) => Default::default(),
+                    None => self.cache.clone(),
+                },
+                board: trello.board.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use async_openai::types::{ResponseFormat, ResponseFormatJsonSchema};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use serenity::all::{ForumTagId, Message};
+
+use crate::{
+    config::{GuildConfig, TriageConfig},
+    content_policy::Policy,
+    forum_tags,
+};
+
+/// How bad a problem is, for sorting and tagging
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// The answer in a triage channel, posted as JSON for other bots and scripts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Triage {
+    pub category: String,
+    pub severity: Severity,
+    pub suggested_fix: String,
+    pub links: Vec<String>,
+}
+
+/// The JSON Schema answers must match. With `categories`, the category must be one of them.
+pub fn schema(categories: &[String]) -> Value {
+    let mut category = json!({
+        "type": "string",
+        "description": "What the question is about",
+    });
+    if !categories.is_empty() {
+        category["enum"] = json!(categories);
+    }
+    json!({
+        "type": "object",
+        "properties": {
+            "category": category,
+            "severity": {
+                "type": "string",
+                "enum": ["low", "medium", "high", "critical"],
+                "description": "How badly this affects the user",
+            },
+            "suggested_fix": {
+                "type": "string",
+                "description": "What the user should try, in a few sentences of markdown",
+            },
+            "links": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Relevant links from the instructions, if any",
+            },
+        },
+        "required": ["category", "severity", "suggested_fix", "links"],
+        "additionalProperties": false,
+    })
+}
+
+/// Added to the system prompt, for APIs that don't enforce the schema themselves
+pub fn instructions(schema: &Value) -> String {
+    format!(
+        "\n\nIn this channel, reply with only a JSON object matching this JSON Schema, and nothing else:\n{}",
+        schema
+    )
+}
+
+/// Asks the API to only produce JSON matching `schema`
+pub fn response_format(schema: &Value) -> ResponseFormat {
+    ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+            description: Some("Triage of a support question".to_string()),
+            name: "triage".to_string(),
+            schema: Some(schema.clone()),
+            strict: Some(true),
+        },
+    }
+}
+
+/// Reads the model's answer, checking it against `schema`
+pub fn parse(text: &str, schema: &Value) -> Result<Triage, String> {
+    // models without enforcement like to wrap JSON in a code block anyway
+    let text = text.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .unwrap_or(text);
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    jsonschema::validate(schema, &value).map_err(|e| e.to_string())?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+impl Triage {
+    /// Holds the result to `policy`: links it doesn't allow are dropped from
+    /// `links`, and `suggested_fix` is treated as any answer is
+    pub fn screen(&mut self, policy: &Policy) {
+        self.links.retain(|link| policy.allows(link));
+        self.suggested_fix = policy.render(&self.suggested_fix);
+    }
+
+    /// As posted: a JSON code block
+    pub fn render(&self) -> String {
+        format!(
+            "```json\n{}\n```",
+            serde_json::to_string_pretty(self).expect("triage serializes")
+        )
+    }
+}
+
+/// The triage settings for `msg`'s channel, or the forum its thread is in
+pub async fn config_for<'a>(
+    ctx: &serenity::prelude::Context,
+    guild: Option<&'a GuildConfig>,
+    msg: &Message,
+) -> Option<&'a TriageConfig> {
+    let triage = guild?.triage.as_ref()?;
+    if triage.channels.contains(&msg.channel_id.get()) {
+        return Some(triage);
+    }
+    let channel = msg.channel(ctx).await.ok()?.guild()?;
+    let parent = channel.parent_id?;
+    triage.channels.contains(&parent.get()).then_some(triage)
+}
+
+/// Adds the forum tags named like the category or severity to the forum post `msg` is in
+pub async fn tag_post(ctx: &serenity::prelude::Context, msg: &Message, triage: &Triage) {
+    if let Some((post, forum)) = forum_tags::post_and_forum(ctx, msg).await {
+        forum_tags::apply(ctx, post, &forum, &names(triage)).await;
+    }
+}
+
+/// Tags whose names match (ignoring case), added to the ones already applied.
+/// Forum posts take at most five.
+pub fn matching_tags(
+    available: &[(ForumTagId, String)],
+    applied: &[ForumTagId],
+    triage: &Triage,
+) -> Vec<ForumTagId> {
+    forum_tags::matching(available, applied, &names(triage))
+}
+
+fn names(triage: &Triage) -> [String; 2] {
+    [
+        triage.category.clone(),
+        triage.severity.as_str().to_string(),
+    ]
+}
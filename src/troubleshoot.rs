@@ -0,0 +1,152 @@
+use std::time::Instant;
+
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestMessage,
+    },
+};
+use serenity::all::{ChannelId, GuildId};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    budget::{self, OverBudget},
+    config::{Config, TroubleshootNode},
+    content_policy::Policy,
+    i18n, oai,
+    queue::Priority,
+    storage::AnswerRecord,
+    telemetry, Data,
+};
+
+/// Added to the system prompt when the steps run out
+const FALLBACK_INSTRUCTIONS: &str = "The asker went through the server's troubleshooting \
+steps below, picking what matched their problem, and it's still not solved. Help them from \
+there. Don't suggest the fixes they already tried again.";
+
+/// The step the picks in `path` (option indexes, first pick first) lead to
+pub fn walk<'a>(root: &'a TroubleshootNode, path: &[usize]) -> Option<&'a TroubleshootNode> {
+    path.iter().try_fold(root, |node, &i| node.options.get(i))
+}
+
+/// The options picked along `path`
+pub fn trail<'a>(root: &'a TroubleshootNode, path: &[usize]) -> Vec<&'a str> {
+    (1..=path.len())
+        .filter_map(|depth| walk(root, &path[..depth]))
+        .map(|node| node.label.as_str())
+        .collect()
+}
+
+/// What the model is told the asker went through: each question with the
+/// option they picked, and the fix they tried, if they got to one
+pub fn steps(root: &TroubleshootNode, path: &[usize]) -> String {
+    let mut lines = vec![];
+    for depth in 0..path.len() {
+        let (Some(node), Some(picked)) = (walk(root, &path[..depth]), walk(root, &path[..=depth]))
+        else {
+            break;
+        };
+        let question = node.question.as_deref().unwrap_or("Which of these?");
+        lines.push(format!("{} {}", question, picked.label));
+    }
+    if let Some(answer) = walk(root, path).and_then(|node| node.answer.as_ref()) {
+        lines.push(format!("Tried, without luck: {}", answer));
+    }
+    lines.join("\n")
+}
+
+/// How handing over to the model went
+pub enum Handover {
+    Answered(String),
+    /// The guild used up its tokens for the month
+    OverBudget(OverBudget),
+    /// The guild only logs answers
+    DryRun,
+}
+
+/// Asks the guild's model to take over where the steps ran out, waiting in
+/// line and counting against the guild's tokens like any other answer.
+/// `system` is the system prompt answers get.
+pub async fn ask(
+    data: &Data,
+    config: &Config,
+    (guild_id, channel_id): (GuildId, ChannelId),
+    system: &str,
+    steps: &str,
+) -> Result<Handover, OpenAIError> {
+    match budget::check(data, config, guild_id).await {
+        Ok(Some(over)) => return Ok(Handover::OverBudget(over)),
+        Ok(None) => {}
+        // answer anyway rather than lock everyone out over a storage hiccup
+        Err(e) => warn!("Failed to check the token budget: {}", e),
+    }
+    let mut ticket = data.queue.join(Priority::Normal);
+    let _permit = loop {
+        match ticket.try_start() {
+            Ok(permit) => break permit,
+            Err(_) => ticket.changed().await,
+        }
+    };
+
+    let start_time = Instant::now();
+    let (provider, model) = data.providers.for_guild(config, Some(guild_id));
+    let instructions = format!("{}\n\n{}", system, FALLBACK_INSTRUCTIONS);
+    let messages = oai::instructed(&instructions, steps);
+    let prompt_tokens = messages.iter().map(oai::count_tokens).sum();
+    let answer = oai::complete_messages(provider, config, &model, messages).await;
+    telemetry::record_request(&model, answer.is_ok(), start_time.elapsed(), None);
+    let dry_run = config.dry_run(Some(guild_id));
+    let record = |ok, completion_tokens| {
+        // dry runs don't count
+        (!dry_run).then(|| AnswerRecord {
+            guild_id,
+            at: OffsetDateTime::now_utc(),
+            ok,
+            latency: start_time.elapsed(),
+            prompt_tokens,
+            completion_tokens,
+            message_id: None,
+            experiment: None,
+            variant: None,
+            prompt_version: Some(data.prompts.active().version.clone()),
+            question: None,
+            answer: None,
+            user_id: None,
+            channel_id: Some(channel_id),
+            model: Some(model.clone()),
+            kb_version: None,
+        })
+    };
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(e) => {
+            oai::record_answer(data, record(false, 0)).await;
+            return Err(e);
+        }
+    };
+    let completion_tokens = oai::count_tokens(&ChatCompletionRequestMessage::Assistant(
+        ChatCompletionRequestAssistantMessage {
+            content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+                answer.clone(),
+            )),
+            ..Default::default()
+        },
+    ));
+    oai::record_answer(data, record(true, completion_tokens)).await;
+    let locale = config.locale(Some(guild_id));
+    let allowed_links = oai::allowed_links(config, Some(guild_id), system);
+    let policy = Policy::new(config, Some(guild_id), &locale, allowed_links);
+    let answer = policy
+        .check(&answer)
+        .unwrap_or_else(|_| i18n::tr(&locale, "policy-topic", &[]));
+    if dry_run {
+        info!(
+            "Dry run, would have answered after troubleshooting: {}",
+            answer
+        );
+        return Ok(Handover::DryRun);
+    }
+    Ok(Handover::Answered(answer))
+}
@@ -0,0 +1,226 @@
+//! Tools from WebAssembly modules, for integrations added without rebuilding
+//! the bot. A module exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, room for `len` bytes of input
+//! - `describe() -> i64`, JSON like `{"name": "...", "description": "...",
+//!   "parameters": {...}, "links": [...]}`
+//! - `call(ptr: i32, len: i32) -> i64`, the tool's result for the JSON
+//!   arguments at `ptr`, as text
+//!
+//! Outputs are given as `ptr << 32 | len`. Modules get no imports, so they
+//! can't reach the network or files, and each call starts from a fresh
+//! instance with limited fuel and memory.
+
+use std::{path::Path, sync::Arc};
+
+use serde_json::Value;
+use tracing::{info, warn};
+use wasmi::{Engine, Linker, Module, StoreLimits, StoreLimitsBuilder};
+
+use crate::{
+    config::WasmToolsConfig,
+    plugins::{Answer, Plugin},
+    storage::Error,
+    tools::{Tool, Tools},
+};
+
+/// Longest output read back from a module
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Longest tool name the API takes
+const MAX_NAME: usize = 64;
+
+/// One module's tool
+#[derive(Clone)]
+pub struct WasmTool {
+    engine: Engine,
+    module: Arc<Module>,
+    fuel: u64,
+    max_memory: usize,
+    name: String,
+    description: String,
+    parameters: Value,
+    links: Vec<String>,
+}
+
+impl WasmTool {
+    /// Compiles a module and asks it what it is
+    pub fn load(wasm: &[u8], config: &WasmToolsConfig) -> Result<WasmTool, Error> {
+        let mut engine_config = wasmi::Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module = Arc::new(Module::new(&engine, wasm)?);
+        let mut tool = WasmTool {
+            engine,
+            module,
+            fuel: config.fuel,
+            max_memory: config.max_memory_mb * 1024 * 1024,
+            name: String::new(),
+            description: String::new(),
+            parameters: Value::Null,
+            links: vec![],
+        };
+        let described: Value = serde_json::from_slice(&tool.run("describe", None)?)?;
+        let name = described["name"].as_str().unwrap_or_default();
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(format!("\"{}\" can't name a tool", name).into());
+        }
+        tool.name = name.to_string();
+        tool.description = described["description"]
+            .as_str()
+            .ok_or("description is missing")?
+            .to_string();
+        tool.parameters = match &described["parameters"] {
+            Value::Null => serde_json::json!({ "type": "object", "properties": {} }),
+            parameters => parameters.clone(),
+        };
+        tool.links = described["links"]
+            .as_array()
+            .map(|links| {
+                links
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(tool)
+    }
+
+    /// Runs `export` in a fresh instance, with `input` written to memory
+    /// for it if given, and reads back its output
+    fn run(&self, export: &str, input: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory)
+            .instances(1)
+            .build();
+        let mut store = wasmi::Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        let instance = Linker::<StoreLimits>::new(&self.engine)
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("the module exports no memory")?;
+        let packed = match input {
+            None => instance
+                .get_typed_func::<(), i64>(&store, export)?
+                .call(&mut store, ())?,
+            Some(input) => {
+                let len = i32::try_from(input.len())?;
+                let ptr = instance
+                    .get_typed_func::<i32, i32>(&store, "alloc")?
+                    .call(&mut store, len)?;
+                memory
+                    .write(&mut store, ptr as u32 as usize, input)
+                    .map_err(|e| e.to_string())?;
+                instance
+                    .get_typed_func::<(i32, i32), i64>(&store, export)?
+                    .call(&mut store, (ptr, len))?
+            }
+        };
+        let (ptr, len) = ((packed as u64 >> 32) as usize, packed as u32 as usize);
+        if len > MAX_OUTPUT {
+            return Err(format!("{} returned more than {} bytes", export, MAX_OUTPUT).into());
+        }
+        let mut output = vec![0; len];
+        memory
+            .read(&store, ptr, &mut output)
+            .map_err(|e| e.to_string())?;
+        Ok(output)
+    }
+}
+
+#[serenity::async_trait]
+impl Tool for WasmTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn parameters(&self) -> Value {
+        self.parameters.clone()
+    }
+
+    fn links(&self) -> Vec<String> {
+        self.links.clone()
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, Error> {
+        let tool = self.clone();
+        // the interpreter doesn't yield, so keep it off the async workers
+        let output = tokio::task::spawn_blocking(move || {
+            tool.run("call", Some(arguments.to_string().as_bytes()))
+        })
+        .await??;
+        Ok(String::from_utf8(output)?)
+    }
+}
+
+/// The tools in `[wasm_tools]`'s folder, offered for every answer
+pub struct WasmPlugin {
+    tools: Vec<WasmTool>,
+}
+
+impl WasmPlugin {
+    /// Loads every `.wasm` module in the folder, skipping (and logging) any
+    /// that don't load
+    pub fn load(config: &WasmToolsConfig) -> WasmPlugin {
+        let mut paths: Vec<_> = match std::fs::read_dir(&config.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|e| e == "wasm"))
+                .collect(),
+            Err(e) => {
+                warn!("Failed to read the tools in {}: {}", config.dir, e);
+                vec![]
+            }
+        };
+        paths.sort();
+        let mut tools: Vec<WasmTool> = vec![];
+        for path in paths {
+            match load_file(&path, config) {
+                Ok(tool) if tools.iter().any(|t| t.name == tool.name) => {
+                    warn!(
+                        "Skipping {}: a tool is already called {}",
+                        path.display(),
+                        tool.name
+                    )
+                }
+                Ok(tool) => {
+                    info!("Loaded tool {} from {}", tool.name, path.display());
+                    tools.push(tool);
+                }
+                Err(e) => warn!("Failed to load the tool in {}: {}", path.display(), e),
+            }
+        }
+        WasmPlugin { tools }
+    }
+}
+
+fn load_file(path: &Path, config: &WasmToolsConfig) -> Result<WasmTool, Error> {
+    WasmTool::load(&std::fs::read(path)?, config)
+}
+
+#[serenity::async_trait]
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &'static str {
+        "wasm_tools"
+    }
+
+    async fn tools(&self, _answer: &Answer<'_>, tools: &mut Tools) {
+        for tool in &self.tools {
+            tools.add(tool.clone());
+        }
+    }
+}
@@ -0,0 +1,77 @@
+#[allow(dead_code)]
+mod support;
+
+use std::time::Duration;
+
+use deskhelp::{
+    activity::{self, Activity},
+    config::ActivityWebhookConfig,
+    storage::AnswerRecord,
+};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use support::{MockResponse, MockServer};
+use time::macros::datetime;
+
+fn record() -> AnswerRecord {
+    AnswerRecord {
+        guild_id: GuildId::new(1),
+        at: datetime!(2024-05-01 12:00 UTC),
+        ok: true,
+        latency: Duration::from_millis(1500),
+        prompt_tokens: 120,
+        completion_tokens: 30,
+        message_id: Some(MessageId::new(3)),
+        experiment: None,
+        variant: None,
+        prompt_version: Some("abc".to_string()),
+        question: None,
+        answer: None,
+        user_id: None,
+        channel_id: Some(ChannelId::new(2)),
+        model: Some("gpt-4o".to_string()),
+        kb_version: None,
+    }
+}
+
+#[tokio::test]
+async fn activity_is_posted_with_its_metadata() {
+    let server = MockServer::start(vec![MockResponse::Json("{}".to_string())]).await;
+    let webhook = ActivityWebhookConfig {
+        url: server.base_url.clone(),
+        secret: None,
+    };
+    let activity = Activity {
+        user_id: Some(UserId::new(4)),
+        ..Activity::new("discord", &record(), "How do I flash it?", "Like this.")
+    };
+    activity::send(&reqwest::Client::new(), &webhook, &activity)
+        .await
+        .unwrap();
+
+    let sent = &server.requests()[0];
+    assert_eq!(sent["source"], "discord");
+    assert_eq!(sent["at"], "2024-05-01T12:00:00Z");
+    assert_eq!(sent["guild_id"], "1");
+    assert_eq!(sent["channel_id"], "2");
+    assert_eq!(sent["user_id"], "4");
+    assert_eq!(sent["message_id"], "3");
+    assert_eq!(sent["question"], "How do I flash it?");
+    assert_eq!(sent["answer"], "Like this.");
+    assert_eq!(sent["model"], "gpt-4o");
+    assert_eq!(sent["prompt_version"], "abc");
+    assert_eq!(sent["prompt_tokens"], 120);
+    assert_eq!(sent["latency_ms"], 1500);
+}
+
+#[tokio::test]
+async fn rejected_activity_is_an_error() {
+    let server = MockServer::start(vec![MockResponse::Error(500)]).await;
+    let webhook = ActivityWebhookConfig {
+        url: server.base_url.clone(),
+        secret: None,
+    };
+    let activity = Activity::new("api", &record(), "Hi", "Hello");
+    assert!(activity::send(&reqwest::Client::new(), &webhook, &activity)
+        .await
+        .is_err());
+}
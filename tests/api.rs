@@ -0,0 +1,52 @@
+#![cfg(feature = "dashboard")]
+
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
+    ChatCompletionRequestToolMessage,
+};
+use deskhelp::{
+    api::{self, Turn},
+    oai,
+};
+
+fn turn(role: &str, content: &str) -> Turn {
+    Turn {
+        role: role.to_string(),
+        content: content.to_string(),
+    }
+}
+
+#[test]
+fn history_reads_like_a_discord_conversation() {
+    let history = [
+        turn("user", "My Car Thing won't boot"),
+        turn("assistant", "Try holding the back button."),
+    ];
+    let messages = api::conversation(&history, "website");
+    assert!(matches!(messages[0], ChatCompletionRequestMessage::User(_)));
+    assert_eq!(
+        oai::message_text(&messages[0]),
+        "website (0): My Car Thing won't boot"
+    );
+    assert_eq!(
+        oai::message_text(&messages[1]),
+        "Try holding the back button."
+    );
+}
+
+#[test]
+fn context_leaves_out_tool_results() {
+    let conversation = [
+        ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage::from(
+            "Here's the guide.",
+        )),
+        ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+            content: "{\"found\": true}".into(),
+            tool_call_id: "call_1".to_string(),
+        }),
+    ];
+    assert_eq!(
+        api::turns(&conversation),
+        [turn("assistant", "Here's the guide.")]
+    );
+}
@@ -0,0 +1,96 @@
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{backfill, config::Config};
+use serenity::all::{GuildId, Message, User, UserId};
+
+const BOT: UserId = UserId::new(1);
+
+fn from(user: u64, content: &str) -> Message {
+    let mut msg = Message::default();
+    msg.author.id = UserId::new(user);
+    msg.author.name = format!("user{}", user);
+    msg.content = content.to_string();
+    msg.guild_id = Some(GuildId::new(10));
+    msg
+}
+
+fn question(user: u64, content: &str) -> Message {
+    let mut msg = from(user, content);
+    let mut bot = User::default();
+    bot.id = BOT;
+    msg.mentions = vec![bot];
+    msg
+}
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn assistant(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+            text.to_string(),
+        )),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn questions_and_answers_are_read_back() {
+    let history = [
+        from(2, "unrelated chatter"),
+        question(2, "<@1> how do I flash it?"),
+        from(1, "Hold the button"),
+        from(
+            1,
+            "while plugging it in.\n-# Generated response in 1.2s (0.1s prep).",
+        ),
+        question(3, "<@1> and then?"),
+        from(1, "Error generating response!"),
+    ];
+    assert_eq!(
+        backfill::conversation(&history, &Config::default(), BOT, None, None, "en-US"),
+        vec![
+            user("user2 (2): <@1> how do I flash it?"),
+            assistant("Hold the button\nwhile plugging it in."),
+            user("user3 (3): <@1> and then?"),
+        ]
+    );
+}
+
+#[test]
+fn persona_answers_count_as_the_bots() {
+    let mut answer = from(99, "Hold the button.");
+    answer.author.name = "DeskHelp".to_string();
+    answer.webhook_id = Some(serenity::all::WebhookId::new(5));
+    let history = [question(2, "<@1> how?"), answer];
+    assert_eq!(
+        backfill::conversation(
+            &history,
+            &Config::default(),
+            BOT,
+            None,
+            Some("DeskHelp"),
+            "en-US"
+        ),
+        vec![user("user2 (2): <@1> how?"), assistant("Hold the button.")]
+    );
+    assert_eq!(
+        backfill::conversation(&history, &Config::default(), BOT, None, None, "en-US").len(),
+        1
+    );
+}
+
+#[test]
+fn channels_are_read_back_once() {
+    let backfilled = backfill::Backfilled::default();
+    let channel = serenity::all::ChannelId::new(7);
+    assert!(backfilled.first_time(channel));
+    assert!(!backfilled.first_time(channel));
+}
@@ -0,0 +1,105 @@
+#![cfg(all(feature = "backup", feature = "encryption"))]
+
+use deskhelp::{
+    backup,
+    config::StorageConfig,
+    prompt::PromptVersion,
+    storage::{AnswerRecord, SqliteStorage, Storage},
+};
+use serenity::all::{GuildId, UserId};
+use time::OffsetDateTime;
+
+const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+#[tokio::test]
+async fn backups_restore_everything_stored() {
+    let dir = std::env::temp_dir();
+    let db = dir.join(format!("deskhelp-backup-{}.db", std::process::id()));
+    let archive = dir.join(format!("deskhelp-backup-{}.tar.gz", std::process::id()));
+    let config = StorageConfig {
+        url: format!("sqlite://{}", db.display()),
+        encryption_key: Some(KEY.to_string()),
+    };
+    let cipher = deskhelp::crypto::Cipher::new(KEY).unwrap();
+    let storage = SqliteStorage::connect(&config.url)
+        .await
+        .unwrap()
+        .with_cipher(cipher);
+    storage
+        .record_answer(&AnswerRecord {
+            guild_id: GuildId::new(1),
+            at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+            ok: true,
+            latency: std::time::Duration::from_millis(1000),
+            prompt_tokens: 100,
+            completion_tokens: 20,
+            message_id: None,
+            experiment: None,
+            variant: None,
+            prompt_version: None,
+            question: Some("my screen is black".to_string()),
+            answer: Some("Try reflashing.".to_string()),
+            user_id: Some(UserId::new(2)),
+            channel_id: None,
+            model: None,
+            kb_version: None,
+        })
+        .await
+        .unwrap();
+    storage
+        .save_prompt_version(&PromptVersion {
+            version: "abc".to_string(),
+            text: "Be brief.".to_string(),
+            created_at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        })
+        .await
+        .unwrap();
+
+    // taken while the storage is still open
+    backup::backup(&config, &archive).await.unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&archive).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    let restored = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    backup::restore(&config, &restored, &archive).await.unwrap();
+    let dump = restored.dump().await.unwrap();
+    assert_eq!(dump, storage.dump().await.unwrap());
+    assert_eq!(
+        dump.answers[0].question.as_deref(),
+        Some("my screen is black")
+    );
+    assert_eq!(dump.prompt_versions[0].text, "Be brief.");
+
+    drop(storage);
+    std::fs::remove_file(&db).unwrap();
+    std::fs::remove_file(&archive).unwrap();
+}
+
+#[test]
+fn backed_up_configs_leave_secrets_out() {
+    let redacted = backup::redact(
+        r#"
+[storage]
+url = "postgres://deskhelp:hunter2@db/deskhelp"
+encryption_key = "MDEy..." # from openssl
+
+[dashboard]
+token = "abc"
+api_tokens = { ci = "def" }
+
+[[providers]]
+name = "openrouter"
+endpoints = [{ api_key = "sk-or-1", base_url = "https://openrouter.ai/api/v1" }]
+"#,
+    )
+    .unwrap();
+    for secret in ["hunter2", "MDEy", "abc", "def", "sk-or-1"] {
+        assert!(!redacted.contains(secret), "{} is in {}", secret, redacted);
+    }
+    assert!(redacted.contains(r#"url = "postgres://deskhelp@db/deskhelp""#));
+    assert!(redacted.contains(r#"encryption_key = "" # from openssl"#));
+    assert!(redacted.contains(r#"base_url = "https://openrouter.ai/api/v1""#));
+}
@@ -0,0 +1,91 @@
+use async_openai::{
+    error::OpenAIError,
+    types::{
+        ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+        ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+        ChatCompletionRequestUserMessageContent, ChatCompletionResponseStream,
+        CreateChatCompletionRequest,
+    },
+};
+use deskhelp::{bench, config::Config, context::Contexts, provider::ChatBackend};
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn assistant(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+            text.to_string(),
+        )),
+        ..Default::default()
+    })
+}
+
+fn contexts() -> Contexts {
+    Contexts::from([
+        (
+            "1".to_string(),
+            vec![
+                user("alice (1): how do I flash it?"),
+                assistant("Hold the button while plugging it in, then run the flasher."),
+                user("alice (1): and then?"),
+            ],
+        ),
+        (
+            "2".to_string(),
+            vec![user("bob (2): hi"), assistant("Hello!")],
+        ),
+    ])
+}
+
+#[test]
+fn every_question_is_replayed_with_what_came_before() {
+    let turns = bench::turns(&contexts());
+    assert_eq!(turns.len(), 3);
+    assert_eq!(turns[0].history.len(), 1);
+    assert_eq!(
+        turns[0].answer.as_deref(),
+        Some("Hold the button while plugging it in, then run the flasher.")
+    );
+    assert_eq!(turns[1].history.len(), 3);
+    assert_eq!(turns[1].answer, None);
+    assert_eq!(turns[2].answer.as_deref(), Some("Hello!"));
+}
+
+#[tokio::test]
+async fn recorded_answers_are_measured_against_the_mock() {
+    let turns = bench::turns(&contexts());
+    let report = bench::run(&Config::default(), None, "mock", &turns[..1]).await;
+    assert_eq!(report.failures, 0);
+    let sample = &report.samples[0];
+    assert!(sample.first_token.is_some());
+    assert!(sample.completion_tokens > 0);
+    // the placeholder, at least one edit, and the final one
+    assert!(sample.discord_calls >= 2);
+    assert!(sample.tokens_per_second() > 0.0);
+    assert!(report.summary().starts_with("1 answers, 0 failed"));
+}
+
+struct Failing;
+
+#[serenity::async_trait]
+impl ChatBackend for Failing {
+    async fn create_stream(
+        &self,
+        _request: CreateChatCompletionRequest,
+    ) -> Result<ChatCompletionResponseStream, OpenAIError> {
+        Err(OpenAIError::InvalidArgument("down".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn answers_that_fail_are_counted() {
+    let turns = bench::turns(&contexts());
+    let report = bench::run(&Config::default(), Some(&Failing), "mock", &turns).await;
+    assert_eq!(report.failures, 3);
+    assert_eq!(report.summary(), "No answers finished (3 failed)");
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use deskhelp::{
+    billing::{self, ChannelSpend},
+    models::ModelConfig,
+    storage::Usage,
+};
+use serenity::all::{ChannelId, GuildId};
+
+fn usage(channel: Option<u64>, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Usage {
+    Usage {
+        guild_id: GuildId::new(1),
+        channel_id: channel.map(ChannelId::new),
+        model: Some(model.to_string()),
+        prompt_tokens,
+        completion_tokens,
+    }
+}
+
+fn models() -> HashMap<String, ModelConfig> {
+    [(
+        "gpt-4o".to_string(),
+        ModelConfig {
+            input_price: Some(2.5),
+            output_price: Some(10.0),
+            ..Default::default()
+        },
+    )]
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn spending_is_priced_per_model_and_channel() {
+    let spend = billing::spend(
+        &[
+            usage(Some(10), "gpt-4o", 1_000_000, 100_000),
+            usage(Some(11), "gpt-4o", 200_000, 0),
+            usage(Some(11), "llama", 5_000, 500),
+            usage(None, "gpt-4o", 0, 0),
+        ],
+        &models(),
+    );
+    assert_eq!(spend.prompt_tokens, 1_205_000);
+    assert_eq!(spend.completion_tokens, 100_500);
+    assert!((spend.cost - 4.0).abs() < 1e-9);
+    assert_eq!(spend.unpriced_tokens, 5_500);
+    assert_eq!(
+        spend.channels[0],
+        ChannelSpend {
+            guild_id: GuildId::new(1),
+            channel_id: Some(ChannelId::new(10)),
+            cost: 3.5,
+            tokens: 1_100_000,
+        }
+    );
+    assert_eq!(spend.channels[1].tokens, 205_500);
+    assert_eq!(spend.channels.len(), 3);
+}
+
+#[test]
+fn each_threshold_alerts_once_a_day() {
+    let thresholds = [5.0, 20.0, 100.0];
+    assert_eq!(billing::crossed(&thresholds, 4.99, None), None);
+    assert_eq!(billing::crossed(&thresholds, 5.0, None), Some(5.0));
+    assert_eq!(billing::crossed(&thresholds, 30.0, None), Some(20.0));
+    assert_eq!(billing::crossed(&thresholds, 30.0, Some(20.0)), None);
+    assert_eq!(
+        billing::crossed(&thresholds, 150.0, Some(20.0)),
+        Some(100.0)
+    );
+}
+
+#[test]
+fn alerts_list_where_the_money_went() {
+    let spend = billing::spend(
+        &[
+            usage(Some(10), "gpt-4o", 1_000_000, 100_000),
+            usage(None, "llama", 500, 0),
+        ],
+        &models(),
+    );
+    let summary = billing::summary("en-US", 3.0, &spend);
+    assert!(summary.starts_with("Spending today passed $3.00: about $3.50 so far"));
+    assert!(summary.contains("500 of those tokens came from models without prices"));
+    assert!(summary.contains("\n- <#10>: $3.50, 1100000 tokens"));
+    assert!(summary.contains("\n- a channel in server 1: $0.00, 500 tokens"));
+}
@@ -0,0 +1,63 @@
+use deskhelp::{
+    budget::{self, OverBudget},
+    config::Config,
+    storage::SqliteStorage,
+};
+use serenity::all::GuildId;
+use time::macros::datetime;
+
+#[test]
+fn months_start_at_midnight_utc_on_the_first() {
+    let now = datetime!(2026-10-17 15:30 UTC);
+    assert_eq!(budget::month_start(now), datetime!(2026-10-01 0:00 UTC));
+    assert_eq!(
+        budget::next_month_start(now),
+        datetime!(2026-11-01 0:00 UTC)
+    );
+    assert_eq!(
+        budget::next_month_start(datetime!(2026-12-31 23:59 UTC)),
+        datetime!(2027-01-01 0:00 UTC)
+    );
+}
+
+#[test]
+fn guilds_can_have_their_own_cap() {
+    let config: Config = toml::from_str(
+        r#"
+        monthly_token_cap = 1000000
+
+        [guilds."1"]
+        monthly_token_cap = 5000
+
+        [guilds."2"]
+        locale = "de"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.monthly_token_cap(Some(GuildId::new(1))), Some(5000));
+    assert_eq!(
+        config.monthly_token_cap(Some(GuildId::new(2))),
+        Some(1000000)
+    );
+    assert_eq!(Config::default().monthly_token_cap(None), None);
+}
+
+#[tokio::test]
+async fn going_over_the_cap_is_told_once_a_month() {
+    let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    let guild_id = GuildId::new(1);
+    let over = OverBudget {
+        used: 1200,
+        cap: 1000,
+        resets_at: datetime!(2026-11-01 0:00 UTC),
+    };
+    assert!(budget::first_time_over(&storage, guild_id, &over).await);
+    assert!(!budget::first_time_over(&storage, guild_id, &over).await);
+    // other guilds, and the next month, are news again
+    assert!(budget::first_time_over(&storage, GuildId::new(2), &over).await);
+    let next_month = OverBudget {
+        resets_at: datetime!(2026-12-01 0:00 UTC),
+        ..over
+    };
+    assert!(budget::first_time_over(&storage, guild_id, &next_month).await);
+}
@@ -0,0 +1,80 @@
+#[allow(dead_code)]
+mod support;
+
+use std::sync::Arc;
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{
+    capture::Captures,
+    models::Capabilities,
+    oai,
+    provider::{ChatBackend, Endpoint, Provider, Providers},
+};
+use futures::TryStreamExt;
+use support::{fixture, MockResponse, MockServer};
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn provider(servers: &[&MockServer]) -> Provider {
+    let endpoints = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Endpoint::new(format!("mock#{}", i), "sk-test", &s.base_url))
+        .collect();
+    Provider::new("mock", endpoints, "mock-model".to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn captures_keep_the_last_requests_redacted() {
+    let broken = MockServer::start(vec![MockResponse::Error(500)]).await;
+    let working = MockServer::start(vec![
+        MockResponse::Sse(fixture("hello.sse")),
+        MockResponse::Sse(fixture("hello.sse")),
+    ])
+    .await;
+    let mut providers = Providers::new(provider(&[&broken, &working]), &Default::default());
+    let captures = Arc::new(Captures::new(2));
+    providers.capture(captures.clone());
+    let provider = providers.get("default").unwrap();
+
+    let (messages, _) = oai::build_prompt(
+        "Be brief.".to_string(),
+        &[user(
+            "alice (123456789012345678): mail me at alice@example.com",
+        )],
+        7000,
+        &[],
+    );
+    let request = oai::chat_request(&provider.model, &Capabilities::default(), messages);
+    let mut stream = provider.create_stream(request.clone()).await.unwrap();
+    while stream.try_next().await.unwrap().is_some() {}
+
+    let all = providers.captures().unwrap().all();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].endpoint, "mock#0");
+    assert!(all[0].error.is_some());
+    assert_eq!(all[1].endpoint, "mock#1");
+    assert_eq!(all[1].error, None);
+    assert_eq!(
+        all[1].request["messages"][1]["content"],
+        "alice [id] mail me at [email]"
+    );
+    assert_eq!(
+        all[1].response[1]["choices"][0]["delta"]["content"],
+        "Hello"
+    );
+
+    let mut stream = provider.create_stream(request).await.unwrap();
+    while stream.try_next().await.unwrap().is_some() {}
+    let all = captures.all();
+    assert_eq!(all.len(), 2, "only the last two are kept");
+    assert_eq!(all[0].endpoint, "mock#1");
+}
@@ -0,0 +1,58 @@
+#[allow(dead_code)]
+mod support;
+
+use deskhelp::{
+    catalog::{self, ModelCatalog},
+    config::Config,
+    provider::{Endpoint, Provider, Providers},
+    storage::SqliteStorage,
+};
+use serenity::all::GuildId;
+use support::{fixture, MockResponse, MockServer};
+
+fn provider(servers: &[&MockServer]) -> Provider {
+    let endpoints = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Endpoint::new(format!("mock#{}", i), "sk-test", &s.base_url))
+        .collect();
+    Provider::new("mock", endpoints, "mock-model".to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn lists_models_from_the_first_endpoint_that_can() {
+    let broken = MockServer::start(vec![MockResponse::Error(404)]).await;
+    let working = MockServer::start(vec![MockResponse::Json(fixture("models.json"))]).await;
+    assert_eq!(
+        provider(&[&broken, &working]).models().await.unwrap(),
+        ["gpt-4o", "gpt-4o-mini", "text-embedding-3-small"]
+    );
+}
+
+#[tokio::test]
+async fn picked_models_are_offered_kept_and_restored() {
+    let server = MockServer::start(vec![MockResponse::Json(fixture("models.json"))]).await;
+    let providers = Providers::new(provider(&[&server]), &Default::default());
+    let catalog = ModelCatalog::default();
+    assert_eq!(catalog.refresh(&providers).await, 3);
+    assert_eq!(
+        catalog.suggest("default", "GPT-4O"),
+        ["gpt-4o", "gpt-4o-mini"]
+    );
+    assert_eq!(catalog.models("community"), None);
+
+    let config = Config::default();
+    let guild = GuildId::new(1);
+    providers.pick_model(guild, Some("gpt-4o".to_string()));
+    assert_eq!(providers.for_guild(&config, Some(guild)).1, "gpt-4o");
+    assert_eq!(providers.for_guild(&config, None).1, "mock-model");
+
+    let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    catalog::save(&storage, &providers).await.unwrap();
+    let restarted = Providers::new(provider(&[&server]), &Default::default());
+    catalog::restore(&storage, &restarted).await.unwrap();
+    assert_eq!(restarted.for_guild(&config, Some(guild)).1, "gpt-4o");
+
+    restarted.pick_model(guild, None);
+    assert_eq!(restarted.for_guild(&config, Some(guild)).1, "mock-model");
+}
@@ -0,0 +1,89 @@
+use deskhelp::{
+    config::Config,
+    content_policy::{self, Policy},
+    links::LinkPolicy,
+};
+use serenity::all::GuildId;
+
+fn list(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn blocked_words_are_masked_as_whole_words_in_any_case() {
+    let words = list(&["darn", "heck no"]);
+    assert_eq!(
+        content_policy::mask("Darn it, heck no! Darnell is fine.", &words, "[removed]"),
+        "[removed] it, [removed]! Darnell is fine."
+    );
+    assert_eq!(
+        content_policy::blocked_words("HECK NO, said Darnell", &words),
+        ["heck no"]
+    );
+    assert_eq!(content_policy::mask("Ärger", &list(&["ärger"]), "*"), "*");
+    assert_eq!(
+        content_policy::mask("nothing here", &words, "*"),
+        "nothing here"
+    );
+}
+
+#[test]
+fn answers_mentioning_a_blocked_topic_are_caught() {
+    let topics = list(&["jailbreak", "warranty claims"]);
+    assert_eq!(
+        content_policy::blocked_topic("About Warranty Claims: ...", &topics),
+        Some("warranty claims")
+    );
+    assert_eq!(
+        content_policy::blocked_topic("Jailbreaking isn't this.", &topics),
+        None
+    );
+}
+
+#[test]
+fn guilds_can_have_their_own_link_policy() {
+    let config: Config = toml::from_str(
+        r#"
+        link_policy = "flag"
+
+        [guilds."1".content_policy]
+        link_policy = "strip"
+        blocked_words = ["darn"]
+        "#,
+    )
+    .unwrap();
+    assert!(config.link_policy(Some(GuildId::new(1))) == LinkPolicy::Strip);
+    assert!(config.link_policy(Some(GuildId::new(2))) == LinkPolicy::Flag);
+}
+
+#[test]
+fn every_answer_goes_through_the_guilds_policy() {
+    let config: Config = toml::from_str(
+        r#"
+        [guilds."1".content_policy]
+        blocked_words = ["darn"]
+        blocked_topics = ["jailbreak"]
+        "#,
+    )
+    .unwrap();
+    let policy = Policy::new(
+        &config,
+        Some(GuildId::new(1)),
+        "en-US",
+        vec!["https://example.com".to_string()],
+    );
+    assert_eq!(
+        policy
+            .check("Darn, see <https://example.com/fix> or <https://elsewhere.example>.")
+            .unwrap(),
+        "[removed], see <https://example.com/fix> or *(link removed)*."
+    );
+    assert_eq!(policy.check("How to jailbreak it"), Err("jailbreak"));
+    assert!(policy.checks_topics());
+    // other guilds go by theirs
+    let elsewhere = Policy::new(&config, Some(GuildId::new(2)), "en-US", vec![]);
+    assert_eq!(
+        elsewhere.check("How to jailbreak it"),
+        Ok("How to jailbreak it".to_string())
+    );
+}
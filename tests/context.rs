@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{
+    config::MemoryBudget,
+    context::{ContextStore, Contexts, MemoryContexts},
+};
+use serenity::all::ChannelId;
+use time::OffsetDateTime;
+
+// Each check runs against every store: memory, and Redis when built with
+// `--features redis` and DESKHELP_TEST_REDIS_URL points at a server the tests
+// may write to (under a `deskhelp-test:` prefix)
+macro_rules! store_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(std::sync::Arc::new(super::MemoryContexts::default())).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "redis")]
+        mod redis {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    let Some((store, _guard)) = super::redis().await else {
+                        return;
+                    };
+                    super::$name(store).await;
+                }
+            )*
+        }
+    };
+}
+
+store_tests!(
+    conversations_grow_per_channel,
+    clearing_forgets_one_channel,
+    everything_can_be_replaced,
+    empty_conversations_are_told_apart,
+    idle_conversations_are_cleared,
+    updates_edit_one_conversation,
+);
+
+/// An empty store, held until the guard is dropped since tests share the server
+#[cfg(feature = "redis")]
+async fn redis() -> Option<(Arc<dyn ContextStore>, tokio::sync::MutexGuard<'static, ()>)> {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let config = deskhelp::config::RedisConfig {
+        url: std::env::var("DESKHELP_TEST_REDIS_URL").ok()?,
+        key_prefix: "deskhelp-test:".to_string(),
+    };
+    let guard = LOCK.lock().await;
+    let conn = deskhelp::shared::connect(&config).await.unwrap();
+    let store = deskhelp::shared::RedisContexts::new(conn, &config);
+    store.replace_all(&Contexts::new()).await.unwrap();
+    Some((Arc::new(store), guard))
+}
+
+fn message(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+async fn conversations_grow_per_channel(store: Arc<dyn ContextStore>) {
+    let (a, b) = (ChannelId::new(1), ChannelId::new(2));
+    assert_eq!(
+        store.push(a, message("one")).await.unwrap(),
+        vec![message("one")]
+    );
+    store.push(b, message("elsewhere")).await.unwrap();
+    assert_eq!(
+        store.push(a, message("two")).await.unwrap(),
+        vec![message("one"), message("two")]
+    );
+    assert_eq!(store.all().await.unwrap().len(), 2);
+    assert_eq!(
+        store.get(a).await.unwrap(),
+        vec![message("one"), message("two")]
+    );
+    assert!(store.get(ChannelId::new(3)).await.unwrap().is_empty());
+}
+
+async fn clearing_forgets_one_channel(store: Arc<dyn ContextStore>) {
+    let (a, b) = (ChannelId::new(1), ChannelId::new(2));
+    store.push(a, message("one")).await.unwrap();
+    store.push(b, message("elsewhere")).await.unwrap();
+    store.clear(a).await.unwrap();
+    assert_eq!(
+        store.push(a, message("two")).await.unwrap(),
+        vec![message("two")]
+    );
+    assert_eq!(store.all().await.unwrap()["2"], vec![message("elsewhere")]);
+}
+
+async fn everything_can_be_replaced(store: Arc<dyn ContextStore>) {
+    store.push(ChannelId::new(1), message("old")).await.unwrap();
+    let contexts: Contexts = [("3".to_string(), vec![message("a"), message("b")])]
+        .into_iter()
+        .collect();
+    store.replace_all(&contexts).await.unwrap();
+    assert_eq!(store.all().await.unwrap(), contexts);
+}
+
+async fn empty_conversations_are_told_apart(store: Arc<dyn ContextStore>) {
+    let (a, b) = (ChannelId::new(1), ChannelId::new(2));
+    assert!(store.is_empty(a).await.unwrap());
+    store.push(a, message("one")).await.unwrap();
+    assert!(!store.is_empty(a).await.unwrap());
+    assert!(store.is_empty(b).await.unwrap());
+    store.clear(a).await.unwrap();
+    assert!(store.is_empty(a).await.unwrap());
+}
+
+async fn idle_conversations_are_cleared(store: Arc<dyn ContextStore>) {
+    let now = OffsetDateTime::now_utc();
+    store.push(ChannelId::new(1), message("one")).await.unwrap();
+    let contexts: Contexts = [("2".to_string(), vec![message("two")])]
+        .into_iter()
+        .collect();
+    store.replace_all(&contexts).await.unwrap();
+    store
+        .push(ChannelId::new(3), message("three"))
+        .await
+        .unwrap();
+
+    let hour = time::Duration::hours(1);
+    assert_eq!(store.clear_idle(now - hour).await.unwrap(), 0);
+    assert_eq!(store.all().await.unwrap().len(), 2);
+    assert_eq!(store.clear_idle(now + hour).await.unwrap(), 2);
+    assert!(store.all().await.unwrap().is_empty());
+    assert_eq!(store.clear_idle(now + hour).await.unwrap(), 0);
+}
+
+async fn updates_edit_one_conversation(store: Arc<dyn ContextStore>) {
+    let (a, b) = (ChannelId::new(1), ChannelId::new(2));
+    store.push(a, message("one")).await.unwrap();
+    store.push(a, message("two")).await.unwrap();
+    store.push(b, message("elsewhere")).await.unwrap();
+    let drop_one = |messages: &mut Vec<ChatCompletionRequestMessage>| {
+        let before = messages.len();
+        messages.retain(|m| *m != message("one"));
+        (before - messages.len()) as u64
+    };
+    assert_eq!(store.update(a, &drop_one).await.unwrap(), 1);
+    assert_eq!(store.update(a, &drop_one).await.unwrap(), 0);
+    let all = store.all().await.unwrap();
+    assert_eq!(all["1"], vec![message("two")]);
+    assert_eq!(all["2"], vec![message("elsewhere")]);
+
+    assert_eq!(
+        store
+            .update(a, &|messages| {
+                let dropped = messages.len() as u64;
+                messages.clear();
+                dropped
+            })
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(store.is_empty(a).await.unwrap());
+    assert_eq!(store.all().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn memory_budgets_keep_the_latest_messages_of_recent_conversations() {
+    let budget = MemoryBudget { megabytes: 8 };
+    let (kept, conversations) = (budget.context_messages(), budget.conversations());
+    let store = MemoryContexts::within(budget);
+    for i in 0..kept + 3 {
+        store
+            .push(ChannelId::new(1), message(&i.to_string()))
+            .await
+            .unwrap();
+    }
+    let context = store
+        .push(ChannelId::new(1), message("last"))
+        .await
+        .unwrap();
+    assert_eq!(context.len(), kept);
+    assert_eq!(context.last(), Some(&message("last")));
+    // kept + 4 messages went in
+    assert_eq!(context[0], message("4"));
+
+    // channel 1 is idle longest once the others have spoken
+    for channel in 2..=conversations as u64 + 1 {
+        store
+            .push(ChannelId::new(channel), message("hi"))
+            .await
+            .unwrap();
+    }
+    let all = store.all().await.unwrap();
+    assert_eq!(all.len(), conversations);
+    assert!(!all.contains_key("1"));
+    assert!(all.contains_key(&(conversations + 1).to_string()));
+
+    let contexts: Contexts = [("1".to_string(), vec![message("a"); kept * 2])]
+        .into_iter()
+        .collect();
+    store.replace_all(&contexts).await.unwrap();
+    assert_eq!(store.all().await.unwrap()["1"].len(), kept);
+}
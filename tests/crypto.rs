@@ -0,0 +1,90 @@
+#![cfg(feature = "encryption")]
+
+use deskhelp::{
+    crypto::Cipher,
+    storage::{AnswerRecord, SqliteStorage, Storage},
+};
+use serenity::all::{GuildId, UserId};
+use time::OffsetDateTime;
+
+const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+const OTHER_KEY: &str = "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=";
+
+#[test]
+fn text_comes_back_as_it_was_sealed() {
+    let cipher = Cipher::new(KEY).unwrap();
+    let sealed = cipher.seal("my screen is black");
+    assert!(!sealed.contains("screen"));
+    assert_ne!(
+        sealed,
+        cipher.seal("my screen is black"),
+        "nonces are fresh"
+    );
+    assert_eq!(cipher.open(sealed).unwrap(), "my screen is black");
+}
+
+#[test]
+fn plain_text_is_read_as_it_is() {
+    let cipher = Cipher::new(KEY).unwrap();
+    assert_eq!(
+        cipher.open("stored before".to_string()).unwrap(),
+        "stored before"
+    );
+    assert_eq!(Cipher::default().seal("no key"), "no key");
+}
+
+#[test]
+fn the_wrong_key_or_no_key_cant_read_it() {
+    let sealed = Cipher::new(KEY).unwrap().seal("secret");
+    assert!(Cipher::new(OTHER_KEY)
+        .unwrap()
+        .open(sealed.clone())
+        .is_err());
+    assert!(Cipher::default().open(sealed).is_err());
+}
+
+#[test]
+fn keys_must_be_32_bytes_of_base64() {
+    assert!(Cipher::new("not base64!").is_err());
+    assert!(Cipher::new("c2hvcnQ=").is_err());
+    assert!(Cipher::from_key(None).is_ok());
+}
+
+#[tokio::test]
+async fn stored_questions_need_the_key() {
+    let path = std::env::temp_dir().join(format!("deskhelp-crypto-{}.db", std::process::id()));
+    let url = format!("sqlite://{}", path.display());
+    let cipher = Cipher::new(KEY).unwrap();
+    let record = AnswerRecord {
+        guild_id: GuildId::new(1),
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        ok: true,
+        latency: std::time::Duration::from_millis(1000),
+        prompt_tokens: 100,
+        completion_tokens: 20,
+        message_id: None,
+        experiment: None,
+        variant: None,
+        prompt_version: None,
+        question: Some("my screen is black".to_string()),
+        answer: Some("Try reflashing.".to_string()),
+        user_id: Some(UserId::new(2)),
+        channel_id: None,
+        model: None,
+        kb_version: None,
+    };
+
+    let storage = SqliteStorage::connect(&url)
+        .await
+        .unwrap()
+        .with_cipher(cipher.clone());
+    storage.record_answer(&record).await.unwrap();
+    assert_eq!(storage.dump().await.unwrap().answers, vec![record]);
+    drop(storage);
+
+    let contents = std::fs::read(&path).unwrap();
+    assert!(!contents.windows(6).any(|w| w == b"screen"));
+    let without_key = SqliteStorage::connect(&url).await.unwrap();
+    assert!(without_key.dump().await.is_err());
+    std::fs::remove_file(&path).unwrap();
+}
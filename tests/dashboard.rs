@@ -0,0 +1,121 @@
+#![cfg(feature = "dashboard")]
+
+use std::time::Duration;
+
+use deskhelp::dashboard::{
+    self, DayUsage, GuildSnapshot, PromptSnapshot, QueueSnapshot, RecentQuestion, Snapshot,
+};
+use deskhelp::storage::{AnswerRecord, SqliteStorage, Storage};
+use serenity::all::GuildId;
+use time::{macros::datetime, OffsetDateTime};
+
+fn record(at: OffsetDateTime, tokens: usize) -> AnswerRecord {
+    AnswerRecord {
+        guild_id: GuildId::new(1),
+        at,
+        ok: true,
+        latency: Duration::from_secs(1),
+        prompt_tokens: tokens,
+        completion_tokens: 0,
+        message_id: None,
+        experiment: None,
+        variant: None,
+        prompt_version: None,
+        question: None,
+        answer: None,
+        user_id: None,
+        channel_id: None,
+        model: None,
+        kb_version: None,
+    }
+}
+
+fn snapshot(question: &str) -> Snapshot {
+    Snapshot {
+        queue: QueueSnapshot {
+            limit: 4,
+            active: 1,
+            waiting: 0,
+            avg_seconds: 2.5,
+        },
+        prompt: PromptSnapshot {
+            version: "abc123".to_string(),
+            pinned: true,
+            created_at: "2026-10-01".to_string(),
+            history: vec![],
+        },
+        guilds: vec![GuildSnapshot {
+            id: "1".to_string(),
+            name: Some("Support".to_string()),
+            provider: "default".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            locale: "en-US".to_string(),
+            dry_run: false,
+            monthly_token_cap: None,
+            autorespond_channels: vec![2],
+            support_roles: vec![],
+            escalate: true,
+            keeps_questions: true,
+        }],
+        usage: vec![DayUsage {
+            date: "2026-10-17".to_string(),
+            tokens: 120,
+        }],
+        recent: vec![RecentQuestion {
+            guild: "Support".to_string(),
+            at: "12:00:00.0".to_string(),
+            question: question.to_string(),
+            answer: None,
+            ok: false,
+        }],
+    }
+}
+
+#[tokio::test]
+async fn usage_is_split_into_days() {
+    let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    let now = datetime!(2026-10-17 15:00 UTC);
+    for (at, tokens) in [
+        (datetime!(2026-10-17 09:00 UTC), 30),
+        (datetime!(2026-10-17 01:00 UTC), 20),
+        (datetime!(2026-10-15 23:59 UTC), 5),
+        // before the graph starts
+        (datetime!(2026-10-10 12:00 UTC), 1000),
+    ] {
+        storage.record_answer(&record(at, tokens)).await.unwrap();
+    }
+    let usage = dashboard::usage_by_day(&storage, 3, now).await.unwrap();
+    assert_eq!(
+        usage,
+        [
+            DayUsage {
+                date: "2026-10-15".to_string(),
+                tokens: 5
+            },
+            DayUsage {
+                date: "2026-10-16".to_string(),
+                tokens: 0
+            },
+            DayUsage {
+                date: "2026-10-17".to_string(),
+                tokens: 50
+            },
+        ]
+    );
+}
+
+#[test]
+fn only_the_token_gets_in() {
+    assert!(dashboard::token_matches("s3cret", "s3cret"));
+    assert!(!dashboard::token_matches("s3cret", "s3cre"));
+    assert!(!dashboard::token_matches("s3cret", ""));
+}
+
+#[test]
+fn questions_are_escaped_on_the_page() {
+    let page = dashboard::render(&snapshot("<script>alert(1)</script>"), "a b&c").unwrap();
+    assert!(page.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+    assert!(!page.contains("<script>"));
+    assert!(page.contains("/prompt/unpin?token=a%20b%26c"));
+    assert!(page.contains("gpt-4o-mini"));
+}
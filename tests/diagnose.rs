@@ -0,0 +1,34 @@
+use deskhelp::diagnose;
+use serenity::all::Permissions;
+
+fn lacking(granted: Permissions, in_thread: bool, persona: bool) -> Vec<Permissions> {
+    diagnose::check(granted, in_thread, persona)
+        .into_iter()
+        .filter(|f| !f.granted)
+        .map(|f| f.needed.permission)
+        .collect()
+}
+
+#[test]
+fn reports_exactly_the_permissions_missing() {
+    let everything_but_reactions = Permissions::VIEW_CHANNEL
+        | Permissions::SEND_MESSAGES
+        | Permissions::READ_MESSAGE_HISTORY
+        | Permissions::EMBED_LINKS
+        | Permissions::ATTACH_FILES
+        | Permissions::CREATE_PUBLIC_THREADS;
+    assert_eq!(
+        lacking(everything_but_reactions, false, false),
+        [Permissions::ADD_REACTIONS]
+    );
+    // threads need their own send permission, and personas webhooks
+    assert_eq!(
+        lacking(everything_but_reactions, true, true),
+        [
+            Permissions::SEND_MESSAGES_IN_THREADS,
+            Permissions::ADD_REACTIONS,
+            Permissions::MANAGE_WEBHOOKS
+        ]
+    );
+    assert!(lacking(Permissions::all(), true, true).is_empty());
+}
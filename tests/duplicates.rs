@@ -0,0 +1,42 @@
+use deskhelp::{duplicates, storage::QuestionEmbedding};
+use serenity::all::{ChannelId, GuildId, MessageId};
+use time::OffsetDateTime;
+
+fn earlier(message: u64, embedding: Vec<f32>) -> QuestionEmbedding {
+    QuestionEmbedding {
+        guild_id: GuildId::new(1),
+        channel_id: ChannelId::new(2),
+        message_id: MessageId::new(message),
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        model: "text-embedding-3-small".to_string(),
+        embedding,
+    }
+}
+
+#[test]
+fn similarity_is_the_cosine_of_the_angle() {
+    assert_eq!(duplicates::similarity(&[1.0, 0.0], &[3.0, 0.0]), 1.0);
+    assert_eq!(duplicates::similarity(&[1.0, 0.0], &[0.0, 2.0]), 0.0);
+    assert_eq!(duplicates::similarity(&[1.0, 0.0], &[-1.0, 0.0]), -1.0);
+    // from another model, or nothing at all
+    assert_eq!(duplicates::similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), 0.0);
+    assert_eq!(duplicates::similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+}
+
+#[test]
+fn the_most_similar_question_over_the_threshold_is_linked() {
+    let questions = [
+        earlier(10, vec![1.0, 0.0]),
+        earlier(11, vec![0.9, 0.1]),
+        earlier(12, vec![1.0, 0.0]),
+        earlier(13, vec![0.0, 1.0]),
+    ];
+    let found = duplicates::most_similar(&[1.0, 0.0], &questions, 0.9).unwrap();
+    // the newest of the two identical ones
+    assert_eq!(found.message_id, MessageId::new(12));
+    assert_eq!(
+        duplicates::link(found),
+        "https://discord.com/channels/1/2/12"
+    );
+    assert!(duplicates::most_similar(&[-1.0, 1.0], &questions, 0.9).is_none());
+}
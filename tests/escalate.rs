@@ -0,0 +1,40 @@
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::escalate;
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn assistant(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+        content: Some(ChatCompletionRequestAssistantMessageContent::Text(
+            text.to_string(),
+        )),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn transcripts_keep_the_conversation_and_its_newest_end() {
+    let conversation = vec![
+        ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage::default()),
+        user("My keyboard won't flash"),
+        assistant("Try another cable."),
+    ];
+    assert_eq!(
+        escalate::transcript(&conversation),
+        "Asker: My keyboard won't flash\nBot: Try another cable."
+    );
+
+    let long = vec![user(&"ä".repeat(20_000)), assistant("Still there")];
+    let transcript = escalate::transcript(&long);
+    assert!(transcript.len() <= 20_000);
+    assert!(transcript.ends_with("Bot: Still there"));
+}
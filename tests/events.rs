@@ -0,0 +1,40 @@
+use deskhelp::events::Event;
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::macros::datetime;
+
+#[test]
+fn events_are_tagged_and_published_by_type() {
+    let feedback = Event::Feedback {
+        at: datetime!(2024-05-01 12:00 UTC),
+        guild_id: Some(GuildId::new(1)),
+        channel_id: ChannelId::new(2),
+        message_id: MessageId::new(3),
+        user_id: UserId::new(4),
+        up: true,
+        added: true,
+    };
+    assert_eq!(feedback.subject("deskhelp"), "deskhelp.feedback");
+    let json = serde_json::to_value(&feedback).unwrap();
+    assert_eq!(json["type"], "feedback");
+    assert_eq!(json["at"], "2024-05-01T12:00:00Z");
+    assert_eq!(json["message_id"], "3");
+    assert_eq!(json["up"], true);
+
+    let question = Event::QuestionReceived {
+        at: datetime!(2024-05-01 12:00 UTC),
+        bot: None,
+        guild_id: None,
+        channel_id: ChannelId::new(2),
+        user_id: UserId::new(4),
+        message_id: MessageId::new(5),
+        question: "Does it work on a Pi?".to_string(),
+    };
+    assert_eq!(
+        question.subject("support.bot"),
+        "support.bot.question_received"
+    );
+    let json = serde_json::to_value(&question).unwrap();
+    assert_eq!(json["type"], "question_received");
+    assert_eq!(json["guild_id"], serde_json::Value::Null);
+    assert_eq!(json["question"], "Does it work on a Pi?");
+}
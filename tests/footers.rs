@@ -0,0 +1,65 @@
+use deskhelp::oai;
+
+#[cfg(feature = "templates")]
+#[test]
+fn footers_are_templates_that_can_be_left_off() {
+    let context = oai::FooterContext {
+        elapsed: 1.25,
+        prep: 0.5,
+        model: "gpt-4o",
+        prompt_tokens: 1200,
+        completion_tokens: 300,
+        tokens: 1500,
+        sources: vec!["https://deskthing.app/".to_string()],
+    };
+    let footer = oai::footer(
+        Some("-# {{ model }}, {{ tokens }} tokens in {{ elapsed }}s{% for s in sources %} · <{{ s }}>{% endfor %}"),
+        "en-US",
+        &context,
+    );
+    assert_eq!(
+        footer,
+        "-# gpt-4o, 1500 tokens in 1.25s · <https://deskthing.app/>"
+    );
+    assert_eq!(oai::footer(Some(""), "en-US", &context), "");
+
+    // without a template, or with a broken one, it's the translated footer
+    for template in [None, Some("{{ modle }}")] {
+        let footer = oai::footer(template, "en-US", &context);
+        assert!(footer.starts_with("-# Generated response in 1.250s (0.500s prep)."));
+    }
+}
+
+#[test]
+fn long_footers_are_cut() {
+    let model = "m".repeat(300);
+    let context = oai::FooterContext {
+        elapsed: 1.25,
+        prep: 0.5,
+        model: &model,
+        prompt_tokens: 1200,
+        completion_tokens: 300,
+        tokens: 1500,
+        sources: vec![],
+    };
+    let footer = oai::footer(Some("-# {{ model }}"), "en-US", &context);
+    assert_eq!(footer.chars().count(), oai::MAX_FOOTER);
+    assert!(footer.starts_with("-# mmm"));
+    assert!(footer.ends_with("m…"));
+}
+
+#[cfg(not(feature = "templates"))]
+#[test]
+fn without_templates_footer_variables_are_still_filled_in() {
+    let context = oai::FooterContext {
+        elapsed: 1.25,
+        prep: 0.5,
+        model: "gpt-4o",
+        prompt_tokens: 1200,
+        completion_tokens: 300,
+        tokens: 1500,
+        sources: vec![],
+    };
+    let footer = oai::footer(Some("-# {{ model }} in {{ elapsed }}s"), "en-US", &context);
+    assert_eq!(footer, "-# gpt-4o in 1.25s");
+}
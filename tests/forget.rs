@@ -0,0 +1,79 @@
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
+};
+use deskhelp::{
+    context::{ContextStore, MemoryContexts},
+    forget,
+};
+use serenity::all::{ChannelId, UserId};
+use time::OffsetDateTime;
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestUserMessage::from(text).into()
+}
+
+fn bot(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestAssistantMessage::from(text).into()
+}
+
+fn conversation() -> Vec<ChatCompletionRequestMessage> {
+    vec![
+        ChatCompletionRequestSystemMessage::from("Be helpful.").into(),
+        user("alice (5): my screen is black"),
+        bot("Try reflashing."),
+        user("bob (6): same here"),
+        bot("Hold the back button."),
+        user("alice (5): that worked"),
+        bot("Glad to hear it."),
+        bot("Anything else?"),
+    ]
+}
+
+#[test]
+fn the_users_messages_go_with_the_replies_to_them() {
+    let mut messages = conversation();
+    assert_eq!(forget::forget_messages(&mut messages, UserId::new(5)), 5);
+    assert_eq!(
+        messages,
+        vec![
+            ChatCompletionRequestSystemMessage::from("Be helpful.").into(),
+            user("bob (6): same here"),
+            bot("Hold the back button."),
+        ]
+    );
+    assert_eq!(forget::forget_messages(&mut messages, UserId::new(50)), 0);
+}
+
+#[tokio::test]
+async fn every_conversation_is_cleaned() {
+    let store = MemoryContexts::default();
+    for channel in [1, 2] {
+        for message in conversation() {
+            store.push(ChannelId::new(channel), message).await.unwrap();
+        }
+    }
+    assert_eq!(
+        forget::forget_conversations(&store, UserId::new(6))
+            .await
+            .unwrap(),
+        4
+    );
+    let contexts = store.all().await.unwrap();
+    assert!(contexts.values().all(|messages| messages.len() == 6));
+}
+
+#[tokio::test]
+async fn forgetting_leaves_conversations_idle() {
+    let store = MemoryContexts::default();
+    for message in conversation() {
+        store.push(ChannelId::new(1), message).await.unwrap();
+    }
+    store.push(ChannelId::new(2), bot("Hi.")).await.unwrap();
+    let before = OffsetDateTime::now_utc();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    forget::forget_conversations(&store, UserId::new(5))
+        .await
+        .unwrap();
+    assert_eq!(store.clear_idle(before).await.unwrap(), 2);
+}
@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use deskhelp::forum_tags;
+use serenity::all::ForumTagId;
+
+fn mapping() -> BTreeMap<String, Vec<String>> {
+    BTreeMap::from([
+        ("flashing".to_string(), vec!["Flashing".to_string()]),
+        (
+            "Spotify app".to_string(),
+            vec!["Spotify".to_string(), "Apps".to_string()],
+        ),
+    ])
+}
+
+#[test]
+fn the_reply_must_name_a_category() {
+    let mapping = mapping();
+    assert!(forum_tags::instructions(&mapping).ends_with("Categories: Spotify app, flashing"));
+    assert_eq!(forum_tags::parse("flashing", &mapping), Some("flashing"));
+    assert_eq!(
+        forum_tags::parse(" `spotify app`.\n", &mapping),
+        Some("Spotify app")
+    );
+    assert_eq!(forum_tags::parse("none", &mapping), None);
+    assert_eq!(forum_tags::parse("It's about flashing.", &mapping), None);
+}
+
+#[test]
+fn a_category_can_add_several_tags() {
+    let tag = ForumTagId::new;
+    let available = [
+        (tag(1), "spotify".to_string()),
+        (tag(2), "Apps".to_string()),
+        (tag(3), "Flashing".to_string()),
+    ];
+    assert_eq!(
+        forum_tags::matching(&available, &[tag(2)], &mapping()["Spotify app"]),
+        vec![tag(2), tag(1)]
+    );
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "dashboard")]
+
+use deskhelp::{
+    config::GithubConfig,
+    github::{self, Issue, Notice, Payload},
+};
+
+fn payload(json: &str) -> Payload {
+    serde_json::from_str(json).unwrap()
+}
+
+const LABELED: &str = r#"{
+    "action": "labeled",
+    "label": {"name": "Question"},
+    "repository": {"full_name": "ItsRiprod/DeskThing"},
+    "issue": {
+        "number": 42,
+        "title": "Spotify app won't connect",
+        "html_url": "https://github.com/ItsRiprod/DeskThing/issues/42",
+        "body": "It times out after login."
+    }
+}"#;
+
+#[test]
+fn signatures_are_checked_like_github_documents() {
+    // the example from GitHub's docs on validating webhook deliveries
+    let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+    assert!(github::verify(
+        "It's a Secret to Everybody",
+        b"Hello, World!",
+        signature
+    ));
+    assert!(!github::verify(
+        "another secret",
+        b"Hello, World!",
+        signature
+    ));
+    assert!(!github::verify(
+        "It's a Secret to Everybody",
+        b"Hello, World?",
+        signature
+    ));
+}
+
+#[test]
+fn questions_are_issues_given_the_label() {
+    let config = GithubConfig::default();
+    let issue = Issue {
+        number: 42,
+        title: "Spotify app won't connect".to_string(),
+        html_url: "https://github.com/ItsRiprod/DeskThing/issues/42".to_string(),
+        body: Some("It times out after login.".to_string()),
+    };
+    assert_eq!(
+        github::notice(&config, "issues", payload(LABELED)),
+        Some(Notice::Question {
+            repo: "ItsRiprod/DeskThing".to_string(),
+            issue
+        })
+    );
+
+    let bug = LABELED.replace("Question", "bug");
+    assert_eq!(github::notice(&config, "issues", payload(&bug)), None);
+    let opened = LABELED.replace("labeled", "opened");
+    assert_eq!(github::notice(&config, "issues", payload(&opened)), None);
+
+    let elsewhere = GithubConfig {
+        repos: vec!["ItsRiprod/deskthing-client".to_string()],
+        ..GithubConfig::default()
+    };
+    assert_eq!(github::notice(&elsewhere, "issues", payload(LABELED)), None);
+}
+
+#[test]
+fn published_releases_are_posted() {
+    let published = r#"{
+        "action": "published",
+        "repository": {"full_name": "ItsRiprod/DeskThing"},
+        "release": {
+            "tag_name": "v0.11.0",
+            "name": "v0.11.0",
+            "html_url": "https://github.com/ItsRiprod/DeskThing/releases/tag/v0.11.0",
+            "published_at": "2026-10-01T12:00:00Z",
+            "body": "- Faster startup"
+        }
+    }"#;
+    let config = GithubConfig::default();
+    let Some(Notice::Release { repo, release }) =
+        github::notice(&config, "release", payload(published))
+    else {
+        panic!("expected a release");
+    };
+    assert_eq!(repo, "ItsRiprod/DeskThing");
+    assert_eq!(release.tag_name, "v0.11.0");
+
+    let created = published.replace("published\"", "created\"");
+    assert_eq!(github::notice(&config, "release", payload(&created)), None);
+    assert_eq!(github::notice(&config, "ping", payload("{}")), None);
+}
+
+#[test]
+fn question_posts_link_the_issue_and_fit_in_a_message() {
+    let issue = Issue {
+        number: 42,
+        title: "Spotify app won't connect".to_string(),
+        html_url: "https://github.com/ItsRiprod/DeskThing/issues/42".to_string(),
+        body: None,
+    };
+    let post = github::question(
+        "en-US",
+        "ItsRiprod/DeskThing",
+        &issue,
+        Some("They can't log in."),
+    );
+    assert!(post.contains("#42"));
+    assert!(post.contains("<https://github.com/ItsRiprod/DeskThing/issues/42>"));
+    assert!(post.ends_with("\nThey can't log in."));
+
+    let long = "a".repeat(3000);
+    assert_eq!(
+        github::question("en-US", "ItsRiprod/DeskThing", &issue, Some(&long))
+            .chars()
+            .count(),
+        2000
+    );
+}
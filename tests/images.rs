@@ -0,0 +1,32 @@
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::oai;
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn images_go_with_the_last_question() {
+    let (mut messages, _) = oai::build_prompt(
+        "Be brief.".to_string(),
+        &[user("first"), user("what's this?")],
+        7000,
+        &[],
+    );
+    oai::attach_images(&mut messages, &["https://cdn.example/a.png".to_string()]);
+    let json = serde_json::to_value(&messages).unwrap();
+    assert_eq!(json[1]["content"], "first");
+    assert_eq!(
+        json[2]["content"],
+        serde_json::json!([
+            { "type": "text", "text": "what's this?" },
+            { "type": "image_url", "image_url": { "url": "https://cdn.example/a.png", "detail": "auto" } },
+        ])
+    );
+}
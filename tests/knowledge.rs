@@ -0,0 +1,218 @@
+#![cfg(feature = "search")]
+
+#[allow(dead_code)]
+mod support;
+
+use deskhelp::{
+    config::SearchConfig,
+    knowledge,
+    provider::{Endpoint, Provider},
+    storage::{Document, KbVersion, SqliteStorage, Storage},
+};
+use serenity::all::GuildId;
+use support::{fixture, MockResponse, MockServer};
+use time::OffsetDateTime;
+
+fn document(name: &str, model: &str, embedding: Vec<f32>) -> Document {
+    Document {
+        guild_id: GuildId::new(1),
+        name: name.to_string(),
+        text: format!("All about {}.", name),
+        url: None,
+        model: model.to_string(),
+        embedding,
+        updated_at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+    }
+}
+
+#[test]
+fn the_most_similar_documents_are_shown_first() {
+    let model = "text-embedding-3-small";
+    let ranked = knowledge::rank(
+        &[1.0, 0.0],
+        vec![
+            document("Close", model, vec![1.0, 0.5]),
+            document("Unrelated", model, vec![0.0, 1.0]),
+            document("Same", model, vec![2.0, 0.0]),
+        ],
+        5,
+    );
+    let names: Vec<_> = ranked.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(names, ["Same", "Close"]);
+}
+
+#[test]
+fn excerpts_are_the_paragraph_most_like_the_query() {
+    let text = "DeskThing runs apps on the Car Thing.\n\n\
+                To pair it, hold the preset button\nuntil the screen flashes.\n\n\
+                Flashing needs a USB cable.";
+    assert_eq!(
+        knowledge::excerpt(text, "pair with the preset button", 100),
+        "To pair it, hold the preset button until the screen flashes."
+    );
+    // nothing in common, so it starts at the start
+    assert_eq!(
+        knowledge::excerpt(text, "spotify", 100),
+        "DeskThing runs apps on the Car Thing."
+    );
+    assert_eq!(knowledge::excerpt(text, "usb", 8), "Flashing…");
+}
+
+#[test]
+fn results_link_to_their_source() {
+    let linked = Document {
+        url: Some("https://deskthing.app/docs/pairing".to_string()),
+        ..document("Pairing", "text-embedding-3-small", vec![])
+    };
+    let unlinked = document("Flashing", "text-embedding-3-small", vec![]);
+    assert_eq!(
+        knowledge::describe("en-US", "pairing", &[linked, unlinked]),
+        "**1. Pairing** — <https://deskthing.app/docs/pairing>\n> All about Pairing.\n\
+         **2. Flashing**\n> All about Flashing."
+    );
+}
+
+#[test]
+fn versions_change_with_the_documents_not_their_order() {
+    let model = "text-embedding-3-small";
+    let (a, b) = (
+        document("Pairing", model, vec![1.0]),
+        document("Flashing", model, vec![0.0]),
+    );
+    let version = KbVersion::version_of(&[a.clone(), b.clone()]);
+    assert_eq!(version.len(), 12);
+    assert_eq!(KbVersion::version_of(&[b.clone(), a.clone()]), version);
+
+    // re-embedding isn't a change, editing is
+    let reembedded = document("Pairing", model, vec![0.5]);
+    assert_eq!(KbVersion::version_of(&[reembedded, b.clone()]), version);
+    let edited = Document {
+        text: "Hold the preset button.".to_string(),
+        ..a
+    };
+    assert_ne!(KbVersion::version_of(&[edited, b]), version);
+}
+
+#[tokio::test]
+async fn pushed_documents_are_found_for_questions_like_them() {
+    let server = MockServer::start(vec![MockResponse::Json(fixture("embedding.json"))]).await;
+    let provider = Provider::new(
+        "mock",
+        vec![Endpoint::new(
+            "mock".to_string(),
+            "sk-test",
+            &server.base_url,
+        )],
+        "mock-model".to_string(),
+    )
+    .unwrap();
+    let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    let config = SearchConfig::default();
+    let guild = GuildId::new(1);
+
+    knowledge::push(
+        &storage,
+        &provider,
+        &config,
+        guild,
+        "Pairing",
+        "Hold the preset button.",
+        Some("https://deskthing.app/docs/pairing"),
+    )
+    .await
+    .unwrap();
+    // embedded with a model no longer configured, so it can't be compared
+    storage
+        .save_document(
+            &document("Old", "text-embedding-ada-002", vec![0.25, -0.5, 0.125]),
+            knowledge::KEEP_VERSIONS,
+        )
+        .await
+        .unwrap();
+
+    let (found, version) =
+        knowledge::retrieve(&storage, &provider, &config, guild, "How do I pair it?")
+            .await
+            .unwrap();
+    assert_eq!(
+        version,
+        Some(KbVersion::version_of(
+            &storage.documents(guild).await.unwrap()
+        ))
+    );
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].name, "Pairing");
+    assert_eq!(
+        server.requests()[0]["input"],
+        "Pairing\nHold the preset button."
+    );
+
+    let instructions = knowledge::instructions(&found);
+    assert!(instructions.contains(
+        "# Pairing\nSource: https://deskthing.app/docs/pairing\nHold the preset button."
+    ));
+    assert_eq!(knowledge::instructions(&[]), "");
+
+    // nothing to look through, so the question isn't embedded
+    let other = GuildId::new(2);
+    let requests = server.requests().len();
+    assert_eq!(
+        knowledge::retrieve(&storage, &provider, &config, other, "Hi")
+            .await
+            .unwrap(),
+        (vec![], None)
+    );
+    assert_eq!(server.requests().len(), requests);
+}
+
+#[tokio::test]
+async fn rolling_back_embeds_only_documents_that_changed() {
+    let server = MockServer::start(vec![MockResponse::Json(fixture("embedding.json"))]).await;
+    let provider = Provider::new(
+        "mock",
+        vec![Endpoint::new(
+            "mock".to_string(),
+            "sk-test",
+            &server.base_url,
+        )],
+        "mock-model".to_string(),
+    )
+    .unwrap();
+    let storage = SqliteStorage::connect("sqlite::memory:").await.unwrap();
+    let config = SearchConfig::default();
+    let guild = GuildId::new(1);
+    let push = |name: &'static str, text: &'static str| {
+        knowledge::push(&storage, &provider, &config, guild, name, text, None)
+    };
+
+    push("Pairing", "Hold the preset button.").await.unwrap();
+    push("Flashing", "Use the flashing guide.").await.unwrap();
+    let before = storage.kb_versions(guild, 1).await.unwrap().remove(0);
+    push("Pairing", "Hold it for 5 seconds.").await.unwrap();
+    push("Wifi", "It doesn't need wifi.").await.unwrap();
+    assert_eq!(server.requests().len(), 4);
+
+    let restored = knowledge::rollback(&storage, &provider, &config, guild, &before.version)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(restored.version, before.version);
+    let documents = storage.documents(guild).await.unwrap();
+    assert_eq!(KbVersion::version_of(&documents), before.version);
+    assert!(documents.iter().all(|d| d.embedding == [0.25, -0.5, 0.125]));
+    // only the edited one is embedded again
+    let requests = server.requests();
+    assert_eq!(requests.len(), 5);
+    assert_eq!(requests[4]["input"], "Pairing\nHold the preset button.");
+    assert_eq!(
+        storage.kb_versions(guild, 1).await.unwrap()[0].version,
+        before.version
+    );
+
+    assert_eq!(
+        knowledge::rollback(&storage, &provider, &config, guild, "nope")
+            .await
+            .unwrap(),
+        None
+    );
+}
@@ -0,0 +1,43 @@
+use deskhelp::{config::Config, known_errors};
+
+fn config() -> Config {
+    toml::from_str(
+        r#"
+        [[known_errors]]
+        patterns = ["unable to enter burn mode"]
+        answer = "burn mode fix"
+
+        [[known_errors]]
+        patterns = ["app * not found"]
+        answer = "reinstall the app"
+        "#,
+    )
+    .unwrap()
+}
+
+fn answer(text: &str) -> Option<String> {
+    known_errors::find(&config().known_errors, text).map(|known| known.answer.clone())
+}
+
+#[test]
+fn known_errors_match_ignoring_case_and_spacing() {
+    assert_eq!(
+        answer("the flasher says: Unable to enter\n  BURN mode, help"),
+        Some("burn mode fix".to_string())
+    );
+    assert_eq!(
+        answer("Error: App local not found"),
+        Some("reinstall the app".to_string())
+    );
+    assert_eq!(
+        answer("App `weather` was not found"),
+        Some("reinstall the app".to_string())
+    );
+}
+
+#[test]
+fn other_questions_go_to_the_model() {
+    assert_eq!(answer("not found: app"), None);
+    assert_eq!(answer("how do I enter burn mode?"), None);
+    assert!(known_errors::find(&[], "unable to enter burn mode").is_none());
+}
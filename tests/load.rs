@@ -0,0 +1,473 @@
+//! Bursts of synthetic messages pushed through the steps `on_message` and
+//! `oai::process_message` take: debouncing, superseding, waiting in line in
+//! the request queue and streaming from a mock provider, posted through
+//! serenity's rate limiter to a mock Discord.
+//!
+//! `DESKHELP_LOAD_MESSAGES`, `DESKHELP_LOAD_RATE` (messages a second),
+//! `DESKHELP_LOAD_USERS` and `DESKHELP_LOAD_CONCURRENCY` size the burst; the
+//! defaults keep `cargo test` quick. The mock Discord allows `RATE_LIMIT` calls
+//! per channel and kind of call in `RATE_WINDOW`, far less than Discord's five
+//! in five seconds, so the rate limiter has to hold calls back. Run it with
+//! `--nocapture` to see how long answers waited and how often it did.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{
+    assembler::{Pace, ResponseAssembler},
+    bench::MockBackend,
+    config::Config,
+    debounce::{self, PendingBatches},
+    i18n,
+    models::{self, Capabilities},
+    oai::{self, LiveAnswer, StreamEnd},
+    paste::CodeFolder,
+    queue::{Priority, RequestQueue},
+    responder::Responder,
+    supersede::InFlight,
+    tools::{self, Tools},
+};
+use serenity::{
+    all::{ChannelId, Http, HttpBuilder, Message, MessageId, UserId},
+    http::Ratelimiter,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+
+const ANSWER: &str = "Hold the button while plugging it in, then run the flasher again.";
+const LOCALE: &str = "en";
+const TOKEN: &str = "Bot load-test";
+/// Messages from one user closer together than this are answered together
+const DEBOUNCE: Duration = Duration::from_millis(50);
+/// Calls the mock Discord takes per channel and kind of call in `RATE_WINDOW`
+const RATE_LIMIT: usize = 5;
+const RATE_WINDOW: Duration = Duration::from_millis(250);
+
+fn setting(name: &str, default: usize) -> usize {
+    std::env::var(name).map_or(default, |v| v.parse().unwrap())
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/tests/fixtures/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+/// Calls in one rate limit bucket: a kind of call in one channel, like
+/// serenity keys them
+#[derive(Default)]
+struct Bucket {
+    window_start: Option<Instant>,
+    served: usize,
+}
+
+/// What the mock Discord was asked to do
+#[derive(Default)]
+struct Calls {
+    posts: usize,
+    edits: usize,
+    /// Calls turned away with a 429 for going over the limit
+    rejected: usize,
+    /// The last content each message was given
+    contents: HashMap<u64, String>,
+    buckets: HashMap<(String, u64), Bucket>,
+}
+
+/// Serves `POST` and `PATCH` on channel messages like Discord would, rate
+/// limits included. It's reached as an HTTPS proxy for discord.com, with a
+/// certificate for it from a test authority.
+async fn mock_discord() -> (serenity_reqwest::Client, Arc<Mutex<Calls>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = format!("http://{}", listener.local_addr().unwrap());
+    let certs = rustls_pemfile::certs(&mut &fixture("discord.pem")[..])
+        .unwrap()
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut &fixture("discord.key")[..])
+        .unwrap()
+        .remove(0);
+    let tls = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, PrivateKey(key))
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(tls));
+
+    let calls = Arc::new(Mutex::new(Calls::default()));
+    let recorded = calls.clone();
+    tokio::spawn(async move {
+        let next_id = Arc::new(AtomicUsize::new(1000));
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let (calls, next_id, acceptor) = (recorded.clone(), next_id.clone(), acceptor.clone());
+            tokio::spawn(async move {
+                // `CONNECT discord.com:443`, then TLS through the tunnel
+                if read_request(&mut socket).await.is_none() {
+                    return;
+                }
+                let established = b"HTTP/1.1 200 Connection established\r\n\r\n";
+                if socket.write_all(established).await.is_err() {
+                    return;
+                }
+                if let Ok(stream) = acceptor.accept(socket).await {
+                    serve(stream, &calls, &next_id).await;
+                }
+            });
+        }
+    });
+
+    let client = serenity_reqwest::Client::builder()
+        .use_rustls_tls()
+        .add_root_certificate(
+            serenity_reqwest::Certificate::from_pem(&fixture("discord_ca.pem")).unwrap(),
+        )
+        .proxy(serenity_reqwest::Proxy::https(proxy).unwrap())
+        .build()
+        .unwrap();
+    (client, calls)
+}
+
+/// The head and body of the next request on `socket`
+async fn read_request(socket: &mut (impl AsyncRead + Unpin)) -> Option<(String, Vec<u8>)> {
+    let mut buf = vec![];
+    let header_end = loop {
+        let mut chunk = [0; 4096];
+        let Ok(n @ 1..) = socket.read(&mut chunk).await else {
+            return None;
+        };
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let content_length = head
+        .to_lowercase()
+        .lines()
+        .find_map(|l| {
+            l.strip_prefix("content-length:")
+                .map(|v| v.trim().to_string())
+        })
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let mut chunk = [0; 4096];
+        let Ok(n @ 1..) = socket.read(&mut chunk).await else {
+            break;
+        };
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Some((head, buf.split_off(header_end)))
+}
+
+async fn serve(
+    mut socket: impl AsyncRead + AsyncWrite + Unpin,
+    calls: &Mutex<Calls>,
+    next_id: &AtomicUsize,
+) {
+    let Some((head, body)) = read_request(&mut socket).await else {
+        return;
+    };
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    // /api/v10/channels/<channel>/messages[/<message>]
+    let path: Vec<&str> = request_line.next().unwrap_or_default().split('/').collect();
+    let channel: u64 = path.get(4).and_then(|c| c.parse().ok()).unwrap_or(1);
+    let existing = match (method.as_str(), path.get(6)) {
+        ("POST", None) => None,
+        ("PATCH", Some(id)) => Some(id.parse::<u64>().unwrap()),
+        _ => panic!("unexpected Discord call {}", head.lines().next().unwrap()),
+    };
+
+    let now = Instant::now();
+    let (id, limit_headers, status) = {
+        let mut calls = calls.lock().unwrap();
+        let bucket = calls.buckets.entry((method.clone(), channel)).or_default();
+        let start = match bucket.window_start {
+            Some(start) if now - start < RATE_WINDOW => start,
+            _ => {
+                bucket.window_start = Some(now);
+                bucket.served = 0;
+                now
+            }
+        };
+        let reset_after = (RATE_WINDOW - (now - start)).as_secs_f64();
+        let over = bucket.served >= RATE_LIMIT;
+        if !over {
+            bucket.served += 1;
+        }
+        let limit_headers = format!(
+            "x-ratelimit-limit: {}\r\nx-ratelimit-remaining: {}\r\nx-ratelimit-reset-after: {:.3}\r\n",
+            RATE_LIMIT,
+            RATE_LIMIT - bucket.served,
+            reset_after
+        );
+        if over {
+            calls.rejected += 1;
+            let retry = format!("retry-after: {:.3}\r\n", reset_after);
+            (0, limit_headers + retry.as_str(), "429 Too Many Requests")
+        } else {
+            let id = existing.unwrap_or_else(|| next_id.fetch_add(1, Ordering::Relaxed) as u64);
+            let content = body["content"].as_str().unwrap_or_default().to_string();
+            if method == "POST" {
+                calls.posts += 1;
+            } else {
+                calls.edits += 1;
+            }
+            calls.contents.insert(id, content);
+            (id, limit_headers, "200 OK")
+        }
+    };
+
+    let json = if id == 0 {
+        r#"{"message": "You are being rate limited.", "retry_after": 0.1, "global": false}"#
+            .to_string()
+    } else {
+        let mut message = Message::default();
+        message.id = MessageId::new(id);
+        message.channel_id = ChannelId::new(channel);
+        message.content = calls.lock().unwrap().contents[&id].clone();
+        serde_json::to_string(&message).unwrap()
+    };
+    let reply = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{}connection: close\r\n\r\n{}",
+        status,
+        json.len(),
+        limit_headers,
+        json
+    );
+    let _ = socket.write_all(reply.as_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+/// What the bot shares between messages
+struct Bot {
+    http: Http,
+    backend: MockBackend,
+    config: Config,
+    pending: PendingBatches,
+    in_flight: Arc<InFlight>,
+    queue: Arc<RequestQueue>,
+    active: AtomicUsize,
+    most_active: AtomicUsize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// Answered along with a later message from the same user
+    Merged,
+    /// Answered in the message with this id, covering this many questions
+    Answered(MessageId, usize),
+    /// Given up on for a newer question from the same user
+    Superseded(usize),
+}
+
+/// One message's trip through the pipeline, as `on_message` and
+/// `oai::process_message` take it
+async fn handle(bot: &Bot, msg: Message) -> serenity::Result<(Outcome, Duration)> {
+    let Some(batch) = debounce::collect(&bot.pending, msg, DEBOUNCE).await else {
+        return Ok((Outcome::Merged, Duration::ZERO));
+    };
+    let msg = batch.last().unwrap();
+    let generation = bot.in_flight.start(msg);
+    let responder = Responder::Reply(Box::new(msg.clone()));
+    let mut sent = responder
+        .send(&bot.http, &i18n::tr(LOCALE, "generating", &[]))
+        .await?;
+
+    let queued_at = Instant::now();
+    let ticket = bot.queue.join(Priority::Normal);
+    let Some(_permit) = oai::wait_in_line(
+        &bot.http,
+        &responder,
+        &mut sent,
+        ticket,
+        &generation,
+        LOCALE,
+    )
+    .await
+    else {
+        return Ok((Outcome::Superseded(batch.len()), queued_at.elapsed()));
+    };
+    let waited = queued_at.elapsed();
+    let active = bot.active.fetch_add(1, Ordering::SeqCst) + 1;
+    bot.most_active.fetch_max(active, Ordering::SeqCst);
+
+    let question = batch
+        .iter()
+        .map(|m| m.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let history = [ChatCompletionRequestMessage::User(
+        ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Text(question),
+            ..Default::default()
+        },
+    )];
+    let capabilities = Capabilities::of(&bot.config, "mock");
+    let (messages, _) = oai::build_prompt(
+        oai::SYSTEM_MESSAGE.to_string(),
+        &history,
+        oai::token_limit(),
+        &[],
+    );
+    let request = oai::chat_request(
+        models::resolve(&bot.config, "mock"),
+        &capabilities,
+        messages,
+    );
+    let tools = Tools::default();
+    let mut stream = tools::create_stream(&bot.backend, request, &tools)
+        .await
+        .unwrap();
+    let mut live = LiveAnswer {
+        folder: CodeFolder::new(None, LOCALE),
+        assembler: ResponseAssembler::new(|text: &str| text.to_string(), Pace::Adaptive),
+        partial: true,
+    };
+    let streamed = oai::stream_answer(
+        &bot.http,
+        &responder,
+        &mut sent,
+        &mut stream,
+        &generation,
+        &mut live,
+    )
+    .await;
+    let outcome = match streamed.end {
+        StreamEnd::Finished => {
+            let actions = live.assembler.finish("");
+            oai::apply(&bot.http, &responder, &mut sent, actions).await;
+            Outcome::Answered(sent.id, batch.len())
+        }
+        StreamEnd::Superseded => Outcome::Superseded(batch.len()),
+        end => panic!("the mock provider's stream ended with {:?}", end),
+    };
+    bot.active.fetch_sub(1, Ordering::SeqCst);
+    Ok((outcome, waited))
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn bursts_of_messages_are_all_answered_within_the_queue_limit() {
+    let messages = setting("DESKHELP_LOAD_MESSAGES", 40);
+    let rate = setting("DESKHELP_LOAD_RATE", 200);
+    let users = setting("DESKHELP_LOAD_USERS", 12) as u64;
+    let concurrency = setting("DESKHELP_LOAD_CONCURRENCY", 3);
+
+    let (client, calls) = mock_discord().await;
+    let holds = Arc::new(AtomicUsize::new(0));
+    let mut ratelimiter = Ratelimiter::new(client.clone(), TOKEN);
+    let held = holds.clone();
+    ratelimiter.set_ratelimit_callback(Box::new(move |_| {
+        held.fetch_add(1, Ordering::SeqCst);
+    }));
+    let bot = Arc::new(Bot {
+        http: HttpBuilder::new(TOKEN)
+            .client(client)
+            .ratelimiter(ratelimiter)
+            .build(),
+        backend: MockBackend {
+            answer: ANSWER.to_string(),
+        },
+        config: Config::default(),
+        pending: PendingBatches::default(),
+        in_flight: Arc::new(InFlight::default()),
+        queue: RequestQueue::new(concurrency),
+        active: AtomicUsize::new(0),
+        most_active: AtomicUsize::new(0),
+    });
+
+    // a fixed sequence, so a failure can be run again
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let start = Instant::now();
+    let mut tasks = vec![];
+    for i in 0..messages {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let mut msg = Message::default();
+        msg.id = MessageId::new(i as u64 + 1);
+        msg.channel_id = ChannelId::new(seed % 3 + 1);
+        msg.author.id = UserId::new(seed % users + 1);
+        msg.content = format!("question {}", i);
+        let bot = bot.clone();
+        tasks.push(tokio::spawn(async move { handle(&bot, msg).await }));
+        tokio::time::sleep(Duration::from_secs_f64(1.0 / rate as f64)).await;
+    }
+    let mut outcomes = vec![];
+    for task in tasks {
+        outcomes.push(task.await.unwrap().expect("Discord call failed"));
+    }
+    let elapsed = start.elapsed();
+
+    assert!(bot.most_active.load(Ordering::SeqCst) <= concurrency);
+    assert_eq!(bot.active.load(Ordering::SeqCst), 0);
+    let batches = outcomes
+        .iter()
+        .filter(|(o, _)| *o != Outcome::Merged)
+        .count();
+    let questions: usize = outcomes
+        .iter()
+        .map(|(o, _)| match o {
+            Outcome::Answered(_, n) | Outcome::Superseded(n) => *n,
+            Outcome::Merged => 0,
+        })
+        .sum();
+    let merged = outcomes.len() - batches;
+    // every message was answered, alone or with others, or given up on for a newer one
+    assert_eq!(questions, messages);
+
+    let calls = calls.lock().unwrap();
+    // one reply per batch, each answer in full
+    assert_eq!(calls.posts, batches);
+    for (outcome, _) in &outcomes {
+        if let Outcome::Answered(id, _) = outcome {
+            assert_eq!(calls.contents[&id.get()], ANSWER);
+        }
+    }
+    // the burst went over the mock's limits, so serenity had to hold calls back
+    let holds = holds.load(Ordering::SeqCst);
+    assert!(holds > 0, "serenity's rate limiter never held a call back");
+
+    let mut waits: Vec<Duration> = outcomes
+        .iter()
+        .filter(|(o, _)| matches!(o, Outcome::Answered(..)))
+        .map(|(_, waited)| *waited)
+        .collect();
+    waits.sort();
+    println!(
+        "{} messages in {:.2?}: {} answers, {} merged, {} superseded; \
+         {} posts, {} edits, {} held back by the rate limiter, {} turned away; \
+         longest wait in line {:.2?}",
+        messages,
+        elapsed,
+        waits.len(),
+        merged,
+        batches - waits.len(),
+        calls.posts,
+        calls.edits,
+        holds,
+        calls.rejected,
+        waits.last().copied().unwrap_or_default(),
+    );
+}
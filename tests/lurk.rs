@@ -0,0 +1,72 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent};
+use deskhelp::{config::LurkConfig, lurk::Lurked};
+use serenity::all::{ChannelId, Message, UserId};
+
+fn said(channel: u64, user: u64, content: &str) -> Message {
+    let mut msg = Message::default();
+    msg.channel_id = ChannelId::new(channel);
+    msg.author.id = UserId::new(user);
+    msg.author.name = format!("user{}", user);
+    msg.content = content.to_string();
+    msg
+}
+
+fn text(message: ChatCompletionRequestMessage) -> String {
+    match message {
+        ChatCompletionRequestMessage::User(user) => match user.content {
+            ChatCompletionRequestUserMessageContent::Text(text) => text,
+            _ => panic!("not text"),
+        },
+        _ => panic!("not a user message"),
+    }
+}
+
+#[test]
+fn the_conversation_is_kept_until_the_bot_answers() {
+    let config = LurkConfig {
+        channels: vec![1],
+        max_tokens: 1000,
+    };
+    let lurked = Lurked::default();
+    lurked.record(&config, &said(1, 2, "my screen is black"));
+    lurked.record(&config, &said(1, 3, "did you flash it?"));
+    lurked.record(&config, &said(9, 3, "not a lurk channel"));
+
+    let overheard = text(lurked.take(ChannelId::new(1)).unwrap());
+    assert!(overheard.ends_with("\nuser2 (2): my screen is black\nuser3 (3): did you flash it?"));
+    assert!(lurked.take(ChannelId::new(1)).is_none());
+    assert!(lurked.take(ChannelId::new(9)).is_none());
+}
+
+#[test]
+fn older_messages_give_way_to_the_budget() {
+    let config = LurkConfig {
+        channels: vec![1],
+        max_tokens: 20,
+    };
+    let lurked = Lurked::default();
+    for i in 0..10 {
+        lurked.record(&config, &said(1, 2, &format!("message number {}", i)));
+    }
+    let overheard = text(lurked.take(ChannelId::new(1)).unwrap());
+    assert!(overheard.contains("message number 9"));
+    assert!(!overheard.contains("message number 0"));
+}
+
+#[test]
+fn forgotten_users_are_left_out() {
+    let config = LurkConfig {
+        channels: vec![1, 2],
+        max_tokens: 1000,
+    };
+    let lurked = Lurked::default();
+    lurked.record(&config, &said(1, 2, "my screen is black"));
+    lurked.record(&config, &said(1, 3, "did you flash it?"));
+    lurked.record(&config, &said(2, 2, "still black"));
+
+    assert_eq!(lurked.forget(UserId::new(2)), 2);
+    assert!(
+        text(lurked.take(ChannelId::new(1)).unwrap()).ends_with("\nuser3 (3): did you flash it?")
+    );
+    assert!(lurked.take(ChannelId::new(2)).is_none());
+}
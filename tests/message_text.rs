@@ -0,0 +1,60 @@
+use deskhelp::message_text;
+use serenity::all::Message;
+
+fn message(content: &str, embeds: serde_json::Value, stickers: serde_json::Value) -> Message {
+    let mut msg = Message::default();
+    msg.content = content.to_string();
+    msg.embeds = serde_json::from_value(embeds).unwrap();
+    msg.sticker_items = serde_json::from_value(stickers).unwrap();
+    msg
+}
+
+#[test]
+fn custom_emoji_are_written_by_name() {
+    assert_eq!(
+        message_text::readable("works now <:thumbsup_cat:1234567890> <a:party:42>!"),
+        "works now :thumbsup_cat: :party:!"
+    );
+    // mentions, channels and stray brackets stay as they are
+    assert_eq!(
+        message_text::readable("<@123> see <#456>, a < b and <:not emoji>"),
+        "<@123> see <#456>, a < b and <:not emoji>"
+    );
+}
+
+#[test]
+fn gifs_and_stickers_are_described() {
+    let url = "https://tenor.com/view/happy-dance-gif-12345";
+    let msg = message(
+        &format!("it flashed! {}", url),
+        serde_json::json!([{ "type": "gifv", "url": url }]),
+        serde_json::json!([{ "id": "1", "name": "Wave", "format_type": 1 }]),
+    );
+    assert_eq!(
+        message_text::of(&msg),
+        "it flashed! [GIF: happy dance]\n[sticker: Wave]"
+    );
+
+    let msg = message(
+        "",
+        serde_json::json!([{ "type": "gifv", "url": "https://example.com/cat.gif" }]),
+        serde_json::json!([]),
+    );
+    assert_eq!(message_text::of(&msg), "[GIF]");
+}
+
+#[test]
+fn only_images_are_read() {
+    let mut msg = Message::default();
+    msg.attachments = serde_json::from_value(serde_json::json!([
+        { "id": "1", "filename": "error.png", "size": 1, "url": "https://cdn/1", "proxy_url": "https://cdn/1", "content_type": "image/png" },
+        { "id": "2", "filename": "log.txt", "size": 1, "url": "https://cdn/2", "proxy_url": "https://cdn/2", "content_type": "text/plain" },
+    ]))
+    .unwrap();
+    let batch = [msg];
+    let images: Vec<_> = message_text::images(&batch)
+        .iter()
+        .map(|a| a.filename.as_str())
+        .collect();
+    assert_eq!(images, ["error.png"]);
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "setup")]
+
+use std::collections::BTreeMap;
+
+use deskhelp::{
+    config::Config,
+    migrate::{self, EnvSettings},
+};
+use serenity::all::GuildId;
+
+#[test]
+fn environment_settings_move_into_each_guild() {
+    let settings = EnvSettings {
+        autorespond_channels: vec![10, 11, 20],
+        model: Some("llama-3.2-11b-vision-preview".to_string()),
+        token_limit: Some(6000),
+    };
+    let guilds = BTreeMap::from([(GuildId::new(1), vec![10, 11]), (GuildId::new(2), vec![20])]);
+    let contents = r#"# answers quickly
+[guilds."2"]
+model = "gpt-4o"
+autorespond_channels = [21]
+"#;
+    let migrated = migrate::apply(contents, &settings, &guilds).unwrap();
+    assert!(migrated.starts_with("# answers quickly"));
+
+    let config: Config = toml::from_str(&migrated).unwrap();
+    let first = config.guild(Some(GuildId::new(1))).unwrap();
+    assert_eq!(first.autorespond_channels, [10, 11]);
+    assert_eq!(first.model.as_deref(), Some("llama-3.2-11b-vision-preview"));
+    // what the config already says is kept
+    let second = config.guild(Some(GuildId::new(2))).unwrap();
+    assert_eq!(second.autorespond_channels, [21, 20]);
+    assert_eq!(second.model.as_deref(), Some("gpt-4o"));
+    assert_eq!(
+        config.models["llama-3.2-11b-vision-preview"].context_tokens,
+        Some(6000)
+    );
+}
+
+#[test]
+fn migrating_twice_changes_nothing() {
+    let settings = EnvSettings {
+        autorespond_channels: vec![10],
+        model: None,
+        token_limit: Some(6000),
+    };
+    let guilds = BTreeMap::from([(GuildId::new(1), vec![10])]);
+    let once = migrate::apply("", &settings, &guilds).unwrap();
+    assert_eq!(migrate::apply(&once, &settings, &guilds).unwrap(), once);
+    assert!(!once.contains("models"));
+}
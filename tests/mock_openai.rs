@@ -0,0 +1,371 @@
+mod support;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{
+    assembler::{Action, Pace, ResponseAssembler},
+    config::Config,
+    models::Capabilities,
+    oai,
+    provider::{ChatBackend, Cooldowns, Endpoint, Provider, Providers},
+    snippets::SnippetTool,
+    storage::{Snippet, SqliteStorage, Storage},
+    tools::{self, Tools},
+};
+use futures::TryStreamExt;
+use serenity::all::GuildId;
+use support::{fixture, MockResponse, MockServer};
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn provider(servers: &[&MockServer]) -> Provider {
+    let endpoints = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Endpoint::new(format!("mock#{}", i), "sk-test", &s.base_url))
+        .collect();
+    Provider::new("mock", endpoints, "mock-model".to_string()).unwrap()
+}
+
+async fn answer(provider: &Provider) -> Result<String, async_openai::error::OpenAIError> {
+    answer_with(provider, &Capabilities::default()).await
+}
+
+async fn answer_with(
+    provider: &Provider,
+    capabilities: &Capabilities,
+) -> Result<String, async_openai::error::OpenAIError> {
+    let (messages, _) = oai::build_prompt("Be brief.".to_string(), &[user("hi")], 7000, &[]);
+    let mut stream = provider
+        .create_stream(oai::chat_request(&provider.model, capabilities, messages))
+        .await?;
+    let mut text = String::new();
+    while let Some(chunk) = stream.try_next().await? {
+        if let Some(content) = &chunk.choices[0].delta.content {
+            text.push_str(content);
+        }
+        if chunk.choices[0].finish_reason.is_some() {
+            break;
+        }
+    }
+    Ok(text)
+}
+
+#[tokio::test]
+async fn streams_an_answer() {
+    let server = MockServer::start(vec![MockResponse::Sse(fixture("hello.sse"))]).await;
+    let text = answer(&provider(&[&server])).await.unwrap();
+    assert_eq!(text, "Hello, world!");
+}
+
+#[tokio::test]
+async fn sends_a_streaming_request() {
+    let server = MockServer::start(vec![MockResponse::Sse(fixture("hello.sse"))]).await;
+    answer(&provider(&[&server])).await.unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request["model"], "mock-model");
+    assert_eq!(request["stream"], true);
+    assert_eq!(request["max_tokens"], 2800);
+    assert_eq!(request["messages"][0]["role"], "system");
+    assert_eq!(request["messages"][1]["content"], "hi");
+}
+
+#[tokio::test]
+async fn reasoning_models_get_the_answer_in_one_piece() {
+    let server = MockServer::start(vec![MockResponse::Json(fixture("hello.json"))]).await;
+    let capabilities = Capabilities {
+        reasoning: true,
+        streaming: false,
+        system_message: false,
+        reasoning_effort: Some(async_openai::types::ReasoningEffort::Low),
+        tools: false,
+        vision: false,
+        context_tokens: None,
+        stream_usage: true,
+    };
+    let text = answer_with(&provider(&[&server]), &capabilities)
+        .await
+        .unwrap();
+    assert_eq!(text, "Hello, world!");
+
+    let request = &server.requests()[0];
+    assert_eq!(request["stream"], false);
+    assert_eq!(request["max_completion_tokens"], 25_000);
+    assert!(request.get("max_tokens").is_none());
+    assert_eq!(request["reasoning_effort"], "low");
+    // the system prompt goes first, as a user message
+    assert_eq!(request["messages"][0]["role"], "user");
+    assert_eq!(request["messages"][0]["content"], "Be brief.");
+}
+
+#[test]
+fn misconfigured_providers_are_left_out_and_named() {
+    let config: Config = toml::from_str(
+        r#"
+        [providers.keyless]
+        model = "gpt-4o"
+
+        [providers.nowhere]
+        endpoints = [{ api_key = "sk-test" }]
+
+        [providers.working]
+        api_key = "sk-test"
+        base_url = "http://127.0.0.1:1/v1"
+        "#,
+    )
+    .unwrap();
+    let default = Endpoint::new("default".to_string(), "sk-test", "http://127.0.0.1:1/v1");
+    let default = Provider::new("default", vec![default], "mock-model".to_string()).unwrap();
+    let providers = Providers::new(default, &config.providers);
+
+    assert!(providers.get("working").is_some());
+    assert!(providers.get("keyless").is_none());
+    let mut misconfigured: Vec<_> = providers.misconfigured().collect();
+    misconfigured.sort();
+    assert_eq!(
+        misconfigured,
+        [
+            ("keyless", "provider keyless has no endpoints"),
+            ("nowhere", "endpoint 0 of provider nowhere has no base_url"),
+        ]
+    );
+    assert!(Provider::new("empty", vec![], "mock-model".to_string()).is_err());
+}
+
+#[tokio::test]
+async fn provider_params_are_added_to_the_request() {
+    let server = MockServer::start(vec![MockResponse::Sse(fixture("hello.sse"))]).await;
+    let config: Config = toml::from_str(
+        r#"
+        [providers.default.params]
+        top_k = 40
+        stop = ["</answer>"]
+        max_tokens = 1000
+        "#,
+    )
+    .unwrap();
+    let providers = Providers::new(provider(&[&server]), &config.providers);
+    answer(providers.get("default").unwrap()).await.unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request["top_k"], 40);
+    assert_eq!(request["stop"][0], "</answer>");
+    // over the fields we set ourselves
+    assert_eq!(request["max_tokens"], 1000);
+    assert_eq!(request["stream"], true);
+}
+
+#[tokio::test]
+async fn fails_over_to_a_working_endpoint() {
+    let broken = MockServer::start(vec![MockResponse::Error(500)]).await;
+    let working = MockServer::start(vec![MockResponse::Sse(fixture("hello.sse"))]).await;
+    let provider = provider(&[&broken, &working]);
+
+    assert_eq!(answer(&provider).await.unwrap(), "Hello, world!");
+    // the broken endpoint is cooling down, so it isn't tried again
+    assert_eq!(answer(&provider).await.unwrap(), "Hello, world!");
+    assert_eq!(broken.requests().len(), 1);
+    assert_eq!(working.requests().len(), 2);
+}
+
+/// Remembers every cooldown instead of keeping to it
+#[derive(Default)]
+struct RecordedCooldowns(Mutex<Vec<(String, Duration)>>);
+
+#[serenity::async_trait]
+impl Cooldowns for RecordedCooldowns {
+    async fn cooling_down(&self, _endpoint: &str) -> bool {
+        false
+    }
+
+    async fn cool_down(&self, endpoint: &str, duration: Duration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((endpoint.to_string(), duration));
+    }
+}
+
+#[tokio::test]
+async fn reports_the_error_when_every_endpoint_fails() {
+    let rate_limited = MockServer::start(vec![MockResponse::Error(429)]).await;
+    let broken = MockServer::start(vec![MockResponse::Error(500)]).await;
+    let mut providers = Providers::new(provider(&[&rate_limited, &broken]), &Default::default());
+    let cooldowns = Arc::new(RecordedCooldowns::default());
+    providers.share_cooldowns(cooldowns.clone());
+    assert!(answer(providers.get("default").unwrap()).await.is_err());
+
+    // rate limits are waited out for longer than other errors
+    let mut cooled = cooldowns.0.lock().unwrap().clone();
+    cooled.sort();
+    assert_eq!(
+        cooled,
+        [
+            ("mock#0".to_string(), Duration::from_secs(60)),
+            ("mock#1".to_string(), Duration::from_secs(30)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn tool_calls_are_answered_before_the_answer_streams() {
+    let server = MockServer::start(vec![
+        MockResponse::Sse(fixture("tool_call.sse")),
+        MockResponse::Sse(fixture("hello.sse")),
+    ])
+    .await;
+    let provider = provider(&[&server]);
+    let storage: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::connect("sqlite::memory:").await.unwrap());
+    let guild_id = GuildId::new(1);
+    storage
+        .save_snippet(&Snippet {
+            guild_id,
+            name: "reset".to_string(),
+            content: "Hold the back button.".to_string(),
+            updated_at: time::OffsetDateTime::now_utc(),
+        })
+        .await
+        .unwrap();
+    let mut tools = Tools::default();
+    tools.add(
+        SnippetTool::for_guild(storage, guild_id)
+            .await
+            .unwrap()
+            .unwrap(),
+    );
+
+    let (messages, _) = oai::build_prompt("Be brief.".to_string(), &[user("hi")], 7000, &[]);
+    let request = oai::chat_request(&provider.model, &Capabilities::default(), messages);
+    let mut stream = tools::create_stream(&provider, request, &tools)
+        .await
+        .unwrap();
+    let mut text = String::new();
+    let mut finishes = 0;
+    while let Some(chunk) = stream.try_next().await.unwrap() {
+        if let Some(content) = &chunk.choices[0].delta.content {
+            text.push_str(content);
+        }
+        if chunk.choices[0].finish_reason.is_some() {
+            finishes += 1;
+        }
+    }
+    assert_eq!(text, "Hello, world!");
+    assert_eq!(finishes, 1);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0]["tools"][0]["function"]["name"], "get_snippet");
+    let messages = requests[1]["messages"].as_array().unwrap();
+    let call = &messages[messages.len() - 2];
+    assert_eq!(call["role"], "assistant");
+    assert_eq!(
+        call["tool_calls"][0]["function"]["arguments"],
+        r#"{"name":"reset"}"#
+    );
+    let result = &messages[messages.len() - 1];
+    assert_eq!(result["role"], "tool");
+    assert_eq!(result["tool_call_id"], "call_1");
+    assert_eq!(result["content"], "Hold the back button.");
+}
+
+#[tokio::test]
+async fn background_jobs_get_the_whole_answer() {
+    let server = MockServer::start(vec![MockResponse::Sse(fixture("hello.sse"))]).await;
+    let provider = provider(&[&server]);
+    let text = oai::complete(
+        &provider,
+        &Config::default(),
+        "mock-model",
+        "Summarize.",
+        "A changelog",
+    )
+    .await
+    .unwrap();
+    assert_eq!(text, "Hello, world!");
+    let request = &server.requests()[0];
+    assert_eq!(request["messages"][0]["content"], "Summarize.");
+    assert_eq!(request["messages"][1]["content"], "A changelog");
+}
+
+#[tokio::test]
+async fn questions_are_embedded_with_the_configured_model() {
+    let server = MockServer::start(vec![
+        MockResponse::Error(500),
+        MockResponse::Json(fixture("embedding.json")),
+    ])
+    .await;
+    let provider = provider(&[&server, &server]);
+    let embedding = provider
+        .embed("text-embedding-3-small", "How do I flash my Car Thing?")
+        .await
+        .unwrap();
+    assert_eq!(embedding, vec![0.25, -0.5, 0.125]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1]["model"], "text-embedding-3-small");
+    assert_eq!(requests[1]["input"], "How do I flash my Car Thing?");
+}
+
+#[tokio::test]
+async fn long_streamed_answers_are_split_into_whole_messages() {
+    let server = MockServer::start(vec![MockResponse::Sse(fixture("code_block.sse"))]).await;
+    let provider = provider(&[&server]);
+    let (messages, _) = oai::build_prompt("Be brief.".to_string(), &[user("hi")], 7000, &[]);
+    let mut stream = provider
+        .create_stream(oai::chat_request(
+            &provider.model,
+            &Capabilities::default(),
+            messages,
+        ))
+        .await
+        .unwrap();
+
+    let mut assembler =
+        ResponseAssembler::new(str::to_string, Pace::Fixed(std::time::Duration::ZERO))
+            .with_limit(70);
+    let mut posted = vec![String::new()];
+    let mut apply = |actions: Vec<Action>| {
+        for action in actions {
+            match action {
+                Action::Edit(text) | Action::Finalize(text) => *posted.last_mut().unwrap() = text,
+                Action::NewMessage(text) => posted.push(text),
+            }
+        }
+    };
+    while let Some(chunk) = stream.try_next().await.unwrap() {
+        if let Some(content) = &chunk.choices[0].delta.content {
+            apply(assembler.push(content, std::time::Instant::now()));
+        }
+        if chunk.choices[0].finish_reason.is_some() {
+            apply(assembler.finish("-# done"));
+            break;
+        }
+    }
+
+    assert!(posted.len() > 1);
+    for message in &posted {
+        assert!(message.len() <= 70, "{:?} is too long", message);
+        assert_eq!(message.matches("```").count() % 2, 0, "{:?}", message);
+    }
+    assert!(posted.last().unwrap().ends_with("-# done"));
+    assert!(posted
+        .iter()
+        .any(|m| m.contains("curl -fsSL https://terbium.app/install-rules")));
+}
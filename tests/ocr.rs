@@ -0,0 +1,26 @@
+#![cfg(feature = "ocr")]
+
+use deskhelp::ocr;
+
+#[test]
+fn transcripts_are_labelled_and_empty_ones_dropped() {
+    assert_eq!(
+        ocr::transcript("error.png", "  Error 0x80070005\nAccess denied\n"),
+        "\n\n[Text in error.png]\nError 0x80070005\nAccess denied"
+    );
+    assert_eq!(ocr::transcript("cat.jpg", "NO TEXT."), "");
+    assert_eq!(ocr::transcript("cat.jpg", "  "), "");
+    let long = ocr::transcript("log.png", &"x".repeat(5000));
+    assert!(long.ends_with("x…"));
+}
+
+#[test]
+fn images_cost_tokens_by_their_tiles() {
+    // scaled down to 768x768, four tiles
+    assert_eq!(ocr::image_tokens(Some(1024), Some(1024)), 765);
+    // small enough for one
+    assert_eq!(ocr::image_tokens(Some(400), Some(300)), 255);
+    // 4000x1000 fits as 2048x512, four tiles in a row
+    assert_eq!(ocr::image_tokens(Some(4000), Some(1000)), 85 + 170 * 4);
+    assert_eq!(ocr::image_tokens(None, Some(300)), 85 + 170 * 8);
+}
@@ -0,0 +1,61 @@
+use deskhelp::paste::{self, CodeFolder, FoldedBlock};
+
+/// What `folder` shows of `text`, streamed in a few characters at a time
+fn stream(folder: &mut CodeFolder, text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut shown: String = chars
+        .chunks(3)
+        .map(|chunk| folder.push(&chunk.iter().collect::<String>()))
+        .collect();
+    shown.push_str(&folder.finish());
+    shown
+}
+
+#[test]
+fn short_code_blocks_are_shown_whole() {
+    let answer = "Run:\n```sh\nnpm install\nnpm start\n```\nThen open it.";
+    let mut folder = CodeFolder::new(Some(2), "en-US");
+    assert_eq!(stream(&mut folder, answer), answer);
+    assert!(folder.folded().is_empty());
+
+    let mut off = CodeFolder::new(None, "en-US");
+    let long = "```\na\nb\nc\nd\n```";
+    assert_eq!(stream(&mut off, long), long);
+}
+
+#[test]
+fn long_code_blocks_are_cut_short_and_kept_whole() {
+    let answer = "Try:\n```Rust\nfn main() {\n    let a = 1;\n    let b = 2;\n}\n```\nDone.";
+    let mut folder = CodeFolder::new(Some(2), "en-US");
+    assert_eq!(
+        stream(&mut folder, answer),
+        "Try:\n```Rust\nfn main() {\n    let a = 1;\n```\n-# 2 more lines in `code-1.rs`\nDone."
+    );
+    assert_eq!(
+        folder.folded(),
+        [FoldedBlock {
+            name: "code-1.rs".to_string(),
+            code: "fn main() {\n    let a = 1;\n    let b = 2;\n}\n".to_string(),
+        }]
+    );
+    // what the model said is kept as it said it
+    assert_eq!(folder.text(), answer);
+}
+
+#[test]
+fn unfinished_code_blocks_are_closed() {
+    let mut folder = CodeFolder::new(Some(1), "en-US");
+    assert_eq!(
+        stream(&mut folder, "```\none\ntwo\nthree"),
+        "```\none\n```\n-# 2 more lines in `code-1.txt`\n"
+    );
+    assert_eq!(folder.folded()[0].code, "one\ntwo\nthree");
+}
+
+#[test]
+fn files_are_named_for_their_language() {
+    assert_eq!(paste::extension("python"), "py");
+    assert_eq!(paste::extension("TS"), "ts");
+    assert_eq!(paste::extension("bash"), "sh");
+    assert_eq!(paste::extension(""), "txt");
+}
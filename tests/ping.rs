@@ -0,0 +1,25 @@
+#[allow(dead_code)]
+mod support;
+
+use deskhelp::provider::{Endpoint, Provider};
+use support::{MockResponse, MockServer};
+
+fn provider(servers: &[&MockServer]) -> Provider {
+    let endpoints = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Endpoint::new(format!("mock#{}", i), "sk-test", &s.base_url))
+        .collect();
+    Provider::new("mock", endpoints, "mock-model".to_string()).unwrap()
+}
+
+#[tokio::test]
+async fn pings_count_any_answer_from_an_endpoint() {
+    let erroring = MockServer::start(vec![MockResponse::Error(404)]).await;
+    assert!(provider(&[&erroring]).ping().await.is_ok());
+
+    // nothing listens on port 1
+    let down = Endpoint::new("down".to_string(), "sk-test", "http://127.0.0.1:1/v1");
+    let provider = Provider::new("down", vec![down], "mock-model".to_string()).unwrap();
+    assert!(provider.ping().await.is_err());
+}
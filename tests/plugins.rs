@@ -0,0 +1,18 @@
+use deskhelp::plugins;
+
+#[test]
+fn plugins_bring_their_own_commands() {
+    let plugins = plugins::compiled_in();
+    let names: Vec<_> = plugins.iter().map(|p| p.name()).collect();
+    assert!(names.contains(&"snippets"));
+    assert!(names.contains(&"releases"));
+    assert_eq!(names.contains(&"trello"), cfg!(feature = "trello"));
+    assert_eq!(names.contains(&"polls"), cfg!(feature = "polls"));
+
+    let commands: Vec<_> = plugins
+        .iter()
+        .flat_map(|p| p.commands())
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(commands, ["version"]);
+}
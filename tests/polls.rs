@@ -0,0 +1,25 @@
+#![cfg(feature = "polls")]
+
+use deskhelp::polls;
+use serde_json::json;
+
+#[test]
+fn polls_are_checked_against_discords_limits() {
+    let (question, answers) = polls::parse(&json!({
+        "question": " Which device did you flash? ",
+        "answers": ["Car Thing", " Raspberry Pi ", ""],
+    }))
+    .unwrap();
+    assert_eq!(question, "Which device did you flash?");
+    assert_eq!(answers, ["Car Thing", "Raspberry Pi"]);
+
+    assert!(polls::parse(&json!({ "question": "Which?", "answers": ["only one"] })).is_err());
+    assert!(polls::parse(&json!({ "answers": ["a", "b"] })).is_err());
+    assert!(polls::parse(&json!({
+        "question": "Which?",
+        "answers": ["a", "x".repeat(polls::MAX_ANSWER + 1)],
+    }))
+    .is_err());
+    let eleven: Vec<_> = (0..11).map(|i| i.to_string()).collect();
+    assert!(polls::parse(&json!({ "question": "Which?", "answers": eleven })).is_err());
+}
@@ -0,0 +1,17 @@
+use deskhelp::profile::describe;
+
+#[test]
+fn profiles_say_what_is_known_about_the_asker() {
+    let joined = time::macros::date!(2024 - 01 - 05);
+    let roles = ["Moderator".to_string(), "Helper".to_string()];
+    assert_eq!(
+        describe(Some(joined), &roles, Some(3)),
+        "The asker joined the server on 2024-01-05. Their top roles: Moderator, Helper. \
+         They have asked you 3 questions before."
+    );
+    assert_eq!(
+        describe(None, &[], Some(0)),
+        "This is their first question to you."
+    );
+    assert_eq!(describe(None, &[], None), "");
+}
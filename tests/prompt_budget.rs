@@ -0,0 +1,209 @@
+use async_openai::types::{
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestDeveloperMessage,
+    ChatCompletionRequestDeveloperMessageContent, ChatCompletionRequestMessage,
+    ChatCompletionRequestMessageContentPartText, ChatCompletionRequestSystemMessageContent,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::oai;
+use proptest::prelude::*;
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn assistant(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestAssistantMessage::from(text).into()
+}
+
+/// Token counts and importance of a conversation, a system prompt's tokens,
+/// and a limit somewhere between nothing fitting and everything fitting
+fn budgets() -> impl Strategy<Value = (usize, Vec<usize>, Vec<u8>, usize)> {
+    (
+        1..200usize,
+        prop::collection::vec((1..300usize, 0..4u8), 0..40),
+    )
+        .prop_flat_map(|(system_tokens, messages)| {
+            let total = system_tokens + messages.iter().map(|(t, _)| t).sum::<usize>();
+            let (tokens, importance) = messages.into_iter().unzip();
+            (
+                Just(system_tokens),
+                Just(tokens),
+                Just(importance),
+                0..total + 50,
+            )
+        })
+}
+
+fn message() -> impl Strategy<Value = ChatCompletionRequestMessage> {
+    let text = "[a-z]{1,10}( [a-z]{1,10}){0,12}";
+    prop_oneof![
+        text.prop_map(|text| {
+            ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                content: ChatCompletionRequestUserMessageContent::Text(format!(
+                    "alice (1): {}",
+                    text
+                )),
+                ..Default::default()
+            })
+        }),
+        text.prop_map(|text| ChatCompletionRequestAssistantMessage::from(text).into()),
+    ]
+}
+
+#[test]
+fn developer_messages_in_parts_count_like_their_text() {
+    let developer = |content| {
+        ChatCompletionRequestMessage::Developer(ChatCompletionRequestDeveloperMessage {
+            content,
+            name: None,
+        })
+    };
+    let parts = developer(ChatCompletionRequestDeveloperMessageContent::Array(vec![
+        ChatCompletionRequestMessageContentPartText {
+            text: "Be brief.".to_string(),
+        },
+        ChatCompletionRequestMessageContentPartText {
+            text: "Answer in English.".to_string(),
+        },
+    ]));
+    let text = developer(ChatCompletionRequestDeveloperMessageContent::Text(
+        "Be brief.\nAnswer in English.".to_string(),
+    ));
+    assert_eq!(oai::count_tokens(&parts), oai::count_tokens(&text));
+}
+
+#[test]
+fn prompt_keeps_the_newest_messages_that_fit() {
+    let system = "Be brief.".to_string();
+    let history: Vec<_> = (0..50)
+        .map(|i| user(&format!("message number {} with a few more words", i)))
+        .collect();
+
+    let (all, all_tokens) = oai::build_prompt(system.clone(), &history, 100_000, &[]);
+    assert_eq!(all.len(), 51);
+
+    let limit = all_tokens / 2;
+    let (some, tokens) = oai::build_prompt(system, &history, limit, &[]);
+    assert!(tokens <= limit);
+    assert!(some.len() > 1 && some.len() < 51);
+    assert!(matches!(some[0], ChatCompletionRequestMessage::System(_)));
+    // the most recent message is always last
+    assert_eq!(some.last(), history.last());
+}
+
+#[test]
+fn prompt_drops_chatter_before_the_first_question_pins_and_steps() {
+    let system = "Be brief.".to_string();
+    let history = vec![
+        user("alice (1): my car thing won't flash after the update"),
+        assistant("1. Hold the back button\n2. Plug it in"),
+        user("alice (1): thanks!"),
+        user("bob (2): the server keeps crashing when I open settings"),
+        assistant("That sounds like a bug in the settings page."),
+        user("carol (3): read the pinned guide first"),
+        user("bob (2): ok"),
+        user("alice (1): any news on the flashing tool?"),
+    ];
+    let (all, all_tokens) = oai::build_prompt(system.clone(), &history, 100_000, &[]);
+    assert_eq!(all.len(), history.len() + 1);
+
+    let (some, tokens) = oai::build_prompt(
+        system,
+        &history,
+        all_tokens - 30,
+        &["read the pinned guide first".to_string()],
+    );
+    assert!(tokens <= all_tokens - 30);
+    // the two short replies go, then the oldest ordinary message
+    assert_eq!(
+        &some[1..],
+        &[
+            history[0].clone(),
+            history[1].clone(),
+            history[4].clone(),
+            history[5].clone(),
+            history[7].clone(),
+        ]
+    );
+}
+
+#[test]
+fn prompt_is_just_the_system_message_when_nothing_fits() {
+    let (messages, _) = oai::build_prompt("Be brief.".to_string(), &[user("hi")], 1, &[]);
+    assert_eq!(messages.len(), 1);
+}
+
+proptest! {
+    #[test]
+    fn what_is_kept_fits_the_budget(
+        (system_tokens, tokens, importance, limit) in budgets()
+    ) {
+        let (kept, used) = oai::fit(system_tokens, &tokens, &importance, limit);
+        prop_assert_eq!(kept.len(), tokens.len());
+        let kept_tokens: usize = tokens.iter().zip(&kept).filter(|(_, k)| **k).map(|(t, _)| t).sum();
+        prop_assert_eq!(used, system_tokens + kept_tokens);
+        if system_tokens <= limit {
+            prop_assert!(used <= limit);
+        }
+        if system_tokens + tokens.iter().sum::<usize>() <= limit {
+            prop_assert!(kept.iter().all(|k| *k));
+        }
+    }
+
+    #[test]
+    fn the_newest_message_is_kept_unless_nothing_is(
+        (system_tokens, tokens, importance, limit) in budgets()
+    ) {
+        let (kept, _) = oai::fit(system_tokens, &tokens, &importance, limit);
+        if kept.last() == Some(&false) {
+            prop_assert!(kept.iter().all(|k| !*k));
+        }
+    }
+
+    #[test]
+    fn less_important_and_older_messages_go_first(
+        (system_tokens, tokens, importance, limit) in budgets()
+    ) {
+        let (kept, _) = oai::fit(system_tokens, &tokens, &importance, limit);
+        if kept.iter().any(|k| *k) {
+            let last = tokens.len() - 1;
+            for dropped in (0..last).filter(|&i| !kept[i]) {
+                for stayed in (0..last).filter(|&i| kept[i]) {
+                    prop_assert!((importance[dropped], dropped) < (importance[stayed], stayed));
+                }
+            }
+        }
+    }
+}
+
+proptest! {
+    // counting tokens is slow enough to keep these to fewer cases
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn prompts_start_with_the_system_message_and_keep_the_conversation_in_order(
+        history in prop::collection::vec(message(), 0..20),
+        limit in 0..600usize,
+    ) {
+        let (prompt, tokens) = oai::build_prompt("Be brief.".to_string(), &history, limit, &[]);
+        let ChatCompletionRequestMessage::System(system) = &prompt[0] else {
+            panic!("{:?} doesn't start with the system message", prompt);
+        };
+        prop_assert_eq!(
+            &system.content,
+            &ChatCompletionRequestSystemMessageContent::Text("Be brief.".to_string())
+        );
+        prop_assert_eq!(tokens, prompt.iter().map(oai::count_tokens).sum::<usize>());
+        if prompt.len() > 1 {
+            prop_assert!(tokens <= limit);
+        }
+        // what's left is the conversation with some messages taken out
+        let mut rest = history.iter();
+        for kept in &prompt[1..] {
+            prop_assert!(rest.any(|m| m == kept), "{:?} is out of order", kept);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use deskhelp::queue::{Priority, QueueStatus, RequestQueue};
+
+#[test]
+fn status_shows_who_is_waiting_in_order() {
+    let queue = RequestQueue::new(1);
+    let mut first = queue.join(Priority::Normal);
+    let _permit = first.try_start().ok().unwrap();
+    let _normal = queue.join(Priority::Normal);
+    let _staff = queue.join(Priority::Staff);
+    let _ticket = queue.join(Priority::Ticket);
+
+    assert_eq!(
+        queue.status(),
+        QueueStatus {
+            limit: 1,
+            active: 1,
+            waiting: vec![Priority::Staff, Priority::Ticket, Priority::Normal],
+            avg_duration: Duration::from_secs(10),
+        }
+    );
+}
@@ -0,0 +1,64 @@
+use deskhelp::quoted::{self, Quoted};
+use serenity::all::{ChannelId, MessageId};
+
+#[test]
+fn message_links_are_found_however_they_are_pasted() {
+    let text = "why does <https://discord.com/channels/1/2/3> happen? \
+                also https://ptb.discord.com/channels/@me/4/5 and \
+                https://discord.com/channels/1/2/3 again, \
+                but not https://discord.com/channels/1/2 or https://example.com/channels/1/2/3";
+    assert_eq!(
+        quoted::message_links(text),
+        [
+            (Some(1), ChannelId::new(2), MessageId::new(3)),
+            (None, ChannelId::new(4), MessageId::new(5)),
+        ]
+    );
+    assert!(quoted::message_links("https://discord.com/channels/1/0/3").is_empty());
+}
+
+#[test]
+fn quoted_messages_are_attributed() {
+    assert_eq!(quoted::instructions(&[]), "");
+    let text = quoted::instructions(&[Quoted {
+        author: "Ann".to_string(),
+        author_id: 7,
+        link: "https://discord.com/channels/1/2/3".to_string(),
+        content: "flashing fails at 42%".to_string(),
+        forwarded: false,
+    }]);
+    assert!(
+        text.contains("Ann (7) wrote (https://discord.com/channels/1/2/3):\nflashing fails at 42%")
+    );
+}
+
+#[test]
+fn forwarded_messages_are_read_from_their_snapshots() {
+    let raw = serde_json::json!({
+        "id": "10",
+        "content": "",
+        "message_reference": { "type": 1, "channel_id": "2", "message_id": "3" },
+        "message_snapshots": [{
+            "message": {
+                "content": "Error <:sad:99>: device not found",
+                "embeds": [],
+                "sticker_items": [{ "id": "1", "name": "Shrug", "format_type": 1 }],
+            },
+        }],
+    });
+    let snapshots = quoted::snapshots(&raw);
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(
+        deskhelp::message_text::of(&snapshots[0]),
+        "Error :sad:: device not found\n[sticker: Shrug]"
+    );
+    assert!(quoted::snapshots(&serde_json::json!({ "content": "hi" })).is_empty());
+
+    let mut msg = serenity::all::Message::default();
+    assert!(!quoted::is_forward(&msg));
+    msg.message_reference = Some(serenity::all::MessageReference::from((
+        ChannelId::new(2),
+        MessageId::new(3),
+    )));
+    assert!(quoted::is_forward(&msg));
+}
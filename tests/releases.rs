@@ -0,0 +1,56 @@
+use deskhelp::{announce, config::Config, releases};
+
+#[test]
+fn releases_are_described_with_version_date_and_link() {
+    let path = format!(
+        "{}/tests/fixtures/github_release.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let release: releases::Release =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(
+        releases::describe("server", "ItsRiprod/DeskThing", &release),
+        "The latest server release (ItsRiprod/DeskThing) is v0.11.4 \"v0.11.4 - Bug fixes\", \
+         published 2025-03-02: https://github.com/ItsRiprod/DeskThing/releases/tag/v0.11.4"
+    );
+
+    let untitled = releases::Release {
+        name: Some("v0.11.4".to_string()),
+        ..release
+    };
+    assert!(
+        releases::describe("server", "ItsRiprod/DeskThing", &untitled)
+            .starts_with("The latest server release (ItsRiprod/DeskThing) is v0.11.4, published")
+    );
+}
+
+#[test]
+fn the_deskthing_repositories_are_checked_unless_configured_otherwise() {
+    let repos = Config::default().releases.repos;
+    assert_eq!(repos["server"], "ItsRiprod/DeskThing");
+    assert_eq!(repos["client"], "ItsRiprod/deskthing-client");
+
+    let config: Config = toml::from_str("[releases]\nrepos = {}").unwrap();
+    assert!(config.releases.repos.is_empty());
+}
+
+#[test]
+fn announcements_link_the_release_and_fit_in_a_message() {
+    let release = releases::Release {
+        tag_name: "v0.11.4".to_string(),
+        name: None,
+        html_url: "https://github.com/ItsRiprod/DeskThing/releases/tag/v0.11.4".to_string(),
+        published_at: time::OffsetDateTime::UNIX_EPOCH,
+        body: None,
+    };
+    assert_eq!(
+        announce::announcement("en-US", "server", &release, Some("- Faster")),
+        "📦 **server v0.11.4** is out! <https://github.com/ItsRiprod/DeskThing/releases/tag/v0.11.4>\n**TL;DR**\n- Faster"
+    );
+    assert!(!announce::announcement("en-US", "server", &release, None).contains("TL;DR"));
+
+    let long = "- change\n".repeat(500);
+    let text = announce::announcement("en-US", "server", &release, Some(&long));
+    assert_eq!(text.chars().count(), 2000);
+    assert!(text.ends_with('…'));
+}
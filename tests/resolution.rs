@@ -0,0 +1,65 @@
+use deskhelp::{
+    resolution::{self, Answer},
+    stats,
+    storage::Rating,
+};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::OffsetDateTime;
+
+#[test]
+fn buttons_say_which_answer_and_whose_question() {
+    for (solved, escalated) in [(true, false), (false, false), (true, true)] {
+        let answer = Answer {
+            solved,
+            answer_id: MessageId::new(123),
+            asker_id: UserId::new(456),
+            escalated,
+        };
+        let id = answer.button_id();
+        assert!(id.len() <= 100);
+        assert_eq!(Answer::parse(&id), Some(answer));
+    }
+    assert_eq!(Answer::parse("deskhelp-escalate"), None);
+    assert_eq!(Answer::parse("deskhelp-resolved-maybe-1-2-0"), None);
+    assert_eq!(Answer::parse("deskhelp-resolved-yes-1-2-0-3"), None);
+
+    let id = resolution::rate_button_id(UserId::new(456), true);
+    assert_eq!(
+        resolution::parse_rate_button(&id),
+        Some((UserId::new(456), true))
+    );
+    assert_eq!(resolution::parse_rate_button("deskhelp-escalate"), None);
+}
+
+#[test]
+fn ratings_are_from_one_to_five() {
+    assert_eq!(resolution::parse_score(" 4 "), Some(4));
+    assert_eq!(resolution::parse_score("0"), None);
+    assert_eq!(resolution::parse_score("6"), None);
+    assert_eq!(resolution::parse_score("great"), None);
+}
+
+#[test]
+fn stats_average_the_bot_and_staff_apart() {
+    let rating = |score, escalated| Rating {
+        guild_id: GuildId::new(1),
+        channel_id: ChannelId::new(2),
+        user_id: UserId::new(3),
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        score,
+        comment: None,
+        escalated,
+        transcript: String::new(),
+    };
+    assert!(stats::ratings("en-US", &[]).is_empty());
+    assert_eq!(
+        stats::ratings(
+            "en-US",
+            &[rating(5, false), rating(4, false), rating(2, true)]
+        ),
+        vec![
+            "Rated 4.5/5 by 2 askers when the bot solved it",
+            "Rated 2.0/5 by 1 askers when support staff took over",
+        ]
+    );
+}
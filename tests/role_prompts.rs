@@ -0,0 +1,51 @@
+use deskhelp::oai;
+use serenity::all::{GuildId, RoleId};
+
+fn prompt_context(timezone: &str) -> oai::PromptContext<'_> {
+    oai::PromptContext {
+        bot_name: "DeskHelp",
+        bot_id: "1",
+        guild_name: "Test",
+        channel_name: "help",
+        active_model: "gpt-4o",
+        timezone,
+        channel_prompt: "",
+        role_prompt: "",
+        user_profile: "",
+    }
+}
+
+#[test]
+fn the_highest_role_with_a_prompt_wins() {
+    let prompts: std::collections::HashMap<String, String> = [
+        ("10", "Be terse and technical."),
+        ("20", "Explain step by step."),
+        ("1", "Be friendly."),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let guild = GuildId::new(1);
+    let (staff, newcomer, other) = (RoleId::new(10), RoleId::new(20), RoleId::new(30));
+    assert_eq!(
+        oai::pick_role_prompt(&prompts, &[(newcomer, 1), (staff, 5), (other, 9)], guild),
+        "Be terse and technical."
+    );
+    assert_eq!(
+        oai::pick_role_prompt(&prompts, &[(newcomer, 1)], guild),
+        "Explain step by step."
+    );
+    // the guild id stands for everyone
+    assert_eq!(
+        oai::pick_role_prompt(&prompts, &[(other, 9)], guild),
+        "Be friendly."
+    );
+    assert_eq!(oai::pick_role_prompt(&prompts, &[], GuildId::new(2)), "");
+
+    let context = oai::PromptContext {
+        role_prompt: "Be terse and technical.",
+        ..prompt_context("UTC")
+    };
+    let prompt = oai::system_prompt("Be brief.", &context);
+    assert!(prompt.ends_with("in the Test server\nBe terse and technical."));
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use deskhelp::{
+    reminders,
+    scheduler::{Scheduler, TaskHandler},
+    storage::{Error, SqliteStorage, Storage, Task},
+};
+use serde_json::json;
+use time::{Duration, OffsetDateTime};
+
+struct Collect(Arc<Mutex<Vec<serde_json::Value>>>);
+
+#[serenity::async_trait]
+impl TaskHandler for Collect {
+    async fn run(&self, task: &Task) -> Result<(), Error> {
+        self.0.lock().unwrap().push(task.payload.clone());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn due_tasks_run_once_and_others_wait() {
+    let storage: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::connect("sqlite::memory:").await.unwrap());
+    let scheduler = Scheduler::new(storage.clone());
+    let ran = Arc::new(Mutex::new(vec![]));
+    scheduler.register("test", Collect(ran.clone()));
+
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    scheduler
+        .schedule("test", now + Duration::hours(1), &json!("later"))
+        .await
+        .unwrap();
+    scheduler
+        .schedule("test", now - Duration::minutes(1), &json!("due"))
+        .await
+        .unwrap();
+    scheduler
+        .schedule("unknown", now, &json!("someone else's"))
+        .await
+        .unwrap();
+
+    assert_eq!(scheduler.run_due(now).await.unwrap(), 1);
+    assert_eq!(scheduler.run_due(now).await.unwrap(), 0);
+    assert_eq!(*ran.lock().unwrap(), [json!("due")]);
+
+    // tasks without a handler are left for whoever has one
+    let left: Vec<_> = storage
+        .due_tasks(now + Duration::hours(1))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(left, ["unknown", "test"]);
+}
+
+#[test]
+fn reminder_delays_are_read_like_people_write_them() {
+    assert_eq!(reminders::parse_delay("2h"), Some(Duration::hours(2)));
+    assert_eq!(reminders::parse_delay("30m"), Some(Duration::minutes(30)));
+    assert_eq!(reminders::parse_delay("1d 12h"), Some(Duration::hours(36)));
+    assert_eq!(reminders::parse_delay("1W"), Some(Duration::weeks(1)));
+    assert_eq!(reminders::parse_delay("90"), None);
+    assert_eq!(reminders::parse_delay("soon"), None);
+    assert_eq!(reminders::parse_delay("0m"), None);
+    assert_eq!(reminders::parse_delay("400d"), None);
+    assert_eq!(reminders::parse_delay(""), None);
+    for unit in ["s", "m", "h", "d", "w"] {
+        assert_eq!(
+            reminders::parse_delay(&format!("99999999999999{}", unit)),
+            None
+        );
+        assert_eq!(
+            reminders::parse_delay(&format!("{}{}", i64::MAX, unit)),
+            None
+        );
+    }
+    assert_eq!(reminders::parse_delay("99999999999999999999w"), None);
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "scripting")]
+
+use deskhelp::scripts::{Incoming, Scripts};
+use serenity::all::Message;
+
+fn message(content: &str) -> Message {
+    let mut msg = Message::default();
+    msg.content = content.to_string();
+    msg
+}
+
+#[test]
+fn incoming_messages_can_be_ignored_or_rewritten() {
+    let scripts = Scripts::from_source(
+        r#"
+        fn on_incoming_message(message) {
+            if message.content.starts_with("!") {
+                return false;
+            }
+            if message.content.contains("pls") {
+                let content = message.content;
+                content.replace("pls", "please");
+                return content;
+            }
+        }
+        "#,
+        1_000_000,
+    )
+    .unwrap();
+    assert_eq!(
+        scripts.on_incoming_message(&message("!ping")),
+        Incoming::Ignore
+    );
+    assert_eq!(
+        scripts.on_incoming_message(&message("help pls")),
+        Incoming::Rewrite("help please".to_string())
+    );
+    assert_eq!(
+        scripts.on_incoming_message(&message("help")),
+        Incoming::Keep
+    );
+}
+
+#[test]
+fn prompts_can_change_their_system_prompt_and_model() {
+    let scripts = Scripts::from_source(
+        r#"
+        fn on_prompt_built(prompt) {
+            if prompt.content.contains("code") {
+                #{ system: prompt.system + " Answer with code.", model: "big" }
+            }
+        }
+        "#,
+        1_000_000,
+    )
+    .unwrap();
+    let (mut system, mut model) = ("Be helpful.".to_string(), "small".to_string());
+    scripts.on_prompt_built(&message("some code please"), &mut system, &mut model);
+    assert_eq!(system, "Be helpful. Answer with code.");
+    assert_eq!(model, "big");
+
+    let (mut system, mut model) = ("Be helpful.".to_string(), "small".to_string());
+    scripts.on_prompt_built(&message("hello"), &mut system, &mut model);
+    assert_eq!((system.as_str(), model.as_str()), ("Be helpful.", "small"));
+}
+
+#[test]
+fn responses_can_be_rewritten() {
+    let scripts = Scripts::from_source(
+        r#"fn on_response_ready(response) { response.answer + " (" + response.model + ")" }"#,
+        1_000_000,
+    )
+    .unwrap();
+    assert!(scripts.has("on_response_ready"));
+    assert!(!scripts.has("on_prompt_built"));
+    assert_eq!(
+        scripts.on_response_ready(&message("hi"), "Hello!", "small"),
+        Some("Hello! (small)".to_string())
+    );
+}
+
+#[test]
+fn failing_and_runaway_scripts_change_nothing() {
+    let scripts = Scripts::from_source(
+        r#"
+        fn on_incoming_message(message) { loop {} }
+        fn on_response_ready(response) { response.missing.len() }
+        "#,
+        10_000,
+    )
+    .unwrap();
+    assert_eq!(scripts.on_incoming_message(&message("hi")), Incoming::Keep);
+    assert_eq!(
+        scripts.on_response_ready(&message("hi"), "Hello!", "small"),
+        None
+    );
+    assert!(Scripts::from_source("fn broken(", 10_000).is_err());
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "search")]
+
+use deskhelp::{search, storage::IndexedAnswer};
+use serenity::all::{ChannelId, GuildId, MessageId};
+use time::OffsetDateTime;
+
+fn answer(message: u64, question: &str, embedding: Vec<f32>) -> IndexedAnswer {
+    IndexedAnswer {
+        guild_id: GuildId::new(1),
+        channel_id: ChannelId::new(2),
+        message_id: MessageId::new(message),
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000 + message as i64).unwrap(),
+        question: question.to_string(),
+        answer: "Try this.".to_string(),
+        model: "text-embedding-3-small".to_string(),
+        embedding,
+        author_id: None,
+        asker_id: None,
+        trusted: false,
+    }
+}
+
+#[test]
+fn the_closest_answers_come_first() {
+    let answers = vec![
+        answer(10, "Spotify won't connect", vec![0.8, 0.6]),
+        answer(11, "Flashing fails", vec![1.0, 0.0]),
+        answer(12, "Unrelated", vec![-1.0, 0.0]),
+        answer(13, "Flashing fails again", vec![1.0, 0.0]),
+    ];
+    let ranked: Vec<_> = search::rank(&[1.0, 0.0], answers, 2)
+        .into_iter()
+        .map(|a| a.message_id.get())
+        .collect();
+    // ties go to the newer answer
+    assert_eq!(ranked, vec![13, 11]);
+}
+
+#[test]
+fn results_link_to_the_answers() {
+    let long = format!("My {} won't boot", "Car Thing ".repeat(20));
+    let results = [answer(10, "Spotify\nwon't connect", vec![])];
+    assert_eq!(
+        search::describe("en-US", &results),
+        "**1.** Spotify won't connect — https://discord.com/channels/1/2/10 (<t:1700000010:d>)"
+    );
+    let results = [answer(11, &long, vec![])];
+    assert!(search::describe("en-US", &results).contains("Car Thi… — "));
+}
+
+#[test]
+fn answers_are_embedded_with_their_questions() {
+    assert_eq!(
+        search::document("How do I flash?", "Use the guide."),
+        "Q: How do I flash?\nA: Use the guide."
+    );
+}
+
+#[tokio::test]
+async fn results_only_come_from_readable_channels() {
+    let mut staff = answer(20, "Staff-only fix", vec![1.0, 0.0]);
+    staff.channel_id = ChannelId::new(3);
+    let answers = vec![
+        staff.clone(),
+        answer(21, "Public fix", vec![1.0, 0.0]),
+        staff,
+        answer(22, "Another public fix", vec![1.0, 0.0]),
+    ];
+    let mut asked = vec![];
+    let shown: Vec<_> = search::visible(answers, 5, |channel_id| {
+        asked.push(channel_id);
+        async move { channel_id != ChannelId::new(3) }
+    })
+    .await
+    .into_iter()
+    .map(|a| a.message_id.get())
+    .collect();
+    assert_eq!(shown, vec![21, 22]);
+    // each channel is only looked up once
+    assert_eq!(asked, vec![ChannelId::new(3), ChannelId::new(2)]);
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "setup")]
+
+use deskhelp::{
+    config::Config,
+    setup::{self, GuildSetup},
+};
+use serenity::all::GuildId;
+
+const CONFIG: &str = r#"# How many answers at once
+max_concurrent_requests = 2
+
+[guilds."1"]
+provider = "community"
+# staff only
+support_roles = [10]
+model = "gpt-4o"
+"#;
+
+#[test]
+fn setup_edits_only_the_guild_section() {
+    let guild = GuildId::new(1);
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    let mut setup = GuildSetup::from_config(&config, guild);
+    assert_eq!(setup.support_roles, [10]);
+    assert_eq!(setup.model.as_deref(), Some("gpt-4o"));
+
+    setup.autorespond_channels = vec![1302692329400041482];
+    setup.support_roles = vec![10, 20];
+    setup.model = None;
+    setup.locale = Some("de".to_string());
+    setup.dry_run = Some(true);
+    let contents = setup::apply(CONFIG, guild, &setup).unwrap();
+    assert!(contents.starts_with("# How many answers at once\nmax_concurrent_requests = 2\n"));
+    assert!(contents.contains("provider = \"community\"\n# staff only\nsupport_roles = [10, 20]\n"));
+    assert!(!contents.contains("model"));
+
+    let config: Config = toml::from_str(&contents).unwrap();
+    assert_eq!(GuildSetup::from_config(&config, guild), setup);
+    assert!(config
+        .autorespond_channels()
+        .contains(&"1302692329400041482".to_string()));
+    assert_eq!(config.max_concurrent_requests, 2);
+}
+
+#[test]
+fn setup_leaves_unset_settings_to_the_global_ones() {
+    let guild = GuildId::new(1);
+    let contents = format!("dry_run = true\n{}", CONFIG);
+    let config: Config = toml::from_str(&contents).unwrap();
+    let setup = GuildSetup::from_config(&config, guild);
+    assert_eq!(setup.dry_run, None);
+    assert!(setup.dry_run_or(&config));
+
+    let contents = setup::apply(&contents, guild, &setup).unwrap();
+    assert_eq!(contents.matches("dry_run").count(), 1);
+    // so turning it off globally turns it off for the guild too
+    let config: Config = toml::from_str(&contents.replace("dry_run = true", "")).unwrap();
+    assert!(!config.dry_run(Some(guild)));
+}
+
+#[test]
+fn setup_adds_guilds_the_config_does_not_have() {
+    let setup = GuildSetup {
+        locale: Some("de".to_string()),
+        ..Default::default()
+    };
+    let contents = setup::apply("", GuildId::new(2), &setup).unwrap();
+    assert!(contents.starts_with("[guilds.2]\n"));
+    let config: Config = toml::from_str(&contents).unwrap();
+    assert_eq!(config.locale(Some(GuildId::new(2))), "de");
+}
+
+#[test]
+fn saves_at_once_keep_each_others_changes() {
+    let path = std::env::temp_dir().join(format!("deskhelp-setup-{}.toml", std::process::id()));
+    std::fs::write(&path, CONFIG).unwrap();
+    std::thread::scope(|scope| {
+        for guild in 2..10 {
+            let path = &path;
+            scope.spawn(move || {
+                let setup = GuildSetup {
+                    support_roles: vec![guild],
+                    ..Default::default()
+                };
+                setup::save_to(path, GuildId::new(guild), &setup).unwrap();
+            });
+        }
+    });
+    let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    for guild in 1..10 {
+        assert!(config.guild(Some(GuildId::new(guild))).is_some());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
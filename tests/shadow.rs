@@ -0,0 +1,24 @@
+use deskhelp::{config::Config, shadow::ShadowOverrides};
+use serenity::all::GuildId;
+
+#[test]
+fn toggling_overrides_the_config_for_one_guild() {
+    let config: Config = toml::from_str(
+        r#"
+        [guilds."1"]
+        dry_run = true
+        "#,
+    )
+    .unwrap();
+    let guild = Some(GuildId::new(1));
+    let shadow = ShadowOverrides::default();
+    assert!(shadow.dry_run(&config, guild));
+    assert!(!shadow.dry_run(&config, None));
+
+    assert!(!shadow.toggle(&config, guild));
+    assert!(!shadow.dry_run(&config, guild));
+    assert!(!shadow.dry_run(&config, None));
+
+    assert!(shadow.toggle(&config, guild));
+    assert!(shadow.dry_run(&config, guild));
+}
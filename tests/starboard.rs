@@ -0,0 +1,63 @@
+#![cfg(feature = "search")]
+
+use deskhelp::{starboard, storage::IndexedAnswer};
+use serenity::all::{ChannelId, GuildId, MessageId, MessageReaction, ReactionType, UserId};
+use time::OffsetDateTime;
+
+fn emojis() -> Vec<String> {
+    vec!["✅".to_string(), "⭐".to_string()]
+}
+
+fn reaction(emoji: &str, count: u64, me: bool) -> MessageReaction {
+    serde_json::from_value(serde_json::json!({
+        "count": count,
+        "count_details": { "burst": 0, "normal": count },
+        "me": me,
+        "me_burst": false,
+        "emoji": { "name": emoji },
+        "burst_colours": [],
+    }))
+    .unwrap()
+}
+
+#[test]
+fn vouches_count_every_configured_emoji_but_the_bots() {
+    let reactions = [
+        reaction("✅", 2, false),
+        reaction("⭐\u{fe0f}", 2, true),
+        reaction("👍", 5, false),
+    ];
+    assert_eq!(starboard::vouches(&reactions, &emojis()), 3);
+    assert!(starboard::is_vouch(
+        &ReactionType::Unicode("⭐".to_string()),
+        &emojis()
+    ));
+    assert!(!starboard::is_vouch(
+        &ReactionType::Unicode("👍".to_string()),
+        &emojis()
+    ));
+}
+
+#[test]
+fn vouched_answers_are_attributed() {
+    let answer = |author_id| IndexedAnswer {
+        guild_id: GuildId::new(1),
+        channel_id: ChannelId::new(2),
+        message_id: MessageId::new(3),
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        question: "Spotify won't connect".to_string(),
+        answer: "Re-link it in settings.".to_string(),
+        model: "text-embedding-3-small".to_string(),
+        embedding: vec![],
+        author_id,
+        asker_id: None,
+        trusted: true,
+    };
+    assert_eq!(starboard::instructions(&[]), "");
+    let text = starboard::instructions(&[answer(Some(UserId::new(9))), answer(None)]);
+    assert!(text.contains(
+        "Answered by <@9> (https://discord.com/channels/1/2/3):\n\
+         Q: Spotify won't connect\nA: Re-link it in settings."
+    ));
+    assert!(text.contains("Answered by you (https://discord.com/channels/1/2/3)"));
+}
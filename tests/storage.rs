@@ -0,0 +1,1006 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use deskhelp::archive::Archive;
+use deskhelp::config::{Config, MemoryBudget, RetentionConfig};
+use deskhelp::prompt::{self, Prompts};
+use deskhelp::retention;
+use deskhelp::storage::{
+    AnswerRecord, Deletion, Document, IndexedAnswer, KbDocument, KbVersion, QuestionEmbedding,
+    Rating, Snippet, SqliteStorage, Storage, VariantStats,
+};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use time::OffsetDateTime;
+
+// Each check runs against every backend: SQLite in memory, and PostgreSQL when
+// built with `--features postgres` and DESKHELP_TEST_POSTGRES_URL points at a
+// database the tests may wipe
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(super::sqlite().await).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "postgres")]
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    let Some((storage, _guard)) = super::postgres().await else {
+                        return;
+                    };
+                    super::$name(storage).await;
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    stats_cover_one_guild_since_a_time,
+    stats_are_empty_without_answers,
+    last_digest_is_remembered,
+    variants_are_compared_by_feedback,
+    settings_can_be_set_and_cleared,
+    prompt_versions_can_be_pinned_across_restarts,
+    archives_move_everything_to_another_instance,
+    questions_are_counted_per_member,
+    transcripts_come_with_feedback,
+    snippets_are_kept_per_guild,
+    tasks_are_taken_once,
+    question_embeddings_are_kept_per_guild_and_model,
+    answers_are_indexed_per_guild_and_model,
+    vouched_for_answers_become_trusted,
+    forgetting_a_user_deletes_their_records,
+    old_records_are_purged,
+    usage_is_grouped_by_channel_and_model,
+    ratings_are_kept_per_thread_and_user,
+    documents_are_replaced_by_name,
+    kb_versions_can_be_rolled_back,
+);
+
+async fn sqlite() -> Arc<dyn Storage> {
+    Arc::new(SqliteStorage::connect("sqlite::memory:").await.unwrap())
+}
+
+#[tokio::test]
+async fn sqlite_keeps_to_a_memory_budget() {
+    let path = std::env::temp_dir().join(format!("deskhelp-budget-{}.db", std::process::id()));
+    let url = format!("sqlite://{}", path.display());
+    let budget = MemoryBudget { megabytes: 32 };
+    let storage = SqliteStorage::connect_within(&url, Some(budget))
+        .await
+        .unwrap();
+    storage.set_setting("a", Some("1")).await.unwrap();
+    let cache_size: i64 = sqlx::query_scalar("PRAGMA cache_size")
+        .fetch_one(storage.pool())
+        .await
+        .unwrap();
+    assert_eq!(cache_size, -(budget.sqlite_cache_kib() as i64));
+    assert_eq!(storage.pool().options().get_max_connections(), 1);
+    assert_eq!(storage.pool().size(), 1);
+    drop(storage);
+
+    // the same file, as it would be read without a budget
+    let storage = SqliteStorage::connect(&url).await.unwrap();
+    assert_eq!(storage.setting("a").await.unwrap().as_deref(), Some("1"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// An empty database, held until the guard is dropped since tests share it
+#[cfg(feature = "postgres")]
+async fn postgres() -> Option<(Arc<dyn Storage>, tokio::sync::MutexGuard<'static, ()>)> {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let url = std::env::var("DESKHELP_TEST_POSTGRES_URL").ok()?;
+    let guard = LOCK.lock().await;
+    let storage = deskhelp::storage::PostgresStorage::connect(&url)
+        .await
+        .unwrap();
+    storage.restore(&Default::default()).await.unwrap();
+    Some((Arc::new(storage), guard))
+}
+
+fn record(guild: u64, ok: bool, latency_ms: u64, at: OffsetDateTime) -> AnswerRecord {
+    AnswerRecord {
+        guild_id: GuildId::new(guild),
+        at,
+        ok,
+        latency: Duration::from_millis(latency_ms),
+        prompt_tokens: 100,
+        completion_tokens: 20,
+        message_id: None,
+        experiment: None,
+        variant: None,
+        prompt_version: None,
+        question: None,
+        answer: None,
+        user_id: None,
+        channel_id: None,
+        model: None,
+        kb_version: None,
+    }
+}
+
+async fn stats_cover_one_guild_since_a_time(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::now_utc();
+    for latency_ms in 1..=20 {
+        storage
+            .record_answer(&record(1, true, latency_ms * 100, now))
+            .await
+            .unwrap();
+    }
+    storage
+        .record_answer(&record(1, false, 30_000, now))
+        .await
+        .unwrap();
+    // another guild, and too long ago
+    storage
+        .record_answer(&record(2, true, 1, now))
+        .await
+        .unwrap();
+    storage
+        .record_answer(&record(1, true, 1, now - time::Duration::days(30)))
+        .await
+        .unwrap();
+
+    let stats = storage
+        .guild_stats(GuildId::new(1), now - time::Duration::days(7))
+        .await
+        .unwrap();
+    assert_eq!(stats.answered, 20);
+    assert_eq!(stats.errors, 1);
+    assert!((stats.error_rate() - 1.0 / 21.0).abs() < 1e-9);
+    // failures don't count towards latency
+    assert_eq!(stats.average_latency, Duration::from_millis(1050));
+    assert_eq!(stats.p95_latency, Duration::from_millis(1900));
+    assert_eq!(stats.prompt_tokens, 2100);
+    assert_eq!(stats.completion_tokens, 420);
+}
+
+async fn stats_are_empty_without_answers(storage: Arc<dyn Storage>) {
+    let stats = storage
+        .guild_stats(GuildId::new(1), OffsetDateTime::UNIX_EPOCH)
+        .await
+        .unwrap();
+    assert_eq!(stats, Default::default());
+    assert_eq!(stats.error_rate(), 0.0);
+}
+
+async fn last_digest_is_remembered(storage: Arc<dyn Storage>) {
+    let guild = GuildId::new(1);
+    assert_eq!(storage.last_digest(guild).await.unwrap(), None);
+
+    let at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    storage.set_last_digest(guild, at).await.unwrap();
+    storage
+        .set_last_digest(guild, at + time::Duration::days(7))
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.last_digest(guild).await.unwrap(),
+        Some(at + time::Duration::days(7))
+    );
+}
+
+#[test]
+fn summary_shows_every_figure() {
+    let stats = deskhelp::storage::GuildStats {
+        answered: 9,
+        errors: 1,
+        average_latency: Duration::from_millis(2500),
+        p95_latency: Duration::from_secs(6),
+        prompt_tokens: 1000,
+        completion_tokens: 200,
+    };
+    let text = deskhelp::stats::summary("en-US", 7, &stats);
+    assert_eq!(
+        text,
+        "**Last 7 days**\nQuestions answered: 9\nErrors: 1 (10.0%)\n\
+         Latency: 2.5s average, 6.0s for the slowest 5%\nTokens: 1000 prompt, 200 completion"
+    );
+}
+
+async fn variants_are_compared_by_feedback(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::now_utc();
+    let answer = |message_id: u64, variant: &str, ok: bool| AnswerRecord {
+        message_id: ok.then(|| MessageId::new(message_id)),
+        experiment: Some("tone".to_string()),
+        variant: Some(variant.to_string()),
+        ..record(1, ok, 1000, now)
+    };
+    for record in [
+        answer(10, "friendly", true),
+        answer(11, "friendly", true),
+        answer(12, "terse", true),
+        answer(13, "terse", false),
+    ] {
+        storage.record_answer(&record).await.unwrap();
+    }
+    for (message, user, up) in [(10, 1, true), (10, 2, true), (11, 1, false), (12, 1, false)] {
+        assert!(storage
+            .add_feedback(MessageId::new(message), UserId::new(user), up)
+            .await
+            .unwrap());
+    }
+    // changed their mind, and a reaction on something that isn't an answer
+    assert!(storage
+        .remove_feedback(MessageId::new(12), UserId::new(1), false)
+        .await
+        .unwrap());
+    assert!(!storage
+        .add_feedback(MessageId::new(99), UserId::new(1), true)
+        .await
+        .unwrap());
+    assert!(!storage
+        .remove_feedback(MessageId::new(99), UserId::new(1), true)
+        .await
+        .unwrap());
+
+    let results = storage.variant_stats("tone").await.unwrap();
+    assert_eq!(
+        results,
+        vec![
+            VariantStats {
+                variant: "friendly".to_string(),
+                answered: 2,
+                errors: 0,
+                average_latency: Duration::from_secs(1),
+                thumbs_up: 2,
+                thumbs_down: 1,
+            },
+            VariantStats {
+                variant: "terse".to_string(),
+                answered: 1,
+                errors: 1,
+                average_latency: Duration::from_secs(1),
+                thumbs_up: 0,
+                thumbs_down: 0,
+            },
+        ]
+    );
+    assert!(storage.variant_stats("other").await.unwrap().is_empty());
+}
+
+async fn settings_can_be_set_and_cleared(storage: Arc<dyn Storage>) {
+    assert_eq!(storage.setting("a").await.unwrap(), None);
+    storage.set_setting("a", Some("1")).await.unwrap();
+    storage.set_setting("a", Some("2")).await.unwrap();
+    assert_eq!(storage.setting("a").await.unwrap().as_deref(), Some("2"));
+    storage.set_setting("a", None).await.unwrap();
+    assert_eq!(storage.setting("a").await.unwrap(), None);
+}
+
+async fn prompt_versions_can_be_pinned_across_restarts(storage: Arc<dyn Storage>) {
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "deskhelp-prompt-{}-{}.txt",
+        std::process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&path, "Be helpful.").unwrap();
+    let config = Config {
+        prompt_file: Some(path.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    let prompts = Prompts::load(&config, storage.clone()).await.unwrap();
+    let first = prompts.active();
+    assert_eq!(first.text, "Be helpful.");
+    assert_eq!(first.version, prompt::version_of("Be helpful."));
+
+    std::fs::write(&path, "Be terse.").unwrap();
+    let prompts = Prompts::load(&config, storage.clone()).await.unwrap();
+    assert_eq!(prompts.active().text, "Be terse.");
+    assert_eq!(prompts.history(10).await.unwrap().len(), 2);
+
+    assert!(prompts.pin("nope").await.unwrap().is_none());
+    prompts.pin(&first.version).await.unwrap().unwrap();
+    assert_eq!(prompts.active().text, "Be helpful.");
+
+    // still pinned after a restart, until unpinned
+    let prompts = Prompts::load(&config, storage.clone()).await.unwrap();
+    assert!(prompts.is_pinned());
+    assert_eq!(prompts.active().text, "Be helpful.");
+    prompts.unpin(&config).await.unwrap();
+    assert_eq!(prompts.active().text, "Be terse.");
+    assert!(!prompts.is_pinned());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+// from SQLite, so for Postgres this is also moving between backends
+async fn archives_move_everything_to_another_instance(new: Arc<dyn Storage>) {
+    let old = sqlite().await;
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    old.record_answer(&AnswerRecord {
+        message_id: Some(MessageId::new(10)),
+        prompt_version: Some("abc".to_string()),
+        question: Some("How do I flash?".to_string()),
+        answer: Some("Follow the guide.".to_string()),
+        channel_id: Some(ChannelId::new(20)),
+        model: Some("gpt-4o".to_string()),
+        ..record(1, true, 1500, now)
+    })
+    .await
+    .unwrap();
+    old.add_feedback(MessageId::new(10), UserId::new(2), true)
+        .await
+        .unwrap();
+    old.set_last_digest(GuildId::new(1), now).await.unwrap();
+    old.save_prompt_version(&deskhelp::prompt::PromptVersion::new(
+        "Be nice.".to_string(),
+    ))
+    .await
+    .unwrap();
+    old.set_setting("prompt.pinned", Some("abc")).await.unwrap();
+    old.count_question(GuildId::new(1), UserId::new(2), now)
+        .await
+        .unwrap();
+    old.save_snippet(&snippet(1, "reset", "Hold the back button.", now))
+        .await
+        .unwrap();
+    old.add_task("reminder", now, &serde_json::json!({"text": "reflash"}))
+        .await
+        .unwrap();
+    old.save_question_embedding(&question_embedding(1, "small", now, vec![0.25, -1.5]))
+        .await
+        .unwrap();
+    old.index_answer(&indexed_answer(1, "small", now, "How do I flash?"))
+        .await
+        .unwrap();
+    old.record_deletion(&Deletion {
+        user_id: UserId::new(3),
+        at: now,
+        records: 4,
+        messages: 2,
+    })
+    .await
+    .unwrap();
+    old.record_rating(&Rating {
+        comment: Some("Quick and right".to_string()),
+        ..rating(1, 20, 2, now, 5)
+    })
+    .await
+    .unwrap();
+
+    old.save_document(&document(1, "pairing", "Hold the preset button.", now), 20)
+        .await
+        .unwrap();
+    old.save_kb_version(&kb_version(1, "abc123", now, "Hold the preset button."), 20)
+        .await
+        .unwrap();
+
+    let contexts = [("123".to_string(), vec![])].into_iter().collect();
+    let json = Archive::export(old.as_ref(), contexts)
+        .await
+        .unwrap()
+        .to_json()
+        .unwrap();
+    let archive = Archive::from_json(&json).unwrap();
+    assert_eq!(archive.contexts.len(), 1);
+
+    new.record_answer(&record(5, true, 1, now)).await.unwrap();
+    archive.restore(new.as_ref()).await.unwrap();
+    assert_eq!(new.dump().await.unwrap(), old.dump().await.unwrap());
+}
+
+async fn questions_are_counted_per_member(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let (guild, user) = (GuildId::new(1), UserId::new(2));
+    assert_eq!(storage.count_question(guild, user, now).await.unwrap(), 0);
+    assert_eq!(storage.count_question(guild, user, now).await.unwrap(), 1);
+    assert_eq!(
+        storage
+            .count_question(GuildId::new(3), user, now)
+            .await
+            .unwrap(),
+        0
+    );
+    let later = now + time::Duration::days(1);
+    assert_eq!(storage.count_question(guild, user, later).await.unwrap(), 2);
+
+    let askers = storage.dump().await.unwrap().askers;
+    assert_eq!(askers.len(), 2);
+    assert_eq!(askers[0].questions, 3);
+    assert_eq!(askers[0].first_asked_at, now);
+}
+
+#[cfg(feature = "backup")]
+#[tokio::test]
+async fn exported_archives_leave_secrets_out() {
+    let config = r#"
+[storage]
+url = "postgres://deskhelp:hunter2@db/deskhelp"
+encryption_key = "MDEy..."
+
+[dashboard]
+token = "abc"
+
+[providers.pooled]
+base_url = "https://openrouter.ai/api/v1"
+endpoints = [{ api_key = "sk-or-1" }]
+"#;
+    let json = Archive::collect(sqlite().await.as_ref(), Default::default(), Some(config))
+        .await
+        .unwrap()
+        .to_json()
+        .unwrap();
+    let json = String::from_utf8(json).unwrap();
+    for secret in ["hunter2", "MDEy", "abc", "sk-or-1"] {
+        assert!(!json.contains(secret), "{} is in {}", secret, json);
+    }
+    assert!(json.contains("https://openrouter.ai/api/v1"));
+}
+
+#[test]
+fn archives_from_newer_versions_are_refused() {
+    let json = br#"{"format": 99, "exported_at": "2026-01-01T00:00:00Z", "config": null}"#;
+    assert!(Archive::from_json(json).is_err());
+}
+
+fn snippet(guild: u64, name: &str, content: &str, updated_at: OffsetDateTime) -> Snippet {
+    Snippet {
+        guild_id: GuildId::new(guild),
+        name: name.to_string(),
+        content: content.to_string(),
+        updated_at,
+    }
+}
+
+fn document(guild: u64, name: &str, text: &str, updated_at: OffsetDateTime) -> Document {
+    Document {
+        guild_id: GuildId::new(guild),
+        name: name.to_string(),
+        text: text.to_string(),
+        url: Some(format!("https://deskthing.app/docs/{}", name)),
+        model: "text-embedding-3-small".to_string(),
+        embedding: vec![0.25, -0.5, 0.125],
+        updated_at,
+    }
+}
+
+async fn documents_are_replaced_by_name(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let guild = GuildId::new(1);
+    storage
+        .save_document(&document(1, "pairing", "Hold the preset button.", now), 20)
+        .await
+        .unwrap();
+    storage
+        .save_document(&document(1, "flashing", "Use the flashing guide.", now), 20)
+        .await
+        .unwrap();
+    storage
+        .save_document(&document(2, "pairing", "Another server's.", now), 20)
+        .await
+        .unwrap();
+
+    let edited = document(1, "pairing", "Hold the preset button for 5 seconds.", now);
+    storage.save_document(&edited, 20).await.unwrap();
+    let documents = storage.documents(guild).await.unwrap();
+    assert_eq!(documents.len(), 2);
+    assert_eq!(documents[1], edited);
+
+    assert!(storage
+        .delete_document(guild, "flashing", 20)
+        .await
+        .unwrap());
+    assert!(!storage
+        .delete_document(guild, "flashing", 20)
+        .await
+        .unwrap());
+    assert_eq!(
+        storage.documents(guild).await.unwrap(),
+        std::slice::from_ref(&edited)
+    );
+
+    // every change is snapshotted with it, without the embeddings
+    let versions = storage.kb_versions(guild, 10).await.unwrap();
+    assert_eq!(versions.len(), 4);
+    assert_eq!(
+        versions[0].version,
+        KbVersion::version_of(std::slice::from_ref(&edited))
+    );
+    assert_eq!(versions[0].documents, [KbDocument::from(&edited)]);
+}
+
+fn kb_version(guild: u64, version: &str, created_at: OffsetDateTime, text: &str) -> KbVersion {
+    KbVersion {
+        guild_id: GuildId::new(guild),
+        version: version.to_string(),
+        created_at,
+        documents: vec![KbDocument::from(&document(
+            guild, "pairing", text, created_at,
+        ))],
+    }
+}
+
+async fn kb_versions_can_be_rolled_back(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let guild = GuildId::new(1);
+    for (i, version) in ["a", "b", "c"].into_iter().enumerate() {
+        let at = now + time::Duration::minutes(i as i64);
+        storage
+            .save_kb_version(&kb_version(1, version, at, version), 2)
+            .await
+            .unwrap();
+    }
+    storage
+        .save_kb_version(&kb_version(2, "a", now, "another server's"), 2)
+        .await
+        .unwrap();
+
+    // only the latest two are kept, newest first
+    let versions = storage.kb_versions(guild, 10).await.unwrap();
+    let names: Vec<&str> = versions.iter().map(|v| v.version.as_str()).collect();
+    assert_eq!(names, ["c", "b"]);
+    assert!(storage.kb_version(guild, "a").await.unwrap().is_none());
+    let b = storage.kb_version(guild, "b").await.unwrap().unwrap();
+    assert_eq!(b.documents[0].text, "b");
+
+    // going back to a version makes it the newest again
+    let later = now + time::Duration::hours(1);
+    storage
+        .save_kb_version(
+            &KbVersion {
+                created_at: later,
+                ..b.clone()
+            },
+            2,
+        )
+        .await
+        .unwrap();
+    assert_eq!(storage.kb_versions(guild, 1).await.unwrap()[0].version, "b");
+
+    storage
+        .save_document(&document(1, "flashing", "Use the flashing guide.", now), 2)
+        .await
+        .unwrap();
+    let restored = [document(1, "pairing", "b", now)];
+    storage
+        .replace_documents(guild, &restored, 2)
+        .await
+        .unwrap();
+    assert_eq!(storage.documents(guild).await.unwrap(), restored);
+    assert_eq!(storage.documents(GuildId::new(2)).await.unwrap(), []);
+    let versions = storage.kb_versions(guild, 10).await.unwrap();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].version, KbVersion::version_of(&restored));
+}
+
+async fn snippets_are_kept_per_guild(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let guild = GuildId::new(1);
+    assert_eq!(storage.snippet(guild, "reset").await.unwrap(), None);
+    storage
+        .save_snippet(&snippet(1, "reset", "Hold the back button.", now))
+        .await
+        .unwrap();
+    storage
+        .save_snippet(&snippet(1, "flash", "Use the flashing guide.", now))
+        .await
+        .unwrap();
+    storage
+        .save_snippet(&snippet(2, "reset", "Another server's.", now))
+        .await
+        .unwrap();
+
+    let later = now + time::Duration::hours(1);
+    let edited = snippet(1, "reset", "Hold the back button for 10 seconds.", later);
+    storage.save_snippet(&edited).await.unwrap();
+    assert_eq!(storage.snippet(guild, "reset").await.unwrap(), Some(edited));
+    let names: Vec<_> = storage
+        .snippets(guild)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.name)
+        .collect();
+    assert_eq!(names, ["flash", "reset"]);
+}
+
+async fn tasks_are_taken_once(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let payload = serde_json::json!({"text": "check the reflash"});
+    let later = storage
+        .add_task("reminder", now + time::Duration::hours(2), &payload)
+        .await
+        .unwrap();
+    let due = storage.add_task("reminder", now, &payload).await.unwrap();
+    assert_ne!(later, due);
+
+    let tasks = storage.due_tasks(now).await.unwrap();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].id, due);
+    assert_eq!(tasks[0].payload, payload);
+    assert!(storage.remove_task(due).await.unwrap());
+    assert!(!storage.remove_task(due).await.unwrap());
+    assert!(storage.due_tasks(now).await.unwrap().is_empty());
+}
+
+async fn transcripts_come_with_feedback(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let kept = |message_id: u64, question: &str, ok: bool| AnswerRecord {
+        message_id: ok.then(|| MessageId::new(message_id)),
+        question: Some(question.to_string()),
+        answer: ok.then(|| "Try this.".to_string()),
+        ..record(1, ok, 1000, now)
+    };
+    for record in [
+        kept(10, "Spotify won't connect", true),
+        kept(11, "ADB can't find the device", false),
+        record(1, true, 1000, now),
+        AnswerRecord {
+            at: now - time::Duration::days(8),
+            ..kept(12, "Last week's", true)
+        },
+    ] {
+        storage.record_answer(&record).await.unwrap();
+    }
+    for (user, up) in [(1, true), (2, true), (3, false)] {
+        storage
+            .add_feedback(MessageId::new(10), UserId::new(user), up)
+            .await
+            .unwrap();
+    }
+
+    let transcripts = storage
+        .transcripts(GuildId::new(1), now - time::Duration::days(7))
+        .await
+        .unwrap();
+    assert_eq!(transcripts.len(), 2);
+    assert_eq!(transcripts[0].question, "Spotify won't connect");
+    assert_eq!(transcripts[0].answer.as_deref(), Some("Try this."));
+    assert_eq!(
+        (transcripts[0].thumbs_up, transcripts[0].thumbs_down),
+        (2, 1)
+    );
+    assert!(!transcripts[1].ok);
+    assert_eq!(
+        (transcripts[1].thumbs_up, transcripts[1].thumbs_down),
+        (0, 0)
+    );
+}
+
+fn question_embedding(
+    guild: u64,
+    model: &str,
+    at: OffsetDateTime,
+    embedding: Vec<f32>,
+) -> QuestionEmbedding {
+    QuestionEmbedding {
+        guild_id: GuildId::new(guild),
+        channel_id: ChannelId::new(20),
+        message_id: MessageId::new(at.unix_timestamp() as u64),
+        at,
+        model: model.to_string(),
+        embedding,
+    }
+}
+
+async fn question_embeddings_are_kept_per_guild_and_model(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let kept = question_embedding(1, "small", now, vec![0.1, 0.2, -0.3]);
+    for question in [
+        kept.clone(),
+        question_embedding(2, "small", now, vec![1.0]),
+        question_embedding(1, "large", now, vec![1.0]),
+        question_embedding(1, "small", now - time::Duration::days(31), vec![1.0]),
+    ] {
+        storage.save_question_embedding(&question).await.unwrap();
+    }
+    assert_eq!(
+        storage
+            .question_embeddings(GuildId::new(1), "small", now - time::Duration::days(30))
+            .await
+            .unwrap(),
+        vec![kept]
+    );
+}
+
+fn indexed_answer(guild: u64, model: &str, at: OffsetDateTime, question: &str) -> IndexedAnswer {
+    IndexedAnswer {
+        guild_id: GuildId::new(guild),
+        channel_id: ChannelId::new(20),
+        message_id: MessageId::new(at.unix_timestamp() as u64),
+        at,
+        question: question.to_string(),
+        answer: "Follow the flashing guide.".to_string(),
+        model: model.to_string(),
+        embedding: vec![0.5, -0.25],
+        author_id: None,
+        asker_id: None,
+        trusted: false,
+    }
+}
+
+async fn answers_are_indexed_per_guild_and_model(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let older = indexed_answer(1, "small", now - time::Duration::days(400), "Old question");
+    let newer = indexed_answer(1, "small", now, "New question");
+    for (i, answer) in [
+        newer.clone(),
+        indexed_answer(2, "small", now, "Another server's"),
+        indexed_answer(1, "large", now, "Another model's"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        // one entry per message
+        let answer = IndexedAnswer {
+            message_id: MessageId::new(answer.message_id.get() + i as u64),
+            ..answer
+        };
+        storage.index_answer(&answer).await.unwrap();
+    }
+    storage.index_answer(&older).await.unwrap();
+    assert_eq!(
+        storage
+            .indexed_answers(GuildId::new(1), "small")
+            .await
+            .unwrap(),
+        vec![older, newer]
+    );
+}
+
+async fn vouched_for_answers_become_trusted(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let answer = indexed_answer(1, "small", now, "How do I flash?");
+    storage.index_answer(&answer).await.unwrap();
+    assert!(storage
+        .trusted_answers(GuildId::new(1), "small")
+        .await
+        .unwrap()
+        .is_empty());
+
+    let trusted = IndexedAnswer {
+        trusted: true,
+        ..answer.clone()
+    };
+    storage.index_answer(&trusted).await.unwrap();
+    let staff = IndexedAnswer {
+        message_id: MessageId::new(99),
+        author_id: Some(UserId::new(5)),
+        trusted: true,
+        ..answer.clone()
+    };
+    storage.index_answer(&staff).await.unwrap();
+    assert_eq!(
+        storage.indexed_answer(answer.message_id).await.unwrap(),
+        Some(trusted.clone())
+    );
+    assert_eq!(
+        storage
+            .trusted_answers(GuildId::new(1), "small")
+            .await
+            .unwrap(),
+        vec![trusted, staff]
+    );
+}
+
+async fn forgetting_a_user_deletes_their_records(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let (user, other) = (UserId::new(5), UserId::new(6));
+    for (message, asker) in [(10, user), (11, other)] {
+        storage
+            .record_answer(&AnswerRecord {
+                message_id: Some(MessageId::new(message)),
+                question: Some("How do I flash?".to_string()),
+                user_id: Some(asker),
+                ..record(1, true, 1000, now)
+            })
+            .await
+            .unwrap();
+        storage
+            .count_question(GuildId::new(1), asker, now)
+            .await
+            .unwrap();
+        storage
+            .index_answer(&IndexedAnswer {
+                message_id: MessageId::new(message),
+                asker_id: Some(asker),
+                ..indexed_answer(1, "small", now, "How do I flash?")
+            })
+            .await
+            .unwrap();
+        storage
+            .add_task(
+                "reminder",
+                now,
+                &serde_json::json!({"user_id": asker, "text": "reflash"}),
+            )
+            .await
+            .unwrap();
+    }
+    for asker in [user, other] {
+        storage
+            .add_feedback(MessageId::new(11), asker, true)
+            .await
+            .unwrap();
+    }
+    storage
+        .index_answer(&IndexedAnswer {
+            message_id: MessageId::new(12),
+            author_id: Some(user),
+            ..indexed_answer(1, "small", now, "Why won't it boot?")
+        })
+        .await
+        .unwrap();
+
+    for asker in [user, other] {
+        storage
+            .record_rating(&rating(1, 20, asker.get(), now, 4))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(storage.forget_user(user).await.unwrap(), 7);
+    assert_eq!(storage.forget_user(user).await.unwrap(), 0);
+    let dump = storage.dump().await.unwrap();
+    assert_eq!(dump.answers.len(), 1);
+    assert_eq!(dump.answers[0].user_id, Some(other));
+    assert_eq!(dump.feedback.len(), 1);
+    assert_eq!(dump.askers.len(), 1);
+    assert_eq!(dump.indexed_answers.len(), 1);
+    assert_eq!(dump.indexed_answers[0].asker_id, Some(other));
+    assert_eq!(dump.tasks.len(), 1);
+    assert_eq!(dump.ratings.len(), 1);
+}
+
+async fn old_records_are_purged(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let old = now - time::Duration::days(10);
+    for (message, at) in [(10, now), (11, old)] {
+        storage
+            .record_answer(&AnswerRecord {
+                message_id: Some(MessageId::new(message)),
+                ..record(1, true, 1000, at)
+            })
+            .await
+            .unwrap();
+        storage
+            .add_feedback(MessageId::new(message), UserId::new(2), true)
+            .await
+            .unwrap();
+        storage
+            .index_answer(&indexed_answer(1, "small", at, "How do I flash?"))
+            .await
+            .unwrap();
+        storage
+            .save_question_embedding(&question_embedding(1, "small", at, vec![1.0]))
+            .await
+            .unwrap();
+        storage
+            .record_deletion(&Deletion {
+                user_id: UserId::new(3),
+                at,
+                records: 1,
+                messages: 0,
+            })
+            .await
+            .unwrap();
+        storage
+            .record_rating(&rating(1, message, 2, at, 3))
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        storage.purge(&Default::default()).await.unwrap(),
+        0,
+        "nothing is purged without a policy"
+    );
+    let retention = RetentionConfig {
+        answers: Some(7),
+        search: Some(7),
+        duplicates: Some(7),
+        conversations: Some(1),
+        deletions: None,
+    };
+    let purge = retention::cutoffs(&retention, now);
+    assert_eq!(purge.answers, Some(now - time::Duration::days(7)));
+    assert_eq!(purge.deletions, None);
+    assert_eq!(storage.purge(&purge).await.unwrap(), 5);
+
+    let dump = storage.dump().await.unwrap();
+    assert_eq!(dump.answers.len(), 1);
+    assert_eq!(dump.answers[0].at, now);
+    assert_eq!(dump.feedback.len(), 1);
+    assert_eq!(dump.indexed_answers.len(), 1);
+    assert_eq!(dump.question_embeddings.len(), 1);
+    assert_eq!(dump.deletions.len(), 2);
+    assert_eq!(dump.ratings.len(), 1);
+}
+
+async fn usage_is_grouped_by_channel_and_model(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let answered = |channel: Option<u64>, model: &str, at| AnswerRecord {
+        channel_id: channel.map(ChannelId::new),
+        model: Some(model.to_string()),
+        ..record(1, true, 1000, at)
+    };
+    for record in [
+        answered(Some(10), "gpt-4o", now),
+        answered(Some(10), "gpt-4o", now),
+        answered(Some(10), "llama", now),
+        answered(Some(10), "gpt-4o", now - time::Duration::days(1)),
+        record(1, true, 1000, now),
+    ] {
+        storage.record_answer(&record).await.unwrap();
+    }
+
+    let usage = storage.usage(now).await.unwrap();
+    assert_eq!(usage.len(), 3);
+    let gpt = usage
+        .iter()
+        .find(|u| u.model.as_deref() == Some("gpt-4o"))
+        .unwrap();
+    assert_eq!(gpt.channel_id, Some(ChannelId::new(10)));
+    assert_eq!((gpt.prompt_tokens, gpt.completion_tokens), (200, 40));
+    assert!(usage
+        .iter()
+        .any(|u| u.channel_id.is_none() && u.model.is_none()));
+}
+
+fn rating(guild: u64, thread: u64, user: u64, at: OffsetDateTime, score: u8) -> Rating {
+    Rating {
+        guild_id: GuildId::new(guild),
+        channel_id: ChannelId::new(thread),
+        user_id: UserId::new(user),
+        at,
+        score,
+        comment: None,
+        escalated: false,
+        transcript: "Asker: How do I flash?\nBot: Follow the guide.".to_string(),
+    }
+}
+
+async fn ratings_are_kept_per_thread_and_user(storage: Arc<dyn Storage>) {
+    let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+    let day = time::Duration::days(1);
+    storage
+        .record_rating(&rating(1, 20, 2, now - day * 3, 2))
+        .await
+        .unwrap();
+    storage
+        .record_rating(&rating(1, 21, 2, now - day, 4))
+        .await
+        .unwrap();
+    storage
+        .record_rating(&rating(2, 22, 3, now, 1))
+        .await
+        .unwrap();
+    // rating the same thread again replaces the rating
+    let again = Rating {
+        comment: Some("Took a while".to_string()),
+        escalated: true,
+        ..rating(1, 21, 2, now, 3)
+    };
+    storage.record_rating(&again).await.unwrap();
+
+    assert_eq!(
+        storage
+            .ratings(GuildId::new(1), now - day * 2)
+            .await
+            .unwrap(),
+        vec![again]
+    );
+    assert_eq!(
+        storage
+            .ratings(GuildId::new(1), now - day * 7)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+}
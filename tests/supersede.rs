@@ -0,0 +1,46 @@
+use std::{sync::Arc, time::Duration};
+
+use deskhelp::supersede::InFlight;
+use serenity::all::{ChannelId, Message, UserId};
+
+fn message(channel: u64, user: u64) -> Message {
+    let mut msg = Message::default();
+    msg.channel_id = ChannelId::new(channel);
+    msg.author.id = UserId::new(user);
+    msg
+}
+
+async fn is_superseded(generation: &deskhelp::supersede::Generation) -> bool {
+    tokio::time::timeout(Duration::from_millis(50), generation.superseded())
+        .await
+        .is_ok()
+}
+
+#[tokio::test]
+async fn a_newer_question_from_the_same_user_takes_over() {
+    let in_flight = Arc::new(InFlight::default());
+    let first = in_flight.start(&message(1, 1));
+    let other_user = in_flight.start(&message(1, 2));
+    let other_channel = in_flight.start(&message(2, 1));
+    assert!(!is_superseded(&first).await);
+
+    let second = in_flight.start(&message(1, 1));
+    assert!(is_superseded(&first).await);
+    assert!(!is_superseded(&second).await);
+    assert!(!is_superseded(&other_user).await);
+    assert!(!is_superseded(&other_channel).await);
+}
+
+#[tokio::test]
+async fn finished_answers_are_not_superseded() {
+    let in_flight = Arc::new(InFlight::default());
+    let first = in_flight.start(&message(1, 1));
+    let second = in_flight.start(&message(1, 1));
+    // the older one finishing doesn't unregister the newer one
+    drop(first);
+    let third = in_flight.start(&message(1, 1));
+    assert!(is_superseded(&second).await);
+    drop(third);
+    let fourth = in_flight.start(&message(1, 1));
+    assert!(!is_superseded(&fourth).await);
+}
@@ -0,0 +1,126 @@
+//! A stand-in for an OpenAI-compatible API: serves canned responses over
+//! plain HTTP and records the requests it gets
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// Reads `tests/fixtures/<name>`
+pub fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e))
+}
+
+#[derive(Clone)]
+pub enum MockResponse {
+    /// A `text/event-stream` body, usually a fixture
+    Sse(String),
+    /// A whole `application/json` answer, as to a request with `stream` off
+    Json(String),
+    /// An error status with an OpenAI-style error body
+    Error(u16),
+}
+
+pub struct MockServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl MockServer {
+    /// Serves `responses` in order, repeating the last one once they run out
+    pub async fn start(responses: Vec<MockResponse>) -> MockServer {
+        assert!(!responses.is_empty(), "mock server needs a response");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for i in 0.. {
+                let Ok((socket, _)) = listener.accept().await else {
+                    break;
+                };
+                let response = responses[i.min(responses.len() - 1)].clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    if let Some(body) = handle(socket, response).await {
+                        recorded.lock().unwrap().push(body);
+                    }
+                });
+            }
+        });
+
+        MockServer { base_url, requests }
+    }
+
+    /// The JSON bodies of every request so far
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(mut socket: TcpStream, response: MockResponse) -> Option<serde_json::Value> {
+    let mut buf = vec![];
+    let header_end = loop {
+        let mut chunk = [0; 4096];
+        let n = socket.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+
+    let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+    let content_length = headers
+        .lines()
+        .find_map(|l| l.strip_prefix("content-length:"))
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let mut chunk = [0; 4096];
+        let n = socket.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body = serde_json::from_slice(&buf[header_end..]).ok();
+
+    let reply = match response {
+        MockResponse::Sse(events) => format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n{}",
+            events
+        ),
+        MockResponse::Json(json) => format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            json.len(),
+            json
+        ),
+        MockResponse::Error(status) => {
+            let error = serde_json::json!({
+                "error": {
+                    "message": "mock error",
+                    "type": "server_error",
+                    "param": null,
+                    "code": null,
+                }
+            })
+            .to_string();
+            format!(
+                "HTTP/1.1 {} Mock Error\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                error.len(),
+                error
+            )
+        }
+    };
+    socket.write_all(reply.as_bytes()).await.ok()?;
+    socket.shutdown().await.ok()?;
+    body
+}
@@ -0,0 +1,42 @@
+use deskhelp::{storage::Transcript, support_digest};
+use time::OffsetDateTime;
+
+fn transcript(question: &str, answer: Option<&str>) -> Transcript {
+    Transcript {
+        at: OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        question: question.to_string(),
+        answer: answer.map(str::to_string),
+        ok: answer.is_some(),
+        thumbs_up: 0,
+        thumbs_down: 0,
+    }
+}
+
+#[test]
+fn the_model_reads_one_line_per_question() {
+    let transcripts = [
+        Transcript {
+            thumbs_down: 2,
+            ..transcript("Spotify\nwon't connect", Some("Re-link it in settings."))
+        },
+        transcript("ADB can't find the device", None),
+    ];
+    assert_eq!(
+        support_digest::input(&transcripts),
+        "[2023-11-14] 👎2 Q: Spotify won't connect A: Re-link it in settings.\n\
+         [2023-11-14] [failed] Q: ADB can't find the device"
+    );
+}
+
+#[test]
+fn long_weeks_keep_the_newest_questions() {
+    let long = "x".repeat(2000);
+    let transcripts: Vec<_> = (0..500)
+        .map(|i| transcript(&format!("{} {}", i, long), Some(&long)))
+        .collect();
+    let input = support_digest::input(&transcripts);
+    assert!(input.len() <= 60_000);
+    assert!(input.ends_with(&format!("{}…", "x".repeat(300))));
+    assert!(input.lines().last().unwrap().contains("Q: 499 "));
+    assert!(!input.contains("Q: 0 "));
+}
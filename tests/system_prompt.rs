@@ -0,0 +1,75 @@
+use deskhelp::oai;
+
+fn prompt_context(timezone: &str) -> oai::PromptContext<'_> {
+    oai::PromptContext {
+        bot_name: "DeskHelp",
+        bot_id: "1",
+        guild_name: "Test",
+        channel_name: "help",
+        active_model: "gpt-4o",
+        timezone,
+        channel_prompt: "",
+        role_prompt: "",
+        user_profile: "",
+    }
+}
+
+#[test]
+fn system_prompt_gives_the_time_in_the_guild_timezone() {
+    let prompt = oai::system_prompt(oai::SYSTEM_MESSAGE, &prompt_context("Europe/Berlin"));
+    assert!(prompt.contains("(Europe/Berlin, UTC+0"));
+    let prompt = oai::system_prompt(oai::SYSTEM_MESSAGE, &prompt_context("Not/AZone"));
+    assert!(prompt.contains(" UTC. You are DeskHelp"));
+}
+
+#[cfg(feature = "templates")]
+#[test]
+fn instructions_are_templates() {
+    let context = oai::PromptContext {
+        channel_prompt: "Only talk about billing.",
+        ..prompt_context("UTC")
+    };
+    // using variables, the instructions are laid out as written
+    let prompt = oai::system_prompt(
+        "You are {{bot_name}} on {{active_model}} in #{{channel_name}}. {{time}}\n{{channel_prompt}}",
+        &context,
+    );
+    assert!(prompt.starts_with("You are DeskHelp on gpt-4o in #help. The time is "));
+    assert!(prompt.ends_with(" otherwise.\nOnly talk about billing."));
+
+    // otherwise the usual ending is added
+    let prompt = oai::system_prompt("Be brief.", &context);
+    assert!(prompt.starts_with("Be brief.\nThe time is "));
+    assert!(
+        prompt.ends_with("You are DeskHelp (id: 1), in the Test server\nOnly talk about billing.")
+    );
+    let prompt = oai::system_prompt("Be brief.", &prompt_context("UTC"));
+    assert!(prompt.ends_with("in the Test server"));
+    let dm = oai::PromptContext {
+        guild_name: "",
+        ..prompt_context("UTC")
+    };
+    assert!(oai::system_prompt("Be brief.", &dm).ends_with("in a direct message"));
+
+    // a mistake in the template leaves the instructions alone
+    for broken in ["Hi {{ bot_nmae }}", "Hi {{ bot_name"] {
+        let prompt = oai::system_prompt(broken, &context);
+        assert!(prompt.starts_with(broken));
+        assert!(prompt.contains("You are DeskHelp (id: 1)"));
+    }
+}
+
+#[cfg(not(feature = "templates"))]
+#[test]
+fn without_templates_variables_are_still_filled_in() {
+    let prompt = oai::system_prompt(
+        "You are {{bot_name}} in #{{ channel_name }}. {{time}}",
+        &prompt_context("UTC"),
+    );
+    assert!(prompt.starts_with("You are DeskHelp in #help. The time is "));
+    let prompt = oai::system_prompt("Be brief.", &prompt_context("UTC"));
+    assert!(prompt.starts_with("Be brief.\nThe time is "));
+    assert!(prompt.ends_with("You are DeskHelp (id: 1), in the Test server"));
+    let prompt = oai::system_prompt("Hi {{ bot_nmae }}", &prompt_context("UTC"));
+    assert!(prompt.starts_with("Hi {{ bot_nmae }}\nThe time is "));
+}
@@ -0,0 +1,25 @@
+use deskhelp::titles;
+
+#[test]
+fn replies_are_cleaned_into_thread_names() {
+    assert_eq!(
+        titles::clean("\"Keyboard not detected when flashing.\"").as_deref(),
+        Some("Keyboard not detected when flashing")
+    );
+    assert_eq!(
+        titles::clean("\n**Spotify login loop**\nBecause the token expired").as_deref(),
+        Some("Spotify login loop")
+    );
+    assert_eq!(titles::clean(" \n\"\" "), None);
+    assert_eq!(
+        titles::clean(&"a".repeat(150)).unwrap().len(),
+        titles::MAX_TITLE
+    );
+}
+
+#[test]
+fn threads_still_named_after_the_placeholder_get_titles() {
+    assert!(titles::is_placeholder("Generating response..."));
+    assert!(titles::is_placeholder(" "));
+    assert!(!titles::is_placeholder("Spotify login loop"));
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "trello")]
+
+use deskhelp::trello::{self, List};
+
+fn board() -> Vec<List> {
+    let path = format!(
+        "{}/tests/fixtures/trello_lists.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn cards_named_like_the_question_come_first() {
+    let board = board();
+    let matches = trello::search(&board, "Is Spotify planned?");
+    let names: Vec<_> = matches.iter().map(|(_, card)| card.name.as_str()).collect();
+    assert_eq!(names, ["Spotify lyrics", "Discord integration"]);
+
+    let text = trello::describe("spotify", &matches);
+    assert!(text.contains(
+        "- Spotify lyrics (in the \"Planned\" list): https://trello.com/c/abc123\n  Show synced lyrics"
+    ));
+    assert!(text.contains("(in the \"In Progress\" list): https://trello.com/c/ghi789"));
+}
+
+#[test]
+fn questions_without_a_card_say_so() {
+    let board = board();
+    assert!(trello::search(&board, "is there a plan for").is_empty());
+    let matches = trello::search(&board, "bluetooth keyboard");
+    assert!(matches.is_empty());
+    assert!(trello::describe("bluetooth keyboard", &matches).starts_with("No open cards"));
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "triage")]
+
+use deskhelp::{
+    config::Config,
+    content_policy::Policy,
+    triage::{self, Severity, Triage},
+};
+use serenity::all::ForumTagId;
+
+fn categories() -> Vec<String> {
+    vec!["billing".to_string(), "login".to_string()]
+}
+
+#[test]
+fn answers_are_checked_against_the_schema() {
+    let schema = triage::schema(&categories());
+    let answer = r#"```json
+{"category": "login", "severity": "high", "suggested_fix": "Reset your password.", "links": []}
+```"#;
+    assert_eq!(
+        triage::parse(answer, &schema).unwrap(),
+        Triage {
+            category: "login".to_string(),
+            severity: Severity::High,
+            suggested_fix: "Reset your password.".to_string(),
+            links: vec![],
+        }
+    );
+
+    let unknown_category =
+        r#"{"category": "weather", "severity": "low", "suggested_fix": "", "links": []}"#;
+    assert!(triage::parse(unknown_category, &schema).is_err());
+    let missing_field = r#"{"category": "login", "severity": "low", "suggested_fix": ""}"#;
+    assert!(triage::parse(missing_field, &schema).is_err());
+    let extra_field = r#"{"category": "login", "severity": "low", "suggested_fix": "", "links": [], "mood": "sad"}"#;
+    assert!(triage::parse(extra_field, &schema).is_err());
+    assert!(triage::parse("Have you tried turning it off and on?", &schema).is_err());
+
+    // without categories, any will do
+    let open = triage::schema(&[]);
+    assert!(triage::parse(unknown_category, &open).is_ok());
+}
+
+#[test]
+fn links_off_the_allowlist_are_dropped() {
+    let mut result = Triage {
+        category: "billing".to_string(),
+        severity: Severity::Low,
+        suggested_fix: "See the docs.".to_string(),
+        links: vec![
+            "https://example.com/docs/billing".to_string(),
+            "https://elsewhere.example/scam".to_string(),
+        ],
+    };
+    let config = Config::default();
+    let policy = Policy::new(
+        &config,
+        None,
+        "en-US",
+        vec!["https://example.com/docs/".to_string()],
+    );
+    result.screen(&policy);
+    assert_eq!(result.links, vec!["https://example.com/docs/billing"]);
+}
+
+#[test]
+fn forum_tags_match_category_or_severity() {
+    let result = Triage {
+        category: "Billing".to_string(),
+        severity: Severity::Critical,
+        suggested_fix: String::new(),
+        links: vec![],
+    };
+    let tag = ForumTagId::new;
+    let available = [
+        (tag(1), "billing".to_string()),
+        (tag(2), "CRITICAL".to_string()),
+        (tag(3), "login".to_string()),
+    ];
+    assert_eq!(
+        triage::matching_tags(&available, &[tag(9)], &result),
+        vec![tag(9), tag(1), tag(2)]
+    );
+    // already applied ones aren't doubled, and posts take five at most
+    let applied = [tag(1), tag(5), tag(6), tag(7), tag(8)];
+    assert_eq!(
+        triage::matching_tags(&available, &applied, &result),
+        applied.to_vec()
+    );
+}
@@ -0,0 +1,91 @@
+use deskhelp::config::Config;
+use serenity::all::{GuildId, Message, UserId};
+
+const BOT: UserId = UserId::new(1);
+
+fn message(guild: Option<u64>, content: &str) -> Message {
+    let mut msg = Message::default();
+    msg.author.id = UserId::new(2);
+    msg.guild_id = guild.map(GuildId::new);
+    msg.content = content.to_string();
+    msg
+}
+
+#[test]
+fn direct_messages_are_all_questions_once_switched_on() {
+    let dm = message(None, "how do I flash it?");
+    let off = Config::default();
+    assert!(off.is_ignored(&dm));
+
+    let on: Config = toml::from_str("direct_messages = true").unwrap();
+    assert!(!on.is_ignored(&dm));
+    assert!(on.is_question(&dm, BOT, None));
+    assert!(!on.is_question(&message(None, "~ not for the bot"), BOT, None));
+    // guild channels still need a mention
+    assert!(!on.is_question(&message(Some(1), "how do I flash it?"), BOT, None));
+}
+
+#[test]
+fn guilds_can_keep_the_bot_out_of_voice_chats() {
+    let config: Config = toml::from_str(
+        r#"
+        [guilds."1"]
+        voice_channels = false
+        "#,
+    )
+    .unwrap();
+    assert!(!config.voice_channels(Some(GuildId::new(1))));
+    assert!(config.voice_channels(Some(GuildId::new(2))));
+}
+
+#[test]
+fn guilds_can_have_their_own_prefix() {
+    let config: Config = toml::from_str(
+        r#"
+        autorespond_channels = [10]
+
+        [guilds."1"]
+        prefix = "!"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.prefix(Some(GuildId::new(1))), "!");
+    assert_eq!(config.prefix(Some(GuildId::new(2))), "~");
+
+    let mut command = message(Some(1), "!ping");
+    command.channel_id = 10.into();
+    assert!(!config.is_question(&command, BOT, None));
+    let mut question = message(Some(1), "~ how do I flash it?");
+    question.channel_id = 10.into();
+    assert!(config.is_question(&question, BOT, None));
+}
+
+#[test]
+fn other_bots_take_over_their_channels() {
+    let config: Config = toml::from_str(
+        r#"
+        autorespond_channels = [10, 20]
+
+        [[bots]]
+        name = "fun"
+        token_env = "FUN_DISCORD_TOKEN"
+        channels = [20, 30]
+        "#,
+    )
+    .unwrap();
+    let in_channel = |channel: u64| {
+        let mut msg = message(Some(1), "what's the best song?");
+        msg.channel_id = channel.into();
+        msg
+    };
+    assert!(config.is_question(&in_channel(10), BOT, None));
+    assert!(!config.is_question(&in_channel(20), BOT, None));
+    assert!(config.is_question(&in_channel(20), BOT, Some("fun")));
+    assert!(config.is_question(&in_channel(30), BOT, Some("fun")));
+    assert!(!config.is_question(&in_channel(10), BOT, Some("fun")));
+    // each still answers when mentioned
+    let mut mention = in_channel(20);
+    mention.mentions.push(Default::default());
+    mention.mentions[0].id = BOT;
+    assert!(config.is_question(&mention, BOT, None));
+}
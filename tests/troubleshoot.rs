@@ -0,0 +1,53 @@
+use deskhelp::{config::Config, troubleshoot};
+use serenity::all::GuildId;
+
+const CONFIG: &str = r#"
+[troubleshoot]
+question = "What's wrong?"
+
+[[troubleshoot.options]]
+label = "Flashing"
+question = "What happens when you flash?"
+
+[[troubleshoot.options.options]]
+label = "Device not found"
+answer = "Try another USB cable, one that carries data."
+
+[[troubleshoot.options.options]]
+label = "Something else"
+
+[[troubleshoot.options]]
+label = "Spotify"
+answer = "Log in to Spotify again in the app's settings."
+
+[guilds."1".troubleshoot]
+answer = "Ask in #help."
+"#;
+
+#[test]
+fn steps_lead_to_fixes_and_tell_the_model_what_was_tried() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    let root = config.troubleshoot(None).unwrap();
+    assert_eq!(
+        troubleshoot::walk(root, &[0, 0]).unwrap().answer.as_deref(),
+        Some("Try another USB cable, one that carries data.")
+    );
+    assert!(troubleshoot::walk(root, &[0, 5]).is_none());
+    assert_eq!(
+        troubleshoot::trail(root, &[0, 0]),
+        ["Flashing", "Device not found"]
+    );
+    assert_eq!(
+        troubleshoot::steps(root, &[0, 0]),
+        "What's wrong? Flashing\nWhat happens when you flash? Device not found\n\
+        Tried, without luck: Try another USB cable, one that carries data."
+    );
+    // where the steps run out, the model hears how far they got
+    assert_eq!(
+        troubleshoot::steps(root, &[0, 1]),
+        "What's wrong? Flashing\nWhat happens when you flash? Something else"
+    );
+
+    let guild = config.troubleshoot(Some(GuildId::new(1))).unwrap();
+    assert_eq!(guild.answer.as_deref(), Some("Ask in #help."));
+}
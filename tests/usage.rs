@@ -0,0 +1,100 @@
+#[allow(dead_code)]
+mod support;
+
+use std::sync::Arc;
+
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent,
+};
+use deskhelp::{
+    models::Capabilities,
+    oai,
+    provider::{Endpoint, Provider},
+    snippets::SnippetTool,
+    storage::{Snippet, SqliteStorage, Storage},
+    tools::{self, Tools},
+};
+use futures::TryStreamExt;
+use serenity::all::GuildId;
+use support::{fixture, MockResponse, MockServer};
+
+fn user(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+        content: ChatCompletionRequestUserMessageContent::Text(text.to_string()),
+        ..Default::default()
+    })
+}
+
+fn provider(servers: &[&MockServer]) -> Provider {
+    let endpoints = servers
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Endpoint::new(format!("mock#{}", i), "sk-test", &s.base_url))
+        .collect();
+    Provider::new("mock", endpoints, "mock-model".to_string()).unwrap()
+}
+
+/// `events` with a final usage chunk, as sent when `stream_options.include_usage` is on
+fn with_usage(events: &str, prompt_tokens: u32, completion_tokens: u32) -> String {
+    let usage = serde_json::json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 1700000000,
+        "model": "mock-model",
+        "choices": [],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    });
+    events.replace("data: [DONE]", &format!("data: {}\n\ndata: [DONE]", usage))
+}
+
+#[tokio::test]
+async fn usage_adds_up_every_round_of_tool_calls() {
+    let server = MockServer::start(vec![
+        MockResponse::Sse(with_usage(&fixture("tool_call.sse"), 100, 10)),
+        MockResponse::Sse(with_usage(&fixture("hello.sse"), 120, 5)),
+    ])
+    .await;
+    let provider = provider(&[&server]);
+    let storage: Arc<dyn Storage> =
+        Arc::new(SqliteStorage::connect("sqlite::memory:").await.unwrap());
+    let guild_id = GuildId::new(1);
+    storage
+        .save_snippet(&Snippet {
+            guild_id,
+            name: "reset".to_string(),
+            content: "Hold the back button.".to_string(),
+            updated_at: time::OffsetDateTime::now_utc(),
+        })
+        .await
+        .unwrap();
+    let mut tools = Tools::default();
+    tools.add(
+        SnippetTool::for_guild(storage, guild_id)
+            .await
+            .unwrap()
+            .unwrap(),
+    );
+
+    let (messages, _) = oai::build_prompt("Be brief.".to_string(), &[user("hi")], 7000, &[]);
+    let request = oai::chat_request(&provider.model, &Capabilities::default(), messages);
+    let mut stream = tools::create_stream(&provider, request, &tools)
+        .await
+        .unwrap();
+    let mut usage = None;
+    while let Some(chunk) = stream.try_next().await.unwrap() {
+        if chunk.usage.is_some() {
+            usage = chunk.usage;
+        }
+    }
+    let usage = usage.unwrap();
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (220, 15));
+    assert_eq!(
+        server.requests()[0]["stream_options"],
+        serde_json::json!({ "include_usage": true })
+    );
+}
@@ -0,0 +1,77 @@
+#![cfg(feature = "wasm")]
+
+use deskhelp::{config::WasmToolsConfig, tools::Tool, wasm_tools::WasmTool};
+use serde_json::json;
+
+/// A module describing itself with `description`, whose `call` runs `body`
+fn module(description: &str, body: &str) -> Vec<u8> {
+    wat::parse_str(format!(
+        r#"(module
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 4096))
+            (data (i32.const 0) "{data}")
+            (func (export "alloc") (param $len i32) (result i32)
+                (global.get $next)
+                (global.set $next (i32.add (global.get $next) (local.get $len))))
+            (func (export "describe") (result i64)
+                (i64.const {len}))
+            (func (export "call") (param $ptr i32) (param $len i32) (result i64)
+                {body}))"#,
+        data = description.replace('"', "\\\""),
+        len = description.len(),
+    ))
+    .unwrap()
+}
+
+/// Returns its arguments as they were passed
+const ECHO: &str = "(i64.or
+    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+    (i64.extend_i32_u (local.get $len)))";
+
+#[tokio::test]
+async fn modules_describe_and_run_their_tool() {
+    let description = json!({
+        "name": "echo",
+        "description": "Says the arguments back",
+        "parameters": { "type": "object", "properties": { "text": { "type": "string" } } },
+        "links": ["https://example.com/"],
+    })
+    .to_string();
+    let tool = WasmTool::load(&module(&description, ECHO), &WasmToolsConfig::default()).unwrap();
+    assert_eq!(tool.name(), "echo");
+    assert_eq!(tool.description(), "Says the arguments back");
+    assert_eq!(tool.links(), ["https://example.com/"]);
+    assert_eq!(
+        tool.call(json!({ "text": "hi" })).await.unwrap(),
+        r#"{"text":"hi"}"#
+    );
+}
+
+#[tokio::test]
+async fn runaway_modules_are_stopped() {
+    let description = json!({ "name": "spin", "description": "Never ends" }).to_string();
+    let tool = WasmTool::load(
+        &module(&description, "(loop $forever (br $forever)) (i64.const 0)"),
+        &WasmToolsConfig {
+            fuel: 100_000,
+            ..WasmToolsConfig::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(tool.parameters()["type"], "object");
+    assert!(tool.call(json!({})).await.is_err());
+}
+
+#[test]
+fn modules_get_nothing_to_import_and_need_a_valid_name() {
+    let importing = wat::parse_str(
+        r#"(module
+            (import "env" "fetch" (func))
+            (memory (export "memory") 1))"#,
+    )
+    .unwrap();
+    assert!(WasmTool::load(&importing, &WasmToolsConfig::default()).is_err());
+
+    let description = json!({ "name": "has spaces", "description": "Bad" }).to_string();
+    assert!(WasmTool::load(&module(&description, ECHO), &WasmToolsConfig::default()).is_err());
+}